@@ -0,0 +1,76 @@
+// sample_plugin.rs
+
+// A plugin adding a panel, a system spinning the template's object, and a vignette overlay. Build it with
+// `cargo build --example sample_plugin` and start the app with `--plugin` and the library it built, found
+// under target/debug/examples
+
+use std::ffi::{c_char, c_void};
+use winit_egui_wgpu::plugins::{PluginRegistrar, PluginSceneApi, PluginTransform, PluginUiApi, PLUGIN_API_VERSION};
+
+const VIGNETTE: &str = "
+@fragment
+fn fs_main(in: OverlayVertex) -> @location(0) vec4<f32> {
+    let offset = in.uv - vec2<f32>(0.5);
+    let pulse = 0.05 * sin(overlay.time * 2.0);
+    let shade = smoothstep(0.35 + pulse, 0.75, length(offset));
+    return vec4<f32>(0.0, 0.0, 0.0, shade * 0.6);
+}
+\0";
+
+struct State {
+    spinning: bool,
+    speed: f32, // Radians per second
+}
+
+#[no_mangle]
+pub extern "C" fn winit_egui_wgpu_plugin_api_version() -> u32 {
+    PLUGIN_API_VERSION
+}
+
+/// # Safety
+/// Called once by the host, with a registrar valid for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn winit_egui_wgpu_plugin_register(registrar: *const PluginRegistrar) {
+    let registrar = &*registrar;
+    // Never freed; the host keeps plugins loaded until it exits
+    let state = Box::into_raw(Box::new(State {
+        spinning: true,
+        speed: 1.0,
+    }))
+    .cast::<c_void>();
+    (registrar.register_panel)(registrar.host, c"Sample Plugin".as_ptr(), panel, state);
+    (registrar.register_system)(registrar.host, spin, state);
+    (registrar.register_overlay)(registrar.host, c"Vignette".as_ptr(), VIGNETTE.as_ptr().cast::<c_char>());
+}
+
+unsafe extern "C" fn panel(user: *mut c_void, ui: *mut c_void, api: *const PluginUiApi) {
+    let (state, api) = (&mut *user.cast::<State>(), &*api);
+    (api.label)(ui, c"Loaded from a dynamic library".as_ptr());
+    (api.separator)(ui);
+    (api.checkbox)(ui, c"Spin the object".as_ptr(), &mut state.spinning);
+    (api.slider)(ui, c"Speed".as_ptr(), &mut state.speed, -5.0, 5.0);
+    if (api.button)(ui, c"Stop".as_ptr()) {
+        state.spinning = false;
+    }
+}
+
+unsafe extern "C" fn spin(user: *mut c_void, scene: *mut c_void, api: *const PluginSceneApi, dt: f32) {
+    let (state, api) = (&*user.cast::<State>(), &*api);
+    if !state.spinning {
+        return;
+    }
+    let object = (api.object)(scene);
+    let mut transform = PluginTransform {
+        translation: [0.0; 3],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        scale: [1.0; 3],
+    };
+    if !(api.get_transform)(scene, object, &mut transform) {
+        return;
+    }
+    // Turns about Y by multiplying in a quaternion of angle speed * dt
+    let (sin, cos) = (state.speed * dt * 0.5).sin_cos();
+    let [x, y, z, w] = transform.rotation;
+    transform.rotation = [cos * x + sin * z, cos * y + sin * w, cos * z - sin * x, cos * w - sin * y];
+    (api.set_transform)(scene, object, &transform);
+}
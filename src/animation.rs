@@ -0,0 +1,435 @@
+// animation.rs
+
+use crate::camera::Camera;
+use crate::morph::Easing;
+use crate::transform::Transform;
+use crate::world::Light;
+use egui::{Color32, Context, Sense, Shape, Stroke};
+use glam::{Quat, Vec3};
+
+// Playback and physics advance in steps of this length whatever the frame rate, so they land on the same values
+// in every run, replays included
+pub const FIXED_STEP: f32 = 1.0 / 60.0;
+// Steps caught up in one frame at most; after a longer hitch the simulation falls behind instead
+pub const MAX_STEPS: f32 = 8.0;
+const TIMELINE_WIDTH: f32 = 320.0;
+const ROW_HEIGHT: f32 = 18.0;
+const KEY_RADIUS: f32 = 5.0;
+
+// Everything the animation drives: the UI's object, the sun, the viewport camera and the background
+pub struct AnimationTargets<'a> {
+    pub transform: &'a mut Transform,
+    pub light: &'a mut Light,
+    pub camera: &'a mut Camera,
+    pub background: &'a mut Vec3, // Linear RGB the scene pass clears to
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+    LightColor,
+    LightIntensity,
+    FieldOfView,
+    Background,
+}
+
+impl Property {
+    pub const ALL: [Property; 7] = [
+        Property::Translation,
+        Property::Rotation,
+        Property::Scale,
+        Property::LightColor,
+        Property::LightIntensity,
+        Property::FieldOfView,
+        Property::Background,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Property::Translation => "Translation",
+            Property::Rotation => "Rotation",
+            Property::Scale => "Scale",
+            Property::LightColor => "Light color",
+            Property::LightIntensity => "Light intensity",
+            Property::FieldOfView => "Camera FOV",
+            Property::Background => "Background",
+        }
+    }
+
+    pub fn read(&self, targets: &AnimationTargets) -> Value {
+        match self {
+            Property::Translation => Value::Vector(targets.transform.translation),
+            Property::Rotation => Value::Rotation(targets.transform.rotation),
+            Property::Scale => Value::Vector(targets.transform.scale),
+            Property::LightColor => Value::Vector(targets.light.color),
+            Property::LightIntensity => Value::Scalar(targets.light.intensity),
+            Property::FieldOfView => Value::Scalar(targets.camera.fovy),
+            Property::Background => Value::Vector(*targets.background),
+        }
+    }
+
+    pub fn write(&self, targets: &mut AnimationTargets, value: Value) {
+        match (self, value) {
+            (Property::Translation, Value::Vector(v)) => targets.transform.translation = v,
+            (Property::Rotation, Value::Rotation(q)) => targets.transform.rotation = q.normalize(),
+            (Property::Scale, Value::Vector(v)) => targets.transform.scale = v,
+            (Property::LightColor, Value::Vector(v)) => targets.light.color = v,
+            (Property::LightIntensity, Value::Scalar(s)) => targets.light.intensity = s,
+            (Property::FieldOfView, Value::Scalar(s)) => targets.camera.fovy = s,
+            (Property::Background, Value::Vector(v)) => *targets.background = v,
+            _ => log::warn!("Ignoring a {value:?} keyed for {}", self.label()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Value {
+    Scalar(f32),
+    Vector(Vec3), // Positions, scales and linear RGB colors
+    Rotation(Quat),
+}
+
+impl Value {
+    pub fn interpolate(self, to: Value, t: f32) -> Value {
+        match (self, to) {
+            (Value::Scalar(a), Value::Scalar(b)) => Value::Scalar(a + (b - a) * t),
+            (Value::Vector(a), Value::Vector(b)) => Value::Vector(a.lerp(b, t)),
+            (Value::Rotation(a), Value::Rotation(b)) => Value::Rotation(a.slerp(b, t)),
+            _ => to, // The keys of one track always hold the same kind of value
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Value::Scalar(s) => format!("{s:.3}"),
+            Value::Vector(v) => format!("({:.3}, {:.3}, {:.3})", v.x, v.y, v.z),
+            Value::Rotation(q) => {
+                let (x, y, z) = q.to_euler(glam::EulerRot::XYZ);
+                format!("({:.1}°, {:.1}°, {:.1}°)", x.to_degrees(), y.to_degrees(), z.to_degrees())
+            }
+        }
+    }
+}
+
+// The easing shapes the way from this key to the next one
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Keyframe {
+    pub time: f32, // Seconds from the start of the animation
+    pub value: Value,
+    pub easing: Easing,
+}
+
+// Keys of one property, kept sorted by time
+#[derive(Debug, Clone)]
+struct Track {
+    property: Property,
+    keys: Vec<Keyframe>,
+}
+
+impl Track {
+    // Holds the first key's value before it and the last key's value after it
+    fn sample(&self, time: f32) -> Option<Value> {
+        let next = self.keys.partition_point(|key| key.time <= time);
+        let Some(from) = next.checked_sub(1).map(|index| &self.keys[index]) else {
+            return self.keys.first().map(|key| key.value);
+        };
+        let Some(to) = self.keys.get(next) else {
+            return Some(from.value);
+        };
+        let t = (time - from.time) / (to.time - from.time).max(f32::EPSILON);
+        Some(from.value.interpolate(to.value, from.easing.apply(t.clamp(0.0, 1.0))))
+    }
+
+    // Keying at a key's time replaces its value. Returns the key's index
+    fn insert(&mut self, key: Keyframe) -> usize {
+        let same_time = |existing: &Keyframe| (existing.time - key.time).abs() < FIXED_STEP / 2.0;
+        if let Some(index) = self.keys.iter().position(same_time) {
+            self.keys[index].value = key.value;
+            return index;
+        }
+        let index = self.keys.partition_point(|existing| existing.time <= key.time);
+        self.keys.insert(index, key);
+        index
+    }
+
+    // Moves a key in time, past its neighbours if need be. Returns its new index
+    fn set_time(&mut self, index: usize, time: f32) -> usize {
+        let mut key = self.keys.remove(index);
+        key.time = time;
+        let index = self.keys.partition_point(|existing| existing.time <= time);
+        self.keys.insert(index, key);
+        index
+    }
+}
+
+// Keyframed property animation with a dope-sheet panel to edit it. Tracks without keys leave their property
+// alone; the others override it for as long as the animation plays or is scrubbed
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub playing: bool,
+    pub looping: bool,
+    pub duration: f32,
+    time: f32,
+    accumulator: f32, // Frame time not yet taken as a fixed step
+    moved: bool,      // Time or keys changed since the values were last applied
+    tracks: Vec<Track>,
+    easing: Easing, // Given to new keys
+    selected: Option<(Property, usize)>,
+    dragging: Option<(Property, usize)>,
+}
+
+impl Animation {
+    pub fn new() -> Self {
+        Self {
+            playing: false,
+            looping: true,
+            duration: 4.0,
+            time: 0.0,
+            accumulator: 0.0,
+            moved: false,
+            tracks: Property::ALL
+                .iter()
+                .map(|&property| Track {
+                    property,
+                    keys: Vec::new(),
+                })
+                .collect(),
+            easing: Easing::SmoothStep,
+            selected: None,
+            dragging: None,
+        }
+    }
+
+    fn track_mut(&mut self, property: Property) -> &mut Track {
+        &mut self.tracks[property as usize]
+    }
+
+    /// Fixed update: advances playback by whole steps of frame time, then writes the keyed values at the new
+    /// time into `targets`. Does nothing while paused unless the panel moved the time or edited keys.
+    pub fn update(&mut self, dt: f32, targets: &mut AnimationTargets) {
+        if self.playing {
+            self.accumulator = (self.accumulator + dt).min(FIXED_STEP * MAX_STEPS);
+            while self.playing && self.accumulator >= FIXED_STEP {
+                self.accumulator -= FIXED_STEP;
+                self.step();
+            }
+        }
+        if std::mem::take(&mut self.moved) {
+            for track in &self.tracks {
+                if let Some(value) = track.sample(self.time) {
+                    track.property.write(targets, value);
+                }
+            }
+        }
+    }
+
+    /// Writes the keyed values at `time`, clamped to the animation's length, into `targets` without moving
+    /// the playhead.
+    pub fn apply_at(&self, time: f32, targets: &mut AnimationTargets) {
+        let time = time.clamp(0.0, self.duration);
+        for track in &self.tracks {
+            if let Some(value) = track.sample(time) {
+                track.property.write(targets, value);
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        self.time += FIXED_STEP;
+        self.moved = true;
+        if self.time >= self.duration {
+            if self.looping {
+                self.time %= self.duration;
+            } else {
+                self.time = self.duration;
+                self.playing = false;
+                self.accumulator = 0.0;
+            }
+        }
+    }
+
+    fn seek(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.duration);
+        self.moved = true;
+    }
+
+    fn key(&mut self, property: Property, targets: &AnimationTargets) {
+        let key = Keyframe {
+            time: self.time,
+            value: property.read(targets),
+            easing: self.easing,
+        };
+        let index = self.track_mut(property).insert(key);
+        self.selected = Some((property, index));
+    }
+
+    pub fn show(&mut self, ctx: &Context, targets: &mut AnimationTargets) {
+        egui::Window::new("Animation")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.transport(ui);
+                ui.separator();
+                egui::Grid::new("animation_tracks").num_columns(3).show(ui, |ui| {
+                    for property in Property::ALL {
+                        ui.label(property.label());
+                        if ui.small_button("◆").on_hover_text("Key the current value here").clicked() {
+                            self.key(property, targets);
+                        }
+                        self.timeline(ui, property);
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+                self.selected_key(ui, targets);
+            });
+    }
+
+    fn transport(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("⏮").on_hover_text("Back to the start").clicked() {
+                self.seek(0.0);
+            }
+            let label = if self.playing { "⏸ Pause" } else { "▶ Play" };
+            if ui.button(label).clicked() {
+                self.playing = !self.playing;
+                // Playing a finished animation starts it over
+                if self.playing && !self.looping && self.time >= self.duration {
+                    self.seek(0.0);
+                }
+            }
+            ui.checkbox(&mut self.looping, "Loop");
+            ui.add(
+                egui::DragValue::new(&mut self.duration)
+                    .speed(0.05)
+                    .range(0.1..=600.0)
+                    .suffix(" s"),
+            )
+            .on_hover_text("Length");
+        });
+        let mut time = self.time;
+        let slider = egui::Slider::new(&mut time, 0.0..=self.duration).suffix(" s").text("Time");
+        if ui.add(slider).changed() {
+            self.seek(time);
+        }
+        egui::ComboBox::from_label("Easing of new keys")
+            .selected_text(self.easing.label())
+            .show_ui(ui, |ui| {
+                for easing in Easing::ALL {
+                    ui.selectable_value(&mut self.easing, easing, easing.label());
+                }
+            });
+    }
+
+    // One dope-sheet row: clicking or dragging empty space scrubs, dragging a key moves it in time
+    fn timeline(&mut self, ui: &mut egui::Ui, property: Property) {
+        let (rect, response) =
+            ui.allocate_exact_size(egui::vec2(TIMELINE_WIDTH, ROW_HEIGHT), Sense::click_and_drag());
+        let duration = self.duration;
+        let to_x = |time: f32| rect.left() + (time / duration).clamp(0.0, 1.0) * rect.width();
+        let to_time = |x: f32| ((x - rect.left()) / rect.width()).clamp(0.0, 1.0) * duration;
+        let track = &self.tracks[property as usize];
+        let key_at = |x: f32| {
+            track
+                .keys
+                .iter()
+                .rposition(|key| (to_x(key.time) - x).abs() <= KEY_RADIUS + 1.0)
+        };
+
+        if let Some(pointer) = response.interact_pointer_pos() {
+            if response.drag_started() {
+                self.dragging = key_at(pointer.x).map(|index| (property, index));
+            }
+            match self.dragging.filter(|(dragged, _)| *dragged == property && response.dragged()) {
+                Some((_, index)) => {
+                    let index = self.track_mut(property).set_time(index, to_time(pointer.x));
+                    self.dragging = Some((property, index));
+                    self.selected = Some((property, index));
+                    self.moved = true;
+                }
+                None if response.clicked() || response.dragged() => match key_at(pointer.x) {
+                    Some(index) if response.clicked() => self.selected = Some((property, index)),
+                    _ => self.seek(to_time(pointer.x)),
+                },
+                None => {}
+            }
+        }
+        if response.drag_stopped() {
+            self.dragging = None;
+        }
+
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+        let track = &self.tracks[property as usize];
+        if let (Some(first), Some(last)) = (track.keys.first(), track.keys.last()) {
+            let y = rect.center().y;
+            painter.hline(to_x(first.time)..=to_x(last.time), y, visuals.widgets.noninteractive.bg_stroke);
+        }
+        for (index, key) in track.keys.iter().enumerate() {
+            let center = egui::pos2(to_x(key.time), rect.center().y);
+            let fill = if self.selected == Some((property, index)) {
+                visuals.selection.stroke.color
+            } else {
+                visuals.text_color()
+            };
+            let diamond = [egui::vec2(0.0, -1.0), egui::vec2(1.0, 0.0), egui::vec2(0.0, 1.0), egui::vec2(-1.0, 0.0)]
+                .map(|offset| center + offset * KEY_RADIUS);
+            painter.add(Shape::convex_polygon(diamond.to_vec(), fill, Stroke::NONE));
+        }
+        painter.vline(to_x(self.time), rect.y_range(), Stroke::new(1.5, Color32::from_rgb(230, 70, 70)));
+    }
+
+    fn selected_key(&mut self, ui: &mut egui::Ui, targets: &AnimationTargets) {
+        let Some((property, index)) = self.selected else {
+            ui.weak("Click a key to edit it");
+            return;
+        };
+        let duration = self.duration;
+        let track = &mut self.tracks[property as usize];
+        let Some(key) = track.keys.get_mut(index) else {
+            self.selected = None;
+            return;
+        };
+        ui.strong(format!("{} key", property.label()));
+        ui.label(key.value.describe());
+        let mut moved = false;
+        let mut time = key.time;
+        ui.horizontal(|ui| {
+            ui.label("Time");
+            moved |= ui
+                .add(egui::DragValue::new(&mut time).speed(0.01).range(0.0..=duration).suffix(" s"))
+                .changed();
+        });
+        egui::ComboBox::from_label("Easing to next key")
+            .selected_text(key.easing.label())
+            .show_ui(ui, |ui| {
+                for easing in Easing::ALL {
+                    moved |= ui.selectable_value(&mut key.easing, easing, easing.label()).changed();
+                }
+            });
+        let mut delete = false;
+        ui.horizontal(|ui| {
+            if ui.button("Set to current").clicked() {
+                key.value = property.read(targets);
+                moved = true;
+            }
+            delete = ui.button("Delete").clicked();
+        });
+        let index = if time != key.time { track.set_time(index, time) } else { index };
+        self.selected = Some((property, index));
+        if delete {
+            track.keys.remove(index);
+            self.selected = None;
+        }
+        self.moved |= moved || delete;
+    }
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
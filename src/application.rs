@@ -0,0 +1,144 @@
+// application.rs
+
+use crate::camera::Camera;
+use crate::dock::Panel;
+use crate::events::EventSender;
+use crate::labels::Labels;
+use crate::material::Materials;
+use crate::mesh::Mesh;
+use crate::post_process::PostPass;
+use crate::prefab::PrefabMeshes;
+use crate::shader_registry::ShaderRegistry;
+use crate::terrain::Terrain;
+use crate::transform::Transform;
+use crate::world::{self, MaterialHandle, MeshHandle};
+use egui_wgpu::wgpu;
+use hecs::{Entity, World};
+use winit::event::WindowEvent;
+
+// What a project built on the template hooks into the app with. Every method has an empty default, so an
+// implementation only overrides what it needs; the template's own windows and systems keep running around it
+pub trait Application: 'static {
+    /// Called once, after the device and the starting scene are set up.
+    fn setup(&mut self, _context: &mut AppContext) {}
+
+    /// Called every frame after the scene scripts run, with the seconds since the last frame.
+    fn update(&mut self, _context: &mut AppContext, _dt: f32) {}
+
+    /// Called every frame while the UI is built, alongside the template's windows.
+    fn ui(&mut self, _ctx: &egui::Context, _context: &mut AppContext) {}
+
+    /// Called every frame after the template's scene passes, to draw over the scene before the UI.
+    fn render(&mut self, _frame: &mut RenderContext) {}
+
+    /// Called for each of the main window's events egui didn't take, before the camera and the viewport see it.
+    /// Returning true keeps it from them; let releases through, or keys and buttons stay held.
+    fn on_event(&mut self, _event: &WindowEvent) -> bool {
+        false
+    }
+
+    /// Called while the menu bar is built, after the template's menus, to add menus of its own.
+    fn menu(&mut self, _ui: &mut egui::Ui) {}
+
+    /// Called once at startup for panels to add to the dock beside the template's own.
+    fn panels(&mut self) -> Vec<Box<dyn Panel>> {
+        Vec::new()
+    }
+
+    /// Called once at startup to add scene shaders beside the built-in ones, or replace them. Each is listed in
+    /// the shader dropdown and gets a pipeline of its own.
+    fn shaders(&mut self, _registry: &mut ShaderRegistry) {}
+
+    /// Called once at startup for passes to run over the HDR scene before bloom and tonemapping. They're dropped
+    /// when the scene isn't post-processed, as with `--xr`.
+    fn post_passes(&mut self, _device: &wgpu::Device) -> Vec<Box<dyn PostPass>> {
+        Vec::new()
+    }
+
+    /// Called when the app is switched away from, to remove what it added to the scene.
+    fn teardown(&mut self, _context: &mut AppContext) {}
+}
+
+// The app as it ships, with nothing added
+pub struct Template;
+
+impl Application for Template {}
+
+// What an `Application` can reach while its hooks run
+pub struct AppContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub world: &'a mut World,
+    pub camera: &'a mut Camera,
+    pub terrain: &'a mut Terrain,
+    pub labels: &'a mut Labels, // Text anchored in the world, added again every frame it should show
+    pub events: EventSender,    // Drives the app like the UI does; clone it for background tasks
+    pub object: Entity,         // The object the template's own windows edit
+    pub(crate) meshes: PrefabMeshes<'a>,
+    pub(crate) materials: &'a mut Materials,
+    pub(crate) shader: &'static str, // The shader picked in the UI, which new meshes and materials use
+    pub(crate) redraw: bool,
+}
+
+// What an `Application` draws with in its `render` hook. Pipelines drawing into `target` with depth testing get
+// their depth state from `DepthConfig`
+pub struct RenderContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub target: &'a wgpu::TextureView, // The scene's color, at the scene's resolution
+    pub depth: &'a wgpu::TextureView,  // The scene's depth, in `DEPTH_FORMAT`
+    pub format: wgpu::TextureFormat,   // Of `target`
+    pub size: (u32, u32),
+    pub camera_layout: &'a wgpu::BindGroupLayout,
+    pub camera_bind_group: &'a wgpu::BindGroup, // The viewport camera's `CameraUniform`
+}
+
+impl AppContext<'_> {
+    /// Draws the next frame even when redraw-on-demand sees nothing it tracks change, as after moving entities.
+    pub fn request_redraw(&mut self) {
+        self.redraw = true;
+    }
+
+    /// Copies `mesh` into the shared geometry buffers and spawns an entity drawing it at `transform`.
+    pub fn spawn_mesh(&mut self, mesh: &Mesh, transform: Transform) -> Entity {
+        let material = MaterialHandle(self.materials.default_for(self.shader));
+        self.spawn_mesh_with_material(mesh, transform, material)
+    }
+
+    /// Like `spawn_mesh`, drawing with `material` rather than the shader's default material.
+    pub fn spawn_mesh_with_material(&mut self, mesh: &Mesh, transform: Transform, material: MaterialHandle) -> Entity {
+        let allocation = self.meshes.geometry.allocate(
+            self.meshes.device,
+            self.meshes.uploads,
+            self.meshes.stats,
+            &mesh.vertices,
+            &mesh.indices,
+        );
+        let handle = MeshHandle {
+            allocation,
+            bounds: mesh.bounds(),
+        };
+        self.redraw = true;
+        world::spawn_object(self.world, transform, handle, material)
+    }
+
+    /// Removes `entity`, freeing its mesh's space in the geometry buffers if it has one.
+    pub fn despawn(&mut self, entity: Entity) {
+        self.meshes.free(self.world, entity);
+        let _ = self.world.despawn(entity);
+        self.redraw = true;
+    }
+
+    /// Adds a material for the shader picked in the UI, starting from its default parameter values.
+    pub fn create_material(&mut self, name: &str) -> MaterialHandle {
+        let id = self.materials.create(self.meshes.device, self.meshes.stats, self.shader, name.to_string());
+        MaterialHandle(id)
+    }
+
+    /// Sets a float parameter of `material`. Returns false if its shader has no such parameter.
+    pub fn set_material_param(&mut self, material: MaterialHandle, name: &str, values: &[f32]) -> bool {
+        self.redraw = true;
+        self.materials.set_param(material.0, name, values)
+    }
+}
@@ -0,0 +1,320 @@
+// assets.rs
+
+use crate::jobs::{self, CancelToken, FrameBudget, Priority};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+
+// How often the watcher thread checks watched files for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+pub type Loader<T> = fn(&Path) -> io::Result<T>;
+
+// Stable reference to an asset; stays valid across reloads until the asset is removed
+pub struct Handle<T> {
+    id: u64,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(id: u64) -> Self {
+        Self {
+            id,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadState {
+    Loading,
+    Ready,
+    Failed(String), // The first load failed; a failed reload keeps the last good value instead
+}
+
+#[derive(Debug)]
+pub enum AssetEvent<T> {
+    Loaded(Handle<T>),
+    Reloaded(Handle<T>), // A watched file changed on disk and was read again
+    Failed(Handle<T>, String),
+}
+
+struct Slot<T> {
+    path: PathBuf,
+    state: LoadState,
+    value: Option<T>,
+    loading: Option<CancelToken>, // The first load, until its result is applied
+}
+
+struct Completed<T> {
+    id: u64,
+    result: io::Result<T>,
+    reload: bool,
+}
+
+struct Watch {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked: bool, // False until the first check has recorded a baseline
+}
+
+type WatchList = Mutex<HashMap<u64, Watch>>;
+
+// Files read on the job pool so the render loop never waits on disk. Finished loads are picked up by `poll`,
+// once per frame.
+pub struct Assets<T> {
+    loader: Loader<T>,
+    slots: HashMap<u64, Slot<T>>,
+    next_id: u64,
+    sender: Sender<Completed<T>>,
+    receiver: Receiver<Completed<T>>,
+    watched: Option<Arc<WatchList>>, // Shared with the watcher thread, started by the first watched asset
+    started: usize,                  // First loads begun since the last time none were in flight
+}
+
+impl<T: Send + 'static> Assets<T> {
+    pub fn new(loader: Loader<T>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            loader,
+            slots: HashMap::new(),
+            next_id: 0,
+            sender,
+            receiver,
+            watched: None,
+            started: 0,
+        }
+    }
+
+    /// Starts reading `path` in the background. With `watch`, the file is read again whenever it changes.
+    pub fn load(&mut self, path: impl Into<PathBuf>, watch: bool) -> Handle<T> {
+        let path = path.into();
+        if self.in_flight().next().is_none() {
+            self.started = 0;
+        }
+        self.started += 1;
+        let handle = self.add(path.clone(), LoadState::Loading, None, watch);
+        let (loader, sender) = (self.loader, self.sender.clone());
+        let cancel = jobs::spawn(Priority::Normal, move |_| {
+            let result = loader(&path);
+            let _ = sender.send(Completed {
+                id: handle.id,
+                result,
+                reload: false,
+            });
+        });
+        if let Some(slot) = self.slots.get_mut(&handle.id) {
+            slot.loading = Some(cancel);
+        }
+        handle
+    }
+
+    /// Adds an asset whose value is already known, such as one just written to `path`.
+    pub fn insert(&mut self, path: impl Into<PathBuf>, value: T, watch: bool) -> Handle<T> {
+        self.add(path.into(), LoadState::Ready, Some(value), watch)
+    }
+
+    fn add(&mut self, path: PathBuf, state: LoadState, value: Option<T>, watch: bool) -> Handle<T> {
+        let id = self.next_id;
+        self.next_id += 1;
+        if watch {
+            self.watch(id, path.clone());
+        }
+        let slot = Slot {
+            path,
+            state,
+            value,
+            loading: None,
+        };
+        self.slots.insert(id, slot);
+        Handle::new(id)
+    }
+
+    fn watch(&mut self, id: u64, path: PathBuf) {
+        // A browser has no files to watch, nor threads to watch them on
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        let watched = self.watched.get_or_insert_with(|| {
+            let watched = Arc::new(WatchList::default());
+            spawn_watcher(Arc::downgrade(&watched), self.loader, self.sender.clone());
+            watched
+        });
+        let watch = Watch {
+            path,
+            modified: None,
+            checked: false,
+        };
+        watched.lock().unwrap().insert(id, watch);
+    }
+
+    /// Applies loads finished since the last call and reports them, as many as `budget` leaves time for.
+    pub fn poll(&mut self, budget: &mut FrameBudget) -> Vec<AssetEvent<T>> {
+        let mut events = Vec::new();
+        while budget.has_time() {
+            let Ok(completed) = self.receiver.try_recv() else {
+                break;
+            };
+            let Some(slot) = self.slots.get_mut(&completed.id) else {
+                continue; // Removed while it was loading
+            };
+            budget.spend();
+            if !completed.reload {
+                slot.loading = None;
+            }
+            let handle = Handle::new(completed.id);
+            match completed.result {
+                Ok(value) => {
+                    slot.value = Some(value);
+                    slot.state = LoadState::Ready;
+                    events.push(if completed.reload {
+                        AssetEvent::Reloaded(handle)
+                    } else {
+                        AssetEvent::Loaded(handle)
+                    });
+                }
+                Err(err) => {
+                    if !completed.reload {
+                        slot.state = LoadState::Failed(err.to_string());
+                    }
+                    events.push(AssetEvent::Failed(handle, err.to_string()));
+                }
+            }
+        }
+        events
+    }
+
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        self.slots.get(&handle.id).and_then(|slot| slot.value.as_ref())
+    }
+
+    pub fn state(&self, handle: Handle<T>) -> Option<&LoadState> {
+        self.slots.get(&handle.id).map(|slot| &slot.state)
+    }
+
+    pub fn path(&self, handle: Handle<T>) -> Option<&Path> {
+        self.slots.get(&handle.id).map(|slot| slot.path.as_path())
+    }
+
+    /// The files whose first load hasn't been applied yet.
+    pub fn in_flight(&self) -> impl Iterator<Item = &Path> {
+        self.slots.values().filter(|slot| slot.loading.is_some()).map(|slot| slot.path.as_path())
+    }
+
+    /// How many of the first loads begun since none were in flight have been applied or dropped, and how many
+    /// were begun, for a progress bar. None while nothing is loading.
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        let pending = self.in_flight().count();
+        (pending > 0).then(|| (self.started.saturating_sub(pending), self.started.max(pending)))
+    }
+
+    /// Forgets the asset and stops watching its file, returning the value if it had loaded. A load still
+    /// queued is cancelled.
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        if let Some(watched) = &self.watched {
+            watched.lock().unwrap().remove(&handle.id);
+        }
+        let slot = self.slots.remove(&handle.id)?;
+        if let Some(loading) = slot.loading {
+            loading.cancel();
+        }
+        slot.value
+    }
+}
+
+/// The first loads in flight across several kinds of asset, shown as one progress bar.
+#[derive(Debug, Clone, Default)]
+pub struct LoadProgress {
+    pub done: usize,
+    pub total: usize,
+    pub files: Vec<PathBuf>,
+}
+
+impl LoadProgress {
+    pub fn add<T: Send + 'static>(&mut self, assets: &Assets<T>) {
+        if let Some((done, total)) = assets.progress() {
+            self.done += done;
+            self.total += total;
+            self.files.extend(assets.in_flight().map(Path::to_path_buf));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+}
+
+// Polls modification times and reloads changed files; exits once the owning `Assets` is dropped
+fn spawn_watcher<T: Send + 'static>(watched: Weak<WatchList>, loader: Loader<T>, sender: Sender<Completed<T>>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(WATCH_INTERVAL);
+        let Some(watched) = watched.upgrade() else {
+            return;
+        };
+        let paths: Vec<(u64, PathBuf)> =
+            watched.lock().unwrap().iter().map(|(id, watch)| (*id, watch.path.clone())).collect();
+        for (id, path) in paths {
+            // A missing file is skipped rather than reported, as editors often save by replacing the file
+            let Ok(modified) = std::fs::metadata(&path).and_then(|metadata| metadata.modified()) else {
+                continue;
+            };
+            let changed = match watched.lock().unwrap().get_mut(&id) {
+                Some(watch) => {
+                    let changed = watch.checked && watch.modified != Some(modified);
+                    watch.modified = Some(modified);
+                    watch.checked = true;
+                    changed
+                }
+                None => false, // Removed since the list was copied
+            };
+            if changed {
+                let result = loader(&path);
+                if sender.send(Completed { id, result, reload: true }).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
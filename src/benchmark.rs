@@ -0,0 +1,150 @@
+// benchmark.rs
+
+use crate::camera::Camera;
+use crate::morph::MAX_SIDES;
+use crate::scene::SceneFile;
+use crate::transform::Transform;
+use crate::ui::RenderingStyle;
+use glam::{Quat, Vec3};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_FRAMES: u32 = 1000;
+const DEFAULT_OUTPUT: &str = "benchmark.json";
+// Drawn before measuring, so pipelines finish compiling and caches and clocks settle
+const WARMUP_FRAMES: u32 = 60;
+// Full orbits of the camera path over the measured frames
+const ORBITS: f32 = 2.0;
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl Summary {
+    fn new(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let p99 = ((sorted.len() as f64 * 0.99).ceil() as usize).clamp(1, sorted.len()) - 1;
+        Some(Self {
+            min_ms: sorted[0],
+            avg_ms: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p99_ms: sorted[p99],
+            max_ms: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BenchmarkReport {
+    pub adapter: String,
+    pub backend: String,
+    pub surface_size: (u32, u32),
+    pub frames: u32,
+    pub cpu: Option<Summary>,
+    pub gpu: Option<Summary>, // None without timestamp query support
+}
+
+// `--benchmark [frames]` run: a fixed scene seen from a camera flying the same path every time, with CPU and
+// GPU frame times written to JSON, and per frame to CSV next to it, so runs before and after a change compare
+pub struct Benchmark {
+    frames: u32,
+    frame: u32, // Counting the warmup
+    output: PathBuf,
+    cpu_ms: Vec<f64>,
+    gpu_ms: Vec<Option<f64>>,
+}
+
+impl Benchmark {
+    /// Reads `--benchmark [frames]` and `--benchmark-output <file.json>` from the command line.
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        let index = args.iter().position(|arg| arg == "--benchmark")?;
+        let frames = args.get(index + 1).and_then(|arg| arg.parse().ok()).unwrap_or(DEFAULT_FRAMES).max(1);
+        let output = args
+            .iter()
+            .skip_while(|arg| *arg != "--benchmark-output")
+            .nth(1)
+            .map_or_else(|| PathBuf::from(DEFAULT_OUTPUT), PathBuf::from);
+        Some(Self {
+            frames,
+            frame: 0,
+            output,
+            cpu_ms: Vec::with_capacity(frames as usize),
+            gpu_ms: Vec::with_capacity(frames as usize),
+        })
+    }
+
+    /// The scene every run draws, independent of the user's settings and last open scene.
+    pub fn scene() -> SceneFile {
+        SceneFile {
+            rendering_style: RenderingStyle::Polygon,
+            sides: MAX_SIDES,
+            radius: 0.5,
+            transform: Transform::IDENTITY,
+            ..SceneFile::default()
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= WARMUP_FRAMES + self.frames
+    }
+
+    /// Camera for the current frame: orbits `start`'s target, tilting up and down, at a pace set by the
+    /// frame count rather than the clock, so every run renders the same images.
+    pub fn camera(&self, start: &Camera) -> Camera {
+        let t = self.frame.saturating_sub(WARMUP_FRAMES) as f32 / self.frames as f32;
+        let angle = t * ORBITS * std::f32::consts::TAU;
+        let tilt = (angle * 0.5).sin() * 0.5;
+        let rotation = Quat::from_rotation_y(angle) * Quat::from_rotation_x(tilt);
+        Camera {
+            position: start.target + rotation * (start.position - start.target),
+            up: Vec3::Y,
+            ..*start
+        }
+    }
+
+    /// Records the frame just presented. `gpu` may lag the CPU time by a few frames, which only shifts the
+    /// per-frame CSV since every frame draws the same scene.
+    pub fn record(&mut self, cpu: Duration, gpu: Option<Duration>) {
+        if self.frame >= WARMUP_FRAMES && !self.is_finished() {
+            self.cpu_ms.push(cpu.as_secs_f64() * 1000.0);
+            self.gpu_ms.push(gpu.map(|gpu| gpu.as_secs_f64() * 1000.0));
+        }
+        self.frame += 1;
+    }
+
+    pub fn report(&self, adapter: &str, backend: &str, surface_size: (u32, u32)) -> BenchmarkReport {
+        let gpu_ms: Vec<f64> = self.gpu_ms.iter().flatten().copied().collect();
+        BenchmarkReport {
+            adapter: adapter.to_string(),
+            backend: backend.to_string(),
+            surface_size,
+            frames: self.cpu_ms.len() as u32,
+            cpu: Summary::new(&self.cpu_ms),
+            gpu: Summary::new(&gpu_ms),
+        }
+    }
+
+    /// Writes the report and the per-frame times. Returns the JSON path.
+    pub fn write(&self, report: &BenchmarkReport) -> io::Result<&Path> {
+        let json = serde_json::to_string_pretty(report).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&self.output, json)?;
+        let mut csv = String::from("frame,cpu_ms,gpu_ms\n");
+        for (frame, (cpu, gpu)) in self.cpu_ms.iter().zip(&self.gpu_ms).enumerate() {
+            let gpu = gpu.map_or(String::new(), |gpu| format!("{gpu:.4}"));
+            let _ = writeln!(csv, "{frame},{cpu:.4},{gpu}");
+        }
+        fs::write(self.output.with_extension("csv"), csv)?;
+        Ok(&self.output)
+    }
+}
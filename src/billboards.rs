@@ -0,0 +1,510 @@
+// billboards.rs
+
+use crate::assets::{AssetEvent, Assets};
+use crate::camera::Camera;
+use crate::depth::DepthConfig;
+use crate::file_dialog::{FileDialogs, FileKind};
+use crate::jobs::FrameBudget;
+use crate::texture::{GpuTexture, TextureImage};
+use crate::stats::RenderStats;
+use crate::toasts::Toasts;
+use crate::transform::Transform;
+use crate::upload::Uploads;
+use crate::world::{self, GlobalTransform};
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::{Vec2, Vec3};
+use hecs::{Entity, World};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Billboards the shader's uniform has room for; past this they are left out
+pub const MAX_BILLBOARDS: usize = 256;
+
+// Side of the built-in marker texture, in texels
+const MARKER_SIZE: u32 = 64;
+
+// Which way a billboard turns to face the camera
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BillboardAlignment {
+    Spherical,   // Always square on to the view, like a sprite
+    Cylindrical, // Only about the entity's up axis, so it stays upright, like a tree impostor
+}
+
+impl BillboardAlignment {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BillboardAlignment::Spherical => "Spherical",
+            BillboardAlignment::Cylindrical => "Cylindrical",
+        }
+    }
+}
+
+// A textured quad at the entity's position that turns to face the camera. The entity's scale multiplies its
+// size and its rotation gives the up axis of cylindrical billboards
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Billboard {
+    pub texture: Option<PathBuf>, // None shows the built-in marker
+    pub color: Vec3,              // Linear tint the texture is multiplied by
+    pub size: Vec2,               // Width and height in world units
+    pub alignment: BillboardAlignment,
+    pub cutoff: f32, // Texels less opaque than this are cut out, and hide nothing behind them
+}
+
+impl Default for Billboard {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            color: Vec3::ONE,
+            size: Vec2::ONE,
+            alignment: BillboardAlignment::Spherical,
+            cutoff: 0.5,
+        }
+    }
+}
+
+// The billboard as laid out in the shader's `Billboards` uniform
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct GpuBillboard {
+    center: [f32; 4], // w: 1 for cylindrical alignment
+    up: [f32; 4],     // w: alpha cutoff
+    color: [f32; 4],
+    size: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct BillboardsUniform {
+    view_proj: [[f32; 4]; 4],
+    camera_right: [f32; 4],
+    camera_up: [f32; 4],
+    camera_position: [f32; 4],
+    billboards: [GpuBillboard; MAX_BILLBOARDS],
+}
+
+// The list of billboards in the world
+pub struct Billboards {
+    choosing: Option<Entity>, // Billboard waiting on the texture dialog
+}
+
+impl Billboards {
+    pub fn new() -> Self {
+        Self { choosing: None }
+    }
+
+    /// Spawns a billboard with the built-in marker at `position`.
+    pub fn place(&mut self, world: &mut World, position: Vec3) -> Entity {
+        let transform = Transform {
+            translation: position,
+            ..Transform::IDENTITY
+        };
+        world.spawn((transform, GlobalTransform(transform.matrix()), Billboard::default()))
+    }
+
+    pub fn is_choosing(&self) -> bool {
+        self.choosing.is_some()
+    }
+
+    /// Shows the image picked in the texture dialog on the billboard whose Choose button opened it.
+    pub fn place_texture(&mut self, world: &mut World, path: PathBuf) {
+        if let Some(entity) = self.choosing.take() {
+            if let Ok(mut billboard) = world.get::<&mut Billboard>(entity) {
+                billboard.texture = Some(path);
+            }
+        }
+    }
+
+    /// The Billboards window: lists every billboard in the world, adds new ones at `placement`, and edits their
+    /// textures, sizes and alignment.
+    pub fn show(&mut self, ctx: &Context, world: &mut World, placement: Vec3, file_dialogs: &mut FileDialogs) {
+        egui::Window::new("Billboards")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                let mut billboards: Vec<Entity> =
+                    world.query::<(Entity, &Billboard)>().iter().map(|(e, _)| e).collect();
+                billboards.sort();
+                if ui.button("Add billboard").clicked() {
+                    self.place(world, placement);
+                }
+                if billboards.len() > MAX_BILLBOARDS {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!("Billboards past the first {MAX_BILLBOARDS} are not drawn"),
+                    );
+                }
+
+                let mut removed = None;
+                for (index, entity) in billboards.into_iter().enumerate() {
+                    egui::CollapsingHeader::new(format!("Billboard {}", index + 1)).id_source(entity).show(ui, |ui| {
+                        ui.push_id(entity, |ui| {
+                            if let Ok(mut billboard) = world.get::<&mut Billboard>(entity) {
+                                let name = billboard.texture.as_deref().and_then(Path::file_name);
+                                let name = name.map_or("Marker".into(), |name| name.to_string_lossy().into_owned());
+                                ui.horizontal(|ui| {
+                                    ui.label(name);
+                                    if ui.button("Choose…").clicked() {
+                                        self.choosing = Some(entity);
+                                        file_dialogs.open(FileKind::Texture);
+                                    }
+                                    if billboard.texture.is_some() && ui.button("Clear").clicked() {
+                                        billboard.texture = None;
+                                    }
+                                });
+                                ui.horizontal(|ui| {
+                                    let mut color = billboard.color.to_array();
+                                    if ui.color_edit_button_rgb(&mut color).changed() {
+                                        billboard.color = Vec3::from(color);
+                                    }
+                                    let alignment = billboard.alignment;
+                                    egui::ComboBox::from_id_source("alignment")
+                                        .selected_text(alignment.label())
+                                        .show_ui(ui, |ui| {
+                                            for alignment in
+                                                [BillboardAlignment::Spherical, BillboardAlignment::Cylindrical]
+                                            {
+                                                ui.selectable_value(
+                                                    &mut billboard.alignment,
+                                                    alignment,
+                                                    alignment.label(),
+                                                );
+                                            }
+                                        });
+                                });
+                                ui.add(egui::Slider::new(&mut billboard.cutoff, 0.0..=1.0).text("Alpha cutoff"));
+                                egui::Grid::new("billboard_size").num_columns(2).show(ui, |ui| {
+                                    ui.label("Size");
+                                    ui.horizontal(|ui| {
+                                        for axis in 0..2 {
+                                            let size = egui::DragValue::new(&mut billboard.size[axis]).speed(0.01);
+                                            ui.add(size.range(0.01..=1000.0));
+                                        }
+                                    });
+                                    ui.end_row();
+                                });
+                            }
+                            let mut transform = world::transform_mut(world, entity);
+                            ui.horizontal(|ui| {
+                                ui.label("Position");
+                                for axis in 0..3 {
+                                    ui.add(egui::DragValue::new(&mut transform.translation[axis]).speed(0.01));
+                                }
+                            });
+                            if ui.button("Remove").clicked() {
+                                removed = Some(entity);
+                            }
+                        });
+                    });
+                }
+                if let Some(entity) = removed {
+                    let _ = world.despawn(entity);
+                }
+            });
+    }
+}
+
+impl Default for Billboards {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A billboard texture with the bind group the billboard pass samples it through
+struct BillboardTexture {
+    gpu: GpuTexture,
+    bind_group: wgpu::BindGroup,
+}
+
+// Draws every billboard as a quad built in the vertex shader, turned to the camera. Texels under the cutoff are
+// discarded and the rest write depth, so billboards hide and are hidden by the scene like any other surface
+pub struct BillboardRenderer {
+    pipeline: wgpu::RenderPipeline,
+    texture_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    marker: BillboardTexture,
+    images: Assets<TextureImage>,
+    textures: HashMap<PathBuf, Option<BillboardTexture>>, // None until the file has loaded
+}
+
+impl BillboardRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Billboard Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("billboards.wgsl").into()),
+        });
+        let uniform_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Billboard Uniform Buffer"),
+                size: size_of::<BillboardsUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Billboard Uniform Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Billboard Uniform Bind Group"),
+            layout: &uniform_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Billboard Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Billboard Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Billboard Pipeline Layout"),
+            bind_group_layouts: &[&uniform_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Billboard Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            // Cylindrical billboards can be seen from behind when looking down their up axis
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(DepthConfig::OPAQUE.state()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let marker = GpuTexture::new(device, stats, uploads, &marker());
+        let marker = BillboardTexture {
+            bind_group: Self::texture_bind_group(device, &texture_layout, &sampler, &marker),
+            gpu: marker,
+        };
+        Self {
+            pipeline,
+            texture_layout,
+            uniform_buffer,
+            uniform_bind_group,
+            sampler,
+            marker,
+            images: Assets::new(TextureImage::load),
+            textures: HashMap::new(),
+        }
+    }
+
+    fn texture_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        texture: &GpuTexture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Billboard Texture Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Starts loading the textures of billboards in `world` that no billboard used before, and turns finished
+    /// loads into textures. Files are reloaded when edited on disk. Returns whether any texture changed.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        budget: &mut FrameBudget,
+        world: &World,
+        toasts: &mut Toasts,
+    ) -> bool {
+        let mut changed = false;
+        for billboard in world.query::<&Billboard>().iter() {
+            if let Some(path) = billboard.texture.as_ref().filter(|path| !self.textures.contains_key(*path)) {
+                self.images.load(path, true);
+                self.textures.insert(path.clone(), None);
+            }
+        }
+        for event in self.images.poll(budget) {
+            match event {
+                AssetEvent::Loaded(handle) | AssetEvent::Reloaded(handle) => {
+                    let (Some(image), Some(path)) = (self.images.get(handle), self.images.path(handle)) else {
+                        continue;
+                    };
+                    let gpu = GpuTexture::new(device, stats, uploads, image);
+                    let texture = BillboardTexture {
+                        bind_group: Self::texture_bind_group(device, &self.texture_layout, &self.sampler, &gpu),
+                        gpu,
+                    };
+                    let previous = self.textures.insert(path.to_path_buf(), Some(texture));
+                    if let Some(Some(previous)) = previous {
+                        stats.texture_destroyed(&previous.gpu.texture);
+                    }
+                    changed = true;
+                }
+                AssetEvent::Failed(handle, err) => {
+                    let path = self.images.path(handle).map(Path::to_path_buf).unwrap_or_default();
+                    log::warn!("Failed to load billboard texture from {}: {err}", path.display());
+                    toasts.error(format!("Failed to load billboard texture: {err}"));
+                }
+            }
+        }
+        changed
+    }
+
+    /// Draws the billboards of `world` into `target`, seen through `camera`, depth tested against and writing
+    /// to the scene's `depth`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        world: &World,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) {
+        let mut query = world.query::<(Entity, &Billboard, &GlobalTransform)>();
+        let mut billboards: Vec<(Entity, &Billboard, &GlobalTransform)> = query.iter().collect();
+        if billboards.is_empty() {
+            return;
+        }
+        billboards.sort_by_key(|(entity, _, _)| *entity);
+        billboards.truncate(MAX_BILLBOARDS);
+
+        let view = camera.view_matrix();
+        let mut uniform = BillboardsUniform {
+            view_proj: camera.view_projection_matrix().to_cols_array_2d(),
+            camera_right: view.row(0).truncate().extend(0.0).to_array(),
+            camera_up: view.row(1).truncate().extend(0.0).to_array(),
+            camera_position: camera.position.extend(1.0).to_array(),
+            billboards: [GpuBillboard::zeroed(); MAX_BILLBOARDS],
+        };
+        for (slot, (_, billboard, global)) in uniform.billboards.iter_mut().zip(&billboards) {
+            let (scale, rotation, translation) = global.0.to_scale_rotation_translation();
+            let cylindrical = billboard.alignment == BillboardAlignment::Cylindrical;
+            *slot = GpuBillboard {
+                center: translation.extend(if cylindrical { 1.0 } else { 0.0 }).to_array(),
+                up: (rotation * Vec3::Y).extend(billboard.cutoff).to_array(),
+                color: billboard.color.extend(1.0).to_array(),
+                size: (billboard.size * scale.truncate()).extend(0.0).extend(0.0).to_array(),
+            };
+        }
+        uploads.write(device, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Billboard Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        for (index, (_, billboard, _)) in billboards.iter().enumerate() {
+            // Still loading, or failed to: the marker stands in
+            let texture = billboard.texture.as_ref().and_then(|path| self.textures.get(path)?.as_ref());
+            pass.set_bind_group(1, &texture.unwrap_or(&self.marker).bind_group, &[]);
+            let index = index as u32;
+            pass.draw(0..6, index..index + 1);
+            stats.record_draw(6, 1);
+        }
+    }
+}
+
+// A white dot inside a ring, so billboards show something before any texture is chosen
+fn marker() -> TextureImage {
+    let mut pixels = Vec::with_capacity((MARKER_SIZE * MARKER_SIZE * 4) as usize);
+    for y in 0..MARKER_SIZE {
+        for x in 0..MARKER_SIZE {
+            let offset = (Vec2::new(x as f32, y as f32) + 0.5) / MARKER_SIZE as f32 * 2.0 - 1.0;
+            let distance = offset.length();
+            let dot = (1.0 - (distance - 0.3).max(0.0) * 20.0).clamp(0.0, 1.0);
+            let ring = (1.0 - ((distance - 0.8).abs() - 0.08).max(0.0) * 20.0).clamp(0.0, 1.0);
+            let alpha = dot.max(ring);
+            pixels.extend_from_slice(&[255, 255, 255, (alpha * 255.0) as u8]);
+        }
+    }
+    TextureImage::new(MARKER_SIZE, MARKER_SIZE, pixels)
+}
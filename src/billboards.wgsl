@@ -0,0 +1,70 @@
+// Billboards: a quad per billboard, built here from the billboard's center and turned to face the camera,
+// either fully or only about the billboard's up axis
+
+struct Billboard {
+    center: vec4<f32>, // w: 1 for cylindrical alignment
+    up: vec4<f32>, // The axis cylindrical billboards turn about; w: alpha cutoff
+    color: vec4<f32>, // Linear tint
+    size: vec4<f32>, // Width and height in world units
+};
+struct Billboards {
+    view_proj: mat4x4<f32>,
+    camera_right: vec4<f32>,
+    camera_up: vec4<f32>,
+    camera_position: vec4<f32>,
+    billboards: array<Billboard, 256>,
+};
+@group(0) @binding(0)
+var<uniform> billboards: Billboards;
+
+@group(1) @binding(0)
+var billboard_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var billboard_sampler: sampler;
+
+// Two triangles covering the quad, centered on the billboard
+const CORNERS = array<vec2<f32>, 6>(
+    vec2<f32>(-0.5, -0.5), vec2<f32>(0.5, -0.5), vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, -0.5), vec2<f32>(0.5, 0.5), vec2<f32>(-0.5, 0.5),
+);
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) billboard: u32,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, @builtin(instance_index) billboard: u32) -> VertexOutput {
+    var corners = CORNERS;
+    let corner = corners[index];
+    let b = billboards.billboards[billboard];
+    var right = billboards.camera_right.xyz;
+    var up = billboards.camera_up.xyz;
+    if b.center.w > 0.5 {
+        // Upright along its own axis, turned about it as far as it can towards the camera
+        let to_camera = billboards.camera_position.xyz - b.center.xyz;
+        let side = cross(b.up.xyz, to_camera);
+        if dot(side, side) > 1e-8 {
+            up = normalize(b.up.xyz);
+            right = normalize(side);
+        }
+    }
+    let position = b.center.xyz + right * corner.x * b.size.x + up * corner.y * b.size.y;
+    var out: VertexOutput;
+    out.clip_position = billboards.view_proj * vec4<f32>(position, 1.0);
+    out.uv = vec2<f32>(corner.x + 0.5, 0.5 - corner.y);
+    out.billboard = billboard;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let b = billboards.billboards[in.billboard];
+    let texel = textureSample(billboard_texture, billboard_sampler, in.uv);
+    // Cut out rather than blended, so what is left can write depth in any order
+    if texel.a < b.up.w {
+        discard;
+    }
+    return vec4<f32>(texel.rgb * b.color.rgb, texel.a);
+}
@@ -0,0 +1,112 @@
+// bind_groups.rs
+
+use egui_wgpu::wgpu;
+use std::collections::HashMap;
+use std::num::NonZeroU64;
+use std::sync::{Arc, Weak};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    layout: wgpu::Id<wgpu::BindGroupLayout>,
+    buffers: Vec<(u32, wgpu::Id<wgpu::Buffer>, Option<NonZeroU64>)>, // Binding, buffer and bound size if not whole
+}
+
+struct Entry {
+    bind_group: Arc<wgpu::BindGroup>,
+    buffers: Vec<Weak<wgpu::Buffer>>, // Watched so the entry goes away once any of them is dropped
+}
+
+// Bind groups shared by everything binding the same buffers through the same layout, created on first use
+pub struct BindGroupCache {
+    entries: HashMap<Key, Entry>,
+}
+
+impl BindGroupCache {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Bind group with each buffer bound whole at its binding index, reusing an existing one when the
+    /// layout and buffers match.
+    pub fn get(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::BindGroupLayout,
+        buffers: &[(u32, &Arc<wgpu::Buffer>)],
+    ) -> Arc<wgpu::BindGroup> {
+        let bindings: Vec<_> = buffers.iter().map(|(binding, buffer)| (*binding, *buffer, None)).collect();
+        self.get_or_create(device, label, layout, &bindings)
+    }
+
+    /// Like `get`, but binds only the first `size` bytes of each buffer, as bindings used with dynamic
+    /// offsets require.
+    pub fn get_ranged(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::BindGroupLayout,
+        buffers: &[(u32, &Arc<wgpu::Buffer>, NonZeroU64)],
+    ) -> Arc<wgpu::BindGroup> {
+        let bindings: Vec<_> =
+            buffers.iter().map(|(binding, buffer, size)| (*binding, *buffer, Some(*size))).collect();
+        self.get_or_create(device, label, layout, &bindings)
+    }
+
+    fn get_or_create(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::BindGroupLayout,
+        buffers: &[(u32, &Arc<wgpu::Buffer>, Option<NonZeroU64>)],
+    ) -> Arc<wgpu::BindGroup> {
+        let key = Key {
+            layout: layout.global_id(),
+            buffers: buffers.iter().map(|(binding, buffer, size)| (*binding, buffer.global_id(), *size)).collect(),
+        };
+        let entry = self.entries.entry(key).or_insert_with(|| {
+            let entries: Vec<wgpu::BindGroupEntry> = buffers
+                .iter()
+                .map(|(binding, buffer, size)| wgpu::BindGroupEntry {
+                    binding: *binding,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer,
+                        offset: 0,
+                        size: *size,
+                    }),
+                })
+                .collect();
+            Entry {
+                bind_group: Arc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(label),
+                    layout,
+                    entries: &entries,
+                })),
+                buffers: buffers.iter().map(|(_, buffer, _)| Arc::downgrade(buffer)).collect(),
+            }
+        });
+        entry.bind_group.clone()
+    }
+
+    /// Drops the bind groups of buffers that no longer exist, so their ids can't match a new buffer.
+    pub fn evict_dropped(&mut self) {
+        self.entries
+            .retain(|_, entry| entry.buffers.iter().all(|buffer| buffer.strong_count() > 0));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Default for BindGroupCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,93 @@
+// bounds.rs
+
+use glam::{Mat4, Vec3, Vec4};
+
+// Axis-aligned bounding box
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self::new(first, first), |aabb, p| {
+            Self::new(aabb.min.min(p), aabb.max.max(p))
+        }))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    // Bounds of this box after transforming it, still axis-aligned so it may grow under rotation
+    pub fn transformed(&self, matrix: Mat4) -> Self {
+        Self::from_points(self.corners().map(|c| matrix.transform_point3(c))).unwrap_or(*self)
+    }
+
+    // Radius of the bounding sphere enclosing the box
+    pub fn radius(&self) -> f32 {
+        self.size().length() * 0.5
+    }
+
+    /// Distance along `direction` at which a ray from `origin` enters the box, None if it misses.
+    /// Returns 0 when the origin is inside.
+    pub fn ray_intersection(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        // Slab test; dividing by zero gives infinities that compare correctly
+        let inverse = direction.recip();
+        let t1 = (self.min - origin) * inverse;
+        let t2 = (self.max - origin) * inverse;
+        let near = t1.min(t2).max_element();
+        let far = t1.max(t2).min_element();
+        (far >= near.max(0.0)).then_some(near.max(0.0))
+    }
+}
+
+// The six planes of a camera's view volume, each pointing inwards
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view-projection matrix with wgpu's 0..1 depth range.
+    pub fn from_view_projection(view_proj: Mat4) -> Self {
+        let (x, y, z, w) = (view_proj.row(0), view_proj.row(1), view_proj.row(2), view_proj.row(3));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|p| p / p.truncate().length().max(1e-6));
+        Self { planes }
+    }
+
+    // Conservative: a box straddling a plane's corner region may pass even though it is outside
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The box corner furthest along the plane normal
+            let normal = plane.truncate();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
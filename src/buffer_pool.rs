@@ -0,0 +1,118 @@
+// buffer_pool.rs
+
+use crate::stats::RenderStats;
+use egui_wgpu::wgpu;
+use std::sync::Arc;
+
+// Idle buffers unused for this many frames are freed, so a one-off burst doesn't pin memory forever
+const TRIM_AFTER_FRAMES: u64 = 300;
+// Smallest size handed out, so tiny requests of varying size share buffers
+const MIN_SIZE: wgpu::BufferAddress = 256;
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BufferPoolStats {
+    pub buffers: u32, // Owned by the pool, in use or idle
+    pub bytes: u64,
+    pub acquired: u64,
+    pub reused: u64,
+}
+
+impl BufferPoolStats {
+    pub fn reuse_rate(&self) -> f64 {
+        if self.acquired == 0 {
+            return 0.0;
+        }
+        self.reused as f64 / self.acquired as f64
+    }
+}
+
+struct PooledBuffer {
+    buffer: Arc<wgpu::Buffer>,
+    last_used: u64, // Frame it was last handed out
+}
+
+// Short-lived buffers such as readbacks and per-frame data, recycled across frames instead of created and
+// dropped each time. A buffer is idle again once every handle to it is dropped; the queue orders work on it,
+// so handing it straight back out is safe even if the GPU hasn't finished with it yet
+pub struct BufferPool {
+    buffers: Vec<PooledBuffer>,
+    frame: u64,
+    stats: BufferPoolStats,
+}
+
+impl BufferPool {
+    pub fn new() -> Self {
+        Self {
+            buffers: Vec::new(),
+            frame: 0,
+            stats: BufferPoolStats::default(),
+        }
+    }
+
+    /// Returns an idle buffer with exactly `usage` and room for `size` bytes, or creates one. Sizes are
+    /// rounded up to a power of two, so the buffer may be larger than asked for.
+    pub fn acquire(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        label: &str,
+        usage: wgpu::BufferUsages,
+        size: wgpu::BufferAddress,
+    ) -> Arc<wgpu::Buffer> {
+        self.stats.acquired += 1;
+        let size = size.max(MIN_SIZE).next_power_of_two();
+        let idle = self
+            .buffers
+            .iter_mut()
+            .filter(|pooled| Arc::strong_count(&pooled.buffer) == 1)
+            .filter(|pooled| pooled.buffer.usage() == usage && pooled.buffer.size() >= size)
+            .min_by_key(|pooled| pooled.buffer.size());
+        if let Some(pooled) = idle {
+            self.stats.reused += 1;
+            pooled.last_used = self.frame;
+            return Arc::clone(&pooled.buffer);
+        }
+
+        let buffer = Arc::new(stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            },
+        ));
+        self.buffers.push(PooledBuffer {
+            buffer: Arc::clone(&buffer),
+            last_used: self.frame,
+        });
+        buffer
+    }
+
+    /// Frees buffers that have sat idle for a while. Call once per frame.
+    pub fn end_frame(&mut self, stats: &mut RenderStats) {
+        self.frame += 1;
+        let frame = self.frame;
+        self.buffers.retain(|pooled| {
+            let stale = Arc::strong_count(&pooled.buffer) == 1 && frame - pooled.last_used > TRIM_AFTER_FRAMES;
+            if stale {
+                stats.buffer_destroyed(&pooled.buffer);
+            }
+            !stale
+        });
+    }
+
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            buffers: self.buffers.len() as u32,
+            bytes: self.buffers.iter().map(|pooled| pooled.buffer.size()).sum(),
+            ..self.stats
+        }
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
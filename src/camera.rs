@@ -1,13 +1,54 @@
 // camera.rs
 
-use glam::{Mat4, Vec3};
+use crate::bounds::Aabb;
+use bytemuck::{Pod, Zeroable};
+use egui_wgpu::wgpu;
+use glam::{Mat4, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
 
-#[derive(Debug, Copy, Clone)]
+// The vertical fields of view, in degrees, the UI offers and zooming stays within
+pub const FOV_RANGE: RangeInclusive<f32> = 10.0..=120.0;
+
+// The heights, in world units, an orthographic view can be zoomed between
+pub const ORTHO_HEIGHT_RANGE: RangeInclusive<f32> = 0.01..=10_000.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    Orthographic, // Spans `ortho_height` world units vertically, however far away things are
+    Pixels,       // Orthographic at one world unit per pixel of the view, for 2D tools
+}
+
+impl Projection {
+    pub const ALL: [Projection; 3] = [Projection::Perspective, Projection::Orthographic, Projection::Pixels];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "Perspective",
+            Projection::Orthographic => "Orthographic",
+            Projection::Pixels => "2D (pixels)",
+        }
+    }
+
+    pub fn is_orthographic(&self) -> bool {
+        !matches!(self, Projection::Perspective)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Camera {
     pub position: Vec3,
     pub target: Vec3,
     pub up: Vec3,
     pub speed: f32,
+    pub aspect: f32,
+    pub fovy: f32, // Vertical field of view in degrees
+    pub znear: f32,
+    pub zfar: f32,
+    pub projection: Projection,
+    pub ortho_height: f32, // World units an orthographic view spans vertically; the view's pixels with `Pixels`
 }
 
 impl Camera {
@@ -17,6 +58,12 @@ impl Camera {
             target,
             up: Vec3::Y, // Default up direction
             speed,
+            aspect: 16.0 / 9.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            projection: Projection::Perspective,
+            ortho_height: 5.0,
         }
     }
 
@@ -24,6 +71,74 @@ impl Camera {
         Mat4::look_at_rh(self.position, self.target, self.up)
     }
 
+    /// Orthographic views keep everything within `zfar` in front of or behind the camera, so what they show
+    /// doesn't depend on where along the view direction it sits.
+    pub fn projection_matrix(&self) -> Mat4 {
+        if !self.projection.is_orthographic() {
+            return Mat4::perspective_rh(self.fovy.to_radians(), self.aspect, self.znear, self.zfar);
+        }
+        let half_height = self.ortho_height * 0.5;
+        let half_width = half_height * self.aspect;
+        Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, -self.zfar, self.zfar)
+    }
+
+    /// How many world units the view spans vertically at `distance` from the camera.
+    pub fn view_height(&self, distance: f32) -> f32 {
+        match self.projection {
+            Projection::Perspective => 2.0 * distance * (self.fovy.to_radians() * 0.5).tan(),
+            Projection::Orthographic | Projection::Pixels => self.ortho_height,
+        }
+    }
+
+    /// Switches projections, keeping what's at the target the same size on screen where it can.
+    pub fn set_projection(&mut self, projection: Projection) {
+        if projection == Projection::Orthographic && self.projection == Projection::Perspective {
+            let height = self.view_height((self.target - self.position).length());
+            self.ortho_height = height.clamp(*ORTHO_HEIGHT_RANGE.start(), *ORTHO_HEIGHT_RANGE.end());
+        }
+        self.projection = projection;
+    }
+
+    /// Fits the camera to a view of `width` by `height` pixels: its aspect, and with `Pixels`, its height.
+    pub fn set_viewport(&mut self, width: u32, height: u32) {
+        self.aspect = width as f32 / height.max(1) as f32;
+        if self.projection == Projection::Pixels {
+            self.ortho_height = height.max(1) as f32;
+        }
+    }
+
+    pub fn view_projection_matrix(&self) -> Mat4 {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    /// World-space ray through a point given in normalized device coordinates (-1..1, Y up).
+    /// Returns the origin on the near plane and a unit direction.
+    pub fn screen_ray(&self, ndc: Vec2) -> (Vec3, Vec3) {
+        let inverse = self.view_projection_matrix().inverse();
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+        (near, (far - near).normalize_or_zero())
+    }
+
+    /// Moves the camera back along its current view direction until `bounds` fits the view. An orthographic view
+    /// is zoomed to fit instead; a pixel view only centers on it.
+    pub fn frame_bounds(&mut self, bounds: &Aabb) {
+        let direction = (self.target - self.position).normalize_or_zero();
+        let direction = if direction == Vec3::ZERO { Vec3::NEG_Z } else { direction };
+
+        // Fit the bounding sphere against the narrower of the two fields of view
+        let half_fovy = self.fovy.to_radians() * 0.5;
+        let half_fovx = (half_fovy.tan() * self.aspect).atan();
+        let distance = bounds.radius().max(0.01) / half_fovy.min(half_fovx).sin();
+        if self.projection == Projection::Orthographic {
+            let height = 2.0 * bounds.radius().max(0.01) * self.aspect.recip().max(1.0);
+            self.ortho_height = height.clamp(*ORTHO_HEIGHT_RANGE.start(), *ORTHO_HEIGHT_RANGE.end());
+        }
+
+        self.target = bounds.center();
+        self.position = self.target - direction * distance;
+    }
+
     pub fn move_forward(&mut self) {
         let direction = (self.target - self.position).normalize();
         self.position += direction * self.speed;
@@ -50,3 +165,85 @@ impl Camera {
         self.target += strafe_direction * self.speed;
     }
 }
+
+// Camera data as laid out in the shaders' uniform buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct CameraUniform {
+    pub view_proj: [[f32; 4]; 4],
+    pub position: [f32; 4], // w unused
+}
+
+impl CameraUniform {
+    pub fn new() -> Self {
+        Self {
+            view_proj: Mat4::IDENTITY.to_cols_array_2d(),
+            position: [0.0; 4],
+        }
+    }
+
+    pub fn update_view_proj(&mut self, camera: &Camera) {
+        self.view_proj = camera.view_projection_matrix().to_cols_array_2d();
+        self.position = camera.position.extend(1.0).to_array();
+    }
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A projection for a field of view given as the tangents of its left, right, top and bottom edges, which may be
+/// asymmetric, as OpenXR and stereo eyes have them. Depth runs 0 to 1 from `near` to `far`, as `Mat4::perspective_rh`
+/// has it.
+pub fn off_center_projection((left, right, up, down): (f32, f32, f32, f32), near: f32, far: f32) -> Mat4 {
+    let (width, height) = (right - left, up - down);
+    Mat4::from_cols_array(&[
+        2.0 / width,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        2.0 / height,
+        0.0,
+        0.0,
+        (right + left) / width,
+        (up + down) / height,
+        far / (near - far),
+        -1.0,
+        0.0,
+        0.0,
+        near * far / (near - far),
+        0.0,
+    ])
+}
+
+/// Layout of group 0 in every scene pipeline: the camera at binding 0 and the scene's lights at binding 1.
+pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Camera Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
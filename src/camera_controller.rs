@@ -0,0 +1,259 @@
+// camera_controller.rs
+
+use crate::camera::{Camera, Projection, FOV_RANGE, ORTHO_HEIGHT_RANGE};
+use crate::input::{Action, ActionMap, GamepadAxis, InputState};
+use glam::{Quat, Vec3};
+use winit::event::MouseButton;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CameraMode {
+    Fps, // Walks: moving keeps to the ground plane whichever way the camera looks
+    Fly, // Moves along the view direction, so looking up and moving forward climbs
+    Orbit,
+    TwoD,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 4] = [CameraMode::Fps, CameraMode::Fly, CameraMode::Orbit, CameraMode::TwoD];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CameraMode::Fps => "FPS",
+            CameraMode::Fly => "Fly",
+            CameraMode::Orbit => "Orbit",
+            CameraMode::TwoD => "2D",
+        }
+    }
+}
+
+// Turns the frame's input into camera movement according to the active mode
+#[derive(Clone)]
+pub struct CameraController {
+    pub mode: CameraMode,
+    pub speed: f32,       // World units per second
+    pub sensitivity: f32, // Degrees per pixel of mouse movement
+    pub smooth_scroll: bool, // Spread wheel notches over a few frames instead of jumping
+    pub smooth_movement: bool, // Ease into and out of moving in the FPS and fly modes
+    zoom_velocity: f32,      // Lines per second still to be applied from smoothed scrolling
+    velocity: Vec3,          // World units per second the FPS and fly modes are moving at
+}
+
+// Degrees per second at full right-stick deflection or while an orbit key is held
+const STICK_LOOK_SPEED: f32 = 120.0;
+
+// How fast smoothed scrolling decays, per second; higher feels snappier
+const SCROLL_DAMPING: f32 = 12.0;
+
+// How fast smoothed movement catches up with the keys, per second
+const MOVE_DAMPING: f32 = 10.0;
+
+// Fraction of the distance each scroll line zooms, applied exponentially so many small steps
+// zoom exactly as far as one large one
+const ZOOM_PER_LINE: f32 = 0.1;
+
+// Fractions of the camera distance per second at full 6DOF push
+const PUCK_PAN_SPEED: f32 = 1.0;
+const PUCK_ZOOM_SPEED: f32 = 1.5;
+
+impl CameraController {
+    pub fn new(speed: f32, sensitivity: f32) -> Self {
+        Self {
+            mode: CameraMode::Orbit,
+            speed,
+            sensitivity,
+            smooth_scroll: true,
+            smooth_movement: true,
+            zoom_velocity: 0.0,
+            velocity: Vec3::ZERO,
+        }
+    }
+
+    /// Switches modes, snapping the camera to look down -Z when entering 2D.
+    pub fn set_mode(&mut self, mode: CameraMode, camera: &mut Camera) {
+        if mode == CameraMode::TwoD && self.mode != CameraMode::TwoD {
+            let distance = (camera.position - camera.target).length();
+            camera.target.z = 0.0;
+            camera.position = camera.target + Vec3::Z * distance;
+            camera.up = Vec3::Y;
+        }
+        self.mode = mode;
+        self.velocity = Vec3::ZERO;
+    }
+
+    // True while a mouse drag steers the camera
+    pub fn is_looking(&self, input: &InputState) -> bool {
+        input.dragging(MouseButton::Right) || input.dragging(MouseButton::Middle)
+    }
+
+    /// Mouse-look in the FPS and fly modes wants the cursor locked in place so it never hits the window edge.
+    pub fn wants_cursor_grab(&self, input: &InputState) -> bool {
+        matches!(self.mode, CameraMode::Fps | CameraMode::Fly) && self.is_looking(input)
+    }
+
+    // Distance to move this frame, given the distance the input asks for. Smoothed, the velocity eases towards
+    // the one asked for at the same rate whatever the frame rate
+    fn smoothed_move(&mut self, wanted: Vec3, dt: f32) -> Vec3 {
+        if !self.smooth_movement || dt <= 0.0 {
+            self.velocity = Vec3::ZERO;
+            return wanted;
+        }
+        let catch_up = 1.0 - (-MOVE_DAMPING * dt).exp();
+        self.velocity = self.velocity.lerp(wanted / dt, catch_up);
+        if self.velocity.length_squared() < 1e-6 {
+            self.velocity = Vec3::ZERO;
+        }
+        self.velocity * dt
+    }
+
+    // Lines to zoom by this frame. Wheel notches feed a decaying velocity whose total travel
+    // equals the notch; trackpads already have OS momentum and apply directly
+    fn smoothed_scroll(&mut self, input: &InputState, dt: f32) -> f32 {
+        let scroll = input.scroll_delta().y;
+        if !self.smooth_scroll || input.is_precise_scroll() {
+            self.zoom_velocity = 0.0;
+            return scroll;
+        }
+        // Scaled so the geometric series of steps sums to exactly `scroll` at a steady frame rate
+        let decay = (-SCROLL_DAMPING * dt).exp();
+        if dt > 0.0 {
+            self.zoom_velocity += scroll * (1.0 - decay) / dt;
+        }
+        let step = self.zoom_velocity * dt;
+        self.zoom_velocity *= decay;
+        if self.zoom_velocity.abs() < 1e-3 {
+            self.zoom_velocity = 0.0;
+        }
+        step
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera, input: &InputState, actions: &ActionMap, dt: f32) {
+        // Analog so a half-pushed stick moves at half speed
+        let axis = |positive: Action, negative: Action| {
+            input.action_value(actions, positive) - input.action_value(actions, negative)
+        };
+        let move_forward = axis(Action::MoveForward, Action::MoveBackward);
+        let move_right = axis(Action::MoveRight, Action::MoveLeft);
+        let move_up = axis(Action::MoveUp, Action::MoveDown);
+        let step = self.speed * dt;
+
+        // Right or middle drag looks around; the left button belongs to selection and the gizmo.
+        // Movement below the drag threshold is ignored so a plain click does not nudge the view
+        let dragging = self.is_looking(input);
+        // FPS and fly look read raw motion since the cursor is grabbed and stops moving
+        let drag_delta = match self.mode {
+            CameraMode::Fps | CameraMode::Fly => input.raw_mouse_delta(),
+            _ => input.cursor_delta(),
+        };
+        // One finger drags like the mouse; two fingers pan and pinch
+        let mouse_delta = if dragging { drag_delta } else { Default::default() } + input.touch_drag();
+        // Triggers zoom like the scroll wheel: orbit and 2D move the camera in, FPS and fly narrow the view
+        let scroll = self.smoothed_scroll(input, dt)
+            + (input.axis(GamepadAxis::RightTrigger) - input.axis(GamepadAxis::LeftTrigger)) * 10.0 * dt;
+        let zoom = (-scroll * ZOOM_PER_LINE).exp() / input.pinch_zoom();
+        // The right stick and the orbit keys turn at the same rate, so the view can be steered without a mouse
+        let stick = glam::Vec2::new(input.axis(GamepadAxis::RightStickX), -input.axis(GamepadAxis::RightStickY))
+            + glam::Vec2::new(axis(Action::OrbitRight, Action::OrbitLeft), axis(Action::OrbitDown, Action::OrbitUp));
+        // A 6DOF puck turns like the stick: twist to yaw, tilt to pitch
+        let puck = input.space_translation();
+        let puck_turn = glam::Vec2::new(-input.space_rotation().y, -input.space_rotation().x);
+        let stick = stick + puck_turn;
+        let rotation =
+            mouse_delta * self.sensitivity.to_radians() + stick * STICK_LOOK_SPEED.to_radians() * dt;
+
+        let offset = camera.position - camera.target;
+        let forward = (-offset).normalize_or_zero();
+        let right = forward.cross(camera.up).normalize_or_zero();
+        // Pan so the content under the fingers follows them, scaled by distance, or by the view's height when
+        // the distance doesn't change it
+        let pixels_to_world = match camera.projection {
+            Projection::Perspective => offset.length() * 0.002,
+            Projection::Orthographic => camera.ortho_height * 0.0024,
+            Projection::Pixels => 1.0,
+        };
+        let touch_pan = input.touch_pan() * pixels_to_world;
+        let screen_up = right.cross(forward);
+        let pan = -right * touch_pan.x + screen_up * touch_pan.y;
+        // Pushing the puck moves the view the same way, scaled by distance like touch panning
+        let puck_pan = (right * puck.x + screen_up * puck.y) * offset.length() * PUCK_PAN_SPEED * dt;
+        let puck_zoom = (-puck.z * PUCK_ZOOM_SPEED * dt).exp();
+
+        match self.mode {
+            CameraMode::Fps | CameraMode::Fly => {
+                // Walking keeps forward level; with the view straight up or down, it falls back to the view's up
+                let ahead = match self.mode {
+                    CameraMode::Fps => {
+                        let level = forward - camera.up * forward.dot(camera.up);
+                        level.try_normalize().unwrap_or(screen_up)
+                    }
+                    _ => forward,
+                };
+                let wanted = ahead * (move_forward + puck.z) * step
+                    + right * (move_right + puck.x) * step
+                    + camera.up * (move_up + puck.y) * step;
+                let translation = self.smoothed_move(wanted, dt) + pan;
+                let look = rotate_offset(forward * offset.length(), camera.up, -rotation.x, -rotation.y);
+                camera.position += translation;
+                camera.target = camera.position + look;
+                match camera.projection {
+                    Projection::Perspective => {
+                        camera.fovy = (camera.fovy * zoom).clamp(*FOV_RANGE.start(), *FOV_RANGE.end());
+                    }
+                    _ => zoom_ortho(camera, zoom),
+                }
+            }
+            CameraMode::Orbit => {
+                let offset = rotate_offset(offset, camera.up, -rotation.x, -rotation.y);
+                let distance = match camera.projection {
+                    Projection::Perspective => (offset.length() * zoom * puck_zoom).max(camera.znear * 2.0),
+                    _ => {
+                        zoom_ortho(camera, zoom * puck_zoom);
+                        offset.length()
+                    }
+                };
+                camera.target += pan + puck_pan;
+                camera.position = camera.target + offset.normalize_or_zero() * distance;
+            }
+            CameraMode::TwoD => {
+                let pan = pan
+                    + Vec3::new(
+                        -mouse_delta.x * pixels_to_world + move_right * step,
+                        mouse_delta.y * pixels_to_world + move_forward * step,
+                        0.0,
+                    );
+                let distance = match camera.projection {
+                    Projection::Perspective => (offset.length() * zoom * puck_zoom).max(camera.znear * 2.0),
+                    _ => {
+                        zoom_ortho(camera, zoom * puck_zoom);
+                        offset.length()
+                    }
+                };
+                camera.target += pan + puck_pan;
+                camera.position = camera.target + Vec3::Z * distance;
+            }
+        }
+    }
+}
+
+// Zooms an orthographic view by scaling how much it spans, since moving the camera wouldn't change it. A pixel
+// view stays at one unit per pixel
+fn zoom_ortho(camera: &mut Camera, zoom: f32) {
+    if camera.projection == Projection::Orthographic {
+        let height = camera.ortho_height * zoom;
+        camera.ortho_height = height.clamp(*ORTHO_HEIGHT_RANGE.start(), *ORTHO_HEIGHT_RANGE.end());
+    }
+}
+
+// Applies yaw around `up` and pitch around the offset's right axis, refusing to flip over the poles
+fn rotate_offset(offset: Vec3, up: Vec3, yaw: f32, pitch: f32) -> Vec3 {
+    let yawed = Quat::from_axis_angle(up, yaw) * offset;
+    let right = yawed.cross(up).normalize_or_zero();
+    if right == Vec3::ZERO {
+        return yawed;
+    }
+    let pitched = Quat::from_axis_angle(right, pitch) * yawed;
+    if pitched.normalize().dot(up).abs() > 0.99 {
+        yawed
+    } else {
+        pitched
+    }
+}
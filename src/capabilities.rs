@@ -0,0 +1,111 @@
+// capabilities.rs
+
+use egui_wgpu::wgpu;
+
+// Optional features requested when the adapter has them: what each one enables, and what is used without it
+const OPTIONAL_FEATURES: [(wgpu::Features, &str, &str); 8] = [
+    (wgpu::Features::TEXTURE_COMPRESSION_BC, "BCn compressed textures", "uncompressed textures"),
+    (wgpu::Features::POLYGON_MODE_LINE, "Wireframe rendering", "filled polygons only"),
+    (wgpu::Features::POLYGON_MODE_POINT, "Point rendering", "filled polygons or wireframe only"),
+    (wgpu::Features::TIMESTAMP_QUERY, "GPU pass timings", "CPU frame timings only"),
+    (wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS, "GPU encoder scope timings", "pass timings only"),
+    (wgpu::Features::PUSH_CONSTANTS, "Push constants", "per-object data in uniform buffers"),
+    (wgpu::Features::MULTI_DRAW_INDIRECT, "Multi-draw indirect", "one indirect draw per call"),
+    (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES, "2x and 8x MSAA", "1x and 4x MSAA only"),
+];
+// Plenty for a model matrix and a few parameters; every adapter exposing push constants allows this much
+const PUSH_CONSTANT_SIZE: u32 = 128;
+
+// What the device was created with, negotiated against the adapter so that missing features or a
+// downlevel GPU degrade a rendering path instead of failing device creation
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub features: wgpu::Features,
+    pub limits: wgpu::Limits,
+    pub vertex_storage: bool, // Storage buffers readable from vertex shaders, which GPU skinning needs
+    pub compute: bool,        // Compute shaders with two storage buffers, which GPU particles need
+    pub fallbacks: Vec<Fallback>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fallback {
+    pub missing: &'static str,
+    pub using: &'static str,
+}
+
+impl Capabilities {
+    pub fn negotiate(adapter: &wgpu::Adapter) -> Self {
+        let available = adapter.features();
+        let supported = adapter.limits();
+        let mut features = wgpu::Features::empty();
+        let mut fallbacks = Vec::new();
+        for (feature, missing, using) in OPTIONAL_FEATURES {
+            if available.contains(feature) {
+                features |= feature;
+            } else {
+                fallbacks.push(Fallback { missing, using });
+            }
+        }
+
+        // wgpu creates one queue per device, whatever the adapter offers
+        fallbacks.push(Fallback {
+            missing: "Async compute queue",
+            using: "compute submitted ahead of rendering on the one queue",
+        });
+
+        let mut limits = wgpu::Limits::default();
+        if !limits.check_limits(&supported) {
+            fallbacks.push(Fallback {
+                missing: "Default limits",
+                using: "downlevel limits",
+            });
+            limits = wgpu::Limits::downlevel_defaults();
+        }
+        // Whatever the tier, the surface can be as large as the adapter allows
+        limits = limits.using_resolution(supported.clone());
+        if features.contains(wgpu::Features::PUSH_CONSTANTS) {
+            limits.max_push_constant_size = PUSH_CONSTANT_SIZE.min(supported.max_push_constant_size);
+        }
+        let vertex_storage = adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::VERTEX_STORAGE)
+            && limits.max_storage_buffers_per_shader_stage > 0;
+        if !vertex_storage {
+            fallbacks.push(Fallback {
+                missing: "GPU skinning",
+                using: "no rigged model previews",
+            });
+        }
+        let compute = adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            && limits.max_storage_buffers_per_shader_stage >= 2;
+        if !compute {
+            fallbacks.push(Fallback {
+                missing: "GPU particles",
+                using: "no particles",
+            });
+        }
+
+        for fallback in &fallbacks {
+            log::info!("{} unavailable; using {}", fallback.missing, fallback.using);
+        }
+        Self {
+            features,
+            limits,
+            vertex_storage,
+            compute,
+            fallbacks,
+        }
+    }
+
+    pub fn device_descriptor(&self) -> wgpu::DeviceDescriptor<'static> {
+        wgpu::DeviceDescriptor {
+            label: None,
+            required_features: self.features,
+            required_limits: self.limits.clone(),
+        }
+    }
+}
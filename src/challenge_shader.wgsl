@@ -1,5 +1,24 @@
 // Vertex shader
 
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct ModelUniform {
+    model: mat4x4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> model_uniform: ModelUniform;
+
+// Editable per material from the Materials panel
+struct Params {
+    inversion: f32,
+    color_shift: vec3<f32>,
+};
+@group(2) @binding(0)
+var<uniform> params: Params;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -17,7 +36,7 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
     out.color = model.color;
-    out.clip_position = vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.view_proj * model_uniform.model * vec4<f32>(model.position, 1.0);
     return out;
 }
 
@@ -26,5 +45,6 @@ fn vs_main(
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let inverted_color = vec3<f32>(1.0) - in.color;
-    return vec4<f32>(inverted_color, 1.0);
+    let color = mix(in.color, inverted_color, params.inversion) + params.color_shift;
+    return vec4<f32>(color, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,86 @@
+// clipboard.rs
+
+use crate::transform::Transform;
+use serde::{Deserialize, Serialize};
+
+// Scene data exchanged through the OS clipboard as JSON, tagged so foreign text is rejected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum ClipboardItem {
+    Transform(Transform),
+}
+
+impl ClipboardItem {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+}
+
+// OS clipboard for text and images; egui's own clipboard only handles text fields
+pub struct Clipboard {
+    #[cfg(not(any(target_os = "android", target_os = "ios", target_arch = "wasm32")))]
+    inner: Option<arboard::Clipboard>, // None when no clipboard is available, e.g. headless
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios", target_arch = "wasm32")))]
+impl Clipboard {
+    pub fn new() -> Self {
+        Self {
+            inner: arboard::Clipboard::new()
+                .map_err(|err| log::warn!("Clipboard unavailable: {err}"))
+                .ok(),
+        }
+    }
+
+    pub fn copy_text(&mut self, text: String) -> Result<(), String> {
+        self.inner()?.set_text(text).map_err(|e| e.to_string())
+    }
+
+    pub fn paste_text(&mut self) -> Result<String, String> {
+        self.inner()?.get_text().map_err(|e| e.to_string())
+    }
+
+    /// Copies tightly packed RGBA8 pixels as an image.
+    pub fn copy_image(&mut self, width: u32, height: u32, rgba: Vec<u8>) -> Result<(), String> {
+        let image = arboard::ImageData {
+            width: width as usize,
+            height: height as usize,
+            bytes: rgba.into(),
+        };
+        self.inner()?.set_image(image).map_err(|e| e.to_string())
+    }
+
+    fn inner(&mut self) -> Result<&mut arboard::Clipboard, String> {
+        self.inner.as_mut().ok_or_else(|| "no clipboard available".to_string())
+    }
+}
+
+// arboard has no phone or browser backends, so copying and pasting there stays within egui's own text fields
+#[cfg(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))]
+impl Clipboard {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn copy_text(&mut self, _: String) -> Result<(), String> {
+        Err("no clipboard on this platform".to_string())
+    }
+
+    pub fn paste_text(&mut self) -> Result<String, String> {
+        Err("no clipboard on this platform".to_string())
+    }
+
+    pub fn copy_image(&mut self, _: u32, _: u32, _: Vec<u8>) -> Result<(), String> {
+        Err("no clipboard on this platform".to_string())
+    }
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
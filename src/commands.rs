@@ -0,0 +1,489 @@
+// commands.rs
+
+use egui::{Align2, Context, Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Every user-triggerable operation, executed centrally by the render loop
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Command {
+    SwitchShader,
+    ToggleRenderingStyle,
+    ResetCamera,
+    FrameSelection,
+    ResetTransform,
+    ToggleGizmo,
+    GizmoTranslate,
+    GizmoRotate,
+    GizmoScale,
+    ToggleObjectDrag,
+    ToggleGrid,
+    OpenModel,
+    OpenTexture,
+    OpenWorld,
+    SaveWorld,
+    ExportGltf,
+    OpenScene,
+    SaveScene,
+    SaveSceneAs,
+    PlacePrefab,
+    SavePrefab,
+    ImportPreset,
+    ExportPreset,
+    ToggleProfiler,
+    ExportGpuTrace,
+    CaptureFrame,
+    ShowDiagnostics,
+    Undo,
+    Redo,
+    CopyTransform,
+    PasteTransform,
+    CopyScreenshot,
+    SaveScreenshot,
+    ToggleVideoCapture,
+    ToggleInputRecording,
+    ReplayInput,
+    ToggleSessionRecording,
+    FocusUi,
+    OpenSceneWindow,
+    Quit,
+}
+
+impl Command {
+    pub const ALL: [Command; 40] = [
+        Command::SwitchShader,
+        Command::ToggleRenderingStyle,
+        Command::ResetCamera,
+        Command::FrameSelection,
+        Command::ResetTransform,
+        Command::ToggleGizmo,
+        Command::GizmoTranslate,
+        Command::GizmoRotate,
+        Command::GizmoScale,
+        Command::ToggleObjectDrag,
+        Command::ToggleGrid,
+        Command::OpenModel,
+        Command::OpenTexture,
+        Command::OpenWorld,
+        Command::SaveWorld,
+        Command::ExportGltf,
+        Command::OpenScene,
+        Command::SaveScene,
+        Command::SaveSceneAs,
+        Command::PlacePrefab,
+        Command::SavePrefab,
+        Command::ImportPreset,
+        Command::ExportPreset,
+        Command::ToggleProfiler,
+        Command::ExportGpuTrace,
+        Command::CaptureFrame,
+        Command::ShowDiagnostics,
+        Command::Undo,
+        Command::Redo,
+        Command::CopyTransform,
+        Command::PasteTransform,
+        Command::CopyScreenshot,
+        Command::SaveScreenshot,
+        Command::ToggleVideoCapture,
+        Command::ToggleInputRecording,
+        Command::ReplayInput,
+        Command::ToggleSessionRecording,
+        Command::FocusUi,
+        Command::OpenSceneWindow,
+        Command::Quit,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Command::SwitchShader => "Switch shader",
+            Command::ToggleRenderingStyle => "Next rendering style",
+            Command::ResetCamera => "Reset camera",
+            Command::FrameSelection => "Frame selection",
+            Command::ResetTransform => "Reset object transform",
+            Command::ToggleGizmo => "Toggle gizmo",
+            Command::GizmoTranslate => "Gizmo: translate",
+            Command::GizmoRotate => "Gizmo: rotate",
+            Command::GizmoScale => "Gizmo: scale",
+            Command::ToggleObjectDrag => "Toggle object dragging",
+            Command::ToggleGrid => "Toggle ground grid",
+            Command::OpenModel => "File: open model",
+            Command::OpenTexture => "File: open texture",
+            Command::OpenWorld => "File: open world",
+            Command::SaveWorld => "File: save world",
+            Command::ExportGltf => "File: export glTF",
+            Command::OpenScene => "File: open scene",
+            Command::SaveScene => "File: save scene",
+            Command::SaveSceneAs => "File: save scene as",
+            Command::PlacePrefab => "File: place prefab",
+            Command::SavePrefab => "File: save object as prefab",
+            Command::ImportPreset => "Import layout preset",
+            Command::ExportPreset => "Export layout preset",
+            Command::ToggleProfiler => "Toggle profiler",
+            Command::ExportGpuTrace => "Export GPU trace",
+            Command::CaptureFrame => "Capture frame in RenderDoc",
+            Command::ShowDiagnostics => "About / Diagnostics",
+            Command::Undo => "Undo",
+            Command::Redo => "Redo",
+            Command::CopyTransform => "Copy object transform",
+            Command::PasteTransform => "Paste object transform",
+            Command::CopyScreenshot => "Copy screenshot",
+            Command::SaveScreenshot => "Save screenshot",
+            Command::ToggleVideoCapture => "Start/stop video capture",
+            Command::ToggleInputRecording => "Start/stop input recording",
+            Command::ReplayInput => "Replay input recording",
+            Command::ToggleSessionRecording => "Start/stop session recording",
+            Command::FocusUi => "Focus the UI for keyboard navigation",
+            Command::OpenSceneWindow => "Open a scene window",
+            Command::Quit => "Quit",
+        }
+    }
+}
+
+pub const PALETTE_SHORTCUT: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::P);
+
+// Always active next to the rebindable redo shortcut
+pub const REDO_SHORTCUT_SECONDARY: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z);
+
+// How long the second half of a chord may take after the first
+const CHORD_TIMEOUT: f64 = 1.5; // Seconds
+
+// A key combination, optionally followed by a second one to form a chord like Ctrl+K Ctrl+S
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Shortcut {
+    pub first: KeyboardShortcut,
+    pub second: Option<KeyboardShortcut>,
+}
+
+impl Shortcut {
+    pub const fn single(first: KeyboardShortcut) -> Self {
+        Self { first, second: None }
+    }
+
+    pub const fn chord(first: KeyboardShortcut, second: KeyboardShortcut) -> Self {
+        Self {
+            first,
+            second: Some(second),
+        }
+    }
+
+    pub fn format(&self, ctx: &Context) -> String {
+        match &self.second {
+            Some(second) => format!("{} {}", ctx.format_shortcut(&self.first), ctx.format_shortcut(second)),
+            None => ctx.format_shortcut(&self.first),
+        }
+    }
+}
+
+impl Command {
+    pub fn default_shortcut(&self) -> Option<Shortcut> {
+        let key = |modifiers, key| KeyboardShortcut::new(modifiers, key);
+        let single = |modifiers, k| Some(Shortcut::single(key(modifiers, k)));
+        // Less common commands live behind Ctrl+K so single keys stay free
+        let chord = |k| Some(Shortcut::chord(key(Modifiers::COMMAND, Key::K), key(Modifiers::COMMAND, k)));
+        match self {
+            Command::SaveScene => single(Modifiers::COMMAND, Key::S),
+            Command::OpenScene => single(Modifiers::COMMAND, Key::O),
+            Command::Undo => single(Modifiers::COMMAND, Key::Z),
+            Command::Redo => single(Modifiers::COMMAND, Key::Y),
+            Command::ShowDiagnostics => single(Modifiers::NONE, Key::F1),
+            Command::ToggleGizmo => single(Modifiers::NONE, Key::F2),
+            Command::ToggleProfiler => single(Modifiers::NONE, Key::F3),
+            Command::ToggleGrid => single(Modifiers::NONE, Key::F4),
+            Command::SwitchShader => single(Modifiers::NONE, Key::F5),
+            Command::CaptureFrame => single(Modifiers::NONE, Key::F9),
+            Command::ToggleRenderingStyle => single(Modifiers::NONE, Key::F6),
+            Command::FocusUi => single(Modifiers::NONE, Key::F10),
+            Command::CopyScreenshot => single(Modifiers::NONE, Key::F12),
+            Command::SaveScreenshot => single(Modifiers::SHIFT, Key::F12),
+            Command::FrameSelection => single(Modifiers::NONE, Key::F),
+            Command::ToggleObjectDrag => single(Modifiers::NONE, Key::G),
+            Command::ResetCamera => single(Modifiers::NONE, Key::Home),
+            Command::ImportPreset => chord(Key::I),
+            Command::ExportPreset => chord(Key::E),
+            Command::ToggleVideoCapture => chord(Key::V),
+            Command::ToggleInputRecording => chord(Key::R),
+            Command::ReplayInput => chord(Key::P),
+            _ => None,
+        }
+    }
+}
+
+// Keyboard shortcuts for commands, dispatched centrally once per frame
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShortcutMap {
+    #[serde(default)]
+    bindings: BTreeMap<Command, Option<Shortcut>>,
+    #[serde(skip)]
+    pending: Option<(KeyboardShortcut, f64)>, // First half of a chord and when it was pressed
+}
+
+impl ShortcutMap {
+    pub fn new() -> Self {
+        Self {
+            bindings: Command::ALL.iter().map(|c| (*c, c.default_shortcut())).collect(),
+            pending: None,
+        }
+    }
+
+    // Commands missing from a loaded file keep their default shortcut
+    pub fn get(&self, command: Command) -> Option<Shortcut> {
+        self.bindings.get(&command).copied().unwrap_or(command.default_shortcut())
+    }
+
+    pub fn bind(&mut self, command: Command, shortcut: Option<Shortcut>) {
+        self.bindings.insert(command, shortcut);
+    }
+
+    pub fn reset(&mut self, command: Command) {
+        self.bind(command, command.default_shortcut());
+    }
+
+    pub fn reset_all(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Unbinds loaded shortcuts that the palette or the secondary redo shortcut would consume first,
+    /// since those commands could never fire.
+    pub fn drop_reserved(&mut self) {
+        let reserved = [PALETTE_SHORTCUT, REDO_SHORTCUT_SECONDARY];
+        for shortcut in self.bindings.values_mut() {
+            if shortcut.is_some_and(|s| s.second.is_none() && reserved.contains(&s.first)) {
+                *shortcut = None;
+            }
+        }
+    }
+
+    /// Other commands sharing `command`'s shortcut. A single shortcut that equals the first
+    /// half of a chord conflicts with it too, since the chord would swallow it.
+    pub fn conflicts(&self, command: Command) -> Vec<Command> {
+        let Some(shortcut) = self.get(command) else {
+            return Vec::new();
+        };
+        Command::ALL
+            .into_iter()
+            .filter(|c| *c != command)
+            .filter(|c| {
+                self.get(*c).is_some_and(|other| {
+                    other == shortcut
+                        || (other.second.is_none() != shortcut.second.is_none() && other.first == shortcut.first)
+                })
+            })
+            .collect()
+    }
+
+    // Menu hint text, empty when unbound
+    pub fn label(&self, ctx: &Context, command: Command) -> String {
+        self.get(command).map(|s| s.format(ctx)).unwrap_or_default()
+    }
+
+    /// Consumes the shortcuts pressed this frame and returns their commands.
+    pub fn dispatch(&mut self, ctx: &Context) -> Vec<Command> {
+        let now = ctx.input(|i| i.time);
+        if self.pending.is_some_and(|(_, started)| now - started > CHORD_TIMEOUT) {
+            self.pending = None;
+        }
+
+        // egui matches shortcuts ignoring extra Shift, so Ctrl+Shift+Z must be tried before Ctrl+Z
+        let mut bound: Vec<(Command, Shortcut)> =
+            Command::ALL.into_iter().filter_map(|c| Some((c, self.get(c)?))).collect();
+        bound.sort_by_key(|(_, s)| std::cmp::Reverse(modifier_count(s.first.modifiers)));
+
+        ctx.input_mut(|i| {
+            // Waiting for the second half: it either completes a chord or the key press is swallowed
+            if let Some((first, _)) = self.pending {
+                let completed = bound
+                    .iter()
+                    .filter(|(_, s)| s.first == first)
+                    .find_map(|(c, s)| s.second.filter(|second| i.consume_shortcut(second)).map(|_| *c));
+                // egui does not report bare modifier keys, so any key event ends the chord
+                let any_key = i.events.iter().any(|e| matches!(e, egui::Event::Key { pressed: true, .. }));
+                if completed.is_some() || any_key {
+                    self.pending = None;
+                    i.events.retain(|e| !matches!(e, egui::Event::Key { pressed: true, .. }));
+                }
+                return completed.into_iter().collect();
+            }
+
+            if let Some((_, chord)) = bound
+                .iter()
+                .find(|(_, s)| s.second.is_some() && i.consume_shortcut(&s.first))
+            {
+                self.pending = Some((chord.first, now));
+                return Vec::new();
+            }
+
+            let mut commands = Vec::new();
+            if i.consume_shortcut(&REDO_SHORTCUT_SECONDARY) {
+                commands.push(Command::Redo);
+            }
+            commands.extend(
+                bound
+                    .iter()
+                    .filter(|(_, s)| s.second.is_none() && i.consume_shortcut(&s.first))
+                    .map(|(c, _)| *c),
+            );
+            commands
+        })
+    }
+
+    /// Shows which chords can complete while the first half is pending.
+    pub fn show_pending(&self, ctx: &Context) {
+        let Some((first, _)) = self.pending else {
+            return;
+        };
+        egui::Area::new(egui::Id::new("pending_chord"))
+            .anchor(Align2::CENTER_BOTTOM, [0.0, -24.0])
+            .interactable(false)
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.strong(format!("{} was pressed. Waiting for second key...", ctx.format_shortcut(&first)));
+                    for command in Command::ALL {
+                        if let Some(second) = self.get(command).filter(|s| s.first == first).and_then(|s| s.second) {
+                            ui.horizontal(|ui| {
+                                ui.monospace(ctx.format_shortcut(&second));
+                                ui.label(command.label());
+                            });
+                        }
+                    }
+                });
+            });
+    }
+}
+
+impl Default for ShortcutMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn modifier_count(modifiers: Modifiers) -> u32 {
+    [modifiers.alt, modifiers.ctrl || modifiers.command, modifiers.shift, modifiers.mac_cmd]
+        .into_iter()
+        .filter(|m| *m)
+        .count() as u32
+}
+
+// Fuzzy-searchable list of all commands, toggled with Ctrl+Shift+P
+#[derive(Debug, Default)]
+pub struct CommandPalette {
+    open: bool,
+    query: String,
+    selected: usize,
+}
+
+impl CommandPalette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    /// Draws the palette if open and returns the command the user picked this frame.
+    pub fn show(&mut self, ctx: &Context, shortcuts: &ShortcutMap) -> Option<Command> {
+        if ctx.input_mut(|i| i.consume_shortcut(&PALETTE_SHORTCUT)) {
+            self.toggle();
+        }
+        if !self.open {
+            return None;
+        }
+
+        let mut matches: Vec<(i32, Command)> = Command::ALL
+            .iter()
+            .filter_map(|c| fuzzy_score(&self.query, c.label()).map(|score| (score, *c)))
+            .collect();
+        matches.sort_by_key(|(score, _)| -score);
+
+        let (up, down, enter, escape) = ctx.input_mut(|i| {
+            (
+                i.consume_key(Modifiers::NONE, Key::ArrowUp),
+                i.consume_key(Modifiers::NONE, Key::ArrowDown),
+                i.consume_key(Modifiers::NONE, Key::Enter),
+                i.consume_key(Modifiers::NONE, Key::Escape),
+            )
+        });
+        if down {
+            self.selected += 1;
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        self.selected = self.selected.min(matches.len().saturating_sub(1));
+
+        let mut picked = None;
+        egui::Window::new("Command Palette")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_TOP, [0.0, 40.0])
+            .fixed_size([360.0, 0.0])
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("Type a command")
+                        .desired_width(f32::INFINITY),
+                );
+                response.request_focus();
+                if response.changed() {
+                    self.selected = 0;
+                }
+                ui.separator();
+                for (i, (_, command)) in matches.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.selectable_label(i == self.selected, command.label()).clicked() {
+                            picked = Some(*command);
+                        }
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            ui.weak(shortcuts.label(ctx, *command));
+                        });
+                    });
+                }
+                if matches.is_empty() {
+                    ui.weak("No matching commands");
+                }
+            });
+
+        if enter {
+            picked = matches.get(self.selected).map(|(_, c)| *c);
+        }
+        if picked.is_some() || escape {
+            self.toggle();
+        }
+        picked
+    }
+}
+
+// Subsequence match; consecutive characters and word starts score higher, None if no match
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let offset = candidate[position..].iter().position(|c| *c == q)?;
+        let index = position + offset;
+        score += 1;
+        if previous_match.is_some_and(|p| p + 1 == index) {
+            score += 3;
+        }
+        if index == 0 || !candidate[index - 1].is_alphanumeric() {
+            score += 2;
+        }
+        previous_match = Some(index);
+        position = index + 1;
+    }
+
+    // Shorter candidates win ties
+    Some(score * 100 - candidate.len() as i32)
+}
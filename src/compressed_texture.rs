@@ -0,0 +1,869 @@
+// compressed_texture.rs
+
+use egui_wgpu::wgpu;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// The first bytes of every KTX2 file
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n'];
+
+// DDS header flags: a FourCC pixel format, and cube map or volume surfaces
+const DDPF_FOURCC: u32 = 0x4;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+// DX10 header: a 2D resource, and the cube map flag
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+const D3D10_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+/// The blocks of a BC1 to BC7 image read from a DDS or KTX2 file, with every mip level the file has. They
+/// upload as they are where the adapter has `TEXTURE_COMPRESSION_BC`, and `decoded_levels` unpacks them on the
+/// CPU where it doesn't.
+pub struct CompressedImage {
+    pub format: wgpu::TextureFormat, // One of the BC formats
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>, // Largest first, each its 4x4 blocks row by row
+    pub opaque: bool,         // BC1 without alpha, whose transparent black texels are plain black
+}
+
+impl CompressedImage {
+    /// Whether `path` is read by `load`, by its extension.
+    pub fn reads(path: &Path) -> bool {
+        let extension = path.extension().unwrap_or_default();
+        extension.eq_ignore_ascii_case("dds") || extension.eq_ignore_ascii_case("ktx2")
+    }
+
+    /// Reads a `.dds` or `.ktx2` file. Only 2D textures in BC formats load; KTX2 supercompression isn't read.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("ktx2")) {
+            parse_ktx2(&bytes)
+        } else {
+            parse_dds(&bytes)
+        }
+    }
+
+    /// Whether sampling the blocks as they are gives the texels the file means. Not so for opaque BC1 that
+    /// uses transparent black, since wgpu has no opaque BC1 format to upload it as.
+    pub fn samples_as_stored(&self) -> bool {
+        use wgpu::TextureFormat as F;
+        let bc1 = matches!(self.format, F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb);
+        let mut blocks = self.levels.iter().flat_map(|level| level.chunks_exact(8));
+        !(self.opaque && bc1 && blocks.any(bc1_punches_through))
+    }
+
+    /// The uncompressed format `decoded_levels` are in, keeping the blocks' color space and range: RGBA8 for
+    /// BC1, 2, 3 and 7, sRGB where they are; RGBA8 unorm or snorm for BC4 and 5; and RGBA16 float for BC6H.
+    pub fn decoded_format(&self) -> wgpu::TextureFormat {
+        use wgpu::TextureFormat as F;
+        match self.format {
+            F::Bc1RgbaUnormSrgb | F::Bc2RgbaUnormSrgb | F::Bc3RgbaUnormSrgb | F::Bc7RgbaUnormSrgb => {
+                F::Rgba8UnormSrgb
+            }
+            F::Bc4RSnorm | F::Bc5RgSnorm => F::Rgba8Snorm,
+            F::Bc6hRgbUfloat | F::Bc6hRgbFloat => F::Rgba16Float,
+            _ => F::Rgba8Unorm,
+        }
+    }
+
+    /// Every level decoded into tightly packed `decoded_format` texels, for adapters that can't sample BC
+    /// formats. BC4 and BC5 fill the channels they lack as the GPU would: green and blue 0, alpha 1.
+    pub fn decoded_levels(&self) -> Vec<Vec<u8>> {
+        let texel = self.decoded_format().block_copy_size(None).unwrap_or(4) as usize;
+        (0..self.levels.len()).map(|level| self.decode_level(level, texel)).collect()
+    }
+
+    fn decode_level(&self, level: usize, texel: usize) -> Vec<u8> {
+        let (width, height) = level_size(self.width, self.height, level);
+        let (width, height) = (width as usize, height as usize);
+        let block_size = self.format.block_copy_size(None).unwrap_or(16) as usize;
+        let blocks_wide = width.div_ceil(4);
+        let mut pixels = vec![0; width * height * texel];
+        for (index, block) in self.levels[level].chunks_exact(block_size).enumerate() {
+            let (left, top) = (index % blocks_wide * 4, index / blocks_wide * 4);
+            for (i, value) in decode_block(self.format, self.opaque, block).iter().enumerate() {
+                let (x, y) = (left + i % 4, top + i / 4);
+                if x < width && y < height {
+                    let start = (y * width + x) * texel;
+                    pixels[start..start + texel].copy_from_slice(&value[..texel]);
+                }
+            }
+        }
+        pixels
+    }
+}
+
+/// Reads the contents of a DDS file, with or without a DX10 header.
+pub fn parse_dds(bytes: &[u8]) -> io::Result<CompressedImage> {
+    if bytes.len() < 128 || &bytes[..4] != b"DDS " || u32_at(bytes, 4) != 124 {
+        return Err(invalid("not a DDS file"));
+    }
+    let (height, width, level_count) = (u32_at(bytes, 12), u32_at(bytes, 16), u32_at(bytes, 28).max(1));
+    if u32_at(bytes, 112) & (DDSCAPS2_CUBEMAP | DDSCAPS2_VOLUME) != 0 {
+        return Err(invalid("only 2D DDS textures load, not cube maps or volumes"));
+    }
+    if u32_at(bytes, 80) & DDPF_FOURCC == 0 {
+        return Err(invalid("uncompressed DDS textures aren't read, only BC1 to BC7"));
+    }
+    check_size(width, height, level_count)?;
+    use wgpu::TextureFormat as F;
+    // Files without a DX10 header don't say what color space they're in; like other 8-bit images, color
+    // formats are taken to be sRGB
+    let (format, data_offset) = match &bytes[84..88] {
+        b"DXT1" => (F::Bc1RgbaUnormSrgb, 128),
+        b"DXT2" | b"DXT3" => (F::Bc2RgbaUnormSrgb, 128),
+        b"DXT4" | b"DXT5" => (F::Bc3RgbaUnormSrgb, 128),
+        b"ATI1" | b"BC4U" => (F::Bc4RUnorm, 128),
+        b"BC4S" => (F::Bc4RSnorm, 128),
+        b"ATI2" | b"BC5U" => (F::Bc5RgUnorm, 128),
+        b"BC5S" => (F::Bc5RgSnorm, 128),
+        b"DX10" => {
+            if bytes.len() < 148 {
+                return Err(invalid("DDS file ends inside its DX10 header"));
+            }
+            let (dimension, misc, array_size) = (u32_at(bytes, 132), u32_at(bytes, 136), u32_at(bytes, 140));
+            if dimension != D3D10_RESOURCE_DIMENSION_TEXTURE2D
+                || misc & D3D10_RESOURCE_MISC_TEXTURECUBE != 0
+                || array_size > 1
+            {
+                return Err(invalid("only 2D DDS textures load, not arrays, cube maps or volumes"));
+            }
+            (dxgi_format(u32_at(bytes, 128))?, 148)
+        }
+        four_cc => {
+            let name = String::from_utf8_lossy(four_cc);
+            return Err(invalid(format!("DDS format {name} isn't supported, only BC1 to BC7")));
+        }
+    };
+    // Levels follow each other, largest first
+    let mut offset: usize = data_offset;
+    let levels = (0..level_count as usize)
+        .map(|level| {
+            let end = level_bytes(format, width, height, level).and_then(|length| offset.checked_add(length));
+            let data = end.and_then(|end| bytes.get(offset..end));
+            let data = data.ok_or_else(|| invalid("DDS file ends inside its data"));
+            offset = end.unwrap_or(usize::MAX);
+            data.map(<[u8]>::to_vec)
+        })
+        .collect::<io::Result<_>>()?;
+    Ok(CompressedImage { format, width, height, levels, opaque: false })
+}
+
+/// Reads the contents of a KTX2 file.
+pub fn parse_ktx2(bytes: &[u8]) -> io::Result<CompressedImage> {
+    if bytes.len() < 80 || bytes[..12] != KTX2_IDENTIFIER {
+        return Err(invalid("not a KTX2 file"));
+    }
+    let (width, height, depth) = (u32_at(bytes, 20), u32_at(bytes, 24), u32_at(bytes, 28));
+    let (layers, faces, level_count) = (u32_at(bytes, 32), u32_at(bytes, 36), u32_at(bytes, 40).max(1));
+    if u32_at(bytes, 44) != 0 {
+        return Err(invalid("supercompressed KTX2 files (Basis Universal, Zstandard) aren't read"));
+    }
+    if depth > 0 || layers > 1 || faces != 1 {
+        return Err(invalid("only 2D KTX2 textures load, not arrays, cube maps or volumes"));
+    }
+    check_size(width, height, level_count)?;
+    let code = u32_at(bytes, 12);
+    let format = vk_format(code)?;
+    // The level index follows the header, largest level first, each an offset and length into the file
+    let levels = (0..level_count as usize)
+        .map(|level| {
+            let entry = 80 + level * 24;
+            let (offset, length) = match (u64_at(bytes, entry), u64_at(bytes, entry + 8)) {
+                (Some(offset), Some(length)) => (offset as usize, length as usize),
+                _ => return Err(invalid("KTX2 file ends inside its level index")),
+            };
+            if level_bytes(format, width, height, level).is_none_or(|expected| length < expected) {
+                return Err(invalid(format!("KTX2 level {level} is too short for its size")));
+            }
+            let data = offset.checked_add(length).and_then(|end| bytes.get(offset..end));
+            data.map(<[u8]>::to_vec).ok_or_else(|| invalid("KTX2 file ends inside its data"))
+        })
+        .collect::<io::Result<_>>()?;
+    // VK_FORMAT_BC1_RGB_UNORM_BLOCK and its sRGB twin
+    let opaque = code == 131 || code == 132;
+    Ok(CompressedImage { format, width, height, levels, opaque })
+}
+
+// Checks the size and level count a header gives before any level is read
+fn check_size(width: u32, height: u32, level_count: u32) -> io::Result<()> {
+    if width == 0 || height == 0 {
+        return Err(invalid("texture is empty"));
+    }
+    if level_count > u32::BITS - width.max(height).leading_zeros() {
+        return Err(invalid(format!("{level_count} mip levels are more than a {width}x{height} texture has")));
+    }
+    Ok(())
+}
+
+// The BC formats a DX10 header names by DXGI_FORMAT; typeless ones are read as unorm
+fn dxgi_format(code: u32) -> io::Result<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+    Ok(match code {
+        70 | 71 => F::Bc1RgbaUnorm,
+        72 => F::Bc1RgbaUnormSrgb,
+        73 | 74 => F::Bc2RgbaUnorm,
+        75 => F::Bc2RgbaUnormSrgb,
+        76 | 77 => F::Bc3RgbaUnorm,
+        78 => F::Bc3RgbaUnormSrgb,
+        79 | 80 => F::Bc4RUnorm,
+        81 => F::Bc4RSnorm,
+        82 | 83 => F::Bc5RgUnorm,
+        84 => F::Bc5RgSnorm,
+        94 | 95 => F::Bc6hRgbUfloat,
+        96 => F::Bc6hRgbFloat,
+        97 | 98 => F::Bc7RgbaUnorm,
+        99 => F::Bc7RgbaUnormSrgb,
+        _ => return Err(invalid(format!("DXGI format {code} isn't supported, only BC1 to BC7"))),
+    })
+}
+
+// The BC formats a KTX2 file names by VkFormat
+fn vk_format(code: u32) -> io::Result<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+    Ok(match code {
+        131 | 133 => F::Bc1RgbaUnorm,
+        132 | 134 => F::Bc1RgbaUnormSrgb,
+        135 => F::Bc2RgbaUnorm,
+        136 => F::Bc2RgbaUnormSrgb,
+        137 => F::Bc3RgbaUnorm,
+        138 => F::Bc3RgbaUnormSrgb,
+        139 => F::Bc4RUnorm,
+        140 => F::Bc4RSnorm,
+        141 => F::Bc5RgUnorm,
+        142 => F::Bc5RgSnorm,
+        143 => F::Bc6hRgbUfloat,
+        144 => F::Bc6hRgbFloat,
+        145 => F::Bc7RgbaUnorm,
+        146 => F::Bc7RgbaUnormSrgb,
+        _ => return Err(invalid(format!("Vulkan format {code} isn't supported, only BC1 to BC7"))),
+    })
+}
+
+fn level_size(width: u32, height: u32, level: usize) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+// Bytes in mip level `level`, whole 4x4 blocks even where the level is smaller, or None past what fits in memory
+fn level_bytes(format: wgpu::TextureFormat, width: u32, height: u32, level: usize) -> Option<usize> {
+    let (width, height) = level_size(width, height, level);
+    let block_size = format.block_copy_size(None).unwrap_or(16) as usize;
+    (width.div_ceil(4) as usize).checked_mul(height.div_ceil(4) as usize)?.checked_mul(block_size)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().expect("four bytes"))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// The 16 texels of one block, row by row, in the decoded format's bytes: RGBA8, or RGBA16 float for BC6H.
+// `opaque` BC1 has no alpha
+fn decode_block(format: wgpu::TextureFormat, opaque: bool, block: &[u8]) -> [[u8; 8]; 16] {
+    use wgpu::TextureFormat as F;
+    let rgba: [[u8; 4]; 16] = match format {
+        F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb if opaque => decode_bc1(block, true).map(|[r, g, b, _]| [r, g, b, 255]),
+        F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb => decode_bc1(block, true),
+        F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb => {
+            let alpha = u64::from_le_bytes(block[..8].try_into().expect("eight bytes"));
+            let mut texels = decode_bc1(&block[8..], false);
+            for (i, texel) in texels.iter_mut().enumerate() {
+                texel[3] = ((alpha >> (i * 4)) & 0xF) as u8 * 17;
+            }
+            texels
+        }
+        F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb => {
+            let alpha = decode_bc4_channel(&block[..8], false);
+            let mut texels = decode_bc1(&block[8..], false);
+            for (texel, alpha) in texels.iter_mut().zip(alpha) {
+                texel[3] = alpha;
+            }
+            texels
+        }
+        F::Bc4RUnorm | F::Bc4RSnorm | F::Bc5RgUnorm | F::Bc5RgSnorm => {
+            let signed = matches!(format, F::Bc4RSnorm | F::Bc5RgSnorm);
+            let red = decode_bc4_channel(&block[..8], signed);
+            let green = match format {
+                F::Bc5RgUnorm | F::Bc5RgSnorm => decode_bc4_channel(&block[8..16], signed),
+                _ => [0; 16],
+            };
+            let one = if signed { 127 } else { 255 };
+            std::array::from_fn(|i| [red[i], green[i], 0, one])
+        }
+        F::Bc6hRgbUfloat | F::Bc6hRgbFloat => {
+            let texels = decode_bc6h(block, format == F::Bc6hRgbFloat);
+            return texels.map(|texel| {
+                let mut bytes = [0; 8];
+                for (channel, value) in texel.into_iter().enumerate() {
+                    bytes[channel * 2..channel * 2 + 2].copy_from_slice(&value.to_le_bytes());
+                }
+                bytes
+            });
+        }
+        _ => decode_bc7(block),
+    };
+    rgba.map(|[r, g, b, a]| [r, g, b, a, 0, 0, 0, 0])
+}
+
+// A BC1 color block. Only BC1 itself has the three-color mode with transparent black; BC2 and BC3 always
+// interpolate four colors
+fn decode_bc1(block: &[u8], punch_through: bool) -> [[u8; 4]; 16] {
+    let (c0, c1) = (u16::from_le_bytes([block[0], block[1]]), u16::from_le_bytes([block[2], block[3]]));
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mix = |wa: u32, wb: u32| -> [u8; 4] {
+        let channel = |i: usize| ((a[i] as u32 * wa + b[i] as u32 * wb + (wa + wb) / 2) / (wa + wb)) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+    let palette = if c0 > c1 || !punch_through {
+        [a, b, mix(2, 1), mix(1, 2)]
+    } else {
+        [a, b, mix(1, 1), [0; 4]]
+    };
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|i| palette[((indices >> (i * 2)) & 3) as usize])
+}
+
+// Whether a BC1 block is in its three-color mode and has a texel of transparent black
+fn bc1_punches_through(block: &[u8]) -> bool {
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    u16::from_le_bytes([block[0], block[1]]) <= u16::from_le_bytes([block[2], block[3]])
+        && (0..16).any(|i| (indices >> (i * 2)) & 3 == 3)
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let (r, g, b) = ((color >> 11) & 31, (color >> 5) & 63, color & 31);
+    [(r << 3 | r >> 2) as u8, (g << 2 | g >> 4) as u8, (b << 3 | b >> 2) as u8, 255]
+}
+
+// One channel of a BC4 block, also BC3's alpha and each of BC5's two. Signed blocks come out as the bytes of
+// snorm values, -127 to 127
+fn decode_bc4_channel(block: &[u8], signed: bool) -> [u8; 16] {
+    let (a, b, low, high) = if signed {
+        let value = |byte: u8| (byte as i8).max(-127) as i32;
+        (value(block[0]), value(block[1]), -127, 127)
+    } else {
+        (block[0] as i32, block[1] as i32, 0, 255)
+    };
+    let palette: [i32; 8] = std::array::from_fn(|i| match i {
+        0 => a,
+        1 => b,
+        _ if a > b => lerp_rounded(a, b, i as i32 - 1, 7),
+        6 => low,
+        7 => high,
+        _ => lerp_rounded(a, b, i as i32 - 1, 5),
+    });
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    std::array::from_fn(|i| palette[((indices >> (i * 3)) & 7) as usize] as u8)
+}
+
+// `a` moved `step` of `steps` towards `b`, to the nearest integer
+fn lerp_rounded(a: i32, b: i32, step: i32, steps: i32) -> i32 {
+    let total = a * (steps - step) + b * step;
+    (total + total.signum() * steps / 2) / steps
+}
+
+// Reads a 128-bit block from its lowest bit up, as BC6H and BC7 pack them
+struct BlockBits {
+    bits: u128,
+    position: u32,
+}
+
+impl BlockBits {
+    fn new(block: &[u8]) -> Self {
+        let bits = u128::from_le_bytes(block[..16].try_into().expect("sixteen bytes"));
+        Self { bits, position: 0 }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.bits >> self.position) as u32 & ((1u64 << count) - 1) as u32;
+        self.position += count;
+        value
+    }
+}
+
+// Interpolation weights out of 64 for 2-, 3- and 4-bit indices
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weight(bits: u32, index: u32) -> u32 {
+    match bits {
+        2 => WEIGHTS_2[index as usize],
+        3 => WEIGHTS_3[index as usize],
+        _ => WEIGHTS_4[index as usize],
+    }
+}
+
+// Which of two subsets each texel of a BC6H or BC7 partition is in, one bit per texel from the lowest
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00,
+    0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE, 0x088C, 0x3110, 0x6666, 0x366C,
+    0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8,
+    0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660, 0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C,
+    0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+// Which of three subsets each texel of a BC7 partition is in, two bits per texel from the lowest
+const PARTITIONS_3: [u32; 64] = [
+    0xAA685050, 0x6A5A5040, 0x5A5A4200, 0x5450A0A8, 0xA5A50000, 0xA0A05050, 0x5555A0A0, 0x5A5A5050, 0xAA550000,
+    0xAA555500, 0xAAAA5500, 0x90909090, 0x94949494, 0xA4A4A4A4, 0xA9A59450, 0x2A0A4250, 0xA5945040, 0x0A425054,
+    0xA5A5A500, 0x55A0A0A0, 0xA8A85454, 0x6A6A4040, 0xA4A45000, 0x1A1A0500, 0x0050A4A4, 0xAAA59090, 0x14696914,
+    0x69691400, 0xA08585A0, 0xAA821414, 0x50A4A450, 0x6A5A0200, 0xA9A58000, 0x5090A0A8, 0xA8A09050, 0x24242424,
+    0x00AA5500, 0x24924924, 0x24499224, 0x50A50A50, 0x500AA550, 0xAAAA4444, 0x66660000, 0xA5A0A5A0, 0x50A050A0,
+    0x69286928, 0x44AAAA44, 0x66666600, 0xAA444444, 0x54A854A8, 0x95809580, 0x96969600, 0xA85454A8, 0x80959580,
+    0xAA141414, 0x96960000, 0xAAAA1414, 0xA05050A0, 0xA0A5A5A0, 0x96000000, 0x40804080, 0xA9A8A9A8, 0xAAAAAA44,
+    0x2A4A5254,
+];
+
+// The texel of the second subset, and of the second and third, whose index is stored a bit shorter
+const ANCHORS_2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+const ANCHORS_3: [[usize; 2]; 64] = [
+    [3, 15], [3, 8], [15, 8], [15, 3], [8, 15], [3, 15], [15, 3], [15, 8], [8, 15], [8, 15], [6, 15], [6, 15],
+    [6, 15], [5, 15], [3, 15], [3, 8], [3, 15], [3, 8], [8, 15], [15, 3], [3, 15], [3, 8], [6, 15], [10, 8],
+    [5, 3], [8, 15], [8, 6], [6, 10], [8, 15], [5, 15], [15, 10], [15, 8], [8, 15], [15, 3], [3, 15], [5, 10],
+    [6, 10], [10, 8], [8, 9], [15, 10], [15, 6], [3, 15], [15, 8], [5, 15], [15, 3], [15, 6], [15, 6], [15, 8],
+    [3, 15], [15, 3], [5, 15], [5, 15], [5, 15], [8, 15], [5, 15], [10, 15], [5, 15], [10, 15], [8, 15], [13, 15],
+    [15, 3], [12, 15], [3, 15], [3, 8],
+];
+
+// The subset `texel` is in, and whether its index is stored a bit shorter
+fn subset(subsets: usize, partition: usize, texel: usize) -> (usize, bool) {
+    match subsets {
+        1 => (0, texel == 0),
+        2 => ((PARTITIONS_2[partition] >> texel) as usize & 1, texel == 0 || texel == ANCHORS_2[partition]),
+        _ => {
+            let anchor = texel == 0 || ANCHORS_3[partition].contains(&texel);
+            ((PARTITIONS_3[partition] >> (texel * 2)) as usize & 3, anchor)
+        }
+    }
+}
+
+// The layout of one BC7 mode
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,         // 0 for opaque modes
+    endpoint_p_bits: bool,   // A low bit for each endpoint
+    shared_p_bits: bool,     // One for both endpoints of a subset
+    index_bits: u32,
+    second_index_bits: u32,  // Modes 4 and 5 index color and alpha separately
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode(3, 4, 0, 0, 4, 0, true, false, 3, 0),
+    bc7_mode(2, 6, 0, 0, 6, 0, false, true, 3, 0),
+    bc7_mode(3, 6, 0, 0, 5, 0, false, false, 2, 0),
+    bc7_mode(2, 6, 0, 0, 7, 0, true, false, 2, 0),
+    bc7_mode(1, 0, 2, 1, 5, 6, false, false, 2, 3),
+    bc7_mode(1, 0, 2, 0, 7, 8, false, false, 2, 2),
+    bc7_mode(1, 0, 0, 0, 7, 7, true, false, 4, 0),
+    bc7_mode(2, 6, 0, 0, 5, 5, true, false, 2, 0),
+];
+
+#[allow(clippy::too_many_arguments)]
+const fn bc7_mode(
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    second_index_bits: u32,
+) -> Bc7Mode {
+    Bc7Mode {
+        subsets,
+        partition_bits,
+        rotation_bits,
+        index_selection_bits,
+        color_bits,
+        alpha_bits,
+        endpoint_p_bits,
+        shared_p_bits,
+        index_bits,
+        second_index_bits,
+    }
+}
+
+fn decode_bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut bits = BlockBits::new(block);
+    let mode_number = block[0].trailing_zeros();
+    let Some(mode) = BC7_MODES.get(mode_number as usize) else {
+        return [[0; 4]; 16]; // Reserved, decoded as transparent black
+    };
+    bits.read(mode_number + 1);
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    // Every endpoint's red, then green, blue and alpha, subset by subset
+    let mut endpoints = [[[0u32; 4]; 2]; 3];
+    let channel_bits = [mode.color_bits, mode.color_bits, mode.color_bits, mode.alpha_bits];
+    for (channel, &count) in channel_bits.iter().enumerate() {
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            for endpoint in subset.iter_mut() {
+                endpoint[channel] = bits.read(count);
+            }
+        }
+    }
+    let p_bits = mode.endpoint_p_bits || mode.shared_p_bits;
+    if p_bits {
+        let shared = mode.shared_p_bits.then(|| [bits.read(1), bits.read(1)]);
+        for (subset, ends) in endpoints.iter_mut().take(mode.subsets).enumerate() {
+            for endpoint in ends.iter_mut() {
+                let p = shared.map_or_else(|| bits.read(1), |shared| shared[subset]);
+                for (value, &count) in endpoint.iter_mut().zip(&channel_bits) {
+                    if count > 0 {
+                        *value = *value << 1 | p;
+                    }
+                }
+            }
+        }
+    }
+    // Widened to 8 bits; modes without alpha are opaque
+    for ends in endpoints.iter_mut().take(mode.subsets) {
+        for endpoint in ends.iter_mut() {
+            for (value, &count) in endpoint.iter_mut().zip(&channel_bits) {
+                *value = if count == 0 { 255 } else { expand_bits(*value, count + u32::from(p_bits)) };
+            }
+        }
+    }
+
+    let mut indices = [[0u32; 2]; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        let (_, anchor) = subset(mode.subsets, partition, texel);
+        index[0] = bits.read(mode.index_bits - u32::from(anchor));
+    }
+    if mode.second_index_bits > 0 {
+        for (texel, index) in indices.iter_mut().enumerate() {
+            index[1] = bits.read(mode.second_index_bits - u32::from(texel == 0));
+        }
+    }
+
+    std::array::from_fn(|texel| {
+        let (subset, _) = subset(mode.subsets, partition, texel);
+        let [from, to] = endpoints[subset];
+        let [first, second] = indices[texel];
+        let (color, alpha) = match (mode.second_index_bits, index_selection) {
+            (0, _) => ((first, mode.index_bits), (first, mode.index_bits)),
+            (bits, 0) => ((first, mode.index_bits), (second, bits)),
+            (bits, _) => ((second, bits), (first, mode.index_bits)),
+        };
+        let mut rgba: [u8; 4] = std::array::from_fn(|channel| {
+            let (index, bits) = if channel < 3 { color } else { alpha };
+            let weight = weight(bits, index);
+            ((from[channel] * (64 - weight) + to[channel] * weight + 32) >> 6) as u8
+        });
+        if rotation > 0 {
+            rgba.swap(rotation as usize - 1, 3);
+        }
+        rgba
+    })
+}
+
+// A `count`-bit value widened to 8 bits by repeating its top bits below it
+fn expand_bits(value: u32, count: u32) -> u32 {
+    let value = value << (8 - count);
+    value | value >> count
+}
+
+// BC6H endpoint fields: the base endpoint W and X, Y and Z, each red, green and blue
+const RW: usize = 0;
+const GW: usize = 1;
+const BW: usize = 2;
+const RX: usize = 3;
+const GX: usize = 4;
+const BX: usize = 5;
+const RY: usize = 6;
+const GY: usize = 7;
+const BY: usize = 8;
+const RZ: usize = 9;
+const GZ: usize = 10;
+const BZ: usize = 11;
+
+// The layout of one BC6H mode. Each field piece is written as in the format's documentation: `(field, a, b)`
+// holds bits a to b, stored starting from b, so (RW, 10, 15) is stored reversed
+struct Bc6hMode {
+    mode: u32,
+    two_regions: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3], // Of X, Y and Z, red to blue; the same as `endpoint_bits` when not transformed
+    transformed: bool,    // X, Y and Z are stored as differences from W
+    fields: &'static [(usize, u32, u32)],
+}
+
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode {
+        mode: 0b00,
+        two_regions: true,
+        endpoint_bits: 10,
+        delta_bits: [5, 5, 5],
+        transformed: true,
+        fields: &[
+            (GY, 4, 4), (BY, 4, 4), (BZ, 4, 4), (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 4, 0), (GZ, 4, 4),
+            (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1), (BY, 3, 0), (RY, 4, 0),
+            (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b01,
+        two_regions: true,
+        endpoint_bits: 7,
+        delta_bits: [6, 6, 6],
+        transformed: true,
+        fields: &[
+            (GY, 5, 5), (GZ, 4, 4), (GZ, 5, 5), (RW, 6, 0), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 6, 0),
+            (BY, 5, 5), (BZ, 2, 2), (GY, 4, 4), (BW, 6, 0), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 5, 0),
+            (GY, 3, 0), (GX, 5, 0), (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0), (RY, 5, 0), (RZ, 5, 0),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b00010,
+        two_regions: true,
+        endpoint_bits: 11,
+        delta_bits: [5, 4, 4],
+        transformed: true,
+        fields: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 4, 0), (RW, 10, 10), (GY, 3, 0), (GX, 3, 0), (GW, 10, 10),
+            (BZ, 0, 0), (GZ, 3, 0), (BX, 3, 0), (BW, 10, 10), (BZ, 1, 1), (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2),
+            (RZ, 4, 0), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b00110,
+        two_regions: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 5, 4],
+        transformed: true,
+        fields: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 10), (GZ, 4, 4), (GY, 3, 0), (GX, 4, 0),
+            (GW, 10, 10), (GZ, 3, 0), (BX, 3, 0), (BW, 10, 10), (BZ, 1, 1), (BY, 3, 0), (RY, 3, 0), (BZ, 0, 0),
+            (BZ, 2, 2), (RZ, 3, 0), (GY, 4, 4), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b01010,
+        two_regions: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 4, 5],
+        transformed: true,
+        fields: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 10), (BY, 4, 4), (GY, 3, 0), (GX, 3, 0),
+            (GW, 10, 10), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BW, 10, 10), (BY, 3, 0), (RY, 3, 0), (BZ, 1, 1),
+            (BZ, 2, 2), (RZ, 3, 0), (BZ, 4, 4), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b01110,
+        two_regions: true,
+        endpoint_bits: 9,
+        delta_bits: [5, 5, 5],
+        transformed: true,
+        fields: &[
+            (RW, 8, 0), (BY, 4, 4), (GW, 8, 0), (GY, 4, 4), (BW, 8, 0), (BZ, 4, 4), (RX, 4, 0), (GZ, 4, 4),
+            (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1), (BY, 3, 0), (RY, 4, 0),
+            (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b10010,
+        two_regions: true,
+        endpoint_bits: 8,
+        delta_bits: [6, 5, 5],
+        transformed: true,
+        fields: &[
+            (RW, 7, 0), (GZ, 4, 4), (BY, 4, 4), (GW, 7, 0), (BZ, 2, 2), (GY, 4, 4), (BW, 7, 0), (BZ, 3, 3),
+            (BZ, 4, 4), (RX, 5, 0), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1),
+            (BY, 3, 0), (RY, 5, 0), (RZ, 5, 0),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b10110,
+        two_regions: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 6, 5],
+        transformed: true,
+        fields: &[
+            (RW, 7, 0), (BZ, 0, 0), (BY, 4, 4), (GW, 7, 0), (GY, 5, 5), (GY, 4, 4), (BW, 7, 0), (GZ, 5, 5),
+            (BZ, 4, 4), (RX, 4, 0), (GZ, 4, 4), (GY, 3, 0), (GX, 5, 0), (GZ, 3, 0), (BX, 4, 0), (BZ, 1, 1),
+            (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b11010,
+        two_regions: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 5, 6],
+        transformed: true,
+        fields: &[
+            (RW, 7, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 7, 0), (BY, 5, 5), (GY, 4, 4), (BW, 7, 0), (BZ, 5, 5),
+            (BZ, 4, 4), (RX, 4, 0), (GZ, 4, 4), (GY, 3, 0), (GX, 4, 0), (BZ, 0, 0), (GZ, 3, 0), (BX, 5, 0),
+            (BY, 3, 0), (RY, 4, 0), (BZ, 2, 2), (RZ, 4, 0), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b11110,
+        two_regions: true,
+        endpoint_bits: 6,
+        delta_bits: [6, 6, 6],
+        transformed: false,
+        fields: &[
+            (RW, 5, 0), (GZ, 4, 4), (BZ, 0, 0), (BZ, 1, 1), (BY, 4, 4), (GW, 5, 0), (GY, 5, 5), (BY, 5, 5),
+            (BZ, 2, 2), (GY, 4, 4), (BW, 5, 0), (GZ, 5, 5), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 5, 0),
+            (GY, 3, 0), (GX, 5, 0), (GZ, 3, 0), (BX, 5, 0), (BY, 3, 0), (RY, 5, 0), (RZ, 5, 0),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b00011,
+        two_regions: false,
+        endpoint_bits: 10,
+        delta_bits: [10, 10, 10],
+        transformed: false,
+        fields: &[(RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 9, 0), (GX, 9, 0), (BX, 9, 0)],
+    },
+    Bc6hMode {
+        mode: 0b00111,
+        two_regions: false,
+        endpoint_bits: 11,
+        delta_bits: [9, 9, 9],
+        transformed: true,
+        fields: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 8, 0), (RW, 10, 10), (GX, 8, 0), (GW, 10, 10), (BX, 8, 0),
+            (BW, 10, 10),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b01011,
+        two_regions: false,
+        endpoint_bits: 12,
+        delta_bits: [8, 8, 8],
+        transformed: true,
+        fields: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 7, 0), (RW, 10, 11), (GX, 7, 0), (GW, 10, 11), (BX, 7, 0),
+            (BW, 10, 11),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b01111,
+        two_regions: false,
+        endpoint_bits: 16,
+        delta_bits: [4, 4, 4],
+        transformed: true,
+        fields: &[
+            (RW, 9, 0), (GW, 9, 0), (BW, 9, 0), (RX, 3, 0), (RW, 10, 15), (GX, 3, 0), (GW, 10, 15), (BX, 3, 0),
+            (BW, 10, 15),
+        ],
+    },
+];
+
+// Half-float bits of each texel's red, green, blue and alpha, alpha always 1
+fn decode_bc6h(block: &[u8], signed: bool) -> [[u16; 4]; 16] {
+    let mut bits = BlockBits::new(block);
+    let low = bits.read(2);
+    let number = if low < 2 { low } else { low | bits.read(3) << 2 };
+    let Some(mode) = BC6H_MODES.iter().find(|mode| mode.mode == number) else {
+        return [[0, 0, 0, 0x3C00]; 16]; // Reserved, decoded as opaque black
+    };
+    let mut fields = [0i32; 12];
+    for &(field, a, b) in mode.fields {
+        for i in 0..=a.abs_diff(b) {
+            let bit = if a >= b { b + i } else { b - i };
+            fields[field] |= (bits.read(1) << bit) as i32;
+        }
+    }
+    let (regions, index_bits) = if mode.two_regions { (2, 3) } else { (1, 4) };
+    let partition = if mode.two_regions { bits.read(5) as usize } else { 0 };
+
+    // W, X, Y and Z, with the differences applied and every component unquantized to 16 bits
+    let mut endpoints: [[i32; 3]; 4] = std::array::from_fn(|end| std::array::from_fn(|c| fields[end * 3 + c]));
+    let mask = (1i32 << mode.endpoint_bits) - 1;
+    for channel in 0..3 {
+        if signed {
+            endpoints[0][channel] = sign_extend(endpoints[0][channel], mode.endpoint_bits);
+        }
+        let base = endpoints[0][channel];
+        for end in endpoints.iter_mut().take(regions * 2).skip(1) {
+            let value = &mut end[channel];
+            if mode.transformed {
+                *value = (base + sign_extend(*value, mode.delta_bits[channel])) & mask;
+                if signed {
+                    *value = sign_extend(*value, mode.endpoint_bits);
+                }
+            } else if signed {
+                *value = sign_extend(*value, mode.endpoint_bits);
+            }
+        }
+    }
+    let endpoints = endpoints.map(|end| end.map(|value| unquantize(value, mode.endpoint_bits, signed)));
+
+    let mut indices = [0u32; 16];
+    for (texel, index) in indices.iter_mut().enumerate() {
+        let (_, anchor) = subset(regions, partition, texel);
+        *index = bits.read(index_bits - u32::from(anchor));
+    }
+    std::array::from_fn(|texel| {
+        let (region, _) = subset(regions, partition, texel);
+        let weight = weight(index_bits, indices[texel]) as i32;
+        let [from, to] = [endpoints[region * 2], endpoints[region * 2 + 1]];
+        let channel = |c: usize| finish_unquantize((from[c] * (64 - weight) + to[c] * weight + 32) >> 6, signed);
+        [channel(0), channel(1), channel(2), 0x3C00]
+    })
+}
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+// An endpoint component widened to the 16-bit range the interpolation runs in
+fn unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        match value {
+            _ if bits >= 15 => value,
+            0 => 0,
+            _ if value == (1 << bits) - 1 => 0xFFFF,
+            _ => ((value << 16) + 0x8000) >> bits,
+        }
+    } else {
+        let magnitude = match value.abs() {
+            _ if bits >= 16 => return value,
+            0 => 0,
+            magnitude if magnitude >= (1 << (bits - 1)) - 1 => 0x7FFF,
+            magnitude => ((magnitude << 15) + 0x4000) >> (bits - 1),
+        };
+        if value < 0 {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+// An interpolated component scaled into half-float bits
+fn finish_unquantize(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        ((-value * 31) >> 5) as u16 | 0x8000
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
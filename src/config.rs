@@ -0,0 +1,166 @@
+// config.rs
+
+use egui_wgpu::wgpu;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Read from where the app is run, like `shaders/` and `plugins/`
+pub const CONFIG_FILE_NAME: &str = "app_config.ron";
+
+// Which GPU to ask for when the machine has more than one
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerPreference {
+    #[default]
+    Default,
+    LowPower, // Usually the integrated GPU
+    HighPerformance, // Usually the discrete GPU
+}
+
+impl PowerPreference {
+    pub fn wgpu(&self) -> wgpu::PowerPreference {
+        match self {
+            PowerPreference::Default => wgpu::PowerPreference::default(),
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+// Which graphics API to draw with. The adapters each one offers are listed in the About / Diagnostics window
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backend {
+    #[default]
+    Auto, // Whichever API the picked adapter is found on first
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl Backend {
+    pub fn wgpu(&self) -> wgpu::Backends {
+        match self {
+            Backend::Auto => wgpu::Backends::all(),
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+
+    /// The backend an adapter was found on, or `Auto` for ones that can't be asked for by name.
+    pub fn of(backend: wgpu::Backend) -> Self {
+        match backend {
+            wgpu::Backend::Vulkan => Backend::Vulkan,
+            wgpu::Backend::Metal => Backend::Metal,
+            wgpu::Backend::Dx12 => Backend::Dx12,
+            wgpu::Backend::Gl => Backend::Gl,
+            _ => Backend::Auto,
+        }
+    }
+
+    /// How `WGPU_BACKEND` names it.
+    pub fn env_name(&self) -> Option<&'static str> {
+        match self {
+            Backend::Auto => None,
+            Backend::Vulkan => Some("vulkan"),
+            Backend::Metal => Some("metal"),
+            Backend::Dx12 => Some("dx12"),
+            Backend::Gl => Some("gl"),
+        }
+    }
+}
+
+// How the app starts, set by whoever builds on the template rather than by its users. Options left unset defer
+// to the user's saved settings; set ones replace them at each launch, and the user can still change them after
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub title: String,
+    pub window_size: (u32, u32), // Physical pixels of the desktop window; phones and browsers fill the screen
+    pub vsync: Option<bool>, // Vsync or uncapped frame pacing
+    pub scale_factor: Option<f32>, // Replaces the monitor's scale factor for the UI
+    pub power_preference: PowerPreference,
+    pub backend: Backend,
+    pub adapter: Option<String>, // Part of the adapter's name, ignoring case, such as "nvidia" or "intel"
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            title: "Voxxele".to_string(),
+            window_size: (1360, 768),
+            vsync: None,
+            scale_factor: None,
+            power_preference: PowerPreference::Default,
+            backend: Backend::Auto,
+            adapter: None,
+        }
+    }
+}
+
+impl AppConfig {
+    /// Reads `app_config.ron` from the working directory, with the defaults for anything it leaves out or
+    /// when there is none.
+    pub fn load() -> Self {
+        Self::load_from(Path::new(CONFIG_FILE_NAME))
+    }
+
+    pub fn load_from(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+                log::warn!("Ignoring invalid app config {}: {err}", path.display());
+                Self::default()
+            }),
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    log::warn!("Failed to read app config from {}: {err}", path.display());
+                }
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn window_size(mut self, width: u32, height: u32) -> Self {
+        self.window_size = (width.max(1), height.max(1));
+        self
+    }
+
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.vsync = Some(vsync);
+        self
+    }
+
+    pub fn scale_factor(mut self, scale: f32) -> Self {
+        self.scale_factor = Some(scale);
+        self
+    }
+
+    pub fn power_preference(mut self, preference: PowerPreference) -> Self {
+        self.power_preference = preference;
+        self
+    }
+
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn adapter(mut self, name: impl Into<String>) -> Self {
+        self.adapter = Some(name.into());
+        self
+    }
+}
@@ -0,0 +1,197 @@
+// console.rs
+
+use egui::{Color32, Context};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, TryLockError};
+
+use crate::egui_tools::detachable_window;
+
+const MAX_ENTRIES: usize = 1000;
+
+// Records at or above this level are always captured for the console, plus whatever RUST_LOG enables
+const CAPTURE_LEVEL: Level = Level::Info;
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+static LOG_BUFFER: Mutex<VecDeque<LogEntry>> = Mutex::new(VecDeque::new());
+
+// Forwards to env_logger for the terminal and keeps the most recent records for the Console panel
+struct ConsoleLogger {
+    inner: env_logger::Logger,
+    // Android apps have no terminal; whatever the panel captures goes to logcat too
+    #[cfg(target_os = "android")]
+    logcat: android_logger::AndroidLogger,
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= CAPTURE_LEVEL || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+        if record.level() <= CAPTURE_LEVEL || self.inner.matches(record) {
+            #[cfg(target_os = "android")]
+            self.logcat.log(record);
+            // Nor do browsers; it goes to the developer console there
+            #[cfg(target_arch = "wasm32")]
+            console_log::log(record);
+            let mut buffer = LOG_BUFFER.lock().unwrap_or_else(|e| e.into_inner());
+            if buffer.len() == MAX_ENTRIES {
+                buffer.pop_front();
+            }
+            buffer.push_back(LogEntry {
+                level: record.level(),
+                target: record.target().to_string(),
+                message: record.args().to_string(),
+            });
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs the global logger. Use instead of `env_logger::init()` so the Console panel has content.
+pub fn init_logger() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let max_level = inner.filter().max(CAPTURE_LEVEL.to_level_filter());
+    let logger = ConsoleLogger {
+        inner,
+        #[cfg(target_os = "android")]
+        logcat: android_logger::AndroidLogger::new(android_logger::Config::default().with_tag("Voxxele")),
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Up to `count` of the most recent records the console captured, oldest first.
+pub fn recent_entries(count: usize) -> Vec<LogEntry> {
+    let buffer = LOG_BUFFER.lock().unwrap_or_else(|e| e.into_inner());
+    buffer.iter().skip(buffer.len().saturating_sub(count)).cloned().collect()
+}
+
+/// Like `recent_entries`, but None rather than waiting while the log is locked, as from a panic hook that
+/// may have interrupted a thread logging.
+pub fn try_recent_entries(count: usize) -> Option<Vec<LogEntry>> {
+    let buffer = match LOG_BUFFER.try_lock() {
+        Ok(buffer) => buffer,
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => return None,
+    };
+    Some(buffer.iter().skip(buffer.len().saturating_sub(count)).cloned().collect())
+}
+
+// Filter state lives behind a lock so a detached Console window can draw it from its own viewport
+struct ConsoleState {
+    level_filter: LevelFilter,
+    search: String,
+}
+
+pub struct Console {
+    state: Arc<Mutex<ConsoleState>>,
+    detached: Arc<AtomicBool>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ConsoleState {
+                level_filter: LevelFilter::Info,
+                search: String::new(),
+            })),
+            detached: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        let state = self.state.clone();
+        detachable_window(ctx, "Console", [520.0, 240.0], &self.detached, move |ui| {
+            state.lock().unwrap_or_else(|e| e.into_inner()).ui(ui);
+        });
+    }
+}
+
+impl ConsoleState {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        // Copy out under the lock so nothing logged while drawing can deadlock
+        let entries: Vec<LogEntry> = LOG_BUFFER
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|entry| self.matches(entry))
+            .cloned()
+            .collect();
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("console_level")
+                .selected_text(self.level_filter.as_str())
+                .show_ui(ui, |ui| {
+                    for level in [
+                        LevelFilter::Error,
+                        LevelFilter::Warn,
+                        LevelFilter::Info,
+                        LevelFilter::Debug,
+                        LevelFilter::Trace,
+                    ] {
+                        ui.selectable_value(&mut self.level_filter, level, level.as_str());
+                    }
+                });
+            ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("Search"));
+            if ui.button("Copy").clicked() {
+                let text = entries
+                    .iter()
+                    .map(format_entry)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                ui.output_mut(|o| o.copied_text = text);
+            }
+            if ui.button("Clear").clicked() {
+                LOG_BUFFER.lock().unwrap_or_else(|e| e.into_inner()).clear();
+            }
+        });
+        ui.separator();
+
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for entry in &entries {
+                    let color = match entry.level {
+                        Level::Error => Color32::from_rgb(255, 90, 90),
+                        Level::Warn => Color32::from_rgb(255, 200, 80),
+                        Level::Info => ui.visuals().text_color(),
+                        Level::Debug | Level::Trace => ui.visuals().weak_text_color(),
+                    };
+                    ui.colored_label(color, egui::RichText::new(format_entry(entry)).monospace());
+                }
+            });
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        entry.level <= self.level_filter
+            && (self.search.is_empty()
+                || entry.message.to_lowercase().contains(&self.search.to_lowercase()))
+    }
+}
+
+fn format_entry(entry: &LogEntry) -> String {
+    format!("[{}] {}: {}", entry.level, entry.target, entry.message)
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
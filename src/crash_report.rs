@@ -0,0 +1,132 @@
+// crash_report.rs
+
+use crate::console;
+use crate::settings::{self, Settings};
+use std::backtrace::Backtrace;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::panic::PanicHookInfo;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, TryLockError};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+// Log lines at the end of a report
+const LOG_LINES: usize = 100;
+
+// What the panic hook can't ask the render loop for once it has panicked, kept up to date as the app runs
+struct CrashContext {
+    diagnostics: String,
+    settings: Option<Settings>, // Serialized only when a report is written
+    screenshot: Option<(u32, u32, Vec<u8>)>, // RGBA8 pixels of the last screenshot taken this session
+}
+
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    diagnostics: String::new(),
+    settings: None,
+    screenshot: None,
+});
+
+// Only the first panic is reported; the ones it sets off in other threads would bury it
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Installs a panic hook that writes a crash report to the `crashes` directory next to the settings,
+/// after the default hook has printed the panic.
+pub fn install() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        if REPORTED.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        match write_report(info) {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(err) => eprintln!("Failed to write crash report: {err}"),
+        }
+    }));
+}
+
+/// Records the adapter, backend and features for reports, as the Diagnostics panel puts them.
+pub fn set_diagnostics(report: String) {
+    lock().diagnostics = report;
+}
+
+/// Records the settings as they stand, including changes not saved yet.
+pub fn set_settings(settings: &Settings) {
+    lock().settings = Some(settings.clone());
+}
+
+/// Keeps a screenshot to save with a report, since the GPU can't be read back from a panic.
+pub fn set_screenshot(width: u32, height: u32, pixels: Vec<u8>) {
+    lock().screenshot = Some((width, height, pixels));
+}
+
+fn lock() -> std::sync::MutexGuard<'static, CrashContext> {
+    CONTEXT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write_report(info: &PanicHookInfo) -> io::Result<PathBuf> {
+    let dir = settings::settings_path("crashes");
+    fs::create_dir_all(&dir)?;
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let path = dir.join(format!("crash-{seconds}.txt"));
+
+    let thread = std::thread::current();
+    let mut report = String::new();
+    let _ = writeln!(report, "{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "Thread '{}' {info}", thread.name().unwrap_or("<unnamed>"));
+    let _ = writeln!(report, "\n## Backtrace\n{}", Backtrace::force_capture());
+
+    // The hook runs before unwinding releases anything, so a lock held by the panicking thread would never be
+    // let go; what it guards is left out rather than waited for
+    let context = match CONTEXT.try_lock() {
+        Ok(context) => Some(context),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    };
+    let Some(context) = context else {
+        report.push_str("## Diagnostics, settings and screenshot\nLeft out: the panic came while they were locked\n\n");
+        write_log(&mut report);
+        fs::write(&path, report)?;
+        return Ok(path);
+    };
+    let diagnostics = match context.diagnostics.as_str() {
+        "" => "Not collected yet; the panic came before the device was created",
+        diagnostics => diagnostics,
+    };
+    let _ = writeln!(report, "## Diagnostics\n{diagnostics}\n");
+    let settings = match &context.settings {
+        Some(settings) => ron::ser::to_string_pretty(settings, ron::ser::PrettyConfig::default())
+            .unwrap_or_else(|err| format!("Failed to serialize settings: {err}")),
+        None => "Not loaded yet".to_string(),
+    };
+    let _ = writeln!(report, "## Settings\n{settings}\n");
+    write_log(&mut report);
+
+    match &context.screenshot {
+        Some((width, height, pixels)) => {
+            let screenshot = path.with_extension("png");
+            let saved = image::save_buffer(&screenshot, pixels, *width, *height, image::ColorType::Rgba8);
+            let _ = match saved {
+                Ok(()) => writeln!(report, "\nLast screenshot: {}", screenshot.display()),
+                Err(err) => writeln!(report, "\nFailed to save the last screenshot: {err}"),
+            };
+        }
+        None => report.push_str("\nNo screenshot was taken this session\n"),
+    }
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+fn write_log(report: &mut String) {
+    let _ = writeln!(report, "## Last {LOG_LINES} log lines");
+    match console::try_recent_entries(LOG_LINES) {
+        Some(entries) => {
+            for entry in entries {
+                let _ = writeln!(report, "[{}] {}: {}", entry.level, entry.target, entry.message);
+            }
+        }
+        None => report.push_str("Left out: the panic came while the log was locked\n"),
+    }
+}
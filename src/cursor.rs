@@ -0,0 +1,90 @@
+// cursor.rs
+
+use winit::window::{CursorGrabMode, Window};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CursorMode {
+    Normal,
+    Hidden,   // Invisible over the window but free to leave it
+    Confined, // Visible but kept inside the window
+    Locked,   // Invisible and fixed in place, for mouse-look with raw motion
+}
+
+/// Applies the cursor mode systems ask for, falling back to what the platform supports and
+/// handing the cursor back whenever egui needs it or the window is not focused.
+pub struct CursorController {
+    requested: CursorMode,
+    target: CursorMode,  // Last mode passed to the window, before fallbacks
+    applied: CursorMode, // What the window actually does
+    focused: bool,
+}
+
+impl CursorController {
+    pub fn new() -> Self {
+        Self {
+            requested: CursorMode::Normal,
+            target: CursorMode::Normal,
+            applied: CursorMode::Normal,
+            focused: true,
+        }
+    }
+
+    // Takes effect on the next `update`
+    pub fn request(&mut self, mode: CursorMode) {
+        self.requested = mode;
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    /// The mode actually in effect after fallbacks.
+    pub fn applied(&self) -> CursorMode {
+        self.applied
+    }
+
+    pub fn update(&mut self, window: &Window, egui_wants_pointer: bool) {
+        let target = if self.focused && !egui_wants_pointer {
+            self.requested
+        } else {
+            CursorMode::Normal
+        };
+        if target != self.target {
+            self.target = target;
+            self.applied = apply(window, target);
+        }
+    }
+}
+
+impl Default for CursorController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Returns the mode that could be applied; Locked falls back to Confined (Windows, X11), and
+// Confined to Normal (macOS, web)
+fn apply(window: &Window, mode: CursorMode) -> CursorMode {
+    let grab = |grab_mode| {
+        window
+            .set_cursor_grab(grab_mode)
+            .map_err(|err| log::debug!("Cursor grab {grab_mode:?} unavailable: {err}"))
+            .is_ok()
+    };
+    let applied = match mode {
+        CursorMode::Normal | CursorMode::Hidden => {
+            grab(CursorGrabMode::None);
+            mode
+        }
+        CursorMode::Confined if grab(CursorGrabMode::Confined) => CursorMode::Confined,
+        CursorMode::Locked if grab(CursorGrabMode::Locked) => CursorMode::Locked,
+        CursorMode::Locked if grab(CursorGrabMode::Confined) => CursorMode::Confined,
+        CursorMode::Confined | CursorMode::Locked => {
+            grab(CursorGrabMode::None);
+            CursorMode::Normal
+        }
+    };
+    // Locking hides the cursor even when only the confined fallback worked
+    window.set_cursor_visible(!matches!(mode, CursorMode::Hidden | CursorMode::Locked));
+    applied
+}
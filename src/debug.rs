@@ -0,0 +1,282 @@
+// debug.rs
+
+use crate::bounds::Aabb;
+use crate::depth::DepthConfig;
+use crate::stats::RenderStats;
+use crate::upload::Uploads;
+use crate::vertex_layout::{vertex_layout, VertexLayout};
+use bytemuck::{Pod, Zeroable};
+use egui::{Color32, Rgba};
+use egui_wgpu::wgpu;
+use glam::Vec3;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Vertices kept per frame at most; lines past this are dropped, so a frame that is never drawn can't pile up
+const MAX_VERTICES: usize = 1 << 20;
+const CIRCLE_SEGMENTS: usize = 32;
+
+// Lines queued since the last frame was drawn. Anything may add to them, from any thread
+static LINES: Mutex<Vec<LineVertex>> = Mutex::new(Vec::new());
+
+// Every category lines have been drawn in, and whether the Debug Draw menu shows it
+static CATEGORIES: Mutex<BTreeMap<&'static str, bool>> = Mutex::new(BTreeMap::new());
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 4], // Linear, premultiplied
+}
+
+impl LineVertex {
+    // Locations must match `VertexInput` in debug.wgsl
+    const LAYOUT: VertexLayout = vertex_layout!(LineVertex, Vertex {
+        0 => position: Float32x3,
+        1 => color: Float32x4,
+    });
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const LAYOUT: &VertexLayout = &LineVertex::LAYOUT;
+        LAYOUT.buffer_layout()
+    }
+}
+
+fn push(segments: impl IntoIterator<Item = (Vec3, Vec3)>, color: Color32) {
+    let color = Rgba::from(color).to_array();
+    let mut lines = LINES.lock().unwrap_or_else(|e| e.into_inner());
+    for (a, b) in segments {
+        if lines.len() + 2 > MAX_VERTICES {
+            return;
+        }
+        lines.push(LineVertex {
+            position: a.to_array(),
+            color,
+        });
+        lines.push(LineVertex {
+            position: b.to_array(),
+            color,
+        });
+    }
+}
+
+/// Draws a line from `a` to `b` in the next frame.
+pub fn line(a: Vec3, b: Vec3, color: Color32) {
+    Category(None).line(a, b, color);
+}
+
+/// Draws the twelve edges of `bounds` in the next frame.
+pub fn aabb(bounds: &Aabb, color: Color32) {
+    Category(None).aabb(bounds, color);
+}
+
+/// Draws a sphere as three circles, one around each axis, in the next frame.
+pub fn sphere(center: Vec3, radius: f32, color: Color32) {
+    Category(None).sphere(center, radius, color);
+}
+
+/// Draws `length` units of the ray from `origin` along `direction`, with an arrowhead at its end, in the next
+/// frame.
+pub fn ray(origin: Vec3, direction: Vec3, length: f32, color: Color32) {
+    Category(None).ray(origin, direction, length, color);
+}
+
+/// Lines drawn under `name`, which the Debug Draw menu lists once anything has been drawn in it and can hide,
+/// as `debug::category("Paths").line(a, b, color)`. Lines drawn with the free functions are always shown.
+pub fn category(name: &'static str) -> Category {
+    Category(Some(name))
+}
+
+/// Every category drawn in so far, by name, and whether it's shown.
+pub fn categories() -> Vec<(&'static str, bool)> {
+    let categories = CATEGORIES.lock().unwrap_or_else(|e| e.into_inner());
+    categories.iter().map(|(name, shown)| (*name, *shown)).collect()
+}
+
+pub fn set_category_shown(name: &'static str, shown: bool) {
+    CATEGORIES.lock().unwrap_or_else(|e| e.into_inner()).insert(name, shown);
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Category(Option<&'static str>);
+
+impl Category {
+    // Records the category on first use; its lines are dropped while it's hidden
+    fn shown(&self) -> bool {
+        let Some(name) = self.0 else {
+            return true;
+        };
+        *CATEGORIES.lock().unwrap_or_else(|e| e.into_inner()).entry(name).or_insert(true)
+    }
+
+    fn push(&self, segments: impl IntoIterator<Item = (Vec3, Vec3)>, color: Color32) {
+        if self.shown() {
+            push(segments, color);
+        }
+    }
+
+    pub fn line(&self, a: Vec3, b: Vec3, color: Color32) {
+        self.push([(a, b)], color);
+    }
+
+    pub fn aabb(&self, bounds: &Aabb, color: Color32) {
+        let corners = bounds.corners();
+        // Corner indices are bit patterns of x, y and z, so edges join corners one bit apart
+        let edges = (0..8).flat_map(|corner: usize| {
+            [1, 2, 4]
+                .into_iter()
+                .filter(move |bit| corner & bit == 0)
+                .map(move |bit| (corners[corner], corners[corner | bit]))
+        });
+        self.push(edges, color);
+    }
+
+    pub fn sphere(&self, center: Vec3, radius: f32, color: Color32) {
+        if !self.shown() {
+            return;
+        }
+        for (u, v) in [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)] {
+            let point = |index: usize| {
+                let angle = index as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            push((0..CIRCLE_SEGMENTS).map(|index| (point(index), point(index + 1))), color);
+        }
+    }
+
+    pub fn ray(&self, origin: Vec3, direction: Vec3, length: f32, color: Color32) {
+        let direction = direction.normalize_or_zero();
+        let end = origin + direction * length;
+        let head = length.min(1.0) * 0.1;
+        let side = direction.any_orthonormal_vector() * head;
+        let up = direction.cross(side);
+        let back = end - direction * head * 2.0;
+        self.push([(origin, end), (end, back + side), (end, back - side), (end, back + up), (end, back - up)], color);
+    }
+}
+
+// Draws the lines queued with `line`, `aabb`, `sphere` and `ray` over the scene, from a vertex buffer refilled
+// every frame. Lines the scene hides are drawn faded rather than left out
+pub struct DebugRenderer {
+    visible_pipeline: wgpu::RenderPipeline,
+    hidden_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: Option<wgpu::Buffer>, // Grown to the largest frame so far
+}
+
+impl DebugRenderer {
+    /// `camera_layout` is the scene's camera bind group layout, so lines land where the scene is drawn.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, camera_layout: &wgpu::BindGroupLayout) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Debug Line Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("debug.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug Line Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label, entry_point, depth_compare| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[LineVertex::desc()],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+                depth_stencil: Some(DepthConfig::READ_ONLY.with_compare(depth_compare).state()),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        Self {
+            visible_pipeline: pipeline("Debug Line Pipeline", "fs_visible", wgpu::CompareFunction::LessEqual),
+            hidden_pipeline: pipeline("Hidden Debug Line Pipeline", "fs_hidden", wgpu::CompareFunction::Greater),
+            vertex_buffer: None,
+        }
+    }
+
+    /// Draws and clears the lines queued since the last call, depth tested against the scene's `depth`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &wgpu::BindGroup,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) {
+        let lines = std::mem::take(&mut *LINES.lock().unwrap_or_else(|e| e.into_inner()));
+        if lines.is_empty() {
+            return;
+        }
+        let bytes: &[u8] = bytemuck::cast_slice(&lines);
+        let size = bytes.len() as wgpu::BufferAddress;
+        if self.vertex_buffer.as_ref().is_none_or(|buffer| buffer.size() < size) {
+            if let Some(buffer) = self.vertex_buffer.take() {
+                stats.buffer_destroyed(&buffer);
+                buffer.destroy();
+            }
+            self.vertex_buffer = Some(stats.create_buffer(
+                device,
+                &wgpu::BufferDescriptor {
+                    label: Some("Debug Line Vertex Buffer"),
+                    size: size.next_power_of_two(),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                },
+            ));
+        }
+        let Some(vertex_buffer) = &self.vertex_buffer else {
+            return;
+        };
+        uploads.write(device, vertex_buffer, 0, bytes);
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Debug Line Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        let count = lines.len() as u32;
+        pass.set_bind_group(0, camera_bind_group, &[]);
+        pass.set_vertex_buffer(0, vertex_buffer.slice(..size));
+        for pipeline in [&self.hidden_pipeline, &self.visible_pipeline] {
+            pass.set_pipeline(pipeline);
+            pass.draw(0..count, 0..1);
+            stats.record_draw(0, 1); // Lines add no triangles
+        }
+    }
+}
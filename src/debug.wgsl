@@ -0,0 +1,38 @@
+// Debug lines: drawn once where they are in front of the scene, and again, faded, where it hides them
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// How much of a line's alpha is left where the scene hides it
+const HIDDEN_ALPHA: f32 = 0.3;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(in.position, 1.0);
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_visible(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+
+@fragment
+fn fs_hidden(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color * HIDDEN_ALPHA; // Premultiplied, so the color fades with the alpha
+}
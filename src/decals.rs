@@ -0,0 +1,516 @@
+// decals.rs
+
+use crate::assets::{AssetEvent, Assets};
+use crate::bounds::Aabb;
+use crate::camera::Camera;
+use crate::file_dialog::{FileDialogs, FileKind};
+use crate::jobs::FrameBudget;
+use crate::texture::{GpuTexture, TextureImage};
+use crate::stats::RenderStats;
+use crate::toasts::Toasts;
+use crate::transform::Transform;
+use crate::upload::Uploads;
+use crate::world::{self, GlobalTransform};
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::{Quat, Vec3};
+use hecs::{Entity, World};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Decals the shader's uniform has room for; past this they are left out
+pub const MAX_DECALS: usize = 64;
+
+// Side of the built-in scorch mark texture, in texels
+const SCORCH_SIZE: u32 = 128;
+
+// A texture projected down the entity's local -Y onto whatever scene geometry lies inside its unit box, which
+// the entity's transform places and scales
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Decal {
+    pub texture: Option<PathBuf>, // None shows the built-in scorch mark
+    pub color: Vec3,              // Linear tint the texture is multiplied by
+    pub opacity: f32,
+}
+
+impl Default for Decal {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            color: Vec3::ONE,
+            opacity: 1.0,
+        }
+    }
+}
+
+// The decal as laid out in the shader's `Decals` uniform
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct GpuDecal {
+    model: [[f32; 4]; 4],
+    inverse_model: [[f32; 4]; 4],
+    color: [f32; 4], // a: opacity
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct DecalsUniform {
+    view_proj: [[f32; 4]; 4],
+    inverse_view_proj: [[f32; 4]; 4],
+    decals: [GpuDecal; MAX_DECALS],
+}
+
+/// Where a ray from `origin` along `direction` meets `bounds`, or else the y = 0 ground plane, with the normal
+/// of the face it meets there.
+pub fn surface_hit(bounds: Option<&Aabb>, origin: Vec3, direction: Vec3) -> Option<(Vec3, Vec3)> {
+    if let Some((bounds, t)) = bounds.and_then(|b| b.ray_intersection(origin, direction).map(|t| (b, t))) {
+        let point = origin + direction * t;
+        // The face hit is along the axis where the point sits furthest out, relative to the box's size
+        let offset = (point - bounds.center()) / (bounds.size() * 0.5).max(Vec3::splat(1e-5));
+        let distance = offset.abs();
+        let axis = (0..3).fold(0, |furthest, axis| if distance[axis] > distance[furthest] { axis } else { furthest });
+        let normal = Vec3::AXES[axis] * offset[axis].signum();
+        return Some((point, normal));
+    }
+    let t = -origin.y / direction.y;
+    (direction.y != 0.0 && t > 0.0).then(|| (origin + direction * t, Vec3::Y))
+}
+
+// The list of decals in the world, and placing new ones by clicking on the scene
+pub struct Decals {
+    pub placing: bool,        // The next click in the viewport places a decal instead of selecting
+    pub size: f32,            // Of decals placed by clicking
+    choosing: Option<Entity>, // Decal waiting on the texture dialog
+}
+
+impl Decals {
+    pub fn new() -> Self {
+        Self {
+            placing: false,
+            size: 1.0,
+            choosing: None,
+        }
+    }
+
+    /// Spawns a decal at `point` on a surface facing along `normal`, projecting onto it.
+    pub fn place(&mut self, world: &mut World, point: Vec3, normal: Vec3) -> Entity {
+        let transform = Transform {
+            translation: point,
+            rotation: Quat::from_rotation_arc(Vec3::Y, normal.normalize_or(Vec3::Y)),
+            scale: Vec3::new(self.size, self.size * 0.5, self.size),
+        };
+        world.spawn((transform, GlobalTransform(transform.matrix()), Decal::default()))
+    }
+
+    pub fn is_choosing(&self) -> bool {
+        self.choosing.is_some()
+    }
+
+    /// Shows the image picked in the texture dialog on the decal whose Choose button opened it.
+    pub fn place_texture(&mut self, world: &mut World, path: PathBuf) {
+        if let Some(entity) = self.choosing.take() {
+            if let Ok(mut decal) = world.get::<&mut Decal>(entity) {
+                decal.texture = Some(path);
+            }
+        }
+    }
+
+    /// The Decals window: lists every decal in the world, adds new ones at `placement` or by clicking, and
+    /// edits their textures and boxes.
+    pub fn show(&mut self, ctx: &Context, world: &mut World, placement: Vec3, file_dialogs: &mut FileDialogs) {
+        egui::Window::new("Decals")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                let mut decals: Vec<Entity> = world.query::<(Entity, &Decal)>().iter().map(|(e, _)| e).collect();
+                decals.sort();
+                ui.horizontal(|ui| {
+                    if ui.button("Add decal").clicked() {
+                        self.place(world, placement, Vec3::Y);
+                    }
+                    ui.toggle_value(&mut self.placing, "Place by clicking");
+                });
+                ui.add(egui::Slider::new(&mut self.size, 0.1..=10.0).logarithmic(true).text("Size of new decals"));
+                if decals.len() > MAX_DECALS {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!("Decals past the first {MAX_DECALS} are not drawn"),
+                    );
+                }
+
+                let mut removed = None;
+                for (index, entity) in decals.into_iter().enumerate() {
+                    egui::CollapsingHeader::new(format!("Decal {}", index + 1)).id_source(entity).show(ui, |ui| {
+                        ui.push_id(entity, |ui| {
+                            if let Ok(mut decal) = world.get::<&mut Decal>(entity) {
+                                let name = decal.texture.as_deref().and_then(Path::file_name);
+                                let name =
+                                    name.map_or("Scorch mark".into(), |name| name.to_string_lossy().into_owned());
+                                ui.horizontal(|ui| {
+                                    ui.label(name);
+                                    if ui.button("Choose…").clicked() {
+                                        self.choosing = Some(entity);
+                                        file_dialogs.open(FileKind::Texture);
+                                    }
+                                    if decal.texture.is_some() && ui.button("Clear").clicked() {
+                                        decal.texture = None;
+                                    }
+                                });
+                                ui.horizontal(|ui| {
+                                    let mut color = decal.color.to_array();
+                                    if ui.color_edit_button_rgb(&mut color).changed() {
+                                        decal.color = Vec3::from(color);
+                                    }
+                                    ui.add(egui::Slider::new(&mut decal.opacity, 0.0..=1.0).text("Opacity"));
+                                });
+                            }
+                            let mut transform = world::transform_mut(world, entity);
+                            egui::Grid::new("decal_box").num_columns(2).show(ui, |ui| {
+                                ui.label("Position");
+                                ui.horizontal(|ui| {
+                                    for axis in 0..3 {
+                                        ui.add(egui::DragValue::new(&mut transform.translation[axis]).speed(0.01));
+                                    }
+                                });
+                                ui.end_row();
+                                // Width, projection depth and height of the box
+                                ui.label("Size");
+                                ui.horizontal(|ui| {
+                                    for axis in 0..3 {
+                                        let size = egui::DragValue::new(&mut transform.scale[axis]).speed(0.01);
+                                        ui.add(size.range(0.01..=100.0));
+                                    }
+                                });
+                                ui.end_row();
+                            });
+                            if ui.button("Remove").clicked() {
+                                removed = Some(entity);
+                            }
+                        });
+                    });
+                }
+                if let Some(entity) = removed {
+                    let _ = world.despawn(entity);
+                }
+            });
+    }
+}
+
+impl Default for Decals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A decal texture with the bind group the decal pass samples it through
+struct DecalTexture {
+    gpu: GpuTexture,
+    bind_group: wgpu::BindGroup,
+}
+
+// Draws every decal after the opaque scene: each one's box is drawn from the inside, and the shader rebuilds the
+// surface behind every pixel from the depth buffer to find where it lies in the box
+pub struct DecalRenderer {
+    pipeline: wgpu::RenderPipeline,
+    view_layout: wgpu::BindGroupLayout,
+    texture_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    view_bind_group: Option<(wgpu::BindGroup, (u32, u32))>, // Remade with the depth buffer, when the scene resizes
+    scorch: DecalTexture,
+    images: Assets<TextureImage>,
+    textures: HashMap<PathBuf, Option<DecalTexture>>, // None until the file has loaded
+}
+
+impl DecalRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Decal Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("decals.wgsl").into()),
+        });
+        let uniform_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Decal Uniform Buffer"),
+                size: size_of::<DecalsUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let texture = |binding, filterable| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Decal View Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(1, false),
+            ],
+        });
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Decal Texture Bind Group Layout"),
+            entries: &[
+                texture(0, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Decal Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Decal Pipeline Layout"),
+            bind_group_layouts: &[&view_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Decal Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            // Only the far side of each box, so it still covers the screen with the camera inside it
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Front),
+                ..Default::default()
+            },
+            // Tested against the scene by hand in the fragment shader, which reads the depth as a texture
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let scorch = GpuTexture::new(device, stats, uploads, &scorch_mark());
+        let scorch = DecalTexture {
+            bind_group: Self::texture_bind_group(device, &texture_layout, &sampler, &scorch),
+            gpu: scorch,
+        };
+        Self {
+            pipeline,
+            view_layout,
+            texture_layout,
+            uniform_buffer,
+            sampler,
+            view_bind_group: None,
+            scorch,
+            images: Assets::new(TextureImage::load),
+            textures: HashMap::new(),
+        }
+    }
+
+    fn texture_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        texture: &GpuTexture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Decal Texture Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Starts loading the textures of decals in `world` that no decal used before, and turns finished loads
+    /// into textures. Files are reloaded when edited on disk. Returns whether any texture changed.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        budget: &mut FrameBudget,
+        world: &World,
+        toasts: &mut Toasts,
+    ) -> bool {
+        let mut changed = false;
+        for decal in world.query::<&Decal>().iter() {
+            if let Some(path) = decal.texture.as_ref().filter(|path| !self.textures.contains_key(*path)) {
+                self.images.load(path, true);
+                self.textures.insert(path.clone(), None);
+            }
+        }
+        for event in self.images.poll(budget) {
+            match event {
+                AssetEvent::Loaded(handle) | AssetEvent::Reloaded(handle) => {
+                    let (Some(image), Some(path)) = (self.images.get(handle), self.images.path(handle)) else {
+                        continue;
+                    };
+                    let gpu = GpuTexture::new(device, stats, uploads, image);
+                    let texture = DecalTexture {
+                        bind_group: Self::texture_bind_group(device, &self.texture_layout, &self.sampler, &gpu),
+                        gpu,
+                    };
+                    let previous = self.textures.insert(path.to_path_buf(), Some(texture));
+                    if let Some(Some(previous)) = previous {
+                        stats.texture_destroyed(&previous.gpu.texture);
+                    }
+                    changed = true;
+                }
+                AssetEvent::Failed(handle, err) => {
+                    let path = self.images.path(handle).map(Path::to_path_buf).unwrap_or_default();
+                    log::warn!("Failed to load decal texture from {}: {err}", path.display());
+                    toasts.error(format!("Failed to load decal texture: {err}"));
+                }
+            }
+        }
+        changed
+    }
+
+    /// Projects the decals of `world` onto the scene in `target` of `size`, seen through `camera`, finding
+    /// surfaces from the scene's `depth`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        world: &World,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        let mut query = world.query::<(Entity, &Decal, &GlobalTransform)>();
+        let mut decals: Vec<(Entity, &Decal, &GlobalTransform)> = query.iter().collect();
+        if decals.is_empty() {
+            return;
+        }
+        decals.sort_by_key(|(entity, _, _)| *entity);
+        decals.truncate(MAX_DECALS);
+
+        let view_proj = camera.view_projection_matrix();
+        let mut uniform = DecalsUniform {
+            view_proj: view_proj.to_cols_array_2d(),
+            inverse_view_proj: view_proj.inverse().to_cols_array_2d(),
+            decals: [GpuDecal::zeroed(); MAX_DECALS],
+        };
+        for (slot, (_, decal, global)) in uniform.decals.iter_mut().zip(&decals) {
+            let model = global.0;
+            *slot = GpuDecal {
+                model: model.to_cols_array_2d(),
+                inverse_model: model.inverse().to_cols_array_2d(),
+                color: decal.color.extend(decal.opacity).to_array(),
+            };
+        }
+        uploads.write(device, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        if self.view_bind_group.as_ref().is_none_or(|(_, sized)| *sized != size) {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Decal View Bind Group"),
+                layout: &self.view_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(depth),
+                    },
+                ],
+            });
+            self.view_bind_group = Some((bind_group, size));
+        }
+        let Some((view_bind_group, _)) = &self.view_bind_group else {
+            return;
+        };
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Decal Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, view_bind_group, &[]);
+        // Decals are drawn in entity order, so later ones go over earlier ones
+        for (index, (_, decal, _)) in decals.iter().enumerate() {
+            // Still loading, or failed to: the scorch mark stands in
+            let texture = decal.texture.as_ref().and_then(|path| self.textures.get(path)?.as_ref());
+            pass.set_bind_group(1, &texture.unwrap_or(&self.scorch).bind_group, &[]);
+            let index = index as u32;
+            pass.draw(0..36, index..index + 1);
+            stats.record_draw(36, 1);
+        }
+    }
+}
+
+// A soft, ragged dark blotch, so decals show something before any texture is chosen
+fn scorch_mark() -> TextureImage {
+    let mut pixels = Vec::with_capacity((SCORCH_SIZE * SCORCH_SIZE * 4) as usize);
+    for y in 0..SCORCH_SIZE {
+        for x in 0..SCORCH_SIZE {
+            let offset = (glam::vec2(x as f32, y as f32) + 0.5) / SCORCH_SIZE as f32 * 2.0 - 1.0;
+            let angle = offset.y.atan2(offset.x);
+            let edge = 0.75 + 0.1 * (angle * 5.0).sin() + 0.06 * (angle * 11.0 + 1.3).sin();
+            let t = (offset.length() / edge).clamp(0.0, 1.0);
+            let alpha = (1.0 - t * t) * (1.0 - t);
+            let shade = 10.0 + 25.0 * t;
+            pixels.extend_from_slice(&[shade as u8, (shade * 0.8) as u8, (shade * 0.6) as u8, (alpha * 230.0) as u8]);
+        }
+    }
+    TextureImage::new(SCORCH_SIZE, SCORCH_SIZE, pixels)
+}
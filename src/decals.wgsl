@@ -0,0 +1,69 @@
+// Decals: each is a box drawn from the inside, and every pixel it covers finds the scene surface behind it from
+// the depth buffer. Surface points inside the box take the decal's texture, projected down the box's local -Y
+
+struct Decal {
+    model: mat4x4<f32>, // Unit box to world
+    inverse_model: mat4x4<f32>,
+    color: vec4<f32>, // Linear tint; a: opacity
+};
+struct Decals {
+    view_proj: mat4x4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+    decals: array<Decal, 64>,
+};
+@group(0) @binding(0)
+var<uniform> decals: Decals;
+// Bound as a plain float texture, as GL can't load texels from a depth texture
+@group(0) @binding(1)
+var scene_depth: texture_2d<f32>;
+
+@group(1) @binding(0)
+var decal_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var decal_sampler: sampler;
+
+// Corners of the unit box are bit patterns of x, y and z; each face is two triangles wound outwards
+const BOX_INDICES = array<u32, 36>(
+    4u, 6u, 2u, 4u, 2u, 0u, 1u, 3u, 7u, 1u, 7u, 5u,
+    0u, 1u, 5u, 0u, 5u, 4u, 6u, 7u, 3u, 6u, 3u, 2u,
+    2u, 3u, 1u, 2u, 1u, 0u, 4u, 5u, 7u, 4u, 7u, 6u,
+);
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) decal: u32,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, @builtin(instance_index) decal: u32) -> VertexOutput {
+    var indices = BOX_INDICES;
+    let corner = indices[index];
+    let position = vec3<f32>(f32(corner & 1u), f32((corner >> 1u) & 1u), f32((corner >> 2u) & 1u)) - 0.5;
+    var out: VertexOutput;
+    out.clip_position = decals.view_proj * decals.decals[decal].model * vec4<f32>(position, 1.0);
+    out.decal = decal;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let decal = decals.decals[in.decal];
+    let size = vec2<f32>(textureDimensions(scene_depth));
+    let depth = textureLoad(scene_depth, vec2<i32>(in.clip_position.xy), 0).r;
+    let ndc = vec2<f32>(in.clip_position.x / size.x * 2.0 - 1.0, 1.0 - in.clip_position.y / size.y * 2.0);
+    let world = decals.inverse_view_proj * vec4<f32>(ndc, depth, 1.0);
+    let position = world.xyz / world.w;
+    let local = (decal.inverse_model * vec4<f32>(position, 1.0)).xyz;
+    // Surfaces facing away from the projection fade out, so the texture doesn't smear down the sides
+    let normal = normalize(cross(dpdy(position), dpdx(position)));
+    let up = normalize(decal.model[1].xyz);
+    let facing = smoothstep(0.2, 0.5, abs(dot(normal, up)));
+
+    // Nothing was drawn here, or the surface lies outside the box
+    if depth >= 1.0 || any(abs(local) > vec3<f32>(0.5)) {
+        discard;
+    }
+    let uv = local.xz + 0.5; // The top of the texture towards the box's -Z
+    let texel = textureSampleLevel(decal_texture, decal_sampler, uv, 0.0);
+    return vec4<f32>(texel.rgb * decal.color.rgb, texel.a * decal.color.a * facing);
+}
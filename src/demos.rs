@@ -0,0 +1,412 @@
+// demos.rs
+
+use crate::application::{AppContext, Application};
+use crate::camera::Camera;
+use crate::hud::BlockType;
+use crate::mesh::Mesh;
+use crate::particles::ParticleEmitter;
+use crate::reflection_probes::ReflectionProbe;
+use crate::transform::Transform;
+use crate::vertex::Vertex;
+use crate::world::{GlobalTransform, Hidden, MaterialHandle};
+use glam::{EulerRot, Quat, Vec3};
+use hecs::Entity;
+
+// Columns along each side of the voxel world
+const VOXEL_EXTENT: i32 = 24;
+
+// Spheres along each side of the sphere grid; reflectivity rises along X and the tint changes along Y
+const SPHERE_GRID: usize = 5;
+
+// Corners of one face of a unit block, counter-clockwise seen from outside
+type Face = [[f32; 3]; 4];
+
+// A demo the gallery can switch to, made the first time it is picked
+pub struct DemoEntry {
+    pub name: &'static str, // What `--demo` takes
+    pub label: &'static str,
+    create: fn() -> Box<dyn Application>,
+}
+
+pub const DEMOS: [DemoEntry; 6] = [
+    DemoEntry {
+        name: "polygon",
+        label: "Polygons",
+        create: || Box::new(PolygonDemo::default()),
+    },
+    DemoEntry {
+        name: "cube",
+        label: "Spinning Cube",
+        create: || Box::new(CubeDemo::default()),
+    },
+    DemoEntry {
+        name: "terrain",
+        label: "Terrain",
+        create: || Box::new(TerrainDemo::default()),
+    },
+    DemoEntry {
+        name: "voxels",
+        label: "Voxel World",
+        create: || Box::new(VoxelDemo::default()),
+    },
+    DemoEntry {
+        name: "particles",
+        label: "Particles",
+        create: || Box::new(ParticlesDemo::default()),
+    },
+    DemoEntry {
+        name: "spheres",
+        label: "Material Sphere Grid",
+        create: || Box::new(SphereGridDemo::default()),
+    },
+];
+
+// Runs one of `DEMOS` at a time in place of the template's object, picked from the Demos menu or with
+// `--demo <name>`. Each demo is an `Application` of its own; the gallery sets it up when it is picked and
+// tears it down when another one is
+pub struct DemoGallery {
+    demos: Vec<Option<Box<dyn Application>>>, // Kept once made, so what they created can be reused
+    selected: Option<usize>,                  // Picked in the menu
+    active: Option<usize>,                    // Set up in the scene
+}
+
+impl DemoGallery {
+    pub fn new() -> Self {
+        Self {
+            demos: DEMOS.iter().map(|_| None).collect(),
+            selected: None,
+            active: None,
+        }
+    }
+
+    /// Starts with the demo named after `--demo`, if any.
+    pub fn from_args(args: &[String]) -> Self {
+        let mut gallery = Self::new();
+        let Some(name) = args.iter().skip_while(|arg| *arg != "--demo").nth(1) else {
+            return gallery;
+        };
+        gallery.selected = DEMOS.iter().position(|demo| demo.name == name);
+        if gallery.selected.is_none() {
+            let names: Vec<&str> = DEMOS.iter().map(|demo| demo.name).collect();
+            log::warn!("No demo called {name:?}; the demos are {}", names.join(", "));
+        }
+        gallery
+    }
+
+    // Tears down the running demo and sets up the selected one, if they differ
+    fn switch(&mut self, context: &mut AppContext) {
+        if self.selected == self.active {
+            return;
+        }
+        if let Some(demo) = self.active.take().and_then(|index| self.demos[index].as_mut()) {
+            demo.teardown(context);
+        }
+        if let Some(index) = self.selected {
+            let demo = self.demos[index].get_or_insert_with(DEMOS[index].create);
+            demo.setup(context);
+        }
+        if self.selected.is_some() {
+            let _ = context.world.insert_one(context.object, Hidden);
+        } else {
+            let _ = context.world.remove_one::<Hidden>(context.object);
+        }
+        self.active = self.selected;
+        context.request_redraw();
+    }
+
+    fn active_demo(&mut self) -> Option<&mut Box<dyn Application>> {
+        self.active.and_then(|index| self.demos[index].as_mut())
+    }
+}
+
+impl Default for DemoGallery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Application for DemoGallery {
+    fn setup(&mut self, context: &mut AppContext) {
+        self.switch(context);
+    }
+
+    fn update(&mut self, context: &mut AppContext, dt: f32) {
+        self.switch(context);
+        if let Some(demo) = self.active_demo() {
+            demo.update(context, dt);
+        }
+    }
+
+    fn ui(&mut self, ctx: &egui::Context, context: &mut AppContext) {
+        if let Some(demo) = self.active_demo() {
+            demo.ui(ctx, context);
+        }
+    }
+
+    fn menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Demos", |ui| {
+            if ui.selectable_label(self.selected.is_none(), "None").clicked() {
+                self.selected = None;
+                ui.close_menu();
+            }
+            ui.separator();
+            for (index, demo) in DEMOS.iter().enumerate() {
+                if ui.selectable_label(self.selected == Some(index), demo.label).clicked() {
+                    self.selected = Some(index);
+                    ui.close_menu();
+                }
+            }
+        });
+        if let Some(demo) = self.active_demo() {
+            demo.menu(ui);
+        }
+    }
+
+    fn teardown(&mut self, context: &mut AppContext) {
+        self.selected = None;
+        self.switch(context);
+    }
+}
+
+fn look(camera: &mut Camera, position: Vec3, target: Vec3) {
+    camera.position = position;
+    camera.target = target;
+}
+
+fn despawn_all(context: &mut AppContext, entities: &mut Vec<Entity>) {
+    for entity in entities.drain(..) {
+        context.despawn(entity);
+    }
+}
+
+fn at(translation: Vec3) -> Transform {
+    Transform {
+        translation,
+        ..Transform::IDENTITY
+    }
+}
+
+// Regular polygons from a triangle to an octagon, side by side
+#[derive(Default)]
+struct PolygonDemo {
+    entities: Vec<Entity>,
+}
+
+impl Application for PolygonDemo {
+    fn setup(&mut self, context: &mut AppContext) {
+        for sides in 3..=8u16 {
+            let x = (sides as f32 - 5.5) * 1.2;
+            let entity = context.spawn_mesh(&Mesh::polygon(sides, 0.5), at(Vec3::new(x, 0.0, 0.0)));
+            self.entities.push(entity);
+        }
+        look(context.camera, Vec3::new(0.0, 0.0, 6.0), Vec3::ZERO);
+    }
+
+    fn teardown(&mut self, context: &mut AppContext) {
+        despawn_all(context, &mut self.entities);
+    }
+}
+
+// A cube turning about two axes, redrawn every frame
+#[derive(Default)]
+struct CubeDemo {
+    cube: Option<Entity>,
+    time: f32,
+}
+
+impl Application for CubeDemo {
+    fn setup(&mut self, context: &mut AppContext) {
+        self.cube = Some(context.spawn_mesh(&Mesh::cube(), Transform::IDENTITY));
+        look(context.camera, Vec3::new(0.0, 0.5, 3.0), Vec3::ZERO);
+    }
+
+    fn update(&mut self, context: &mut AppContext, dt: f32) {
+        self.time += dt;
+        let Some(mut transform) = self.cube.and_then(|cube| context.world.get::<&mut Transform>(cube).ok()) else {
+            return;
+        };
+        transform.rotation = Quat::from_euler(EulerRot::YXZ, self.time * 0.8, self.time * 0.4, 0.0);
+        drop(transform);
+        context.request_redraw();
+    }
+
+    fn teardown(&mut self, context: &mut AppContext) {
+        if let Some(cube) = self.cube.take() {
+            context.despawn(cube);
+        }
+    }
+}
+
+// The heightmap terrain, seen from above its hills
+#[derive(Default)]
+struct TerrainDemo {
+    was_enabled: bool, // Restored on teardown
+}
+
+impl Application for TerrainDemo {
+    fn setup(&mut self, context: &mut AppContext) {
+        self.was_enabled = context.terrain.enabled;
+        context.terrain.enabled = true;
+        look(context.camera, Vec3::new(0.0, 18.0, 40.0), Vec3::new(0.0, 2.0, 0.0));
+    }
+
+    fn teardown(&mut self, context: &mut AppContext) {
+        context.terrain.enabled = self.was_enabled;
+    }
+}
+
+// Rolling hills of unit blocks, meshed as a whole with the faces between neighboring blocks left out
+#[derive(Default)]
+struct VoxelDemo {
+    mesh: Option<Mesh>, // Built the first time the demo runs
+    entity: Option<Entity>,
+}
+
+impl Application for VoxelDemo {
+    fn setup(&mut self, context: &mut AppContext) {
+        let mesh = self.mesh.get_or_insert_with(voxel_mesh);
+        self.entity = Some(context.spawn_mesh(mesh, Transform::IDENTITY));
+        look(context.camera, Vec3::new(16.0, 18.0, 26.0), Vec3::new(0.0, 2.0, 0.0));
+    }
+
+    fn teardown(&mut self, context: &mut AppContext) {
+        if let Some(entity) = self.entity.take() {
+            context.despawn(entity);
+        }
+    }
+}
+
+// Blocks stacked in each column of the voxel world
+fn column_height(x: i32, z: i32) -> i32 {
+    if !(0..VOXEL_EXTENT).contains(&x) || !(0..VOXEL_EXTENT).contains(&z) {
+        return 0;
+    }
+    let (x, z) = (x as f32, z as f32);
+    let height = 3.5 + 2.0 * (x * 0.35).sin() * (z * 0.3).cos() + 1.5 * ((x + z) * 0.15).sin();
+    (height.round() as i32).clamp(1, 8)
+}
+
+fn voxel_mesh() -> Mesh {
+    let mut mesh = Mesh::default();
+    let offset = Vec3::new(VOXEL_EXTENT as f32 / 2.0, 0.0, VOXEL_EXTENT as f32 / 2.0);
+    // The side faces, each with the neighbor that would hide it
+    let sides: [((i32, i32), Face); 4] = [
+        ((1, 0), [[1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [1.0, 1.0, 1.0]]),
+        ((-1, 0), [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 1.0], [0.0, 1.0, 0.0]]),
+        ((0, 1), [[0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [1.0, 1.0, 1.0], [0.0, 1.0, 1.0]]),
+        ((0, -1), [[1.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]]),
+    ];
+    let top: Face = [[0.0, 1.0, 1.0], [1.0, 1.0, 1.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+    for x in 0..VOXEL_EXTENT {
+        for z in 0..VOXEL_EXTENT {
+            let height = column_height(x, z);
+            for y in 0..height {
+                let color = match height - y {
+                    1 => BlockType::Grass,
+                    2 | 3 => BlockType::Dirt,
+                    _ => BlockType::Stone,
+                }
+                .color();
+                let block = Vec3::new(x as f32, y as f32, z as f32) - offset;
+                if y == height - 1 {
+                    push_face(&mut mesh, block, top, color);
+                }
+                for ((dx, dz), corners) in sides {
+                    if column_height(x + dx, z + dz) <= y {
+                        push_face(&mut mesh, block, corners, color);
+                    }
+                }
+            }
+        }
+    }
+    mesh
+}
+
+fn push_face(mesh: &mut Mesh, block: Vec3, corners: Face, color: [f32; 3]) {
+    let first = mesh.vertices.len() as u16;
+    for corner in corners {
+        mesh.vertices.push(Vertex::new((block + Vec3::from(corner)).to_array(), color));
+    }
+    mesh.indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+}
+
+// Emitters set up as a fire, a fountain and a column of smoke
+#[derive(Default)]
+struct ParticlesDemo {
+    entities: Vec<Entity>,
+}
+
+impl Application for ParticlesDemo {
+    fn setup(&mut self, context: &mut AppContext) {
+        let mut fountain = ParticleEmitter::new();
+        fountain.rate = 600.0;
+        fountain.speed = 3.0;
+        fountain.spread = 8.0;
+        fountain.gravity = 3.0;
+        fountain.start_color = [0.5, 0.8, 1.0, 1.0];
+        fountain.end_color = [0.2, 0.4, 1.0, 0.0];
+        let mut smoke = ParticleEmitter::new();
+        smoke.rate = 60.0;
+        smoke.lifetime = 5.0;
+        smoke.speed = 0.4;
+        smoke.spread = 15.0;
+        smoke.size = 0.25;
+        smoke.gravity = -0.1;
+        smoke.start_color = [0.6, 0.6, 0.6, 0.6];
+        smoke.end_color = [0.3, 0.3, 0.3, 0.0];
+        for (x, emitter) in [(-2.0, ParticleEmitter::new()), (0.0, fountain), (2.0, smoke)] {
+            let transform = at(Vec3::new(x, -0.5, 0.0));
+            self.entities.push(context.world.spawn((transform, GlobalTransform(transform.matrix()), emitter)));
+        }
+        look(context.camera, Vec3::new(0.0, 1.0, 7.0), Vec3::new(0.0, 0.5, 0.0));
+    }
+
+    fn teardown(&mut self, context: &mut AppContext) {
+        despawn_all(context, &mut self.entities);
+    }
+}
+
+// Spheres in a grid, growing more reflective from left to right with a tint per row, around a reflection
+// probe that recaptures as they change. The scene shader has no metallic or roughness terms, so the grid
+// sweeps what it does have
+#[derive(Default)]
+struct SphereGridDemo {
+    materials: Vec<MaterialHandle>, // Made once; materials cannot be removed
+    entities: Vec<Entity>,
+}
+
+impl Application for SphereGridDemo {
+    fn setup(&mut self, context: &mut AppContext) {
+        let tints = [[0.9, 0.2, 0.2], [0.9, 0.7, 0.3], [0.3, 0.8, 0.3], [0.3, 0.5, 0.9], [0.9, 0.9, 0.9]];
+        if self.materials.is_empty() {
+            for (row, tint) in tints.iter().enumerate() {
+                for column in 0..SPHERE_GRID {
+                    let material = context.create_material(&format!("Sphere {} {}", row + 1, column + 1));
+                    context.set_material_param(material, "tint", tint);
+                    let reflectivity = column as f32 / (SPHERE_GRID - 1) as f32;
+                    context.set_material_param(material, "reflectivity", &[reflectivity]);
+                    self.materials.push(material);
+                }
+            }
+        }
+        let sphere = Mesh::sphere(32, 16, 0.45);
+        let half = (SPHERE_GRID - 1) as f32 / 2.0;
+        for (index, &material) in self.materials.iter().enumerate() {
+            let (row, column) = (index / SPHERE_GRID, index % SPHERE_GRID);
+            let position = Vec3::new(column as f32 - half, half - row as f32, 0.0) * 1.2;
+            let entity = context.spawn_mesh_with_material(&sphere, at(position), material);
+            self.entities.push(entity);
+        }
+        let probe = ReflectionProbe {
+            auto_update: true,
+            ..ReflectionProbe::default()
+        };
+        let transform = at(Vec3::new(0.0, 0.0, 1.0));
+        self.entities.push(context.world.spawn((transform, GlobalTransform(transform.matrix()), probe)));
+        look(context.camera, Vec3::new(0.0, 0.0, 8.0), Vec3::ZERO);
+    }
+
+    fn teardown(&mut self, context: &mut AppContext) {
+        despawn_all(context, &mut self.entities);
+    }
+}
@@ -0,0 +1,122 @@
+// depth.rs
+
+use crate::stats::RenderStats;
+use egui_wgpu::wgpu;
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// How a pipeline uses the depth buffer. Pipelines opt in with one of the presets, adjusted with the `with_`
+// methods, and build their `wgpu::DepthStencilState` from it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepthConfig {
+    pub write: bool,
+    pub compare: wgpu::CompareFunction,
+    pub stencil: wgpu::StencilState,
+}
+
+impl DepthConfig {
+    /// Tested and written, for solid surfaces: every scene pipeline and bundle.
+    pub const OPAQUE: DepthConfig = DepthConfig::new(true, wgpu::CompareFunction::Less);
+
+    /// Tested but not written, for surfaces that must not hide what is drawn after them, such as the grid and the
+    /// sky. Equal depth passes, so they can be drawn at the far plane.
+    pub const READ_ONLY: DepthConfig = DepthConfig::new(false, wgpu::CompareFunction::LessEqual);
+
+    /// Neither tested nor written, for overlays drawn over everything in a pass that has a depth attachment.
+    pub const OVERLAY: DepthConfig = DepthConfig::new(false, wgpu::CompareFunction::Always);
+
+    const fn new(write: bool, compare: wgpu::CompareFunction) -> Self {
+        Self {
+            write,
+            compare,
+            stencil: wgpu::StencilState {
+                front: wgpu::StencilFaceState::IGNORE,
+                back: wgpu::StencilFaceState::IGNORE,
+                read_mask: 0,
+                write_mask: 0,
+            },
+        }
+    }
+
+    pub fn with_compare(self, compare: wgpu::CompareFunction) -> Self {
+        Self { compare, ..self }
+    }
+
+    pub fn with_write(self, write: bool) -> Self {
+        Self { write, ..self }
+    }
+
+    /// The stencil test: its compare function and pass/fail/depth-fail operations per face, and the read and write
+    /// masks. Needs a format with a stencil aspect, so use it with `state_for`; `DEPTH_FORMAT` has none.
+    pub fn with_stencil(self, stencil: wgpu::StencilState) -> Self {
+        Self { stencil, ..self }
+    }
+
+    /// The state for a pipeline rendering into a `format` depth attachment.
+    pub fn state_for(&self, format: wgpu::TextureFormat) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format,
+            depth_write_enabled: self.write,
+            depth_compare: self.compare,
+            stencil: self.stencil.clone(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+
+    /// The state for a pipeline rendering into a `DepthBuffer`.
+    pub fn state(&self) -> wgpu::DepthStencilState {
+        self.state_for(DEPTH_FORMAT)
+    }
+}
+
+// The scene's depth, at the scene's resolution. Written by the scene pass and the skinned pass after it, then
+// read as a texture by passes that fade against the scene, such as particles
+pub struct DepthBuffer {
+    target: Option<(wgpu::Texture, wgpu::TextureView)>,
+}
+
+impl DepthBuffer {
+    pub fn new() -> Self {
+        Self { target: None }
+    }
+
+    /// Recreates the buffer if the scene changed size.
+    pub fn resize(&mut self, device: &wgpu::Device, stats: &mut RenderStats, (width, height): (u32, u32)) {
+        if let Some((texture, _)) = &self.target {
+            if (texture.width(), texture.height()) == (width, height) {
+                return;
+            }
+            stats.texture_destroyed(texture);
+        }
+        let texture = stats.create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Scene Depth Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.target = Some((texture, view));
+    }
+
+    /// Panics before the first `resize`.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.target.as_ref().expect("Depth buffer used before it was sized").1
+    }
+}
+
+impl Default for DepthBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
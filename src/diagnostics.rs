@@ -0,0 +1,159 @@
+// diagnostics.rs
+
+use crate::capabilities::{Capabilities, Fallback};
+use egui::Context;
+use egui_wgpu::wgpu;
+
+// GPU and surface details captured at startup, for the About/Diagnostics panel and bug reports
+#[derive(Debug)]
+pub struct Diagnostics {
+    pub open: bool,
+    adapter: wgpu::AdapterInfo,
+    adapter_features: wgpu::Features,
+    device_features: wgpu::Features,
+    limits: wgpu::Limits,
+    surface: wgpu::SurfaceCapabilities,
+    fallbacks: Vec<Fallback>, // Rendering paths degraded for missing features or limits
+    adapters: Vec<wgpu::AdapterInfo>, // Every adapter on the machine, on every backend
+    switch_to: Option<wgpu::AdapterInfo>, // Picked to restart the renderer on, until the render loop takes it
+}
+
+impl Diagnostics {
+    pub fn new(
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        surface: &wgpu::Surface,
+        capabilities: &Capabilities,
+        adapters: Vec<wgpu::AdapterInfo>,
+    ) -> Self {
+        Self {
+            open: false,
+            adapter: adapter.get_info(),
+            adapter_features: adapter.features(),
+            device_features: device.features(),
+            limits: device.limits(),
+            surface: surface.get_capabilities(adapter),
+            fallbacks: capabilities.fallbacks.clone(),
+            adapters,
+            switch_to: None,
+        }
+    }
+
+    /// The adapter picked to restart the renderer on, once.
+    pub fn take_switch(&mut self) -> Option<wgpu::AdapterInfo> {
+        self.switch_to.take()
+    }
+
+    // Plain-text version of the panel, meant to be pasted into an issue
+    pub fn report(&self) -> String {
+        let info = &self.adapter;
+        let lines = [
+            format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            format!("OS: {} ({})", std::env::consts::OS, std::env::consts::ARCH),
+            format!("Adapter: {} ({:?})", info.name, info.device_type),
+            format!("Backend: {:?}", info.backend),
+            format!("Driver: {} {}", info.driver, info.driver_info),
+            format!("Vendor/device: {:#06x}/{:#06x}", info.vendor, info.device),
+            format!("Enabled features: {}", feature_names(self.device_features)),
+            format!("Adapter features: {}", feature_names(self.adapter_features)),
+            format!("Fallbacks: {}", self.fallback_names()),
+            format!("Surface formats: {:?}", self.surface.formats),
+            format!("Present modes: {:?}", self.surface.present_modes),
+            format!("Alpha modes: {:?}", self.surface.alpha_modes),
+            format!("Adapters: {}", self.adapter_names()),
+            format!("Limits: {:#?}", self.limits),
+        ];
+        lines.join("\n")
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        let mut open = self.open;
+        egui::Window::new("About / Diagnostics")
+            .open(&mut open)
+            .default_size([420.0, 360.0])
+            .show(ctx, |ui| {
+                let info = &self.adapter;
+                ui.label(format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+                if ui.button("Copy report").clicked() {
+                    ui.output_mut(|o| o.copied_text = self.report());
+                }
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("diagnostics_grid").num_columns(2).striped(true).show(ui, |ui| {
+                        for (name, value) in [
+                            ("Adapter", info.name.clone()),
+                            ("Device type", format!("{:?}", info.device_type)),
+                            ("Backend", format!("{:?}", info.backend)),
+                            ("Driver", info.driver.clone()),
+                            ("Driver info", info.driver_info.clone()),
+                            ("Vendor / device", format!("{:#06x} / {:#06x}", info.vendor, info.device)),
+                            ("OS", format!("{} ({})", std::env::consts::OS, std::env::consts::ARCH)),
+                        ] {
+                            ui.label(name);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+
+                    egui::CollapsingHeader::new("Features").show(ui, |ui| {
+                        ui.label(format!("Enabled: {}", feature_names(self.device_features)));
+                        ui.label(format!("Supported: {}", feature_names(self.adapter_features)));
+                    });
+                    egui::CollapsingHeader::new(format!("Fallbacks ({})", self.fallbacks.len())).show(ui, |ui| {
+                        if self.fallbacks.is_empty() {
+                            ui.label("All optional features are available");
+                        }
+                        for fallback in &self.fallbacks {
+                            ui.label(format!("{}: using {}", fallback.missing, fallback.using));
+                        }
+                    });
+                    egui::CollapsingHeader::new(format!("Adapters ({})", self.adapters.len())).show(ui, |ui| {
+                        if self.adapters.is_empty() {
+                            ui.label("The browser picks the adapter");
+                        }
+                        for adapter in &self.adapters {
+                            ui.horizontal(|ui| {
+                                let current = adapter.name == info.name && adapter.backend == info.backend;
+                                let restart = ui.add_enabled(!current, egui::Button::new("Restart on"));
+                                if restart.on_disabled_hover_text("Drawing with it now").clicked() {
+                                    self.switch_to = Some(adapter.clone());
+                                }
+                                let kind = format!("{:?}, {:?}", adapter.backend, adapter.device_type);
+                                ui.label(format!("{} ({kind})", adapter.name));
+                            });
+                        }
+                    });
+                    egui::CollapsingHeader::new("Limits").show(ui, |ui| {
+                        ui.monospace(format!("{:#?}", self.limits));
+                    });
+                    egui::CollapsingHeader::new("Surface").show(ui, |ui| {
+                        ui.label(format!("Formats: {:?}", self.surface.formats));
+                        ui.label(format!("Present modes: {:?}", self.surface.present_modes));
+                        ui.label(format!("Alpha modes: {:?}", self.surface.alpha_modes));
+                    });
+                });
+            });
+        self.open = open;
+    }
+
+    fn adapter_names(&self) -> String {
+        let names: Vec<_> = self.adapters.iter().map(|a| format!("{} ({:?})", a.name, a.backend)).collect();
+        names.join(", ")
+    }
+
+    fn fallback_names(&self) -> String {
+        if self.fallbacks.is_empty() {
+            return "none".to_string();
+        }
+        let names: Vec<_> = self.fallbacks.iter().map(|f| format!("{} -> {}", f.missing, f.using)).collect();
+        names.join(", ")
+    }
+}
+
+fn feature_names(features: wgpu::Features) -> String {
+    if features.is_empty() {
+        return "none".to_string();
+    }
+    features.iter_names().map(|(name, _)| name).collect::<Vec<_>>().join(", ")
+}
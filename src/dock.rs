@@ -0,0 +1,264 @@
+// dock.rs
+
+use egui::{Context, Id};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Width a side opens at before it's dragged
+const DEFAULT_WIDTH: f32 = 280.0;
+
+/// A panel the dock can show on either side of the window or floating. Projects built on the template add their
+/// own with `UIState::register_panel` or `Application::panels`.
+pub trait Panel {
+    /// Shown on the panel's tab, and what its placement is saved under, so keep it unique.
+    fn title(&self) -> &str;
+
+    /// Draws the panel's contents, inside a scroll area.
+    fn ui(&mut self, ui: &mut egui::Ui);
+}
+
+// Where a panel is shown
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DockSide {
+    Left,
+    Right,
+    Floating,
+}
+
+impl DockSide {
+    pub const ALL: [DockSide; 3] = [DockSide::Left, DockSide::Right, DockSide::Floating];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DockSide::Left => "Dock left",
+            DockSide::Right => "Dock right",
+            DockSide::Floating => "Float",
+        }
+    }
+}
+
+// The template's own panels, which `UIState` draws since they edit its state
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BuiltinPanel {
+    Scene,
+    Shader,
+    Camera,
+    Stats,
+}
+
+impl BuiltinPanel {
+    pub const ALL: [BuiltinPanel; 4] = [
+        BuiltinPanel::Scene,
+        BuiltinPanel::Shader,
+        BuiltinPanel::Camera,
+        BuiltinPanel::Stats,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            BuiltinPanel::Scene => "Scene",
+            BuiltinPanel::Shader => "Shader",
+            BuiltinPanel::Camera => "Camera",
+            BuiltinPanel::Stats => "Stats",
+        }
+    }
+
+    fn default_side(&self) -> DockSide {
+        match self {
+            BuiltinPanel::Scene | BuiltinPanel::Shader => DockSide::Left,
+            BuiltinPanel::Camera | BuiltinPanel::Stats => DockSide::Right,
+        }
+    }
+}
+
+// Kept in egui's memory rather than the settings, so it's saved with the window layout and in layout presets
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Layout {
+    sides: HashMap<String, DockSide>, // By title; panels not in here are where they start
+    left_tab: Option<String>,
+    right_tab: Option<String>,
+}
+
+impl Layout {
+    fn id() -> Id {
+        Id::new("dock_layout")
+    }
+
+    fn load(ctx: &Context) -> Self {
+        ctx.data_mut(|data| data.get_persisted(Self::id())).unwrap_or_default()
+    }
+
+    fn store(self, ctx: &Context) {
+        ctx.data_mut(|data| data.insert_persisted(Self::id(), self));
+    }
+
+    fn side(&self, tab: Tab, title: &str) -> DockSide {
+        self.sides.get(title).copied().unwrap_or(match tab {
+            Tab::Builtin(panel) => panel.default_side(),
+            Tab::Custom(_) => DockSide::Floating,
+        })
+    }
+
+    fn active_tab(&self, side: DockSide) -> Option<&str> {
+        match side {
+            DockSide::Left => self.left_tab.as_deref(),
+            DockSide::Right => self.right_tab.as_deref(),
+            DockSide::Floating => None,
+        }
+    }
+
+    // Moves the panel to `side`, as the tab showing there
+    fn place(&mut self, title: &str, side: DockSide) {
+        self.sides.insert(title.to_string(), side);
+        match side {
+            DockSide::Left => self.left_tab = Some(title.to_string()),
+            DockSide::Right => self.right_tab = Some(title.to_string()),
+            DockSide::Floating => {}
+        }
+    }
+}
+
+// A built-in panel, or a registered one by index
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Tab {
+    Builtin(BuiltinPanel),
+    Custom(usize),
+}
+
+// Side panels of tabs either side of the viewport, with any panel moved out into its own window
+pub struct Dock {
+    panels: Vec<Box<dyn Panel>>,
+}
+
+impl Dock {
+    pub fn new() -> Self {
+        Self { panels: Vec::new() }
+    }
+
+    pub fn register(&mut self, panel: Box<dyn Panel>) {
+        if self.panels.iter().any(|p| p.title() == panel.title()) {
+            log::warn!("A panel titled \"{}\" is already registered; both share a placement", panel.title());
+        }
+        self.panels.push(panel);
+    }
+
+    /// Shows every panel where the layout puts it. The built-in ones are drawn by `builtin`. Call it after the
+    /// menu bar, so the sides start below it.
+    pub fn show(&mut self, ctx: &Context, mut builtin: impl FnMut(BuiltinPanel, &mut egui::Ui)) {
+        let mut layout = Layout::load(ctx);
+        let tabs = self.tabs();
+
+        for side in [DockSide::Left, DockSide::Right] {
+            let docked: Vec<Tab> = tabs
+                .iter()
+                .copied()
+                .filter(|&tab| layout.side(tab, self.title(tab)) == side)
+                .collect();
+            let Some(&first) = docked.first() else {
+                continue;
+            };
+            // The tab last picked on this side, or the first if it's been moved away
+            let active = docked
+                .iter()
+                .copied()
+                .find(|&tab| layout.active_tab(side) == Some(self.title(tab)))
+                .unwrap_or(first);
+            let panel = match side {
+                DockSide::Left => egui::SidePanel::left("dock_left"),
+                _ => egui::SidePanel::right("dock_right"),
+            };
+            panel.default_width(DEFAULT_WIDTH).resizable(true).show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for &tab in &docked {
+                        let title = self.title(tab).to_string();
+                        let response = ui.selectable_label(tab == active, &title);
+                        if response.clicked() {
+                            layout.place(&title, side);
+                        }
+                        response.context_menu(|ui| placement_menu(ui, &mut layout, tab, &title));
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .id_source(("dock_scroll", side))
+                    .show(ui, |ui| self.tab_ui(active, ui, &mut builtin));
+            });
+        }
+
+        for tab in tabs {
+            let title = self.title(tab).to_string();
+            if layout.side(tab, &title) != DockSide::Floating {
+                continue;
+            }
+            egui::Window::new(&title)
+                .id(Id::new(("dock_window", &title)))
+                .default_width(DEFAULT_WIDTH)
+                .vscroll(true)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        for side in [DockSide::Left, DockSide::Right] {
+                            if ui.small_button(side.label()).clicked() {
+                                layout.place(&title, side);
+                            }
+                        }
+                    });
+                    ui.separator();
+                    self.tab_ui(tab, ui, &mut builtin);
+                });
+        }
+
+        layout.store(ctx);
+    }
+
+    /// Lists every panel with where it's shown, for the View menu.
+    pub fn menu_ui(&self, ui: &mut egui::Ui) {
+        let mut layout = Layout::load(ui.ctx());
+        for tab in self.tabs() {
+            let title = self.title(tab).to_string();
+            ui.menu_button(&title, |ui| placement_menu(ui, &mut layout, tab, &title));
+        }
+        ui.separator();
+        if ui.button("Reset Panel Layout").clicked() {
+            layout = Layout::default();
+            ui.close_menu();
+        }
+        layout.store(ui.ctx());
+    }
+
+    fn tabs(&self) -> Vec<Tab> {
+        let builtins = BuiltinPanel::ALL.into_iter().map(Tab::Builtin);
+        builtins.chain((0..self.panels.len()).map(Tab::Custom)).collect()
+    }
+
+    fn title(&self, tab: Tab) -> &str {
+        match tab {
+            Tab::Builtin(panel) => panel.title(),
+            Tab::Custom(index) => self.panels[index].title(),
+        }
+    }
+
+    fn tab_ui(&mut self, tab: Tab, ui: &mut egui::Ui, builtin: &mut impl FnMut(BuiltinPanel, &mut egui::Ui)) {
+        match tab {
+            Tab::Builtin(panel) => builtin(panel, ui),
+            Tab::Custom(index) => self.panels[index].ui(ui),
+        }
+    }
+}
+
+impl Default for Dock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Where the panel is shown, with the other places to move it to
+fn placement_menu(ui: &mut egui::Ui, layout: &mut Layout, tab: Tab, title: &str) {
+    let current = layout.side(tab, title);
+    for side in DockSide::ALL {
+        if ui.radio(current == side, side.label()).clicked() {
+            layout.place(title, side);
+            ui.close_menu();
+        }
+    }
+}
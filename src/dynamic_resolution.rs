@@ -0,0 +1,326 @@
+// dynamic_resolution.rs
+
+use crate::stats::RenderStats;
+use egui_wgpu::wgpu;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+pub const DEFAULT_TARGET_FPS: u32 = 60;
+pub const TARGET_FPS_RANGE: RangeInclusive<u32> = 20..=240;
+// Above 1 the scene is supersampled, downscaled to the surface by the same pass that otherwise upscales it
+pub const RENDER_SCALE_RANGE: RangeInclusive<f32> = 0.25..=2.0;
+// The scale moves in steps of 5% per axis down to half resolution, so small fluctuations in frame time don't
+// recreate the target every frame
+const SCALE_STEP: f32 = 0.05;
+const MAX_STEPS: u32 = 10;
+// Frames to wait after a change before judging its effect, since GPU timings arrive a few frames late
+const SETTLE_FRAMES: u32 = 20;
+// Weight of the newest sample in the smoothed frame time
+const SMOOTHING: f32 = 0.1;
+// Fractions of the frame budget above which the scale drops, and below which it rises again
+const OVER_BUDGET: f32 = 1.05;
+const UNDER_BUDGET: f32 = 0.8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpscaleFilter {
+    Bilinear,
+    Sharpened, // Bilinear plus contrast-adaptive sharpening, like FSR's RCAS pass
+}
+
+impl UpscaleFilter {
+    pub const ALL: [UpscaleFilter; 2] = [UpscaleFilter::Bilinear, UpscaleFilter::Sharpened];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            UpscaleFilter::Bilinear => "Bilinear",
+            UpscaleFilter::Sharpened => "Sharpened",
+        }
+    }
+}
+
+// Picks the resolution the scene is rendered at from recent frame times: lower while frames take longer than
+// the target frame rate allows, higher again once there is headroom. The UI always stays at full resolution
+pub struct ResolutionController {
+    pub enabled: bool,
+    pub target_fps: u32,
+    pub filter: UpscaleFilter,
+    pub render_scale: f32, // Fixed part of the scale, which dynamic resolution lowers further
+    steps: u32,         // Steps below full resolution
+    frame_ms: f32,      // Smoothed
+    settle_frames: u32, // Left before the scale may change again
+}
+
+impl ResolutionController {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            target_fps: DEFAULT_TARGET_FPS,
+            filter: UpscaleFilter::Sharpened,
+            render_scale: 1.0,
+            steps: 0,
+            frame_ms: 0.0,
+            settle_frames: 0,
+        }
+    }
+
+    /// Fraction of the surface's width and height the scene is rendered at.
+    pub fn scale(&self) -> f32 {
+        if self.enabled {
+            self.render_scale * (1.0 - self.steps as f32 * SCALE_STEP)
+        } else {
+            self.render_scale
+        }
+    }
+
+    /// `size` at `scale`, kept within `max_dimension` on either side without changing its aspect.
+    pub fn scaled_size(&self, (width, height): (u32, u32), max_dimension: u32) -> (u32, u32) {
+        let scale = self.scale().min(max_dimension as f32 / width.max(height).max(1) as f32);
+        let scaled = |size: u32| ((size as f32 * scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+
+    /// Feeds in how long the last drawn frame took, the GPU or CPU side, whichever was slower.
+    pub fn update(&mut self, frame_time: Duration) {
+        let ms = frame_time.as_secs_f32() * 1000.0;
+        self.frame_ms = if self.frame_ms == 0.0 {
+            ms
+        } else {
+            self.frame_ms * (1.0 - SMOOTHING) + ms * SMOOTHING
+        };
+        if !self.enabled {
+            self.steps = 0;
+            return;
+        }
+        if self.settle_frames > 0 {
+            self.settle_frames -= 1;
+            return;
+        }
+        let budget_ms = 1000.0 / self.target_fps as f32;
+        let steps = if self.frame_ms > budget_ms * OVER_BUDGET {
+            (self.steps + 1).min(MAX_STEPS)
+        } else if self.frame_ms < budget_ms * UNDER_BUDGET {
+            self.steps.saturating_sub(1)
+        } else {
+            self.steps
+        };
+        if steps != self.steps {
+            self.steps = steps;
+            self.settle_frames = SETTLE_FRAMES;
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Render scale");
+            ui.add(egui::DragValue::new(&mut self.render_scale).speed(0.01).range(RENDER_SCALE_RANGE))
+                .on_hover_text("Scene resolution relative to the window's pixels, whatever the display scale");
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.enabled, "Dynamic resolution")
+                .on_hover_text("Renders the scene at a lower resolution while frames take too long");
+            ui.add_enabled(
+                self.enabled,
+                egui::DragValue::new(&mut self.target_fps).range(TARGET_FPS_RANGE).suffix(" FPS target"),
+            );
+        });
+        if self.enabled {
+            ui.horizontal(|ui| {
+                ui.label("Upscaling");
+                for filter in UpscaleFilter::ALL {
+                    ui.radio_value(&mut self.filter, filter, filter.label());
+                }
+            });
+            ui.weak(format!("Scene at {:.0}%, {:.1} ms per frame", self.scale() * 100.0, self.frame_ms));
+        }
+    }
+}
+
+impl Default for ResolutionController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Texture the scene is drawn into below full resolution
+struct Target {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+// The offscreen target the scene renders into while scaled down, and the pass that stretches it over the
+// surface before the UI is drawn on top
+pub struct SceneTarget {
+    format: wgpu::TextureFormat,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    bilinear: wgpu::RenderPipeline,
+    sharpened: wgpu::RenderPipeline,
+    target: Option<Target>,
+}
+
+impl SceneTarget {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Upscale Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Upscale Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Upscale Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("upscale.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Upscale Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label, entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point,
+                    targets: &[Some(format.into())],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        Self {
+            format,
+            bilinear: pipeline("Bilinear Upscale Pipeline", "fs_bilinear"),
+            sharpened: pipeline("Sharpened Upscale Pipeline", "fs_sharpen"),
+            layout,
+            sampler,
+            target: None,
+        }
+    }
+
+    /// Makes the target `size`, replacing it if its size differs.
+    pub fn resize(&mut self, device: &wgpu::Device, stats: &mut RenderStats, (width, height): (u32, u32)) {
+        if let Some(target) = &self.target {
+            if (target.texture.width(), target.texture.height()) == (width, height) {
+                return;
+            }
+        }
+        self.release(stats);
+        let texture = stats.create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Scaled Scene Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                // Copied from by passes that read the scene back, such as water
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            },
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Upscale Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        self.target = Some(Target {
+            texture,
+            view,
+            bind_group,
+        });
+    }
+
+    /// Frees the target while the scene renders straight to the surface.
+    pub fn release(&mut self, stats: &mut RenderStats) {
+        if let Some(target) = self.target.take() {
+            stats.texture_destroyed(&target.texture);
+        }
+    }
+
+    /// View to draw the scene into, if it is currently scaled.
+    pub fn view(&self) -> Option<&wgpu::TextureView> {
+        self.target.as_ref().map(|target| &target.view)
+    }
+
+    /// Texture behind `view`, if the scene is currently scaled.
+    pub fn texture(&self) -> Option<&wgpu::Texture> {
+        self.target.as_ref().map(|target| &target.texture)
+    }
+
+    /// Stretches the scene over all of `surface_view`. Does nothing without a target.
+    pub fn upscale(&self, encoder: &mut wgpu::CommandEncoder, surface_view: &wgpu::TextureView, filter: UpscaleFilter) {
+        let Some(target) = &self.target else {
+            return;
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Upscale Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: surface_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), // Every pixel is overwritten
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(match filter {
+            UpscaleFilter::Bilinear => &self.bilinear,
+            UpscaleFilter::Sharpened => &self.sharpened,
+        });
+        pass.set_bind_group(0, &target.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
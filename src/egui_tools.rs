@@ -1,13 +1,52 @@
-use egui::Context;
-use egui_wgpu::wgpu::{CommandEncoder, Device, Queue, StoreOp, TextureFormat, TextureView};
+use egui::{Context, TextureId, ViewportId, ViewportIdMap, ViewportInfo, ViewportOutput};
+use egui_wgpu::wgpu::{CommandEncoder, Device, FilterMode, Queue, StoreOp, TextureFormat, TextureView};
 use egui_wgpu::{wgpu, Renderer, ScreenDescriptor};
+use egui_winit::accesskit_winit::ActionRequestEvent;
 use egui_winit::State;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use web_time::Instant;
 use winit::event::WindowEvent;
-use winit::window::Window;
+use winit::event_loop::{EventLoopProxy, EventLoopWindowTarget};
+use winit::window::{Window, WindowId};
+
+// Events sent to the event loop from outside winit
+#[derive(Debug)]
+pub enum UserEvent {
+    AccessKit(ActionRequestEvent), // A screen reader or other assistive technology acting on a widget
+    AppEvents,                     // `AppEvent`s were sent for the render loop to apply
+}
+
+impl From<ActionRequestEvent> for UserEvent {
+    fn from(event: ActionRequestEvent) -> Self {
+        UserEvent::AccessKit(event)
+    }
+}
+
+// A deferred egui viewport shown in its own native window
+struct ChildViewport {
+    window: Arc<Window>,
+    surface: wgpu::Surface<'static>,
+    config: wgpu::SurfaceConfiguration,
+    state: State,
+    info: ViewportInfo,
+    output: ViewportOutput,
+}
 
 pub struct EguiRenderer {
     state: State,
     renderer: Renderer,
+    output_color_format: TextureFormat,
+    viewports: HashMap<ViewportId, ChildViewport>,
+    // Deferred viewports requested by the last root frame
+    viewport_output: ViewportIdMap<ViewportOutput>,
+    focused: Option<ViewportId>, // Window with keyboard focus, None while the app is in the background
+    repaint_requested: bool,     // An event since the last frame changes what the UI shows
+    repaint_at: Option<Instant>, // When the last frame asked to be followed by another, e.g. for an animation
 }
 
 impl EguiRenderer {
@@ -23,6 +62,10 @@ impl EguiRenderer {
         window: &Window,
     ) -> EguiRenderer {
         let egui_context = Context::default();
+        // Deferred viewports get real OS windows instead of being drawn inside the main one
+        #[cfg(not(target_arch = "wasm32"))]
+        egui_context.set_embed_viewports(false);
+        disable_builtin_zoom(&egui_context);
 
         let egui_state = egui_winit::State::new(
             egui_context,
@@ -41,17 +84,148 @@ impl EguiRenderer {
         EguiRenderer {
             state: egui_state,
             renderer: egui_renderer,
+            output_color_format,
+            viewports: HashMap::new(),
+            viewport_output: ViewportIdMap::default(),
+            focused: Some(ViewportId::ROOT),
+            repaint_requested: true,
+            repaint_at: None,
+        }
+    }
+
+    /// Exposes the main window's widgets to assistive technologies. AccessKit only starts building
+    /// its tree once a screen reader asks for it.
+    pub fn init_accesskit(&mut self, window: &Window, proxy: EventLoopProxy<UserEvent>) {
+        let ctx = self.context().clone();
+        self.state.init_accesskit(window, proxy, move || {
+            ctx.enable_accesskit();
+            ctx.request_repaint();
+            ctx.accesskit_placeholder_tree_update()
+        });
+    }
+
+    pub fn handle_accesskit_event(&mut self, event: ActionRequestEvent) {
+        self.state.on_accesskit_action_request(event.request);
+        self.repaint_requested = true;
+    }
+
+    // Returns true if egui consumed the event, e.g. a click on a window
+    pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        self.track_focus(ViewportId::ROOT, event);
+        let response = self.state.on_window_event(window, event);
+        self.repaint_requested |= response.repaint;
+        response.consumed
+    }
+
+    /// Whether the main window's UI would look different if drawn now, because of input since the last
+    /// frame or a repaint egui asked for.
+    pub fn wants_repaint(&self) -> bool {
+        self.repaint_requested || self.repaint_at.is_some_and(|at| Instant::now() >= at)
+    }
+
+    // Focus moves between windows as a loss on one followed by a gain on the other
+    fn track_focus(&mut self, id: ViewportId, event: &WindowEvent) {
+        match event {
+            WindowEvent::Focused(true) => self.focused = Some(id),
+            WindowEvent::Focused(false) if self.focused == Some(id) => self.focused = None,
+            _ => {}
+        }
+    }
+
+    /// The egui window the user is typing into, if any of the app's windows has focus.
+    pub fn focused_viewport(&self) -> Option<ViewportId> {
+        self.focused
+    }
+
+    /// Routes an event for one of the detached viewport windows. Returns false if `window_id` is not one of them.
+    /// Keys the detached window does not use are passed on to the root context, so app shortcuts work from
+    /// every window.
+    pub fn handle_viewport_input(&mut self, device: &Device, window_id: WindowId, event: &WindowEvent) -> bool {
+        let Some((&id, child)) = self.viewports.iter_mut().find(|(_, c)| c.window.id() == window_id) else {
+            return false;
+        };
+        match event {
+            // The panel decides whether to re-attach; egui reports this as `close_requested`
+            WindowEvent::CloseRequested => child.info.events.push(egui::ViewportEvent::Close),
+            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                child.config.width = size.width;
+                child.config.height = size.height;
+                child.surface.configure(device, &child.config);
+            }
+            _ => {}
+        }
+        let consumed = child.state.on_window_event(&child.window, event).consumed;
+        // Modifiers are tracked per window by each egui state; the root one must not miss a Ctrl held
+        // down over a detached window. A text field focused in the main window keeps its own typing
+        let forward = match event {
+            WindowEvent::ModifiersChanged(_) => true,
+            WindowEvent::KeyboardInput { .. } => !consumed && !self.state.egui_ctx().wants_keyboard_input(),
+            _ => false,
+        };
+        if forward {
+            let _ = self.state.on_window_event(&child.window, event);
+        }
+        self.track_focus(id, event);
+        true
+    }
+
+    // Restores window positions, collapsing headers and widget state saved by `save_memory`
+    pub fn load_memory(&self, path: &Path) -> io::Result<()> {
+        let text = fs::read_to_string(path)?;
+        let memory: egui::Memory =
+            ron::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.set_memory(memory);
+        Ok(())
+    }
+
+    pub fn set_memory(&self, memory: egui::Memory) {
+        self.context().memory_mut(|m| *m = memory);
+        // Options are part of the saved memory
+        disable_builtin_zoom(self.context());
+    }
+
+    pub fn save_memory(&self, path: &Path) -> io::Result<()> {
+        let text = self
+            .context()
+            .memory(ron::to_string)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
         }
+        fs::write(path, text)
+    }
+
+    /// Makes a wgpu texture usable in `egui::Image`. The view must be `Rgba8UnormSrgb`.
+    pub fn register_texture(&mut self, device: &Device, view: &TextureView, filter: FilterMode) -> TextureId {
+        self.renderer.register_native_texture(device, view, filter)
     }
 
-    pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) {
-        self.state.on_window_event(window, event);
+    // Points an already registered id at a new view, e.g. after a render target was resized
+    pub fn update_texture(&mut self, device: &Device, view: &TextureView, filter: FilterMode, id: TextureId) {
+        self.renderer
+            .update_egui_texture_from_wgpu_texture(device, view, filter, id);
+    }
+
+    pub fn free_texture(&mut self, id: TextureId) {
+        self.renderer.free_texture(&id);
+    }
+
+    // Queues a synthetic input event for the focused window's next frame, e.g. controller navigation
+    pub fn push_event(&mut self, event: egui::Event) {
+        let child = self.focused.and_then(|id| self.viewports.get_mut(&id));
+        let state = match child {
+            Some(child) => &mut child.state,
+            None => &mut self.state,
+        };
+        state.egui_input_mut().events.push(event);
+        self.repaint_requested = true;
     }
 
     pub fn ppp(&mut self, v: f32) {
         self.state.egui_ctx().set_pixels_per_point(v);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         &mut self,
         device: &Device,
@@ -67,12 +241,20 @@ impl EguiRenderer {
             .set_pixels_per_point(screen_descriptor.pixels_per_point);
 
         let raw_input = self.state.take_egui_input(window);
-        let full_output = self.state.egui_ctx().run(raw_input, |ui| {
+        let full_output = self.state.egui_ctx().run(raw_input, |_ui| {
             run_ui(self.state.egui_ctx());
         });
 
         self.state
             .handle_platform_output(window, full_output.platform_output);
+        self.repaint_requested = false;
+        // Duration::MAX, meaning no repaint needed, overflows to None
+        self.repaint_at = full_output
+            .viewport_output
+            .get(&ViewportId::ROOT)
+            .and_then(|output| Instant::now().checked_add(output.repaint_delay));
+        self.viewport_output = full_output.viewport_output;
+        self.viewport_output.remove(&ViewportId::ROOT);
 
         let tris = self
             .state
@@ -104,4 +286,212 @@ impl EguiRenderer {
             self.renderer.free_texture(x)
         }
     }
+
+    /// Opens and closes native windows to match the deferred viewports of the last frame, then draws each one.
+    /// Call after the root frame has been submitted, since every viewport shares the renderer's buffers.
+    pub fn update_viewports(
+        &mut self,
+        elwt: &EventLoopWindowTarget<UserEvent>,
+        instance: &wgpu::Instance,
+        adapter: &wgpu::Adapter,
+        device: &Device,
+        queue: &Queue,
+    ) {
+        puffin::profile_function!();
+        let ctx = self.context().clone();
+        let outputs = std::mem::take(&mut self.viewport_output);
+
+        // Dropping the child closes its window
+        self.viewports.retain(|id, _| outputs.contains_key(id));
+
+        for (id, output) in outputs {
+            if output.viewport_ui_cb.is_none() {
+                continue; // Immediate viewports are drawn embedded
+            }
+            if let Some(child) = self.viewports.get_mut(&id) {
+                child.output = output;
+                let commands = std::mem::take(&mut child.output.commands);
+                egui_winit::process_viewport_commands(
+                    &ctx,
+                    &mut child.info,
+                    commands,
+                    &child.window,
+                    &mut Default::default(),
+                );
+                continue;
+            }
+            match self.create_viewport(elwt, instance, adapter, device, &ctx, id, output) {
+                Ok(child) => {
+                    self.viewports.insert(id, child);
+                }
+                Err(err) => log::warn!("Failed to open window for viewport {id:?}: {err}"),
+            }
+        }
+
+        let ids: Vec<ViewportId> = self.viewports.keys().copied().collect();
+        for id in ids {
+            self.draw_viewport(device, queue, &ctx, id);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_viewport(
+        &self,
+        elwt: &EventLoopWindowTarget<UserEvent>,
+        instance: &wgpu::Instance,
+        adapter: &wgpu::Adapter,
+        device: &Device,
+        ctx: &Context,
+        id: ViewportId,
+        output: ViewportOutput,
+    ) -> Result<ChildViewport, Box<dyn std::error::Error>> {
+        let window = Arc::new(egui_winit::create_window(ctx, elwt, &output.builder)?);
+        let surface = instance.create_surface(window.clone())?;
+        let size = window.inner_size();
+        let capabilities = surface.get_capabilities(adapter);
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: self.output_color_format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: wgpu::PresentMode::AutoVsync,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: capabilities.alpha_modes[0],
+            view_formats: vec![],
+        };
+        surface.configure(device, &config);
+
+        let state = State::new(ctx.clone(), id, &window, Some(window.scale_factor() as f32), None);
+        let mut info = ViewportInfo::default();
+        egui_winit::update_viewport_info(&mut info, ctx, &window, true);
+
+        Ok(ChildViewport {
+            window,
+            surface,
+            config,
+            state,
+            info,
+            output,
+        })
+    }
+
+    fn draw_viewport(&mut self, device: &Device, queue: &Queue, ctx: &Context, id: ViewportId) {
+        let Some(child) = self.viewports.get_mut(&id) else {
+            return;
+        };
+        let Some(ui_cb) = child.output.viewport_ui_cb.clone() else {
+            return;
+        };
+
+        egui_winit::update_viewport_info(&mut child.info, ctx, &child.window, false);
+        let mut raw_input = child.state.take_egui_input(&child.window);
+        raw_input.viewports.insert(id, child.info.clone());
+        child.info.events.clear();
+
+        let full_output = ctx.run(raw_input, |ctx| ui_cb(ctx));
+        child.state
+            .handle_platform_output(&child.window, full_output.platform_output);
+
+        let acquired = match child.surface.get_current_texture() {
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                child.surface.configure(device, &child.config);
+                child.surface.get_current_texture()
+            }
+            acquired => acquired,
+        };
+        let surface_texture = match acquired {
+            Ok(texture) => texture,
+            Err(err) => {
+                log::warn!("Skipping viewport {id:?} frame: {err}");
+                return;
+            }
+        };
+        let view = surface_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let screen_descriptor = ScreenDescriptor {
+            size_in_pixels: [child.config.width, child.config.height],
+            pixels_per_point: full_output.pixels_per_point,
+        };
+        let tris = ctx.tessellate(full_output.shapes, full_output.pixels_per_point);
+
+        for (id, image_delta) in &full_output.textures_delta.set {
+            self.renderer
+                .update_texture(device, queue, *id, image_delta);
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("egui viewport encoder"),
+        });
+        self.renderer
+            .update_buffers(device, queue, &mut encoder, &tris, &screen_descriptor);
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                label: Some("egui viewport render pass"),
+                occlusion_query_set: None,
+            });
+            self.renderer.render(&mut rpass, &tris, &screen_descriptor);
+        }
+        queue.submit(Some(encoder.finish()));
+        surface_texture.present();
+        for x in &full_output.textures_delta.free {
+            self.renderer.free_texture(x)
+        }
+    }
+}
+
+// UI scale is owned by UIState and applied through the screen descriptor, so egui must not zoom on its own
+fn disable_builtin_zoom(ctx: &Context) {
+    ctx.options_mut(|o| o.zoom_with_keyboard = false);
+}
+
+/// Shows `add_contents` in an `egui::Window` with a "Detach" button, or in its own native window while `detached` is set.
+/// Closing the native window re-attaches the panel.
+pub fn detachable_window(
+    ctx: &Context,
+    title: &'static str,
+    default_size: [f32; 2],
+    detached: &Arc<AtomicBool>,
+    add_contents: impl Fn(&mut egui::Ui) + Send + Sync + 'static,
+) {
+    if !detached.load(Ordering::Relaxed) {
+        egui::Window::new(title)
+            .default_open(false)
+            .default_size(default_size)
+            .show(ctx, |ui| {
+                if ui.small_button("⧉ Detach").on_hover_text("Move into its own window").clicked() {
+                    detached.store(true, Ordering::Relaxed);
+                }
+                add_contents(ui);
+            });
+        return;
+    }
+
+    let detached = detached.clone();
+    ctx.show_viewport_deferred(
+        ViewportId::from_hash_of(title),
+        egui::ViewportBuilder::default()
+            .with_title(title)
+            .with_inner_size(default_size),
+        move |ctx, class| {
+            if class == egui::ViewportClass::Embedded {
+                // No native windows on this platform, fall back to a regular window
+                egui::Window::new(title).show(ctx, |ui| add_contents(ui));
+            } else {
+                egui::CentralPanel::default().show(ctx, |ui| add_contents(ui));
+            }
+            if ctx.input(|i| i.viewport().close_requested()) {
+                detached.store(false, Ordering::Relaxed);
+            }
+        },
+    );
 }
@@ -0,0 +1,625 @@
+// environment.rs
+
+use crate::camera::Camera;
+use crate::depth::DepthConfig;
+use crate::environment_map::EnvironmentMap;
+use crate::file_dialog::{FileDialogs, FileKind};
+use crate::stats::RenderStats;
+use crate::transform::Transform;
+use crate::upload::Uploads;
+use crate::world::Light;
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::{Quat, Vec3};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+use std::path::{Path, PathBuf};
+
+// The sun's path leans south by this much, so it never stands straight overhead
+const SUN_TILT: f32 = 0.5;
+
+// Linear RGB of the sky at full day, at sunrise and sunset, and at night
+const DAY_ZENITH: Vec3 = Vec3::new(0.12, 0.3, 0.7);
+const DAY_HORIZON: Vec3 = Vec3::new(0.5, 0.65, 0.85);
+const DUSK_ZENITH: Vec3 = Vec3::new(0.15, 0.15, 0.35);
+const DUSK_HORIZON: Vec3 = Vec3::new(0.85, 0.4, 0.18);
+const NIGHT_ZENITH: Vec3 = Vec3::new(0.004, 0.008, 0.025);
+const NIGHT_HORIZON: Vec3 = Vec3::new(0.02, 0.03, 0.06);
+const SUN_NOON: Vec3 = Vec3::new(1.0, 0.97, 0.9);
+const SUN_LOW: Vec3 = Vec3::new(1.0, 0.55, 0.3);
+
+// Brings the analytic sky's luminance at a clear noon zenith to about what the gradient shows there
+const LUMINANCE_SCALE: f32 = 0.05;
+
+// Laid out like `Sky` in sky.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct SkyUniform {
+    inverse_view_proj: [[f32; 4]; 4],
+    zenith: [f32; 4],
+    horizon: [f32; 4],
+    ground: [f32; 4],
+    sun_direction: [f32; 4],
+    perez: [[f32; 4]; 5], // A to E, each for luminance and the two chromaticities
+    sky_zenith: [f32; 4], // w: how much the analytic sky shows over the gradient
+    image: [f32; 4],      // 1 if the environment image shows instead, its intensity and its rotation in radians
+}
+
+// What the sky and the sun look like at one time of day
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Daylight {
+    to_sun: Vec3,
+    sun_color: Vec3,
+    sun_intensity: f32,
+    zenith: Vec3,
+    horizon: Vec3,
+}
+
+impl Daylight {
+    // `hour` 6 is sunrise, 12 noon and 18 sunset
+    fn at(hour: f32, peak_intensity: f32) -> Self {
+        let angle = (hour / 24.0) * TAU - FRAC_PI_2;
+        let to_sun = Quat::from_rotation_x(-SUN_TILT) * Vec3::new(angle.cos(), angle.sin(), 0.0);
+        let elevation = to_sun.y;
+        let day = smoothstep(-0.05, 0.3, elevation);
+        // Strongest with the sun at the horizon, gone well above or below it
+        let dusk = (1.0 - (elevation / 0.25).abs()).clamp(0.0, 1.0);
+        let zenith = NIGHT_ZENITH.lerp(DAY_ZENITH, day).lerp(DUSK_ZENITH, dusk * 0.6);
+        let horizon = NIGHT_HORIZON.lerp(DAY_HORIZON, day).lerp(DUSK_HORIZON, dusk * 0.8);
+        Self {
+            to_sun,
+            sun_color: SUN_LOW.lerp(SUN_NOON, smoothstep(0.0, 0.5, elevation)),
+            sun_intensity: peak_intensity * smoothstep(-0.05, 0.15, elevation),
+            zenith,
+            horizon,
+        }
+    }
+}
+
+// How the background sky is drawn
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SkyModel {
+    Gradient, // Hand-picked colors from the horizon up to the zenith
+    Preetham, // The analytic daylight model, from the sun's position and the haze
+}
+
+impl SkyModel {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SkyModel::Gradient => "Gradient",
+            SkyModel::Preetham => "Analytic (Preetham)",
+        }
+    }
+}
+
+// What fills the background behind the scene
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Background {
+    Color, // The scene's background color, which the day/night cycle sets to the horizon
+    Sky,   // The gradient or analytic sky at the time of day, even with the cycle off
+    Image, // The environment image once loaded, and until then the sky while the cycle runs
+}
+
+impl Background {
+    pub const ALL: [Background; 3] = [Background::Color, Background::Sky, Background::Image];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Background::Color => "Solid color",
+            Background::Sky => "Sky",
+            Background::Image => "Environment image",
+        }
+    }
+}
+
+// Preetham, Shirley and Smits' analytic daylight model: luminance and chromaticity across the sky from the
+// sun's position and the turbidity of the air. Only defined with the sun above the horizon, so a sun below it
+// is treated as just above
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Preetham {
+    perez: [Vec3; 5], // A to E of the Perez distribution, for luminance and the x and y chromaticities
+    zenith: Vec3,     // Luminance and chromaticity at the zenith, divided by the distribution there
+}
+
+impl Preetham {
+    // `exposure` scales the luminance, which the model gives in thousands of candela per square meter
+    fn new(turbidity: f32, to_sun: Vec3, exposure: f32) -> Self {
+        let t = turbidity;
+        let perez = [
+            Vec3::new(0.1787 * t - 1.4630, -0.0193 * t - 0.2592, -0.0167 * t - 0.2608),
+            Vec3::new(-0.3554 * t + 0.4275, -0.0665 * t + 0.0008, -0.0950 * t + 0.0092),
+            Vec3::new(-0.0227 * t + 5.3251, -0.0004 * t + 0.2125, -0.0079 * t + 0.2102),
+            Vec3::new(0.1206 * t - 2.5771, -0.0641 * t - 0.8989, -0.0441 * t - 1.6537),
+            Vec3::new(-0.0670 * t + 0.3703, -0.0033 * t + 0.0452, -0.0109 * t + 0.0529),
+        ];
+        let theta = to_sun.y.clamp(0.02, 1.0).acos();
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let cubic = |a: f32, b: f32, c: f32, d: f32| ((a * theta + b) * theta + c) * theta + d;
+        let x = t * t * cubic(0.00166, -0.00375, 0.00209, 0.0)
+            + t * cubic(-0.02903, 0.06377, -0.03202, 0.00394)
+            + cubic(0.11693, -0.21196, 0.06052, 0.25886);
+        let y = t * t * cubic(0.00275, -0.00610, 0.00317, 0.0)
+            + t * cubic(-0.04214, 0.08970, -0.04153, 0.00516)
+            + cubic(0.15346, -0.26756, 0.06670, 0.26688);
+        let zenith = Vec3::new(luminance.max(0.0) * exposure * LUMINANCE_SCALE, x, y);
+        Self {
+            perez,
+            zenith: zenith / perez_distribution(&perez, 1.0, theta),
+        }
+    }
+
+    // Linear RGB of the sky along `direction`, which is clamped to the horizon
+    fn color(&self, direction: Vec3, to_sun: Vec3) -> Vec3 {
+        let to_sun = Vec3::new(to_sun.x, to_sun.y.max(0.02), to_sun.z).normalize();
+        let gamma = direction.dot(to_sun).clamp(-1.0, 1.0).acos();
+        let yxy = self.zenith * perez_distribution(&self.perez, direction.y.max(0.01), gamma);
+        // Chromaticity to XYZ, then XYZ to linear sRGB
+        let xyz = Vec3::new(yxy.y / yxy.z * yxy.x, yxy.x, (1.0 - yxy.y - yxy.z) / yxy.z * yxy.x);
+        Vec3::new(
+            3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+            -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+            0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+        )
+        .max(Vec3::ZERO)
+    }
+}
+
+// Relative brightness of the sky at `cos_theta` from the zenith and `gamma` radians from the sun
+fn perez_distribution(perez: &[Vec3; 5], cos_theta: f32, gamma: f32) -> Vec3 {
+    let [a, b, c, d, e] = *perez;
+    (Vec3::ONE + a * (b / cos_theta).exp()) * (Vec3::ONE + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// How fog thickens with distance from the camera
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FogMode {
+    Off,
+    Linear,      // From nothing at `start` to solid at `end`
+    Exponential, // Thickening by `density` per unit
+}
+
+impl FogMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FogMode::Off => "Off",
+            FogMode::Linear => "Linear",
+            FogMode::Exponential => "Exponential",
+        }
+    }
+}
+
+// Fog the lit shaders blend surfaces into, read through the lights uniform
+#[derive(Debug, Clone)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Vec3,         // Linear RGB
+    pub match_sky: bool,     // Take the horizon's color while the day/night cycle runs
+    pub start: f32,          // Linear fog only
+    pub end: f32,            // Linear fog only
+    pub density: f32,        // Exponential fog only
+    pub height_falloff: f32, // How quickly fog thins above y = 0; 0 keeps it even at every height
+}
+
+impl Fog {
+    pub fn new() -> Self {
+        Self {
+            mode: FogMode::Off,
+            color: Vec3::new(0.5, 0.6, 0.7),
+            match_sky: true,
+            start: 10.0,
+            end: 100.0,
+            density: 0.02,
+            height_falloff: 0.0,
+        }
+    }
+
+    // The mode as the shaders test it, then the color, then start, end, density and height falloff
+    pub fn uniform(&self) -> (u32, [f32; 3], [f32; 4]) {
+        let mode = match self.mode {
+            FogMode::Off => 0,
+            FogMode::Linear => 1,
+            FogMode::Exponential => 2,
+        };
+        let params = [self.start, self.end.max(self.start + 0.01), self.density, self.height_falloff];
+        (mode, self.color.to_array(), params)
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui, sky_active: bool) {
+        egui::ComboBox::from_label("Fog").selected_text(self.mode.label()).show_ui(ui, |ui| {
+            for mode in [FogMode::Off, FogMode::Linear, FogMode::Exponential] {
+                ui.selectable_value(&mut self.mode, mode, mode.label());
+            }
+        });
+        ui.add_enabled_ui(self.mode != FogMode::Off, |ui| {
+            ui.horizontal(|ui| {
+                ui.add_enabled_ui(!(self.match_sky && sky_active), |ui| {
+                    let mut color = self.color.to_array();
+                    if ui.color_edit_button_rgb(&mut color).changed() {
+                        self.color = Vec3::from(color);
+                    }
+                });
+                ui.checkbox(&mut self.match_sky, "Match sky");
+            });
+            match self.mode {
+                FogMode::Off => {}
+                FogMode::Linear => {
+                    ui.add(egui::Slider::new(&mut self.start, 0.0..=500.0).text("Fog start"));
+                    ui.add(egui::Slider::new(&mut self.end, 0.0..=1000.0).text("Fog end"));
+                }
+                FogMode::Exponential => {
+                    ui.add(egui::Slider::new(&mut self.density, 0.0001..=0.5).logarithmic(true).text("Density"));
+                }
+            }
+            ui.add(egui::Slider::new(&mut self.height_falloff, 0.0..=2.0).text("Height falloff"));
+        });
+    }
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// An equirectangular HDR or EXR image the scene can be seen against and lit by, converted to a cubemap by
+// `EnvironmentMap`
+#[derive(Debug, Clone)]
+pub struct EnvironmentImage {
+    pub path: Option<PathBuf>,
+    pub lighting: bool,   // Gives the ambient light, and reflections where no probe reaches
+    pub intensity: f32,
+    pub rotation: f32, // Degrees the image is turned about the vertical
+}
+
+impl EnvironmentImage {
+    pub fn new() -> Self {
+        Self {
+            path: None,
+            lighting: true,
+            intensity: 1.0,
+            rotation: 0.0,
+        }
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui, file_dialogs: &mut FileDialogs) {
+        ui.horizontal(|ui| {
+            let name = self.path.as_deref().and_then(Path::file_name);
+            ui.label(name.map_or("No environment image".into(), |name| name.to_string_lossy().into_owned()));
+            if ui.button("Load Image…").on_hover_text(IMAGE_HINT).clicked() {
+                file_dialogs.open(FileKind::EnvironmentImage);
+            }
+            if self.path.is_some() && ui.button("Clear").clicked() {
+                self.path = None;
+            }
+        });
+        ui.add_enabled_ui(self.path.is_some(), |ui| {
+            ui.checkbox(&mut self.lighting, "Light the scene");
+            ui.add(egui::Slider::new(&mut self.intensity, 0.0..=10.0).text("Image intensity"));
+            ui.add(egui::Slider::new(&mut self.rotation, 0.0..=360.0).text("Image rotation").suffix("°"));
+        });
+    }
+}
+
+const IMAGE_HINT: &str = "An equirectangular HDR, EXR, PNG or JPEG, or one of six cube faces named like \
+    sky_px.png to sky_nz.png, or right.png, left.png, top.png, bottom.png, front.png and back.png";
+
+impl Default for EnvironmentImage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Time of day, moving the sun across the sky and recoloring the sky with it. Off, the sun and the background
+// are left to the Lights window and the scene
+#[derive(Debug, Clone)]
+pub struct Environment {
+    pub enabled: bool,
+    pub playing: bool,
+    pub hour: f32,          // 0..24
+    pub day_length: f32,    // Seconds one full day takes
+    pub sun_intensity: f32, // At noon
+    pub sky_model: SkyModel,
+    pub turbidity: f32,     // Haze of the analytic sky, 2 for clear air up to 10 for thick haze
+    pub exposure: f32,      // Brightness of the analytic sky
+    pub fog: Fog,
+    pub background: Background,
+    pub image: EnvironmentImage,
+    moved: bool,            // The time or settings changed since the sun was last placed
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            playing: true,
+            hour: 10.0,
+            day_length: 120.0,
+            sun_intensity: 1.0,
+            sky_model: SkyModel::Gradient,
+            turbidity: 3.0,
+            exposure: 1.0,
+            fog: Fog::new(),
+            background: Background::Image,
+            image: EnvironmentImage::new(),
+            moved: true,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.playing
+    }
+
+    /// Whether the sky pass has anything to draw: the sky, or the environment image once `image_ready`.
+    pub fn draws_sky(&self, image_ready: bool) -> bool {
+        match self.background {
+            Background::Color => false,
+            Background::Sky => true,
+            Background::Image => image_ready || self.enabled,
+        }
+    }
+
+    /// Advances the time of day by `dt`, then turns and colors the `sun` and sets `background`, and the fog if it
+    /// matches the sky, to the horizon.
+    pub fn update(&mut self, dt: f32, sun: (&mut Transform, &mut Light), background: &mut Vec3) {
+        if !self.enabled {
+            return;
+        }
+        if self.playing && self.day_length > 0.0 {
+            self.hour = (self.hour + dt / self.day_length * 24.0).rem_euclid(24.0);
+            self.moved = true;
+        }
+        if !std::mem::take(&mut self.moved) {
+            return;
+        }
+        let daylight = Daylight::at(self.hour, self.sun_intensity);
+        let (transform, light) = sun;
+        // Directional lights shine along their -Z
+        transform.rotation = Quat::from_rotation_arc(Vec3::NEG_Z, -daylight.to_sun);
+        light.color = daylight.sun_color;
+        light.intensity = daylight.sun_intensity;
+        let horizon = self.horizon(&daylight);
+        *background = horizon;
+        if self.fog.match_sky {
+            self.fog.color = horizon;
+        }
+    }
+
+    /// The Environment window: switches the day/night cycle on, sets the time and the length of a day, chooses
+    /// the environment image, and sets up the fog.
+    pub fn show(&mut self, ctx: &Context, file_dialogs: &mut FileDialogs) {
+        egui::Window::new("Environment")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::ComboBox::from_label("Background")
+                    .selected_text(self.background.label())
+                    .show_ui(ui, |ui| {
+                        for background in Background::ALL {
+                            ui.selectable_value(&mut self.background, background, background.label());
+                        }
+                    });
+                ui.separator();
+                self.moved |= ui.checkbox(&mut self.enabled, "Day/night cycle").changed();
+                // The sky background shows the time of day and the sky even with the cycle off
+                ui.add_enabled_ui(self.enabled || self.background == Background::Sky, |ui| {
+                    ui.horizontal(|ui| {
+                        let label = if self.playing { "⏸ Pause" } else { "▶ Play" };
+                        if ui.button(label).clicked() {
+                            self.playing = !self.playing;
+                        }
+                        let minutes = (self.hour.fract() * 60.0) as u32;
+                        ui.label(format!("{:02}:{minutes:02}", self.hour as u32));
+                    });
+                    let time = egui::Slider::new(&mut self.hour, 0.0..=24.0).text("Time of day").suffix(" h");
+                    self.moved |= ui.add(time).changed();
+                    ui.add(
+                        egui::Slider::new(&mut self.day_length, 5.0..=3600.0)
+                            .logarithmic(true)
+                            .text("Day length")
+                            .suffix(" s"),
+                    );
+                    let intensity = egui::Slider::new(&mut self.sun_intensity, 0.0..=10.0).text("Sun at noon");
+                    self.moved |= ui.add(intensity).changed();
+                    egui::ComboBox::from_label("Sky").selected_text(self.sky_model.label()).show_ui(ui, |ui| {
+                        for model in [SkyModel::Gradient, SkyModel::Preetham] {
+                            self.moved |= ui.selectable_value(&mut self.sky_model, model, model.label()).changed();
+                        }
+                    });
+                    if self.sky_model == SkyModel::Preetham {
+                        let turbidity = egui::Slider::new(&mut self.turbidity, 2.0..=10.0).text("Turbidity");
+                        self.moved |= ui.add(turbidity).changed();
+                        let exposure = egui::Slider::new(&mut self.exposure, 0.1..=10.0).logarithmic(true);
+                        self.moved |= ui.add(exposure.text("Exposure")).changed();
+                    }
+                });
+                ui.separator();
+                self.image.settings_ui(ui, file_dialogs);
+                ui.separator();
+                let match_sky = self.fog.match_sky;
+                self.fog.settings_ui(ui, self.enabled);
+                // Recolored on the next update rather than waiting for the sun to move
+                self.moved |= self.fog.match_sky && !match_sky;
+            });
+    }
+
+    // How much the analytic sky shows over the night gradient; it fades out as the sun sets
+    fn analytic_amount(&self, daylight: &Daylight) -> f32 {
+        match self.sky_model {
+            SkyModel::Gradient => 0.0,
+            SkyModel::Preetham => smoothstep(-0.1, 0.05, daylight.to_sun.y),
+        }
+    }
+
+    // Color of the horizon away from the sun, which the background and the fog take
+    fn horizon(&self, daylight: &Daylight) -> Vec3 {
+        let amount = self.analytic_amount(daylight);
+        if amount == 0.0 {
+            return daylight.horizon;
+        }
+        let preetham = Preetham::new(self.turbidity, daylight.to_sun, self.exposure);
+        let across = Vec3::Y.cross(daylight.to_sun).normalize_or(Vec3::X);
+        daylight.horizon.lerp(preetham.color(across, daylight.to_sun), amount)
+    }
+
+    // The sky as the sky pass draws it, showing the environment image instead with `image_ready`
+    fn sky(&self, camera: &Camera, image_ready: bool) -> SkyUniform {
+        let daylight = Daylight::at(self.hour, self.sun_intensity);
+        let glow = smoothstep(-0.1, 0.05, daylight.to_sun.y);
+        let preetham = Preetham::new(self.turbidity, daylight.to_sun, self.exposure);
+        SkyUniform {
+            inverse_view_proj: camera.view_projection_matrix().inverse().to_cols_array_2d(),
+            zenith: daylight.zenith.extend(1.0).to_array(),
+            horizon: daylight.horizon.extend(1.0).to_array(),
+            ground: (daylight.horizon * 0.35).extend(1.0).to_array(),
+            sun_direction: daylight.to_sun.extend(glow).to_array(),
+            perez: preetham.perez.map(|coefficient| coefficient.extend(0.0).to_array()),
+            sky_zenith: preetham.zenith.extend(self.analytic_amount(&daylight)).to_array(),
+            image: [
+                f32::from(u8::from(image_ready && self.background == Background::Image)),
+                self.image.intensity,
+                self.image.rotation.to_radians(),
+                0.0,
+            ],
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Fills the background the scene left empty with the environment's sky, or its image. Drawn at the far plane
+// after the opaque passes, so only pixels still at the cleared depth are shaded
+pub struct SkyRenderer {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl SkyRenderer {
+    /// Draws into `format` targets, with the environment image bound through `image_layout`.
+    pub fn new(
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        format: wgpu::TextureFormat,
+        image_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Sky Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("sky.wgsl").into()),
+        });
+        let uniform_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Sky Uniform Buffer"),
+                size: size_of::<SkyUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sky Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sky Bind Group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[&layout, image_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sky Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(DepthConfig::READ_ONLY.state()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            pipeline,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    /// Draws the sky of `environment`, or the image `map` holds, behind everything already in `target`, seen
+    /// through `camera`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        environment: &Environment,
+        map: &EnvironmentMap,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) {
+        let sky = environment.sky(camera, map.is_ready());
+        uploads.write(device, &self.uniform_buffer, 0, bytemuck::bytes_of(&sky));
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Sky Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_bind_group(1, map.bind_group(), &[]);
+        pass.draw(0..3, 0..1);
+        stats.record_draw(3, 1);
+    }
+}
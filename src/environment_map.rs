@@ -0,0 +1,496 @@
+// environment_map.rs
+
+use crate::assets::{AssetEvent, Assets, Handle};
+use crate::environment::EnvironmentImage;
+use crate::jobs::FrameBudget;
+use crate::stats::RenderStats;
+use crate::toasts::Toasts;
+use crate::upload::Uploads;
+use egui_wgpu::wgpu;
+use glam::Vec3;
+use std::f32::consts::PI;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Side of each cube face images are converted to, in texels
+const FACE_SIZE: u32 = 512;
+
+const FACE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// Matches the workgroup size in environment_map.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+
+// The suffixes that name the six images of a cube split into files, in the order +X, -X, +Y, -Y, +Z, -Z
+const FACE_NAMES: [[&str; 6]; 2] = [
+    ["px", "nx", "py", "ny", "pz", "nz"],
+    ["right", "left", "top", "bottom", "front", "back"],
+];
+
+// A decoded equirectangular image in linear RGBA, with its average color over the sphere
+pub struct HdrImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<f32>,
+    average: Vec3,
+}
+
+impl HdrImage {
+    /// Reads an equirectangular HDR, EXR or 8-bit image, or one face of a cube split into six files named like
+    /// `sky_px.png` to `sky_nz.png` or `right.jpg` to `back.jpg`, which are read together and unwrapped onto an
+    /// equirectangular image.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        if let Some(faces) = face_paths(path) {
+            return Self::from_faces(&faces);
+        }
+        let (width, height, pixels) = read_linear(path)?;
+        Ok(Self::new(width, height, pixels))
+    }
+
+    fn new(width: u32, height: u32, pixels: Vec<f32>) -> Self {
+        // Rows towards the poles cover less of the sphere, so each counts by how wide it is there
+        let (mut sum, mut weight) = (Vec3::ZERO, 0.0);
+        for (y, row) in pixels.chunks_exact(width as usize * 4).enumerate() {
+            let row_weight = ((y as f32 + 0.5) / height as f32 * PI).sin();
+            for pixel in row.chunks_exact(4) {
+                let color = Vec3::new(pixel[0], pixel[1], pixel[2]);
+                if color.is_finite() {
+                    sum += color.max(Vec3::ZERO) * row_weight;
+                    weight += row_weight;
+                }
+            }
+        }
+        Self {
+            width,
+            height,
+            pixels,
+            average: if weight > 0.0 { sum / weight } else { Vec3::ZERO },
+        }
+    }
+
+    // Unwraps six square faces onto an equirectangular image laid out as environment_map.wgsl reads it back,
+    // so the cube it converts to has the faces as they were
+    fn from_faces(paths: &[PathBuf; 6]) -> io::Result<Self> {
+        let mut faces = Vec::with_capacity(6);
+        for path in paths {
+            let (width, height, pixels) = read_linear(path)?;
+            if width != height || faces.first().is_some_and(|(size, _)| *size != width) {
+                let message = format!("{} isn't square and the size of the other faces", path.display());
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+            faces.push((width, pixels));
+        }
+        let size = faces[0].0.min(FACE_SIZE);
+        let (width, height) = (size * 4, size * 2);
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            let latitude = (y as f32 + 0.5) / height as f32 * PI;
+            for x in 0..width {
+                // Longitude across the image with -Z at its center, as the converter reads it
+                let longitude = ((x as f32 + 0.5) / width as f32 - 0.5) * 2.0 * PI;
+                let direction = Vec3::new(
+                    longitude.sin() * latitude.sin(),
+                    latitude.cos(),
+                    -longitude.cos() * latitude.sin(),
+                );
+                let (face, u, v) = face_uv(direction);
+                let (face_size, face_pixels) = &faces[face];
+                pixels.extend_from_slice(&sample_face(face_pixels, *face_size, u, v));
+            }
+        }
+        Ok(Self::new(width, height, pixels))
+    }
+}
+
+// The six files of the cube `path` is a face of, if it's named like one and all six are there
+fn face_paths(path: &Path) -> Option<[PathBuf; 6]> {
+    let stem = path.file_stem()?.to_str()?;
+    let extension = path.extension().map(|extension| extension.to_string_lossy()).unwrap_or_default();
+    let lower = stem.to_ascii_lowercase();
+    FACE_NAMES.iter().find_map(|names| {
+        let name = names.iter().find(|name| lower.ends_with(*name))?;
+        let prefix = &stem[..stem.len() - name.len()];
+        let paths = names.map(|name| path.with_file_name(format!("{prefix}{name}.{extension}")));
+        paths.iter().all(|path| path.is_file()).then_some(paths)
+    })
+}
+
+// An image's pixels as linear RGBA floats; 8 and 16-bit images are taken to be sRGB
+fn read_linear(path: &Path) -> io::Result<(u32, u32, Vec<f32>)> {
+    let image = image::open(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let float = matches!(image, image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_));
+    let image = image.to_rgba32f();
+    let (width, height) = image.dimensions();
+    let mut pixels = image.into_raw();
+    if !float {
+        for pixel in pixels.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel = if *channel <= 0.04045 {
+                    *channel / 12.92
+                } else {
+                    ((*channel + 0.055) / 1.055).powf(2.4)
+                };
+            }
+        }
+    }
+    Ok((width, height, pixels))
+}
+
+// The face `direction` points through, and where on it, -1 to 1 from the top left. The inverse of
+// `face_direction` in environment_map.wgsl
+fn face_uv(direction: Vec3) -> (usize, f32, f32) {
+    let Vec3 { x, y, z } = direction;
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    if ax >= ay && ax >= az {
+        if x > 0.0 {
+            (0, -z / ax, -y / ax)
+        } else {
+            (1, z / ax, -y / ax)
+        }
+    } else if ay >= az {
+        if y > 0.0 {
+            (2, x / ay, z / ay)
+        } else {
+            (3, x / ay, -z / ay)
+        }
+    } else if z > 0.0 {
+        (4, x / az, -y / az)
+    } else {
+        (5, -x / az, -y / az)
+    }
+}
+
+// Bilinearly filtered RGBA of a square face at `u` and `v`, -1 to 1, clamped at its edges
+fn sample_face(pixels: &[f32], size: u32, u: f32, v: f32) -> [f32; 4] {
+    let max = size as f32 - 1.0;
+    let position = |t: f32| ((t + 1.0) * 0.5 * size as f32 - 0.5).clamp(0.0, max);
+    let (px, py) = (position(u), position(v));
+    let (x0, y0) = (px.floor() as usize, py.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(size as usize - 1), (y0 + 1).min(size as usize - 1));
+    let (tx, ty) = (px.fract(), py.fract());
+    let texel = |x: usize, y: usize, channel: usize| pixels[(y * size as usize + x) * 4 + channel];
+    std::array::from_fn(|channel| {
+        let top = texel(x0, y0, channel) * (1.0 - tx) + texel(x1, y0, channel) * tx;
+        let bottom = texel(x0, y1, channel) * (1.0 - tx) + texel(x1, y1, channel) * tx;
+        top * (1.0 - ty) + bottom * ty
+    })
+}
+
+// The environment image as a cubemap, for the sky to draw and the main shader to light with. Images are read on
+// the job pool, uploaded as they are, and resampled onto the cube's faces by a compute pass. Until one has
+// loaded the cube is black
+pub struct EnvironmentMap {
+    pub bind_group_layout: wgpu::BindGroupLayout, // The cube and its sampler
+    converter: Option<(wgpu::ComputePipeline, wgpu::BindGroupLayout)>, // None without compute shaders
+    sampler: wgpu::Sampler,
+    images: Assets<HdrImage>,
+    path: Option<PathBuf>,
+    handle: Option<Handle<HdrImage>>,
+    cube: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    pending: Option<(wgpu::Texture, wgpu::BindGroup)>, // An uploaded image waiting to be converted
+    average: Option<Vec3>,                             // Of the image the cube holds, if it holds one
+}
+
+impl EnvironmentMap {
+    /// Without `compute`, images can't be converted and loading one reports an error.
+    pub fn new(device: &wgpu::Device, stats: &mut RenderStats, compute: bool) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Environment Map Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let converter = compute.then(|| {
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Environment Map Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("environment_map.wgsl").into()),
+            });
+            let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Environment Conversion Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: FACE_FORMAT,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Environment Conversion Pipeline Layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            });
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Environment Conversion Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: "main",
+                compilation_options: Default::default(),
+            });
+            (pipeline, layout)
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Map Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let cube = create_cube(device, stats, 1, false);
+        let view = cube_view(&cube);
+        let bind_group = create_bind_group(device, &bind_group_layout, &view, &sampler);
+        Self {
+            bind_group_layout,
+            converter,
+            sampler,
+            images: Assets::new(HdrImage::load),
+            path: None,
+            handle: None,
+            cube,
+            view,
+            bind_group,
+            pending: None,
+            average: None,
+        }
+    }
+
+    /// The cube and its sampler, for the sky.
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// The cube, for the main shader's reflections.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Whether the cube holds an image.
+    pub fn is_ready(&self) -> bool {
+        self.average.is_some()
+    }
+
+    /// The average color of the image the cube holds, which ambient light can take.
+    pub fn average(&self) -> Option<Vec3> {
+        self.average
+    }
+
+    /// Loads the file `image` names when it changes, and uploads images as they finish loading, ready for
+    /// `convert`. Files are reloaded when edited on disk. Returns whether the cube was replaced.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        budget: &mut FrameBudget,
+        image: &EnvironmentImage,
+        toasts: &mut Toasts,
+    ) -> bool {
+        let mut changed = false;
+        if image.path != self.path {
+            if let Some(handle) = self.handle.take() {
+                self.images.remove(handle);
+            }
+            self.path.clone_from(&image.path);
+            self.handle = self.path.as_ref().map(|path| self.images.load(path, true));
+            if self.path.is_none() && self.average.take().is_some() {
+                self.replace_cube(device, stats, 1);
+                changed = true;
+            }
+        }
+        for event in self.images.poll(budget) {
+            match event {
+                AssetEvent::Loaded(handle) | AssetEvent::Reloaded(handle) => {
+                    if let Err(err) = self.upload(device, stats, uploads, handle) {
+                        log::warn!("Failed to use environment image: {err}");
+                        toasts.error(format!("Failed to use environment image: {err}"));
+                    } else {
+                        changed = true;
+                    }
+                }
+                AssetEvent::Failed(handle, err) => {
+                    let path = self.images.path(handle).map(Path::to_path_buf).unwrap_or_default();
+                    log::warn!("Failed to load environment image from {}: {err}", path.display());
+                    toasts.error(format!("Failed to load environment image: {err}"));
+                }
+            }
+        }
+        changed
+    }
+
+    /// Resamples an image uploaded by `update` onto the cube. Returns whether there was one.
+    pub fn convert(&mut self, stats: &mut RenderStats, encoder: &mut wgpu::CommandEncoder) -> bool {
+        let (Some((source, bind_group)), Some((pipeline, _))) = (self.pending.take(), &self.converter) else {
+            return false;
+        };
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Environment Conversion Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let groups = FACE_SIZE.div_ceil(WORKGROUP_SIZE);
+            pass.dispatch_workgroups(groups, groups, 6);
+        }
+        // Freed once the pass has run
+        stats.texture_destroyed(&source);
+        true
+    }
+
+    fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        handle: Handle<HdrImage>,
+    ) -> Result<(), String> {
+        let Some(image) = self.images.get(handle).filter(|_| self.handle == Some(handle)) else {
+            return Ok(());
+        };
+        if self.converter.is_none() {
+            return Err("converting it needs compute shaders, which this adapter lacks".into());
+        }
+        let limit = device.limits().max_texture_dimension_2d;
+        if image.width > limit || image.height > limit {
+            let (width, height) = (image.width, image.height);
+            return Err(format!("{width}x{height} is larger than the {limit} texels a side this GPU allows"));
+        }
+        let source = stats.create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Environment Image Texture"),
+                size: wgpu::Extent3d {
+                    width: image.width,
+                    height: image.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+        );
+        uploads.write_texture(device, &source, bytemuck::cast_slice(&image.pixels));
+        let average = image.average;
+        self.replace_cube(device, stats, FACE_SIZE);
+        let (_, layout) = self.converter.as_ref().expect("checked above");
+        let faces = self.cube.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Environment Face View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let source_view = source.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment Conversion Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&faces),
+                },
+            ],
+        });
+        if let Some((previous, _)) = self.pending.replace((source, bind_group)) {
+            stats.texture_destroyed(&previous);
+        }
+        self.average = Some(average);
+        Ok(())
+    }
+
+    fn replace_cube(&mut self, device: &wgpu::Device, stats: &mut RenderStats, size: u32) {
+        let cube = create_cube(device, stats, size, self.converter.is_some());
+        stats.texture_destroyed(&std::mem::replace(&mut self.cube, cube));
+        self.view = cube_view(&self.cube);
+        self.bind_group = create_bind_group(device, &self.bind_group_layout, &self.view, &self.sampler);
+    }
+}
+
+fn create_cube(device: &wgpu::Device, stats: &mut RenderStats, size: u32, storage: bool) -> wgpu::Texture {
+    let mut usage = wgpu::TextureUsages::TEXTURE_BINDING;
+    if storage {
+        usage |= wgpu::TextureUsages::STORAGE_BINDING;
+    }
+    stats.create_texture(
+        device,
+        &wgpu::TextureDescriptor {
+            label: Some("Environment Cube Texture"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FACE_FORMAT,
+            usage,
+            view_formats: &[],
+        },
+    )
+}
+
+fn cube_view(cube: &wgpu::Texture) -> wgpu::TextureView {
+    cube.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Environment Cube View"),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Environment Map Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
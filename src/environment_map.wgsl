@@ -0,0 +1,54 @@
+// Environment images: resamples an equirectangular image onto the six faces of a cubemap, one invocation per
+// face texel. Faces follow the cube map convention of the backends, so sampling the cube along a direction
+// reads what was written for it here
+
+@group(0) @binding(0)
+var source: texture_2d<f32>; // Linear RGBA, not filterable, so it is read texel by texel
+@group(0) @binding(1)
+var faces: texture_storage_2d_array<rgba16float, write>;
+
+const PI: f32 = 3.14159265;
+
+// Largest value a half float holds, which bright suns in HDR images can pass
+const MAX_HALF: f32 = 65504.0;
+
+// The direction through `uv`, -1 to 1 from the top left, of cube face `face`: +X, -X, +Y, -Y, +Z, -Z
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -uv.y, -uv.x); }
+        case 1u: { return vec3<f32>(-1.0, -uv.y, uv.x); }
+        case 2u: { return vec3<f32>(uv.x, 1.0, uv.y); }
+        case 3u: { return vec3<f32>(uv.x, -1.0, -uv.y); }
+        case 4u: { return vec3<f32>(uv.x, -uv.y, 1.0); }
+        default: { return vec3<f32>(-uv.x, -uv.y, -1.0); }
+    }
+}
+
+// A texel of the source, wrapping around horizontally and clamped at the poles
+fn load(texel: vec2<i32>, size: vec2<i32>) -> vec3<f32> {
+    let x = ((texel.x % size.x) + size.x) % size.x;
+    let y = clamp(texel.y, 0, size.y - 1);
+    return textureLoad(source, vec2<i32>(x, y), 0).rgb;
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(faces);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+    let direction = normalize(face_direction(id.z, uv));
+
+    // Longitude across the image with -Z at its center, latitude down it from the zenith
+    let source_size = vec2<i32>(textureDimensions(source));
+    let longitude = atan2(direction.x, -direction.z) / (2.0 * PI) + 0.5;
+    let latitude = acos(clamp(direction.y, -1.0, 1.0)) / PI;
+    let position = vec2<f32>(longitude, latitude) * vec2<f32>(source_size) - 0.5;
+    let base = vec2<i32>(floor(position));
+    let t = position - floor(position);
+    let top = mix(load(base, source_size), load(base + vec2<i32>(1, 0), source_size), t.x);
+    let bottom = mix(load(base + vec2<i32>(0, 1), source_size), load(base + vec2<i32>(1, 1), source_size), t.x);
+    let color = clamp(mix(top, bottom, t.y), vec3<f32>(0.0), vec3<f32>(MAX_HALF));
+    textureStore(faces, id.xy, id.z, vec4<f32>(color, 1.0));
+}
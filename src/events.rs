@@ -0,0 +1,97 @@
+// events.rs
+
+use crate::commands::Command;
+use crate::egui_tools::UserEvent;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use winit::event_loop::EventLoopProxy;
+
+/// A change to the app, carrying what it needs. The UI sends them rather than reaching into the render loop's
+/// state, and background tasks and scripts holding an `EventSender` drive the app the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppEvent {
+    Command(Command),            // Anything in the command palette, as if picked there
+    SetSides(u16),               // Of the polygon shape, clamped to the allowed range
+    LoadMesh(PathBuf),           // Replaces the object's mesh with the model at the path, like Open Model
+    SetShader(String),           // Draws with the shader registered under the name
+    Screenshot(Option<PathBuf>), // Saves the next frame there, or to a new file in the screenshots directory
+}
+
+impl From<Command> for AppEvent {
+    fn from(command: Command) -> Self {
+        AppEvent::Command(command)
+    }
+}
+
+/// Sends `AppEvent`s to the render loop from any thread, waking it if it's idle. Clones all feed the same loop.
+#[derive(Debug, Clone)]
+pub struct EventSender {
+    sender: Sender<AppEvent>,
+    wake: Option<EventLoopProxy<UserEvent>>, // Set once the render loop is listening
+}
+
+impl EventSender {
+    /// Queues `event` for the render loop, which applies it after the UI pass of the next frame it draws, and
+    /// makes sure there is one. Does nothing once the app has exited.
+    pub fn send(&self, event: impl Into<AppEvent>) {
+        if self.sender.send(event.into()).is_ok() {
+            if let Some(wake) = &self.wake {
+                let _ = wake.send_event(UserEvent::AppEvents);
+            }
+        }
+    }
+}
+
+/// The queue of events the render loop applies each frame, in the order they were sent.
+#[derive(Debug)]
+pub struct EventBus {
+    sender: EventSender,
+    receiver: Receiver<AppEvent>,
+    received: Vec<AppEvent>, // Taken off the channel but not applied yet
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: EventSender { sender, wake: None },
+            receiver,
+            received: Vec::new(),
+        }
+    }
+
+    /// Has every sender handed out from now on wake the event loop through `proxy`. Senders handed out before
+    /// still queue events; they're applied whenever the loop next draws.
+    pub fn wake_with(&mut self, proxy: EventLoopProxy<UserEvent>) {
+        self.sender.wake = Some(proxy);
+    }
+
+    /// A sender to hand to a background task or script.
+    pub fn sender(&self) -> EventSender {
+        self.sender.clone()
+    }
+
+    /// Queues `event` from the render thread, which is awake already.
+    pub fn send(&self, event: impl Into<AppEvent>) {
+        let _ = self.sender.sender.send(event.into());
+    }
+
+    /// Takes what has been sent so far off the channel, returning whether anything is waiting to be applied, so
+    /// a frame that would otherwise be skipped gets drawn.
+    pub fn receive(&mut self) -> bool {
+        self.received.extend(self.receiver.try_iter());
+        !self.received.is_empty()
+    }
+
+    /// Takes everything sent so far, oldest first.
+    pub fn drain(&mut self) -> Vec<AppEvent> {
+        self.receive();
+        std::mem::take(&mut self.received)
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,152 @@
+// file_dialog.rs
+
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+// The kinds of files the app knows how to open or save, each with its own filter
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FileKind {
+    Model,
+    Texture,
+    EnvironmentImage,
+    World,
+    Scene,
+    Prefab,
+    Script,
+    Preset,
+    Recording,
+    GpuTrace,
+    Gltf,
+    Video,
+}
+
+impl FileKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FileKind::Model => "Model",
+            FileKind::Texture => "Texture",
+            FileKind::EnvironmentImage => "Environment Image",
+            FileKind::World => "World",
+            FileKind::Scene => "Scene",
+            FileKind::Prefab => "Prefab",
+            FileKind::Script => "Script",
+            FileKind::Preset => "Layout Preset",
+            FileKind::Recording => "Input Recording",
+            FileKind::GpuTrace => "GPU Trace",
+            FileKind::Gltf => "glTF Binary",
+            FileKind::Video => "Video",
+        }
+    }
+
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            FileKind::Model => &["obj", "gltf", "glb"],
+            FileKind::Texture => &["png", "dds", "ktx2"],
+            FileKind::EnvironmentImage => &["hdr", "exr", "png", "jpg", "jpeg"],
+            FileKind::World => &["vox"],
+            FileKind::Scene => &["scene", "json"], // RON, or JSON by extension
+            FileKind::Prefab => &["prefab"],
+            FileKind::Script => &["rhai"],
+            FileKind::Preset => &["ron"],
+            FileKind::Recording => &["inputs"],
+            FileKind::GpuTrace => &["json"],
+            FileKind::Gltf => &["glb"],
+            FileKind::Video => &["mp4", "mkv", "webm", "png"], // PNG writes numbered frames
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DialogAction {
+    Open,
+    Save,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileDialogResult {
+    pub action: DialogAction,
+    pub kind: FileKind,
+    pub path: PathBuf,
+}
+
+// Runs native dialogs off the event loop thread and hands back chosen paths through a channel
+pub struct FileDialogs {
+    sender: Sender<Option<FileDialogResult>>,
+    receiver: Receiver<Option<FileDialogResult>>,
+    pending: usize,
+}
+
+impl FileDialogs {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver,
+            pending: 0,
+        }
+    }
+
+    pub fn open(&mut self, kind: FileKind) {
+        self.spawn(DialogAction::Open, kind);
+    }
+
+    pub fn save(&mut self, kind: FileKind) {
+        self.spawn(DialogAction::Save, kind);
+    }
+
+    // True while a dialog is still waiting for the user
+    pub fn is_pending(&self) -> bool {
+        self.pending > 0
+    }
+
+    /// Returns the next path the user picked, if any dialog has completed since the last call.
+    pub fn poll(&mut self) -> Option<FileDialogResult> {
+        while let Ok(result) = self.receiver.try_recv() {
+            self.pending -= 1;
+            if result.is_some() {
+                return result;
+            }
+        }
+        None
+    }
+
+    // Phones and browsers have no native file dialog to show; files there come with the app
+    #[cfg(any(target_os = "android", target_os = "ios", target_arch = "wasm32"))]
+    fn spawn(&mut self, _: DialogAction, kind: FileKind) {
+        log::warn!("No file dialog for {} on this platform", kind.label());
+        // Answered like a cancelled dialog
+        self.pending += 1;
+        let _ = self.sender.send(None);
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "ios", target_arch = "wasm32")))]
+    fn spawn(&mut self, action: DialogAction, kind: FileKind) {
+        let dialog = rfd::AsyncFileDialog::new()
+            .set_title(format!("{} {}", if action == DialogAction::Open { "Open" } else { "Save" }, kind.label()))
+            .add_filter(kind.label(), kind.extensions());
+        let sender = self.sender.clone();
+        self.pending += 1;
+
+        std::thread::spawn(move || {
+            let handle = pollster::block_on(async {
+                match action {
+                    DialogAction::Open => dialog.pick_file().await,
+                    DialogAction::Save => dialog.save_file().await,
+                }
+            });
+            let result = handle.map(|handle| FileDialogResult {
+                action,
+                kind,
+                path: handle.path().to_path_buf(),
+            });
+            // The receiver only disappears when the app is shutting down
+            let _ = sender.send(result);
+        });
+    }
+}
+
+impl Default for FileDialogs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,100 @@
+// frame_capture.rs
+
+use std::path::PathBuf;
+
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_arch = "wasm32")))]
+type Api = renderdoc::RenderDoc<renderdoc::V141>;
+
+// RenderDoc can't run on Apple platforms or in browsers, where it is never attached
+#[cfg(any(target_os = "macos", target_os = "ios", target_arch = "wasm32"))]
+enum Api {}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_arch = "wasm32"))]
+impl Api {
+    fn new() -> Result<Self, ()> {
+        Err(())
+    }
+
+    fn get_api_version(&self) -> (u32, u32, u32) {
+        match *self {}
+    }
+
+    fn get_num_captures(&self) -> u32 {
+        match *self {}
+    }
+
+    fn trigger_capture(&mut self) {
+        match *self {}
+    }
+
+    fn get_capture(&self, _: u32) -> Option<(PathBuf, std::time::SystemTime)> {
+        match *self {}
+    }
+}
+
+// RenderDoc's in-application API, for capturing a frame on demand. Only available when the app was launched
+// from RenderDoc or had it injected; otherwise every call is a no-op
+pub struct FrameCapture {
+    renderdoc: Option<Api>,
+    seen_captures: u32,
+    pending: bool, // Triggered and waiting for the next present to be captured
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        let renderdoc = Api::new().ok();
+        if let Some(renderdoc) = &renderdoc {
+            let (major, minor, patch) = renderdoc.get_api_version();
+            log::info!("RenderDoc {major}.{minor}.{patch} attached; frame captures are available");
+        }
+        Self {
+            seen_captures: renderdoc.as_ref().map_or(0, |renderdoc| renderdoc.get_num_captures()),
+            renderdoc,
+            pending: false,
+        }
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.renderdoc.is_some()
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Captures the next presented frame. Returns false if RenderDoc isn't attached.
+    pub fn trigger(&mut self) -> bool {
+        let Some(renderdoc) = &mut self.renderdoc else {
+            return false;
+        };
+        renderdoc.trigger_capture();
+        self.pending = true;
+        true
+    }
+
+    /// Path of a capture written since the last call, if any. Call once per frame after presenting.
+    pub fn poll(&mut self) -> Option<PathBuf> {
+        let renderdoc = self.renderdoc.as_ref()?;
+        if renderdoc.get_num_captures() <= self.seen_captures {
+            return None;
+        }
+        let (path, _) = renderdoc.get_capture(self.seen_captures)?;
+        self.seen_captures += 1;
+        self.pending = false;
+        Some(path)
+    }
+
+    pub fn status(&self) -> String {
+        match &self.renderdoc {
+            None => "Launch the app from RenderDoc to capture frames".to_string(),
+            Some(_) if self.pending => "Capturing the next frame".to_string(),
+            Some(_) => format!("RenderDoc attached, {} capture(s) this session", self.seen_captures),
+        }
+    }
+}
+
+impl Default for FrameCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
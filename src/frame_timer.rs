@@ -0,0 +1,99 @@
+// frame_timer.rs
+
+use egui::{Align2, Context};
+use std::collections::VecDeque;
+use std::time::Duration;
+use web_time::Instant;
+
+// Frame times kept for the averages, a few seconds at common frame rates
+const HISTORY: usize = 240;
+const OVERLAY_MARGIN: f32 = 8.0;
+
+// Time between frames and the statistics players quote: average FPS and the 1% lows, the slowest frames
+// that show up as stutter even when the average looks fine
+pub struct FrameTimer {
+    pub show_overlay: bool,
+    last_tick: Option<Instant>,
+    paused: bool, // The next interval was held back on purpose, so it is left out of the statistics
+    dt: Duration,
+    frame_times: VecDeque<Duration>,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        Self {
+            show_overlay: false,
+            last_tick: None,
+            paused: false,
+            dt: Duration::ZERO,
+            frame_times: VecDeque::with_capacity(HISTORY),
+        }
+    }
+
+    /// Call once per drawn frame. Returns the time since the previous call, zero on the first.
+    pub fn tick(&mut self, now: Instant) -> Duration {
+        self.dt = self.last_tick.map_or(Duration::ZERO, |last| now - last);
+        if self.last_tick.is_some() && !std::mem::take(&mut self.paused) {
+            if self.frame_times.len() == HISTORY {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(self.dt);
+        }
+        self.last_tick = Some(now);
+        self.dt
+    }
+
+    /// Call when the next frame is deliberately held back, so the gap isn't counted as one slow frame.
+    /// The next `tick` still returns it, as animations have to catch up on it.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn dt(&self) -> Duration {
+        self.dt
+    }
+
+    /// Frames per second averaged over the recent frames.
+    pub fn average_fps(&self) -> f64 {
+        let total: Duration = self.frame_times.iter().sum();
+        if total.is_zero() {
+            return 0.0;
+        }
+        self.frame_times.len() as f64 / total.as_secs_f64()
+    }
+
+    /// The frame rate the slowest 1% of the recent frames would run at, at least one frame.
+    pub fn one_percent_low_fps(&self) -> f64 {
+        let mut sorted: Vec<Duration> = self.frame_times.iter().copied().collect();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+        let slowest = &sorted[..sorted.len().div_ceil(100)];
+        let total: Duration = slowest.iter().sum();
+        if total.is_zero() {
+            return 0.0;
+        }
+        slowest.len() as f64 / total.as_secs_f64()
+    }
+
+    pub fn show_overlay(&self, ctx: &Context) {
+        if !self.show_overlay {
+            return;
+        }
+        let average = self.average_fps();
+        let frame_ms = if average > 0.0 { 1000.0 / average } else { 0.0 };
+        egui::Area::new(egui::Id::new("frame_timer_overlay"))
+            .anchor(Align2::RIGHT_TOP, [-OVERLAY_MARGIN, OVERLAY_MARGIN])
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.monospace(format!("{average:6.1} FPS  {frame_ms:5.2} ms"));
+                    ui.monospace(format!("{:6.1} FPS  1% low", self.one_percent_low_fps()));
+                });
+            });
+    }
+}
+
+impl Default for FrameTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,119 @@
+// gamepad.rs
+
+use crate::input::{GamepadButton, InputEvent, InputState};
+use egui::{Key, Modifiers};
+
+// Controllers polled once per frame through gilrs; a no-op unless built with the `gamepad` feature
+pub struct Gamepads {
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "gamepad")]
+            gilrs: gilrs::Gilrs::new()
+                .map_err(|err| log::warn!("Gamepad support unavailable: {err}"))
+                .ok(),
+        }
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    pub fn poll(&mut self) -> Vec<InputEvent> {
+        Vec::new()
+    }
+
+    /// Drains pending controller events, to be applied to `InputState`.
+    #[cfg(feature = "gamepad")]
+    pub fn poll(&mut self) -> Vec<InputEvent> {
+        use crate::input::GamepadAxis;
+        use gilrs::{Axis, Button, EventType};
+
+        let mut events = Vec::new();
+        let Some(gilrs) = &mut self.gilrs else {
+            return events;
+        };
+        while let Some(event) = gilrs.next_event() {
+            let axis = |axis, value| InputEvent::GamepadAxis { axis, value };
+            events.push(match event.event {
+                EventType::ButtonPressed(button, _) | EventType::ButtonReleased(button, _) => {
+                    let Some(button) = map_button(button) else {
+                        continue;
+                    };
+                    let pressed = matches!(event.event, EventType::ButtonPressed(..));
+                    InputEvent::GamepadButton { button, pressed }
+                }
+                EventType::ButtonChanged(Button::LeftTrigger2, value, _) => axis(GamepadAxis::LeftTrigger, value),
+                EventType::ButtonChanged(Button::RightTrigger2, value, _) => axis(GamepadAxis::RightTrigger, value),
+                EventType::AxisChanged(stick, value, _) => match stick {
+                    Axis::LeftStickX => axis(GamepadAxis::LeftStickX, value),
+                    Axis::LeftStickY => axis(GamepadAxis::LeftStickY, value),
+                    Axis::RightStickX => axis(GamepadAxis::RightStickX, value),
+                    Axis::RightStickY => axis(GamepadAxis::RightStickY, value),
+                    _ => continue,
+                },
+                EventType::Connected => {
+                    log::info!("Gamepad connected: {}", gilrs.gamepad(event.id).name());
+                    continue;
+                }
+                EventType::Disconnected => {
+                    log::info!("Gamepad disconnected");
+                    continue;
+                }
+                _ => continue,
+            });
+        }
+        events
+    }
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "gamepad")]
+fn map_button(button: gilrs::Button) -> Option<GamepadButton> {
+    use gilrs::Button;
+    Some(match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftBumper,
+        Button::RightTrigger => GamepadButton::RightBumper,
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+/// Keyboard events that let a controller move focus through egui widgets:
+/// D-pad up/down cycle focus, left/right adjust sliders, South activates and East leaves text fields.
+pub fn ui_navigation_events(input: &InputState) -> Vec<egui::Event> {
+    let key = |key: Key, modifiers: Modifiers| egui::Event::Key {
+        key,
+        physical_key: None,
+        pressed: true,
+        repeat: false,
+        modifiers,
+    };
+    [
+        (GamepadButton::DPadDown, key(Key::Tab, Modifiers::NONE)),
+        (GamepadButton::DPadUp, key(Key::Tab, Modifiers::SHIFT)),
+        (GamepadButton::DPadLeft, key(Key::ArrowLeft, Modifiers::NONE)),
+        (GamepadButton::DPadRight, key(Key::ArrowRight, Modifiers::NONE)),
+        (GamepadButton::South, key(Key::Enter, Modifiers::NONE)),
+        (GamepadButton::East, key(Key::Escape, Modifiers::NONE)),
+    ]
+    .into_iter()
+    .filter(|(button, _)| input.gamepad_just_pressed(*button))
+    .map(|(_, event)| event)
+    .collect()
+}
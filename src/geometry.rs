@@ -0,0 +1,308 @@
+// geometry.rs
+
+use crate::depth::DEPTH_FORMAT;
+use crate::stats::RenderStats;
+use crate::upload::Uploads;
+use crate::vertex::Vertex;
+use egui_wgpu::wgpu;
+use std::ops::Range;
+use wgpu::util::RenderEncoder;
+
+// Fewest bundles to record before the work is spread over threads; fewer are quicker recorded on one than
+// handed out
+#[cfg(not(target_arch = "wasm32"))]
+const PARALLEL_BUNDLES: usize = 64;
+
+// Where a mesh lives in the arena's shared buffers. Its indices are local to the mesh and offset by the
+// base vertex when drawn, so 16-bit indices still work however large the shared buffer gets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeshAllocation {
+    vertices: Range<u32>,
+    indices: Range<u32>, // Padded to an even count so every mesh starts on a 4-byte boundary
+    pub num_indices: u32,
+}
+
+// Many small meshes suballocated from one shared vertex buffer and one shared index buffer, so a scene of
+// hundreds of objects binds its geometry once and draws ranges of it instead of owning buffers per mesh.
+// Freed ranges are reused by later allocations; the buffers only grow when nothing free is big enough. A copy
+// of both buffers is kept on the CPU, so meshes can be read back, as exporters need, without waiting on the GPU
+pub struct GeometryArena {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    vertices: Vec<Vertex>, // What the buffers hold, slot for slot
+    indices: Vec<u16>,
+    vertex_slots: FreeList,
+    index_slots: FreeList,
+    generation: u64,
+}
+
+impl GeometryArena {
+    /// Allocates room for `vertex_capacity` vertices and `index_capacity` indices across all meshes.
+    pub fn with_capacity(
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        vertex_capacity: u32,
+        index_capacity: u32,
+    ) -> Self {
+        let index_capacity = index_capacity.next_multiple_of(2);
+        let vertex_size = vertex_bytes(vertex_capacity);
+        let index_size = index_bytes(index_capacity);
+        Self {
+            vertex_buffer: create_buffer(device, stats, "Vertex Buffer", wgpu::BufferUsages::VERTEX, vertex_size),
+            index_buffer: create_buffer(device, stats, "Index Buffer", wgpu::BufferUsages::INDEX, index_size),
+            vertices: vec![Vertex::new([0.0; 3], [0.0; 3]); vertex_capacity as usize],
+            indices: vec![0; index_capacity as usize],
+            vertex_slots: FreeList::new(vertex_capacity),
+            index_slots: FreeList::new(index_capacity),
+            generation: 0,
+        }
+    }
+
+    /// Bumped whenever the shared buffers are replaced, which invalidates anything recorded against them.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Copies a mesh into the arena, growing the shared buffers if it doesn't fit.
+    pub fn allocate(
+        &mut self,
+        device: &wgpu::Device,
+        uploads: &mut Uploads,
+        stats: &mut RenderStats,
+        vertices: &[Vertex],
+        indices: &[u16],
+    ) -> MeshAllocation {
+        let num_vertices = vertices.len() as u32;
+        let num_indices = indices.len() as u32;
+        let padded_indices = num_indices.next_multiple_of(2);
+
+        let vertex_range = match self.vertex_slots.allocate(num_vertices) {
+            Some(range) => range,
+            None => {
+                let capacity = grown_capacity(self.vertex_slots.capacity, num_vertices);
+                let old = &self.vertex_buffer;
+                self.vertex_buffer = grow_buffer(device, uploads, stats, old, "Vertex Buffer", vertex_bytes(capacity));
+                self.vertex_slots.grow(capacity);
+                self.vertices.resize(capacity as usize, Vertex::new([0.0; 3], [0.0; 3]));
+                self.generation += 1;
+                self.vertex_slots.allocate(num_vertices).expect("Grown to fit")
+            }
+        };
+        let index_range = match self.index_slots.allocate(padded_indices) {
+            Some(range) => range,
+            None => {
+                let capacity = grown_capacity(self.index_slots.capacity, padded_indices).next_multiple_of(2);
+                let old = &self.index_buffer;
+                self.index_buffer = grow_buffer(device, uploads, stats, old, "Index Buffer", index_bytes(capacity));
+                self.index_slots.grow(capacity);
+                self.indices.resize(capacity as usize, 0);
+                self.generation += 1;
+                self.index_slots.allocate(padded_indices).expect("Grown to fit")
+            }
+        };
+
+        // Buffer writes must be a multiple of 4 bytes, so an odd index count gets a padding index
+        let mut padded = indices.to_vec();
+        padded.resize(padded_indices as usize, 0);
+        let (vertex_offset, index_offset) = (vertex_bytes(vertex_range.start), index_bytes(index_range.start));
+        uploads.write(device, &self.vertex_buffer, vertex_offset, bytemuck::cast_slice(vertices));
+        uploads.write(device, &self.index_buffer, index_offset, bytemuck::cast_slice(&padded));
+        self.vertices[vertex_range.start as usize..vertex_range.end as usize].copy_from_slice(vertices);
+        self.indices[index_range.start as usize..index_range.end as usize].copy_from_slice(&padded);
+        MeshAllocation {
+            vertices: vertex_range,
+            indices: index_range,
+            num_indices,
+        }
+    }
+
+    /// A mesh's vertices and its indices, which are local to those vertices.
+    pub fn read(&self, mesh: &MeshAllocation) -> (&[Vertex], &[u16]) {
+        let vertices = &self.vertices[mesh.vertices.start as usize..mesh.vertices.end as usize];
+        let first = mesh.indices.start as usize;
+        (vertices, &self.indices[first..first + mesh.num_indices as usize])
+    }
+
+    /// Returns a mesh's ranges to the arena. Draws already recorded with it must not be replayed afterwards.
+    pub fn free(&mut self, allocation: &MeshAllocation) {
+        self.vertex_slots.free(allocation.vertices.clone());
+        self.index_slots.free(allocation.indices.clone());
+    }
+
+    /// Binds the shared buffers; any number of `draw` calls can follow.
+    pub fn bind<'a>(&'a self, encoder: &mut impl RenderEncoder<'a>) {
+        encoder.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        encoder.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+    }
+
+    pub fn draw<'a>(&'a self, encoder: &mut impl RenderEncoder<'a>, mesh: &MeshAllocation, instances: Range<u32>) {
+        let first = mesh.indices.start;
+        encoder.draw_indexed(first..first + mesh.num_indices, mesh.vertices.start as i32, instances);
+    }
+
+    /// Records `draw` into a bundle that can be replayed every frame in passes of `samples` samples per pixel.
+    /// The bundle refers to the current buffers and bakes in the dynamic offsets, so it must be re-recorded when
+    /// the generation changes, the mesh is freed or an offset changes.
+    pub fn record_bundle(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        samples: u32,
+        draw: &BundleDraw,
+    ) -> wgpu::RenderBundle {
+        let mut encoder = device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+            label: Some("Mesh Bundle Encoder"),
+            color_formats: &[Some(format)],
+            depth_stencil: Some(wgpu::RenderBundleDepthStencil {
+                format: DEPTH_FORMAT,
+                depth_read_only: false,
+                stencil_read_only: true,
+            }),
+            sample_count: samples,
+            multiview: None,
+        });
+        encoder.set_pipeline(draw.pipeline);
+        for (index, (bind_group, offset)) in draw.bind_groups.iter().enumerate() {
+            encoder.set_bind_group(index as u32, bind_group, offset.as_slice());
+        }
+        self.bind(&mut encoder);
+        self.draw(&mut encoder, draw.mesh, 0..1);
+        encoder.finish(&wgpu::RenderBundleDescriptor {
+            label: Some("Mesh Bundle"),
+        })
+    }
+
+    /// Records a bundle for each of `draws`, in order. A long list is split into runs recorded on threads of
+    /// their own, one per core, so a scene of thousands of meshes doesn't re-record them all on one.
+    pub fn record_bundles(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        samples: u32,
+        draws: &[BundleDraw],
+    ) -> Vec<wgpu::RenderBundle> {
+        let record = |draws: &[BundleDraw]| -> Vec<wgpu::RenderBundle> {
+            draws.iter().map(|draw| self.record_bundle(device, format, samples, draw)).collect()
+        };
+        // The browser has no threads to spawn, and its wgpu objects can't be sent to one
+        #[cfg(not(target_arch = "wasm32"))]
+        if draws.len() >= PARALLEL_BUNDLES {
+            let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+            let run = draws.len().div_ceil(threads).max(PARALLEL_BUNDLES / 2);
+            return std::thread::scope(|scope| {
+                let runs: Vec<_> = draws.chunks(run).map(|run| scope.spawn(move || record(run))).collect();
+                runs.into_iter().flat_map(|run| run.join().expect("Bundle recording thread panicked")).collect()
+            });
+        }
+        record(draws)
+    }
+}
+
+// A mesh ready to record into a bundle: the pipeline it's drawn with, and each bind group in order with its
+// dynamic offset if it takes one
+pub struct BundleDraw<'a> {
+    pub pipeline: &'a wgpu::RenderPipeline,
+    pub bind_groups: Vec<(&'a wgpu::BindGroup, Option<wgpu::DynamicOffset>)>,
+    pub mesh: &'a MeshAllocation,
+}
+
+// First-fit allocator over `0..capacity` slots, merging neighbouring free ranges as they are returned
+struct FreeList {
+    capacity: u32,
+    free: Vec<Range<u32>>, // Sorted by start, never adjacent to each other
+}
+
+impl FreeList {
+    fn new(capacity: u32) -> Self {
+        let mut list = Self {
+            capacity: 0,
+            free: Vec::new(),
+        };
+        list.grow(capacity);
+        list
+    }
+
+    fn allocate(&mut self, len: u32) -> Option<Range<u32>> {
+        if len == 0 {
+            return Some(0..0);
+        }
+        let index = self.free.iter().position(|range| range.len() as u32 >= len)?;
+        let range = &mut self.free[index];
+        let allocated = range.start..range.start + len;
+        range.start += len;
+        if range.start == range.end {
+            self.free.remove(index);
+        }
+        Some(allocated)
+    }
+
+    fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        let index = self.free.partition_point(|free| free.start < range.start);
+        self.free.insert(index, range);
+        // Merge with the following range, then with the preceding one
+        if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+            self.free[index].end = self.free.remove(index + 1).end;
+        }
+        if index > 0 && self.free[index - 1].end == self.free[index].start {
+            self.free[index - 1].end = self.free.remove(index).end;
+        }
+    }
+
+    fn grow(&mut self, capacity: u32) {
+        let added = self.capacity..capacity;
+        self.capacity = capacity;
+        self.free(added);
+    }
+}
+
+// At least double, so a scene loading many meshes grows the buffers a logarithmic number of times
+fn grown_capacity(capacity: u32, needed: u32) -> u32 {
+    (capacity * 2).max(capacity + needed)
+}
+
+fn vertex_bytes(count: u32) -> u64 {
+    count as u64 * size_of::<Vertex>() as u64
+}
+
+fn index_bytes(count: u32) -> u64 {
+    count as u64 * size_of::<u16>() as u64
+}
+
+// Moves the contents of `old` into a larger buffer on the GPU, ordered after writes already staged to it
+fn grow_buffer(
+    device: &wgpu::Device,
+    uploads: &mut Uploads,
+    stats: &mut RenderStats,
+    old: &wgpu::Buffer,
+    label: &str,
+    size: u64,
+) -> wgpu::Buffer {
+    let usage = old.usage() & (wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::INDEX);
+    let buffer = create_buffer(device, stats, label, usage, size);
+    uploads.copy(device, old, &buffer, old.size());
+    stats.buffer_destroyed(old);
+    buffer
+}
+
+fn create_buffer(
+    device: &wgpu::Device,
+    stats: &mut RenderStats,
+    label: &str,
+    usage: wgpu::BufferUsages,
+    size: u64,
+) -> wgpu::Buffer {
+    stats.create_buffer(
+        device,
+        &wgpu::BufferDescriptor {
+            label: Some(label),
+            // Rounded up to the copy alignment so any write that fits the contents fits the buffer
+            size: size.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT).max(wgpu::COPY_BUFFER_ALIGNMENT),
+            // COPY_SRC lets the contents move over when the buffer grows
+            usage: usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        },
+    )
+}
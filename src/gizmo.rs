@@ -0,0 +1,289 @@
+// gizmo.rs
+
+use crate::transform::Transform;
+use crate::viewport_panel;
+use egui::{pos2, Color32, Context, Id, LayerId, Order, Pos2, Rect, Stroke};
+use glam::{Mat4, Quat, Vec3};
+
+const AXIS_COLORS: [Color32; 3] = [
+    Color32::from_rgb(230, 70, 70),
+    Color32::from_rgb(80, 200, 80),
+    Color32::from_rgb(70, 120, 240),
+];
+const HOVER_COLOR: Color32 = Color32::from_rgb(255, 220, 60);
+const PICK_RADIUS: f32 = 8.0; // Pixels
+const SCREEN_SIZE: f32 = 0.15; // Handle length as a fraction of the camera distance
+const CIRCLE_SEGMENTS: usize = 48;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+impl GizmoMode {
+    pub const ALL: [GizmoMode; 3] = [GizmoMode::Translate, GizmoMode::Rotate, GizmoMode::Scale];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GizmoMode::Translate => "Translate",
+            GizmoMode::Rotate => "Rotate",
+            GizmoMode::Scale => "Scale",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GizmoSpace {
+    Local,
+    World,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct GizmoDrag {
+    axis: usize,
+    start: Transform,
+    start_pointer: Pos2,
+    size: f32, // Handle length when the drag began, so the mapping stays fixed while dragging
+}
+
+// Screen-space handles for manipulating a transform, drawn with the egui painter under all windows
+pub struct Gizmo {
+    pub enabled: bool,
+    pub mode: GizmoMode,
+    pub space: GizmoSpace,
+    pub snapping: bool,
+    pub translate_snap: f32,
+    pub rotate_snap: f32, // Degrees
+    pub scale_snap: f32,
+    drag: Option<GizmoDrag>,
+}
+
+impl Gizmo {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            mode: GizmoMode::Translate,
+            space: GizmoSpace::World,
+            snapping: false,
+            translate_snap: 0.25,
+            rotate_snap: 15.0,
+            scale_snap: 0.1,
+            drag: None,
+        }
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Show gizmo");
+        ui.horizontal(|ui| {
+            for mode in GizmoMode::ALL {
+                ui.radio_value(&mut self.mode, mode, mode.label());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.space, GizmoSpace::World, "World");
+            ui.radio_value(&mut self.space, GizmoSpace::Local, "Local");
+        });
+        ui.checkbox(&mut self.snapping, "Snap");
+        if self.snapping {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.translate_snap).speed(0.01).range(0.01..=10.0).prefix("move "));
+                ui.add(egui::DragValue::new(&mut self.rotate_snap).speed(1.0).range(1.0..=90.0).prefix("rot ").suffix("°"));
+                ui.add(egui::DragValue::new(&mut self.scale_snap).speed(0.01).range(0.01..=1.0).prefix("scale "));
+            });
+        }
+    }
+
+    /// Draws the handles for `transform` and applies any drag to it. Returns true if it changed.
+    pub fn show(&mut self, ctx: &Context, view_proj: Mat4, camera_position: Vec3, transform: &mut Transform) -> bool {
+        if !self.enabled {
+            self.drag = None;
+            return false;
+        }
+
+        let rect = viewport_panel::scene_rect(ctx);
+        let origin = transform.translation;
+        let Some(origin_screen) = project(view_proj, rect, origin) else {
+            return false;
+        };
+        let size = (camera_position - origin).length() * SCREEN_SIZE;
+        let axes = self.axes(transform);
+
+        let (pointer, pressed, down) =
+            ctx.input(|i| (i.pointer.hover_pos(), i.pointer.primary_pressed(), i.pointer.primary_down()));
+
+        // Hover testing ignores the pointer while it is over an egui window or panel
+        let hovered_axis = match (pointer, viewport_panel::pointer_over_ui(ctx)) {
+            (Some(p), false) => self.pick_axis(view_proj, rect, origin, &axes, size, p),
+            _ => None,
+        };
+
+        if let (true, Some(axis), Some(p)) = (pressed, hovered_axis, pointer) {
+            self.drag = Some(GizmoDrag {
+                axis,
+                start: *transform,
+                start_pointer: p,
+                size,
+            });
+        } else if !down {
+            self.drag = None;
+        }
+
+        let mut changed = false;
+        if let (Some(drag), Some(p)) = (self.drag, pointer) {
+            let updated = self.apply_drag(&drag, view_proj, rect, camera_position, p);
+            changed = updated != *transform;
+            *transform = updated;
+        }
+
+        let active_axis = self.drag.map(|d| d.axis).or(hovered_axis);
+        let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("gizmo")));
+        for (i, axis) in axes.iter().enumerate() {
+            let color = if active_axis == Some(i) { HOVER_COLOR } else { AXIS_COLORS[i] };
+            let stroke = Stroke::new(3.0, color);
+            match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let Some(end) = project(view_proj, rect, origin + *axis * size) else {
+                        continue;
+                    };
+                    painter.line_segment([origin_screen, end], stroke);
+                    if self.mode == GizmoMode::Translate {
+                        painter.circle_filled(end, 5.0, color);
+                    } else {
+                        painter.rect_filled(Rect::from_center_size(end, egui::vec2(9.0, 9.0)), 0.0, color);
+                    }
+                }
+                GizmoMode::Rotate => {
+                    let points = circle_points(view_proj, rect, origin, *axis, size);
+                    painter.add(egui::Shape::line(points, stroke));
+                }
+            }
+        }
+
+        changed
+    }
+
+    fn axes(&self, transform: &Transform) -> [Vec3; 3] {
+        match (self.space, self.mode) {
+            // Scaling always happens along the object's own axes
+            (GizmoSpace::Local, _) | (_, GizmoMode::Scale) => {
+                [transform.rotation * Vec3::X, transform.rotation * Vec3::Y, transform.rotation * Vec3::Z]
+            }
+            (GizmoSpace::World, _) => [Vec3::X, Vec3::Y, Vec3::Z],
+        }
+    }
+
+    fn pick_axis(&self, view_proj: Mat4, rect: Rect, origin: Vec3, axes: &[Vec3; 3], size: f32, pointer: Pos2) -> Option<usize> {
+        let origin_screen = project(view_proj, rect, origin)?;
+        axes.iter()
+            .enumerate()
+            .filter_map(|(i, axis)| {
+                let distance = match self.mode {
+                    GizmoMode::Translate | GizmoMode::Scale => {
+                        let end = project(view_proj, rect, origin + *axis * size)?;
+                        distance_to_segment(pointer, origin_screen, end)
+                    }
+                    GizmoMode::Rotate => circle_points(view_proj, rect, origin, *axis, size)
+                        .windows(2)
+                        .map(|w| distance_to_segment(pointer, w[0], w[1]))
+                        .fold(f32::INFINITY, f32::min),
+                };
+                (distance < PICK_RADIUS).then_some((i, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    fn apply_drag(&self, drag: &GizmoDrag, view_proj: Mat4, rect: Rect, camera_position: Vec3, pointer: Pos2) -> Transform {
+        let start = drag.start;
+        let size = drag.size;
+        let axis = self.axes(&start)[drag.axis];
+        let pointer_delta = pointer - drag.start_pointer;
+        let mut result = start;
+
+        let (Some(origin_screen), Some(axis_screen)) = (
+            project(view_proj, rect, start.translation),
+            project(view_proj, rect, start.translation + axis * size),
+        ) else {
+            return result;
+        };
+        let screen_axis = axis_screen - origin_screen;
+
+        match self.mode {
+            GizmoMode::Translate => {
+                // Pointer movement projected onto the axis as drawn, converted back to world units
+                let amount = pointer_delta.dot(screen_axis) / screen_axis.length_sq().max(1e-6) * size;
+                let amount = self.snap(amount, self.translate_snap);
+                result.translation = start.translation + axis * amount;
+            }
+            GizmoMode::Scale => {
+                let amount = pointer_delta.dot(screen_axis) / screen_axis.length_sq().max(1e-6);
+                let factor = (1.0 + self.snap(amount, self.scale_snap)).max(0.01);
+                result.scale[drag.axis] = start.scale[drag.axis] * factor;
+            }
+            GizmoMode::Rotate => {
+                let from = drag.start_pointer - origin_screen;
+                let to = pointer - origin_screen;
+                let mut angle = (from.x * to.y - from.y * to.x).atan2(from.dot(to));
+                // Screen Y points down, and the rotation sense flips when the axis faces away
+                if axis.dot(camera_position - start.translation) > 0.0 {
+                    angle = -angle;
+                }
+                let angle = self.snap(angle.to_degrees(), self.rotate_snap).to_radians();
+                result.rotation = (Quat::from_axis_angle(axis, angle) * start.rotation).normalize();
+            }
+        }
+
+        result
+    }
+
+    fn snap(&self, value: f32, step: f32) -> f32 {
+        if self.snapping && step > 0.0 {
+            (value / step).round() * step
+        } else {
+            value
+        }
+    }
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// World position to egui screen position, None when behind the camera
+pub fn project(view_proj: Mat4, rect: Rect, point: Vec3) -> Option<Pos2> {
+    let clip = view_proj * point.extend(1.0);
+    if clip.w <= 1e-5 {
+        return None;
+    }
+    let ndc = clip.truncate() / clip.w;
+    Some(pos2(
+        rect.left() + (ndc.x + 1.0) * 0.5 * rect.width(),
+        rect.top() + (1.0 - ndc.y) * 0.5 * rect.height(),
+    ))
+}
+
+fn circle_points(view_proj: Mat4, rect: Rect, center: Vec3, axis: Vec3, radius: f32) -> Vec<Pos2> {
+    let tangent = axis.any_orthonormal_vector();
+    let bitangent = axis.cross(tangent);
+    (0..=CIRCLE_SEGMENTS)
+        .filter_map(|i| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            let offset = (tangent * angle.cos() + bitangent * angle.sin()) * radius;
+            project(view_proj, rect, center + offset)
+        })
+        .collect()
+}
+
+fn distance_to_segment(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_sq().max(1e-6)).clamp(0.0, 1.0);
+    (a + ab * t).distance(p)
+}
@@ -0,0 +1,265 @@
+// gltf_export.rs
+
+use crate::camera::Camera;
+use crate::geometry::{GeometryArena, MeshAllocation};
+use crate::material::{MaterialId, Materials};
+use crate::world::{GlobalTransform, Hidden, Light, LightKind, MaterialHandle, MeshHandle};
+use glam::{Mat4, Vec3};
+use hecs::World;
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// glTF's component types and buffer view targets
+const FLOAT: u32 = 5126;
+const UNSIGNED_SHORT: u32 = 5123;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// The GLB container's magic, version and chunk types
+const GLB_MAGIC: u32 = 0x4654_6C67; // "glTF"
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+// The binary chunk and the views and accessors into it, built up as meshes are added
+#[derive(Default)]
+struct Buffers {
+    bin: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl Buffers {
+    // Appends `bytes` as a buffer view, 4-byte aligned as every accessor type here needs
+    fn view(&mut self, bytes: &[u8], target: u32) -> usize {
+        let offset = self.bin.len();
+        self.bin.extend_from_slice(bytes);
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.views.len() - 1
+    }
+
+    fn accessor(&mut self, accessor: Value) -> usize {
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+}
+
+/// Writes the visible meshes with their world transforms and materials, the enabled lights and the camera to
+/// a binary glTF at `path`. Hierarchies are flattened, and lights use KHR_lights_punctual.
+pub fn export_glb(
+    path: &Path,
+    world: &World,
+    geometry: &GeometryArena,
+    materials: &Materials,
+    camera: &Camera,
+) -> io::Result<()> {
+    let mut buffers = Buffers::default();
+    let mut nodes = Vec::new();
+    let mut meshes: Vec<(MeshAllocation, MaterialId)> = Vec::new(); // Exported once however many nodes use them
+    let mut gltf_meshes = Vec::new();
+    let mut gltf_materials = Vec::new();
+    let mut material_ids: Vec<MaterialId> = Vec::new();
+
+    let query = world.query::<(&MeshHandle, &GlobalTransform, Option<&MaterialHandle>)>();
+    for (mesh, global, material) in query.without::<&Hidden>().iter() {
+        let material = material.map_or(MaterialId::DEFAULT, |material| material.0);
+        let key = (mesh.allocation.clone(), material);
+        let index = match meshes.iter().position(|existing| *existing == key) {
+            Some(index) => index,
+            None => {
+                let material_index = match material_ids.iter().position(|id| *id == material) {
+                    Some(index) => index,
+                    None => {
+                        gltf_materials.push(material_json(materials, material));
+                        material_ids.push(material);
+                        material_ids.len() - 1
+                    }
+                };
+                gltf_meshes.push(mesh_json(&mut buffers, geometry, &mesh.allocation, material_index));
+                meshes.push(key);
+                meshes.len() - 1
+            }
+        };
+        nodes.push(node_json(global.0, json!({ "mesh": index })));
+    }
+
+    let mut lights = Vec::new();
+    for (light, global) in world.query::<(&Light, &GlobalTransform)>().iter() {
+        if !light.enabled {
+            continue;
+        }
+        nodes.push(node_json(global.0, json!({ "extensions": { "KHR_lights_punctual": { "light": lights.len() } } })));
+        lights.push(light_json(light));
+    }
+
+    // glTF cameras look down their node's -Z, as the view matrix's inverse places them
+    nodes.push(node_json(camera.view_matrix().inverse(), json!({ "camera": 0 })));
+    let cameras = [if camera.projection.is_orthographic() {
+        json!({
+            "type": "orthographic",
+            "orthographic": {
+                "xmag": camera.ortho_height * camera.aspect * 0.5,
+                "ymag": camera.ortho_height * 0.5,
+                "znear": 0.0,
+                "zfar": camera.zfar,
+            },
+        })
+    } else {
+        json!({
+            "type": "perspective",
+            "perspective": {
+                "yfov": camera.fovy.to_radians(),
+                "aspectRatio": camera.aspect,
+                "znear": camera.znear,
+                "zfar": camera.zfar,
+            },
+        })
+    }];
+
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "winit_egui_wgpu" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "cameras": cameras,
+    });
+    // glTF doesn't allow empty arrays, so those with nothing to hold are left out
+    for (name, items) in [
+        ("meshes", gltf_meshes),
+        ("materials", gltf_materials),
+        ("accessors", buffers.accessors),
+        ("bufferViews", buffers.views),
+    ] {
+        if !items.is_empty() {
+            document[name] = Value::Array(items);
+        }
+    }
+    if !buffers.bin.is_empty() {
+        document["buffers"] = json!([{ "byteLength": buffers.bin.len() }]);
+    }
+    if !lights.is_empty() {
+        document["extensionsUsed"] = json!(["KHR_lights_punctual"]);
+        document["extensions"] = json!({ "KHR_lights_punctual": { "lights": lights } });
+    }
+    fs::write(path, glb(&document, &buffers.bin)?)
+}
+
+fn node_json(matrix: Mat4, mut node: Value) -> Value {
+    let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+    node["translation"] = json!(translation.to_array());
+    node["rotation"] = json!(rotation.normalize().to_array());
+    node["scale"] = json!(scale.to_array());
+    node
+}
+
+fn mesh_json(buffers: &mut Buffers, geometry: &GeometryArena, mesh: &MeshAllocation, material: usize) -> Value {
+    let (vertices, indices) = geometry.read(mesh);
+    let positions: Vec<[f32; 3]> = vertices.iter().map(|vertex| vertex.position).collect();
+    let colors: Vec<[f32; 3]> = vertices.iter().map(|vertex| vertex.color).collect();
+    // POSITION accessors must say their bounds
+    let (min, max) = positions.iter().fold((Vec3::MAX, Vec3::MIN), |(min, max), position| {
+        (min.min(Vec3::from(*position)), max.max(Vec3::from(*position)))
+    });
+
+    let view = buffers.view(bytemuck::cast_slice(&positions), ARRAY_BUFFER);
+    let position = buffers.accessor(json!({
+        "bufferView": view,
+        "componentType": FLOAT,
+        "count": positions.len(),
+        "type": "VEC3",
+        "min": min.to_array(),
+        "max": max.to_array(),
+    }));
+    let view = buffers.view(bytemuck::cast_slice(&colors), ARRAY_BUFFER);
+    let color = buffers.accessor(json!({
+        "bufferView": view,
+        "componentType": FLOAT,
+        "count": colors.len(),
+        "type": "VEC3",
+    }));
+    let view = buffers.view(bytemuck::cast_slice(indices), ELEMENT_ARRAY_BUFFER);
+    let index = buffers.accessor(json!({
+        "bufferView": view,
+        "componentType": UNSIGNED_SHORT,
+        "count": indices.len(),
+        "type": "SCALAR",
+    }));
+    // No normals are stored; glTF readers fall back to flat shading, as the scene shader draws
+    json!({
+        "primitives": [{
+            "attributes": { "POSITION": position, "COLOR_0": color },
+            "indices": index,
+            "material": material,
+        }],
+    })
+}
+
+// The scene shader's tint and brightness as the base color, and its reflectivity as smoothness
+fn material_json(materials: &Materials, id: MaterialId) -> Value {
+    let param = |name: &str, default: &[f32]| materials.param(id, name).unwrap_or_else(|| default.to_vec());
+    let tint = param("tint", &[1.0, 1.0, 1.0]);
+    let brightness = param("brightness", &[1.0])[0];
+    let reflectivity = param("reflectivity", &[0.0])[0];
+    let base = Vec3::new(tint[0], tint[1], tint[2]) * brightness;
+    json!({
+        "name": materials.get(id).name,
+        "pbrMetallicRoughness": {
+            "baseColorFactor": base.clamp(Vec3::ZERO, Vec3::ONE).extend(1.0).to_array(),
+            "metallicFactor": 0.0,
+            "roughnessFactor": (1.0 - reflectivity).clamp(0.0, 1.0),
+        },
+    })
+}
+
+fn light_json(light: &Light) -> Value {
+    let mut json = json!({
+        "color": light.color.to_array(),
+        "intensity": light.intensity,
+    });
+    match light.kind {
+        LightKind::Directional => json["type"] = json!("directional"),
+        LightKind::Point { range } => {
+            json["type"] = json!("point");
+            json["range"] = json!(range);
+        }
+        LightKind::Spot {
+            range,
+            inner_angle,
+            outer_angle,
+        } => {
+            json["type"] = json!("spot");
+            json["range"] = json!(range);
+            json["spot"] = json!({ "innerConeAngle": inner_angle, "outerConeAngle": outer_angle });
+        }
+    }
+    json
+}
+
+// The GLB container: a header, then the JSON chunk padded with spaces and the binary chunk, if there is
+// anything for it to hold, padded with zeros
+fn glb(document: &Value, bin: &[u8]) -> io::Result<Vec<u8>> {
+    let mut json = serde_json::to_vec(document)?;
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let bin_chunk = if bin.is_empty() { 0 } else { 8 + bin.len() };
+    let length = 12 + 8 + json.len() + bin_chunk;
+    let mut glb = Vec::with_capacity(length);
+    for word in [GLB_MAGIC, GLB_VERSION, length as u32, json.len() as u32, CHUNK_JSON] {
+        glb.extend_from_slice(&word.to_le_bytes());
+    }
+    glb.extend_from_slice(&json);
+    if !bin.is_empty() {
+        for word in [bin.len() as u32, CHUNK_BIN] {
+            glb.extend_from_slice(&word.to_le_bytes());
+        }
+        glb.extend_from_slice(bin);
+    }
+    Ok(glb)
+}
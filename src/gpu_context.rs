@@ -0,0 +1,204 @@
+// gpu_context.rs
+
+use crate::capabilities::Capabilities;
+use crate::settings;
+use crate::xr::{XrDevice, XrSession};
+use egui_wgpu::wgpu;
+use std::sync::Arc;
+use winit::window::Window;
+
+// Surface formats in order of preference. The shaders write linear color, so an sRGB format is what makes
+// it display right; past these, any other sRGB format the surface offers is taken before a linear one
+const PREFERRED_SURFACE_FORMATS: [wgpu::TextureFormat; 2] =
+    [wgpu::TextureFormat::Bgra8UnormSrgb, wgpu::TextureFormat::Rgba8UnormSrgb];
+
+/// Which adapter to create the device on. The `WGPU_BACKEND`, `WGPU_POWER_PREF` and `WGPU_ADAPTER_NAME`
+/// environment variables take precedence over what the app asks for, as in wgpu's own examples.
+#[derive(Debug, Clone)]
+pub struct AdapterRequest {
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    pub name: Option<String>, // Part of the adapter's name, ignoring case; the power preference decides otherwise
+}
+
+impl AdapterRequest {
+    pub fn with_env(mut self) -> Self {
+        if let Some(backends) = wgpu::util::backend_bits_from_env() {
+            self.backends = backends;
+        }
+        if let Some(power_preference) = wgpu::util::power_preference_from_env() {
+            self.power_preference = power_preference;
+        }
+        if let Some(name) = std::env::var("WGPU_ADAPTER_NAME").ok().filter(|name| !name.is_empty()) {
+            self.name = Some(name);
+        }
+        self
+    }
+
+    // The first adapter named like the request that can present to `surface`. Browsers hand out a single
+    // adapter, so there the power preference always decides
+    #[cfg(not(target_arch = "wasm32"))]
+    fn named(&self, instance: &wgpu::Instance, surface: &wgpu::Surface) -> Option<wgpu::Adapter> {
+        let name = self.name.as_ref()?.to_lowercase();
+        let found = instance.enumerate_adapters(self.backends).into_iter().find(|adapter| {
+            adapter.get_info().name.to_lowercase().contains(&name) && adapter.is_surface_supported(surface)
+        });
+        if found.is_none() {
+            log::warn!("No adapter named like {name:?} can draw to the window; picking one by power preference");
+        }
+        found
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn named(&self, _instance: &wgpu::Instance, _surface: &wgpu::Surface) -> Option<wgpu::Adapter> {
+        None
+    }
+}
+
+/// Every adapter on every backend this build can use, whether or not it could draw to the window, for picking
+/// another one to restart on. Empty in a browser, which only hands out the one it picks.
+pub fn all_adapters() -> Vec<wgpu::AdapterInfo> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        instance.enumerate_adapters(wgpu::Backends::all()).iter().map(wgpu::Adapter::get_info).collect()
+    }
+    #[cfg(target_arch = "wasm32")]
+    Vec::new()
+}
+
+// A window's surface and the device drawing into it, with what the adapter turned out to support
+pub struct GpuContext {
+    pub instance: wgpu::Instance,
+    pub surface: wgpu::Surface<'static>,
+    pub adapter: wgpu::Adapter,
+    pub device: Arc<wgpu::Device>, // Shared with the pipeline compile workers
+    pub queue: wgpu::Queue,
+    pub config: wgpu::SurfaceConfiguration,
+    pub capabilities: Capabilities,
+    pub xr: XrSession, // Running only when the device was created for a headset
+}
+
+impl GpuContext {
+    /// Creates a device for `window` on the adapter `request` picks and configures its surface at `width` by
+    /// `height` with `present_mode`, or FIFO where the surface doesn't support it. With `xr`, the OpenXR runtime
+    /// picks the device, falling back to the desktop without a headset. Panics when no adapter can present to the
+    /// window.
+    pub async fn new(
+        window: Arc<Window>,
+        width: u32,
+        height: u32,
+        present_mode: wgpu::PresentMode,
+        request: &AdapterRequest,
+        xr: bool,
+    ) -> Self {
+        let headset = xr.then(XrSession::create_device).and_then(|created| {
+            created.map_err(|err| log::warn!("VR unavailable, drawing to the window: {err}")).ok()
+        });
+        let (instance, surface, adapter, device, queue, capabilities, xr) = match headset {
+            Some(XrDevice {
+                instance,
+                adapter,
+                device,
+                queue,
+                capabilities,
+                session,
+            }) => {
+                let surface = instance.create_surface(window).expect("Failed to create surface!");
+                assert!(adapter.is_surface_supported(&surface), "The headset's GPU can't present to the window");
+                (instance, surface, adapter, device, queue, capabilities, session)
+            }
+            None => {
+                let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+                    backends: request.backends,
+                    ..Default::default()
+                });
+                let surface = instance.create_surface(window).expect("Failed to create surface!");
+                let adapter = match request.named(&instance, &surface) {
+                    Some(adapter) => adapter,
+                    None => instance
+                        .request_adapter(&wgpu::RequestAdapterOptions {
+                            power_preference: request.power_preference,
+                            force_fallback_adapter: false,
+                            compatible_surface: Some(&surface),
+                        })
+                        .await
+                        .expect("Failed to find an appropriate adapter"),
+                };
+                let info = adapter.get_info();
+                log::info!("Drawing with {} on {:?}", info.name, info.backend);
+
+                let capabilities = Capabilities::negotiate(&adapter);
+                let (device, queue) = adapter
+                    .request_device(&capabilities.device_descriptor(), None)
+                    .await
+                    .expect("Failed to create device");
+                (instance, surface, adapter, device, queue, capabilities, XrSession::new())
+            }
+        };
+
+        let swapchain_capabilities = surface.get_capabilities(&adapter);
+        let format = select_surface_format(&swapchain_capabilities.formats);
+        if !format.is_srgb() {
+            log::warn!("No sRGB surface format available, colors will look washed out: using {format:?}");
+        }
+        let present_mode = select_present_mode(&swapchain_capabilities.present_modes, present_mode);
+        // Copying out of the swapchain is what makes Copy Screenshot possible, where supported
+        let screenshot_usage = swapchain_capabilities.usages & wgpu::TextureUsages::COPY_SRC;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | screenshot_usage,
+            format,
+            width,
+            height,
+            present_mode,
+            desired_maximum_frame_latency: settings::DEFAULT_FRAME_LATENCY,
+            alpha_mode: swapchain_capabilities.alpha_modes[0],
+            view_formats: vec![],
+        };
+        surface.configure(&device, &config);
+
+        Self {
+            instance,
+            surface,
+            adapter,
+            device: Arc::new(device),
+            queue,
+            config,
+            capabilities,
+            xr,
+        }
+    }
+
+    /// Configures the surface again at `width` by `height`, ignoring zero sizes such as minimized windows.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.config.width = width;
+        self.config.height = height;
+        self.surface.configure(&self.device, &self.config);
+    }
+}
+
+/// Picks the first of the preferred formats `formats` offers, then any sRGB one, then whatever comes first.
+pub fn select_surface_format(formats: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
+    PREFERRED_SURFACE_FORMATS
+        .into_iter()
+        .find(|preferred| formats.contains(preferred))
+        .or_else(|| formats.iter().copied().find(wgpu::TextureFormat::is_srgb))
+        .or_else(|| formats.first().copied())
+        .expect("The surface supports no formats; the adapter can't present to it")
+}
+
+/// `requested` if the surface supports it, otherwise FIFO, which every surface does. The automatic modes are
+/// always supported, as wgpu picks among the supported ones for them.
+pub fn select_present_mode(supported: &[wgpu::PresentMode], requested: wgpu::PresentMode) -> wgpu::PresentMode {
+    match requested {
+        wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => requested,
+        mode if supported.contains(&mode) => mode,
+        mode => {
+            log::warn!("Present mode {mode:?} is not supported, using Fifo");
+            wgpu::PresentMode::Fifo
+        }
+    }
+}
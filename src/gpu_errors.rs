@@ -0,0 +1,98 @@
+// gpu_errors.rs
+
+use crate::toasts::Toasts;
+use egui_wgpu::wgpu;
+use std::sync::{Arc, Mutex};
+
+// Reports wgpu validation errors in the console and as toasts instead of through wgpu's default handler,
+// which panics. An error repeated every frame is reported once, until a different one comes along. Also
+// notices the device being lost, as when the driver resets
+pub struct GpuErrors {
+    uncaptured: Arc<Mutex<Vec<String>>>, // Filled from whichever thread wgpu raises the error on
+    lost: Arc<Mutex<Option<String>>>,    // Likewise
+    last_reported: Option<String>,
+}
+
+impl GpuErrors {
+    pub fn install(device: &wgpu::Device) -> Self {
+        let uncaptured = Arc::new(Mutex::new(Vec::new()));
+        let queue = Arc::clone(&uncaptured);
+        device.on_uncaptured_error(Box::new(move |error| {
+            queue.lock().unwrap_or_else(|e| e.into_inner()).push(summarize(&error));
+        }));
+        let lost = Arc::new(Mutex::new(None));
+        let reason = Arc::clone(&lost);
+        device.set_device_lost_callback(move |kind, message| {
+            // Dropping or destroying the device on purpose, as on exit, reports it lost too
+            if let wgpu::DeviceLostReason::Unknown | wgpu::DeviceLostReason::DeviceInvalid = kind {
+                *reason.lock().unwrap_or_else(|e| e.into_inner()) = Some(format!("{kind:?}: {message}"));
+            }
+        });
+        Self {
+            uncaptured,
+            lost,
+            last_reported: None,
+        }
+    }
+
+    /// Why the device was lost, if it was since the last call. Nothing drawn with it will show from then on.
+    pub fn take_device_lost(&mut self) -> Option<String> {
+        self.lost.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    /// Reports errors raised outside any error scope since the last call.
+    pub fn report_uncaptured(&mut self, toasts: &mut Toasts) {
+        let errors = std::mem::take(&mut *self.uncaptured.lock().unwrap_or_else(|e| e.into_inner()));
+        for error in errors {
+            self.report("GPU", error, toasts);
+        }
+    }
+
+    /// Pops a scope opened with `device.push_error_scope(wgpu::ErrorFilter::Validation)` and reports what
+    /// it caught under `context`. Returns true if the scope caught an error.
+    pub fn pop_scope(&mut self, device: &wgpu::Device, context: &str, toasts: &mut Toasts) -> bool {
+        // wgpu-core validates as calls are made, so this resolves without waiting on the GPU
+        #[cfg(not(target_arch = "wasm32"))]
+        let caught = pollster::block_on(device.pop_error_scope());
+        // WebGPU answers asynchronously, and the browser's one thread can't block on it. What the scope
+        // caught is reported with the uncaptured errors instead, and the caller carries on as if nothing was
+        #[cfg(target_arch = "wasm32")]
+        let caught = {
+            let (queue, scope, context) = (Arc::clone(&self.uncaptured), device.pop_error_scope(), context.to_string());
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Some(error) = scope.await {
+                    let summary = format!("{context}: {}", summarize(&error));
+                    queue.lock().unwrap_or_else(|e| e.into_inner()).push(summary);
+                }
+            });
+            None
+        };
+        match caught {
+            Some(error) => {
+                self.report(context, summarize(&error), toasts);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn report(&mut self, context: &str, summary: String, toasts: &mut Toasts) {
+        if self.last_reported.as_ref() == Some(&summary) {
+            return;
+        }
+        log::error!("{context} error: {summary}");
+        toasts.error(format!("{context} error: {summary}"));
+        self.last_reported = Some(summary);
+    }
+}
+
+// One line from wgpu's multi-line report, keeping the "In ..., label = '...'" context and the cause
+fn summarize(error: &wgpu::Error) -> String {
+    error
+        .to_string()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && *line != "Caused by:" && *line != "Validation Error")
+        .collect::<Vec<_>>()
+        .join(": ")
+}
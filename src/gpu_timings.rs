@@ -0,0 +1,181 @@
+// gpu_timings.rs
+
+use egui::Context;
+use egui_wgpu::wgpu;
+use puffin::{GlobalProfiler, NanoSecond, ScopeDetails, ScopeId, StreamInfo, ThreadInfo};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use wgpu_profiler::{GpuProfiler, GpuProfilerSettings, GpuTimerQueryResult};
+
+// Weight of the newest frame in the smoothed times the GPU Profiler window shows
+const SMOOTHING: f64 = 0.1;
+
+// GPU time spent in each pass and encoder scope, measured with timer queries and read back a few frames
+// later. Finished frames go to the puffin profiler on a track of their own and can be exported as a chrome trace.
+pub struct GpuTimings {
+    pub profiler: GpuProfiler,
+    scope_ids: HashMap<String, ScopeId>, // Registered with puffin once per label
+    latest: Vec<GpuTimerQueryResult>,
+    smoothed: HashMap<String, f64>, // Milliseconds per scope, keyed by the labels of the scope and its parents
+}
+
+impl GpuTimings {
+    pub fn new() -> Self {
+        let profiler = GpuProfiler::new(GpuProfilerSettings::default()).expect("Default GPU profiler settings");
+        Self {
+            profiler,
+            scope_ids: HashMap::new(),
+            latest: Vec::new(),
+            smoothed: HashMap::new(),
+        }
+    }
+
+    /// Call once the frame's encoders, each with its queries resolved, have been submitted.
+    pub fn end_frame(&mut self, queue: &wgpu::Queue) {
+        if let Err(err) = self.profiler.end_frame() {
+            log::warn!("GPU profiler frame failed: {err}");
+        }
+        if let Some(results) = self.profiler.process_finished_frame(queue.get_timestamp_period()) {
+            if puffin::are_scopes_on() {
+                self.report_to_puffin(&results);
+            }
+            self.smooth(&results, "");
+            self.latest = results;
+        }
+    }
+
+    /// GPU time of the most recent finished frame, None without timestamp query support.
+    pub fn frame_time(&self) -> Option<Duration> {
+        let times = self.latest.iter().filter_map(|result| result.time.as_ref());
+        let seconds = times.map(|time| time.end - time.start).reduce(|a, b| a + b)?;
+        Some(Duration::from_secs_f64(seconds.max(0.0)))
+    }
+
+    /// Writes the most recent finished frame to `path`, viewable in chrome://tracing or Perfetto.
+    pub fn export_chrome_trace(&self, path: &Path) -> io::Result<()> {
+        if !self.latest.iter().any(|result| result.time.is_some()) {
+            return Err(io::Error::other("no GPU timings yet; the device may not support timestamp queries"));
+        }
+        wgpu_profiler::chrometrace::write_chrometrace(path, &self.latest)
+    }
+
+    /// The GPU Profiler window: each pass and encoder scope of the latest finished frame, with its smoothed
+    /// time and share of the frame. `features` are the device's, which timer queries need.
+    pub fn show(&self, ctx: &Context, features: wgpu::Features) {
+        egui::Window::new("GPU Profiler")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                if !features.contains(wgpu::Features::TIMESTAMP_QUERY) {
+                    ui.weak("This GPU doesn't support timestamp queries, so passes can't be timed");
+                    return;
+                }
+                let Some(frame) = self.frame_time() else {
+                    ui.weak("Waiting for the first frame's timings");
+                    return;
+                };
+                let total: f64 = self.latest.iter().filter_map(|result| self.smoothed.get(&key("", result))).sum();
+                ui.label(format!("GPU frame: {:.2} ms", frame.as_secs_f64() * 1000.0));
+                self.scopes_ui(ui, &self.latest, "", total);
+            });
+    }
+
+    fn scopes_ui(&self, ui: &mut egui::Ui, results: &[GpuTimerQueryResult], parent: &str, total: f64) {
+        for result in results {
+            let key = key(parent, result);
+            let Some(&ms) = self.smoothed.get(&key) else {
+                continue;
+            };
+            let text = format!("{}: {ms:.3} ms", result.label);
+            let bar = egui::ProgressBar::new((ms / total.max(f64::EPSILON)) as f32).desired_width(60.0);
+            if result.nested_queries.is_empty() {
+                ui.horizontal(|ui| {
+                    ui.add(bar);
+                    ui.label(text);
+                });
+            } else {
+                egui::CollapsingHeader::new(text).id_source(&key).show(ui, |ui| {
+                    ui.add(bar);
+                    self.scopes_ui(ui, &result.nested_queries, &key, total);
+                });
+            }
+        }
+    }
+
+    fn smooth(&mut self, results: &[GpuTimerQueryResult], parent: &str) {
+        for result in results {
+            let Some(time) = &result.time else {
+                continue;
+            };
+            let key = key(parent, result);
+            let ms = (time.end - time.start) * 1000.0;
+            let smoothed = self.smoothed.entry(key.clone()).or_insert(ms);
+            *smoothed += (ms - *smoothed) * SMOOTHING;
+            self.smooth(&result.nested_queries, &key);
+        }
+    }
+
+    fn report_to_puffin(&mut self, results: &[GpuTimerQueryResult]) {
+        let times = results.iter().filter_map(|result| result.time.as_ref());
+        let Some(end) = times.map(|time| time.end).reduce(f64::max) else {
+            return;
+        };
+        // GPU timestamps have their own epoch, so the frame is placed to end now: durations and nesting are
+        // exact, the offset against the CPU scopes is not
+        let offset = puffin::now_ns() - seconds_to_ns(end);
+        let mut profiler = GlobalProfiler::lock();
+        let mut stream_info = StreamInfo {
+            range_ns: (NanoSecond::MAX, NanoSecond::MIN),
+            ..Default::default()
+        };
+        self.write_scopes(&mut profiler, &mut stream_info, results, offset, 1);
+        let info = ThreadInfo {
+            start_time_ns: None,
+            name: "GPU".to_string(),
+        };
+        profiler.report_user_scopes(info, &stream_info.as_stream_into_ref());
+    }
+
+    fn write_scopes(
+        &mut self,
+        profiler: &mut GlobalProfiler,
+        stream_info: &mut StreamInfo,
+        results: &[GpuTimerQueryResult],
+        offset: NanoSecond,
+        depth: usize,
+    ) {
+        for result in results {
+            let Some(time) = &result.time else {
+                continue;
+            };
+            let id = *self.scope_ids.entry(result.label.clone()).or_insert_with(|| {
+                profiler.register_user_scopes(&[ScopeDetails::from_scope_name(result.label.clone())])[0]
+            });
+            let (start, end) = (offset + seconds_to_ns(time.start), offset + seconds_to_ns(time.end));
+            stream_info.depth = stream_info.depth.max(depth);
+            stream_info.num_scopes += 1;
+            stream_info.range_ns = (stream_info.range_ns.0.min(start), stream_info.range_ns.1.max(end));
+            let (scope, _) = stream_info.stream.begin_scope(|| start, id, "");
+            self.write_scopes(profiler, stream_info, &result.nested_queries, offset, depth + 1);
+            stream_info.stream.end_scope(scope, end);
+        }
+    }
+}
+
+impl Default for GpuTimings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Where a scope's smoothed time is kept: its label under those of its parents
+fn key(parent: &str, result: &GpuTimerQueryResult) -> String {
+    format!("{parent}/{}", result.label)
+}
+
+fn seconds_to_ns(seconds: f64) -> NanoSecond {
+    (seconds * 1e9) as NanoSecond
+}
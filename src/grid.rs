@@ -0,0 +1,226 @@
+// grid.rs
+
+use crate::camera::Camera;
+use crate::depth::DepthConfig;
+use crate::stats::RenderStats;
+use crate::upload::Uploads;
+use bytemuck::{Pod, Zeroable};
+use egui::{Align2, Color32, Context, FontId, Id, LayerId, Order, Stroke};
+use egui_wgpu::wgpu;
+use glam::Vec3;
+
+const AXIS_COLORS: [Color32; 3] = [
+    Color32::from_rgb(230, 70, 70),
+    Color32::from_rgb(80, 200, 80),
+    Color32::from_rgb(70, 120, 240),
+];
+const INDICATOR_RADIUS: f32 = 36.0; // Pixels from the indicator's center to the tip of an axis
+const INDICATOR_MARGIN: f32 = 16.0;
+
+// Laid out like `Grid` in grid.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct GridUniform {
+    view_proj: [[f32; 4]; 4],
+    inverse_view_proj: [[f32; 4]; 4],
+    camera_position: [f32; 3],
+    fade_distance: f32,
+    spacing: f32,
+    major_every: f32,
+    _padding: [f32; 2],
+}
+
+// What the ground grid and the axis indicator show; owned by the UI, read by the renderer each frame
+#[derive(Debug, Clone)]
+pub struct GridSettings {
+    pub enabled: bool,
+    pub axes: bool,         // The XYZ indicator in the corner of the viewport
+    pub spacing: f32,       // World units between minor lines
+    pub major_every: u32,   // Minor cells between major lines
+    pub fade_distance: f32, // The grid is gone past this distance from the camera
+}
+
+impl GridSettings {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            axes: true,
+            spacing: 1.0,
+            major_every: 10,
+            fade_distance: 60.0,
+        }
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.spacing, 0.05..=10.0).logarithmic(true).text("Grid spacing"));
+        ui.add(egui::Slider::new(&mut self.major_every, 2..=20).text("Cells per major line"));
+        ui.add(egui::Slider::new(&mut self.fade_distance, 5.0..=500.0).logarithmic(true).text("Grid fade distance"));
+    }
+
+    /// Paints the axis indicator in the bottom-left corner of the space the panels leave free, turned like the
+    /// world seen through `camera`.
+    pub fn show_axes(&self, ctx: &Context, camera: &Camera) {
+        if !self.axes {
+            return;
+        }
+        let rect = ctx.available_rect();
+        let inset = INDICATOR_MARGIN + INDICATOR_RADIUS;
+        let center = rect.left_bottom() + egui::vec2(inset, -inset);
+        let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("axis_indicator")));
+        painter.circle_filled(center, INDICATOR_RADIUS + 8.0, Color32::from_black_alpha(60));
+
+        // Axes pointing away from the viewer are drawn first, so the nearer ones cross over them
+        let view = camera.view_matrix();
+        let mut axes: Vec<(usize, Vec3)> =
+            (0..3).map(|axis| (axis, view.transform_vector3(Vec3::AXES[axis]))).collect();
+        axes.sort_by(|(_, a), (_, b)| a.z.total_cmp(&b.z));
+        let font = FontId::proportional(12.0);
+        for (axis, direction) in axes {
+            let tip = center + egui::vec2(direction.x, -direction.y) * INDICATOR_RADIUS;
+            // Faded while pointing away, so a flipped view still reads correctly
+            let color = if direction.z < -0.1 { AXIS_COLORS[axis].gamma_multiply(0.6) } else { AXIS_COLORS[axis] };
+            painter.line_segment([center, tip], Stroke::new(2.0, color));
+            painter.circle_filled(tip, 7.0, color);
+            painter.text(tip, Align2::CENTER_CENTER, ["X", "Y", "Z"][axis], font.clone(), Color32::WHITE);
+        }
+    }
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Draws the ground grid over the opaque scene with a fullscreen triangle, depth tested against the scene so
+// objects standing on or below the plane hide it
+pub struct GridRenderer {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl GridRenderer {
+    pub fn new(device: &wgpu::Device, stats: &mut RenderStats, format: wgpu::TextureFormat) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Grid Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("grid.wgsl").into()),
+        });
+        let uniform_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Grid Uniform Buffer"),
+                size: size_of::<GridUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grid Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grid Bind Group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Grid Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Grid Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Tested but not written, so particles still fade against the objects behind the grid
+            depth_stencil: Some(DepthConfig::READ_ONLY.state()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            pipeline,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    /// Draws the grid as `settings` describe it over `target`, seen through `camera`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        settings: &GridSettings,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) {
+        let view_proj = camera.view_projection_matrix();
+        let uniform = GridUniform {
+            view_proj: view_proj.to_cols_array_2d(),
+            inverse_view_proj: view_proj.inverse().to_cols_array_2d(),
+            camera_position: camera.position.to_array(),
+            fade_distance: settings.fade_distance,
+            spacing: settings.spacing,
+            major_every: settings.major_every as f32,
+            _padding: [0.0; 2],
+        };
+        uploads.write(device, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Grid Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+        stats.record_draw(3, 1);
+    }
+}
@@ -0,0 +1,82 @@
+// Editor ground grid on the y = 0 plane: each pixel's view ray is intersected with the plane, and lines are
+// drawn where the hit point crosses a multiple of the spacing, antialiased by their screen-space width
+
+struct Grid {
+    view_proj: mat4x4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+    camera_position: vec3<f32>,
+    fade_distance: f32, // The grid is gone past this distance from the camera
+    spacing: f32,       // World units between minor lines
+    major_every: f32,   // Minor cells between major lines
+};
+@group(0) @binding(0)
+var<uniform> grid: Grid;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) near: vec3<f32>, // Where the pixel's view ray meets the near and far planes
+    @location(1) far: vec3<f32>,
+};
+
+fn unproject(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+    let world = grid.inverse_view_proj * vec4<f32>(ndc, depth, 1.0);
+    return world.xyz / world.w;
+}
+
+// A single triangle covering the screen, so no vertex buffer is needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.near = unproject(ndc, 0.0);
+    out.far = unproject(ndc, 1.0);
+    return out;
+}
+
+// How much of the pixel lines every `spacing` units cover, with about a pixel of falloff
+fn lines(position: vec2<f32>, spacing: f32) -> f32 {
+    let cell = position / spacing;
+    let width = fwidth(cell);
+    let distance = abs(fract(cell - 0.5) - 0.5) / max(width, vec2<f32>(1e-5));
+    // Lines closer together than a few pixels would only shimmer, so they fade out
+    let crowding = 1.0 - smoothstep(0.15, 0.35, max(width.x, width.y));
+    return (1.0 - min(min(distance.x, distance.y), 1.0)) * crowding;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32, // Of the hit point, so the scene hides the grid where it is in front
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let ray = in.far - in.near;
+    let t = -in.near.y / select(ray.y, 1e-6, abs(ray.y) < 1e-6);
+    let point = in.near + ray * t;
+
+    // Derivatives are taken before anything is discarded
+    let minor = lines(point.xz, grid.spacing);
+    let major = lines(point.xz, grid.spacing * grid.major_every);
+    let width = max(fwidth(point.xz), vec2<f32>(1e-5));
+    let x_axis = 1.0 - min(abs(point.z) / width.y, 1.0); // Where z = 0
+    let z_axis = 1.0 - min(abs(point.x) / width.x, 1.0); // Where x = 0
+
+    var color = vec4<f32>(0.5, 0.5, 0.5, 0.3 * minor);
+    color = mix(color, vec4<f32>(0.6, 0.6, 0.6, 0.6), major);
+    color = mix(color, vec4<f32>(0.9, 0.25, 0.25, 0.9), x_axis);
+    color = mix(color, vec4<f32>(0.25, 0.45, 0.95, 0.9), z_axis);
+    let distance = length(point - grid.camera_position);
+    color.a *= 1.0 - smoothstep(grid.fade_distance * 0.25, grid.fade_distance, distance);
+
+    // Rays that never reach the plane see no grid
+    if t <= 0.0 || color.a <= 0.001 {
+        discard;
+    }
+    let clip = grid.view_proj * vec4<f32>(point, 1.0);
+    var out: FragmentOutput;
+    out.color = color;
+    out.depth = clamp(clip.z / clip.w, 0.0, 1.0);
+    return out;
+}
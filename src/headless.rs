@@ -0,0 +1,310 @@
+// headless.rs
+
+use crate::bind_groups::BindGroupCache;
+use crate::buffer_pool::BufferPool;
+use crate::camera::{self, Camera, CameraUniform};
+use crate::capabilities::Capabilities;
+use crate::depth::DepthBuffer;
+use crate::environment::Fog;
+use crate::geometry::GeometryArena;
+use crate::lighting::Lighting;
+use crate::material::Materials;
+use crate::object_uniforms::ObjectUniforms;
+use crate::pipelines;
+use crate::primitives;
+use crate::reflection_probes::ProbeRenderer;
+use crate::screenshot::{self, Screenshot};
+use crate::shader_cache::ShaderCache;
+use crate::shader_manifest::ShaderLibrary;
+use crate::shader_params::ShaderParams;
+use crate::stats::RenderStats;
+use crate::transform::{ModelUniform, Transform};
+use crate::upload::Uploads;
+use crate::vertex::Vertex;
+use crate::world::{GlobalTransform, Light, LightKind};
+use egui_wgpu::wgpu;
+use glam::{EulerRot, Mat4, Quat, Vec3};
+use hecs::World;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// What headless frames are drawn in: 8-bit sRGB like the usual surface formats, but always RGBA
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+// Size of `--headless` frames when none is given
+const DEFAULT_SIZE: (u32, u32) = (1280, 720);
+
+// The background scenes start with
+const BACKGROUND: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+// A fixed scene, set up the same way on every run so its frames can be compared against stored images
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TestScene {
+    Polygon,   // The hexagon the template starts with, face on
+    Cube,      // Turned to show three faces, lit by the default sun
+    LitSphere, // Lit only by a point light to one side, so its falloff shows
+}
+
+impl TestScene {
+    pub const ALL: [TestScene; 3] = [TestScene::Polygon, TestScene::Cube, TestScene::LitSphere];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TestScene::Polygon => "polygon",
+            TestScene::Cube => "cube",
+            TestScene::LitSphere => "lit_sphere",
+        }
+    }
+
+    // The scene's one mesh and where it stands, with its lights spawned into `world`
+    fn build(&self, world: &mut World) -> ((Vec<Vertex>, Vec<u16>), Mat4) {
+        let sun = Transform {
+            rotation: Quat::from_euler(EulerRot::YXZ, -0.6, -0.8, 0.0),
+            ..Transform::IDENTITY
+        };
+        match self {
+            TestScene::Polygon => {
+                world.spawn((sun, GlobalTransform(sun.matrix()), Light::default()));
+                (Vertex::generate_polygon(6, 0.5), Mat4::IDENTITY)
+            }
+            TestScene::Cube => {
+                world.spawn((sun, GlobalTransform(sun.matrix()), Light::default()));
+                let rotation = Quat::from_euler(EulerRot::YXZ, 0.6, 0.5, 0.0);
+                (Vertex::generate_cube(), Mat4::from_quat(rotation))
+            }
+            TestScene::LitSphere => {
+                let light = Light {
+                    kind: LightKind::Point { range: 4.0 },
+                    color: Vec3::new(1.0, 0.85, 0.6),
+                    intensity: 1.5,
+                    ..Light::default()
+                };
+                let transform = Transform {
+                    translation: Vec3::new(1.2, 0.8, 1.2),
+                    ..Transform::IDENTITY
+                };
+                world.spawn((transform, GlobalTransform(transform.matrix()), light));
+                (primitives::uv_sphere(32, 16, 0.6), Mat4::IDENTITY)
+            }
+        }
+    }
+}
+
+// Draws test scenes offscreen through the main shader and the scene pipeline layout the app uses, with no window
+// or surface, and reads the frames back
+pub struct HeadlessRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    stats: RenderStats,
+    uploads: Uploads,
+    pool: BufferPool,
+    bind_groups: BindGroupCache,
+    lighting: Lighting,
+    objects: ObjectUniforms,
+    materials: Materials,
+    probes: ProbeRenderer,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    target: wgpu::Texture,
+    depth: DepthBuffer,
+}
+
+impl HeadlessRenderer {
+    /// Sets up a `width` by `height` target on the default adapter, or returns None when there is no adapter,
+    /// as on machines without a GPU or a software renderer.
+    pub fn new(width: u32, height: u32) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::util::backend_bits_from_env().unwrap_or_default(),
+            ..Default::default()
+        });
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        let capabilities = Capabilities::negotiate(&adapter);
+        let (device, queue) = pollster::block_on(adapter.request_device(&capabilities.device_descriptor(), None))
+            .inspect_err(|err| log::warn!("Failed to create a headless device: {err}"))
+            .ok()?;
+
+        let mut stats = RenderStats::new();
+        let mut uploads = Uploads::new();
+        let lighting = Lighting::new(&device, &mut stats);
+        let camera_layout = camera::bind_group_layout(&device);
+        let camera_buffer = stats.create_buffer(
+            &device,
+            &wgpu::BufferDescriptor {
+                label: Some("Headless Camera Buffer"),
+                size: size_of::<CameraUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Headless Camera Bind Group"),
+            layout: &camera_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lighting.buffer().as_entire_binding(),
+                },
+            ],
+        });
+        let objects = ObjectUniforms::new(&device, &mut stats);
+
+        // The built-in main shader with the material values the app starts from
+        let program = ShaderLibrary::load(None).get("main");
+        let mut params = ShaderParams::new(&device, "main", &program).ok()?;
+        params.set("tint", &[1.0, 1.0, 1.0]);
+        params.set("brightness", &[1.0]);
+        params.set("reflectivity", &[0.2]);
+        params.set("specular", &[0.5]);
+        params.set("shininess", &[32.0]);
+        let materials = Materials::new(&device, &mut stats, &mut uploads, vec![params]);
+        let probes = ProbeRenderer::new(&device, &mut stats, FORMAT, &camera_layout, lighting.buffer());
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Headless Pipeline Layout"),
+            bind_group_layouts: &[
+                &camera_layout,
+                &objects.bind_group_layout,
+                materials.layout("main"),
+                &probes.bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let cache = ShaderCache::load(&adapter.get_info());
+        let constants = HashMap::from([("MY_CONSTANT".to_string(), 1.0)]);
+        let label = "Headless Pipeline";
+        let fill = wgpu::PolygonMode::Fill;
+        let pipeline =
+            pipelines::create_scene_pipeline(&device, &cache, label, &program, &layout, FORMAT, &constants, 1, fill);
+
+        let target = stats.create_texture(
+            &device,
+            &wgpu::TextureDescriptor {
+                label: Some("Headless Target"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            },
+        );
+        let mut depth = DepthBuffer::new();
+        depth.resize(&device, &mut stats, (width, height));
+        Some(Self {
+            device,
+            queue,
+            stats,
+            uploads,
+            pool: BufferPool::new(),
+            bind_groups: BindGroupCache::new(),
+            lighting,
+            objects,
+            materials,
+            probes,
+            camera_buffer,
+            camera_bind_group,
+            pipeline,
+            target,
+            depth,
+        })
+    }
+
+    /// Draws `scene` and returns the frame as tightly packed RGBA8 rows, top first.
+    pub fn render(&mut self, scene: TestScene) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+        let (width, height) = (self.target.width(), self.target.height());
+        let mut world = World::new();
+        let ((vertices, indices), model) = scene.build(&mut world);
+        let mut geometry =
+            GeometryArena::with_capacity(&self.device, &mut self.stats, vertices.len() as u32, indices.len() as u32);
+        let mesh = geometry.allocate(&self.device, &mut self.uploads, &mut self.stats, &vertices, &indices);
+
+        let mut camera = Camera::new(Vec3::new(0.0, 0.0, 2.5), Vec3::ZERO, 0.0);
+        camera.aspect = width as f32 / height as f32;
+        let mut uniform = CameraUniform::new();
+        uniform.update_view_proj(&camera);
+        self.uploads.write(&self.device, &self.camera_buffer, 0, bytemuck::bytes_of(&uniform));
+        self.lighting.update(&self.device, &mut self.uploads, &world, &Fog::new(), None);
+        self.objects.begin_frame(&self.device, &mut self.stats, 1);
+        let offset = self.objects.push(ModelUniform::from_matrix(model));
+        self.objects.upload(&self.device, &mut self.uploads);
+        self.materials.upload(&self.device, &mut self.uploads);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless Encoder"),
+        });
+        // No probes are placed; this only writes their empty list
+        self.probes.render(&self.device, &mut self.uploads, &mut encoder, &world, Vec3::ZERO, 1.0, |_, _| Vec::new());
+        let object_bind_group = self.objects.bind_group(&self.device, &mut self.bind_groups);
+        let view = self.target.create_view(&wgpu::TextureViewDescriptor::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Headless Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(BACKGROUND),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.depth.view(),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            pass.set_bind_group(1, &object_bind_group, &[offset]);
+            pass.set_bind_group(2, self.materials.bind_group(self.materials.default_for("main")), &[]);
+            pass.set_bind_group(3, self.probes.bind_group(), &[]);
+            geometry.bind(&mut pass);
+            geometry.draw(&mut pass, &mesh, 0..1);
+        }
+        let screenshot = Screenshot::record(&self.device, &mut self.pool, &mut self.stats, &mut encoder, &self.target);
+        self.uploads.submit(&self.queue, [encoder.finish()]);
+        Ok(screenshot.read(&self.device)?.into_rgba())
+    }
+}
+
+
+/// The frame size `--headless [WIDTHxHEIGHT]` asks for, or None without the flag.
+pub fn size_from_args(args: &[String]) -> Option<(u32, u32)> {
+    let index = args.iter().position(|arg| arg == "--headless")?;
+    let size = args.get(index + 1).and_then(|arg| {
+        let (width, height) = arg.split_once('x')?;
+        Some((width.parse::<u32>().ok()?.max(1), height.parse::<u32>().ok()?.max(1)))
+    });
+    Some(size.unwrap_or(DEFAULT_SIZE))
+}
+
+/// Renders the scene the app starts with at `width` by `height` without opening a window, and writes it as
+/// a PNG to the screenshots directory, the same way Save Screenshot does. Returns the path written.
+pub fn run_headless(width: u32, height: u32) -> Result<PathBuf, String> {
+    let mut renderer = HeadlessRenderer::new(width, height).ok_or("no adapter to render with")?;
+    let pixels = renderer
+        .render(TestScene::Polygon)
+        .map_err(|err| format!("failed to read the frame back: {err}"))?;
+    let path = screenshot::screenshot_path();
+    screenshot::save_png(&path, width, height, pixels)?;
+    Ok(path)
+}
@@ -0,0 +1,210 @@
+// history.rs
+
+use crate::scene::SceneNode;
+use crate::transform::Transform;
+use egui::Context;
+
+const MAX_EDITS: usize = 100;
+
+// A reversible scene edit, stored as the values before and after the change
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneEdit {
+    Transform { before: Transform, after: Transform },
+    Polygon { before: (u16, f32), after: (u16, f32) }, // (sides, radius)
+    Nodes { before: Vec<SceneNode>, after: Vec<SceneNode> }, // Every Scene Tree node, as saved
+}
+
+impl SceneEdit {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SceneEdit::Transform { .. } => "Transform object",
+            SceneEdit::Polygon { .. } => "Change polygon",
+            SceneEdit::Nodes { .. } => "Edit scene tree",
+        }
+    }
+
+    pub fn inverse(&self) -> SceneEdit {
+        match self.clone() {
+            SceneEdit::Transform { before, after } => SceneEdit::Transform { before: after, after: before },
+            SceneEdit::Polygon { before, after } => SceneEdit::Polygon { before: after, after: before },
+            SceneEdit::Nodes { before, after } => SceneEdit::Nodes { before: after, after: before },
+        }
+    }
+}
+
+// Values the history compares against to detect new edits
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SceneSnapshot {
+    pub transform: Transform,
+    pub polygon: (u16, f32),
+}
+
+/// Undo/redo stacks for UI-driven scene edits.
+///
+/// Edits are not recorded by the widgets themselves: `track` diffs the scene against the last
+/// committed snapshot once no interaction is in progress, so a whole gizmo or slider drag becomes one edit.
+#[derive(Debug, Default)]
+pub struct History {
+    undo_stack: Vec<SceneEdit>,
+    redo_stack: Vec<SceneEdit>,
+    committed: Option<SceneSnapshot>,
+    committed_nodes: Option<Vec<SceneNode>>, // Tracked apart, as only the render loop can list them
+    revision: u64, // Bumped by every edit, undo and redo
+    saved_revision: u64,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    // True if anything changed since the scene was last saved or loaded
+    pub fn is_dirty(&self) -> bool {
+        self.revision != self.saved_revision
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_revision = self.revision;
+    }
+
+    /// Forgets all edits and takes the next tracked scene as the new clean baseline, e.g. after loading.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn track(&mut self, current: SceneSnapshot, interacting: bool) {
+        let Some(committed) = self.committed else {
+            self.committed = Some(current);
+            return;
+        };
+        if interacting || committed == current {
+            return;
+        }
+        if committed.transform != current.transform {
+            self.push(SceneEdit::Transform {
+                before: committed.transform,
+                after: current.transform,
+            });
+        }
+        if committed.polygon != current.polygon {
+            self.push(SceneEdit::Polygon {
+                before: committed.polygon,
+                after: current.polygon,
+            });
+        }
+        self.committed = Some(current);
+    }
+
+    /// Like `track`, for the Scene Tree's nodes: adding, removing, renaming, reparenting and editing them.
+    pub fn track_nodes(&mut self, current: Vec<SceneNode>, interacting: bool) {
+        let Some(committed) = &self.committed_nodes else {
+            self.committed_nodes = Some(current);
+            return;
+        };
+        if interacting || *committed == current {
+            return;
+        }
+        self.push(SceneEdit::Nodes {
+            before: committed.clone(),
+            after: current.clone(),
+        });
+        self.committed_nodes = Some(current);
+    }
+
+    fn push(&mut self, edit: SceneEdit) {
+        self.revision += 1;
+        self.redo_stack.clear();
+        self.undo_stack.push(edit);
+        if self.undo_stack.len() > MAX_EDITS {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    /// Pops the last edit and returns the edit that reverts it.
+    pub fn undo(&mut self) -> Option<SceneEdit> {
+        let edit = self.undo_stack.pop()?;
+        let inverse = edit.inverse();
+        self.redo_stack.push(edit);
+        Some(self.commit(inverse))
+    }
+
+    pub fn redo(&mut self) -> Option<SceneEdit> {
+        let edit = self.redo_stack.pop()?;
+        self.undo_stack.push(edit.clone());
+        Some(self.commit(edit))
+    }
+
+    // The caller applies the returned edit, so the committed snapshot must already match it
+    fn commit(&mut self, edit: SceneEdit) -> SceneEdit {
+        self.revision += 1;
+        match &edit {
+            SceneEdit::Transform { after, .. } => {
+                if let Some(committed) = &mut self.committed {
+                    committed.transform = *after;
+                }
+            }
+            SceneEdit::Polygon { after, .. } => {
+                if let Some(committed) = &mut self.committed {
+                    committed.polygon = *after;
+                }
+            }
+            SceneEdit::Nodes { after, .. } => self.committed_nodes = Some(after.clone()),
+        }
+        edit
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    /// Returns how many steps to undo (negative) or redo (positive) the user clicked.
+    pub fn show(&mut self, ctx: &Context) -> i32 {
+        let mut steps = 0;
+        egui::Window::new("History")
+            .default_open(false)
+            .default_size([220.0, 260.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(self.can_undo(), egui::Button::new("Undo")).clicked() {
+                        steps = -1;
+                    }
+                    if ui.add_enabled(self.can_redo(), egui::Button::new("Redo")).clicked() {
+                        steps = 1;
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.clear();
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+                    // Clicking an entry jumps to the state right after it
+                    let undo_count = self.undo_stack.len() as i32;
+                    for (i, edit) in self.undo_stack.iter().enumerate() {
+                        let current = i as i32 == undo_count - 1;
+                        if ui.selectable_label(current, edit.label()).clicked() {
+                            steps = i as i32 + 1 - undo_count;
+                        }
+                    }
+                    for (i, edit) in self.redo_stack.iter().rev().enumerate() {
+                        let label = egui::RichText::new(edit.label()).weak();
+                        if ui.selectable_label(false, label).clicked() {
+                            steps = i as i32 + 1;
+                        }
+                    }
+                    if self.undo_stack.is_empty() && self.redo_stack.is_empty() {
+                        ui.weak("No edits yet");
+                    }
+                });
+            });
+        steps
+    }
+}
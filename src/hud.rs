@@ -0,0 +1,230 @@
+// hud.rs
+
+use crate::bounds::Aabb;
+use crate::camera::Camera;
+use crate::debug;
+use crate::terrain::Terrain;
+use crate::viewport_panel;
+use crate::world::{GlobalTransform, Hidden, MeshHandle};
+use egui::{Align2, Color32, Context, FontId, Id, LayerId, Order, Stroke};
+use glam::{IVec3, Vec2, Vec3};
+use hecs::World;
+
+const CROSSHAIR_ARM: f32 = 9.0; // Pixels from the center to the end of each arm
+const CROSSHAIR_GAP: f32 = 3.0; // Left open in the middle so the targeted spot stays visible
+const HUD_MARGIN: f32 = 12.0;
+const SWATCH_SIZE: f32 = 28.0;
+
+// How far away the crosshair still targets a block, in world units
+const REACH: f32 = 64.0;
+
+// How far past a hit the ray is followed to find the block behind the face it hit
+const FACE_EPSILON: f32 = 1e-3;
+
+// Kinds of unit block; the voxel demo builds its world from the first three
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockType {
+    Grass,
+    Dirt,
+    Stone,
+    Sand,
+    Wood,
+}
+
+impl BlockType {
+    pub const ALL: [BlockType; 5] = [
+        BlockType::Grass,
+        BlockType::Dirt,
+        BlockType::Stone,
+        BlockType::Sand,
+        BlockType::Wood,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BlockType::Grass => "Grass",
+            BlockType::Dirt => "Dirt",
+            BlockType::Stone => "Stone",
+            BlockType::Sand => "Sand",
+            BlockType::Wood => "Wood",
+        }
+    }
+
+    /// Linear RGB, as vertex colors take it.
+    pub fn color(&self) -> [f32; 3] {
+        match self {
+            BlockType::Grass => [0.3, 0.6, 0.2],
+            BlockType::Dirt => [0.45, 0.3, 0.2],
+            BlockType::Stone => [0.45, 0.45, 0.48],
+            BlockType::Sand => [0.85, 0.78, 0.5],
+            BlockType::Wood => [0.5, 0.35, 0.18],
+        }
+    }
+
+    pub fn next(&self) -> BlockType {
+        let index = BlockType::ALL.iter().position(|block| block == self).unwrap_or(0);
+        BlockType::ALL[(index + 1) % BlockType::ALL.len()]
+    }
+}
+
+// Heads-up display over the viewport: a crosshair, the selected block type and a coordinates readout. Painted in
+// its own layer under the editor's windows, so it stays put whichever panels are open
+#[derive(Debug, Clone)]
+pub struct Hud {
+    pub enabled: bool,
+    pub crosshair: bool,
+    pub coordinates: bool,
+    pub block_indicator: bool,
+    pub block: BlockType,       // What would be placed at the targeted block
+    pub target: Option<IVec3>, // Block under the crosshair, found by `aim` each frame
+}
+
+impl Hud {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            crosshair: true,
+            coordinates: true,
+            block_indicator: true,
+            block: BlockType::Grass,
+            target: None,
+        }
+    }
+
+    /// Finds the block the center of the view points at, the nearest of the meshes' bounds and the terrain within
+    /// reach, and outlines it with debug lines. Bounds the camera is inside of don't count.
+    pub fn aim(&mut self, camera: &Camera, world: &World, terrain: &Terrain) {
+        self.target = None;
+        if !self.enabled || !self.crosshair {
+            return;
+        }
+        let (origin, direction) = camera.screen_ray(Vec2::ZERO);
+        let meshes = world
+            .query::<(&MeshHandle, &GlobalTransform)>()
+            .without::<&Hidden>()
+            .iter()
+            .filter_map(|(mesh, global)| mesh.bounds.map(|bounds| bounds.transformed(global.0)))
+            .filter_map(|bounds| bounds.ray_intersection(origin, direction))
+            .filter(|&distance| distance > 0.0)
+            .min_by(f32::total_cmp);
+        let terrain = terrain
+            .enabled
+            .then(|| terrain.ray_hit(origin, direction))
+            .flatten()
+            .map(|point| (point - origin).length());
+        let Some(distance) = meshes.into_iter().chain(terrain).min_by(f32::total_cmp).filter(|d| *d <= REACH) else {
+            return;
+        };
+        let block = (origin + direction * (distance + FACE_EPSILON)).floor();
+        self.target = Some(block.as_ivec3());
+        debug::aabb(&Aabb::new(block, block + Vec3::ONE), Color32::WHITE);
+    }
+
+    pub fn select_next_block(&mut self) {
+        self.block = self.block.next();
+    }
+
+    /// The HUD submenu: what the HUD shows, and the selected block type.
+    pub fn menu_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Show HUD");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.checkbox(&mut self.crosshair, "Crosshair");
+            ui.checkbox(&mut self.coordinates, "Coordinates");
+            ui.checkbox(&mut self.block_indicator, "Selected block");
+            egui::ComboBox::from_label("Block")
+                .selected_text(self.block.label())
+                .show_ui(ui, |ui| {
+                    for block in BlockType::ALL {
+                        ui.selectable_value(&mut self.block, block, block.label());
+                    }
+                });
+        });
+    }
+
+    /// Paints the HUD. The crosshair marks the middle of the scene, where `aim` casts its ray; the readout and
+    /// the block indicator keep to the space the panels leave free. `next_key` names the key that changes block.
+    pub fn show(&self, ctx: &Context, camera: &Camera, next_key: &str) {
+        if !self.enabled {
+            return;
+        }
+        let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("hud")));
+        let rect = ctx.available_rect();
+
+        if self.crosshair {
+            let center = viewport_panel::scene_rect(ctx).center();
+            for direction in [egui::vec2(1.0, 0.0), egui::vec2(0.0, 1.0)] {
+                for sign in [-1.0, 1.0] {
+                    let inner = center + direction * sign * CROSSHAIR_GAP;
+                    let outer = center + direction * sign * CROSSHAIR_ARM;
+                    // A dark outline keeps it readable over light and dark backgrounds alike
+                    painter.line_segment([inner, outer], Stroke::new(4.0, Color32::from_black_alpha(140)));
+                    painter.line_segment([inner, outer], Stroke::new(2.0, Color32::WHITE));
+                }
+            }
+        }
+
+        if self.coordinates {
+            let position = camera.position;
+            let block = position.floor().as_ivec3();
+            let target = match self.target {
+                Some(target) => format!("{} {} {}", target.x, target.y, target.z),
+                None => "none".to_string(),
+            };
+            let text = format!(
+                "XYZ: {:.2} / {:.2} / {:.2}\nBlock: {} {} {}\nFacing: {}\nTarget: {target}",
+                position.x,
+                position.y,
+                position.z,
+                block.x,
+                block.y,
+                block.z,
+                facing(camera.target - camera.position),
+            );
+            let galley = painter.layout_no_wrap(text, FontId::monospace(13.0), Color32::WHITE);
+            let origin = rect.left_top() + egui::vec2(HUD_MARGIN, HUD_MARGIN);
+            let background = egui::Rect::from_min_size(origin, galley.size()).expand(6.0);
+            painter.rect_filled(background, 4.0, Color32::from_black_alpha(120));
+            painter.galley(origin, galley, Color32::WHITE);
+        }
+
+        if self.block_indicator {
+            let [r, g, b] = self.block.color().map(|channel| (channel.powf(1.0 / 2.2) * 255.0) as u8);
+            let center = rect.center_bottom() - egui::vec2(0.0, HUD_MARGIN + SWATCH_SIZE * 0.5);
+            let swatch = egui::Rect::from_center_size(center, egui::vec2(SWATCH_SIZE, SWATCH_SIZE));
+            painter.rect_filled(swatch.expand(3.0), 4.0, Color32::from_black_alpha(140));
+            painter.rect_filled(swatch, 2.0, Color32::from_rgb(r, g, b));
+            painter.text(
+                swatch.right_center() + egui::vec2(10.0, 0.0),
+                Align2::LEFT_CENTER,
+                format!("{} ({next_key} to change)", self.block.label()),
+                FontId::proportional(14.0),
+                Color32::WHITE,
+            );
+        }
+    }
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Compass direction of a view direction on the ground plane, taking -Z as north
+fn facing(direction: Vec3) -> &'static str {
+    let flat = Vec2::new(direction.x, direction.z);
+    if flat.length_squared() < 1e-6 {
+        return if direction.y > 0.0 { "Up" } else { "Down" };
+    }
+    if flat.x.abs() > flat.y.abs() {
+        if flat.x > 0.0 {
+            "East (+X)"
+        } else {
+            "West (-X)"
+        }
+    } else if flat.y > 0.0 {
+        "South (+Z)"
+    } else {
+        "North (-Z)"
+    }
+}
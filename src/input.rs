@@ -0,0 +1,968 @@
+// input.rs
+
+use crate::commands::Command;
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use winit::event::{DeviceEvent, ElementState, Force, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+
+// Everything the user can trigger from the keyboard, mouse or a controller
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Quit,
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    NudgeLeft,
+    NudgeRight,
+    NudgeUp,
+    NudgeDown,
+    OrbitLeft,
+    OrbitRight,
+    OrbitUp,
+    OrbitDown,
+    CycleSelection,
+    NextBlock,
+    ToggleUi,
+}
+
+impl Action {
+    pub const ALL: [Action; 18] = [
+        Action::Quit,
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::NudgeLeft,
+        Action::NudgeRight,
+        Action::NudgeUp,
+        Action::NudgeDown,
+        Action::OrbitLeft,
+        Action::OrbitRight,
+        Action::OrbitUp,
+        Action::OrbitDown,
+        Action::CycleSelection,
+        Action::NextBlock,
+        Action::ToggleUi,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::MoveForward => "Move forward",
+            Action::MoveBackward => "Move backward",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::NudgeLeft => "Nudge selection left",
+            Action::NudgeRight => "Nudge selection right",
+            Action::NudgeUp => "Nudge selection up",
+            Action::NudgeDown => "Nudge selection down",
+            Action::OrbitLeft => "Orbit left",
+            Action::OrbitRight => "Orbit right",
+            Action::OrbitUp => "Orbit up",
+            Action::OrbitDown => "Orbit down",
+            Action::CycleSelection => "Cycle selection",
+            Action::NextBlock => "Next block type",
+            Action::ToggleUi => "Hide/show the UI",
+        }
+    }
+
+    pub fn default_gamepad(&self) -> Option<GamepadInput> {
+        match self {
+            Action::Quit => None,
+            Action::MoveForward => Some(GamepadInput::Axis(GamepadAxis::LeftStickY, true)),
+            Action::MoveBackward => Some(GamepadInput::Axis(GamepadAxis::LeftStickY, false)),
+            Action::MoveLeft => Some(GamepadInput::Axis(GamepadAxis::LeftStickX, false)),
+            Action::MoveRight => Some(GamepadInput::Axis(GamepadAxis::LeftStickX, true)),
+            Action::MoveUp => Some(GamepadInput::Button(GamepadButton::RightBumper)),
+            Action::MoveDown => Some(GamepadInput::Button(GamepadButton::LeftBumper)),
+            // The D-pad already navigates the UI and the right stick orbits
+            Action::NudgeLeft | Action::NudgeRight | Action::NudgeUp | Action::NudgeDown => None,
+            Action::OrbitLeft | Action::OrbitRight | Action::OrbitUp | Action::OrbitDown => None,
+            Action::CycleSelection | Action::NextBlock | Action::ToggleUi => None,
+        }
+    }
+
+    pub fn default_key(&self) -> KeyCode {
+        match self {
+            Action::Quit => KeyCode::Escape,
+            Action::MoveForward => KeyCode::KeyW,
+            Action::MoveBackward => KeyCode::KeyS,
+            Action::MoveLeft => KeyCode::KeyA,
+            Action::MoveRight => KeyCode::KeyD,
+            Action::MoveUp => KeyCode::Space,
+            Action::MoveDown => KeyCode::ShiftLeft,
+            Action::NudgeLeft => KeyCode::KeyJ,
+            Action::NudgeRight => KeyCode::KeyL,
+            Action::NudgeUp => KeyCode::KeyI,
+            Action::NudgeDown => KeyCode::KeyK,
+            Action::OrbitLeft => KeyCode::ArrowLeft,
+            Action::OrbitRight => KeyCode::ArrowRight,
+            Action::OrbitUp => KeyCode::ArrowUp,
+            Action::OrbitDown => KeyCode::ArrowDown,
+            Action::CycleSelection => KeyCode::Tab,
+            Action::NextBlock => KeyCode::KeyB,
+            Action::ToggleUi => KeyCode::KeyH,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY, // Up is positive
+    RightStickX,
+    RightStickY,
+    LeftTrigger, // 0..1
+    RightTrigger,
+}
+
+impl GamepadAxis {
+    pub const ALL: [GamepadAxis; 6] = [
+        GamepadAxis::LeftStickX,
+        GamepadAxis::LeftStickY,
+        GamepadAxis::RightStickX,
+        GamepadAxis::RightStickY,
+        GamepadAxis::LeftTrigger,
+        GamepadAxis::RightTrigger,
+    ];
+}
+
+const STICK_DEADZONE: f32 = 0.15;
+
+// Pixel scroll deltas are converted to lines so wheels and trackpads zoom by the same amount
+const PIXELS_PER_LINE: f32 = 50.0;
+
+// How far an axis must be pushed to count as a press of a digital action
+const AXIS_PRESS_THRESHOLD: f32 = 0.5;
+
+// App-side key repeat for actions that step, like nudging; OS repeat events are ignored so the
+// rate is the same on every platform
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyRepeat {
+    pub delay: f32, // Seconds held before the first repeat
+    pub rate: f32,  // Repeats per second after that
+}
+
+impl KeyRepeat {
+    // Repeats due after holding for `held` seconds, not counting the initial press
+    fn repeats(&self, held: f64) -> u32 {
+        let after_delay = held - self.delay as f64;
+        if after_delay < 0.0 {
+            0
+        } else {
+            (after_delay * self.rate.max(0.0) as f64) as u32 + 1
+        }
+    }
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        Self { delay: 0.4, rate: 20.0 }
+    }
+}
+
+// One physical controller input an action can be bound to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum GamepadInput {
+    Button(GamepadButton),
+    Axis(GamepadAxis, bool), // Direction, true for positive
+}
+
+impl GamepadInput {
+    pub fn label(&self) -> String {
+        match self {
+            GamepadInput::Button(button) => format!("{button:?}"),
+            GamepadInput::Axis(axis, positive) => {
+                let direction = match (axis, positive) {
+                    (GamepadAxis::LeftStickX | GamepadAxis::RightStickX, true) => "right",
+                    (GamepadAxis::LeftStickX | GamepadAxis::RightStickX, false) => "left",
+                    (GamepadAxis::LeftStickY | GamepadAxis::RightStickY, true) => "up",
+                    (GamepadAxis::LeftStickY | GamepadAxis::RightStickY, false) => "down",
+                    (_, true) => "",
+                    (_, false) => "-",
+                };
+                format!("{axis:?} {direction}").trim_end().to_string()
+            }
+        }
+    }
+}
+
+// Ctrl, Alt or Super turn key presses into shortcuts, so Ctrl+S saves instead of moving back; Shift is left out
+// since it's a movement key by default
+fn shortcut_modifiers() -> ModifiersState {
+    ModifiersState::CONTROL | ModifiersState::ALT | ModifiersState::SUPER
+}
+
+// The modifier a key sets while it's held itself, so a modifier key can be bound on its own
+pub fn key_modifier(key: KeyCode) -> ModifiersState {
+    match key {
+        KeyCode::ShiftLeft | KeyCode::ShiftRight => ModifiersState::SHIFT,
+        KeyCode::ControlLeft | KeyCode::ControlRight => ModifiersState::CONTROL,
+        KeyCode::AltLeft | KeyCode::AltRight => ModifiersState::ALT,
+        KeyCode::SuperLeft | KeyCode::SuperRight => ModifiersState::SUPER,
+        _ => ModifiersState::empty(),
+    }
+}
+
+// Maps physical keys, mouse buttons and controller inputs to actions so systems never hard-code any of them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ActionMap {
+    keys: BTreeMap<Action, KeyCode>,
+    modifiers: BTreeMap<Action, ModifiersState>, // Held with the key; actions missing from it need none
+    mouse: BTreeMap<Action, Option<MouseButton>>,
+    gamepad: BTreeMap<Action, Option<GamepadInput>>,
+    pub repeat: KeyRepeat,
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Self {
+            keys: Action::ALL.iter().map(|a| (*a, a.default_key())).collect(),
+            modifiers: BTreeMap::new(),
+            mouse: BTreeMap::new(),
+            gamepad: Action::ALL.iter().map(|a| (*a, a.default_gamepad())).collect(),
+            repeat: KeyRepeat::default(),
+        }
+    }
+
+    // Actions missing from a loaded file keep their default key
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.keys.get(&action).copied().unwrap_or(action.default_key())
+    }
+
+    pub fn modifiers(&self, action: Action) -> ModifiersState {
+        self.modifiers.get(&action).copied().unwrap_or_default()
+    }
+
+    // No action has a mouse button by default, they're all taken by the camera
+    pub fn mouse(&self, action: Action) -> Option<MouseButton> {
+        self.mouse.get(&action).copied().flatten()
+    }
+
+    pub fn gamepad(&self, action: Action) -> Option<GamepadInput> {
+        self.gamepad.get(&action).copied().unwrap_or(action.default_gamepad())
+    }
+
+    /// Binds `key`, to be held with `modifiers`. The key's own modifier is dropped, so binding Shift alone
+    /// doesn't ask for Shift+Shift.
+    pub fn bind(&mut self, action: Action, key: KeyCode, modifiers: ModifiersState) {
+        self.keys.insert(action, key);
+        self.modifiers.insert(action, modifiers.difference(key_modifier(key)));
+    }
+
+    pub fn bind_mouse(&mut self, action: Action, button: Option<MouseButton>) {
+        self.mouse.insert(action, button);
+    }
+
+    pub fn bind_gamepad(&mut self, action: Action, input: Option<GamepadInput>) {
+        self.gamepad.insert(action, input);
+    }
+
+    pub fn is_default(&self, action: Action) -> bool {
+        self.key(action) == action.default_key()
+            && self.modifiers(action).is_empty()
+            && self.mouse(action).is_none()
+            && self.gamepad(action) == action.default_gamepad()
+    }
+
+    pub fn reset(&mut self, action: Action) {
+        self.bind(action, action.default_key(), ModifiersState::empty());
+        self.bind_mouse(action, None);
+        self.bind_gamepad(action, action.default_gamepad());
+    }
+
+    pub fn reset_all(&mut self) {
+        *self = Self::new();
+    }
+
+    // First action bound to `key`, in declaration order
+    pub fn action_for_key(&self, key: KeyCode) -> Option<Action> {
+        Action::ALL.into_iter().find(|a| self.key(*a) == key)
+    }
+
+    /// Other actions sharing `action`'s key and modifiers, mouse button or controller input.
+    pub fn conflicts(&self, action: Action) -> Vec<Action> {
+        let key = (self.key(action), self.modifiers(action));
+        let mouse = self.mouse(action);
+        let gamepad = self.gamepad(action);
+        Action::ALL
+            .into_iter()
+            .filter(|a| *a != action)
+            .filter(|a| {
+                (self.key(*a), self.modifiers(*a)) == key
+                    || (mouse.is_some() && self.mouse(*a) == mouse)
+                    || (gamepad.is_some() && self.gamepad(*a) == gamepad)
+            })
+            .collect()
+    }
+
+    /// The action's key with its modifiers, like "Ctrl+Shift+K".
+    pub fn key_text(&self, action: Action) -> String {
+        let modifiers = self.modifiers(action);
+        let mut parts: Vec<String> = [
+            (ModifiersState::CONTROL, "Ctrl"),
+            (ModifiersState::ALT, "Alt"),
+            (ModifiersState::SHIFT, "Shift"),
+            (ModifiersState::SUPER, "Super"),
+        ]
+        .into_iter()
+        .filter(|(modifier, _)| modifiers.contains(*modifier))
+        .map(|(_, name)| name.to_string())
+        .collect();
+        parts.push(key_label(self.key(action)));
+        parts.join("+")
+    }
+}
+
+// Which binding the keybinding editor is waiting for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Rebinding {
+    Key(Action),
+    Gamepad(Action),
+    Shortcut(Command, Option<egui::KeyboardShortcut>), // With the first half once a chord is under way
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn key_label(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    name.strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+        .unwrap_or(&name)
+        .to_string()
+}
+
+// Pressure reported with a touch; only pens and force-sensitive screens provide it
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PenState {
+    pub pressure: f32, // 0..1
+}
+
+impl PenState {
+    fn from_force(force: Force) -> Self {
+        Self {
+            pressure: force.normalized().clamp(0.0, 1.0) as f32,
+        }
+    }
+}
+
+// Everything InputState reacts to, in a serializable form so it can be recorded and replayed
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Key { code: KeyCode, pressed: bool },
+    Modifiers(ModifiersState),
+    MouseButton { button: MouseButton, pressed: bool },
+    CursorMoved(Vec2), // Physical pixels
+    CursorLeft,
+    Scroll { lines: Vec2, precise: bool }, // Precise for trackpads and other pixel-based devices
+    Touch {
+        id: u64,
+        phase: TouchPhase,
+        position: Vec2,
+        #[serde(default)]
+        pen: Option<PenState>,
+    },
+    MouseMotion(Vec2), // Raw device delta
+    FocusLost,
+    GamepadButton { button: GamepadButton, pressed: bool },
+    GamepadAxis { axis: GamepadAxis, value: f32 },
+    SpaceMotion { translation: Vec3, rotation: Vec3 }, // 6DOF device deflection, each axis -1..1
+}
+
+impl InputEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        Some(match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                // OS repeat timing differs per platform; held keys are repeated by KeyRepeat instead
+                let PhysicalKey::Code(code) = event.physical_key else {
+                    return None;
+                };
+                if event.repeat {
+                    return None;
+                }
+                InputEvent::Key {
+                    code,
+                    pressed: event.state == ElementState::Pressed,
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => InputEvent::Modifiers(modifiers.state()),
+            WindowEvent::MouseInput { state, button, .. } => InputEvent::MouseButton {
+                button: *button,
+                pressed: *state == ElementState::Pressed,
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                InputEvent::CursorMoved(Vec2::new(position.x as f32, position.y as f32))
+            }
+            WindowEvent::CursorLeft { .. } => InputEvent::CursorLeft,
+            WindowEvent::Touch(touch) => InputEvent::Touch {
+                id: touch.id,
+                phase: touch.phase,
+                position: Vec2::new(touch.location.x as f32, touch.location.y as f32),
+                pen: touch.force.map(PenState::from_force),
+            },
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y) => InputEvent::Scroll {
+                    lines: Vec2::new(*x, *y),
+                    precise: false,
+                },
+                MouseScrollDelta::PixelDelta(pos) => InputEvent::Scroll {
+                    lines: Vec2::new(pos.x as f32, pos.y as f32) / PIXELS_PER_LINE,
+                    precise: true,
+                },
+            },
+            WindowEvent::Focused(false) => InputEvent::FocusLost,
+            _ => return None,
+        })
+    }
+
+    // Device events are not tied to a window; only relative mouse motion is used
+    pub fn from_device_event(event: &DeviceEvent) -> Option<Self> {
+        match event {
+            DeviceEvent::MouseMotion { delta } => {
+                Some(InputEvent::MouseMotion(Vec2::new(delta.0 as f32, delta.1 as f32)))
+            }
+            _ => None,
+        }
+    }
+}
+
+// How far the cursor must travel while a button is held before the press becomes a drag
+const DRAG_THRESHOLD: f32 = 4.0; // Physical pixels
+const DOUBLE_CLICK_TIME: f64 = 0.3; // Seconds
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DragPhase {
+    Started,
+    Dragging,
+    Ended,
+}
+
+// A held mouse button that has not yet been classified, or is being dragged
+#[derive(Debug, Copy, Clone)]
+struct Press {
+    origin: Vec2,
+    dragging: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Click {
+    button: MouseButton,
+    time: f64,
+    position: Vec2,
+}
+
+// Keyboard and mouse state built from window events, read by systems once per frame
+#[derive(Debug, Clone, Default)]
+pub struct InputState {
+    keys_down: HashSet<KeyCode>,
+    keys_pressed: HashSet<KeyCode>,
+    keys_released: HashSet<KeyCode>,
+    buttons_down: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    modifiers: ModifiersState,
+    cursor: Option<Vec2>,
+    cursor_delta: Vec2,
+    raw_mouse_delta: Vec2, // Unaccelerated device units, keeps coming at the window edges
+    scroll_delta: Vec2, // In lines
+    precise_scroll: bool, // The last scroll came from a trackpad, which brings its own momentum
+    pad_down: HashSet<GamepadButton>,
+    pad_pressed: HashSet<GamepadButton>,
+    pad_released: HashSet<GamepadButton>,
+    axes: HashMap<GamepadAxis, f32>,
+    space_translation: Vec3, // Current 6DOF device push, held until it reports a change
+    space_rotation: Vec3,
+    presses: HashMap<MouseButton, Press>,
+    drag_events: HashMap<MouseButton, DragPhase>, // Started or Ended this frame
+    clicks: HashSet<MouseButton>,
+    double_clicks: HashSet<MouseButton>,
+    last_click: Option<Click>,
+    touches: BTreeMap<u64, Vec2>, // Finger id to position, for fingers that went down on the viewport
+    touch_drag: Vec2,
+    touch_pan: Vec2,
+    pen: Option<(u64, PenState)>, // Touch id and latest reading of a pen in contact with the viewport
+    pinch: f32, // Log of the finger spread ratio this frame, so the default of 0 means no pinch
+    time: f64,  // Seconds of frame time seen so far
+    frame_dt: f64, // Length of the current frame, to find repeats that fell within it
+    key_press_times: HashMap<KeyCode, f64>,
+    pad_press_times: HashMap<GamepadButton, f64>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Live window events go through the same path as replayed ones
+    pub fn process_event(&mut self, event: &WindowEvent) {
+        if let Some(event) = InputEvent::from_window_event(event) {
+            self.apply(&event);
+        }
+    }
+
+    pub fn apply(&mut self, event: &InputEvent) {
+        match *event {
+            // Repeats of a held key add nothing; older recordings still contain OS repeats
+            InputEvent::Key { code, pressed: true } => {
+                if self.keys_down.insert(code) {
+                    self.keys_pressed.insert(code);
+                    self.key_press_times.insert(code, self.time);
+                }
+            }
+            InputEvent::Key { code, pressed: false } => {
+                self.keys_down.remove(&code);
+                self.keys_released.insert(code);
+                self.key_press_times.remove(&code);
+            }
+            InputEvent::Modifiers(modifiers) => self.modifiers = modifiers,
+            InputEvent::MouseButton { button, pressed: true } => {
+                self.buttons_down.insert(button);
+                self.buttons_pressed.insert(button);
+                if let Some(origin) = self.cursor {
+                    self.presses.insert(button, Press { origin, dragging: false });
+                }
+            }
+            InputEvent::MouseButton { button, pressed: false } => {
+                self.buttons_down.remove(&button);
+                self.buttons_released.insert(button);
+                if let Some(press) = self.presses.remove(&button) {
+                    self.classify_release(button, press);
+                }
+            }
+            InputEvent::CursorMoved(cursor) => {
+                if let Some(last) = self.cursor {
+                    self.cursor_delta += cursor - last;
+                }
+                self.cursor = Some(cursor);
+                for (button, press) in &mut self.presses {
+                    if !press.dragging && press.origin.distance(cursor) > DRAG_THRESHOLD {
+                        press.dragging = true;
+                        self.drag_events.insert(*button, DragPhase::Started);
+                    }
+                }
+            }
+            InputEvent::CursorLeft => self.cursor = None,
+            InputEvent::Touch {
+                id,
+                phase,
+                position,
+                pen,
+            } => {
+                self.process_touch(id, phase, position, pen.is_some());
+                self.process_pen(id, phase, pen);
+            }
+            InputEvent::Scroll { lines, precise } => {
+                self.scroll_delta += lines;
+                self.precise_scroll = precise;
+            }
+            InputEvent::MouseMotion(delta) => self.raw_mouse_delta += delta,
+            // Releases are not delivered while unfocused, so forget everything held
+            InputEvent::FocusLost => self.release_all(),
+            InputEvent::GamepadButton { button, pressed } => self.gamepad_button(button, pressed),
+            InputEvent::GamepadAxis { axis, value } => self.set_axis(axis, value),
+            // The device reports every change, including the return to rest
+            InputEvent::SpaceMotion { translation, rotation } => {
+                self.space_translation = translation;
+                self.space_rotation = rotation;
+            }
+        }
+    }
+
+    // Advances the clock used for double-click timing and key repeat; replays drive it with their fixed step
+    pub fn advance_time(&mut self, dt: f32) {
+        self.frame_dt = dt as f64;
+        self.time += self.frame_dt;
+    }
+
+    /// Clears the per-frame state; call once every system has read this frame's input.
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.cursor_delta = Vec2::ZERO;
+        self.raw_mouse_delta = Vec2::ZERO;
+        self.scroll_delta = Vec2::ZERO;
+        self.pad_pressed.clear();
+        self.pad_released.clear();
+        self.drag_events.clear();
+        self.clicks.clear();
+        self.double_clicks.clear();
+        self.touch_drag = Vec2::ZERO;
+        self.touch_pan = Vec2::ZERO;
+        self.pinch = 0.0;
+    }
+
+    pub fn process_device_event(&mut self, event: &DeviceEvent) {
+        if let Some(event) = InputEvent::from_device_event(event) {
+            self.apply(&event);
+        }
+    }
+
+    fn process_touch(&mut self, id: u64, phase: TouchPhase, position: Vec2, has_pressure: bool) {
+        match phase {
+            TouchPhase::Started => {
+                self.touches.insert(id, position);
+            }
+            TouchPhase::Moved => {
+                let Some(previous) = self.touches.get(&id).copied() else {
+                    return; // Started over egui
+                };
+                // Gestures use the first two fingers; compare them before and after this move
+                let pair = |touches: &BTreeMap<u64, Vec2>| {
+                    let mut fingers = touches.values();
+                    Some((*fingers.next()?, *fingers.next()?))
+                };
+                let before = pair(&self.touches);
+                self.touches.insert(id, position);
+                match (before, pair(&self.touches)) {
+                    (Some((a0, b0)), Some((a1, b1))) => {
+                        self.touch_pan += (a1 + b1) * 0.5 - (a0 + b0) * 0.5;
+                        if a0.distance(b0) > 1.0 {
+                            self.pinch += (a1.distance(b1).max(1.0) / a0.distance(b0)).ln();
+                        }
+                    }
+                    // A pen paints rather than orbits, so its strokes stay out of the drag
+                    _ if has_pressure || self.pen.is_some_and(|(pen_id, _)| pen_id == id) => {}
+                    _ => self.touch_drag += position - previous,
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&id);
+            }
+        }
+    }
+
+    // The most recent touch with pressure is the pen; lifting it ends the stroke
+    fn process_pen(&mut self, id: u64, phase: TouchPhase, pen: Option<PenState>) {
+        match (phase, pen) {
+            (TouchPhase::Ended | TouchPhase::Cancelled, _) if self.pen.is_some_and(|(pen_id, _)| pen_id == id) => {
+                self.pen = None;
+            }
+            (TouchPhase::Started | TouchPhase::Moved, Some(pen)) if self.touches.contains_key(&id) => {
+                self.pen = Some((id, pen));
+            }
+            _ => {}
+        }
+    }
+
+    // A release ends a drag, or else counts as a click, or a double-click if it closely follows one
+    fn classify_release(&mut self, button: MouseButton, press: Press) {
+        if press.dragging {
+            self.drag_events.insert(button, DragPhase::Ended);
+            return;
+        }
+        self.clicks.insert(button);
+        let now = self.time;
+        let is_double = self.last_click.is_some_and(|last| {
+            last.button == button
+                && now - last.time <= DOUBLE_CLICK_TIME
+                && last.position.distance(press.origin) <= DRAG_THRESHOLD
+        });
+        if is_double {
+            self.double_clicks.insert(button);
+            self.last_click = None; // A third click starts a new pair
+        } else {
+            self.last_click = Some(Click {
+                button,
+                time: now,
+                position: press.origin,
+            });
+        }
+    }
+
+    // Fed by the gamepad backend; all connected controllers are merged into one
+    pub fn gamepad_button(&mut self, button: GamepadButton, pressed: bool) {
+        if pressed {
+            if self.pad_down.insert(button) {
+                self.pad_pressed.insert(button);
+                self.pad_press_times.insert(button, self.time);
+            }
+        } else if self.pad_down.remove(&button) {
+            self.pad_released.insert(button);
+            self.pad_press_times.remove(&button);
+        }
+    }
+
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.axes.insert(axis, value);
+    }
+
+    pub fn gamepad_down(&self, button: GamepadButton) -> bool {
+        self.pad_down.contains(&button)
+    }
+
+    pub fn gamepad_just_pressed(&self, button: GamepadButton) -> bool {
+        self.pad_pressed.contains(&button)
+    }
+
+    pub fn gamepad_just_released(&self, button: GamepadButton) -> bool {
+        self.pad_released.contains(&button)
+    }
+
+    // Stick values inside the deadzone read as 0 so worn sticks do not drift
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        let value = self.axes.get(&axis).copied().unwrap_or(0.0);
+        if value.abs() < STICK_DEADZONE {
+            0.0
+        } else {
+            value
+        }
+    }
+
+    /// How far a 6DOF device is pushed along x (right), y (up) and z (away from the user), each -1..1.
+    pub fn space_translation(&self) -> Vec3 {
+        self.space_translation
+    }
+
+    // Tilt around x, twist around y and roll around z, each -1..1
+    pub fn space_rotation(&self) -> Vec3 {
+        self.space_rotation
+    }
+
+    pub fn release_all(&mut self) {
+        self.pen = None;
+        // Modifier changes in another window are reported to that window only
+        self.modifiers = ModifiersState::empty();
+        self.key_press_times.clear();
+        self.keys_released.extend(self.keys_down.drain());
+        self.buttons_released.extend(self.buttons_down.drain());
+        self.touches.clear();
+        // Drags are cut short, but a lost press never turns into a click
+        for (button, press) in self.presses.drain() {
+            if press.dragging {
+                self.drag_events.insert(button, DragPhase::Ended);
+            }
+        }
+    }
+
+    pub fn is_pressed(&self, key: KeyCode) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    pub fn just_pressed(&self, key: KeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn just_released(&self, key: KeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    // How far a controller input is pushed, 0..1; buttons are either 0 or 1
+    pub fn gamepad_value(&self, input: GamepadInput) -> f32 {
+        match input {
+            GamepadInput::Button(button) => self.gamepad_down(button) as i32 as f32,
+            GamepadInput::Axis(axis, positive) => {
+                let value = self.axis(axis);
+                if positive {
+                    value.max(0.0)
+                } else {
+                    (-value).max(0.0)
+                }
+            }
+        }
+    }
+
+    /// First controller input pushed this frame, used when binding an action to the controller.
+    pub fn gamepad_activity(&self) -> Option<GamepadInput> {
+        if let Some(button) = self.pad_pressed.iter().min() {
+            return Some(GamepadInput::Button(*button));
+        }
+        GamepadAxis::ALL.into_iter().find_map(|axis| {
+            let value = self.axis(axis);
+            (value.abs() > AXIS_PRESS_THRESHOLD).then_some(GamepadInput::Axis(axis, value > 0.0))
+        })
+    }
+
+    // The binding's modifiers are held, and no other shortcut modifier; extra Shift is fine unless bound
+    fn modifiers_match(&self, wanted: ModifiersState) -> bool {
+        let shortcut = shortcut_modifiers();
+        self.modifiers.contains(wanted) && self.modifiers.intersection(shortcut) == wanted.intersection(shortcut)
+    }
+
+    fn action_modifiers_match(&self, actions: &ActionMap, action: Action) -> bool {
+        self.modifiers_match(actions.modifiers(action) | key_modifier(actions.key(action)))
+    }
+
+    fn action_key_pressed(&self, actions: &ActionMap, action: Action) -> bool {
+        self.is_pressed(actions.key(action)) && self.action_modifiers_match(actions, action)
+    }
+
+    // Mouse bindings take no modifiers, but Ctrl+click and the like are left to the viewport
+    fn action_mouse_down(&self, actions: &ActionMap, action: Action) -> bool {
+        actions.mouse(action).is_some_and(|b| self.mouse_down(b)) && self.modifiers_match(ModifiersState::empty())
+    }
+
+    pub fn action_pressed(&self, actions: &ActionMap, action: Action) -> bool {
+        self.action_value(actions, action) > AXIS_PRESS_THRESHOLD
+    }
+
+    /// Analog strength of an action, 1 while its key or mouse button is held.
+    pub fn action_value(&self, actions: &ActionMap, action: Action) -> f32 {
+        let pad = actions.gamepad(action).map_or(0.0, |input| self.gamepad_value(input));
+        if self.action_key_pressed(actions, action) || self.action_mouse_down(actions, action) {
+            1.0
+        } else {
+            pad
+        }
+    }
+
+    // Axis bindings only report the key; sticks have no edge to detect
+    pub fn action_just_pressed(&self, actions: &ActionMap, action: Action) -> bool {
+        let mouse = actions.mouse(action).is_some_and(|b| self.mouse_just_pressed(b));
+        (self.just_pressed(actions.key(action)) && self.action_modifiers_match(actions, action))
+            || (mouse && self.modifiers_match(ModifiersState::empty()))
+            || matches!(actions.gamepad(action), Some(GamepadInput::Button(b)) if self.gamepad_just_pressed(b))
+    }
+
+    // Modifiers are often let go first, so releases don't check them
+    pub fn action_just_released(&self, actions: &ActionMap, action: Action) -> bool {
+        self.just_released(actions.key(action))
+            || actions.mouse(action).is_some_and(|b| self.mouse_just_released(b))
+            || matches!(actions.gamepad(action), Some(GamepadInput::Button(b)) if self.gamepad_just_released(b))
+    }
+
+    /// True on the press and again at the repeat rate while held, for stepping actions like nudges.
+    pub fn action_repeated(&self, actions: &ActionMap, action: Action) -> bool {
+        if self.action_just_pressed(actions, action) {
+            return true;
+        }
+        // A repeat is due if its time falls within this frame
+        let repeated = |pressed_at: f64| {
+            let held = self.time - pressed_at;
+            actions.repeat.repeats(held) > actions.repeat.repeats(held - self.frame_dt)
+        };
+        let key = self.action_key_pressed(actions, action)
+            && self.key_press_times.get(&actions.key(action)).is_some_and(|t| repeated(*t));
+        let pad = match actions.gamepad(action) {
+            Some(GamepadInput::Button(button)) => self.pad_press_times.get(&button).is_some_and(|t| repeated(*t)),
+            _ => false,
+        };
+        key || pad
+    }
+
+    pub fn mouse_down(&self, button: MouseButton) -> bool {
+        self.buttons_down.contains(&button)
+    }
+
+    pub fn mouse_just_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    pub fn mouse_just_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Pressed and released without moving past the drag threshold.
+    pub fn clicked(&self, button: MouseButton) -> bool {
+        self.clicks.contains(&button)
+    }
+
+    // Also reports `clicked` for the same release
+    pub fn double_clicked(&self, button: MouseButton) -> bool {
+        self.double_clicks.contains(&button)
+    }
+
+    /// Phase of a drag with `button` this frame, None if it is not being dragged.
+    pub fn drag(&self, button: MouseButton) -> Option<DragPhase> {
+        self.drag_events.get(&button).copied().or_else(|| {
+            self.presses
+                .get(&button)
+                .filter(|press| press.dragging)
+                .map(|_| DragPhase::Dragging)
+        })
+    }
+
+    // True from the frame a drag starts up to, but not including, the frame it ends
+    pub fn dragging(&self, button: MouseButton) -> bool {
+        matches!(self.drag(button), Some(DragPhase::Started | DragPhase::Dragging))
+    }
+
+    // Where the button went down for the current drag
+    pub fn drag_origin(&self, button: MouseButton) -> Option<Vec2> {
+        self.presses.get(&button).map(|press| press.origin)
+    }
+
+    // Movement of a single finger this frame, in physical pixels
+    pub fn touch_drag(&self) -> Vec2 {
+        self.touch_drag
+    }
+
+    // Movement of the midpoint between two fingers this frame
+    pub fn touch_pan(&self) -> Vec2 {
+        self.touch_pan
+    }
+
+    /// How much two fingers spread apart this frame, above 1 when zooming in.
+    pub fn pinch_zoom(&self) -> f32 {
+        self.pinch.exp()
+    }
+
+    /// Pressure of the pen touching the viewport, 0..1. None for mice and fingers, which callers
+    /// usually treat as full pressure.
+    pub fn pen_pressure(&self) -> Option<f32> {
+        self.pen.map(|(_, pen)| pen.pressure)
+    }
+
+    /// Where the pen touches the viewport, in physical pixels.
+    pub fn pen_position(&self) -> Option<Vec2> {
+        self.pen.and_then(|(id, _)| self.touches.get(&id).copied())
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    // Physical pixels, None while the cursor is outside the window
+    pub fn cursor_position(&self) -> Option<Vec2> {
+        self.cursor
+    }
+
+    pub fn cursor_delta(&self) -> Vec2 {
+        self.cursor_delta
+    }
+
+    // Relative motion for mouse-look; unlike cursor_delta it works while the cursor is grabbed
+    pub fn raw_mouse_delta(&self) -> Vec2 {
+        self.raw_mouse_delta
+    }
+
+    pub fn scroll_delta(&self) -> Vec2 {
+        self.scroll_delta
+    }
+
+    // False for notched mouse wheels, whose steps benefit from smoothing
+    pub fn is_precise_scroll(&self) -> bool {
+        self.precise_scroll
+    }
+}
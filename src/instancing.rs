@@ -0,0 +1,273 @@
+// instancing.rs
+
+use crate::depth::DepthConfig;
+use crate::geometry::{GeometryArena, MeshAllocation};
+use crate::stats::RenderStats;
+use crate::transform::Transform;
+use crate::upload::Uploads;
+use crate::vertex::Vertex;
+use crate::vertex_layout::{vertex_layout, VertexLayout};
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::Vec3;
+
+// Most instances along each side of the grid, so at most this cubed in all
+pub const MAX_GRID_SIZE: u32 = 40;
+
+// One copy of the mesh: where it stands relative to the object, and what its vertex colors are multiplied by
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Instance {
+    pub transform: Transform,
+    pub color: Vec3,
+}
+
+impl Instance {
+    pub fn to_raw(&self) -> InstanceRaw {
+        let [model_0, model_1, model_2, model_3] = self.transform.matrix().to_cols_array_2d();
+        InstanceRaw {
+            model_0,
+            model_1,
+            model_2,
+            model_3,
+            color: self.color.to_array(),
+        }
+    }
+}
+
+// An instance as the instance buffer holds it, its matrix split into columns since a vertex attribute holds
+// at most four values
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct InstanceRaw {
+    model_0: [f32; 4],
+    model_1: [f32; 4],
+    model_2: [f32; 4],
+    model_3: [f32; 4],
+    color: [f32; 3],
+}
+
+impl InstanceRaw {
+    // Locations must match `InstanceInput` in instancing.wgsl, after the ones `Vertex` takes
+    pub const LAYOUT: VertexLayout = vertex_layout!(InstanceRaw, Instance {
+        4 => model_0: Float32x4,
+        5 => model_1: Float32x4,
+        6 => model_2: Float32x4,
+        7 => model_3: Float32x4,
+        8 => color: Float32x3,
+    });
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const LAYOUT: &VertexLayout = &InstanceRaw::LAYOUT;
+        LAYOUT.buffer_layout()
+    }
+}
+
+// A cube of copies of the object's mesh centered on the object, drawn in a single instanced draw to see how
+// far the GPU goes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InstanceGrid {
+    pub enabled: bool,
+    pub size: u32,     // Instances along each side
+    pub spacing: f32,  // Between neighbours' centers, in the object's units
+    pub colored: bool, // Tinted by where they stand in the grid; off, they keep the mesh's colors
+}
+
+impl InstanceGrid {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            size: 10,
+            spacing: 1.5,
+            colored: true,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.size.pow(3)
+    }
+
+    /// Every instance of the grid, X fastest.
+    pub fn instances(&self) -> Vec<Instance> {
+        let last = self.size.saturating_sub(1).max(1) as f32;
+        let center = (self.size as f32 - 1.0) * 0.5;
+        let mut instances = Vec::with_capacity(self.count() as usize);
+        for z in 0..self.size {
+            for y in 0..self.size {
+                for x in 0..self.size {
+                    let cell = Vec3::new(x as f32, y as f32, z as f32);
+                    instances.push(Instance {
+                        transform: Transform {
+                            translation: (cell - center) * self.spacing,
+                            ..Transform::IDENTITY
+                        },
+                        // Kept off black, so the corner at the origin still shows its shading
+                        color: if self.colored { 0.25 + cell / last * 0.75 } else { Vec3::ONE },
+                    });
+                }
+            }
+        }
+        instances
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        egui::Window::new("Instancing")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.enabled, "Instance grid");
+                ui.add_enabled_ui(self.enabled, |ui| {
+                    ui.add(egui::Slider::new(&mut self.size, 1..=MAX_GRID_SIZE).text("Grid size"));
+                    ui.add(egui::Slider::new(&mut self.spacing, 0.5..=5.0).text("Spacing"));
+                    ui.checkbox(&mut self.colored, "Color by position");
+                    ui.label(format!("{} instances of the object's mesh in one draw", self.count()));
+                });
+            });
+    }
+}
+
+impl Default for InstanceGrid {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Pipeline drawing the instance grid, in a pass after the scene's that shares its depth buffer. The mesh comes
+// from the shared geometry buffers and the instances from a second vertex buffer, stepped once per instance
+pub struct InstancingRenderer {
+    pipeline: wgpu::RenderPipeline,
+    buffer: Option<wgpu::Buffer>,
+    count: u32,
+    built: Option<InstanceGrid>, // The grid the buffer holds
+}
+
+impl InstancingRenderer {
+    /// `camera_layout` and `object_layout` are the scene's first two bind group layouts.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_layout: &wgpu::BindGroupLayout,
+        object_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Instancing Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("instancing.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Instancing Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, object_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Instancing Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc(), InstanceRaw::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(DepthConfig::OPAQUE.state()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            pipeline,
+            buffer: None,
+            count: 0,
+            built: None,
+        }
+    }
+
+    /// Refills the instance buffer if `grid` changed since the last call, growing it if the grid no longer fits.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        grid: &InstanceGrid,
+    ) {
+        if self.built.as_ref() == Some(grid) {
+            return;
+        }
+        let raw: Vec<InstanceRaw> = grid.instances().iter().map(Instance::to_raw).collect();
+        let bytes: &[u8] = bytemuck::cast_slice(&raw);
+        if self.buffer.as_ref().is_none_or(|buffer| buffer.size() < bytes.len() as u64) {
+            if let Some(old) = self.buffer.take() {
+                stats.buffer_destroyed(&old);
+            }
+            let buffer = stats.create_buffer(
+                device,
+                &wgpu::BufferDescriptor {
+                    label: Some("Instance Buffer"),
+                    size: bytes.len() as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                },
+            );
+            self.buffer = Some(buffer);
+        }
+        if let Some(buffer) = &self.buffer {
+            uploads.write(device, buffer, 0, bytes);
+        }
+        self.count = raw.len() as u32;
+        self.built = Some(*grid);
+    }
+
+    /// Draws every instance of `mesh` over the scene in `target`, depth tested against the scene's `depth`, with
+    /// the given camera and object bind groups.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        stats: &mut RenderStats,
+        encoder: &mut wgpu::CommandEncoder,
+        geometry: &GeometryArena,
+        mesh: &MeshAllocation,
+        groups: &[(&wgpu::BindGroup, &[wgpu::DynamicOffset]); 2],
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) {
+        let Some(buffer) = self.buffer.as_ref().filter(|_| self.count > 0) else {
+            return;
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Instancing Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        for (index, (bind_group, offsets)) in groups.iter().enumerate() {
+            pass.set_bind_group(index as u32, bind_group, offsets);
+        }
+        geometry.bind(&mut pass);
+        pass.set_vertex_buffer(1, buffer.slice(..));
+        geometry.draw(&mut pass, mesh, 0..self.count);
+        stats.record_draw(mesh.num_indices, self.count);
+    }
+}
@@ -0,0 +1,118 @@
+// Many copies of one mesh in a single draw, each placed and tinted by its own entry in the instance buffer
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Every enabled light in the scene, gathered each frame from the world's Light components
+struct Light {
+    position: vec4<f32>, // w: 0 directional, 1 point, 2 spot
+    direction: vec4<f32>, // w: range
+    color: vec4<f32>, // Times intensity
+    cone: vec4<f32>, // Cosines of the spot's inner and outer angles
+};
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    fog_color: vec3<f32>,
+    fog_mode: u32, // 0 off, 1 linear, 2 exponential
+    fog: vec4<f32>, // Start, end, density and height falloff
+    lights: array<Light, 16>,
+};
+@group(0) @binding(1)
+var<uniform> lights: Lights;
+
+struct ModelUniform {
+    model: mat4x4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> model_uniform: ModelUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) normal: vec3<f32>, // Zero when the mesh has none
+};
+
+// The columns of the instance's matrix, relative to the object's
+struct InstanceInput {
+    @location(4) model_0: vec4<f32>,
+    @location(5) model_1: vec4<f32>,
+    @location(6) model_2: vec4<f32>,
+    @location(7) model_3: vec4<f32>,
+    @location(8) color: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let placement = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world = model_uniform.model * placement;
+    var out: VertexOutput;
+    out.color = in.color * instance.color;
+    let world_position = world * vec4<f32>(in.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    out.normal = (world * vec4<f32>(in.normal, 0.0)).xyz;
+    return out;
+}
+
+// Diffuse light reaching a surface at `position` facing `normal`
+fn shade(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var total = lights.ambient;
+    for (var i = 0u; i < lights.count; i++) {
+        let light = lights.lights[i];
+        var to_light = -light.direction.xyz;
+        var falloff = 1.0;
+        if light.position.w > 0.5 {
+            let offset = light.position.xyz - position;
+            let distance = length(offset);
+            to_light = offset / max(distance, 0.0001);
+            falloff = clamp(1.0 - distance / light.direction.w, 0.0, 1.0);
+            falloff *= falloff;
+            if light.position.w > 1.5 {
+                let cos_angle = dot(-to_light, light.direction.xyz);
+                falloff *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+            }
+        }
+        total += light.color.rgb * max(dot(normal, to_light), 0.0) * falloff;
+    }
+    return total;
+}
+
+// How much of a surface at `position` the fog hides, 0 to 1
+fn fog_amount(position: vec3<f32>) -> f32 {
+    let distance = length(position - camera.position.xyz);
+    var amount = 0.0;
+    if lights.fog_mode == 1u {
+        amount = clamp((distance - lights.fog.x) / (lights.fog.y - lights.fog.x), 0.0, 1.0);
+    } else if lights.fog_mode == 2u {
+        amount = 1.0 - exp(-lights.fog.z * distance);
+    }
+    // Thinner the higher the surface stands above the ground plane
+    return amount * exp(-lights.fog.w * max(position.y, 0.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Flat shaded like the scene shader where the mesh has no normals
+    var normal = normalize(cross(dpdx(in.world_position), dpdy(in.world_position)));
+    if dot(normal, camera.position.xyz - in.world_position) < 0.0 {
+        normal = -normal;
+    }
+    if dot(in.normal, in.normal) > 0.0 {
+        normal = normalize(in.normal);
+    }
+    let color = in.color * shade(in.world_position, normal);
+    return vec4<f32>(mix(color, lights.fog_color, fog_amount(in.world_position)), 1.0);
+}
@@ -0,0 +1,210 @@
+// jobs.rs
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+use web_time::Instant;
+
+// Main-thread time per frame for applying finished jobs' results; whatever is left waits for the next frame
+pub const APPLY_BUDGET: Duration = Duration::from_millis(2);
+
+// Earlier variants run first; within a priority, jobs run in the order they were spawned
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Priority {
+    High,   // Work the user is waiting to see, such as a mesh being edited
+    Normal, // Loading files
+    Low,    // Anything that can wait for the rest, such as encoding screenshots
+}
+
+impl Priority {
+    const COUNT: usize = 3;
+}
+
+// Shared between a job and whoever spawned it. A job cancelled before it starts never runs; one already
+// running can check `is_cancelled` and stop early
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+type Task = Box<dyn FnOnce(&CancelToken) + Send>;
+
+struct Shared {
+    queues: Mutex<[VecDeque<(Task, CancelToken)>; Priority::COUNT]>,
+    available: Condvar,
+    running: AtomicUsize,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct JobStats {
+    pub workers: usize,
+    pub queued: usize,
+    pub running: usize,
+}
+
+// Fixed set of worker threads taking jobs by priority. One pool serves the whole app, so mesh builds, file
+// loads, pipeline compiles and screenshot encoding share the cores instead of each starting threads of their
+// own. Blocking waits such as file dialogs and the file watcher keep their dedicated threads, since they
+// would hold a worker for as long as they wait
+struct JobPool {
+    shared: Arc<Shared>,
+    workers: usize,
+}
+
+impl JobPool {
+    fn new() -> Self {
+        // One core is left to the render loop. Browsers can't start threads, so jobs run as they are pushed
+        let workers = if cfg!(target_arch = "wasm32") {
+            0
+        } else {
+            thread::available_parallelism().map_or(2, |n| n.get().saturating_sub(1).max(2))
+        };
+        let shared = Arc::new(Shared {
+            queues: Mutex::new(Default::default()),
+            available: Condvar::new(),
+            running: AtomicUsize::new(0),
+        });
+        for index in 0..workers {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name(format!("job-worker-{index}"))
+                .spawn(move || work(&shared))
+                .expect("Failed to start job worker thread");
+        }
+        Self { shared, workers }
+    }
+
+    fn push(&self, priority: Priority, task: Task, cancel: CancelToken) {
+        if self.workers == 0 {
+            run_task(&self.shared, task, &cancel);
+            return;
+        }
+        self.shared.queues.lock().unwrap()[priority as usize].push_back((task, cancel));
+        self.shared.available.notify_one();
+    }
+}
+
+fn work(shared: &Shared) {
+    loop {
+        let (task, cancel) = {
+            let mut queues = shared.queues.lock().unwrap();
+            loop {
+                if let Some(job) = queues.iter_mut().find_map(VecDeque::pop_front) {
+                    break job;
+                }
+                queues = shared.available.wait(queues).unwrap();
+            }
+        };
+        if cancel.is_cancelled() {
+            continue;
+        }
+        run_task(shared, task, &cancel);
+    }
+}
+
+fn run_task(shared: &Shared, task: Task, cancel: &CancelToken) {
+    shared.running.fetch_add(1, Ordering::Relaxed);
+    // A panicking job loses its result but must not take the worker down with it
+    if panic::catch_unwind(AssertUnwindSafe(|| task(cancel))).is_err() {
+        log::error!("Background job panicked");
+    }
+    shared.running.fetch_sub(1, Ordering::Relaxed);
+}
+
+fn pool() -> &'static JobPool {
+    static POOL: OnceLock<JobPool> = OnceLock::new();
+    POOL.get_or_init(JobPool::new)
+}
+
+/// Queues `job` on the shared pool. Cancelling the returned token before it starts skips it.
+pub fn spawn(priority: Priority, job: impl FnOnce(&CancelToken) + Send + 'static) -> CancelToken {
+    let cancel = CancelToken::default();
+    pool().push(priority, Box::new(job), cancel.clone());
+    cancel
+}
+
+/// Queues `job` on the shared pool and returns a handle to pick up its result from the render loop.
+pub fn run<T: Send + 'static>(priority: Priority, job: impl FnOnce(&CancelToken) -> T + Send + 'static) -> Job<T> {
+    let (sender, receiver) = mpsc::channel();
+    let cancel = spawn(priority, move |cancel| {
+        let _ = sender.send(job(cancel));
+    });
+    Job { receiver, cancel }
+}
+
+pub fn stats() -> JobStats {
+    let pool = pool();
+    JobStats {
+        workers: pool.workers,
+        queued: pool.shared.queues.lock().unwrap().iter().map(VecDeque::len).sum(),
+        running: pool.shared.running.load(Ordering::Relaxed),
+    }
+}
+
+#[derive(Debug)]
+pub enum JobState<T> {
+    Running,
+    Done(T),
+    Lost, // Cancelled before it ran, or panicked
+}
+
+// A job whose result comes back to the thread that spawned it
+pub struct Job<T> {
+    receiver: Receiver<T>,
+    cancel: CancelToken,
+}
+
+impl<T> Job<T> {
+    /// Takes the result if the job has finished.
+    pub fn poll(&self) -> JobState<T> {
+        match self.receiver.try_recv() {
+            Ok(result) => JobState::Done(result),
+            Err(TryRecvError::Empty) => JobState::Running,
+            Err(TryRecvError::Disconnected) => JobState::Lost,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+// Main-thread time left this frame for applying job results. The first result of a frame is always
+// allowed, so one slow result can't hold back the rest forever. Results over budget stay queued; an idle
+// window still picks them up, as redraw-on-demand keeps polling
+#[derive(Debug)]
+pub struct FrameBudget {
+    deadline: Instant,
+    applied: u32,
+}
+
+impl FrameBudget {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            deadline: Instant::now() + budget,
+            applied: 0,
+        }
+    }
+
+    /// Whether another result may be applied this frame.
+    pub fn has_time(&self) -> bool {
+        self.applied == 0 || Instant::now() < self.deadline
+    }
+
+    /// Counts a result as applied.
+    pub fn spend(&mut self) {
+        self.applied += 1;
+    }
+}
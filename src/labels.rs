@@ -0,0 +1,140 @@
+// labels.rs
+
+use crate::bounds::Aabb;
+use crate::gizmo::project;
+use crate::viewport_panel;
+use crate::world::{self, GlobalTransform, Name};
+use egui::{Align2, Color32, Context, FontId, Id, LayerId, Order};
+use glam::{Mat4, Vec3};
+use hecs::{Entity, World};
+
+const OCCLUDED_ALPHA: f32 = 0.25;
+
+// Text anchored to a point in the world, optionally with an icon drawn in front of it
+#[derive(Debug, Clone)]
+pub struct WorldLabel {
+    pub position: Vec3,
+    pub text: String,
+    pub icon: Option<&'static str>,
+    pub color: Color32,
+}
+
+impl WorldLabel {
+    pub fn new(position: Vec3, text: impl Into<String>) -> Self {
+        Self {
+            position,
+            text: text.into(),
+            icon: None,
+            color: Color32::WHITE,
+        }
+    }
+
+    pub fn icon(mut self, icon: &'static str) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+
+    pub fn color(mut self, color: Color32) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+// Labels collected during a frame and painted under all windows, like the gizmo
+pub struct Labels {
+    pub enabled: bool,
+    pub fade_start: f32, // Labels start fading beyond this camera distance...
+    pub fade_end: f32,   // ...and are hidden past this one
+    pub show_names: bool, // Every named entity gets a label with its name
+    labels: Vec<WorldLabel>,
+}
+
+impl Labels {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            fade_start: 10.0,
+            fade_end: 30.0,
+            show_names: false,
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, label: WorldLabel) {
+        self.labels.push(label);
+    }
+
+    /// Shows `text` at `position` this frame, as `labels.text(position, format!("{speed:.1} m/s"), color)`.
+    pub fn text(&mut self, position: Vec3, text: impl Into<String>, color: Color32) {
+        self.add(WorldLabel::new(position, text).color(color));
+    }
+
+    /// Labels every entity with a `Name` this frame, above its bounds where it has a mesh, while `show_names` is on.
+    pub fn add_names(&mut self, world: &World) {
+        if !self.enabled || !self.show_names {
+            return;
+        }
+        for (entity, name, global) in world.query::<(Entity, &Name, &GlobalTransform)>().iter() {
+            let position = match world::world_bounds(world, entity) {
+                Some(bounds) => Vec3::new(bounds.center().x, bounds.max.y, bounds.center().z),
+                None => global.0.w_axis.truncate(),
+            };
+            self.text(position, name.0.clone(), Color32::LIGHT_GRAY);
+        }
+    }
+
+    /// Draws and clears this frame's labels. Labels behind one of `occluders`, as seen from the camera, are faded.
+    pub fn show(&mut self, ctx: &Context, view_proj: Mat4, camera_position: Vec3, occluders: &[Aabb]) {
+        let labels = std::mem::take(&mut self.labels);
+        if !self.enabled {
+            return;
+        }
+
+        let rect = viewport_panel::scene_rect(ctx);
+        let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("world_labels")));
+        let font = FontId::proportional(14.0);
+
+        for label in labels {
+            let Some(screen) = project(view_proj, rect, label.position) else {
+                continue;
+            };
+            let to_label = label.position - camera_position;
+            let distance = to_label.length();
+            let mut alpha = 1.0 - ((distance - self.fade_start) / (self.fade_end - self.fade_start).max(1e-3)).clamp(0.0, 1.0);
+            if is_occluded(camera_position, to_label, distance, occluders) {
+                alpha *= OCCLUDED_ALPHA;
+            }
+            if alpha <= 0.0 {
+                continue;
+            }
+
+            let color = label.color.gamma_multiply(alpha);
+            let shadow = Color32::from_black_alpha((160.0 * alpha) as u8);
+            let text = match label.icon {
+                Some(icon) => format!("{icon} {}", label.text),
+                None => label.text,
+            };
+            // Drop shadow keeps the text readable on any background
+            painter.text(screen + egui::vec2(1.0, 1.0), Align2::CENTER_BOTTOM, &text, font.clone(), shadow);
+            painter.text(screen, Align2::CENTER_BOTTOM, &text, font.clone(), color);
+        }
+    }
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// There is no depth buffer to test against, so occlusion is approximated with the scene's bounding boxes
+fn is_occluded(camera_position: Vec3, to_label: Vec3, distance: f32, occluders: &[Aabb]) -> bool {
+    if distance <= f32::EPSILON {
+        return false;
+    }
+    let direction = to_label / distance;
+    occluders.iter().any(|aabb| {
+        aabb.ray_intersection(camera_position, direction)
+            .is_some_and(|t| t > 0.0 && t < distance - 1e-3)
+    })
+}
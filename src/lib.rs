@@ -1,417 +1,3602 @@
-mod egui_tools;
-mod camera;
-mod vertex;
+pub mod animation;
+pub mod application;
+pub mod assets;
+pub mod benchmark;
+pub mod billboards;
+pub mod bind_groups;
+pub mod bounds;
+pub mod buffer_pool;
+pub mod capabilities;
+pub mod camera;
+pub mod camera_controller;
+pub mod clipboard;
+pub mod commands;
+pub mod compressed_texture;
+pub mod config;
+pub mod console;
+pub mod crash_report;
+pub mod cursor;
+pub mod debug;
+pub mod decals;
+pub mod demos;
+pub mod depth;
+pub mod diagnostics;
+pub mod dock;
+pub mod dynamic_resolution;
+pub mod egui_tools;
+pub mod environment;
+pub mod environment_map;
+pub mod events;
+pub mod file_dialog;
+pub mod frame_capture;
+pub mod frame_timer;
+pub mod geometry;
+pub mod gamepad;
+pub mod gizmo;
+pub mod gltf_export;
+pub mod gpu_context;
+pub mod gpu_errors;
+pub mod gpu_timings;
+pub mod grid;
+pub mod headless;
+pub mod history;
+pub mod hud;
+pub mod input;
+pub mod instancing;
+pub mod jobs;
+pub mod labels;
+pub mod lighting;
+pub mod material;
+pub mod mesh;
+pub mod mesh_builder;
+pub mod morph;
+pub mod msaa;
+pub mod network;
+pub mod object_drag;
+pub mod object_uniforms;
+pub mod occlusion;
+pub mod outline;
+pub mod pacing;
+pub mod particles;
+pub mod physics;
+pub mod pipeline_cache;
+pub mod pipelines;
+pub mod plots;
+pub mod plugins;
+pub mod post_process;
+pub mod primitives;
+pub mod prefab;
+pub mod preset;
+pub mod recording;
+pub mod redraw;
+pub mod reflection_probes;
+pub mod replay;
+pub mod scene;
+pub mod scene_tree;
+pub mod scene_windows;
+pub mod screenshot;
+pub mod scripting;
+pub mod sequencer;
+pub mod settings;
+pub mod shader_cache;
+pub mod shader_manifest;
+pub mod shader_params;
+pub mod shader_registry;
+pub mod shader_reload;
+pub mod skinning;
+pub mod spacemouse;
+pub mod stats;
+pub mod stereo;
+pub mod submission;
+pub mod terrain;
+pub mod texture;
+pub mod theme;
+pub mod toasts;
+pub mod transform;
+pub mod ui;
+pub mod upload;
+pub mod vertex;
+pub mod vertex_layout;
+pub mod video_capture;
+pub mod viewport_panel;
+pub mod water;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+pub mod world;
+pub mod xr;
 
-use crate::egui_tools::EguiRenderer;
-use camera::Camera;
+// The API projects building on the template start from
+pub use application::{AppContext, Application, RenderContext, Template};
+pub use camera::Camera;
+pub use config::AppConfig;
+pub use dock::Panel;
+pub use egui_tools::EguiRenderer;
+pub use events::{AppEvent, EventSender};
+pub use gpu_context::{AdapterRequest, GpuContext};
+pub use headless::run_headless;
+pub use mesh::Mesh;
+pub use post_process::{FullscreenPass, PostContext, PostPass};
+pub use shader_registry::{ShaderRegistry, ShaderVariant};
+
+use crate::egui_tools::UserEvent;
+use animation::AnimationTargets;
+use assets::{AssetEvent, Assets, Handle, LoadProgress, LoadState};
+use benchmark::Benchmark;
+use billboards::{Billboard, BillboardRenderer};
+use bind_groups::BindGroupCache;
+use bounds::{Aabb, Frustum};
+use buffer_pool::BufferPool;
+use camera::CameraUniform;
+use camera_controller::CameraController;
+use clipboard::{Clipboard, ClipboardItem};
+use commands::Command;
+use config::Backend;
+use cursor::{CursorController, CursorMode};
+use debug::DebugRenderer;
+use decals::{Decal, DecalRenderer};
+use demos::DemoGallery;
+use depth::DepthBuffer;
+use diagnostics::Diagnostics;
+use dynamic_resolution::{SceneTarget, RENDER_SCALE_RANGE, TARGET_FPS_RANGE};
+use environment::SkyRenderer;
+use environment_map::EnvironmentMap;
+use file_dialog::{DialogAction, FileDialogResult, FileKind};
+use geometry::{BundleDraw, GeometryArena, MeshAllocation};
+use gamepad::Gamepads;
+use gpu_errors::GpuErrors;
+use gpu_timings::GpuTimings;
+use grid::GridRenderer;
+use input::{Action, ActionMap, DragPhase, InputEvent, InputState, Rebinding};
+use instancing::InstancingRenderer;
+use jobs::{FrameBudget, Job, JobState, Priority};
+use lighting::Lighting;
+use material::{MaterialKey, Materials};
+use mesh::MeshFile;
+use mesh_builder::{MeshBuilder, MeshShape};
+use morph::{MAX_SIDES, MIN_SIDES};
+use network::NetworkEvent;
+use object_uniforms::ObjectUniforms;
+use outline::SelectionOutline;
+use particles::{ParticleEmitter, ParticleSystem};
+use physics::{Collider, RigidBody};
+use msaa::Msaa;
+use pipeline_cache::{PipelineCache, PipelineKey};
+use pipelines::{PendingPipeline, PipelineStatus, RenderMode};
+use plugins::{PluginHost, PluginOverlays};
+use post_process::{PostProcessor, HDR_FORMAT};
+use prefab::{Prefab, PrefabInstance, PrefabLibrary, PrefabLink, PrefabMeshes};
+use preset::{LayoutPreset, PresetRequest};
+use recording::{InputRecorder, InputRecording};
+use redraw::{FrameInputs, RedrawTracker};
+use reflection_probes::{ProbeRenderer, ReflectionProbe};
+use replay::{SessionSnapshot, SessionStep};
+use scene::{SceneBillboard, SceneCamera, SceneDecal, SceneEmitter, SceneFile, SceneLight, SceneProbe, SCENE_VERSION};
+use screenshot::{ConvertedScreenshot, Screenshot};
+use scripting::Script;
+use sequencer::SequencerTargets;
+use submission::{Stage, SubmissionScheduler};
+use terrain::TerrainRenderer;
+use ui::{InputCapture, RenderingStyle, UIState, DISPLAY_SCALE_RANGE};
+use upload::Uploads;
 use vertex::Vertex;
-use egui_wgpu::wgpu::{InstanceDescriptor, PowerPreference, RequestAdapterOptions, TextureFormat};
+use viewport_panel::ViewportPanel;
+use water::WaterRenderer;
+use world::{
+    ActiveCamera, Draw, GlobalTransform, Hidden, Light, MaterialHandle, MeshHandle, Name, Parent, TransformTracker,
+    Visible,
+};
+use xr::XrSession;
 use egui_wgpu::{wgpu, ScreenDescriptor};
-use glam::{Mat4, Vec3};
-use std::collections::HashMap;
+use glam::{Mat4, Vec2, Vec3};
+use hecs::{Entity, World};
+use scene_windows::SceneWindows;
+use settings::{BackgroundMode, PacingMode, PresentModePreference, Settings};
+use shader_cache::ShaderCache;
+use shader_manifest::ShaderLibrary;
+use shader_params::ShaderParams;
+use shader_registry::ShaderId;
+use skinning::{SkinnedMesh, SkinnedModel, SkinningRenderer};
+use spacemouse::SpaceMouse;
+use stats::RenderStats;
+use stereo::StereoRenderer;
+use toasts::Toasts;
+use transform::{ModelUniform, Transform};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
+use web_time::Instant;
 use winit::dpi::PhysicalSize;
-use winit::event::{Event, WindowEvent};
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::keyboard::{Key, ModifiersState, NamedKey};
-use wgpu::util::DeviceExt;
-
-// Rendering styles enum
-enum RenderingStyle {
-    Polygon,
-    Cube,
+use winit::event::{ElementState, Event, MouseButton, TouchPhase, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoopBuilder, EventLoopWindowTarget};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::Window;
+
+// World units the selection moves per nudge key press or repeat
+const NUDGE_STEP: f32 = 0.05;
+
+// What an entity's scene bundle was recorded with: material, object offset and mesh
+type BundleKey = (MaterialKey, wgpu::DynamicOffset, MeshAllocation);
+
+// A screenshot copied from a frame's surface texture, with the file it's saved to unless it's for the clipboard
+type RecordedScreenshot = (Screenshot, Option<PathBuf>);
+
+// Starts the app again with the same arguments, on the backend and adapter in `settings`. Environment variables
+// would override the saved choice, so they're set to it
+fn relaunch(settings: &Settings) {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let exe = match std::env::current_exe() {
+            Ok(exe) => exe,
+            Err(err) => {
+                log::error!("Failed to restart: {err}");
+                return;
+            }
+        };
+        let mut command = std::process::Command::new(exe);
+        command.args(std::env::args_os().skip(1)).env_remove("WGPU_POWER_PREF");
+        match settings.backend.env_name() {
+            Some(backend) => command.env("WGPU_BACKEND", backend),
+            None => command.env_remove("WGPU_BACKEND"),
+        };
+        match &settings.adapter {
+            Some(name) => command.env("WGPU_ADAPTER_NAME", name),
+            None => command.env_remove("WGPU_ADAPTER_NAME"),
+        };
+        if let Err(err) = command.spawn() {
+            log::error!("Failed to restart: {err}");
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = settings;
+}
+
+// Copies the preferences the UI changes back into `settings`
+fn update_settings(settings: &mut Settings, ui_state: &UIState, action_map: &ActionMap) {
+    settings.theme = ui_state.theme.clone();
+    settings.ui_scale = ui_state.scale_factor;
+    settings.display_scale = ui_state.display_scale;
+    settings.keybindings = action_map.clone();
+    settings.shortcuts = ui_state.shortcuts.clone();
+    settings.memory_budget_mib = ui_state.memory_budget_mib;
+    settings.frame_latency = ui_state.frame_latency;
+    settings.background_mode = ui_state.background_mode;
+    settings.background_fps = ui_state.background_fps;
+    settings.pacing_mode = ui_state.pacing_mode;
+    settings.fps_cap = ui_state.fps_cap;
+    settings.present_mode = ui_state.present_mode;
+    settings.redraw_on_demand = ui_state.redraw_on_demand;
+    settings.dynamic_resolution = ui_state.resolution.enabled;
+    settings.target_fps = ui_state.resolution.target_fps;
+    settings.upscale_filter = ui_state.resolution.filter;
+    settings.render_scale = ui_state.resolution.render_scale;
+    settings.msaa_samples = ui_state.msaa_samples;
+    settings.stereo = ui_state.stereo;
+    settings.post_fx = ui_state.post_fx.clone();
+    settings.sides = ui_state.sides;
+    settings.tessellation = ui_state.tessellation;
+    settings.rendering_style = ui_state.rendering_style;
+    settings.active_shader = ui_state.active_shader.name().to_string();
+    settings.scene_in_panel = ui_state.scene_in_panel;
+}
+
+// Route a path picked in a file dialog to the loader for its kind
+fn handle_file_dialog_result(result: FileDialogResult, toasts: &mut Toasts) {
+    let verb = match result.action {
+        DialogAction::Open => "loading",
+        DialogAction::Save => "saving",
+    };
+    let message = format!("{} {} is not supported yet", result.kind.label(), verb);
+    log::warn!("{message}: {}", result.path.display());
+    toasts.warning(message);
+}
+
+// Saves the current layout to a preset file or applies one, reporting the outcome as a toast
+fn handle_preset_request(
+    request: PresetRequest,
+    ui_state: &mut UIState,
+    action_map: &mut ActionMap,
+    egui_renderer: &EguiRenderer,
+) {
+    match request {
+        PresetRequest::Save(path) => {
+            let preset = LayoutPreset {
+                theme: ui_state.theme.clone(),
+                ui_scale: ui_state.scale_factor,
+                keybindings: action_map.clone(),
+                shortcuts: ui_state.shortcuts.clone(),
+                egui_memory: egui_renderer.context().memory(|m| m.clone()),
+            };
+            match preset.save(&path) {
+                Ok(()) => ui_state.toasts.success(format!("Saved layout preset to {}", path.display())),
+                Err(err) => {
+                    log::warn!("Failed to save layout preset to {}: {err}", path.display());
+                    ui_state.toasts.error(format!("Failed to save layout preset: {err}"));
+                }
+            }
+        }
+        PresetRequest::Load(path) => match LayoutPreset::load(&path) {
+            Ok(preset) => {
+                ui_state.theme = preset.theme;
+                ui_state.set_ui_scale(preset.ui_scale);
+                *action_map = preset.keybindings;
+                ui_state.shortcuts = preset.shortcuts;
+                ui_state.shortcuts.drop_reserved();
+                egui_renderer.set_memory(preset.egui_memory);
+                ui_state.toasts.success(format!("Loaded layout preset {}", path.display()));
+            }
+            Err(err) => {
+                log::warn!("Failed to load layout preset from {}: {err}", path.display());
+                ui_state.toasts.error(format!("Failed to load layout preset: {err}"));
+            }
+        },
+    }
+    ui_state.presets.refresh();
+}
+
+// World-space ray through a cursor position given in physical pixels, over a scene shown at `(origin, size)` in
+// the window
+fn cursor_ray(camera: &Camera, (origin, size): (Vec2, Vec2), cursor: Vec2) -> (Vec3, Vec3) {
+    let cursor = cursor - origin;
+    let ndc = Vec2::new(cursor.x / size.x * 2.0 - 1.0, 1.0 - cursor.y / size.y * 2.0);
+    camera.screen_ray(ndc)
+}
+
+fn load_recording(path: &std::path::Path, toasts: &mut Toasts) -> Option<InputRecording> {
+    InputRecording::load(path)
+        .map_err(|err| {
+            log::warn!("Failed to load input recording from {}: {err}", path.display());
+            toasts.error(format!("Failed to load input recording: {err}"));
+        })
+        .ok()
+}
+
+// The shape the UI currently asks for
+fn mesh_shape(ui_state: &UIState) -> MeshShape {
+    match ui_state.rendering_style {
+        RenderingStyle::Polygon => MeshShape::Polygon {
+            sides: ui_state.sides,
+            radius: ui_state.radius,
+        },
+        // Hidden behind its particles, but still a cube for anything measuring the object
+        RenderingStyle::Cube | RenderingStyle::Particles => MeshShape::Cube,
+        RenderingStyle::Textured => MeshShape::TexturedCube,
+        style => MeshShape::Primitive(style.primitive(&ui_state.tessellation).unwrap()),
+    }
 }
 
+// A mesh file the object shows in place of its shape
+struct ImportedMesh {
+    parts: Vec<Entity>,      // Parented to the object, drawing the file's submeshes after the first
+    material: MaterialHandle, // The object's own, from before the file replaced it
+}
+
+impl ImportedMesh {
+    // Despawns the other submeshes, freeing their geometry, and gives the object back its material. The object's
+    // mesh is left to be replaced by whoever calls this
+    fn remove(self, world: &mut World, geometry: &mut GeometryArena, object: Entity) {
+        for part in self.parts {
+            if let Ok(mesh) = world.get::<&MeshHandle>(part) {
+                geometry.free(&mesh.allocation);
+            }
+            let _ = world.despawn(part);
+        }
+        let _ = world.insert_one(object, self.material);
+    }
+}
+
+// What loading a scene replaces, owned by the render loop
+struct SceneTargets<'a> {
+    world: &'a mut World,
+    object: Entity, // The object the UI edits
+    sun: Entity,
+    camera: &'a mut Camera,
+    background: &'a mut Vec3,
+    meshes: PrefabMeshes<'a>,
+    prefabs: &'a mut PrefabLibrary,
+    materials: &'a mut Materials,
+}
+
+fn current_scene(
+    ui_state: &UIState,
+    world: &World,
+    materials: &Materials,
+    object: Entity,
+    sun: Entity,
+    camera: &Camera,
+    background: Vec3,
+) -> SceneFile {
+    // The sun first, then any lights gameplay code added
+    // Prefab instances are saved as instances, not as the lights and emitters they are made of
+    let mut lights: Vec<(Entity, SceneLight)> = world
+        .query::<(Entity, &Light, &Transform)>()
+        .without::<&PrefabLink>()
+        .iter()
+        .map(|(entity, light, transform)| {
+            let light = SceneLight {
+                transform: *transform,
+                kind: light.kind,
+                color: light.color,
+                intensity: light.intensity,
+                enabled: light.enabled,
+                casts_shadows: light.casts_shadows,
+            };
+            (entity, light)
+        })
+        .collect();
+    lights.sort_by_key(|(entity, _)| (*entity != sun, *entity));
+    // The Particles rendering style's emitter is left out, as it comes back with the style
+    let mut emitters: Vec<(Entity, SceneEmitter)> = world
+        .query::<(Entity, &ParticleEmitter, &Transform, Option<&Parent>)>()
+        .without::<&PrefabLink>()
+        .iter()
+        .filter(|(_, _, _, parent)| parent.is_none_or(|parent| parent.0 != object))
+        .map(|(entity, emitter, transform, _)| {
+            let emitter = SceneEmitter {
+                transform: *transform,
+                emitter: emitter.clone(),
+            };
+            (entity, emitter)
+        })
+        .collect();
+    emitters.sort_by_key(|(entity, _)| *entity);
+    let mut decals: Vec<(Entity, SceneDecal)> = world
+        .query::<(Entity, &Decal, &Transform)>()
+        .without::<&PrefabLink>()
+        .iter()
+        .map(|(entity, decal, transform)| {
+            let decal = SceneDecal {
+                transform: *transform,
+                decal: decal.clone(),
+            };
+            (entity, decal)
+        })
+        .collect();
+    decals.sort_by_key(|(entity, _)| *entity);
+    let mut billboards: Vec<(Entity, SceneBillboard)> = world
+        .query::<(Entity, &Billboard, &Transform)>()
+        .without::<&PrefabLink>()
+        .iter()
+        .map(|(entity, billboard, transform)| {
+            let billboard = SceneBillboard {
+                transform: *transform,
+                billboard: billboard.clone(),
+            };
+            (entity, billboard)
+        })
+        .collect();
+    billboards.sort_by_key(|(entity, _)| *entity);
+    let mut probes: Vec<(Entity, SceneProbe)> = world
+        .query::<(Entity, &ReflectionProbe, &Transform)>()
+        .without::<&PrefabLink>()
+        .iter()
+        .map(|(entity, probe, transform)| {
+            let probe = SceneProbe {
+                transform: *transform,
+                probe: probe.clone(),
+            };
+            (entity, probe)
+        })
+        .collect();
+    probes.sort_by_key(|(entity, _)| *entity);
+    SceneFile {
+        version: SCENE_VERSION,
+        rendering_style: ui_state.rendering_style,
+        sides: ui_state.sides,
+        radius: ui_state.radius,
+        tessellation: ui_state.tessellation,
+        transform: world::transform(world, object),
+        shader: Some(ui_state.active_shader.name().to_string()),
+        body: world.get::<&RigidBody>(object).ok().map(|body| body.kind),
+        collider: world.get::<&Collider>(object).ok().map(|collider| (*collider).clone()),
+        script: world.get::<&Script>(object).ok().map(|script| script.path.clone()),
+        material: world.get::<&MaterialHandle>(object).ok().map(|material| material.0.index()),
+        lights: lights.into_iter().map(|(_, light)| light).collect(),
+        camera: Some(SceneCamera::from(camera)),
+        background,
+        emitters: emitters.into_iter().map(|(_, emitter)| emitter).collect(),
+        decals: decals.into_iter().map(|(_, decal)| decal).collect(),
+        billboards: billboards.into_iter().map(|(_, billboard)| billboard).collect(),
+        probes: probes.into_iter().map(|(_, probe)| probe).collect(),
+        prefabs: prefab::save_instances(world),
+        materials: materials.save(),
+        nodes: scene_tree::save_nodes(world, object),
+    }
+}
+
+fn save_scene(
+    path: PathBuf,
+    ui_state: &mut UIState,
+    scene: SceneFile,
+    scenes: &mut Assets<SceneFile>,
+    watched_scene: &mut Option<Handle<SceneFile>>,
+) {
+    match scene.save(&path) {
+        Ok(()) => {
+            ui_state.toasts.success(format!("Saved scene to {}", path.display()));
+            // Watched afresh, so the write just made doesn't come back as an outside change
+            if let Some(previous) = watched_scene.replace(scenes.insert(path.clone(), scene, true)) {
+                scenes.remove(previous);
+            }
+            ui_state.scene_saved(path);
+        }
+        Err(err) => {
+            log::warn!("Failed to save scene to {}: {err}", path.display());
+            ui_state.toasts.error(format!("Failed to save scene: {err}"));
+            ui_state.exit_after_save = false;
+        }
+    }
+}
+
+// Scenes from peers have no path, and keep the one any scene was last loaded from or saved to
+fn apply_scene(scene: &SceneFile, path: Option<&Path>, ui_state: &mut UIState, targets: &mut SceneTargets) {
+    ui_state.rendering_style = scene.rendering_style;
+    ui_state.sides = scene.sides.clamp(MIN_SIDES, MAX_SIDES);
+    ui_state.radius = scene.radius;
+    ui_state.tessellation = scene.tessellation.clamped();
+    ui_state.morph.playing = false;
+    ui_state.animation.playing = false;
+    ui_state.sequencer.playing = false;
+    ui_state.physics.running = false;
+    if let Some(shader) = scene.shader.as_deref() {
+        match ui_state.shader_named(shader) {
+            Some(id) => ui_state.active_shader = id,
+            None => log::warn!("Scene uses unknown shader {shader:?}; keeping {}", ui_state.active_shader.name()),
+        }
+    }
+
+    let world = &mut *targets.world;
+    let object = targets.object;
+    *world::transform_mut(world, object) = scene.transform;
+    let _ = world.remove_one::<RigidBody>(object);
+    let _ = world.remove_one::<Collider>(object);
+    if let Some(kind) = scene.body {
+        let _ = world.insert_one(object, RigidBody { kind });
+    }
+    if let Some(collider) = &scene.collider {
+        let _ = world.insert_one(object, collider.clone());
+    }
+    match &scene.script {
+        Some(path) => ui_state.scripts.attach(world, object, path.clone()),
+        None => ui_state.scripts.detach(world, object),
+    }
+    let meshes = &mut targets.meshes;
+    let restored = targets.materials.restore(meshes.device, meshes.stats, &scene.materials);
+    if let Some(id) = scene.material.and_then(|index| restored.get(index).copied().flatten()) {
+        let _ = world.insert_one(object, MaterialHandle(id));
+    }
+    let material = |index: usize| restored.get(index).copied().flatten();
+    scene_tree::restore_nodes(world, meshes, &scene.nodes, object, material);
+
+    let instances: Vec<Entity> = world.query::<(Entity, &PrefabInstance)>().iter().map(|(root, _)| root).collect();
+    for root in instances {
+        prefab::despawn_instance(world, &mut targets.meshes, root);
+    }
+    for saved in &scene.prefabs {
+        if targets.prefabs.restore(world, &mut targets.meshes, saved).is_none() {
+            log::warn!("Skipping malformed instance of {} in scene", saved.path.display());
+        }
+    }
+
+    // The sun takes the first light; any others, and every emitter, decal, billboard and probe, replace the ones
+    // in the world
+    let replaced: Vec<Entity> = world
+        .query::<(
+            Entity,
+            Option<&Light>,
+            Option<&ParticleEmitter>,
+            Option<&Decal>,
+            Option<&Billboard>,
+            Option<&ReflectionProbe>,
+        )>()
+        .without::<&PrefabLink>()
+        .iter()
+        .filter(|(entity, light, emitter, decal, billboard, probe)| {
+            let replaced = emitter.is_some() || decal.is_some() || billboard.is_some() || probe.is_some();
+            (light.is_some() && *entity != targets.sun) || replaced
+        })
+        .map(|(entity, ..)| entity)
+        .collect();
+    for entity in replaced {
+        let _ = world.despawn(entity);
+    }
+    let mut lights = scene.lights.iter();
+    let sun = lights.next().cloned().unwrap_or(SceneLight {
+        intensity: 0.0,
+        ..SceneLight::default()
+    });
+    *world::transform_mut(world, targets.sun) = sun.transform;
+    if let Ok(mut light) = world.get::<&mut Light>(targets.sun) {
+        light.kind = sun.kind;
+        light.color = sun.color;
+        light.intensity = sun.intensity;
+        light.enabled = sun.enabled;
+        light.casts_shadows = sun.casts_shadows;
+    }
+    for light in lights {
+        let component = Light {
+            kind: light.kind,
+            color: light.color,
+            intensity: light.intensity,
+            enabled: light.enabled,
+            casts_shadows: light.casts_shadows,
+        };
+        world.spawn((light.transform, GlobalTransform(light.transform.matrix()), component));
+    }
+    for emitter in &scene.emitters {
+        let transform = emitter.transform;
+        world.spawn((transform, GlobalTransform(transform.matrix()), emitter.emitter.clone()));
+    }
+    for decal in &scene.decals {
+        let transform = decal.transform;
+        world.spawn((transform, GlobalTransform(transform.matrix()), decal.decal.clone()));
+    }
+    for billboard in &scene.billboards {
+        let transform = billboard.transform;
+        world.spawn((transform, GlobalTransform(transform.matrix()), billboard.billboard.clone()));
+    }
+    for probe in &scene.probes {
+        let transform = probe.transform;
+        world.spawn((transform, GlobalTransform(transform.matrix()), probe.probe.clone()));
+    }
+
+    if let Some(camera) = &scene.camera {
+        *targets.camera = camera.apply(targets.camera);
+    }
+    *targets.background = scene.background;
+    ui_state.history.reset();
+    if let Some(path) = path {
+        ui_state.scene_path = Some(path.to_path_buf());
+    }
+}
+
+// The fixed update's state at the start of a frame, for the session replay to rewind to
+#[allow(clippy::too_many_arguments)]
+fn session_snapshot(
+    ui_state: &UIState,
+    world: &World,
+    materials: &Materials,
+    object: Entity,
+    sun: Entity,
+    camera: &Camera,
+    background: Vec3,
+    input: &InputState,
+    camera_controller: &CameraController,
+) -> SessionSnapshot {
+    SessionSnapshot {
+        scene: current_scene(ui_state, world, materials, object, sun, camera, background),
+        input: input.clone(),
+        camera_controller: camera_controller.clone(),
+        morph: ui_state.morph.clone(),
+        animation: ui_state.animation.clone(),
+        sequencer: ui_state.sequencer.clone(),
+        physics: ui_state.physics.snapshot(),
+        hour: ui_state.environment.hour,
+    }
+}
+
+// Rewinds to a session replay keyframe: the scene as a loaded one would be, then what the fixed update keeps
+// between frames. Scripts and particles start over, as they do when a scene is loaded
+fn restore_session(
+    snapshot: &SessionSnapshot,
+    ui_state: &mut UIState,
+    targets: &mut SceneTargets,
+    input: &mut InputState,
+    camera_controller: &mut CameraController,
+) {
+    apply_scene(&snapshot.scene, None, ui_state, targets);
+    *input = snapshot.input.clone();
+    *camera_controller = snapshot.camera_controller.clone();
+    ui_state.morph = snapshot.morph.clone();
+    ui_state.animation = snapshot.animation.clone();
+    ui_state.sequencer = snapshot.sequencer.clone();
+    ui_state.physics.restore(&snapshot.physics);
+    ui_state.environment.hour = snapshot.hour;
+}
+
+fn handle_scene_event(
+    event: AssetEvent<SceneFile>,
+    scenes: &mut Assets<SceneFile>,
+    watched_scene: &mut Option<Handle<SceneFile>>,
+    ui_state: &mut UIState,
+    targets: &mut SceneTargets,
+) {
+    match event {
+        AssetEvent::Loaded(handle) => {
+            if let Some(previous) = watched_scene.replace(handle) {
+                scenes.remove(previous);
+            }
+            if let (Some(scene), Some(path)) = (scenes.get(handle), scenes.path(handle)) {
+                apply_scene(scene, Some(path), ui_state, targets);
+                ui_state.toasts.success(format!("Loaded scene {}", path.display()));
+            }
+        }
+        AssetEvent::Reloaded(handle) => {
+            let (Some(scene), Some(path)) = (scenes.get(handle), scenes.path(handle)) else {
+                return;
+            };
+            if ui_state.history.is_dirty() {
+                ui_state.toasts.warning(format!("{} changed on disk; keeping unsaved edits", path.display()));
+            } else {
+                apply_scene(scene, Some(path), ui_state, targets);
+                ui_state.toasts.info(format!("Reloaded scene {}", path.display()));
+            }
+        }
+        AssetEvent::Failed(handle, err) => {
+            let path = scenes.path(handle).map(Path::to_path_buf).unwrap_or_default();
+            log::warn!("Failed to load scene from {}: {err}", path.display());
+            if let Some(LoadState::Failed(_)) = scenes.state(handle) {
+                scenes.remove(handle);
+                ui_state.toasts.error(format!("Failed to load scene: {err}"));
+            } else {
+                ui_state.toasts.warning(format!("Failed to reload scene: {err}"));
+            }
+        }
+    }
+}
+
+/// Runs the template with its demo gallery, starting on the demo `--demo` names if any.
 pub async fn run() {
-    let event_loop = EventLoop::new().unwrap();
+    run_app(DemoGallery::from_args(&std::env::args().collect::<Vec<_>>())).await
+}
+
+// The activity Android started the app with, needed to build its event loop
+#[cfg(target_os = "android")]
+static ANDROID_APP: std::sync::OnceLock<winit::platform::android::activity::AndroidApp> = std::sync::OnceLock::new();
+
+/// Entry point Android's NativeActivity calls into the cdylib with.
+#[cfg(target_os = "android")]
+#[no_mangle]
+fn android_main(app: winit::platform::android::activity::AndroidApp) {
+    console::init_logger();
+    let _ = ANDROID_APP.set(app);
+    pollster::block_on(run());
+}
+
+// The id of the canvas `run_web` was asked to draw into
+#[cfg(target_arch = "wasm32")]
+static WEB_CANVAS: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
+/// Entry point for the web build: runs the template in the `<canvas>` with id `canvas_id`, which is sized to
+/// fill the browser window. Returns once the app is started; it keeps running from the browser's event loop.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+pub fn run_web(canvas_id: &str) {
+    console_error_panic_hook::set_once();
+    console::init_logger();
+    let _ = WEB_CANVAS.set(canvas_id.to_string());
+    // The adapter and device are requested asynchronously in a browser, which can't block on them
+    wasm_bindgen_futures::spawn_local(run());
+}
 
-    let builder = winit::window::WindowBuilder::new().with_title("Voxxele");
+/// Runs the template with `app` hooked into its setup, frame updates and UI, configured by `app_config.ron` in
+/// the working directory if there is one.
+pub async fn run_app(app: impl Application) {
+    run_app_with_config(app, AppConfig::load()).await
+}
+
+/// Runs the template with `app` as `run_app` does, configured by `app_config` instead of the config file.
+pub async fn run_app_with_config(mut app: impl Application, app_config: AppConfig) {
+    crash_report::install();
+    let mut builder = EventLoopBuilder::<UserEvent>::with_user_event();
+    #[cfg(target_os = "android")]
+    {
+        use winit::platform::android::EventLoopBuilderExtAndroid;
+        builder.with_android_app(ANDROID_APP.get().expect("Started without android_main").clone());
+    }
+    #[cfg_attr(not(target_os = "android"), allow(unused_mut))]
+    let mut event_loop = builder.build().unwrap();
+
+    // Hidden until AccessKit is attached, which has to happen before the window is first shown
+    let builder = winit::window::WindowBuilder::new().with_title(&app_config.title).with_visible(false);
+    // In a browser the window is a canvas already on the page
+    #[cfg(target_arch = "wasm32")]
+    let builder = {
+        use winit::platform::web::WindowBuilderExtWebSys;
+        builder.with_canvas(Some(web::canvas(WEB_CANVAS.get().expect("Started without run_web"))))
+    };
     let window = builder.build(&event_loop).unwrap();
     let window = Arc::new(window);
-    let initial_width = 1360;
-    let initial_height = 768;
-    window.request_inner_size(PhysicalSize::new(initial_width, initial_height));
+
+    // Android has no native window to draw into until the activity resumes
+    #[cfg(target_os = "android")]
+    {
+        use winit::platform::pump_events::EventLoopExtPumpEvents;
+        let mut resumed = false;
+        while !resumed {
+            let _ = event_loop.pump_events(None, |event, _| resumed |= matches!(event, Event::Resumed));
+        }
+    }
+
+    // The canvas follows the browser window, and its size changes arrive as resizes like a desktop window's
+    #[cfg(target_arch = "wasm32")]
+    let (initial_width, initial_height) = web::fill_browser_window(window.clone());
+    // Phones and tablets are always full screen; on the desktop the window starts at a fixed size
+    #[cfg(not(target_arch = "wasm32"))]
+    let (initial_width, initial_height) = if cfg!(any(target_os = "android", target_os = "ios")) {
+        let size = window.inner_size();
+        (size.width.max(1), size.height.max(1))
+    } else {
+        let (width, height) = app_config.window_size;
+        let _ = window.request_inner_size(PhysicalSize::new(width, height));
+        (width.max(1), height.max(1))
+    };
 
     let mut camera = Camera::new(Vec3::new(0.0, 0.0, 2.0), Vec3::ZERO, 0.1);
+    camera.aspect = initial_width as f32 / initial_height as f32;
+    let initial_camera = camera;
+    let camera_controller = CameraController::new(2.0, 0.4);
 
-    // Create the wgpu instance and surface
-    let instance = egui_wgpu::wgpu::Instance::new(InstanceDescriptor::default());
-    let surface = instance
-        .create_surface(window.clone())
-        .expect("Failed to create surface!");
-
-    let power_pref = PowerPreference::default();
-    let adapter = instance
-        .request_adapter(&RequestAdapterOptions {
-            power_preference: power_pref,
-            force_fallback_adapter: false,
-            compatible_surface: Some(&surface),
-        })
-        .await
-        .expect("Failed to find an appropriate adapter");
-
-    let features = wgpu::Features::empty();
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                required_features: features,
-                required_limits: Default::default(),
-            },
-            None,
-        )
-        .await
-        .expect("Failed to create device");
-
-    let swapchain_capabilities = surface.get_capabilities(&adapter);
-    let selected_format = TextureFormat::Bgra8UnormSrgb;
-    let swapchain_format = swapchain_capabilities
-        .formats
-        .iter()
-        .find(|d| **d == selected_format)
-        .expect("failed to select proper surface texture format!");
-
-    let mut config = wgpu::SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: *swapchain_format,
-        width: initial_width,
-        height: initial_height,
-        present_mode: wgpu::PresentMode::AutoVsync,
-        desired_maximum_frame_latency: 0,
-        alpha_mode: swapchain_capabilities.alpha_modes[0],
-        view_formats: vec![],
+    // `--benchmark [frames]` measures a fixed scene and exits; frame times mean nothing capped by vsync
+    let benchmark = Benchmark::from_args(&std::env::args().collect::<Vec<_>>());
+    let present_mode = match (&benchmark, app_config.vsync) {
+        (Some(_), _) | (None, Some(false)) => wgpu::PresentMode::AutoNoVsync,
+        (None, _) => wgpu::PresentMode::AutoVsync,
     };
 
-    surface.configure(&device, &config);
+    // The app config's backend and adapter are used over the ones last picked in the About / Diagnostics window
+    let settings = Settings::load();
+    let adapter_request = AdapterRequest {
+        backends: match app_config.backend {
+            Backend::Auto => settings.backend.wgpu(),
+            backend => backend.wgpu(),
+        },
+        power_preference: app_config.power_preference.wgpu(),
+        name: app_config.adapter.clone().or_else(|| settings.adapter.clone()),
+    }
+    .with_env();
+    let GpuContext {
+        instance,
+        surface,
+        adapter,
+        device,
+        queue,
+        config,
+        capabilities,
+        mut xr,
+    } = {
+        // `--xr` draws to a headset as well, where the build and the machine allow
+        let xr = XrSession::requested(&std::env::args().collect::<Vec<_>>());
+        GpuContext::new(window.clone(), initial_width, initial_height, present_mode, &adapter_request, xr).await
+    };
+    let gpu_errors = GpuErrors::install(&device);
+    let gpu_timings = GpuTimings::new();
 
-    // Load shaders
-    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Main Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-    });
-    
-    let challenge_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Challenge Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("challenge_shader.wgsl").into()),
-    });
-    
-    // Pipeline compilation options
+    // Pipeline-overridable constants shared by the scene pipelines
     let mut constants = HashMap::new();
     constants.insert("MY_CONSTANT".to_string(), 1.0); // Example constant value, replace as needed
 
-    let compilation_options = wgpu::PipelineCompilationOptions {
-        constants: &constants, // Pipeline-overridable constants
-        zero_initialize_workgroup_memory: true, // Set based on your requirements
-    };
+    let mut render_stats = RenderStats::new();
+    let mut bind_groups = BindGroupCache::new();
 
-    // Group vertex and fragment state creation together
-    let vertex_state_main = wgpu::VertexState {
-        module: &shader,
-        entry_point: "vs_main",
-        buffers: &[Vertex::desc()], // Use the Vertex description
-        compilation_options: compilation_options.clone(), // Added compilation options
-    };
+    // Camera uniform buffer and bind group
+    let mut camera_uniform = CameraUniform::new();
+    camera_uniform.update_view_proj(&camera);
 
-    let fragment_state_main = wgpu::FragmentState {
-        module: &shader,
-        entry_point: "fs_main", // Entry point in your fragment shader
-        targets: &[Some(wgpu::ColorTargetState {
-            format: config.format,
-            blend: Some(wgpu::BlendState {
-                color: wgpu::BlendComponent::REPLACE,
-                alpha: wgpu::BlendComponent::REPLACE,
-            }),
-            write_mask: wgpu::ColorWrites::ALL,
-        })],
-        compilation_options: compilation_options.clone(),
-    };
+    let camera_buffer = Arc::new(render_stats.create_buffer_init(
+        &device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[camera_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        },
+    ));
+
+    // The scene's lights share the camera's group, so every scene pipeline can read them
+    let lighting = Lighting::new(&device, &mut render_stats);
+    let camera_bind_group_layout = camera::bind_group_layout(&device);
+
+    let camera_bind_group = bind_groups.get(
+        &device,
+        "Camera Bind Group",
+        &camera_bind_group_layout,
+        &[(0, &camera_buffer), (1, lighting.buffer())],
+    );
 
-    let vertex_state_challenge = wgpu::VertexState {
-        module: &challenge_shader,
-        entry_point: "vs_main",
-        buffers: &[Vertex::desc()], // Use the Vertex description
-        compilation_options: compilation_options.clone(),
+    // Per-object uniforms for every drawn entity's transform, packed with dynamic offsets
+    let mut initial_transform = Transform::IDENTITY;
+    let objects = ObjectUniforms::new(&device, &mut render_stats);
+
+    // `--shaders <manifest.ron>` swaps built-in shaders for WGSL, SPIR-V or naga modules on disk. A browser has
+    // neither, so the web build draws with the built-in shaders compiled into it
+    let manifest_path = if cfg!(target_arch = "wasm32") {
+        None
+    } else {
+        std::env::args().skip_while(|arg| arg != "--shaders").nth(1).map(PathBuf::from)
     };
+    let shaders = ShaderLibrary::load(manifest_path.as_deref());
+    // The built-in scene shaders, then the app's
+    let mut registry = ShaderRegistry::new(&shaders);
+    app.shaders(&mut registry);
 
-    let fragment_state_challenge = wgpu::FragmentState {
-        module: &challenge_shader,
-        entry_point: "fs_main", // Entry point in your fragment shader
-        targets: &[Some(wgpu::ColorTargetState {
-            format: config.format,
-            blend: Some(wgpu::BlendState {
-                color: wgpu::BlendComponent::REPLACE,
-                alpha: wgpu::BlendComponent::REPLACE,
-            }),
-            write_mask: wgpu::ColorWrites::ALL,
-        })],
-        compilation_options: compilation_options.clone(),
+    // Uniforms and textures in group 2, reflected from each shader; every material of a shader starts from
+    // the defaults it was registered with. A shader that doesn't validate, or whose parameters can't be reflected,
+    // can't be drawn with, so it's left out, or for the main shader swapped for the built-in one
+    let mut shader_errors = shaders.errors().to_vec();
+    let mut shader_params = Vec::new();
+    let reflect = |registry: &ShaderRegistry, id: ShaderId| {
+        let program = &registry.get(id).program;
+        program.validate().and_then(|()| ShaderParams::new(&device, id.name(), program))
     };
+    for id in registry.variants().iter().map(|variant| variant.id).collect::<Vec<_>>() {
+        let mut params = reflect(&registry, id);
+        if let (Err(err), true) = (&params, id == ShaderId::MAIN) {
+            shader_errors.push(format!("Drawing with the built-in main shader: {err}"));
+            registry.set_program(id, ShaderLibrary::builtin("main"));
+            params = reflect(&registry, id);
+        }
+        let variant = registry.get(id);
+        match params {
+            Ok(mut params) => {
+                for (name, values) in &variant.defaults {
+                    params.set(name, values);
+                }
+                shader_params.push(params);
+            }
+            Err(err) if id == ShaderId::MAIN => panic!("Failed to reflect the built-in main shader: {err}"),
+            Err(err) => {
+                shader_errors.push(format!("Shader {:?} left out: {err}", id.name()));
+                registry.unregister(id);
+            }
+        }
+    }
+    let mut uploads = Uploads::new();
+    let mut materials = Materials::new(&device, &mut render_stats, &mut uploads, shader_params);
 
-    // Create render pipeline layout
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("Render Pipeline Layout"),
-        bind_group_layouts: &[],
-        push_constant_ranges: &[],
-    });
+    // The scene is drawn in HDR and post-processed into the surface's format, unless it goes to a headset, whose
+    // swapchain takes the surface's format, or the adapter can't render HDR textures. Every pipeline drawing into
+    // the scene uses `scene_format`
+    let post_processing = !XrSession::requested(&std::env::args().collect::<Vec<_>>())
+        && PostProcessor::is_supported(&adapter);
+    let mut post = post_processing.then(|| PostProcessor::new(&device, &mut render_stats, config.format));
+    let scene_format = if post.is_some() { HDR_FORMAT } else { config.format };
 
-    // Create the main render pipeline
-    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(&render_pipeline_layout),
-        vertex: vertex_state_main,
-        fragment: Some(fragment_state_main),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            polygon_mode: wgpu::PolygonMode::Fill,
-            unclipped_depth: false,
-            conservative: false,
-        },
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
-    });
+    let stereo = StereoRenderer::new(
+        &device,
+        &mut render_stats,
+        scene_format,
+        &camera_bind_group_layout,
+        lighting.buffer(),
+    );
+    let scene_windows = SceneWindows::new(scene_format);
+    let viewport_panel = ViewportPanel::new(config.format);
+    let probes = ProbeRenderer::new(
+        &device,
+        &mut render_stats,
+        scene_format,
+        &camera_bind_group_layout,
+        lighting.buffer(),
+    );
 
-    // Create the challenge render pipeline
-    let challenge_render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Challenge Render Pipeline"),
-        layout: Some(&render_pipeline_layout),
-        vertex: vertex_state_challenge,
-        fragment: Some(fragment_state_challenge),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            polygon_mode: wgpu::PolygonMode::Fill,
-            unclipped_depth: false,
-            conservative: false,
-        },
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
+    // Create a render pipeline layout per shader; they differ in the parameter group, and only shaders that read
+    // the reflection probes take them in group 3
+    let pipeline_layouts: BTreeMap<ShaderId, Arc<wgpu::PipelineLayout>> = registry
+        .variants()
+        .iter()
+        .map(|variant| {
+            let mut groups =
+                vec![&camera_bind_group_layout, &objects.bind_group_layout, materials.layout(variant.id.name())];
+            if variant.reflections {
+                groups.push(&probes.bind_group_layout);
+            }
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(variant.label),
+                bind_group_layouts: &groups,
+                push_constant_ranges: &[],
+            });
+            (variant.id, Arc::new(layout))
+        })
+        .collect();
+
+    let scene_target = SceneTarget::new(&device, config.format);
+    let scene_depth = DepthBuffer::new();
+    let outline = SelectionOutline::new(
+        &device,
+        &mut render_stats,
+        scene_format,
+        &camera_bind_group_layout,
+        &objects.bind_group_layout,
+    );
+    let skinning = capabilities.vertex_storage.then(|| {
+        SkinningRenderer::new(&device, scene_format, &camera_bind_group_layout, &objects.bind_group_layout)
     });
+    let instancing =
+        InstancingRenderer::new(&device, scene_format, &camera_bind_group_layout, &objects.bind_group_layout);
+    let particles = capabilities.compute.then(|| ParticleSystem::new(&device, &mut render_stats, scene_format));
+    let environment_map = EnvironmentMap::new(&device, &mut render_stats, capabilities.compute);
+    let sky = SkyRenderer::new(&device, &mut render_stats, scene_format, &environment_map.bind_group_layout);
+    let grid = GridRenderer::new(&device, &mut render_stats, scene_format);
+    let water = WaterRenderer::new(&device, &mut render_stats, scene_format);
+    let decals = DecalRenderer::new(&device, &mut render_stats, &mut uploads, scene_format);
+    let billboards = BillboardRenderer::new(&device, &mut render_stats, &mut uploads, scene_format);
+    let terrain = TerrainRenderer::new(
+        &device,
+        &mut render_stats,
+        &mut uploads,
+        scene_format,
+        &camera_bind_group_layout,
+    );
+    let debug_lines = DebugRenderer::new(&device, scene_format, &camera_bind_group_layout);
+
+    // The scene pass is multisampled at the saved sample count, or not at all if the adapter can't do it
+    let sample_counts = msaa::supported_sample_counts(&adapter, capabilities.features, scene_format);
+    let samples = Some(settings.msaa_samples).filter(|samples| sample_counts.contains(samples)).unwrap_or(1);
+    let msaa = Msaa::new(&device, scene_format, samples);
+
+    // The scene pipelines compile on worker threads; until one is ready the scene is drawn with the fallback,
+    // which is small enough to build up front
+    let shader_cache = Arc::new(ShaderCache::load(&adapter.get_info()));
+    // Every scene pipeline built so far, so toggling MSAA or wireframe back doesn't compile anything
+    let pipeline_cache = Arc::new(PipelineCache::new());
+    let create_fallback = {
+        let (device, cache, program) = (device.clone(), shader_cache.clone(), shaders.get("fallback"));
+        let (layout, format, constants) = (pipeline_layouts[&ShaderId::MAIN].clone(), scene_format, constants.clone());
+        let pipeline_cache = pipeline_cache.clone();
+        move |samples| {
+            let label = "Fallback Render Pipeline";
+            // Only drawn until the scene pipelines are ready, so always filled
+            let key = PipelineKey {
+                label,
+                program: pipelines::program_key(&program),
+                format,
+                samples,
+                polygon_mode: wgpu::PolygonMode::Fill,
+            };
+            pipeline_cache.get_or_create(key, || {
+                pipelines::create_scene_pipeline(
+                    &device,
+                    &cache,
+                    label,
+                    &program,
+                    &layout,
+                    format,
+                    &constants,
+                    samples,
+                    wgpu::PolygonMode::Fill,
+                )
+            })
+        }
+    };
+    let fallback_pipeline = create_fallback(1);
+    // The fallback for the scene pass, with as many samples as `msaa`
+    let fallback_multisampled = (samples > 1).then(|| create_fallback(samples));
+    // One pipeline per registered shader, labeled with its name
+    let scene_pipelines: BTreeMap<ShaderId, PendingPipeline> = registry
+        .variants()
+        .iter()
+        .map(|variant| {
+            let pipeline = PendingPipeline::spawn(
+                device.clone(),
+                shader_cache.clone(),
+                pipeline_cache.clone(),
+                variant.id.name(),
+                variant.program.clone(),
+                pipeline_layouts[&variant.id].clone(),
+                scene_format,
+                constants.clone(),
+                samples,
+            );
+            (variant.id, pipeline)
+        })
+        .collect();
 
-    let mut sides: u16 = 5; 
-    let mut rendering_style = RenderingStyle::Polygon; // Default to polygon
-    let mut previous_sides = sides;
+    let mut ui_state = UIState::new();
+    ui_state.events.wake_with(event_loop.create_proxy());
+    ui_state.shaders = registry.choices();
+    for err in shader_errors {
+        ui_state.toasts.error(err);
+    }
+    ui_state.msaa_samples = samples;
+    ui_state.sample_counts = sample_counts;
+    let render_modes = RenderMode::ALL.into_iter().filter(|mode| mode.is_supported(capabilities.features));
+    ui_state.render_modes = render_modes.collect();
+    ui_state.compute = capabilities.compute;
+    ui_state.present_modes = surface.get_capabilities(&adapter).present_modes;
+    for panel in app.panels() {
+        ui_state.register_panel(panel);
+    }
+    if let Some(post) = &mut post {
+        for pass in app.post_passes(&device) {
+            post.add_pass(pass);
+        }
+    }
+    // Saving a registered shader's file in `shaders/`, such as `shader.wgsl`, rebuilds the pipeline that draws
+    // with it
+    #[cfg(not(target_arch = "wasm32"))]
+    ui_state.shader_reload.watch(Path::new(shader_reload::SHADER_DIR), registry.reload_files());
+
+    // Plugin libraries register their panels, systems and overlays once, here; they stay loaded until exit
+    let (plugins, plugin_errors) = PluginHost::load(&std::env::args().collect::<Vec<_>>());
+    let (plugin_overlays, overlay_errors) = PluginOverlays::new(&device, &mut render_stats, scene_format, &plugins);
+    for err in plugin_errors.into_iter().chain(overlay_errors) {
+        ui_state.toasts.error(err);
+    }
+    ui_state.network.start_from_args(&std::env::args().collect::<Vec<_>>(), &mut ui_state.toasts);
+    let diagnostics = Diagnostics::new(&adapter, &device, &surface, &capabilities, gpu_context::all_adapters());
+    crash_report::set_diagnostics(diagnostics.report());
+    ui_state.diagnostics = Some(diagnostics);
+    // Mobile systems take the window's surface away while the app is in the background
+    let surface = Some(surface);
+    xr.attach(
+        &device,
+        &mut render_stats,
+        &camera_bind_group_layout,
+        lighting.buffer(),
+        config.format,
+        &mut ui_state.toasts,
+    );
+    ui_state.theme = settings.theme.clone();
+    ui_state.set_ui_scale(settings.ui_scale);
+    ui_state.display_scale = settings
+        .display_scale
+        .map(|scale| scale.clamp(*DISPLAY_SCALE_RANGE.start(), *DISPLAY_SCALE_RANGE.end()));
+    let action_map = settings.keybindings.clone();
+    ui_state.shortcuts = settings.shortcuts.clone();
+    ui_state.shortcuts.drop_reserved();
+    ui_state.memory_budget_mib = settings.memory_budget_mib;
+    ui_state.pacer.set_refresh_rate(window.current_monitor().and_then(|m| m.refresh_rate_millihertz()));
+    ui_state.frame_latency = settings
+        .frame_latency
+        .clamp(*settings::FRAME_LATENCY_RANGE.start(), *settings::FRAME_LATENCY_RANGE.end());
+    ui_state.background_mode = settings.background_mode;
+    ui_state.redraw_on_demand = settings.redraw_on_demand;
+    ui_state.resolution.enabled = settings.dynamic_resolution;
+    ui_state.resolution.target_fps = settings.target_fps.clamp(*TARGET_FPS_RANGE.start(), *TARGET_FPS_RANGE.end());
+    ui_state.resolution.filter = settings.upscale_filter;
+    ui_state.resolution.render_scale =
+        settings.render_scale.clamp(*RENDER_SCALE_RANGE.start(), *RENDER_SCALE_RANGE.end());
+    ui_state.stereo = settings.stereo;
+    ui_state.post_fx = settings.post_fx.clone();
+    ui_state.background_fps = settings
+        .background_fps
+        .clamp(*settings::BACKGROUND_FPS_RANGE.start(), *settings::BACKGROUND_FPS_RANGE.end());
+    ui_state.pacing_mode = settings.pacing_mode;
+    ui_state.fps_cap = settings.fps_cap.clamp(*settings::FPS_CAP_RANGE.start(), *settings::FPS_CAP_RANGE.end());
+    // Settings may come from a machine whose surface offered other modes
+    ui_state.present_mode = match settings.present_mode.wgpu() {
+        Some(mode) if !ui_state.present_modes.contains(&mode) => PresentModePreference::Automatic,
+        _ => settings.present_mode,
+    };
+    ui_state.sides = settings.sides.clamp(MIN_SIDES, MAX_SIDES);
+    ui_state.tessellation = settings.tessellation.clamped();
+    ui_state.rendering_style = settings.rendering_style;
+    ui_state.active_shader = registry.resolve(&settings.active_shader);
+    ui_state.scene_in_panel = settings.scene_in_panel;
+    // The app's config replaces what the user saved, for the options it sets
+    if let Some(vsync) = app_config.vsync {
+        ui_state.pacing_mode = if vsync { PacingMode::Vsync } else { PacingMode::Uncapped };
+    }
+    if let Some(scale) = app_config.scale_factor {
+        ui_state.display_scale = Some(scale.clamp(*DISPLAY_SCALE_RANGE.start(), *DISPLAY_SCALE_RANGE.end()));
+    }
+
+    // Nothing the user set may change what a benchmark draws or how often
+    if benchmark.is_some() {
+        let scene = Benchmark::scene();
+        ui_state.rendering_style = scene.rendering_style;
+        ui_state.sides = scene.sides;
+        ui_state.radius = scene.radius;
+        initial_transform = scene.transform;
+        ui_state.redraw_on_demand = false;
+        ui_state.resolution.enabled = false;
+        ui_state.scene_in_panel = false;
+    }
 
     // Generate polygon vertices and indices
-    let (vertices, indices) = Vertex::generate_polygon(sides, 0.5);
+    let initial_shape = mesh_shape(&ui_state);
+    let (vertices, indices) = initial_shape.generate();
+    let bounds = Aabb::from_points(vertices.iter().map(|v| Vec3::from(v.position)));
 
-    // Create the vertex buffer
-    let mut vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Vertex Buffer"),
-        contents: bytemuck::cast_slice(&vertices),
-        usage: wgpu::BufferUsages::VERTEX,
-    });
+    let submissions = SubmissionScheduler::new();
+    let buffer_pool = BufferPool::new();
 
-    // Create the index buffer
-    let mut index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Index Buffer"),
-        contents: bytemuck::cast_slice(&indices),
-        usage: wgpu::BufferUsages::INDEX,
-    });
+    // Sized for the largest mesh the UI can ask for; a rebuild frees the old mesh first, so it reuses the space
+    let (largest_vertices, largest_indices) = Vertex::generate_polygon(MAX_SIDES, 1.0);
+    let (cube_vertices, cube_indices) = Vertex::generate_cube();
+    let mut geometry = GeometryArena::with_capacity(
+        &device,
+        &mut render_stats,
+        largest_vertices.len().max(cube_vertices.len()) as u32,
+        largest_indices.len().max(cube_indices.len()) as u32,
+    );
+    let allocation = geometry.allocate(&device, &mut uploads, &mut render_stats, &vertices, &indices);
+    let mesh_builder = MeshBuilder::new(initial_shape);
 
-    let mut num_indices = indices.len() as u32;
+    // Scene entities. The UI edits `object`, the one object the template starts with; gameplay code can spawn
+    // more, and the systems in `world` draw them all
+    let mut world = World::new();
+    let mesh = MeshHandle { allocation, bounds };
+    let material = MaterialHandle(materials.default_for(ui_state.active_shader.name()));
+    let object = world::spawn_object(&mut world, initial_transform, mesh, material);
+    let _ = world.insert(object, (initial_shape, Name("Object".to_string())));
+    let transforms = TransformTracker::new();
+    let camera_entity = world.spawn((camera, ActiveCamera));
+    let sun = Light::default();
+    let sun = world.spawn((Transform::IDENTITY, GlobalTransform(Mat4::IDENTITY), sun, Name("Sun".to_string())));
+    let background = Vec3::new(0.1, 0.2, 0.3); // Linear RGB the scene pass clears to
+    app.setup(&mut AppContext {
+        device: &device,
+        queue: &queue,
+        world: &mut world,
+        camera: &mut camera,
+        terrain: &mut ui_state.terrain,
+        labels: &mut ui_state.labels,
+        events: ui_state.events.sender(),
+        object,
+        meshes: PrefabMeshes {
+            device: &device,
+            uploads: &mut uploads,
+            stats: &mut render_stats,
+            geometry: &mut geometry,
+        },
+        materials: &mut materials,
+        shader: ui_state.active_shader.name(),
+        redraw: false,
+    });
+    // Each entity's draw baked for the shader, object offset and mesh it was recorded with; dropped whenever
+    // a mesh is rebuilt or the object buffer grows
+    let scene_bundles: HashMap<Entity, (BundleKey, wgpu::RenderBundle)> = HashMap::new();
+    let bundle_generation = geometry.generation(); // Arena buffers the bundles were recorded against
 
     let mut egui_renderer = EguiRenderer::new(&device, config.format, None, 1, &window);
+    egui_renderer.init_accesskit(&window, event_loop.create_proxy());
+    window.set_visible(true);
 
-    let mut close_requested = false;
-    let mut modifiers = ModifiersState::default();
+    let egui_memory_path = settings::settings_path("egui_memory.ron");
+    if let Err(err) = egui_renderer.load_memory(&egui_memory_path) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            log::warn!("Failed to restore UI state from {}: {err}", egui_memory_path.display());
+        }
+    }
 
-    let mut scale_factor = 1.0;
+    // `--replay <file>` plays a recording from a fresh start and exits when it ends, for regression runs
+    let mut recorder = InputRecorder::new();
+    let replay_path = std::env::args().skip_while(|arg| arg != "--replay").nth(1);
+    let exit_after_replay = replay_path.is_some();
+    if let Some(recording) = replay_path.and_then(|path| load_recording(path.as_ref(), &mut ui_state.toasts)) {
+        recorder.start_playback(recording);
+    }
+    crash_report::set_settings(&settings);
 
-    let mut active_shader = "main";
+    let configured_size = (config.width, config.height);
+    let mut runtime = Runtime {
+        app,
+        app_config,
+        window,
+        camera,
+        initial_camera,
+        camera_controller,
+        benchmark,
+        settings,
+        instance,
+        surface,
+        adapter,
+        device,
+        queue,
+        config,
+        xr,
+        gpu_errors,
+        gpu_timings,
+        render_stats,
+        bind_groups,
+        camera_uniform,
+        camera_buffer,
+        lighting,
+        camera_bind_group_layout,
+        camera_bind_group,
+        objects,
+        registry,
+        uploads,
+        materials,
+        post,
+        scene_format,
+        stereo,
+        scene_windows,
+        viewport_panel,
+        probes,
+        scene_target,
+        scene_depth,
+        outline,
+        skinning,
+        instancing,
+        particles,
+        environment_map,
+        sky,
+        grid,
+        water,
+        decals,
+        billboards,
+        terrain,
+        debug_lines,
+        msaa,
+        shader_cache,
+        create_fallback: Box::new(create_fallback),
+        fallback_pipeline,
+        fallback_multisampled,
+        scene_pipelines,
+        ui_state,
+        plugins,
+        plugin_overlays,
+        action_map,
+        submissions,
+        buffer_pool,
+        geometry,
+        mesh_builder,
+        world,
+        object,
+        transforms,
+        camera_entity,
+        sun,
+        background,
+        scene_bundles,
+        bundle_generation,
+        egui_renderer,
+        egui_memory_path,
+        close_requested: false,
+        restart_renderer: false,
+        device_lost: false,
+        input: InputState::new(),
+        gamepads: Gamepads::new(),
+        space_mouse: SpaceMouse::new(),
+        cursor: CursorController::new(),
+        clipboard: Clipboard::new(),
+        last_pick: None,
+        style_emitter: None,
+        recorder,
+        pending_recording: None,
+        recordings: Assets::new(InputRecording::load),
+        scenes: Assets::new(SceneFile::load),
+        models: Assets::new(SkinnedModel::load),
+        mesh_files: Assets::new(MeshFile::load),
+        imported: None,
+        prefabs: PrefabLibrary::new(),
+        skinned: None,
+        screenshot_job: None,
+        watched_scene: None,
+        exit_after_replay,
+        last_frame: Instant::now(),
+        pending_size: None,
+        configured_size,
+        focused: true,
+        redraw: RedrawTracker::new(),
+        settings_reported: Instant::now(),
+    };
+    event_loop.run(move |event, elwt| runtime.handle_event(event, elwt)).expect("Failed to run event loop");
+}
 
-    event_loop.run(move |event, elwt| {
-        elwt.set_control_flow(ControlFlow::Poll);
+// Everything the event loop keeps from one event to the next: the window and device, the renderers, the scene
+// and the UI. Each phase of a frame is a method of its own, called in order by `redraw`
+struct Runtime<A: Application> {
+    app: A,
+    app_config: AppConfig,
+    window: Arc<Window>,
+    camera: Camera,
+    initial_camera: Camera,
+    camera_controller: CameraController,
+    benchmark: Option<Benchmark>,
+    settings: Settings,
+    instance: wgpu::Instance,
+    surface: Option<wgpu::Surface<'static>>,
+    adapter: wgpu::Adapter,
+    device: Arc<wgpu::Device>,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    xr: XrSession,
+    gpu_errors: GpuErrors,
+    gpu_timings: GpuTimings,
+    render_stats: RenderStats,
+    bind_groups: BindGroupCache,
+    camera_uniform: CameraUniform,
+    camera_buffer: Arc<wgpu::Buffer>,
+    lighting: Lighting,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    camera_bind_group: Arc<wgpu::BindGroup>,
+    objects: ObjectUniforms,
+    registry: ShaderRegistry,
+    uploads: Uploads,
+    materials: Materials,
+    post: Option<PostProcessor>,
+    scene_format: wgpu::TextureFormat,
+    stereo: StereoRenderer,
+    scene_windows: SceneWindows,
+    viewport_panel: ViewportPanel,
+    probes: ProbeRenderer,
+    scene_target: SceneTarget,
+    scene_depth: DepthBuffer,
+    outline: SelectionOutline,
+    skinning: Option<SkinningRenderer>,
+    instancing: InstancingRenderer,
+    particles: Option<ParticleSystem>,
+    environment_map: EnvironmentMap,
+    sky: SkyRenderer,
+    grid: GridRenderer,
+    water: WaterRenderer,
+    decals: DecalRenderer,
+    billboards: BillboardRenderer,
+    terrain: TerrainRenderer,
+    debug_lines: DebugRenderer,
+    msaa: Msaa,
+    shader_cache: Arc<ShaderCache>,
+    create_fallback: Box<dyn Fn(u32) -> Arc<wgpu::RenderPipeline>>, // For a sample count
+    fallback_pipeline: Arc<wgpu::RenderPipeline>,
+    fallback_multisampled: Option<Arc<wgpu::RenderPipeline>>,
+    scene_pipelines: BTreeMap<ShaderId, PendingPipeline>,
+    ui_state: UIState,
+    plugins: PluginHost,
+    plugin_overlays: PluginOverlays,
+    action_map: ActionMap,
+    submissions: SubmissionScheduler,
+    buffer_pool: BufferPool,
+    geometry: GeometryArena,
+    mesh_builder: MeshBuilder,
+    world: World,
+    object: Entity,
+    transforms: TransformTracker,
+    camera_entity: Entity,
+    sun: Entity,
+    background: Vec3,
+    scene_bundles: HashMap<Entity, (BundleKey, wgpu::RenderBundle)>,
+    bundle_generation: u64,
+    egui_renderer: EguiRenderer,
+    egui_memory_path: PathBuf,
+    close_requested: bool,
+    restart_renderer: bool, // Launch the app again once it has exited, on the adapter just picked
+    device_lost: bool,      // Nothing can be drawn once it is; the app stays open to save and exit
+    input: InputState,
+    gamepads: Gamepads,
+    space_mouse: SpaceMouse,
+    cursor: CursorController,
+    clipboard: Clipboard,
+    last_pick: Option<(Vec3, Vec3, Option<f32>)>, // Ray of the last click and where it hit a mesh
+    style_emitter: Option<Entity>,                // Spawned under the object for the Particles rendering style
+    recorder: InputRecorder,
+    pending_recording: Option<InputRecording>, // Stopped recording waiting for the save dialog
+    recordings: Assets<InputRecording>,
+    scenes: Assets<SceneFile>,
+    models: Assets<SkinnedModel>,
+    mesh_files: Assets<MeshFile>,
+    imported: Option<ImportedMesh>,
+    prefabs: PrefabLibrary,
+    skinned: Option<(Entity, SkinnedMesh)>, // The rigged model being previewed
+    screenshot_job: Option<Job<ConvertedScreenshot>>, // Converting a screenshot to copy or save
+    watched_scene: Option<Handle<SceneFile>>, // The open scene's file, reloaded when it is edited outside the app
+    exit_after_replay: bool,
+    last_frame: Instant,
+    pending_size: Option<PhysicalSize<u32>>, // Latest window size not yet applied to the surface
+    configured_size: (u32, u32),
+    focused: bool,
+    redraw: RedrawTracker,
+    settings_reported: Instant, // When crash reports last got the settings
+}
 
+// A frame between encoding the scene and presenting it: the surface texture it ends in, the UI's encoder, and the
+// scene's draws with the object offsets they were given
+struct Frame {
+    surface_texture: wgpu::SurfaceTexture,
+    surface_view: wgpu::TextureView,
+    encoder: wgpu::CommandEncoder,
+    draws: Vec<Draw>,
+    offsets: Vec<wgpu::DynamicOffset>,
+    acquire_wait: Duration,
+}
+
+impl<A: Application> Runtime<A> {
+    fn handle_event(&mut self, event: Event<UserEvent>, elwt: &EventLoopWindowTarget<UserEvent>) {
         match event {
+            // Scene windows have their own cameras and UIs, and are drawn by the main window's frame
+            Event::WindowEvent { window_id, event } if self.scene_windows.owns(window_id) => {
+                self.scene_windows.handle_event(&self.device, &mut self.render_stats, window_id, &event);
+                if self.ui_state.redraw_on_demand && event != WindowEvent::RedrawRequested {
+                    self.redraw.mark_dirty();
+                    self.window.request_redraw();
+                }
+            }
+            // Detached egui panels live in their own windows and only feed egui; the camera and viewport
+            // only react to the main window
+            Event::WindowEvent { window_id, event } if window_id != self.window.id() => {
+                self.egui_renderer.handle_viewport_input(&self.device, window_id, &event);
+                // Detached panels are drawn by the main window's frame
+                if self.ui_state.redraw_on_demand && event != WindowEvent::RedrawRequested {
+                    self.redraw.mark_dirty();
+                    self.window.request_redraw();
+                }
+            }
             Event::WindowEvent { event, .. } => {
-                egui_renderer.handle_input(&window, &event);
+                let redraw_requested = event == WindowEvent::RedrawRequested;
+                self.input(event);
+                if redraw_requested {
+                    self.redraw(elwt);
+                }
+            }
 
-                match event {
-                    WindowEvent::CloseRequested => {
-                        close_requested = true;
+            // Sent when a mobile app goes to the background, whose window surface is destroyed with it
+            Event::Suspended => self.surface = None,
+            Event::Resumed if self.surface.is_none() => match self.instance.create_surface(self.window.clone()) {
+                Ok(created) => {
+                    // The device may have been rotated in the meantime
+                    let size = self.window.inner_size();
+                    if size.width > 0 && size.height > 0 {
+                        (self.config.width, self.config.height) = (size.width, size.height);
+                        self.camera.aspect = size.width as f32 / size.height as f32;
                     }
-                    WindowEvent::ModifiersChanged(new) => {
-                        modifiers = new.state();
+                    created.configure(&self.device, &self.config);
+                    self.configured_size = (self.config.width, self.config.height);
+                    self.surface = Some(created);
+                    self.window.request_redraw();
+                }
+                Err(err) => log::error!("Failed to recreate the surface on resume: {err}"),
+            },
+            Event::UserEvent(UserEvent::AccessKit(event)) if event.window_id == self.window.id() => {
+                self.egui_renderer.handle_accesskit_event(event);
+            }
+            Event::UserEvent(UserEvent::AppEvents) => self.window.request_redraw(),
+            Event::DeviceEvent { event, .. } => {
+                let replaying = self.recorder.is_playing() || self.ui_state.session.is_reviewing();
+                if let (false, Some(input_event)) = (replaying, InputEvent::from_device_event(&event)) {
+                    self.recorder.record(&input_event);
+                    self.ui_state.session.feed(&input_event, &mut self.input);
+                    if self.ui_state.redraw_on_demand && self.focused {
+                        self.window.request_redraw();
                     }
-                    WindowEvent::KeyboardInput {
-                        event: kb_event, ..
-                    } => {
-                        if kb_event.logical_key == Key::Named(NamedKey::Escape) {
-                            close_requested = true;
-                        }
+                }
+            }
+            Event::AboutToWait if self.close_requested => elwt.exit(),
+            // In the background, frames are spaced out by the event loop's timer instead of run back to back
+            Event::AboutToWait => match (self.focused, self.ui_state.background_mode) {
+                // Drawn frames request the next one themselves; once one is skipped, the loop idles between
+                // polls until an event arrives
+                (true, _) if self.ui_state.redraw_on_demand => {
+                    let next_poll = self.last_frame + redraw::IDLE_POLL_INTERVAL;
+                    if Instant::now() >= next_poll {
+                        self.window.request_redraw();
                     }
-                    WindowEvent::Resized(new_size) => {
-                        config.width = new_size.width;
-                        config.height = new_size.height;
-                        surface.configure(&device, &config);
+                    elwt.set_control_flow(ControlFlow::WaitUntil(next_poll));
+                }
+                (true, _) | (false, BackgroundMode::FullSpeed) => elwt.set_control_flow(ControlFlow::Poll),
+                (false, BackgroundMode::Throttled) => {
+                    let interval = Duration::from_secs_f64(1.0 / self.ui_state.background_fps as f64);
+                    let next_frame = self.last_frame + interval;
+                    if Instant::now() >= next_frame {
+                        self.window.request_redraw();
                     }
-                    WindowEvent::RedrawRequested => {
-                        if sides != previous_sides || matches!(rendering_style, RenderingStyle::Cube) {
-                            let (new_vertices, new_indices) = match rendering_style {
-                                RenderingStyle::Polygon => Vertex::generate_polygon(sides, 0.5),
-                                RenderingStyle::Cube => Vertex::generate_cube(),  // Call generate_cube here
-                            };
-                            
-                            vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                                label: Some("Vertex Buffer"),
-                                contents: bytemuck::cast_slice(&new_vertices),
-                                usage: wgpu::BufferUsages::VERTEX,
-                            });
-                    
-                            index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                                label: Some("Index Buffer"),
-                                contents: bytemuck::cast_slice(&new_indices),
-                                usage: wgpu::BufferUsages::INDEX,
-                            });
-                    
-                            num_indices = new_indices.len() as u32;
-                            previous_sides = sides; // Update the previous_sides value
+                    elwt.set_control_flow(ControlFlow::WaitUntil(next_frame));
+                }
+                (false, BackgroundMode::OnDemand) => elwt.set_control_flow(ControlFlow::Wait),
+            },
+            Event::LoopExiting => {
+                if let Err(err) = self.egui_renderer.save_memory(&self.egui_memory_path) {
+                    log::warn!("Failed to save UI state to {}: {err}", self.egui_memory_path.display());
+                }
+                update_settings(&mut self.settings, &self.ui_state, &self.action_map);
+                if let Err(err) = self.settings.save() {
+                    log::warn!("Failed to save settings: {err}");
+                }
+                if let Err(err) = self.shader_cache.save() {
+                    log::warn!("Failed to save shader cache: {err}");
+                }
+                if self.restart_renderer {
+                    relaunch(&self.settings);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Draws a frame: the surface follows the window, everything in the scene and the UI moves on, then the scene
+    // is encoded, the UI drawn over it and the frame presented
+    fn redraw(&mut self, elwt: &EventLoopWindowTarget<UserEvent>) {
+        // Suspended; there is nothing to draw into until the app resumes
+        if self.surface.is_none() {
+            return;
+        }
+        // Minimized; the surface keeps its old size and frames resume with the next resize
+        let size = self.window.inner_size();
+        if self.device_lost || self.window.is_minimized() == Some(true) || size.width == 0 || size.height == 0 {
+            return;
+        }
+        puffin::GlobalProfiler::lock().new_frame();
+        self.ui_state.pacer.set_fps_cap(match self.ui_state.pacing_mode {
+            PacingMode::Capped if self.benchmark.is_none() => Some(self.ui_state.fps_cap),
+            _ => None,
+        });
+        self.ui_state.pacer.begin_frame();
+        self.render_stats.begin_frame();
+        self.resize();
+        // Nothing is encoded for a frame that would look the same as the last, or whose texture couldn't be had
+        let Some(dt) = self.update() else { return };
+        let Some(mut frame) = self.render(dt) else { return };
+        let screenshot = self.ui(&mut frame, elwt);
+        self.present(frame, screenshot, elwt);
+    }
+
+    // A main window event goes to egui first, then the application, then the viewport's input
+    fn input(&mut self, event: WindowEvent) {
+        let Self {
+            app, window, viewport_panel, ui_state, action_map, egui_renderer, close_requested, input, cursor, recorder,
+            pending_size, focused, ..
+        } = self;
+        // Until a widget has keyboard focus, the selection-cycling key belongs to the viewport;
+        // egui would otherwise use Tab to move focus into the UI
+        let viewport_key = matches!(&event, WindowEvent::KeyboardInput { event: kb_event, .. }
+            if kb_event.physical_key == PhysicalKey::Code(action_map.key(Action::CycleSelection)))
+            && egui_renderer.context().memory(|m| m.focused().is_none());
+        let consumed = if viewport_key {
+            false
+        } else if event != WindowEvent::RedrawRequested {
+            puffin::profile_scope!("handle_event");
+            egui_renderer.handle_input(window, &event)
+        } else {
+            egui_renderer.handle_input(window, &event)
+        };
+        // egui gets first pick: keys typed into a text field or the keybinding editor never move
+        // the camera, presses and touches on egui never start viewport clicks or drags, and
+        // scrolling over a window scrolls it instead of zooming. Releases always go through so
+        // nothing is left held
+        let captured_press = match &event {
+            // Not `consumed`, which egui-winit always sets for Tab
+            WindowEvent::KeyboardInput { event: kb_event, .. } => {
+                kb_event.state == ElementState::Pressed
+                    && (egui_renderer.context().wants_keyboard_input() || ui_state.captures_keyboard())
+            }
+            // The scene shown in a panel is part of egui, but what lands on it is the viewport's
+            WindowEvent::MouseInput { state, .. } => {
+                *state == ElementState::Pressed && consumed && !viewport_panel.hovered()
+            }
+            WindowEvent::Touch(touch) => {
+                touch.phase == TouchPhase::Started && consumed && !viewport_panel.hovered()
+            }
+            WindowEvent::MouseWheel { .. } => consumed && !viewport_panel.hovered(),
+            _ => false,
+        };
+        // The application sees what egui left before the viewport does, and can keep it
+        let captured_press = captured_press || (event != WindowEvent::RedrawRequested && app.on_event(&event));
+        // Live input is ignored while a recording plays back
+        if let (false, Some(input_event)) = (captured_press, InputEvent::from_window_event(&event)) {
+            if !recorder.is_playing() && !ui_state.session.is_reviewing() {
+                recorder.record(&input_event);
+                ui_state.session.feed(&input_event, input);
+            }
+        }
+
+        // Redraws on demand, in the background or by choice, are driven by the window's own events
+        if (!*focused || ui_state.redraw_on_demand) && event != WindowEvent::RedrawRequested {
+            window.request_redraw();
+        }
+
+        match event {
+            WindowEvent::CloseRequested => {
+                *close_requested |= ui_state.request_exit();
+            }
+            WindowEvent::KeyboardInput {
+                event: kb_event, ..
+            } if kb_event.state == ElementState::Pressed => {
+                if let PhysicalKey::Code(code) = kb_event.physical_key {
+                    match ui_state.rebinding {
+                        Some(Rebinding::Key(action)) => {
+                            if code != KeyCode::Escape {
+                                action_map.bind(action, code, input.modifiers());
+                            }
+                            ui_state.rebinding = None;
                         }
-                    
-                        let surface_texture = surface
-                            .get_current_texture()
-                            .expect("Failed to acquire next swap chain texture");
-                
-                        let surface_view = surface_texture
-                            .texture
-                            .create_view(&wgpu::TextureViewDescriptor::default());
-                
-                        let mut encoder =
-                            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                                label: None,
-                            });
-                
-                        let screen_descriptor = ScreenDescriptor {
-                            size_in_pixels: [config.width, config.height],
-                            pixels_per_point: window.scale_factor() as f32 * scale_factor,
+                        Some(Rebinding::Gamepad(_)) if code == KeyCode::Escape => ui_state.rebinding = None,
+                        _ => {}
+                    }
+                }
+            }
+            WindowEvent::Focused(now_focused) => {
+                cursor.set_focused(now_focused);
+                *focused = now_focused;
+                // Back to full speed straight away rather than at the next background tick
+                window.request_redraw();
+            }
+            // May have landed on a monitor with a different refresh rate
+            WindowEvent::Moved(_) => ui_state
+                .pacer
+                .set_refresh_rate(window.current_monitor().and_then(|m| m.refresh_rate_millihertz())),
+            // Interactive resizes send many of these per frame; only the last one is applied. A minimized
+            // window reports 0x0, which a surface can't be configured with, so it keeps its old size
+            WindowEvent::Resized(new_size) if new_size.width > 0 && new_size.height > 0 => {
+                *pending_size = Some(new_size);
+            }
+            // Moved to a monitor with a different DPI; egui picks up the new scale through
+            // `window.scale_factor()` next frame, the surface has to follow the new physical size
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                log::debug!("Scale factor changed to {scale_factor}");
+                let size = window.inner_size();
+                if size.width > 0 && size.height > 0 {
+                    *pending_size = Some(size);
+                }
+            }
+            _ => {} // Wildcard pattern to catch all unhandled WindowEvent variants
+        }
+    }
+
+    // Everything that depends on the surface size is recreated here, once per frame at most
+    fn resize(&mut self) {
+        let Self {
+            camera, benchmark, surface, device, config, ui_state, pending_size, configured_size, ..
+        } = self;
+        let Some(surface) = surface.as_ref() else { return };
+        let latency_changed = config.desired_maximum_frame_latency != ui_state.frame_latency;
+        let present_mode = match (&benchmark, ui_state.present_mode.wgpu()) {
+            (Some(_), _) => wgpu::PresentMode::AutoNoVsync,
+            (None, Some(mode)) => mode,
+            (None, None) => ui_state.pacing_mode.present_mode(),
+        };
+        let present_mode_changed = config.present_mode != present_mode;
+        if let Some(size) = pending_size.take() {
+            config.width = size.width;
+            config.height = size.height;
+            camera.aspect = size.width as f32 / size.height as f32;
+        }
+        if latency_changed || present_mode_changed || (config.width, config.height) != *configured_size {
+            config.desired_maximum_frame_latency = ui_state.frame_latency;
+            config.present_mode = present_mode;
+            surface.configure(device, config);
+            *configured_size = (config.width, config.height);
+        }
+    }
+
+    // Polls files, assets and input, then steps the scene on by the frame's time, which it returns. Returns `None`
+    // when redrawing on demand and nothing has changed since the last frame
+    fn update(&mut self) -> Option<f32> {
+        let (initial_camera, object, sun) = (self.initial_camera, self.object, self.sun);
+        let exit_after_replay = self.exit_after_replay;
+        let Self {
+            app, window, camera, camera_controller, benchmark, settings, device, queue, config, xr, gpu_timings,
+            render_stats, bind_groups, registry, uploads, materials, viewport_panel, probes, skinning, particles,
+            environment_map, decals, billboards, terrain, scene_pipelines, ui_state, plugins, action_map, buffer_pool,
+            geometry, mesh_builder, world, transforms, background, scene_bundles, egui_renderer, close_requested,
+            input, gamepads, space_mouse, cursor, clipboard, last_pick, style_emitter, recorder, pending_recording,
+            recordings, scenes, models, mesh_files, imported, prefabs, skinned, screenshot_job, watched_scene,
+            last_frame, configured_size, redraw, settings_reported, ..
+        } = self;
+        bind_groups.evict_dropped();
+        render_stats.cached_bind_groups = bind_groups.len() as u32;
+        render_stats.buffer_pool = buffer_pool.stats();
+        render_stats.jobs = jobs::stats();
+        render_stats.budget = Some(ui_state.memory_budget_mib as u64 * 1024 * 1024);
+        if render_stats.exceeded_budget() {
+            // Nothing the renderer holds can be dropped and rebuilt yet, so this only warns
+            let used = stats::format_bytes(render_stats.memory_bytes());
+            log::warn!("GPU memory over budget: {used} of {} MiB", ui_state.memory_budget_mib);
+            ui_state.toasts.warning(format!("GPU memory over budget ({used})"));
+        }
+
+        let now = Instant::now();
+        let dt = ui_state.frame_timer.tick(now).as_secs_f32();
+        *last_frame = now;
+        plots::debug_plot("frame_ms", dt as f64 * 1000.0);
+        if now - *settings_reported >= Duration::from_secs(1) {
+            *settings_reported = now;
+            update_settings(settings, ui_state, action_map);
+            crash_report::set_settings(settings);
+        }
+
+        // Shared by everything below that applies results finished on the job pool
+        let mut apply_budget = FrameBudget::new(jobs::APPLY_BUDGET);
+        while let Some(result) = ui_state.file_dialogs.poll() {
+            redraw.mark_dirty();
+            if result.kind == FileKind::Model && result.action == DialogAction::Open {
+                mesh_files.load(result.path, false);
+            } else if result.kind == FileKind::Scene {
+                match result.action {
+                    DialogAction::Open => {
+                        scenes.load(result.path, true);
+                    }
+                    DialogAction::Save => {
+                        let scene = current_scene(
+                            ui_state,
+                            world,
+                            materials,
+                            object,
+                            sun,
+                            camera,
+                            *background,
+                        );
+                        save_scene(result.path, ui_state, scene, scenes, watched_scene);
+                    }
+                }
+            } else if result.kind == FileKind::Prefab {
+                match result.action {
+                    DialogAction::Open => prefabs.instantiate(result.path),
+                    DialogAction::Save => {
+                        let prefab = Prefab::capture(world, object);
+                        let mut meshes = PrefabMeshes {
+                            device,
+                            uploads,
+                            stats: render_stats,
+                            geometry,
                         };
-                
-                        // Use the main render pipeline
-                        {
-                            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                                label: Some("Render Pass"),
-                                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                                    view: &surface_view,
-                                    resolve_target: None,
-                                    ops: wgpu::Operations {
-                                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                                            r: 0.1,
-                                            g: 0.2,
-                                            b: 0.3,
-                                            a: 1.0,
-                                        }),
-                                        store: wgpu::StoreOp::Store,
-                                    },
-                                })],
-                                depth_stencil_attachment: None,
-                                // Adding missing fields with default values
-                                occlusion_query_set: None, // Default value, as occlusion queries aren't used
-                                timestamp_writes: None,    // Default value, as no timestamps are written
-                            });
-                        
-                            match active_shader {
-                                "main" => render_pass.set_pipeline(&render_pipeline),
-                                "challenge" => render_pass.set_pipeline(&challenge_render_pipeline),
-                                _ => render_pass.set_pipeline(&render_pipeline), // Default fallback
+                        match prefabs.save(world, &mut meshes, result.path.clone(), prefab) {
+                            Ok(()) => ui_state
+                                .toasts
+                                .success(format!("Saved prefab to {}", result.path.display())),
+                            Err(err) => {
+                                log::warn!("Failed to save prefab to {}: {err}", result.path.display());
+                                ui_state.toasts.error(format!("Failed to save prefab: {err}"));
                             }
-                            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-                            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                            render_pass.draw_indexed(0..num_indices, 0, 0..1);
-                        }                        
-                
-                        egui_renderer.draw(
-                            &device,
-                            &queue,
-                            &mut encoder,
-                            &window,
-                            &surface_view,
-                            screen_descriptor,
-                            |ctx| {
-                                egui::Window::new("UI Window")
-                                    .resizable(true)
-                                    .vscroll(true)
-                                    .default_open(true)
-                                    .show(ctx, |ui| {
-                                        ui.label("Vertex and Shader control");
-    
-                                        if ui.button("Switch Shader").clicked() {
-                                            if active_shader == "main" {
-                                                active_shader = "challenge"; // Switch to challenge shader
-                                            } else {
-                                                active_shader = "main"; // Switch back to main shader
-                                            }
-                                        }
-    
-                                        ui.separator();
-    
-                                        // Add the UI component to adjust the number of sides for polygons
-                                        if let RenderingStyle::Polygon = rendering_style {
-                                            ui.horizontal(|ui| {
-                                                ui.label(format!("Polygon sides: {}", sides));
-                                                if ui.button("-").clicked() {
-                                                    sides = (sides - 1).max(3); // Ensure a minimum of 3 sides
-                                                }
-                                                if ui.button("+").clicked() {
-                                                    sides = (sides + 1).min(12); // Set a max number of sides, for example, 12
-                                                }
-                                            });
-                                        }
-    
-                                        // Add button to switch rendering style
-                                        ui.separator();
-                                        if ui.button("Switch to Cube").clicked() {
-                                            rendering_style = match rendering_style {
-                                                RenderingStyle::Polygon => RenderingStyle::Cube,
-                                                RenderingStyle::Cube => RenderingStyle::Polygon,
-                                            };
-                                        }
-    
-                                        ui.separator();
-                                        ui.horizontal(|ui| {
-                                            ui.label(format!(
-                                                "Pixels per point: {}",
-                                                ctx.pixels_per_point()
-                                            ));
-                                            if ui.button("-").clicked() {
-                                                scale_factor = (scale_factor - 0.1).max(0.3);
-                                            }
-                                            if ui.button("+").clicked() {
-                                                scale_factor = (scale_factor + 0.1).min(3.0);
-                                            }
-                                        });
-                                    });
-                            },
-                        );
-                
-                        queue.submit(Some(encoder.finish()));
-                        surface_texture.present();
-                        window.request_redraw();
+                        }
+                    }
+                }
+            } else if result.kind == FileKind::Script && result.action == DialogAction::Open {
+                ui_state.scripts.attach(world, object, result.path);
+            } else if result.kind == FileKind::Texture && result.action == DialogAction::Open {
+                if ui_state.decals.is_choosing() {
+                    ui_state.decals.place_texture(world, result.path);
+                } else if ui_state.billboards.is_choosing() {
+                    ui_state.billboards.place_texture(world, result.path);
+                } else if ui_state.terrain.is_choosing() {
+                    ui_state.terrain.place_texture(result.path);
+                } else if !materials.place_texture(world, object, result.path) {
+                    ui_state.toasts.warning("The object's material has no texture to set");
+                }
+            } else if result.kind == FileKind::EnvironmentImage && result.action == DialogAction::Open {
+                ui_state.environment.image.path = Some(result.path);
+            } else if result.kind == FileKind::Preset {
+                let request = match result.action {
+                    DialogAction::Open => PresetRequest::Load(result.path),
+                    DialogAction::Save => PresetRequest::Save(result.path),
+                };
+                handle_preset_request(request, ui_state, action_map, egui_renderer);
+            } else if result.kind == FileKind::Recording {
+                match result.action {
+                    DialogAction::Open => {
+                        recordings.load(result.path, false);
+                    }
+                    DialogAction::Save => {
+                        let result = pending_recording
+                            .take()
+                            .map(|recording: InputRecording| recording.save(&result.path));
+                        if let Some(Err(err)) = result {
+                            log::warn!("Failed to save input recording: {err}");
+                            ui_state.toasts.error(format!("Failed to save input recording: {err}"));
+                        }
+                    }
+                }
+            } else if result.kind == FileKind::GpuTrace {
+                match gpu_timings.export_chrome_trace(&result.path) {
+                    Ok(()) => {
+                        ui_state.toasts.success(format!("Saved GPU trace to {}", result.path.display()))
+                    }
+                    Err(err) => {
+                        log::warn!("Failed to save GPU trace: {err}");
+                        ui_state.toasts.error(format!("Failed to save GPU trace: {err}"));
                     }
-                    _ => {} // Wildcard pattern to catch all unhandled WindowEvent variants
-                }                
+                }
+            } else if result.kind == FileKind::Video {
+                ui_state.video.set_path(result.path);
+            } else if result.kind == FileKind::Gltf {
+                match gltf_export::export_glb(&result.path, world, geometry, materials, camera) {
+                    Ok(()) => ui_state.toasts.success(format!("Exported {}", result.path.display())),
+                    Err(err) => {
+                        log::warn!("Failed to export glTF: {err}");
+                        ui_state.toasts.error(format!("Failed to export glTF: {err}"));
+                    }
+                }
+            } else {
+                handle_file_dialog_result(result, &mut ui_state.toasts);
             }
+        }
 
-            Event::AboutToWait => {
-                if close_requested {
-                    elwt.exit()
+        if let Some(job) = screenshot_job.as_ref().filter(|_| apply_budget.has_time()) {
+            match job.poll() {
+                JobState::Done(shot) => {
+                    apply_budget.spend();
+                    *screenshot_job = None;
+                    crash_report::set_screenshot(shot.width, shot.height, shot.pixels.clone());
+                    match shot.saved {
+                        Some(Ok(path)) => {
+                            log::info!("Saved screenshot {}", path.display());
+                            ui_state.toasts.success(format!("Saved screenshot {}", path.display()));
+                        }
+                        Some(Err(err)) => ui_state.toasts.error(format!("Screenshot failed: {err}")),
+                        None => match clipboard.copy_image(shot.width, shot.height, shot.pixels) {
+                            Ok(()) => ui_state.toasts.success("Screenshot copied to clipboard"),
+                            Err(err) => ui_state.toasts.error(format!("Screenshot failed: {err}")),
+                        },
+                    }
+                }
+                JobState::Lost => {
+                    *screenshot_job = None;
+                    ui_state.toasts.error("Screenshot failed: encoding was lost");
+                }
+                JobState::Running => {}
+            }
+        }
+        for event in scenes.poll(&mut apply_budget) {
+            redraw.mark_dirty();
+            let mut targets = SceneTargets {
+                world,
+                object,
+                sun,
+                camera,
+                background,
+                meshes: PrefabMeshes {
+                    device,
+                    uploads,
+                    stats: render_stats,
+                    geometry,
+                },
+                prefabs,
+                materials,
+            };
+            handle_scene_event(event, scenes, watched_scene, ui_state, &mut targets);
+        }
+        for event in ui_state.network.poll(&mut ui_state.toasts) {
+            redraw.mark_dirty();
+            match event {
+                NetworkEvent::Scene(scene) => {
+                    let mut targets = SceneTargets {
+                        world,
+                        object,
+                        sun,
+                        camera,
+                        background,
+                        meshes: PrefabMeshes {
+                            device,
+                            uploads,
+                            stats: render_stats,
+                            geometry,
+                        },
+                        prefabs,
+                        materials,
+                    };
+                    apply_scene(&scene, None, ui_state, &mut targets);
+                    ui_state.toasts.info("Received the scene from a peer");
+                }
+                NetworkEvent::Object {
+                    transform,
+                    sides,
+                    radius,
+                } => {
+                    *world::transform_mut(world, object) = transform;
+                    ui_state.sides = sides.clamp(MIN_SIDES, MAX_SIDES);
+                    ui_state.radius = radius;
+                }
+                NetworkEvent::SceneWanted(to) => {
+                    let scene = current_scene(
+                        ui_state,
+                        world,
+                        materials,
+                        object,
+                        sun,
+                        camera,
+                        *background,
+                    );
+                    ui_state.network.send_scene(scene, to);
                 }
             }
+        }
+        ui_state.scripts.poll(&mut apply_budget, world, &mut ui_state.toasts);
+        for (name, program) in ui_state.shader_reload.poll(&mut apply_budget, &mut ui_state.toasts) {
+            // The old pipeline keeps drawing until the new one is ready
+            if let Some(pipeline) = registry.id(name).and_then(|id| scene_pipelines.get_mut(&id)) {
+                pipeline.respawn(program);
+            }
+            redraw.mark_dirty();
+        }
+        let toasts = &mut ui_state.toasts;
+        if materials.update(device, render_stats, uploads, &mut apply_budget, toasts) {
+            redraw.mark_dirty();
+        }
+        let toasts = &mut ui_state.toasts;
+        if decals.update(device, render_stats, uploads, &mut apply_budget, world, toasts) {
+            redraw.mark_dirty();
+        }
+        let (toasts, image) = (&mut ui_state.toasts, &ui_state.environment.image);
+        if environment_map.update(device, render_stats, uploads, &mut apply_budget, image, toasts) {
+            // Bundles hold the probe bind group the old cube was bound in
+            probes.set_environment_map(device, environment_map.view());
+            scene_bundles.clear();
+            redraw.mark_dirty();
+        }
+        let toasts = &mut ui_state.toasts;
+        if billboards.update(device, render_stats, uploads, &mut apply_budget, world, toasts) {
+            redraw.mark_dirty();
+        }
+        let toasts = &mut ui_state.toasts;
+        if terrain.update(device, render_stats, uploads, &mut apply_budget, &ui_state.terrain, toasts) {
+            redraw.mark_dirty();
+        }
+        let mut meshes = PrefabMeshes {
+            device,
+            uploads,
+            stats: render_stats,
+            geometry,
+        };
+        let placement = Transform {
+            translation: camera.target,
+            ..Transform::IDENTITY
+        };
+        if prefabs.update(&mut apply_budget, world, &mut meshes, &mut ui_state.toasts, placement) {
+            redraw.mark_dirty();
+        }
+        for event in models.poll(&mut apply_budget) {
+            redraw.mark_dirty();
+            match event {
+                AssetEvent::Loaded(handle) => {
+                    let file_name = models.path(handle).and_then(Path::file_name);
+                    let name = file_name.map_or_else(String::new, |name| name.to_string_lossy().into());
+                    let (Some(model), Some(renderer)) = (models.remove(handle), &skinning) else {
+                        continue;
+                    };
+                    ui_state.toasts.success(format!(
+                        "Loaded {name}: {} joints, {} clips",
+                        model.skeleton.joint_count(),
+                        model.clips.len()
+                    ));
+                    if let Some(bounds) = &model.bounds {
+                        camera.frame_bounds(bounds);
+                    }
+                    let node = Name(name.clone());
+                    let mut mesh = renderer.create_mesh(device, render_stats, name, model);
+                    mesh.play_first_clip();
+                    if let Some((entity, previous)) = skinned.take() {
+                        let _ = world.despawn(entity);
+                        previous.destroy(render_stats);
+                    }
+                    let global = GlobalTransform(Mat4::IDENTITY);
+                    let entity = world.spawn((Transform::IDENTITY, global, node));
+                    *skinned = Some((entity, mesh));
+                }
+                AssetEvent::Failed(handle, err) => {
+                    models.remove(handle);
+                    log::warn!("Failed to load model: {err}");
+                    ui_state.toasts.error(format!("Failed to load model: {err}"));
+                }
+                AssetEvent::Reloaded(_) => {} // Models are not watched
+            }
+        }
+        for event in mesh_files.poll(&mut apply_budget) {
+            redraw.mark_dirty();
+            match event {
+                AssetEvent::Loaded(handle) => {
+                    let path = mesh_files.path(handle).map(Path::to_path_buf).unwrap_or_default();
+                    let Some(file) = mesh_files.remove(handle) else {
+                        continue;
+                    };
+                    // Rigged glTF files are previewed with their animations where the GPU can
+                    // skin them, and shown in their rest pose where it can't
+                    if file.skinned && skinning.is_some() {
+                        models.load(path, false);
+                        continue;
+                    }
+                    let name = path.file_name().map(|name| name.to_string_lossy().into_owned());
+                    let name = name.unwrap_or_default();
+                    let indices: usize = file.submeshes.iter().map(|s| s.mesh.indices.len()).sum();
+                    let triangles = indices / 3;
+                    ui_state.toasts.success(format!(
+                        "Loaded {name}: {} submeshes, {triangles} triangles",
+                        file.submeshes.len()
+                    ));
+
+                    // The first submesh replaces the object's mesh and the rest are parented to
+                    // it. Submeshes without a material of their own draw with the object's
+                    let shader = ui_state.active_shader.name();
+                    let current = *world.get::<&MaterialHandle>(object).unwrap();
+                    let object_material = imported.as_ref().map_or(current, |shown| shown.material);
+                    let mut parts = file.submeshes.iter().map(|submesh| {
+                        let Mesh { vertices, indices } = &submesh.mesh;
+                        let allocation = geometry.allocate(device, uploads, render_stats, vertices, indices);
+                        let material = submesh.material.as_ref().map_or(object_material, |wanted| {
+                            let id = materials.create(device, render_stats, shader, wanted.name.clone());
+                            materials.set_param(id, "tint", &wanted.color);
+                            if let Some(texture) = &wanted.texture {
+                                materials.set_texture(id, 0, Some(texture.clone()));
+                            }
+                            MaterialHandle(id)
+                        });
+                        (MeshHandle { allocation, bounds: submesh.mesh.bounds() }, material)
+                    });
+                    let Some((mesh, material)) = parts.next() else {
+                        continue;
+                    };
+                    let parts: Vec<_> = parts.collect();
+                    if let Some(previous) = imported.take() {
+                        previous.remove(world, geometry, object);
+                    }
+                    let mut object_mesh = world.get::<&mut MeshHandle>(object).unwrap();
+                    geometry.free(&object_mesh.allocation);
+                    *object_mesh = mesh;
+                    drop(object_mesh);
+                    let _ = world.insert_one(object, material);
+                    let _ = world.remove_one::<MeshShape>(object);
+                    let parts = parts
+                        .into_iter()
+                        .map(|(mesh, material)| {
+                            let transform = Transform::IDENTITY;
+                            let part = world::spawn_object(world, transform, mesh, material);
+                            let _ = world.insert_one(part, Parent(object));
+                            part
+                        })
+                        .collect();
+                    *imported = Some(ImportedMesh {
+                        parts,
+                        material: object_material,
+                    });
+                    ui_state.loaded_mesh = Some(name);
+                    scene_bundles.clear();
+                    if let Some(bounds) = file.bounds() {
+                        camera.frame_bounds(&bounds.transformed(world::world_matrix(world, object)));
+                    }
+                }
+                AssetEvent::Failed(handle, err) => {
+                    mesh_files.remove(handle);
+                    log::warn!("Failed to load mesh: {err}");
+                    ui_state.toasts.error(format!("Failed to load mesh: {err}"));
+                }
+                AssetEvent::Reloaded(_) => {} // Mesh files are not watched
+            }
+        }
+        for event in recordings.poll(&mut apply_budget) {
+            redraw.mark_dirty();
+            match event {
+                AssetEvent::Loaded(handle) => {
+                    if let Some(recording) = recordings.remove(handle) {
+                        // Same starting point as when the recording began
+                        *camera = Camera {
+                            aspect: camera.aspect,
+                            ..initial_camera
+                        };
+                        *input = InputState::new();
+                        recorder.start_playback(recording);
+                        if ui_state.sequencer.follow_recordings {
+                            ui_state.sequencer.restart();
+                        }
+                    }
+                }
+                AssetEvent::Failed(handle, err) => {
+                    recordings.remove(handle);
+                    log::warn!("Failed to load input recording: {err}");
+                    ui_state.toasts.error(format!("Failed to load input recording: {err}"));
+                }
+                AssetEvent::Reloaded(_) => {} // Recordings are not watched
+            }
+        }
+
+        for device_event in gamepads.poll().into_iter().chain(space_mouse.poll()) {
+            if !recorder.is_playing() && !ui_state.session.is_reviewing() {
+                recorder.record(&device_event);
+                ui_state.session.feed(&device_event, input);
+            }
+        }
+        let was_playing = recorder.is_playing();
+        let dt = recorder.advance(input).unwrap_or(dt);
+        // A reviewed session replays its recorded steps instead, and holds still between them
+        let dt = match ui_state.session.begin_frame(dt) {
+            SessionStep::Live(dt) | SessionStep::Replay(dt) => dt,
+            SessionStep::Keyframe(dt) => {
+                let snapshot = session_snapshot(
+                    ui_state,
+                    world,
+                    materials,
+                    object,
+                    sun,
+                    camera,
+                    *background,
+                    input,
+                    camera_controller,
+                );
+                ui_state.session.add_keyframe(snapshot);
+                dt
+            }
+            SessionStep::Verify(dt) => {
+                let scene = current_scene(
+                    ui_state,
+                    world,
+                    materials,
+                    object,
+                    sun,
+                    camera,
+                    *background,
+                );
+                ui_state.session.verify(&scene);
+                dt
+            }
+            SessionStep::Restore(snapshot) => {
+                let mut targets = SceneTargets {
+                    world,
+                    object,
+                    sun,
+                    camera,
+                    background,
+                    meshes: PrefabMeshes {
+                        device,
+                        uploads,
+                        stats: render_stats,
+                        geometry,
+                    },
+                    prefabs,
+                    materials,
+                };
+                restore_session(&snapshot, ui_state, &mut targets, input, camera_controller);
+                // Physics reads world poses, which must not be the ones from before the restore
+                world::propagate_transforms(world, transforms);
+                redraw.mark_dirty();
+                0.0
+            }
+            SessionStep::Paused => 0.0,
+        };
+        ui_state.session.apply_events(input);
+        input.advance_time(dt);
+        if was_playing && !recorder.is_playing() {
+            ui_state.toasts.info("Input playback finished");
+            *close_requested |= exit_after_replay;
+        }
+        ui_state.input_capture = if recorder.is_recording() {
+            InputCapture::Recording
+        } else if recorder.is_playing() {
+            InputCapture::Playing
+        } else {
+            InputCapture::Idle
+        };
+        if let Some(Rebinding::Gamepad(action)) = ui_state.rebinding {
+            if let Some(pressed) = input.gamepad_activity() {
+                action_map.bind_gamepad(action, Some(pressed));
+                ui_state.rebinding = None;
+            }
+        } else {
+            // Navigates whichever window has focus, detached panels included
+            for event in gamepad::ui_navigation_events(input) {
+                egui_renderer.push_event(event);
+            }
+        }
+
+        ui_state.update_morph(dt);
+        if input.action_just_pressed(action_map, Action::Quit) {
+            *close_requested |= ui_state.request_exit();
+        }
+        if input.action_just_pressed(action_map, Action::ToggleUi) {
+            ui_state.ui_hidden = !ui_state.ui_hidden;
+        }
+        // With the scene in a panel, the camera only moves once the panel has been clicked
+        if viewport_panel.has_focus() {
+            camera_controller.update_camera(camera, input, action_map, dt);
+        }
+        if let Some((position, target, up)) = ui_state.network.followed_camera() {
+            (camera.position, camera.target, camera.up) = (position, target, up);
+        }
+        let (sides, radius) = (ui_state.sides, ui_state.radius);
+        ui_state.network.publish(camera, world::transform(world, object), sides, radius);
+        if let Some((_, mesh)) = skinned {
+            mesh.advance(dt);
+        }
+        ui_state.animation.update(
+            dt,
+            &mut AnimationTargets {
+                transform: &mut world::transform_mut(world, object),
+                light: &mut world.get::<&mut Light>(sun).expect("Sun has no Light"),
+                camera,
+                background,
+            },
+        );
+        ui_state.sequencer.update(
+            dt,
+            &mut SequencerTargets {
+                properties: AnimationTargets {
+                    transform: &mut world::transform_mut(world, object),
+                    light: &mut world.get::<&mut Light>(sun).expect("Sun has no Light"),
+                    camera,
+                    background,
+                },
+                animation: &ui_state.animation,
+                skinned: skinned.as_mut().map(|(_, mesh)| mesh),
+            },
+        );
+        ui_state.environment.update(
+            dt,
+            (
+                &mut world::transform_mut(world, sun),
+                &mut world.get::<&mut Light>(sun).expect("Sun has no Light"),
+            ),
+            background,
+        );
+        ui_state.water.update(dt);
+        ui_state.physics.update(dt, world);
+        ui_state.physics.draw_debug();
+        ui_state.scripts.update(dt, world, camera, &mut ui_state.toasts);
+        let mut context = AppContext {
+            device,
+            queue,
+            world,
+            camera,
+            terrain: &mut ui_state.terrain,
+            labels: &mut ui_state.labels,
+            events: ui_state.events.sender(),
+            object,
+            meshes: PrefabMeshes {
+                device,
+                uploads,
+                stats: render_stats,
+                geometry,
+            },
+            materials,
+            shader: ui_state.active_shader.name(),
+            redraw: false,
+        };
+        app.update(&mut context, dt);
+        plugins.update(&mut context, dt);
+        if context.redraw {
+            redraw.mark_dirty();
+        }
+        ui_state.labels.add_names(world);
+        if let Some(benchmark) = &benchmark {
+            *camera = Camera {
+                aspect: camera.aspect,
+                ..benchmark.camera(&initial_camera)
+            };
+        }
+        // With a single object in the scene, cycling alternates between it and nothing
+        let object_bounds = world::world_bounds(world, object);
+        if input.action_just_pressed(action_map, Action::CycleSelection) && object_bounds.is_some() {
+            ui_state.selected = !ui_state.selected;
+        }
+        if input.action_just_pressed(action_map, Action::NextBlock) && ui_state.hud.enabled {
+            ui_state.hud.select_next_block();
+        }
+        ui_state.hud.aim(camera, world, &ui_state.terrain);
+        // Nudge keys step the selection while no text field has focus
+        ui_state.nudging = false;
+        if ui_state.selected && !egui_renderer.context().wants_keyboard_input() {
+            let nudges = [
+                (Action::NudgeLeft, -Vec3::X),
+                (Action::NudgeRight, Vec3::X),
+                (Action::NudgeUp, Vec3::Y),
+                (Action::NudgeDown, -Vec3::Y),
+            ];
+            for (action, direction) in nudges {
+                if input.action_repeated(action_map, action) {
+                    world::transform_mut(world, object).translation += direction * NUDGE_STEP;
+                }
+                ui_state.nudging |= input.action_pressed(action_map, action);
+            }
+        }
+        cursor.request(if camera_controller.wants_cursor_grab(input) {
+            CursorMode::Locked
+        } else {
+            CursorMode::Normal
+        });
+        let over_ui = egui_renderer.context().wants_pointer_input() && !viewport_panel.hovered();
+        cursor.update(window, over_ui);
+
+        // Where the scene is in the window, for rays through the cursor
+        let full_window = (Vec2::ZERO, Vec2::new(config.width as f32, config.height as f32));
+        let scene_pixels = viewport_panel.pixel_rect().unwrap_or(full_window);
+
+        // Clicking a mesh selects it in the Scene Tree, and selects the object for the gizmo if
+        // it's the object; double-clicking also frames it. A click that ends on a gizmo handle
+        // belongs to the gizmo. While placing decals, a click puts one on the object or the ground
+        // instead
+        let clicked = input.clicked(MouseButton::Left) && !ui_state.gizmo.is_dragging();
+        if clicked && ui_state.decals.placing {
+            if let Some(cursor) = input.cursor_position() {
+                let (origin, direction) = cursor_ray(camera, scene_pixels, cursor);
+                let bounds = world::world_bounds(world, object);
+                if let Some((point, normal)) = decals::surface_hit(bounds.as_ref(), origin, direction) {
+                    ui_state.decals.place(world, point, normal);
+                }
+            }
+        } else if clicked {
+            if let Some(cursor) = input.cursor_position() {
+                let (origin, direction) = cursor_ray(camera, scene_pixels, cursor);
+                let picked = world::pick(world, origin, direction);
+                ui_state.selected = picked.is_some_and(|(entity, _)| entity == object);
+                ui_state.scene_tree.select(picked.map(|(entity, _)| entity));
+                *last_pick = Some((origin, direction, picked.map(|(_, distance)| distance)));
+                let bounds = picked.and_then(|(entity, _)| world::world_bounds(world, entity));
+                if let (true, Some(bounds)) = (input.double_clicked(MouseButton::Left), bounds) {
+                    camera.frame_bounds(&bounds);
+                }
+            }
+        }
+
+        // While painting the terrain, holding the left button over it paints there instead. A pen
+        // touching the scene paints at its tip, as firmly as it's pressed
+        let painting = ui_state.terrain.enabled && ui_state.terrain.painting;
+        if painting && !ui_state.gizmo.is_dragging() {
+            let stroke = match input.pen_position() {
+                Some(tip) => Some((tip, input.pen_pressure().unwrap_or(1.0))),
+                None if input.mouse_down(MouseButton::Left) => {
+                    input.cursor_position().map(|cursor| (cursor, 1.0))
+                }
+                None => None,
+            };
+            if let Some((position, pressure)) = stroke {
+                let (origin, direction) = cursor_ray(camera, scene_pixels, position);
+                if let Some(point) = ui_state.terrain.ray_hit(origin, direction) {
+                    ui_state.terrain.paint(point, dt, pressure);
+                    redraw.mark_dirty();
+                }
+            }
+        }
+
+        // With object dragging on, a left drag that starts on the object moves it
+        match input.drag(MouseButton::Left) {
+            Some(DragPhase::Started) if !ui_state.gizmo.is_dragging() && !painting => {
+                let bounds = world::world_bounds(world, object);
+                if let (Some(origin), Some(bounds)) = (input.drag_origin(MouseButton::Left), bounds) {
+                    let view_direction = (camera.target - camera.position).normalize_or_zero();
+                    let ray = cursor_ray(camera, scene_pixels, origin);
+                    let transform = world::transform(world, object);
+                    if ui_state.object_drag.begin(ray, view_direction, &transform, &bounds) {
+                        ui_state.selected = true;
+                        ui_state.scene_tree.select(Some(object));
+                    }
+                }
+            }
+            Some(DragPhase::Ended) | None => ui_state.object_drag.end(),
             _ => {}
         }
-    });
+        if let (true, Some(cursor)) = (ui_state.object_drag.is_dragging(), input.cursor_position()) {
+            let snap = ui_state.gizmo.snapping.then_some(ui_state.gizmo.translate_snap);
+            let ray = cursor_ray(camera, scene_pixels, cursor);
+            let mut transform = world::transform_mut(world, object);
+            ui_state.object_drag.update(ray, &mut transform, snap);
+        }
+        // "Back to shape" in the UI window puts the generated mesh back in place of a mesh file's
+        if ui_state.loaded_mesh.is_none() {
+            if let Some(imported) = imported.take() {
+                imported.remove(world, geometry, object);
+                scene_bundles.clear();
+                mesh_builder.rebuild();
+            }
+        }
+        // The Particles style hides the object and emits from it instead. The emitter is made again
+        // if something else removes it, like loading a scene
+        let emitting = ui_state.rendering_style == RenderingStyle::Particles && particles.is_some();
+        if emitting {
+            if !style_emitter.is_some_and(|emitter| world.contains(emitter)) {
+                let transform = Transform::IDENTITY;
+                *style_emitter = Some(world.spawn((
+                    transform,
+                    GlobalTransform(transform.matrix()),
+                    ParticleEmitter::new(),
+                    Parent(object),
+                    Name("Particles".to_string()),
+                )));
+                let _ = world.insert_one(object, Hidden);
+                redraw.mark_dirty();
+            }
+        } else if let Some(emitter) = style_emitter.take() {
+            let _ = world.despawn(emitter);
+            let _ = world.remove_one::<Hidden>(object);
+            redraw.mark_dirty();
+        }
+
+        // Only rebuild when the geometry actually changed; the old mesh stays up until the new
+        // one has been generated off the render thread
+        mesh_builder.request(mesh_shape(ui_state));
+        if let Some(built) = mesh_builder.poll(&mut apply_budget) {
+            puffin::profile_scope!("mesh_upload");
+            let mut mesh = world.get::<&mut MeshHandle>(object).unwrap();
+            geometry.free(&mesh.allocation);
+            mesh.allocation = geometry.allocate(device, uploads, render_stats, &built.vertices, &built.indices);
+            mesh.bounds = built.bounds;
+            drop(mesh);
+            let _ = world.insert_one(object, built.shape);
+            if let Some(imported) = imported.take() {
+                imported.remove(world, geometry, object);
+                ui_state.loaded_mesh = None;
+            }
+            scene_bundles.clear();
+            redraw.mark_dirty();
+            plots::debug_plot("mesh_rebuild_ms", built.elapsed.as_secs_f64() * 1000.0);
+        }
+
+        // With redraw on demand, a frame that would look the same as the last one is neither
+        // encoded nor presented. Input, file and asset polling above still ran
+        if ui_state.redraw_on_demand {
+            let skinned_playing = skinned.as_ref().is_some_and(|(_, mesh)| mesh.is_playing());
+            let particles_active = particles.as_ref().is_some_and(ParticleSystem::is_active);
+            // The headset takes a frame at its own rate, whether or not anything changed. Events
+            // waiting to be applied change something, whatever sent them
+            if egui_renderer.wants_repaint()
+                || ui_state.events.receive()
+                || xr.is_running()
+                || ui_state.is_animating()
+                || skinned_playing
+                || particles_active
+            {
+                redraw.mark_dirty();
+            }
+            let inputs = FrameInputs {
+                camera: *camera,
+                transform: world::transform(world, object),
+                surface_size: *configured_size,
+            };
+            if !redraw.should_draw(inputs) {
+                render_stats.cancel_frame();
+                render_stats.skipped_frames = redraw.skipped();
+                ui_state.pacer.pause();
+                ui_state.frame_timer.pause();
+                input.end_frame();
+                return None;
+            }
+        }
+        Some(dt)
+    }
+
+    // Acquires the surface texture and encodes the scene into it. Returns `None` when no texture could be acquired
+    fn render(&mut self, dt: f32) -> Option<Frame> {
+        let (scene_format, object, camera_entity) = (self.scene_format, self.object, self.camera_entity);
+        let Self {
+            app, window, camera, surface, device, queue, config, xr, gpu_errors, gpu_timings, render_stats,
+            bind_groups, camera_uniform, camera_buffer, lighting, camera_bind_group_layout, camera_bind_group, objects,
+            registry, uploads, materials, post, stereo, scene_windows, viewport_panel, probes, scene_target,
+            scene_depth, outline, skinning, instancing, particles, environment_map, sky, grid, water, decals,
+            billboards, terrain, debug_lines, msaa, create_fallback, fallback_pipeline, fallback_multisampled,
+            scene_pipelines, ui_state, plugin_overlays, submissions, geometry, world, transforms, background,
+            scene_bundles, bundle_generation, egui_renderer, input, last_pick, recordings, scenes, models, mesh_files,
+            skinned, redraw, ..
+        } = self;
+        let surface = surface.as_ref()?;
+        let acquire_start = Instant::now();
+        let acquired = {
+            puffin::profile_scope!("acquire");
+            match surface.get_current_texture() {
+                Ok(texture) => Ok(texture),
+                // The surface stopped matching the window between frames, as after moving to
+                // another monitor; configure it again and retry once
+                Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                    surface.configure(device, config);
+                    surface.get_current_texture()
+                }
+                Err(err) => Err(err),
+            }
+        };
+        let surface_texture = match acquired {
+            Ok(texture) => texture,
+            // Timed out waiting on the display, or still mismatched; the next frame tries again
+            Err(err) => {
+                if let wgpu::SurfaceError::OutOfMemory = err {
+                    log::error!("Failed to acquire next swap chain texture: {err}");
+                    ui_state.toasts.error(format!("Failed to acquire the next frame: {err}"));
+                } else {
+                    log::debug!("Skipped a frame: {err}");
+                }
+                render_stats.cancel_frame();
+                ui_state.pacer.pause();
+                ui_state.frame_timer.pause();
+                input.end_frame();
+                window.request_redraw();
+                return None;
+            }
+        };
+        let acquire_wait = acquire_start.elapsed();
+
+        // With the scene in a panel, it's drawn at the panel's size and the camera takes its aspect
+        // and, for a pixel view, its height
+        let panel_active = ui_state.scene_in_panel && !ui_state.ui_hidden;
+        let panel_size = viewport_panel.prepare(device, render_stats, egui_renderer, panel_active);
+        let output_size = panel_size.unwrap_or((config.width, config.height));
+        camera.set_viewport(output_size.0, output_size.1);
+
+        // The controllers drive the viewport camera; systems see it as the active camera entity
+        if let Ok(mut active) = world.get::<&mut Camera>(camera_entity) {
+            *active = *camera;
+        }
+        let mut view = world::active_camera(world).unwrap_or(*camera);
+        // Waits for the headset's frame, then the window mirrors what it sees
+        if let Some(head) = xr.begin_frame(&view, &mut ui_state.toasts) {
+            view = head;
+        }
+        camera_uniform.update_view_proj(&view);
+        uploads.write(device, camera_buffer, 0, bytemuck::cast_slice(&[*camera_uniform]));
+
+
+        let surface_view = surface_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        // The scene and the UI are encoded into separate command buffers, submitted together once both
+        // are finished. The scene's per-mesh bundles were recorded above, across threads when many
+        // changed
+        let mut scene_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Scene Encoder"),
+        });
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("UI Encoder"),
+        });
+
+        // Transforms may still change during the UI pass; a culled object reappears a frame late
+        // at worst
+        // Switching the shader gives the object that shader's default material
+        if let Ok(mut material) = world.get::<&mut MaterialHandle>(object) {
+            if materials.get(material.0).shader != ui_state.active_shader.name() {
+                material.0 = materials.default_for(ui_state.active_shader.name());
+            }
+        }
+        world::propagate_transforms(world, transforms);
+        let image = &ui_state.environment.image;
+        let image_light = environment_map.average().filter(|_| image.lighting).map(|average| {
+            average * image.intensity
+        });
+        lighting.update(device, uploads, world, &ui_state.environment.fog, image_light);
+        let image_intensity = if image_light.is_some() { image.intensity } else { 0.0 };
+        probes.set_environment_light(image_intensity, image.rotation.to_radians());
+        // A newly loaded environment image is resampled onto its cube ahead of the passes showing it
+        let mut environment_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Environment Map Encoder"),
+        });
+        if environment_map.convert(render_stats, &mut environment_encoder) {
+            submissions.push(Stage::Compute, environment_encoder.finish());
+        }
+        // Particles step ahead of the scene in a command buffer of their own, so the passes below
+        // draw this frame's positions
+        if let Some(particles) = particles {
+            let mut compute_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Particle Encoder"),
+            });
+            if particles.simulate(device, uploads, world, dt, &mut compute_encoder) {
+                submissions.push(Stage::Compute, compute_encoder.finish());
+            }
+        }
+        let frustum = Frustum::from_view_projection(view.view_projection_matrix());
+        let culled = world::cull(world, &frustum);
+        // Of what's left, what's hidden behind the rest. The eyes of a stereo view see around what
+        // hides things from the camera between them, so they keep every mesh in the frustum
+        let occluded = if ui_state.stereo.is_enabled() {
+            0
+        } else {
+            let ground = ui_state.terrain.occluder(view.position);
+            let view_proj = view.view_projection_matrix();
+            ui_state.occlusion.cull(world, geometry, &ground, view_proj)
+        };
+        // Culling bounds in green where drawn and red where culled
+        if ui_state.show_bounds {
+            let meshes = world.query_mut::<(&MeshHandle, &GlobalTransform, &Visible)>();
+            for (mesh, global, visible) in meshes {
+                let color = if visible.0 {
+                    egui::Color32::from_rgb(80, 200, 80)
+                } else {
+                    egui::Color32::from_rgb(230, 70, 70)
+                };
+                if let Some(bounds) = mesh.bounds {
+                    debug::aabb(&bounds.transformed(global.0), color);
+                }
+            }
+        }
+        if let Some((origin, direction, hit)) = last_pick.filter(|_| ui_state.show_pick_rays) {
+            let color = if hit.is_some() {
+                egui::Color32::from_rgb(255, 220, 60)
+            } else {
+                egui::Color32::GRAY
+            };
+            debug::ray(origin, direction, hit.unwrap_or(camera.zfar), color);
+            if let Some(distance) = hit {
+                debug::sphere(origin + direction * distance, 0.05, color);
+            }
+        }
+        let draws = world::draws(world, materials);
+        // Probes see every mesh, not just those in the camera's view
+        let requests = ui_state.reflection_probes.take_requests();
+        let probe_draws = if probes.update(world, *background, &requests) {
+            world::all_draws(world, materials)
+        } else {
+            Vec::new()
+        };
+        // The eyes and scene windows see around the window's view, so they draw every mesh too
+        let eye_draws = if xr.is_rendering() || !scene_windows.is_empty() {
+            world::all_draws(world, materials)
+        } else {
+            Vec::new()
+        };
+        let object_count = draws.len() + probe_draws.len() + eye_draws.len() + usize::from(skinned.is_some());
+        if objects.begin_frame(device, render_stats, object_count)
+            || geometry.generation() != *bundle_generation
+        {
+            scene_bundles.clear();
+            *bundle_generation = geometry.generation();
+        }
+        // Filled in again once the UI has had its chance to move objects
+        let offsets: Vec<wgpu::DynamicOffset> =
+            draws.iter().map(|draw| objects.push(ModelUniform::from_matrix(draw.model))).collect();
+        let probe_offsets: Vec<wgpu::DynamicOffset> = probe_draws
+            .iter()
+            .map(|draw| objects.push(ModelUniform::from_matrix(draw.model)))
+            .collect();
+        let eye_offsets: Vec<wgpu::DynamicOffset> = eye_draws
+            .iter()
+            .map(|draw| objects.push(ModelUniform::from_matrix(draw.model)))
+            .collect();
+        scene_bundles.retain(|entity, _| draws.iter().any(|draw| draw.entity == *entity));
+        if ui_state.msaa_samples != msaa.samples() {
+            let samples = ui_state.msaa_samples;
+            msaa.set_samples(render_stats, samples);
+            for pipeline in scene_pipelines.values_mut() {
+                pipeline.set_samples(samples);
+            }
+            *fallback_multisampled = (samples > 1).then(|| create_fallback(samples));
+            scene_bundles.clear();
+        }
+        let polygon_mode = ui_state.render_mode.polygon_mode();
+        // Every pipeline is polled every frame, not just until the first one is ready
+        let mut ready = false;
+        for pipeline in scene_pipelines.values_mut() {
+            pipeline.set_polygon_mode(polygon_mode);
+            ready |= pipeline.poll();
+        }
+        if ready {
+            scene_bundles.clear();
+        }
+        ui_state.compiling_pipelines = scene_pipelines
+            .values()
+            .filter(|pipeline| pipeline.status() == PipelineStatus::Compiling)
+            .map(|pipeline| pipeline.label)
+            .collect();
+        let mut loading = LoadProgress::default();
+        loading.add(recordings);
+        loading.add(scenes);
+        loading.add(models);
+        loading.add(mesh_files);
+        loading.add(materials.images());
+        ui_state.loading_assets = loading;
+        // Bundle recording and the pass report their errors when the encoder finishes
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let object_bind_group = objects.bind_group(device, bind_groups);
+        // Bundles of `draws` seen through `camera_group`, with `probe_group` to reflect, for a pass of
+        // `samples` samples per pixel
+        let scene_bundle = |camera_group: &wgpu::BindGroup,
+                            probe_group: &wgpu::BindGroup,
+                            draws: &[(&Draw, wgpu::DynamicOffset)],
+                            samples: u32| {
+            let fallback = fallback_multisampled.as_deref().filter(|_| samples > 1);
+            let draws: Vec<BundleDraw> = draws
+                .iter()
+                .map(|&(draw, offset)| {
+                    let shader = registry.id(draw.material.shader).unwrap_or(ShaderId::MAIN);
+                    let pipeline = scene_pipelines[&shader].get_multisampled(samples);
+                    // The fallback shares the main layout, so it takes the default main material.
+                    // Only shaders that read the reflection probes have the probe group
+                    let (pipeline, params, groups) = match pipeline {
+                        Some(pipeline) => {
+                            let groups = if registry.get(shader).reflections { 4 } else { 3 };
+                            (pipeline, materials.bind_group(draw.material.material), groups)
+                        }
+                        None => {
+                            let pipeline = fallback.unwrap_or(fallback_pipeline);
+                            (pipeline, materials.bind_group(materials.default_for("main")), 4)
+                        }
+                    };
+                    let all_groups: [(&wgpu::BindGroup, Option<wgpu::DynamicOffset>); 4] = [
+                        (camera_group, None),
+                        (&object_bind_group, Some(offset)),
+                        (params, None),
+                        (probe_group, None),
+                    ];
+                    BundleDraw {
+                        pipeline,
+                        bind_groups: all_groups[..groups].to_vec(),
+                        mesh: &draw.mesh,
+                    }
+                })
+                .collect();
+            geometry.record_bundles(device, scene_format, samples, &draws)
+        };
+        // Only draws that changed since their bundle was recorded are recorded again
+        let stale: Vec<(&Draw, wgpu::DynamicOffset)> = draws
+            .iter()
+            .zip(offsets.iter().copied())
+            .filter(|(draw, offset)| {
+                let key = (draw.material, *offset, draw.mesh.clone());
+                scene_bundles.get(&draw.entity).is_none_or(|(recorded, _)| *recorded != key)
+            })
+            .collect();
+        let recorded = scene_bundle(camera_bind_group, probes.bind_group(), &stale, msaa.samples());
+        for ((draw, offset), bundle) in stale.into_iter().zip(recorded) {
+            scene_bundles.insert(draw.entity, ((draw.material, offset, draw.mesh.clone()), bundle));
+        }
+
+        // Away from full resolution the scene goes to an offscreen target, stretched over the surface
+        // before the UI is drawn. Supersampling stops at the largest texture the device allows
+        let max_size = device.limits().max_texture_dimension_2d;
+        let scene_size = ui_state.resolution.scaled_size(output_size, max_size);
+        if scene_size == output_size {
+            scene_target.release(render_stats);
+        } else {
+            scene_target.resize(device, render_stats, scene_size);
+        }
+        if let Some(post) = post {
+            post.resize(device, render_stats, scene_size);
+        }
+        // Where the scene ends up: the panel's texture while it's shown in one, otherwise the surface
+        let output_view = viewport_panel.view().unwrap_or(&surface_view);
+        let output_texture = viewport_panel.texture().unwrap_or(&surface_texture.texture);
+        // What the scene passes draw into: the HDR texture while post-processing, otherwise the
+        // scaled target or the output itself
+        let scene_view = post.as_ref().and_then(PostProcessor::view).or(scene_target.view());
+        let scene_view = scene_view.unwrap_or(output_view);
+        let scene_texture = post.as_ref().and_then(PostProcessor::texture).or(scene_target.texture());
+        let scene_texture = scene_texture.unwrap_or(output_texture);
+        render_stats.scene_size = scene_size;
+        scene_depth.resize(device, render_stats, scene_size);
+        msaa.resize(device, render_stats, scene_size);
+        // A waiting probe is captured ahead of the scene that reflects it
+        {
+            let mut probe_scope = gpu_timings.profiler.scope("Reflection Probes", &mut scene_encoder, device);
+            let record = |camera_group: &wgpu::BindGroup, probe_group: &wgpu::BindGroup| {
+                let draws: Vec<_> = probe_draws.iter().zip(probe_offsets.iter().copied()).collect();
+                scene_bundle(camera_group, probe_group, &draws, 1)
+            };
+            let far = view.zfar;
+            if probes.render(device, uploads, &mut probe_scope, world, *background, far, record) {
+                for draw in probe_draws.iter().cycle().take(probe_draws.len() * 6) {
+                    render_stats.record_draw(draw.mesh.num_indices, 1);
+                }
+                redraw.mark_dirty();
+            }
+        }
+        if xr.is_rendering() {
+            let mut eye_scope = gpu_timings.profiler.scope("XR Eyes", &mut scene_encoder, device);
+            let record = |camera_group: &wgpu::BindGroup| {
+                let draws: Vec<_> = eye_draws.iter().zip(eye_offsets.iter().copied()).collect();
+                scene_bundle(camera_group, probes.bind_group(), &draws, 1)
+            };
+            let toasts = &mut ui_state.toasts;
+            if xr.render(device, uploads, &mut eye_scope, *background, record, toasts) {
+                for draw in eye_draws.iter().cycle().take(eye_draws.len() * 2) {
+                    render_stats.record_draw(draw.mesh.num_indices, 1);
+                }
+            }
+        }
+        {
+            puffin::profile_scope!("encode_scene");
+            let mut scene_scope = gpu_timings.profiler.scope("Scene", &mut scene_encoder, device);
+            // With MSAA the pass draws into the multisampled targets, and the color is resolved into
+            // the scene's own target; only the resolved color is kept
+            let target = scene_view;
+            let (view, resolve_target) = msaa.color_attachment(target);
+            let pass_descriptor = wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: background.x as f64,
+                            g: background.y as f64,
+                            b: background.z as f64,
+                            a: 1.0,
+                        }),
+                        store: match resolve_target {
+                            Some(_) => wgpu::StoreOp::Discard,
+                            None => wgpu::StoreOp::Store,
+                        },
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: msaa.depth_attachment(scene_depth.view()),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                // Adding missing fields with default values
+                occlusion_query_set: None, // Default value, as occlusion queries aren't used
+                timestamp_writes: None,    // Filled in by the GPU profiler
+            };
+            let mut render_pass = scene_scope.scoped_render_pass("Render Pass", device, pass_descriptor);
+
+            // Replays the recorded draws instead of re-issuing pipeline, bind group and buffer calls
+            let bundles = draws.iter().filter_map(|draw| scene_bundles.get(&draw.entity));
+            render_pass.execute_bundles(bundles.map(|(_, bundle)| bundle));
+            for draw in &draws {
+                render_stats.record_draw(draw.mesh.num_indices, 1);
+            }
+            for _ in 0..culled {
+                render_stats.record_culled();
+            }
+            for _ in 0..occluded {
+                render_stats.record_occluded();
+            }
+        }
+        if msaa.samples() > 1 {
+            let mut resolve_scope = gpu_timings.profiler.scope("MSAA Resolve", &mut scene_encoder, device);
+            msaa.resolve_depth(&mut resolve_scope, scene_depth.view());
+        }
+        // Skinned meshes go in a pass of their own, depth tested against the scene
+        if let (Some(renderer), Some((entity, mesh))) = (&skinning, skinned) {
+            let model = world.get::<&GlobalTransform>(*entity).map(|global| global.0);
+            let offset = objects.push(ModelUniform::from_matrix(model.unwrap_or(Mat4::IDENTITY)));
+            mesh.upload(device, uploads);
+            let object_bind_group = objects.bind_group(device, bind_groups);
+            let groups: [(&wgpu::BindGroup, &[wgpu::DynamicOffset]); 2] =
+                [(camera_bind_group, &[]), (&object_bind_group, &[offset])];
+            let target = scene_view;
+            let mut skinning_scope = gpu_timings.profiler.scope("Skinning", &mut scene_encoder, device);
+            let depth = scene_depth.view();
+            renderer.draw(render_stats, &mut skinning_scope, mesh, &groups, target, depth);
+        }
+        // Copies of the object's mesh, likewise in a pass of their own
+        if ui_state.instancing.enabled {
+            instancing.update(device, render_stats, uploads, &ui_state.instancing);
+            let offset = objects.push(ModelUniform::from_matrix(world::world_matrix(world, object)));
+            let object_bind_group = objects.bind_group(device, bind_groups);
+            let groups: [(&wgpu::BindGroup, &[wgpu::DynamicOffset]); 2] =
+                [(camera_bind_group, &[]), (&object_bind_group, &[offset])];
+            let mesh = world.get::<&MeshHandle>(object).unwrap().allocation.clone();
+            let target = scene_view;
+            let mut instancing_scope = gpu_timings.profiler.scope("Instancing", &mut scene_encoder, device);
+            let depth = scene_depth.view();
+            instancing.draw(render_stats, &mut instancing_scope, geometry, &mesh, &groups, target, depth);
+        }
+        // Terrain is opaque too, depth tested against the scene and lit by the same lights
+        if ui_state.terrain.enabled {
+            let target = scene_view;
+            let mut terrain_scope = gpu_timings.profiler.scope("Terrain", &mut scene_encoder, device);
+            terrain.draw(
+                device,
+                render_stats,
+                uploads,
+                &mut terrain_scope,
+                camera_bind_group,
+                &ui_state.terrain,
+                target,
+                scene_depth.view(),
+            );
+        }
+        // Decals go onto the opaque scene, before anything else covers it
+        {
+            let target = scene_view;
+            let mut decal_scope = gpu_timings.profiler.scope("Decals", &mut scene_encoder, device);
+            decals.draw(
+                device,
+                render_stats,
+                uploads,
+                &mut decal_scope,
+                &view,
+                world,
+                target,
+                scene_depth.view(),
+                scene_size,
+            );
+        }
+        // Billboards stand in the scene after the decals, so none are projected onto them
+        {
+            let target = scene_view;
+            let mut billboard_scope = gpu_timings.profiler.scope("Billboards", &mut scene_encoder, device);
+            billboards.draw(
+                device,
+                render_stats,
+                uploads,
+                &mut billboard_scope,
+                &view,
+                world,
+                target,
+                scene_depth.view(),
+            );
+        }
+        // The sky fills in whatever the opaque passes left at the far plane
+        if ui_state.environment.draws_sky(environment_map.is_ready()) {
+            let target = scene_view;
+            let mut sky_scope = gpu_timings.profiler.scope("Sky", &mut scene_encoder, device);
+            sky.draw(
+                device,
+                render_stats,
+                uploads,
+                &mut sky_scope,
+                &view,
+                &ui_state.environment,
+                environment_map,
+                target,
+                scene_depth.view(),
+            );
+        }
+        // The grid blends over the opaque scene, hidden where objects stand in front of it
+        if ui_state.grid.enabled {
+            let target = scene_view;
+            let mut grid_scope = gpu_timings.profiler.scope("Grid", &mut scene_encoder, device);
+            grid.draw(
+                device,
+                render_stats,
+                uploads,
+                &mut grid_scope,
+                &view,
+                &ui_state.grid,
+                target,
+                scene_depth.view(),
+            );
+        }
+        // Water reads back everything opaque drawn so far, to refract and reflect it
+        if ui_state.water.enabled {
+            let texture = scene_texture;
+            let target = scene_view;
+            let mut water_scope = gpu_timings.profiler.scope("Water", &mut scene_encoder, device);
+            water.draw(
+                device,
+                render_stats,
+                uploads,
+                &mut water_scope,
+                &view,
+                &ui_state.water,
+                *background,
+                texture,
+                target,
+                scene_depth.view(),
+            );
+        }
+        // Particles blend over everything opaque, fading out where they meet it
+        if let Some(particles) = particles.as_mut().filter(|particles| particles.is_active()) {
+            let target = scene_view;
+            let mut particle_scope = gpu_timings.profiler.scope("Particles", &mut scene_encoder, device);
+            particles.draw(
+                device,
+                render_stats,
+                uploads,
+                &mut particle_scope,
+                &view,
+                target,
+                scene_depth.view(),
+                scene_size,
+            );
+        }
+        // Plugin overlays blend over the finished scene, under the debug lines and outline
+        if !plugin_overlays.is_empty() {
+            let target = scene_view;
+            let mut overlay_scope = gpu_timings.profiler.scope("Plugin Overlays", &mut scene_encoder, device);
+            plugin_overlays.draw(
+                device,
+                render_stats,
+                uploads,
+                &mut overlay_scope,
+                target,
+                scene_size,
+            );
+        }
+        {
+            let target = scene_view;
+            let mut app_scope = gpu_timings.profiler.scope("Application", &mut scene_encoder, device);
+            app.render(&mut RenderContext {
+                device,
+                queue,
+                encoder: &mut app_scope,
+                target,
+                depth: scene_depth.view(),
+                format: scene_format,
+                size: scene_size,
+                camera_layout: camera_bind_group_layout,
+                camera_bind_group,
+            });
+        }
+        {
+            let target = scene_view;
+            let mut debug_scope = gpu_timings.profiler.scope("Debug Lines", &mut scene_encoder, device);
+            debug_lines.draw(
+                device,
+                render_stats,
+                uploads,
+                &mut debug_scope,
+                camera_bind_group,
+                target,
+                scene_depth.view(),
+            );
+        }
+        // The selection outline goes over the scene at the scene's resolution, before upscaling. It's
+        // around the object while it's selected for the gizmo, or else what the Scene Tree has selected
+        let highlighted = match ui_state.scene_tree.selected() {
+            Some(entity) if entity != object => Some(entity),
+            _ => Some(object).filter(|_| ui_state.selected),
+        };
+        let selected_draw = draws
+            .iter()
+            .zip(&offsets)
+            .find(|(draw, _)| Some(draw.entity) == highlighted);
+        if let Some((draw, &offset)) = selected_draw {
+            let color = egui_renderer.context().style().visuals.selection.stroke.color;
+            outline.set_color(device, uploads, egui::Rgba::from(color).to_array());
+            let object_bind_group = objects.bind_group(device, bind_groups);
+            let groups: [(&wgpu::BindGroup, &[wgpu::DynamicOffset]); 2] =
+                [(camera_bind_group, &[]), (&object_bind_group, &[offset])];
+            let target = scene_view;
+            let mut outline_scope = gpu_timings.profiler.scope("Outline", &mut scene_encoder, device);
+            outline.draw(
+                device,
+                render_stats,
+                &mut outline_scope,
+                geometry,
+                &draw.mesh,
+                &groups,
+                target,
+                scene_size,
+            );
+        }
+        // The eyes replace everything drawn above; the culled draws are close enough to what either
+        // eye sees, being at most half the separation away
+        if ui_state.stereo.is_enabled() {
+            let target = scene_view;
+            let mut stereo_scope = gpu_timings.profiler.scope("Stereo", &mut scene_encoder, device);
+            let record = |camera_group: &wgpu::BindGroup| {
+                let draws: Vec<_> = draws.iter().zip(offsets.iter().copied()).collect();
+                scene_bundle(camera_group, probes.bind_group(), &draws, 1)
+            };
+            if stereo.render(
+                device,
+                render_stats,
+                uploads,
+                &mut stereo_scope,
+                &ui_state.stereo,
+                &view,
+                *background,
+                record,
+                target,
+                scene_size,
+            ) {
+                for draw in draws.iter().cycle().take(draws.len() * 2) {
+                    render_stats.record_draw(draw.mesh.num_indices, 1);
+                }
+            }
+        } else {
+            stereo.release(render_stats);
+        }
+        if !scene_windows.is_empty() {
+            let mut windows_scope = gpu_timings.profiler.scope("Scene Windows", &mut scene_encoder, device);
+            let record = |camera_group: &wgpu::BindGroup| {
+                let draws: Vec<_> = eye_draws.iter().zip(eye_offsets.iter().copied()).collect();
+                scene_bundle(camera_group, probes.bind_group(), &draws, 1)
+            };
+            scene_windows.render(device, render_stats, uploads, &mut windows_scope, *background, record);
+            for draw in eye_draws.iter().cycle().take(eye_draws.len() * scene_windows.len()) {
+                render_stats.record_draw(draw.mesh.num_indices, 1);
+            }
+        }
+        // Ends in the scaled target, or straight in the output at full resolution
+        if let Some(post) = post {
+            let mut post_scope = gpu_timings.profiler.scope("Post-Processing", &mut scene_encoder, device);
+            let output = scene_target.view().unwrap_or(output_view);
+            post.run(device, uploads, &mut post_scope, &ui_state.post_fx, output);
+        }
+        if scene_target.view().is_some() {
+            let mut upscale_scope = gpu_timings.profiler.scope("Upscale", &mut scene_encoder, device);
+            scene_target.upscale(&mut upscale_scope, output_view, ui_state.resolution.filter);
+        }
+        gpu_timings.profiler.resolve_queries(&mut scene_encoder);
+        let scene_commands = scene_encoder.finish();
+        // A scene that fails validation is left out of the submit, and a compiled pipeline that
+        // caused it is dropped so the fallback draws from the next frame on
+        let scene_commands = if gpu_errors.pop_scope(device, "Render Pass", &mut ui_state.toasts) {
+            scene_bundles.clear();
+            for draw in &draws {
+                let shader = registry.id(draw.material.shader).unwrap_or(ShaderId::MAIN);
+                let pipeline = scene_pipelines.get_mut(&shader).unwrap();
+                if pipeline.get().is_some() {
+                    pipeline.fail();
+                }
+            }
+            None
+        } else {
+            Some(scene_commands)
+        };
+        if let Some(scene_commands) = scene_commands {
+            submissions.push(Stage::Scene, scene_commands);
+        }
+        Some(Frame {
+            surface_texture,
+            surface_view,
+            encoder,
+            draws,
+            offsets,
+            acquire_wait,
+        })
+    }
+
+    // Draws the UI over the frame, then applies the events and commands it sent. Returns the screenshot one of them
+    // recorded
+    fn ui(&mut self, frame: &mut Frame, elwt: &EventLoopWindowTarget<UserEvent>) -> Option<RecordedScreenshot> {
+        let Frame { surface_texture, surface_view, encoder, .. } = frame;
+        let (initial_camera, object, sun) = (self.initial_camera, self.object, self.sun);
+        let Self {
+            app, window, camera, camera_controller, settings, instance, adapter, device, queue, config, gpu_timings,
+            render_stats, lighting, camera_bind_group_layout, registry, uploads, materials, post, scene_windows,
+            viewport_panel, particles, ui_state, plugins, action_map, buffer_pool, geometry, world, background,
+            egui_renderer, close_requested, restart_renderer, clipboard, recorder, pending_recording, scenes,
+            mesh_files, prefabs, skinned, watched_scene, redraw, ..
+        } = self;
+        let screen_descriptor = ScreenDescriptor {
+            size_in_pixels: [config.width, config.height],
+            pixels_per_point: ui_state.pixels_per_point(window.scale_factor() as f32),
+        };
+
+        let mut ui_scope = gpu_timings.profiler.scope("UI", encoder, device);
+        egui_renderer.draw(
+            device,
+            queue,
+            &mut ui_scope,
+            window,
+            surface_view,
+            screen_descriptor,
+            |ctx| {
+                puffin::profile_scope!("ui");
+                let object_bounds = world::world_bounds(world, object);
+                ui_state.draw_ui(
+                    ctx,
+                    camera,
+                    camera_controller,
+                    object_bounds,
+                    &mut world::transform_mut(world, object),
+                    render_stats,
+                    action_map,
+                    &mut |ui| app.menu(ui),
+                );
+                viewport_panel.show(ctx, ui_state.scene_in_panel && !ui_state.ui_hidden);
+                ui_state.animation.show(
+                    ctx,
+                    &mut AnimationTargets {
+                        transform: &mut world::transform_mut(world, object),
+                        light: &mut world.get::<&mut Light>(sun).expect("Sun has no Light"),
+                        camera,
+                        background,
+                    },
+                );
+                ui_state.sequencer.show(
+                    ctx,
+                    &mut SequencerTargets {
+                        properties: AnimationTargets {
+                            transform: &mut world::transform_mut(world, object),
+                            light: &mut world.get::<&mut Light>(sun).expect("Sun has no Light"),
+                            camera,
+                            background,
+                        },
+                        animation: &ui_state.animation,
+                        skinned: skinned.as_mut().map(|(_, mesh)| mesh),
+                    },
+                );
+                if let Some((_, mesh)) = skinned {
+                    mesh.show(ctx);
+                }
+                if let Some(particles) = particles {
+                    particles.show(ctx, world);
+                }
+                ui_state.physics.show(ctx, world, object);
+                ui_state.scripts.show(ctx, world, object, &mut ui_state.file_dialogs);
+                lighting.show(ctx, world, sun, camera.target);
+                if let Some(post) = post {
+                    post.show(ctx, &mut ui_state.post_fx);
+                }
+                gpu_timings.show(ctx, device.features());
+                ui_state.decals.show(ctx, world, camera.target, &mut ui_state.file_dialogs);
+                ui_state.billboards.show(ctx, world, camera.target, &mut ui_state.file_dialogs);
+                ui_state.reflection_probes.show(ctx, world, camera.target);
+                let mut meshes = PrefabMeshes {
+                    device,
+                    uploads,
+                    stats: render_stats,
+                    geometry,
+                };
+                prefabs.show(ctx, world, &mut meshes);
+                // The object and sun are held by the render loop and can't be removed
+                let protected = [object, sun];
+                let placement = camera.target;
+                let scene_tree = &mut ui_state.scene_tree;
+                scene_tree.show(ctx, world, &mut meshes, materials, placement, &protected);
+                // Typing a name or dragging a value is one edit once done
+                let interacting = ctx.input(|i| i.pointer.any_down()) || ctx.wants_keyboard_input();
+                ui_state.history.track_nodes(scene_tree::save_nodes(world, object), interacting);
+                let mut context = AppContext {
+                    device,
+                    queue,
+                    world,
+                    camera,
+                    terrain: &mut ui_state.terrain,
+                    labels: &mut ui_state.labels,
+                    events: ui_state.events.sender(),
+                    object,
+                    meshes,
+                    materials,
+                    shader: ui_state.active_shader.name(),
+                    redraw: false,
+                };
+                app.ui(ctx, &mut context);
+                plugins.show(ctx);
+                if context.redraw {
+                    redraw.mark_dirty();
+                }
+                let assigned = materials.show(
+                    ctx,
+                    device,
+                    render_stats,
+                    world,
+                    object,
+                    &mut ui_state.file_dialogs,
+                );
+                if let Some(id) = assigned {
+                    let _ = world.insert_one(object, MaterialHandle(id));
+                    ui_state.active_shader = registry.resolve(materials.get(id).shader);
+                }
+            },
+        );
+        drop(ui_scope);
+
+        let mut screenshot = None;
+        let mut screenshot_to = None; // Where an event asked for the next saved screenshot to go
+        for event in ui_state.events.drain() {
+            redraw.mark_dirty();
+            match event {
+                AppEvent::Command(command) => ui_state.commands.push(command),
+                AppEvent::SetSides(sides) => {
+                    // The playing morph sends its own shape; any other takes over from it
+                    ui_state.morph.playing &= sides == ui_state.morph.sides();
+                    ui_state.sides = sides.clamp(MIN_SIDES, MAX_SIDES);
+                }
+                AppEvent::LoadMesh(path) => {
+                    mesh_files.load(path, false);
+                }
+                AppEvent::SetShader(name) => match registry.id(&name) {
+                    Some(id) => ui_state.active_shader = id,
+                    None => ui_state.toasts.warning(format!("There is no shader called {name:?}")),
+                },
+                AppEvent::Screenshot(path) => {
+                    screenshot_to = path;
+                    ui_state.commands.push(Command::SaveScreenshot);
+                }
+            }
+        }
+        for command in std::mem::take(&mut ui_state.commands) {
+            match ui_state.apply_command(command) {
+                Some(Command::ResetCamera) => {
+                    *camera = Camera {
+                        aspect: camera.aspect,
+                        ..initial_camera
+                    };
+                }
+                Some(Command::FrameSelection) => {
+                    let bounds = world::world_bounds(world, object);
+                    if let Some(bounds) = bounds.filter(|_| ui_state.selected) {
+                        camera.frame_bounds(&bounds);
+                    }
+                }
+                Some(Command::ResetTransform) => {
+                    *world::transform_mut(world, object) = Transform::IDENTITY;
+                }
+                Some(command @ (Command::Undo | Command::Redo)) => {
+                    let nodes = match command {
+                        Command::Undo => ui_state.undo(&mut world::transform_mut(world, object)),
+                        _ => ui_state.redo(&mut world::transform_mut(world, object)),
+                    };
+                    if let Some(nodes) = nodes {
+                        let mut meshes = PrefabMeshes {
+                            device,
+                            uploads,
+                            stats: render_stats,
+                            geometry,
+                        };
+                        let material = |index| materials.iter().nth(index).map(|(id, _)| id);
+                        scene_tree::restore_nodes(world, &mut meshes, &nodes, object, material);
+                    }
+                }
+                Some(Command::SaveScene) => {
+                    if let Some(path) = ui_state.scene_path.clone() {
+                        let scene = current_scene(
+                            ui_state,
+                            world,
+                            materials,
+                            object,
+                            sun,
+                            camera,
+                            *background,
+                        );
+                        save_scene(path, ui_state, scene, scenes, watched_scene);
+                    }
+                }
+                Some(Command::CopyTransform) => {
+                    let json = ClipboardItem::Transform(world::transform(world, object)).to_json();
+                    match clipboard.copy_text(json) {
+                        Ok(()) => ui_state.toasts.info("Copied transform"),
+                        Err(err) => ui_state.toasts.error(format!("Copy failed: {err}")),
+                    }
+                }
+                // Applied to the selected object; the history records it like any other edit
+                Some(Command::PasteTransform) => match clipboard.paste_text() {
+                    Ok(text) => match ClipboardItem::from_json(&text) {
+                        Ok(ClipboardItem::Transform(transform)) => {
+                            *world::transform_mut(world, object) = transform;
+                        }
+                        Err(_) => ui_state.toasts.warning("Clipboard does not hold a transform"),
+                    },
+                    Err(err) => ui_state.toasts.error(format!("Paste failed: {err}")),
+                },
+                // Saved screenshots go to a new file; copied ones have no path
+                Some(command @ (Command::CopyScreenshot | Command::SaveScreenshot)) => {
+                    if config.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+                        let recorded = Screenshot::record(
+                            device,
+                            buffer_pool,
+                            render_stats,
+                            encoder,
+                            &surface_texture.texture,
+                        );
+                        let save = command == Command::SaveScreenshot;
+                        let path = save.then(|| {
+                            screenshot_to.take().unwrap_or_else(screenshot::screenshot_path)
+                        });
+                        screenshot = Some((recorded, path));
+                    } else {
+                        ui_state.toasts.warning("Screenshots are not supported by this surface");
+                    }
+                }
+                Some(Command::ToggleInputRecording) => {
+                    if let Some(recording) = recorder.stop_recording() {
+                        ui_state.toasts.info(format!(
+                            "Recorded {} input events over {:.1}s",
+                            recording.events.len(),
+                            recording.duration()
+                        ));
+                        *pending_recording = Some(recording);
+                        ui_state.file_dialogs.save(FileKind::Recording);
+                    } else if !recorder.is_playing() {
+                        *camera = Camera {
+                            aspect: camera.aspect,
+                            ..initial_camera
+                        };
+                        recorder.start_recording();
+                        if ui_state.sequencer.follow_recordings {
+                            ui_state.sequencer.restart();
+                        }
+                    }
+                }
+                Some(Command::ToggleSessionRecording) => ui_state.session.toggle_recording(),
+                // A Tab with nothing focused makes egui focus its first widget
+                Some(Command::FocusUi) => egui_renderer.push_event(egui::Event::Key {
+                    key: egui::Key::Tab,
+                    physical_key: None,
+                    pressed: true,
+                    repeat: false,
+                    modifiers: egui::Modifiers::NONE,
+                }),
+                Some(Command::OpenSceneWindow) => {
+                    let opened = scene_windows.open(
+                        elwt,
+                        instance,
+                        adapter,
+                        device,
+                        render_stats,
+                        camera_bind_group_layout,
+                        lighting.buffer(),
+                        config.format,
+                        *camera,
+                    );
+                    if let Err(err) = opened {
+                        ui_state.toasts.error(format!("Failed to open a scene window: {err}"));
+                    }
+                }
+                Some(Command::Quit) => *close_requested |= ui_state.request_exit(),
+                _ => {}
+            }
+        }
+
+        // Video frames are copied after the UI, like screenshots, and read back frames later
+        if config.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+            let texture = &surface_texture.texture;
+            ui_state.video.record(device, buffer_pool, render_stats, encoder, texture);
+        } else if ui_state.video.is_recording() {
+            ui_state.toasts.warning("Video capture is not supported by this surface");
+            ui_state.video.toggle(&mut ui_state.toasts);
+        }
+
+        *close_requested |= ui_state.exit_confirmed;
+
+        // Switching adapters means a new device for everything on the GPU, so the app exits
+        // through the usual unsaved-changes prompt and starts again
+        let switch = ui_state.diagnostics.as_mut().and_then(Diagnostics::take_switch);
+        if let Some(info) = switch {
+            log::info!("Restarting the renderer on {} ({:?})", info.name, info.backend);
+            settings.backend = Backend::of(info.backend);
+            settings.adapter = Some(info.name);
+            *restart_renderer = true;
+            *close_requested |= ui_state.request_exit();
+        }
+        *restart_renderer &= *close_requested || ui_state.is_exiting();
+
+        if let Some(request) = ui_state.preset_request.take() {
+            handle_preset_request(request, ui_state, action_map, egui_renderer);
+        }
+
+        screenshot
+    }
+
+    // Submits and presents the frame, then hands off its screenshot and paces the next one
+    fn present(
+        &mut self,
+        frame: Frame,
+        screenshot: Option<RecordedScreenshot>,
+        elwt: &EventLoopWindowTarget<UserEvent>,
+    ) {
+        let Frame { surface_texture, mut encoder, draws, offsets, acquire_wait, .. } = frame;
+        let Self {
+            app_config, window, camera, benchmark, instance, adapter, device, queue, config, xr, gpu_errors,
+            gpu_timings, render_stats, objects, uploads, materials, scene_windows, ui_state, submissions, buffer_pool,
+            world, transforms, egui_renderer, close_requested, device_lost, input, screenshot_job, last_frame, focused,
+            ..
+        } = self;
+        // Staged writes are submitted ahead of the frame's commands, so UI edits apply to this frame
+        // Only objects the UI moved need their uniforms rewritten
+        let moved = world::propagate_transforms(world, transforms);
+        for (draw, &offset) in draws.iter().zip(&offsets) {
+            if !moved.contains(&draw.entity) {
+                continue;
+            }
+            if let Ok(global) = world.get::<&GlobalTransform>(draw.entity) {
+                objects.set(offset, ModelUniform::from_matrix(global.0));
+            }
+        }
+        objects.upload(device, uploads);
+        materials.upload(device, uploads);
+
+        {
+            puffin::profile_scope!("submit");
+            gpu_timings.profiler.resolve_queries(&mut encoder);
+            device.push_error_scope(wgpu::ErrorFilter::Validation);
+            submissions.push(Stage::Ui, encoder.finish());
+            submissions.submit(queue, uploads);
+            xr.end_frame(&mut ui_state.toasts);
+            gpu_errors.pop_scope(device, "Submit", &mut ui_state.toasts);
+        }
+        gpu_timings.end_frame(queue);
+        buffer_pool.end_frame(render_stats);
+        gpu_errors.report_uncaptured(&mut ui_state.toasts);
+        if let Some(reason) = gpu_errors.take_device_lost() {
+            // Every resource belongs to the lost device; the UI can't be drawn to say so
+            log::error!("GPU device lost ({reason}); restart the app to render again");
+            let title = &app_config.title;
+            window.set_title(&format!("{title} (GPU device lost, restart to render again)"));
+            *device_lost = true;
+        }
+        ui_state.video.end_frame(device, &mut ui_state.toasts);
+        // Only the readback waits for the GPU; converting the pixels happens on the job pool
+        if let Some((screenshot, path)) = screenshot {
+            match screenshot.read(device) {
+                Ok(pixels) => {
+                    *screenshot_job = Some(jobs::run(Priority::Low, move |_| {
+                        let (width, height) = (pixels.width(), pixels.height());
+                        let pixels = pixels.into_rgba();
+                        let saved = path.map(|path| {
+                            screenshot::save_png(&path, width, height, pixels.clone()).map(|()| path)
+                        });
+                        ConvertedScreenshot {
+                            width,
+                            height,
+                            pixels,
+                            saved,
+                        }
+                    }));
+                }
+                Err(err) => ui_state.toasts.error(format!("Screenshot failed: {err}")),
+            }
+        }
+        {
+            puffin::profile_scope!("present");
+            surface_texture.present();
+        }
+        ui_state.pacer.end_frame(acquire_wait);
+        let frame_time = gpu_timings.frame_time().unwrap_or_default().max(ui_state.pacer.work());
+        ui_state.resolution.update(frame_time);
+        if let Some(benchmark) = benchmark {
+            let cpu_time = last_frame.elapsed().saturating_sub(acquire_wait);
+            benchmark.record(cpu_time, gpu_timings.frame_time());
+        }
+        if let Some(benchmark) = benchmark.take_if(|benchmark| benchmark.is_finished()) {
+            let info = adapter.get_info();
+            let backend = format!("{:?}", info.backend);
+            let report = benchmark.report(&info.name, &backend, (config.width, config.height));
+            match benchmark.write(&report) {
+                Ok(path) => log::info!("Wrote benchmark results to {}", path.display()),
+                Err(err) => log::error!("Failed to write benchmark results: {err}"),
+            }
+            *close_requested = true;
+        }
+        if let Some(path) = ui_state.frame_capture.poll() {
+            log::info!("Saved RenderDoc capture {}", path.display());
+            ui_state.toasts.success(format!("Saved RenderDoc capture {}", path.display()));
+        }
+        egui_renderer.update_viewports(elwt, instance, adapter, device, queue);
+        scene_windows.present(device, queue, camera);
+        input.end_frame();
+        if *focused || xr.is_running() || ui_state.background_mode == BackgroundMode::FullSpeed {
+            window.request_redraw();
+        } else {
+            ui_state.pacer.pause();
+            ui_state.frame_timer.pause();
+        }
+    }
 }
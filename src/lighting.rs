@@ -0,0 +1,292 @@
+// lighting.rs
+
+use crate::environment::Fog;
+use crate::stats::RenderStats;
+use crate::transform::Transform;
+use crate::upload::Uploads;
+use crate::world::{GlobalTransform, Light, LightKind};
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::{EulerRot, Quat, Vec3};
+use hecs::{Entity, World};
+use std::sync::Arc;
+
+// Lights the shaders' uniform has room for; enabled lights past this are left out
+pub const MAX_LIGHTS: usize = 16;
+
+// Light every surface gets, so sides facing away from every light stay readable
+const AMBIENT: f32 = 0.25;
+
+// Range and cone new point and spot lights start with
+const DEFAULT_RANGE: f32 = 5.0;
+const DEFAULT_INNER_ANGLE: f32 = 0.3;
+const DEFAULT_OUTER_ANGLE: f32 = 0.5;
+
+// One light as laid out in the shaders' `Lights` uniform
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct GpuLight {
+    position: [f32; 4],  // w: 0 directional, 1 point, 2 spot
+    direction: [f32; 4], // Where the light shines; w: range, unused by directional lights
+    color: [f32; 4],     // Times intensity; w: 1 if the light casts shadows
+    cone: [f32; 4],      // Cosines of the inner and outer angles, for spot lights
+}
+
+// The enabled lights of the world and the fog as the shaders read them, bound next to the camera in group 0
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct LightsUniform {
+    ambient: [f32; 3],
+    count: u32,
+    fog_color: [f32; 3],
+    fog_mode: u32, // 0 off, 1 linear, 2 exponential
+    fog: [f32; 4], // Start, end, density and height falloff
+    lights: [GpuLight; MAX_LIGHTS],
+}
+
+impl GpuLight {
+    fn new(light: &Light, global: &GlobalTransform) -> Self {
+        let position = global.0.w_axis.truncate();
+        let direction = (-global.0.z_axis.truncate()).normalize_or(Vec3::NEG_Z);
+        let (kind, range, cone) = match light.kind {
+            LightKind::Directional => (0.0, 0.0, [1.0, 1.0]),
+            LightKind::Point { range } => (1.0, range, [1.0, 1.0]),
+            LightKind::Spot {
+                range,
+                inner_angle,
+                outer_angle,
+            } => (2.0, range, [inner_angle.cos(), outer_angle.cos()]),
+        };
+        let color = light.color * light.intensity;
+        Self {
+            position: position.extend(kind).to_array(),
+            direction: direction.extend(range).to_array(),
+            color: color.extend(if light.casts_shadows { 1.0 } else { 0.0 }).to_array(),
+            cone: [cone[0], cone[1], 0.0, 0.0],
+        }
+    }
+}
+
+impl LightsUniform {
+    // Every enabled light of the world in entity order, so the sun the template starts with comes first, `fog`,
+    // and `ambient` light in place of the default. Also returns how many enabled lights didn't fit
+    fn gather(world: &World, fog: &Fog, ambient: Option<Vec3>) -> (Self, usize) {
+        let mut query = world.query::<(Entity, &Light, &GlobalTransform)>();
+        let mut enabled: Vec<(Entity, &Light, &GlobalTransform)> =
+            query.iter().filter(|(_, light, _)| light.enabled).collect();
+        enabled.sort_by_key(|(entity, _, _)| *entity);
+        let (fog_mode, fog_color, fog) = fog.uniform();
+        let mut uniform = Self {
+            ambient: ambient.unwrap_or(Vec3::splat(AMBIENT)).to_array(),
+            count: enabled.len().min(MAX_LIGHTS) as u32,
+            fog_color,
+            fog_mode,
+            fog,
+            lights: [GpuLight::zeroed(); MAX_LIGHTS],
+        };
+        for (slot, (_, light, global)) in uniform.lights.iter_mut().zip(&enabled) {
+            *slot = GpuLight::new(light, global);
+        }
+        (uniform, enabled.len().saturating_sub(MAX_LIGHTS))
+    }
+}
+
+// The lights uniform buffer, rewritten whenever a light or the fog changes
+pub struct Lighting {
+    buffer: Arc<wgpu::Buffer>,
+    uploaded: LightsUniform,
+    skipped: usize, // Enabled lights past `MAX_LIGHTS` at the last update
+}
+
+impl Lighting {
+    pub fn new(device: &wgpu::Device, stats: &mut RenderStats) -> Self {
+        let uploaded = LightsUniform::zeroed();
+        let buffer = Arc::new(stats.create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Lights Buffer"),
+                contents: bytemuck::bytes_of(&uploaded),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+        ));
+        Self {
+            buffer,
+            uploaded,
+            skipped: 0,
+        }
+    }
+
+    pub fn buffer(&self) -> &Arc<wgpu::Buffer> {
+        &self.buffer
+    }
+
+    // Gathers the world's lights, `fog` and any `ambient` light replacing the default, as an environment image
+    // gives, staging them for the GPU if they changed since the last call
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        uploads: &mut Uploads,
+        world: &World,
+        fog: &Fog,
+        ambient: Option<Vec3>,
+    ) {
+        let (uniform, skipped) = LightsUniform::gather(world, fog, ambient);
+        self.skipped = skipped;
+        if uniform != self.uploaded {
+            uploads.write(device, &self.buffer, 0, bytemuck::bytes_of(&uniform));
+            self.uploaded = uniform;
+        }
+    }
+
+    /// The Lights window: edits every light in the world and adds new ones at `placement`.
+    pub fn show(&mut self, ctx: &Context, world: &mut World, sun: Entity, placement: Vec3) {
+        egui::Window::new("Lights")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Add");
+                    let kinds = [
+                        LightKind::Directional,
+                        LightKind::Point { range: DEFAULT_RANGE },
+                        LightKind::Spot {
+                            range: DEFAULT_RANGE,
+                            inner_angle: DEFAULT_INNER_ANGLE,
+                            outer_angle: DEFAULT_OUTER_ANGLE,
+                        },
+                    ];
+                    for kind in kinds {
+                        if ui.button(kind.label()).clicked() {
+                            let transform = Transform {
+                                translation: placement,
+                                ..Transform::IDENTITY
+                            };
+                            let light = Light {
+                                kind,
+                                ..Light::default()
+                            };
+                            world.spawn((transform, GlobalTransform(transform.matrix()), light));
+                        }
+                    }
+                });
+                if self.skipped > 0 {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!("{} enabled lights past the first {MAX_LIGHTS} are not drawn", self.skipped),
+                    );
+                }
+
+                let mut lights: Vec<Entity> = world.query::<(Entity, &Light)>().iter().map(|(e, _)| e).collect();
+                lights.sort();
+                let mut removed = None;
+                for (index, entity) in lights.into_iter().enumerate() {
+                    let (Ok(mut light), Ok(mut transform)) =
+                        (world.get::<&mut Light>(entity), world.get::<&mut Transform>(entity))
+                    else {
+                        continue;
+                    };
+                    let name = if entity == sun {
+                        "Sun".to_string()
+                    } else {
+                        format!("{} light #{}", light.kind.label(), index + 1)
+                    };
+                    egui::CollapsingHeader::new(name).id_source(entity).show(ui, |ui| {
+                        ui.push_id(entity, |ui| light_ui(ui, &mut light, &mut transform));
+                        if entity != sun && ui.button("Remove").clicked() {
+                            removed = Some(entity);
+                        }
+                    });
+                }
+                if let Some(entity) = removed {
+                    let _ = world.despawn(entity);
+                }
+            });
+    }
+}
+
+fn light_ui(ui: &mut egui::Ui, light: &mut Light, transform: &mut Transform) {
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut light.enabled, "Enabled");
+        ui.checkbox(&mut light.casts_shadows, "Casts shadows");
+    });
+    egui::Grid::new("light").num_columns(2).show(ui, |ui| {
+        ui.label("Kind");
+        let mut kind = light.kind;
+        egui::ComboBox::from_id_source("light_kind").selected_text(kind.label()).show_ui(ui, |ui| {
+            let range = match light.kind {
+                LightKind::Point { range } | LightKind::Spot { range, .. } => range,
+                LightKind::Directional => DEFAULT_RANGE,
+            };
+            ui.selectable_value(&mut kind, LightKind::Directional, "Directional");
+            ui.selectable_value(&mut kind, LightKind::Point { range }, "Point");
+            let spot = LightKind::Spot {
+                range,
+                inner_angle: DEFAULT_INNER_ANGLE,
+                outer_angle: DEFAULT_OUTER_ANGLE,
+            };
+            ui.selectable_value(&mut kind, spot, "Spot");
+        });
+        if kind.label() != light.kind.label() {
+            light.kind = kind;
+        }
+        ui.end_row();
+
+        ui.label("Color");
+        let mut color = light.color.to_array();
+        if ui.color_edit_button_rgb(&mut color).changed() {
+            light.color = Vec3::from(color);
+        }
+        ui.end_row();
+        ui.label("Intensity");
+        ui.add(egui::DragValue::new(&mut light.intensity).speed(0.01).range(0.0..=100.0));
+        ui.end_row();
+
+        match &mut light.kind {
+            LightKind::Directional => {}
+            LightKind::Point { range } => {
+                ui.label("Range");
+                ui.add(egui::DragValue::new(range).speed(0.05).range(0.01..=1000.0));
+                ui.end_row();
+            }
+            LightKind::Spot {
+                range,
+                inner_angle,
+                outer_angle,
+            } => {
+                ui.label("Range");
+                ui.add(egui::DragValue::new(range).speed(0.05).range(0.01..=1000.0));
+                ui.end_row();
+                ui.label("Cone");
+                ui.horizontal(|ui| {
+                    ui.drag_angle(inner_angle);
+                    ui.drag_angle(outer_angle);
+                });
+                *outer_angle = outer_angle.clamp(0.01, std::f32::consts::FRAC_PI_2);
+                *inner_angle = inner_angle.clamp(0.0, *outer_angle);
+                ui.end_row();
+            }
+        }
+
+        if !matches!(light.kind, LightKind::Directional) {
+            ui.label("Position");
+            ui.horizontal(|ui| {
+                for axis in 0..3 {
+                    ui.add(egui::DragValue::new(&mut transform.translation[axis]).speed(0.01));
+                }
+            });
+            ui.end_row();
+        }
+        if !matches!(light.kind, LightKind::Point { .. }) {
+            // Yaw turns the light about Y, then pitch tilts it up or down
+            ui.label("Aim");
+            let (mut yaw, mut pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+            let changed = ui.horizontal(|ui| ui.drag_angle(&mut yaw).changed() | ui.drag_angle(&mut pitch).changed());
+            if changed.inner {
+                transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+            }
+            ui.end_row();
+        }
+    });
+}
@@ -1,13 +1,20 @@
-use winit_egui_wgpu::run;
-mod camera;
-mod vertex;
-//mod ui;
-mod egui_tools;
-
+use winit_egui_wgpu::{console, headless, run, run_headless};
 
 fn main() {
     #[cfg(not(target_arch = "wasm32"))]
     {
+        console::init_logger();
+        // `--headless [WIDTHxHEIGHT]` writes one frame to a PNG without opening a window, as for CI
+        if let Some((width, height)) = headless::size_from_args(&std::env::args().collect::<Vec<_>>()) {
+            match run_headless(width, height) {
+                Ok(path) => log::info!("Wrote {}", path.display()),
+                Err(err) => {
+                    log::error!("Headless rendering failed: {err}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
         pollster::block_on(run());
     }
-}
\ No newline at end of file
+}
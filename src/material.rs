@@ -0,0 +1,523 @@
+// material.rs
+
+use crate::assets::{AssetEvent, Assets, Handle};
+use crate::file_dialog::{FileDialogs, FileKind};
+use crate::jobs::FrameBudget;
+use crate::scene::SceneMaterial;
+use crate::shader_params::{ShaderParams, UniformBlock, PARAMS_GROUP};
+use crate::stats::RenderStats;
+use crate::toasts::Toasts;
+use crate::texture::{self, GpuTexture, TextureImage};
+use crate::upload::Uploads;
+use crate::world::MaterialHandle;
+use egui::Context;
+use egui_wgpu::wgpu;
+use hecs::{Entity, World};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Identifies a material in `Materials`; entities draw with one through `MaterialHandle`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialId(u32);
+
+impl MaterialId {
+    // The default material of the first shader given to `Materials::new`
+    pub const DEFAULT: MaterialId = MaterialId(0);
+
+    // Its place in `Materials::save`
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+// What draws are sorted and scene bundles keyed by: the pipeline first, so it switches as rarely as possible,
+// then the material, then how many times its bind group has been rebuilt
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialKey {
+    pub shader: &'static str,
+    pub material: MaterialId,
+    revision: u32,
+}
+
+// One texture binding of a material and the file it shows, if any; unset slots show plain white
+#[derive(Debug, Clone)]
+pub struct TextureSlot {
+    pub name: String,
+    binding: u32,
+    pub path: Option<PathBuf>,
+}
+
+// A shader together with its own parameter values and textures, and the bind group that holds them
+pub struct Material {
+    pub name: String,
+    pub shader: &'static str,
+    pub blocks: Vec<UniformBlock>,
+    buffers: Vec<Arc<wgpu::Buffer>>,
+    pub textures: Vec<TextureSlot>,
+    bind_group: wgpu::BindGroup,
+    revision: u32,
+    dirty: bool, // Parameters edited since the last upload
+    stale: bool, // A texture changed since the bind group was made
+}
+
+// Every material, the parameter layouts of the shaders they use, and the textures they sample
+pub struct Materials {
+    shaders: Vec<ShaderParams>,
+    materials: Vec<Material>,
+    defaults: HashMap<&'static str, MaterialId>, // Each shader's first material
+    white: GpuTexture,
+    sampler: wgpu::Sampler,
+    images: Assets<TextureImage>,
+    textures: HashMap<PathBuf, (Handle<TextureImage>, Option<GpuTexture>)>, // None until the file has loaded
+    selected: MaterialId,                  // Shown in the Materials window
+    choosing: Option<(MaterialId, usize)>, // Texture slot waiting on the file dialog
+}
+
+impl Materials {
+    /// Takes over the parameter layouts of `shaders` and makes a default material for each from the values
+    /// their uniforms were given.
+    pub fn new(
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        shaders: Vec<ShaderParams>,
+    ) -> Self {
+        let white = GpuTexture::new(device, stats, uploads, &TextureImage::new(1, 1, vec![255; 4]));
+        let sampler = texture::create_sampler(device, "Material Sampler");
+        let mut materials = Self {
+            shaders,
+            materials: Vec::new(),
+            defaults: HashMap::new(),
+            white,
+            sampler,
+            images: Assets::new(TextureImage::load),
+            textures: HashMap::new(),
+            selected: MaterialId::DEFAULT,
+            choosing: None,
+        };
+        let labels: Vec<&'static str> = materials.shaders.iter().map(|shader| shader.label).collect();
+        for label in labels {
+            let id = materials.create(device, stats, label, label.to_string());
+            materials.defaults.insert(label, id);
+        }
+        materials
+    }
+
+    /// The layout the parameter group of pipelines using `shader` must have.
+    pub fn layout(&self, shader: &str) -> &wgpu::BindGroupLayout {
+        &self.shader(shader).bind_group_layout
+    }
+
+    fn shader(&self, shader: &str) -> &ShaderParams {
+        self.shaders
+            .iter()
+            .find(|params| params.label == shader)
+            .unwrap_or_else(|| panic!("No parameter layout for shader {shader:?}"))
+    }
+
+    /// The material objects switched to `shader` get.
+    pub fn default_for(&self, shader: &str) -> MaterialId {
+        self.defaults.get(shader).copied().unwrap_or(MaterialId::DEFAULT)
+    }
+
+    /// The texture images being read, for the progress of loads in flight.
+    pub fn images(&self) -> &Assets<TextureImage> {
+        &self.images
+    }
+
+    pub fn get(&self, id: MaterialId) -> &Material {
+        &self.materials[id.0 as usize]
+    }
+
+    /// Every material with its id, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (MaterialId, &Material)> {
+        self.materials.iter().enumerate().map(|(index, material)| (MaterialId(index as u32), material))
+    }
+
+    pub fn key(&self, id: MaterialId) -> MaterialKey {
+        let material = self.get(id);
+        MaterialKey {
+            shader: material.shader,
+            material: id,
+            revision: material.revision,
+        }
+    }
+
+    pub fn bind_group(&self, id: MaterialId) -> &wgpu::BindGroup {
+        &self.get(id).bind_group
+    }
+
+    /// Adds a material for `shader`, starting from the shader's parameter values and with no textures.
+    pub fn create(&mut self, device: &wgpu::Device, stats: &mut RenderStats, shader: &str, name: String) -> MaterialId {
+        let params = self.shader(shader);
+        let blocks = params.blocks.clone();
+        let textures = params
+            .textures
+            .iter()
+            .map(|texture| TextureSlot {
+                name: texture.name.clone(),
+                binding: texture.binding,
+                path: None,
+            })
+            .collect();
+        self.insert(device, stats, params.label, name, blocks, textures)
+    }
+
+    /// Adds a copy of `id` that can then be edited on its own.
+    pub fn duplicate(&mut self, device: &wgpu::Device, stats: &mut RenderStats, id: MaterialId) -> MaterialId {
+        let source = self.get(id);
+        let name = format!("{} copy", source.name);
+        let (shader, blocks, textures) = (source.shader, source.blocks.clone(), source.textures.clone());
+        self.insert(device, stats, shader, name, blocks, textures)
+    }
+
+    fn insert(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        shader: &'static str,
+        name: String,
+        blocks: Vec<UniformBlock>,
+        textures: Vec<TextureSlot>,
+    ) -> MaterialId {
+        let buffers: Vec<Arc<wgpu::Buffer>> = blocks
+            .iter()
+            .map(|block| {
+                Arc::new(stats.create_buffer_init(
+                    device,
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some(&block.name),
+                        contents: block.bytes(),
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    },
+                ))
+            })
+            .collect();
+        for path in textures.iter().filter_map(|slot| slot.path.clone()) {
+            self.watch(path);
+        }
+        let bind_group = self.create_bind_group(device, shader, &blocks, &buffers, &textures);
+        self.materials.push(Material {
+            name,
+            shader,
+            blocks,
+            buffers,
+            textures,
+            bind_group,
+            revision: 0,
+            dirty: false,
+            stale: false,
+        });
+        MaterialId(self.materials.len() as u32 - 1)
+    }
+
+    // Binds the material's buffers, its textures where loaded and white elsewhere, and the shared sampler
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        shader: &str,
+        blocks: &[UniformBlock],
+        buffers: &[Arc<wgpu::Buffer>],
+        textures: &[TextureSlot],
+    ) -> wgpu::BindGroup {
+        let params = self.shader(shader);
+        let mut entries: Vec<wgpu::BindGroupEntry> = blocks
+            .iter()
+            .zip(buffers)
+            .map(|(block, buffer)| wgpu::BindGroupEntry {
+                binding: block.binding,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        entries.extend(textures.iter().map(|slot| {
+            let loaded = slot.path.as_ref().and_then(|path| self.textures.get(path)?.1.as_ref());
+            wgpu::BindGroupEntry {
+                binding: slot.binding,
+                resource: wgpu::BindingResource::TextureView(&loaded.unwrap_or(&self.white).view),
+            }
+        }));
+        entries.extend(params.samplers.iter().map(|sampler| wgpu::BindGroupEntry {
+            binding: sampler.binding,
+            resource: wgpu::BindingResource::Sampler(&self.sampler),
+        }));
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Material Bind Group"),
+            layout: &params.bind_group_layout,
+            entries: &entries,
+        })
+    }
+
+    // Starts loading the image at `path` if no material has used it yet; it is reloaded when edited on disk
+    fn watch(&mut self, path: PathBuf) {
+        if !self.textures.contains_key(&path) {
+            let handle = self.images.load(&path, true);
+            self.textures.insert(path, (handle, None));
+        }
+    }
+
+    /// Reads a float parameter of material `id` by name, or None if its shader has no such parameter.
+    pub fn param(&self, id: MaterialId, name: &str) -> Option<Vec<f32>> {
+        self.get(id).blocks.iter().find_map(|block| block.get(name))
+    }
+
+    /// Sets a float parameter of material `id` by name. Returns false if its shader has no such parameter.
+    pub fn set_param(&mut self, id: MaterialId, name: &str, values: &[f32]) -> bool {
+        let material = &mut self.materials[id.0 as usize];
+        let mut found = false;
+        for block in &mut material.blocks {
+            found |= block.set(name, values);
+        }
+        material.dirty |= found;
+        found
+    }
+
+    /// Shows the image at `path` in texture `slot` of material `id`.
+    pub fn set_texture(&mut self, id: MaterialId, slot: usize, path: Option<PathBuf>) {
+        if let Some(path) = &path {
+            self.watch(path.clone());
+        }
+        let material = &mut self.materials[id.0 as usize];
+        if let Some(texture) = material.textures.get_mut(slot) {
+            texture.path = path;
+            material.stale = true;
+        }
+    }
+
+    /// Puts an image picked in the texture dialog where it was asked for: the slot whose Choose button
+    /// opened the dialog, or else the first slot of `object`'s material. Returns false if there is no slot
+    /// to put it in.
+    pub fn place_texture(&mut self, world: &World, object: Entity, path: PathBuf) -> bool {
+        let target = self
+            .choosing
+            .take()
+            .or_else(|| world.get::<&MaterialHandle>(object).ok().map(|handle| (handle.0, 0)));
+        match target {
+            Some((id, slot)) if slot < self.get(id).textures.len() => {
+                self.set_texture(id, slot, Some(path));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Every material as saved with a scene, in id order, so a saved index names the same material.
+    pub fn save(&self) -> Vec<SceneMaterial> {
+        self.materials
+            .iter()
+            .map(|material| SceneMaterial {
+                name: material.name.clone(),
+                shader: material.shader.to_string(),
+                params: material.blocks.iter().flat_map(UniformBlock::values).collect(),
+                textures: material
+                    .textures
+                    .iter()
+                    .filter_map(|slot| Some((slot.name.clone(), slot.path.clone()?)))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Takes the saved materials' parameters and textures, reusing the material with the same name and shader
+    /// and creating one where there is none. Returns their ids by index, None where the shader isn't known.
+    pub fn restore(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        saved: &[SceneMaterial],
+    ) -> Vec<Option<MaterialId>> {
+        saved
+            .iter()
+            .map(|saved| {
+                let shader = self.shaders.iter().map(|params| params.label).find(|label| *label == saved.shader);
+                let Some(shader) = shader else {
+                    log::warn!("Material {:?} uses unknown shader {:?}; skipping it", saved.name, saved.shader);
+                    return None;
+                };
+                let existing = self
+                    .iter()
+                    .find(|(_, material)| material.name == saved.name && material.shader == shader)
+                    .map(|(id, _)| id);
+                let id = match existing {
+                    Some(id) => id,
+                    None => self.create(device, stats, shader, saved.name.clone()),
+                };
+                let material = &mut self.materials[id.0 as usize];
+                for (name, values) in &saved.params {
+                    for block in &mut material.blocks {
+                        material.dirty |= block.set_value(name, values);
+                    }
+                }
+                for slot in 0..material.textures.len() {
+                    let texture = &self.get(id).textures[slot];
+                    let saved_path = saved.textures.iter().find(|(name, _)| *name == texture.name);
+                    let path = saved_path.map(|(_, path)| path.clone());
+                    if texture.path != path {
+                        self.set_texture(id, slot, path);
+                    }
+                }
+                Some(id)
+            })
+            .collect()
+    }
+
+    // Stages edited parameters for the GPU; they land with the next submit
+    pub fn upload(&mut self, device: &wgpu::Device, uploads: &mut Uploads) {
+        for material in self.materials.iter_mut().filter(|material| material.dirty) {
+            for (block, buffer) in material.blocks.iter().zip(&material.buffers) {
+                uploads.write(device, buffer, 0, block.bytes());
+            }
+            material.dirty = false;
+        }
+    }
+
+    /// Turns finished image loads into textures and remakes the bind groups of materials whose textures
+    /// changed. Returns whether any bind group was remade.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        budget: &mut FrameBudget,
+        toasts: &mut Toasts,
+    ) -> bool {
+        for event in self.images.poll(budget) {
+            match event {
+                AssetEvent::Loaded(handle) | AssetEvent::Reloaded(handle) => {
+                    let (Some(image), Some(path)) = (self.images.get(handle), self.images.path(handle)) else {
+                        continue;
+                    };
+                    let texture = GpuTexture::new(device, stats, uploads, image);
+                    let path = path.to_path_buf();
+                    let previous = self.textures.get_mut(&path).and_then(|(_, gpu)| gpu.replace(texture));
+                    if let Some(previous) = previous {
+                        stats.texture_destroyed(&previous.texture);
+                    }
+                    for material in &mut self.materials {
+                        material.stale |= material.textures.iter().any(|slot| slot.path.as_ref() == Some(&path));
+                    }
+                }
+                AssetEvent::Failed(handle, err) => {
+                    let path = self.images.path(handle).map(Path::to_path_buf).unwrap_or_default();
+                    log::warn!("Failed to load texture from {}: {err}", path.display());
+                    toasts.error(format!("Failed to load texture: {err}"));
+                }
+            }
+        }
+
+        let mut changed = false;
+        for index in 0..self.materials.len() {
+            let material = &self.materials[index];
+            if !material.stale {
+                continue;
+            }
+            let (blocks, buffers, textures) = (&material.blocks, &material.buffers, &material.textures);
+            let bind_group = self.create_bind_group(device, material.shader, blocks, buffers, textures);
+            let material = &mut self.materials[index];
+            material.bind_group = bind_group;
+            material.revision += 1;
+            material.stale = false;
+            changed = true;
+        }
+        changed
+    }
+
+    /// The Materials window: picks the object's material and edits any material's parameters and textures.
+    /// Returns the material the object was switched to, if it was.
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        world: &World,
+        object: Entity,
+        file_dialogs: &mut FileDialogs,
+    ) -> Option<MaterialId> {
+        let mut assigned = None;
+        egui::Window::new("Materials")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                let current = world.get::<&MaterialHandle>(object).ok().map(|handle| handle.0);
+                if let Some(current) = current {
+                    let mut choice = current;
+                    egui::ComboBox::from_label("Object")
+                        .selected_text(&self.get(current).name)
+                        .show_ui(ui, |ui| {
+                            for (index, material) in self.materials.iter().enumerate() {
+                                ui.selectable_value(&mut choice, MaterialId(index as u32), &material.name);
+                            }
+                        });
+                    if choice != current {
+                        assigned = Some(choice);
+                        self.selected = choice;
+                    }
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("edited_material")
+                        .selected_text(&self.get(self.selected).name)
+                        .show_ui(ui, |ui| {
+                            for (index, material) in self.materials.iter().enumerate() {
+                                ui.selectable_value(&mut self.selected, MaterialId(index as u32), &material.name);
+                            }
+                        });
+                    if ui.button("Duplicate").clicked() {
+                        self.selected = self.duplicate(device, stats, self.selected);
+                    }
+                    ui.menu_button("New", |ui| {
+                        let labels: Vec<&'static str> = self.shaders.iter().map(|shader| shader.label).collect();
+                        for label in labels {
+                            if ui.button(label).clicked() {
+                                let name = format!("{label} {}", self.materials.len() + 1);
+                                self.selected = self.create(device, stats, label, name);
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                });
+
+                let id = self.selected;
+                let material = &mut self.materials[id.0 as usize];
+                ui.horizontal(|ui| {
+                    ui.label("Name");
+                    ui.text_edit_singleline(&mut material.name);
+                });
+                ui.weak(format!("{} shader, group {PARAMS_GROUP}", material.shader));
+                for block in &mut material.blocks {
+                    ui.separator();
+                    ui.strong(&block.name);
+                    material.dirty |= block.ui(ui);
+                }
+                if material.blocks.is_empty() {
+                    ui.weak("This shader has no parameter uniforms");
+                }
+
+                let mut cleared = None;
+                for (slot, texture) in material.textures.iter().enumerate() {
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.strong(&texture.name);
+                        let file = texture.path.as_ref().and_then(|path| path.file_name());
+                        match file {
+                            Some(file) => ui.label(file.to_string_lossy()),
+                            None => ui.weak("white"),
+                        };
+                        if ui.add_enabled(!file_dialogs.is_pending(), egui::Button::new("Choose...")).clicked() {
+                            self.choosing = Some((id, slot));
+                            file_dialogs.open(FileKind::Texture);
+                        }
+                        if texture.path.is_some() && ui.button("Clear").clicked() {
+                            cleared = Some(slot);
+                        }
+                    });
+                }
+                if let Some(slot) = cleared {
+                    self.set_texture(id, slot, None);
+                }
+            });
+        assigned
+    }
+}
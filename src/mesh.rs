@@ -0,0 +1,390 @@
+// mesh.rs
+
+use crate::bounds::Aabb;
+use crate::primitives;
+use crate::vertex::Vertex;
+use glam::{Mat3, Mat4, Vec3};
+use std::collections::hash_map::{Entry, HashMap};
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Deepest node nesting followed in a glTF file, so a cycle in a malformed one can't hang the load
+const MAX_DEPTH: usize = 64;
+
+// An OBJ face corner: the indices of its position, texture coordinates and normal
+type ObjCorner = (usize, Option<usize>, Option<usize>);
+
+// Triangle-list geometry on the CPU, ready to be copied into the shared geometry buffers with
+// `AppContext::spawn_mesh`
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u16>) -> Self {
+        Self { vertices, indices }
+    }
+
+    /// A unit cube centered on the origin.
+    pub fn cube() -> Self {
+        let (vertices, indices) = Vertex::generate_cube();
+        Self { vertices, indices }
+    }
+
+    /// A flat regular polygon facing +Z.
+    pub fn polygon(sides: u16, radius: f32) -> Self {
+        let (vertices, indices) = Vertex::generate_polygon(sides, radius);
+        Self { vertices, indices }
+    }
+
+    /// A UV sphere centered on the origin, lit smoothly. Panics past the vertices u16 indices reach, as for 255
+    /// segments by 256 rings.
+    pub fn sphere(segments: u16, rings: u16, radius: f32) -> Self {
+        let (vertices, indices) = primitives::uv_sphere(segments, rings, radius);
+        Self { vertices, indices }
+    }
+
+    /// The box around the vertices, or None when there are none.
+    pub fn bounds(&self) -> Option<Aabb> {
+        Aabb::from_points(self.vertices.iter().map(|vertex| Vec3::from(vertex.position)))
+    }
+}
+
+// A material a mesh file gives one of its submeshes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedMaterial {
+    pub name: String,
+    pub color: [f32; 3],          // Linear base color, white when the file gives none
+    pub texture: Option<PathBuf>, // The base color image, when it is a file of its own
+}
+
+// Part of a mesh file drawn with one material
+#[derive(Debug, Clone)]
+pub struct Submesh {
+    pub mesh: Mesh,
+    pub material: Option<ImportedMaterial>,
+}
+
+// The triangles of an .obj, .gltf or .glb file, one submesh per material. Node transforms are baked into the
+// vertices, so the submeshes share one origin
+#[derive(Debug, Clone)]
+pub struct MeshFile {
+    pub submeshes: Vec<Submesh>,
+    pub skinned: bool, // A glTF file with a skin, better shown as a rigged model
+}
+
+impl MeshFile {
+    /// Reads an .obj file, with its .mtl materials from next to it, or a .gltf or .glb file, by extension.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+        let file = match extension.as_deref() {
+            Some("obj") => Self::load_obj(path)?,
+            Some("gltf" | "glb") => Self::load_gltf(path)?,
+            _ => return Err(invalid("Only .obj, .gltf and .glb meshes can be loaded")),
+        };
+        if file.submeshes.is_empty() {
+            return Err(invalid("The file has no triangles"));
+        }
+        Ok(file)
+    }
+
+    /// The box around every submesh, or None when there are no vertices.
+    pub fn bounds(&self) -> Option<Aabb> {
+        let corners = self.submeshes.iter().filter_map(|submesh| submesh.mesh.bounds()).flat_map(|b| b.corners());
+        Aabb::from_points(corners)
+    }
+
+    fn load_obj(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut positions: Vec<([f32; 3], [f32; 3])> = Vec::new();
+        let mut uvs: Vec<[f32; 2]> = Vec::new();
+        let mut normals: Vec<[f32; 3]> = Vec::new();
+        let mut library = HashMap::new();
+        let mut parts: Vec<(Option<String>, SubmeshBuilder<ObjCorner>)> = Vec::new(); // By material name
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let Some(keyword) = words.next() else {
+                continue;
+            };
+            let at_line = |message: &str| invalid(format!("Line {}: {message}", number + 1));
+            let floats = |words: std::str::SplitWhitespace| {
+                words.map(str::parse::<f32>).collect::<Result<Vec<_>, _>>().map_err(|_| at_line("Not a number"))
+            };
+            match keyword {
+                "v" => {
+                    let values = floats(words)?;
+                    let [x, y, z] = values[..values.len().min(3)] else {
+                        return Err(at_line("A position needs three coordinates"));
+                    };
+                    // Some exporters append a vertex color
+                    let color = match values[3..] {
+                        [r, g, b, ..] => [r, g, b],
+                        _ => [1.0; 3],
+                    };
+                    positions.push(([x, y, z], color));
+                }
+                "vt" => {
+                    let values = floats(words)?;
+                    let [u, v] = values[..values.len().min(2)] else {
+                        return Err(at_line("Texture coordinates need two values"));
+                    };
+                    // OBJ's V grows upwards
+                    uvs.push([u, 1.0 - v]);
+                }
+                "vn" => {
+                    let values = floats(words)?;
+                    let [x, y, z] = values[..values.len().min(3)] else {
+                        return Err(at_line("A normal needs three coordinates"));
+                    };
+                    normals.push([x, y, z]);
+                }
+                "mtllib" => {
+                    let name = line.trim_start()[keyword.len()..].trim();
+                    let mtl_path = path.with_file_name(name);
+                    match load_mtl(&mtl_path) {
+                        Ok(materials) => library.extend(materials),
+                        Err(err) => log::warn!("Failed to load materials from {}: {err}", mtl_path.display()),
+                    }
+                }
+                "usemtl" => {
+                    let name = line.trim_start()[keyword.len()..].trim().to_string();
+                    parts.push((Some(name), SubmeshBuilder::new()));
+                }
+                "f" => {
+                    let corners = words
+                        .map(|word| {
+                            let mut fields = word.split('/');
+                            let mut index = |count: usize| match fields.next().filter(|field| !field.is_empty()) {
+                                None => Ok(None),
+                                Some(field) => obj_index(field, count).map(Some).ok_or_else(|| at_line("Bad index")),
+                            };
+                            let position = index(positions.len())?.ok_or_else(|| at_line("A corner has no position"))?;
+                            Ok((position, index(uvs.len())?, index(normals.len())?))
+                        })
+                        .collect::<io::Result<Vec<_>>>()?;
+                    if corners.len() < 3 {
+                        return Err(at_line("A face needs at least three corners"));
+                    }
+                    if parts.is_empty() {
+                        parts.push((None, SubmeshBuilder::new()));
+                    }
+                    let (_, builder) = parts.last_mut().unwrap();
+                    // Faces are convex polygons, split into a fan
+                    for i in 1..corners.len() - 1 {
+                        for key in [corners[0], corners[i], corners[i + 1]] {
+                            builder.push(key, || {
+                                let (position, color) = positions[key.0];
+                                Vertex {
+                                    position,
+                                    color,
+                                    uv: key.1.map_or([0.0; 2], |i| uvs[i]),
+                                    normal: key.2.map_or([0.0; 3], |i| normals[i]),
+                                }
+                            })?;
+                        }
+                    }
+                }
+                _ => {} // Objects, groups, smoothing groups and the rest don't change what is drawn
+            }
+        }
+        let submeshes = parts
+            .into_iter()
+            .filter(|(_, builder)| !builder.indices.is_empty())
+            .map(|(name, builder)| Submesh {
+                material: name.map(|name| {
+                    library.get(&name).cloned().unwrap_or(ImportedMaterial {
+                        name,
+                        color: [1.0; 3],
+                        texture: None,
+                    })
+                }),
+                mesh: builder.finish(),
+            })
+            .collect();
+        Ok(Self {
+            submeshes,
+            skinned: false,
+        })
+    }
+
+    fn load_gltf(path: &Path) -> io::Result<Self> {
+        let gltf::Gltf { document, blob } = gltf::Gltf::open(path).map_err(invalid_gltf)?;
+        let buffers = gltf::import_buffers(&document, path.parent(), blob).map_err(invalid_gltf)?;
+        let materials: Vec<ImportedMaterial> = document
+            .materials()
+            .map(|material| {
+                let pbr = material.pbr_metallic_roughness();
+                let [r, g, b, _] = pbr.base_color_factor();
+                // Images packed into the file's buffers have no path to load them from
+                let texture = pbr.base_color_texture().and_then(|info| match info.texture().source().source() {
+                    gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => Some(path.with_file_name(uri)),
+                    _ => None,
+                });
+                let index = material.index().unwrap_or_default();
+                ImportedMaterial {
+                    name: material.name().map_or_else(|| format!("Material {index}"), str::to_string),
+                    color: [r, g, b],
+                    texture,
+                }
+            })
+            .collect();
+
+        // Every mesh node of the default scene, or of all roots when there is none, with its world matrix
+        let roots: Vec<gltf::Node> = match document.default_scene().or_else(|| document.scenes().next()) {
+            Some(scene) => scene.nodes().collect(),
+            None => document.nodes().collect(),
+        };
+        let mut pending: Vec<(gltf::Node, Mat4, usize)> =
+            roots.into_iter().map(|node| (node, Mat4::IDENTITY, 0)).collect();
+        let mut submeshes = Vec::new();
+        while let Some((node, parent, depth)) = pending.pop() {
+            let matrix = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+            if depth < MAX_DEPTH {
+                pending.extend(node.children().map(|child| (child, matrix, depth + 1)));
+            }
+            let Some(mesh) = node.mesh() else {
+                continue;
+            };
+            let normal_matrix = Mat3::from_mat4(matrix).inverse().transpose();
+            for primitive in mesh.primitives().filter(|p| p.mode() == gltf::mesh::Mode::Triangles) {
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let positions: Vec<[f32; 3]> = reader
+                    .read_positions()
+                    .ok_or_else(|| invalid("A primitive has no positions"))?
+                    .collect();
+                let normals: Vec<[f32; 3]> = reader.read_normals().map(Iterator::collect).unwrap_or_default();
+                let uvs: Vec<[f32; 2]> = reader.read_tex_coords(0).map_or_else(Vec::new, |uv| uv.into_f32().collect());
+                let colors: Vec<[f32; 3]> = reader.read_colors(0).map_or_else(Vec::new, |c| c.into_rgb_f32().collect());
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(read) => read.into_u32().collect(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                if indices.iter().any(|&index| index as usize >= positions.len()) {
+                    return Err(invalid("A primitive has an index past its vertices"));
+                }
+                let mut builder = SubmeshBuilder::new();
+                for index in indices {
+                    let i = index as usize;
+                    builder.push(index, || Vertex {
+                        position: matrix.transform_point3(Vec3::from(positions[i])).to_array(),
+                        color: colors.get(i).copied().unwrap_or([1.0; 3]),
+                        uv: uvs.get(i).copied().unwrap_or_default(),
+                        normal: normals.get(i).map_or([0.0; 3], |&normal| {
+                            (normal_matrix * Vec3::from(normal)).normalize_or_zero().to_array()
+                        }),
+                    })?;
+                }
+                submeshes.push(Submesh {
+                    mesh: builder.finish(),
+                    material: primitive.material().index().map(|index| materials[index].clone()),
+                });
+            }
+        }
+        Ok(Self {
+            submeshes,
+            skinned: document.skins().next().is_some(),
+        })
+    }
+}
+
+// Gathers a submesh's triangles, sharing a vertex between every corner with the same `K`
+struct SubmeshBuilder<K> {
+    vertices: Vec<Vertex>,
+    indices: Vec<u16>,
+    shared: HashMap<K, u16>,
+}
+
+impl<K: Hash + Eq> SubmeshBuilder<K> {
+    fn new() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            shared: HashMap::new(),
+        }
+    }
+
+    // Adds a corner, making its vertex with `vertex` if no earlier corner had the same key
+    fn push(&mut self, key: K, vertex: impl FnOnce() -> Vertex) -> io::Result<()> {
+        let index = match self.shared.entry(key) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                let index = u16::try_from(self.vertices.len())
+                    .map_err(|_| invalid(format!("A submesh has more than {} vertices", u16::MAX as u32 + 1)))?;
+                self.vertices.push(vertex());
+                *entry.insert(index)
+            }
+        };
+        self.indices.push(index);
+        Ok(())
+    }
+
+    fn finish(self) -> Mesh {
+        Mesh::new(self.vertices, self.indices)
+    }
+}
+
+// A 1-based OBJ index, or a negative one counting back from the last of `count` values, made 0-based
+fn obj_index(field: &str, count: usize) -> Option<usize> {
+    let index = field.parse::<isize>().ok()?;
+    let index = if index < 0 { count as isize + index } else { index - 1 };
+    (0..count as isize).contains(&index).then_some(index as usize)
+}
+
+// The materials of an .mtl file by name: the diffuse color and its image. The rest of the Phong model is ignored
+fn load_mtl(path: &Path) -> io::Result<HashMap<String, ImportedMaterial>> {
+    let text = fs::read_to_string(path)?;
+    let mut materials = HashMap::new();
+    let mut current: Option<ImportedMaterial> = None;
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let Some((keyword, rest)) = line.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let rest = rest.trim();
+        match keyword {
+            "newmtl" => {
+                if let Some(material) = current.take() {
+                    materials.insert(material.name.clone(), material);
+                }
+                current = Some(ImportedMaterial {
+                    name: rest.to_string(),
+                    color: [1.0; 3],
+                    texture: None,
+                });
+            }
+            "Kd" => {
+                let values: Vec<f32> = rest.split_whitespace().filter_map(|word| word.parse().ok()).collect();
+                if let (Some(material), &[r, g, b]) = (&mut current, &values[..]) {
+                    material.color = [r, g, b];
+                }
+            }
+            // Options such as `-s` come before the file name, which is taken to be the last word
+            "map_Kd" => {
+                if let (Some(material), Some(name)) = (&mut current, rest.split_whitespace().last()) {
+                    material.texture = Some(path.with_file_name(name));
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(material) = current {
+        materials.insert(material.name.clone(), material);
+    }
+    Ok(materials)
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn invalid_gltf(err: gltf::Error) -> io::Error {
+    match err {
+        gltf::Error::Io(err) => err,
+        err => invalid(err.to_string()),
+    }
+}
@@ -0,0 +1,118 @@
+// mesh_builder.rs
+
+use crate::bounds::Aabb;
+use crate::jobs::{self, FrameBudget, Priority};
+use crate::primitives::Primitive;
+use crate::vertex::Vertex;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+use web_time::Instant;
+
+// Also a component, on entities whose mesh was generated from it
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MeshShape {
+    Polygon { sides: u16, radius: f32 },
+    Cube,
+    TexturedCube,
+    Primitive(Primitive),
+}
+
+impl MeshShape {
+    pub fn generate(&self) -> (Vec<Vertex>, Vec<u16>) {
+        match *self {
+            MeshShape::Polygon { sides, radius } => Vertex::generate_polygon(sides, radius),
+            MeshShape::Cube => Vertex::generate_cube(),
+            MeshShape::TexturedCube => Vertex::generate_textured_cube(),
+            MeshShape::Primitive(primitive) => primitive.generate(),
+        }
+    }
+}
+
+pub struct BuiltMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+    pub bounds: Option<Aabb>,
+    pub shape: MeshShape,
+    pub elapsed: Duration, // Time spent generating on the worker
+}
+
+// Generates meshes on the job pool so scrubbing a shape slider never stalls a frame. At most one build is
+// in flight; shapes requested meanwhile replace each other and only the last one is built once it finishes
+pub struct MeshBuilder {
+    sender: Sender<BuiltMesh>,
+    receiver: Receiver<BuiltMesh>,
+    requested: MeshShape,
+    in_flight: bool,
+    queued: Option<MeshShape>,
+}
+
+impl MeshBuilder {
+    /// `current` is the shape already on the GPU, so asking for it again builds nothing.
+    pub fn new(current: MeshShape) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            requested: current,
+            in_flight: false,
+            queued: None,
+        }
+    }
+
+    /// Asks for `shape` to be built unless it is the shape most recently asked for.
+    pub fn request(&mut self, shape: MeshShape) {
+        if shape == self.requested {
+            return;
+        }
+        self.requested = shape;
+        if self.in_flight {
+            self.queued = Some(shape);
+        } else {
+            self.spawn(shape);
+        }
+    }
+
+    /// Builds the shape most recently asked for again, as after something else replaced the mesh it went to.
+    pub fn rebuild(&mut self) {
+        if self.in_flight {
+            self.queued = Some(self.requested);
+        } else {
+            self.spawn(self.requested);
+        }
+    }
+
+    /// Returns a mesh that finished building since the last call, starting the next queued build if any.
+    /// Without time left in `budget`, the mesh is left for a later frame.
+    pub fn poll(&mut self, budget: &mut FrameBudget) -> Option<BuiltMesh> {
+        if !budget.has_time() {
+            return None;
+        }
+        let built = self.receiver.try_recv().ok()?;
+        budget.spend();
+        self.in_flight = false;
+        if let Some(shape) = self.queued.take() {
+            self.spawn(shape);
+        }
+        Some(built)
+    }
+
+    fn spawn(&mut self, shape: MeshShape) {
+        let sender = self.sender.clone();
+        self.in_flight = true;
+        jobs::spawn(Priority::High, move |_| {
+            puffin::profile_scope!("mesh_build");
+            let start = Instant::now();
+            let (vertices, indices) = shape.generate();
+            let bounds = Aabb::from_points(vertices.iter().map(|v| Vec3::from(v.position)));
+            let _ = sender.send(BuiltMesh {
+                vertices,
+                indices,
+                bounds,
+                shape,
+                elapsed: start.elapsed(),
+            });
+        });
+    }
+}
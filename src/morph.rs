@@ -0,0 +1,103 @@
+// morph.rs
+
+use std::f32::consts::PI;
+
+pub const MIN_SIDES: u16 = 3;
+pub const MAX_SIDES: u16 = 12;
+const MIN_RADIUS: f32 = 0.3;
+const MAX_RADIUS: f32 = 0.6;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    SmoothStep,
+    Sine,
+    CubicInOut,
+}
+
+impl Easing {
+    pub const ALL: [Easing; 4] = [Easing::Linear, Easing::SmoothStep, Easing::Sine, Easing::CubicInOut];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Easing::Linear => "Linear",
+            Easing::SmoothStep => "Smoothstep",
+            Easing::Sine => "Sine",
+            Easing::CubicInOut => "Cubic in/out",
+        }
+    }
+
+    // Maps 0..=1 to 0..=1
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::SmoothStep => t * t * (3.0 - 2.0 * t),
+            Easing::Sine => 0.5 - 0.5 * (t * PI).cos(),
+            Easing::CubicInOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::CubicInOut => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+        }
+    }
+}
+
+// Animates the polygon's side count and radius back and forth, driven by the frame delta time
+#[derive(Debug, Clone)]
+pub struct PolygonMorph {
+    pub playing: bool,
+    pub speed: f32, // Cycles per second
+    pub easing: Easing,
+    phase: f32,     // 0..1, one full grow-and-shrink cycle
+}
+
+impl PolygonMorph {
+    pub fn new() -> Self {
+        Self {
+            playing: false,
+            speed: 0.25,
+            easing: Easing::SmoothStep,
+            phase: 0.0,
+        }
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        if self.playing {
+            self.phase = (self.phase + dt * self.speed).fract();
+        }
+    }
+
+    // Eased ping-pong between 0 and 1
+    fn amount(&self) -> f32 {
+        let t = 1.0 - (2.0 * self.phase - 1.0).abs();
+        self.easing.apply(t)
+    }
+
+    pub fn sides(&self) -> u16 {
+        MIN_SIDES + (self.amount() * (MAX_SIDES - MIN_SIDES) as f32).round() as u16
+    }
+
+    pub fn radius(&self) -> f32 {
+        MIN_RADIUS + self.amount() * (MAX_RADIUS - MIN_RADIUS)
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let label = if self.playing { "⏸ Pause" } else { "▶ Play" };
+            if ui.button(label).clicked() {
+                self.playing = !self.playing;
+            }
+            ui.add(egui::Slider::new(&mut self.speed, 0.05..=2.0).text("cycles/s").logarithmic(true));
+        });
+        egui::ComboBox::from_label("Easing")
+            .selected_text(self.easing.label())
+            .show_ui(ui, |ui| {
+                for easing in Easing::ALL {
+                    ui.selectable_value(&mut self.easing, easing, easing.label());
+                }
+            });
+    }
+}
+
+impl Default for PolygonMorph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,230 @@
+// msaa.rs
+
+use crate::depth::{DepthConfig, DEPTH_FORMAT};
+use crate::stats::RenderStats;
+use egui_wgpu::wgpu;
+
+// The sample counts offered in the settings
+pub const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+pub const DEFAULT_SAMPLES: u32 = 4;
+
+/// The sample counts the scene pass can use with `format`. Without adapter-specific format features only 1 and
+/// 4, which WebGPU guarantees, are offered.
+pub fn supported_sample_counts(
+    adapter: &wgpu::Adapter,
+    features: wgpu::Features,
+    format: wgpu::TextureFormat,
+) -> Vec<u32> {
+    let adapter_specific = features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+    let resolves = adapter
+        .get_texture_format_features(format)
+        .flags
+        .contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE);
+    SAMPLE_COUNTS
+        .into_iter()
+        .filter(|&count| match count {
+            1 => true,
+            _ if !adapter_specific => count == 4,
+            _ => {
+                resolves
+                    && [format, DEPTH_FORMAT]
+                        .iter()
+                        .all(|format| adapter.get_texture_format_features(*format).flags.sample_count_supported(count))
+            }
+        })
+        .collect()
+}
+
+// Multisampled color and depth for the scene pass
+struct Targets {
+    color: wgpu::Texture,
+    color_view: wgpu::TextureView,
+    depth: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+    depth_bind_group: wgpu::BindGroup,
+}
+
+// Antialiases the scene pass: the scene's meshes are drawn into multisampled targets, the color resolved into the
+// scene's own target and the depth copied into the scene's depth buffer. The passes after it draw single-sampled
+// onto the resolved scene as before
+pub struct Msaa {
+    format: wgpu::TextureFormat,
+    samples: u32,
+    depth_layout: wgpu::BindGroupLayout,
+    depth_resolve: wgpu::RenderPipeline,
+    targets: Option<Targets>, // None at one sample, or until the first `resize`
+}
+
+impl Msaa {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, samples: u32) -> Self {
+        let depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("MSAA Depth Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    // Read as plain floats, since GL can't load from depth textures
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: true,
+                },
+                count: None,
+            }],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("MSAA Depth Resolve Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("msaa_depth.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("MSAA Depth Resolve Pipeline Layout"),
+            bind_group_layouts: &[&depth_layout],
+            push_constant_ranges: &[],
+        });
+        let depth_resolve = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("MSAA Depth Resolve Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Every pixel is overwritten, whatever the buffer held
+            depth_stencil: Some(DepthConfig::OVERLAY.with_write(true).state()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            format,
+            samples,
+            depth_layout,
+            depth_resolve,
+            targets: None,
+        }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Switches to `samples` samples per pixel; the targets are remade by the next `resize`.
+    pub fn set_samples(&mut self, stats: &mut RenderStats, samples: u32) {
+        if samples != self.samples {
+            self.samples = samples;
+            self.release(stats);
+        }
+    }
+
+    /// Makes the targets `size`, replacing them if their size differs, or frees them at one sample.
+    pub fn resize(&mut self, device: &wgpu::Device, stats: &mut RenderStats, (width, height): (u32, u32)) {
+        if self.samples == 1 {
+            self.release(stats);
+            return;
+        }
+        if let Some(targets) = &self.targets {
+            if (targets.color.width(), targets.color.height()) == (width, height) {
+                return;
+            }
+        }
+        self.release(stats);
+        let create = |stats: &mut RenderStats, label, format, usage| {
+            stats.create_texture(
+                device,
+                &wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: self.samples,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage,
+                    view_formats: &[],
+                },
+            )
+        };
+        let color = create(stats, "MSAA Color Texture", self.format, wgpu::TextureUsages::RENDER_ATTACHMENT);
+        let depth = create(
+            stats,
+            "MSAA Depth Texture",
+            DEPTH_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        );
+        let color_view = color.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("MSAA Depth Bind Group"),
+            layout: &self.depth_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&depth_view),
+            }],
+        });
+        self.targets = Some(Targets {
+            color,
+            color_view,
+            depth,
+            depth_view,
+            depth_bind_group,
+        });
+    }
+
+    fn release(&mut self, stats: &mut RenderStats) {
+        if let Some(targets) = self.targets.take() {
+            stats.texture_destroyed(&targets.color);
+            stats.texture_destroyed(&targets.depth);
+        }
+    }
+
+    /// The scene pass's color attachment and resolve target, given the view the scene ends up in.
+    pub fn color_attachment<'a>(
+        &'a self,
+        target: &'a wgpu::TextureView,
+    ) -> (&'a wgpu::TextureView, Option<&'a wgpu::TextureView>) {
+        match &self.targets {
+            Some(targets) => (&targets.color_view, Some(target)),
+            None => (target, None),
+        }
+    }
+
+    /// The scene pass's depth attachment, given the scene's depth buffer.
+    pub fn depth_attachment<'a>(&'a self, depth: &'a wgpu::TextureView) -> &'a wgpu::TextureView {
+        self.targets.as_ref().map_or(depth, |targets| &targets.depth_view)
+    }
+
+    /// Copies the scene pass's depth into `depth`, the scene's depth buffer. Does nothing at one sample, where
+    /// the pass wrote it directly.
+    pub fn resolve_depth(&self, encoder: &mut wgpu::CommandEncoder, depth: &wgpu::TextureView) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("MSAA Depth Resolve Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0), // Every pixel is overwritten
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.depth_resolve);
+        pass.set_bind_group(0, &targets.depth_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
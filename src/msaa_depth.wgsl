@@ -0,0 +1,24 @@
+// Copies the multisampled scene depth into the single-sampled depth buffer the passes after the scene test
+// against and read
+
+@group(0) @binding(0)
+var depth: texture_multisampled_2d<f32>;
+
+// A single triangle covering the screen, so no vertex buffer is needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// The nearest of the pixel's samples, so wherever an edge covers a pixel at all, what is drawn after the scene
+// stays behind it
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @builtin(frag_depth) f32 {
+    let texel = vec2<i32>(position.xy);
+    var nearest = 1.0;
+    for (var i = 0u; i < textureNumSamples(depth); i++) {
+        nearest = min(nearest, textureLoad(depth, texel, i32(i)).r);
+    }
+    return nearest;
+}
@@ -0,0 +1,565 @@
+// network.rs
+
+use crate::camera::Camera;
+use crate::debug;
+use crate::labels::{Labels, WorldLabel};
+use crate::scene::SceneFile;
+use crate::toasts::Toasts;
+use crate::transform::Transform;
+use egui::Context;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use web_time::Instant;
+
+// Port hosts listen on unless given another
+pub const DEFAULT_PORT: u16 = 7878;
+
+// Interface hosts listen on unless given another. Anyone who can reach the port can edit the scene, so only this
+// machine can unless the host names a wider one
+const DEFAULT_INTERFACE: &str = "127.0.0.1";
+
+// Longest message a peer may send, in bytes; one that runs on past it ends the connection
+const MAX_MESSAGE_LEN: u64 = 4 * 1024 * 1024;
+
+// Least time between two camera or object updates, so a drag sends about twenty a second rather than one a frame
+const SEND_INTERVAL: Duration = Duration::from_millis(50);
+
+// How long joining waits for the host to answer, and how often the host checks whether to stop listening
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+// The id the host sends its own messages as; peers are numbered from 1 as they join
+const HOST: u32 = 0;
+
+// What instances tell each other
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Message {
+    Hello { name: String },
+    Scene(Box<SceneFile>), // The whole scene, sent to peers as they join and whenever asked
+    Camera { position: Vec3, target: Vec3, up: Vec3 },
+    Object { transform: Transform, sides: u16, radius: f32 }, // The edited object
+}
+
+// One message as written to the stream, a line of JSON. The host fills in `from` as it passes messages on,
+// so every instance can tell peers' cameras apart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Envelope {
+    from: u32,
+    message: Message,
+}
+
+// What the connection threads hand the render loop
+enum Event {
+    Connected(u32, Sender<String>), // A stream to write to, for the id it is known by here
+    Received(u32, Envelope),
+    Disconnected(u32),
+    Failed(String),
+}
+
+/// What arrived from other instances for the render loop to apply.
+#[derive(Debug, Clone)]
+pub enum NetworkEvent {
+    Scene(Box<SceneFile>),
+    Object { transform: Transform, sides: u16, radius: f32 },
+    SceneWanted(Option<u32>), // The current scene should be sent to this peer, or every peer with None
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Role {
+    Offline,
+    Hosting,
+    Joining,
+    Joined,
+}
+
+// Another instance seen through this one's connections: a peer directly, or through the host
+struct RemoteView {
+    name: String,
+    camera: Option<(Vec3, Vec3, Vec3)>, // Position, target and up
+}
+
+// Shares the camera and scene edits with other instances over TCP, so several people can look at and edit the
+// same scene. One instance hosts and passes everything it hears on to the others; joining instances are sent
+// the host's scene, then the edited object and every camera are kept in step
+pub struct Network {
+    pub name: String,
+    pub address: String, // Host and port to join, or the port to host on
+    pub show_cameras: bool,
+    pub follow: Option<u32>, // Peer whose camera this one's follows
+    role: Role,
+    events: Option<Receiver<Event>>,
+    writers: BTreeMap<u32, Sender<String>>, // Every peer while hosting, or just the host once joined
+    remotes: BTreeMap<u32, RemoteView>,
+    stop: Arc<AtomicBool>, // Stops the listener thread
+    sent_camera: Option<(Vec3, Vec3, Vec3)>,
+    sent_object: Option<(Transform, u16, f32)>,
+    last_send: Option<Instant>,
+    scene_wanted: bool, // "Send scene" was clicked
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Self {
+            name: std::env::var("USER").unwrap_or_else(|_| "Peer".into()),
+            address: format!("127.0.0.1:{DEFAULT_PORT}"),
+            show_cameras: true,
+            follow: None,
+            role: Role::Offline,
+            events: None,
+            writers: BTreeMap::new(),
+            remotes: BTreeMap::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            sent_camera: None,
+            sent_object: None,
+            last_send: None,
+            scene_wanted: false,
+        }
+    }
+
+    /// Hosts with `--host`, optionally followed by a port or an address to listen on, or joins the address
+    /// after `--join`.
+    pub fn start_from_args(&mut self, args: &[String], toasts: &mut Toasts) {
+        let mut args = args.iter().skip(1).peekable();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--host" => {
+                    let address = args.next_if(|address| address.parse::<u16>().is_ok() || address.contains(':'));
+                    self.host(&listen_address(address.map_or("", String::as_str)), toasts);
+                }
+                "--join" => match args.next() {
+                    Some(address) => {
+                        self.address.clone_from(address);
+                        self.join();
+                    }
+                    None => toasts.error("--join needs an address to connect to"),
+                },
+                _ => {}
+            }
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.role != Role::Offline
+    }
+
+    /// Listens for peers at `address`, an interface and port.
+    pub fn host(&mut self, address: &str, toasts: &mut Toasts) {
+        self.disconnect();
+        let listener = match TcpListener::bind(address).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        }) {
+            Ok(listener) => listener,
+            Err(err) => {
+                log::warn!("Failed to listen on {address}: {err}");
+                toasts.error(format!("Failed to host on {address}: {err}"));
+                return;
+            }
+        };
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        self.stop = stop.clone();
+        thread::spawn(move || {
+            let mut next_id = HOST + 1;
+            while !stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if connect(stream, next_id, &sender).is_err() {
+                            return;
+                        }
+                        next_id += 1;
+                    }
+                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+                    Err(err) => {
+                        let _ = sender.send(Event::Failed(format!("Stopped accepting peers: {err}")));
+                        return;
+                    }
+                }
+            }
+        });
+        self.events = Some(receiver);
+        self.role = Role::Hosting;
+        toasts.success(format!("Hosting on {address}"));
+    }
+
+    /// Connects to the host at `address` in the background.
+    pub fn join(&mut self) {
+        self.disconnect();
+        let address = self.address.clone();
+        let (sender, receiver) = mpsc::channel();
+        // Browsers have neither raw TCP sockets nor threads to connect on
+        if cfg!(target_arch = "wasm32") {
+            let _ = sender.send(Event::Failed("Joining isn't available in a browser".to_string()));
+            self.events = Some(receiver);
+            self.role = Role::Joining;
+            return;
+        }
+        thread::spawn(move || {
+            let stream = address
+                .to_socket_addrs()
+                .and_then(|mut addresses| {
+                    addresses.next().ok_or_else(|| std::io::Error::other("the address didn't resolve"))
+                })
+                .and_then(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT));
+            match stream {
+                Ok(stream) => {
+                    let _ = connect(stream, HOST, &sender);
+                }
+                Err(err) => {
+                    let _ = sender.send(Event::Failed(format!("Failed to join {address}: {err}")));
+                }
+            }
+        });
+        self.events = Some(receiver);
+        self.role = Role::Joining;
+    }
+
+    /// Closes every connection and stops hosting.
+    pub fn disconnect(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Dropping the writers ends their threads, which shut the streams and so end the readers
+        self.writers.clear();
+        self.remotes.clear();
+        self.events = None;
+        self.follow = None;
+        self.role = Role::Offline;
+        self.sent_camera = None;
+        self.sent_object = None;
+    }
+
+    /// Takes in what the connections received since the last call, passing it on to the other peers while
+    /// hosting. Returns what the render loop should apply.
+    pub fn poll(&mut self, toasts: &mut Toasts) -> Vec<NetworkEvent> {
+        let mut applied = Vec::new();
+        if std::mem::take(&mut self.scene_wanted) {
+            applied.push(NetworkEvent::SceneWanted(None));
+        }
+        let Some(events) = &self.events else {
+            return applied;
+        };
+        let events: Vec<Event> = events.try_iter().collect();
+        for event in events {
+            match event {
+                Event::Connected(id, writer) => {
+                    self.writers.insert(id, writer);
+                    self.send_to(id, HOST, &Message::Hello { name: self.name.clone() });
+                    if self.role == Role::Hosting {
+                        // Everyone else already knows the new peer's name from its hello, passed on below
+                        let names: Vec<(u32, String)> =
+                            self.remotes.iter().map(|(id, remote)| (*id, remote.name.clone())).collect();
+                        for (from, name) in names {
+                            self.send_to(id, from, &Message::Hello { name });
+                        }
+                        applied.push(NetworkEvent::SceneWanted(Some(id)));
+                    } else {
+                        self.role = Role::Joined;
+                        toasts.success(format!("Joined {}", self.address));
+                    }
+                }
+                Event::Received(id, mut envelope) => {
+                    if self.role == Role::Hosting {
+                        envelope.from = id;
+                        self.relay(&envelope);
+                    }
+                    if let Some(event) = self.receive(envelope, toasts) {
+                        applied.push(event);
+                    }
+                }
+                Event::Disconnected(id) => {
+                    self.writers.remove(&id);
+                    if self.role == Role::Hosting {
+                        if let Some(remote) = self.remotes.remove(&id) {
+                            toasts.info(format!("{} left", remote.name));
+                        }
+                    } else {
+                        toasts.warning("Lost the connection to the host");
+                        self.disconnect();
+                        return applied;
+                    }
+                }
+                Event::Failed(err) => {
+                    log::warn!("{err}");
+                    toasts.error(err);
+                    if self.role != Role::Hosting {
+                        self.disconnect();
+                        return applied;
+                    }
+                }
+            }
+        }
+        applied
+    }
+
+    // Records or returns one message from `envelope.from`
+    fn receive(&mut self, envelope: Envelope, toasts: &mut Toasts) -> Option<NetworkEvent> {
+        let from = envelope.from;
+        match envelope.message {
+            Message::Hello { name } => {
+                toasts.info(format!("{name} joined"));
+                self.remotes.insert(from, RemoteView { name, camera: None });
+                None
+            }
+            Message::Scene(mut scene) => {
+                // A peer's paths name files on its machine, and a script or prefab is code and data this one
+                // would run and load from its own disk, so they aren't taken
+                if scene.strip_paths() > 0 {
+                    toasts.warning("Left out the scripts, prefabs and textures of the received scene");
+                }
+                // The scene's own object and camera count as sent, so they aren't echoed straight back
+                self.sent_object = Some((scene.transform, scene.sides, scene.radius));
+                self.sent_camera = None;
+                Some(NetworkEvent::Scene(scene))
+            }
+            Message::Camera { position, target, up } => {
+                if let Some(remote) = self.remotes.get_mut(&from) {
+                    remote.camera = Some((position, target, up));
+                }
+                None
+            }
+            Message::Object {
+                transform,
+                sides,
+                radius,
+            } => {
+                self.sent_object = Some((transform, sides, radius));
+                Some(NetworkEvent::Object {
+                    transform,
+                    sides,
+                    radius,
+                })
+            }
+        }
+    }
+
+    // Passes a peer's message on to every other peer
+    fn relay(&mut self, envelope: &Envelope) {
+        let Ok(line) = encode(envelope) else {
+            return;
+        };
+        for (id, writer) in &self.writers {
+            if *id != envelope.from {
+                let _ = writer.send(line.clone());
+            }
+        }
+    }
+
+    fn send_to(&self, id: u32, from: u32, message: &Message) {
+        if let (Some(writer), Ok(line)) = (self.writers.get(&id), encode(&Envelope { from, message: message.clone() }))
+        {
+            let _ = writer.send(line);
+        }
+    }
+
+    fn broadcast(&self, message: Message) {
+        let Ok(line) = encode(&Envelope { from: HOST, message }) else {
+            return;
+        };
+        for writer in self.writers.values() {
+            let _ = writer.send(line.clone());
+        }
+    }
+
+    /// Sends `scene` to peer `to`, or to every peer with None.
+    pub fn send_scene(&mut self, scene: SceneFile, to: Option<u32>) {
+        self.sent_object = Some((scene.transform, scene.sides, scene.radius));
+        let message = Message::Scene(Box::new(scene));
+        match to {
+            Some(id) => self.send_to(id, HOST, &message),
+            None => self.broadcast(message),
+        }
+    }
+
+    /// The pose of the camera being followed, if any.
+    pub fn followed_camera(&self) -> Option<(Vec3, Vec3, Vec3)> {
+        self.follow.and_then(|id| self.remotes.get(&id)).and_then(|remote| remote.camera)
+    }
+
+    /// Sends `camera` and the edited object if either changed since they were last sent, at most every
+    /// `SEND_INTERVAL`.
+    pub fn publish(&mut self, camera: &Camera, transform: Transform, sides: u16, radius: f32) {
+        if self.writers.is_empty() || self.last_send.is_some_and(|last| last.elapsed() < SEND_INTERVAL) {
+            return;
+        }
+        let pose = (camera.position, camera.target, camera.up);
+        let mut sent = false;
+        if self.sent_camera != Some(pose) {
+            self.broadcast(Message::Camera {
+                position: pose.0,
+                target: pose.1,
+                up: pose.2,
+            });
+            self.sent_camera = Some(pose);
+            sent = true;
+        }
+        let object = (transform, sides, radius);
+        if self.sent_object != Some(object) {
+            self.broadcast(Message::Object {
+                transform,
+                sides,
+                radius,
+            });
+            self.sent_object = Some(object);
+            sent = true;
+        }
+        if sent {
+            self.last_send = Some(Instant::now());
+        }
+    }
+
+    /// Marks every peer's camera in the viewport, with a line along where it looks.
+    pub fn mark_cameras(&self, labels: &mut Labels) {
+        if !self.show_cameras {
+            return;
+        }
+        for remote in self.remotes.values() {
+            let Some((position, target, _)) = remote.camera else {
+                continue;
+            };
+            let color = egui::Color32::from_rgb(120, 200, 255);
+            labels.add(WorldLabel::new(position, remote.name.clone()).icon("🎥").color(color));
+            debug::ray(position, (target - position).normalize_or(Vec3::NEG_Z), 1.0, color);
+        }
+    }
+
+    /// The Collaboration window: hosts or joins a session, lists the peers and follows their cameras.
+    pub fn show(&mut self, ctx: &Context, toasts: &mut Toasts) {
+        egui::Window::new("Collaboration")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                ui.add_enabled_ui(self.role == Role::Offline, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Name");
+                        ui.text_edit_singleline(&mut self.name);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Address");
+                        ui.text_edit_singleline(&mut self.address);
+                    });
+                    ui.horizontal(|ui| {
+                        let hover = "Listens at the address. Only this machine can join 127.0.0.1; 0.0.0.0 lets anyone \
+                                     who can reach the port join and edit";
+                        if ui.button("Host").on_hover_text(hover).clicked() {
+                            let address = listen_address(&self.address);
+                            self.host(&address, toasts);
+                        }
+                        if ui.button("Join").clicked() {
+                            self.join();
+                        }
+                    });
+                });
+                if self.role == Role::Offline {
+                    return;
+                }
+                ui.separator();
+                ui.label(match self.role {
+                    Role::Hosting => format!("Hosting, {} connected", self.writers.len()),
+                    Role::Joining => format!("Joining {}…", self.address),
+                    _ => format!("Joined {}", self.address),
+                });
+                ui.checkbox(&mut self.show_cameras, "Show peer cameras");
+                let mut follow = self.follow;
+                ui.radio_value(&mut follow, None, "Own camera");
+                for (id, remote) in &self.remotes {
+                    let label = format!("Follow {}", remote.name);
+                    ui.add_enabled_ui(remote.camera.is_some(), |ui| ui.radio_value(&mut follow, Some(*id), label));
+                }
+                self.follow = follow;
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!self.writers.is_empty(), egui::Button::new("Send scene")).clicked() {
+                        self.scene_wanted = true;
+                    }
+                    if ui.button("Disconnect").clicked() {
+                        self.disconnect();
+                    }
+                });
+            });
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Network {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// The address to listen at for `given`: an interface and port, a port on the default interface, or with
+// neither the default port there too
+fn listen_address(given: &str) -> String {
+    let given = given.trim();
+    if given.is_empty() {
+        format!("{DEFAULT_INTERFACE}:{DEFAULT_PORT}")
+    } else if given.parse::<u16>().is_ok() {
+        format!("{DEFAULT_INTERFACE}:{given}")
+    } else {
+        given.to_string()
+    }
+}
+
+fn encode(envelope: &Envelope) -> serde_json::Result<String> {
+    let mut line = serde_json::to_string(envelope)?;
+    line.push('\n');
+    Ok(line)
+}
+
+// Starts a writer and a reader thread for `stream`, known here as `id`, and hands the writer to the render loop.
+// Fails once the render loop has stopped listening
+fn connect(stream: TcpStream, id: u32, events: &Sender<Event>) -> Result<(), ()> {
+    let _ = stream.set_nodelay(true);
+    let (Ok(reader), Ok(mut writer)) = (stream.try_clone(), stream.try_clone()) else {
+        return Ok(());
+    };
+    let (lines, outgoing) = mpsc::channel::<String>();
+    events.send(Event::Connected(id, lines)).map_err(|_| ())?;
+    thread::spawn(move || {
+        for line in outgoing {
+            if writer.write_all(line.as_bytes()).is_err() {
+                break;
+            }
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+    });
+    let events = events.clone();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match (&mut reader).take(MAX_MESSAGE_LEN + 1).read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) if !line.ends_with(b"\n") => {
+                    if line.len() as u64 > MAX_MESSAGE_LEN {
+                        log::warn!("Dropping peer {id}, whose message ran past {MAX_MESSAGE_LEN} bytes");
+                    }
+                    break;
+                }
+                Ok(_) => {}
+            }
+            match serde_json::from_slice(&line) {
+                Ok(envelope) => {
+                    if events.send(Event::Received(id, envelope)).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => log::warn!("Ignoring a malformed message from peer {id}: {err}"),
+            }
+        }
+        let _ = events.send(Event::Disconnected(id));
+    });
+    Ok(())
+}
@@ -0,0 +1,166 @@
+// object_drag.rs
+
+use crate::bounds::Aabb;
+use crate::gizmo;
+use crate::transform::Transform;
+use crate::viewport_panel;
+use egui::{Color32, Context, Id, LayerId, Order, Stroke};
+use glam::{Mat4, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DragConstraint {
+    ViewPlane, // Parallel to the screen through the grabbed point
+    X,
+    Y,
+    Z,
+}
+
+impl DragConstraint {
+    pub const ALL: [DragConstraint; 4] = [
+        DragConstraint::ViewPlane,
+        DragConstraint::X,
+        DragConstraint::Y,
+        DragConstraint::Z,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DragConstraint::ViewPlane => "Screen",
+            DragConstraint::X => "X",
+            DragConstraint::Y => "Y",
+            DragConstraint::Z => "Z",
+        }
+    }
+
+    fn axis(&self) -> Option<Vec3> {
+        match self {
+            DragConstraint::ViewPlane => None,
+            DragConstraint::X => Some(Vec3::X),
+            DragConstraint::Y => Some(Vec3::Y),
+            DragConstraint::Z => Some(Vec3::Z),
+        }
+    }
+
+    fn color(&self) -> Color32 {
+        match self {
+            DragConstraint::ViewPlane => Color32::WHITE,
+            DragConstraint::X => Color32::from_rgb(230, 70, 70),
+            DragConstraint::Y => Color32::from_rgb(80, 200, 80),
+            DragConstraint::Z => Color32::from_rgb(70, 120, 240),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct ActiveDrag {
+    start: Vec3,  // Object translation when the drag began
+    grab: Vec3,   // Point under the cursor on the drag plane when the drag began
+    normal: Vec3, // Drag plane normal, fixed for the whole drag
+}
+
+// Left-dragging the selected object moves it directly, for quick layout without aiming at gizmo handles
+pub struct ObjectDrag {
+    pub enabled: bool,
+    pub constraint: DragConstraint,
+    active: Option<ActiveDrag>,
+}
+
+impl ObjectDrag {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            constraint: DragConstraint::ViewPlane,
+            active: None,
+        }
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.active.is_some()
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Drag object in viewport");
+        if self.enabled {
+            ui.horizontal(|ui| {
+                ui.label("Along");
+                for constraint in DragConstraint::ALL {
+                    ui.radio_value(&mut self.constraint, constraint, constraint.label());
+                }
+            });
+        }
+    }
+
+    /// Starts a drag if the ray from the press hits `bounds`. Returns true if it did.
+    pub fn begin(&mut self, ray: (Vec3, Vec3), view_direction: Vec3, transform: &Transform, bounds: &Aabb) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let (origin, direction) = ray;
+        let Some(t) = bounds.ray_intersection(origin, direction) else {
+            return false;
+        };
+        let grab = origin + direction * t;
+        // An axis drag uses the plane containing the axis that faces the camera the most
+        let normal = match self.constraint.axis() {
+            Some(axis) => axis.cross(view_direction).cross(axis).normalize_or_zero(),
+            None => view_direction,
+        };
+        if normal == Vec3::ZERO {
+            return false; // Looking straight down the axis
+        }
+        self.active = Some(ActiveDrag {
+            start: transform.translation,
+            grab,
+            normal,
+        });
+        true
+    }
+
+    /// Moves the object so the grabbed point follows the ray, snapping the offset to `snap` world units.
+    pub fn update(&self, ray: (Vec3, Vec3), transform: &mut Transform, snap: Option<f32>) {
+        let Some(drag) = self.active else {
+            return;
+        };
+        let (origin, direction) = ray;
+        let denominator = direction.dot(drag.normal);
+        if denominator.abs() < 1e-4 {
+            return; // Ray runs along the plane
+        }
+        let t = (drag.grab - origin).dot(drag.normal) / denominator;
+        if t < 0.0 {
+            return; // Plane is behind the camera
+        }
+        let mut offset = origin + direction * t - drag.grab;
+        if let Some(axis) = self.constraint.axis() {
+            offset = axis * offset.dot(axis);
+        }
+        if let Some(step) = snap.filter(|s| *s > 0.0) {
+            offset = (offset / step).round() * step;
+        }
+        transform.translation = drag.start + offset;
+    }
+
+    pub fn end(&mut self) {
+        self.active = None;
+    }
+
+    // Guide line through the object along the constrained axis while dragging
+    pub fn show(&self, ctx: &Context, view_proj: Mat4, camera_position: Vec3, transform: &Transform) {
+        let (Some(_), Some(axis)) = (self.active, self.constraint.axis()) else {
+            return;
+        };
+        let rect = viewport_panel::scene_rect(ctx);
+        let reach = (camera_position - transform.translation).length();
+        let ends = [-reach, reach].map(|d| gizmo::project(view_proj, rect, transform.translation + axis * d));
+        if let [Some(a), Some(b)] = ends {
+            let painter = ctx.layer_painter(LayerId::new(Order::Background, Id::new("object_drag")));
+            painter.line_segment([a, b], Stroke::new(1.5, self.constraint.color()));
+        }
+    }
+}
+
+impl Default for ObjectDrag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,109 @@
+// object_uniforms.rs
+
+use crate::bind_groups::BindGroupCache;
+use crate::stats::RenderStats;
+use crate::transform::ModelUniform;
+use crate::upload::Uploads;
+use egui_wgpu::wgpu;
+use std::num::NonZeroU64;
+use std::sync::Arc;
+
+// Room for this many objects before the arena first has to grow
+const INITIAL_CAPACITY: u64 = 64;
+
+// Every object's uniforms for the frame packed into one buffer, each drawn through the same bind group at
+// its own dynamic offset instead of with a buffer and bind group of its own
+pub struct ObjectUniforms {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    buffer: Arc<wgpu::Buffer>,
+    stride: u64, // Uniform size rounded up to the device's dynamic offset alignment
+    staged: Vec<u8>,
+    uploaded: Vec<u8>, // What the buffer holds, so unchanged objects aren't written again
+}
+
+impl ObjectUniforms {
+    pub fn new(device: &wgpu::Device, stats: &mut RenderStats) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = (size_of::<ModelUniform>() as u64).next_multiple_of(alignment);
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Object Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(size_of::<ModelUniform>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let buffer = create_buffer(device, stats, stride * INITIAL_CAPACITY);
+        Self {
+            bind_group_layout,
+            buffer,
+            stride,
+            staged: Vec::new(),
+            uploaded: Vec::new(),
+        }
+    }
+
+    /// Starts a new frame of `count` objects, growing the buffer if they don't fit. Returns true if it
+    /// grew, which replaces the bind group.
+    pub fn begin_frame(&mut self, device: &wgpu::Device, stats: &mut RenderStats, count: usize) -> bool {
+        self.staged.clear();
+        let needed = self.stride * count as u64;
+        if needed <= self.buffer.size() {
+            return false;
+        }
+        stats.buffer_destroyed(&self.buffer);
+        self.buffer = create_buffer(device, stats, needed.next_power_of_two());
+        self.uploaded.clear();
+        true
+    }
+
+    /// Adds an object to the frame and returns its dynamic offset.
+    pub fn push(&mut self, uniform: ModelUniform) -> wgpu::DynamicOffset {
+        let offset = self.staged.len();
+        self.staged.resize(offset + self.stride as usize, 0);
+        self.set(offset as wgpu::DynamicOffset, uniform);
+        offset as wgpu::DynamicOffset
+    }
+
+    /// Replaces the uniforms pushed at `offset`, for edits made after the object was drawn.
+    pub fn set(&mut self, offset: wgpu::DynamicOffset, uniform: ModelUniform) {
+        let start = offset as usize;
+        self.staged[start..start + size_of::<ModelUniform>()].copy_from_slice(bytemuck::bytes_of(&uniform));
+    }
+
+    pub fn bind_group(&self, device: &wgpu::Device, bind_groups: &mut BindGroupCache) -> Arc<wgpu::BindGroup> {
+        let size = NonZeroU64::new(size_of::<ModelUniform>() as u64).unwrap();
+        bind_groups.get_ranged(device, "Object Bind Group", &self.bind_group_layout, &[(0, &self.buffer, size)])
+    }
+
+    // Stages the objects that changed since the last upload for the GPU in a single write covering all of
+    // them; they land with the next submit
+    pub fn upload(&mut self, device: &wgpu::Device, uploads: &mut Uploads) {
+        let stride = self.stride as usize;
+        let slot = |index: usize| index * stride..(index + 1) * stride;
+        let changed = |index: &usize| self.staged.get(slot(*index)) != self.uploaded.get(slot(*index));
+        let count = self.staged.len() / stride;
+        if let (Some(first), Some(last)) = ((0..count).find(changed), (0..count).rev().find(changed)) {
+            let range = slot(first).start..slot(last).end;
+            uploads.write(device, &self.buffer, range.start as u64, &self.staged[range]);
+        }
+        self.uploaded.clone_from(&self.staged);
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, stats: &mut RenderStats, size: u64) -> Arc<wgpu::Buffer> {
+    Arc::new(stats.create_buffer(
+        device,
+        &wgpu::BufferDescriptor {
+            label: Some("Object Uniform Buffer"),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        },
+    ))
+}
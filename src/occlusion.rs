@@ -0,0 +1,224 @@
+// occlusion.rs
+
+use crate::bounds::Aabb;
+use crate::geometry::{GeometryArena, MeshAllocation};
+use crate::world::{GlobalTransform, Hidden, MeshHandle, Visible};
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+use hecs::World;
+
+// Side of the software depth buffer in texels. Coarse on purpose: it only has to tell whole objects apart
+const WIDTH: usize = 128;
+const HEIGHT: usize = 64;
+
+// Meshes with more triangles than this are only tested, never rasterized as occluders
+const MAX_OCCLUDER_TRIANGLES: usize = 2048;
+
+// Triangles rasterized per frame at most, largest occluders first
+const TRIANGLE_BUDGET: usize = 16384;
+
+// How far behind the occluders a mesh has to be, so one facing the camera isn't hidden by its own surface
+const DEPTH_BIAS: f32 = 1e-5;
+
+// Software hierarchical-Z occlusion culling. The biggest meshes the frustum kept, and the terrain, are drawn
+// into a small depth buffer on the CPU, which is reduced into a pyramid of the farthest depth under each texel.
+// A mesh whose bounds are entirely behind that depth, at the level where they cover at most 2x2 texels, is
+// hidden behind the occluders and skipped like one outside the view
+pub struct OcclusionCuller {
+    pub enabled: bool,
+    view_proj: Mat4,
+    levels: Vec<(usize, usize, Vec<f32>)>, // Width, height and depths, level 0 the full buffer
+}
+
+impl OcclusionCuller {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            view_proj: Mat4::IDENTITY,
+            levels: Vec::new(),
+        }
+    }
+
+    /// Culling system, run after the frustum `cull`: marks every visible mesh hidden behind the others, or
+    /// behind `ground` triangles, not visible. Returns how many were.
+    pub fn cull(
+        &mut self,
+        world: &mut World,
+        geometry: &GeometryArena,
+        ground: &[[Vec3; 3]],
+        view_proj: Mat4,
+    ) -> u32 {
+        if !self.enabled {
+            return 0;
+        }
+        self.view_proj = view_proj;
+        self.levels.clear();
+        self.levels.push((WIDTH, HEIGHT, vec![1.0; WIDTH * HEIGHT]));
+        for triangle in ground {
+            self.rasterize(triangle.map(|corner| view_proj * corner.extend(1.0)));
+        }
+        let mut occluders: Vec<(f32, MeshAllocation, Mat4)> = Vec::new();
+        let mut meshes = world.query::<(&MeshHandle, &GlobalTransform, &Visible)>().without::<&Hidden>();
+        for (mesh, global, visible) in meshes.iter() {
+            if let (true, Some(bounds)) = (visible.0, mesh.bounds) {
+                let area = self.screen_area(&bounds.transformed(global.0));
+                occluders.push((area, mesh.allocation.clone(), global.0));
+            }
+        }
+        drop(meshes);
+        occluders.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut budget = TRIANGLE_BUDGET;
+        for (_, mesh, model) in occluders {
+            let triangles = mesh.num_indices as usize / 3;
+            if triangles > MAX_OCCLUDER_TRIANGLES || triangles > budget {
+                continue;
+            }
+            budget -= triangles;
+            let (vertices, indices) = geometry.read(&mesh);
+            let transform = view_proj * model;
+            for triangle in indices.chunks_exact(3) {
+                let corner = |index: u16| transform * Vec3::from(vertices[index as usize].position).extend(1.0);
+                self.rasterize([corner(triangle[0]), corner(triangle[1]), corner(triangle[2])]);
+            }
+        }
+        self.build_pyramid();
+
+        let mut occluded = 0;
+        for (mesh, global, visible) in world.query_mut::<(&MeshHandle, &GlobalTransform, &mut Visible)>() {
+            if visible.0 && mesh.bounds.is_some_and(|bounds| self.is_occluded(&bounds.transformed(global.0))) {
+                visible.0 = false;
+                occluded += 1;
+            }
+        }
+        occluded
+    }
+
+    // Share of the depth buffer `bounds` covers, or the whole of it when they reach behind the camera
+    fn screen_area(&self, bounds: &Aabb) -> f32 {
+        match self.screen_rect(bounds) {
+            Some((min, max)) => ((max.x - min.x) * (max.y - min.y)).max(0.0),
+            None => f32::INFINITY,
+        }
+    }
+
+    // Texel-space rectangle `bounds` cover, with the nearest depth they reach in the minimum's z, or None if they
+    // reach behind the near plane
+    fn screen_rect(&self, bounds: &Aabb) -> Option<(Vec3, Vec3)> {
+        let mut min = Vec3::splat(f32::INFINITY);
+        let mut max = Vec3::splat(f32::NEG_INFINITY);
+        for corner in bounds.corners() {
+            let clip = self.view_proj * corner.extend(1.0);
+            if clip.w <= f32::EPSILON || clip.z < 0.0 {
+                return None;
+            }
+            let texel = to_texel(clip);
+            min = min.min(texel);
+            max = max.max(texel);
+        }
+        Some((min, max))
+    }
+
+    fn is_occluded(&self, bounds: &Aabb) -> bool {
+        let Some((min, max)) = self.screen_rect(bounds) else {
+            return false;
+        };
+        // Every texel the rectangle touches, clamped to the buffer. Off the buffer, it's outside the view anyway
+        let x0 = (min.x.floor().max(0.0) as usize).min(WIDTH - 1);
+        let y0 = (min.y.floor().max(0.0) as usize).min(HEIGHT - 1);
+        let x1 = (max.x.ceil().max(1.0) as usize - 1).clamp(x0, WIDTH - 1);
+        let y1 = (max.y.ceil().max(1.0) as usize - 1).clamp(y0, HEIGHT - 1);
+        let spans = |level: usize| (x1 >> level) - (x0 >> level) > 1 || (y1 >> level) - (y0 >> level) > 1;
+        let mut level = 0;
+        while level + 1 < self.levels.len() && spans(level) {
+            level += 1;
+        }
+        let (width, _, depths) = &self.levels[level];
+        let rows = (y0 >> level)..=(y1 >> level);
+        rows.flat_map(|y| ((x0 >> level)..=(x1 >> level)).map(move |x| y * width + x))
+            .all(|texel| depths[texel] < min.z - DEPTH_BIAS)
+    }
+
+    // Draws one clip-space triangle into level 0, keeping the nearest depth in each texel whose center it covers
+    fn rasterize(&mut self, triangle: [Vec4; 3]) {
+        let (polygon, count) = clip_near(triangle);
+        let texels = polygon.map(to_texel);
+        let depths = &mut self.levels[0].2;
+        for i in 1..count.saturating_sub(1) {
+            let [a, b, c] = [texels[0], texels[i], texels[i + 1]];
+            let area = edge(a, b, c);
+            if area.abs() <= f32::EPSILON {
+                continue;
+            }
+            let (min, max) = (a.min(b).min(c), a.max(b).max(c));
+            let (x0, x1) = (min.x.max(0.0) as usize, (max.x.ceil().max(0.0) as usize).min(WIDTH));
+            let (y0, y1) = (min.y.max(0.0) as usize, (max.y.ceil().max(0.0) as usize).min(HEIGHT));
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let center = Vec3::new(x as f32 + 0.5, y as f32 + 0.5, 0.0);
+                    // Either winding counts: an occluder seen from behind hides just as much
+                    let weights = Vec3::new(edge(b, c, center), edge(c, a, center), edge(a, b, center)) / area;
+                    if weights.min_element() < 0.0 {
+                        continue;
+                    }
+                    let depth = weights.dot(Vec3::new(a.z, b.z, c.z));
+                    let texel = &mut depths[y * WIDTH + x];
+                    *texel = texel.min(depth);
+                }
+            }
+        }
+    }
+
+    // Reduces level 0 into ever smaller levels, each texel the farthest of the up to 2x2 below it
+    fn build_pyramid(&mut self) {
+        loop {
+            let (width, height, depths) = self.levels.last().expect("level 0 is always there");
+            if *width == 1 && *height == 1 {
+                return;
+            }
+            let (next_width, next_height) = (width.div_ceil(2), height.div_ceil(2));
+            let mut next = vec![0.0f32; next_width * next_height];
+            for y in 0..*height {
+                for x in 0..*width {
+                    let texel = &mut next[(y / 2) * next_width + x / 2];
+                    *texel = texel.max(depths[y * width + x]);
+                }
+            }
+            self.levels.push((next_width, next_height, next));
+        }
+    }
+}
+
+impl Default for OcclusionCuller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Texel coordinates, Y down, and depth of a clip-space point in front of the camera
+fn to_texel(clip: Vec4) -> Vec3 {
+    let ndc = clip.xyz() / clip.w;
+    Vec3::new((ndc.x * 0.5 + 0.5) * WIDTH as f32, (0.5 - ndc.y * 0.5) * HEIGHT as f32, ndc.z)
+}
+
+// Twice the signed area of the triangle `a`, `b`, `c` in texel space
+fn edge(a: Vec3, b: Vec3, c: Vec3) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+// The part of a clip-space triangle in front of the near plane, z >= 0 with wgpu's depth range: a polygon of
+// up to four corners and how many there are
+fn clip_near(triangle: [Vec4; 3]) -> ([Vec4; 4], usize) {
+    let mut polygon = [Vec4::W; 4];
+    let mut count = 0;
+    for i in 0..3 {
+        let (from, to) = (triangle[i], triangle[(i + 1) % 3]);
+        if from.z >= 0.0 {
+            polygon[count] = from;
+            count += 1;
+        }
+        if (from.z >= 0.0) != (to.z >= 0.0) {
+            polygon[count] = from.lerp(to, from.z / (from.z - to.z));
+            count += 1;
+        }
+    }
+    (polygon, count)
+}
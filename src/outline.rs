@@ -0,0 +1,272 @@
+// outline.rs
+
+use crate::geometry::{GeometryArena, MeshAllocation};
+use crate::stats::RenderStats;
+use crate::upload::Uploads;
+use crate::vertex::Vertex;
+use bytemuck::{Pod, Zeroable};
+use egui_wgpu::wgpu;
+
+// Pixels between the object's silhouette and the outer edge of the outline
+const OUTLINE_WIDTH: f32 = 3.0;
+const MASK_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+// Laid out like `Outline` in outline.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct OutlineUniform {
+    color: [f32; 4],
+    width: f32,
+    _padding: [f32; 3],
+}
+
+// Mask of the selected object at the scene's resolution
+struct Mask {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+// Draws a silhouette outline around the selected object after the scene pass: the object's mesh goes into a
+// mask texture, then a fullscreen pass tints every pixel within a few pixels outside the mask
+pub struct SelectionOutline {
+    mask_pipeline: wgpu::RenderPipeline,
+    outline_pipeline: wgpu::RenderPipeline,
+    outline_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    uniform: OutlineUniform,
+    mask: Option<Mask>,
+}
+
+impl SelectionOutline {
+    /// `camera_layout` and `object_layout` are the scene's first two bind group layouts, so the mask pass
+    /// draws the mesh exactly where the scene pass did.
+    pub fn new(
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        format: wgpu::TextureFormat,
+        camera_layout: &wgpu::BindGroupLayout,
+        object_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Outline Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("outline.wgsl").into()),
+        });
+        let mask_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline Mask Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, object_layout],
+            push_constant_ranges: &[],
+        });
+        let mask_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Outline Mask Pipeline"),
+            layout: Some(&mask_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_mask",
+                buffers: &[Vertex::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_mask",
+                targets: &[Some(MASK_FORMAT.into())],
+                compilation_options: Default::default(),
+            }),
+            // Both sides, so the silhouette is whole whichever way the object faces
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let outline_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Outline Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline Pipeline Layout"),
+            bind_group_layouts: &[&outline_layout],
+            push_constant_ranges: &[],
+        });
+        let outline_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Outline Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_outline",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_outline",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let uniform = OutlineUniform {
+            color: [1.0; 4],
+            width: OUTLINE_WIDTH,
+            _padding: [0.0; 3],
+        };
+        let uniform_buffer = stats.create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Outline Uniform Buffer"),
+                contents: bytemuck::bytes_of(&uniform),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+        Self {
+            mask_pipeline,
+            outline_pipeline,
+            outline_layout,
+            uniform_buffer,
+            uniform,
+            mask: None,
+        }
+    }
+
+    /// Sets the outline color, as linear RGBA with unmultiplied alpha.
+    pub fn set_color(&mut self, device: &wgpu::Device, uploads: &mut Uploads, color: [f32; 4]) {
+        if self.uniform.color != color {
+            self.uniform.color = color;
+            uploads.write(device, &self.uniform_buffer, 0, bytemuck::bytes_of(&self.uniform));
+        }
+    }
+
+    // Makes the mask match the target the scene is drawn into
+    fn resize(&mut self, device: &wgpu::Device, stats: &mut RenderStats, (width, height): (u32, u32)) {
+        if let Some(mask) = &self.mask {
+            if (mask.texture.width(), mask.texture.height()) == (width, height) {
+                return;
+            }
+            stats.texture_destroyed(&mask.texture);
+        }
+        let texture = stats.create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Outline Mask Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: MASK_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Outline Bind Group"),
+            layout: &self.outline_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        self.mask = Some(Mask {
+            texture,
+            view,
+            bind_group,
+        });
+    }
+
+    /// Outlines `mesh`, drawn with the given camera and object bind groups, on `target` of `size`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        encoder: &mut wgpu::CommandEncoder,
+        geometry: &GeometryArena,
+        mesh: &MeshAllocation,
+        groups: &[(&wgpu::BindGroup, &[wgpu::DynamicOffset]); 2],
+        target: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        self.resize(device, stats, size);
+        let Some(mask) = &self.mask else {
+            return;
+        };
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Outline Mask Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &mask.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.mask_pipeline);
+            for (index, (bind_group, offsets)) in groups.iter().enumerate() {
+                pass.set_bind_group(index as u32, bind_group, offsets);
+            }
+            geometry.bind(&mut pass);
+            geometry.draw(&mut pass, mesh, 0..1);
+        }
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Outline Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.outline_pipeline);
+        pass.set_bind_group(0, &mask.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
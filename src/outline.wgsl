@@ -0,0 +1,68 @@
+// Silhouette outline around the selected object: its mesh is drawn into a mask, then every pixel just
+// outside the mask is tinted in the selection color
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct ModelUniform {
+    model: mat4x4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> model_uniform: ModelUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+@vertex
+fn vs_mask(in: VertexInput) -> @builtin(position) vec4<f32> {
+    return camera.view_proj * model_uniform.model * vec4<f32>(in.position, 1.0);
+}
+
+@fragment
+fn fs_mask() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0);
+}
+
+struct Outline {
+    color: vec4<f32>,
+    width: f32,
+};
+// Bound on their own in the outline pass; numbered apart from the mask pass's bindings above
+@group(0) @binding(2)
+var mask: texture_2d<f32>;
+@group(0) @binding(3)
+var<uniform> outline: Outline;
+
+// A single triangle covering the screen, so no vertex buffer is needed
+@vertex
+fn vs_outline(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+// Looks for the mask within `width` pixels along 16 directions at four radii
+@fragment
+fn fs_outline(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(mask));
+    let pixel = vec2<i32>(position.xy);
+    if textureLoad(mask, pixel, 0).r > 0.5 {
+        discard;
+    }
+    for (var ring = 1; ring <= 4; ring += 1) {
+        let radius = outline.width * f32(ring) / 4.0;
+        for (var i = 0; i < 16; i += 1) {
+            let angle = f32(i) * 0.39269908;
+            let offset = vec2<i32>(round(vec2<f32>(cos(angle), sin(angle)) * radius));
+            let probe = clamp(pixel + offset, vec2<i32>(0), size - 1);
+            if textureLoad(mask, probe, 0).r > 0.5 {
+                return outline.color;
+            }
+        }
+    }
+    discard;
+}
@@ -0,0 +1,152 @@
+// pacing.rs
+
+use egui_plot::{HLine, Line, Plot, PlotPoints};
+use std::collections::VecDeque;
+use std::time::Duration;
+use web_time::Instant;
+
+// Present intervals kept for the graph, a few seconds at common refresh rates
+const HISTORY: usize = 300;
+// Slack left before the vsync deadline for OS scheduling jitter
+const WAKE_MARGIN: Duration = Duration::from_millis(2);
+// A browser's one thread can't block, so frames there come as fast as the page's animation frames allow
+const CAN_SLEEP: bool = !cfg!(target_arch = "wasm32");
+// Weight of the newest sample in the smoothed frame work time
+const WORK_SMOOTHING: f32 = 0.1;
+
+// Measures present-to-present intervals against the display refresh and can delay the start of each frame
+// so input is read as late as possible while still making the next vsync
+pub struct FramePacer {
+    pub enabled: bool,
+    refresh: Option<Duration>, // Unknown when the platform doesn't report the monitor's rate
+    cap: Option<Duration>,     // Shortest time between frame starts when the frame rate is capped
+    frame_start: Option<Instant>,
+    last_present: Option<Instant>,
+    work: Duration,           // Smoothed time from frame start to present
+    intervals: VecDeque<f64>, // Milliseconds between consecutive presents
+    pub missed_vsyncs: u64,
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            refresh: None,
+            cap: None,
+            frame_start: None,
+            last_present: None,
+            work: Duration::ZERO,
+            intervals: VecDeque::with_capacity(HISTORY),
+            missed_vsyncs: 0,
+        }
+    }
+
+    pub fn set_refresh_rate(&mut self, millihertz: Option<u32>) {
+        self.refresh = millihertz
+            .filter(|mhz| *mhz > 0)
+            .map(|mhz| Duration::from_secs_f64(1000.0 / mhz as f64));
+    }
+
+    /// Holds the frame rate at `fps` by sleeping before frames that would start early, or lifts the cap.
+    pub fn set_fps_cap(&mut self, fps: Option<u32>) {
+        self.cap = fps.filter(|fps| *fps > 0).map(|fps| Duration::from_secs_f64(1.0 / fps as f64));
+    }
+
+    /// Call at the start of a frame. When pacing, sleeps until the latest start that still leaves the
+    /// usual frame work time before the next vsync. When capped, sleeps until a whole frame interval has
+    /// passed since the last frame started.
+    pub fn begin_frame(&mut self) {
+        // The first frame after a pause starts straight away
+        if let (Some(cap), Some(frame_start), Some(_)) = (self.cap, self.frame_start, self.last_present) {
+            let start = frame_start + cap;
+            let now = Instant::now();
+            if start > now && CAN_SLEEP {
+                puffin::profile_scope!("fps_cap");
+                std::thread::sleep(start - now);
+            }
+        }
+        if let (true, Some(refresh), Some(last_present)) = (self.enabled, self.refresh, self.last_present) {
+            let start = last_present + refresh.saturating_sub(self.work + WAKE_MARGIN);
+            let now = Instant::now();
+            if start > now && CAN_SLEEP {
+                puffin::profile_scope!("frame_pacing");
+                std::thread::sleep(start - now);
+            }
+        }
+        self.frame_start = Some(Instant::now());
+    }
+
+    /// Call right after presenting. `blocked` is how long acquiring the surface texture waited on the
+    /// display, which isn't work that pacing could move.
+    pub fn end_frame(&mut self, blocked: Duration) {
+        let now = Instant::now();
+        if let Some(start) = self.frame_start {
+            let work = (now - start).saturating_sub(blocked);
+            self.work = self.work.mul_f32(1.0 - WORK_SMOOTHING) + work.mul_f32(WORK_SMOOTHING);
+        }
+        if let Some(last_present) = self.last_present {
+            let interval = now - last_present;
+            if let Some(refresh) = self.refresh {
+                // An interval spanning several refreshes means the frames in between showed the old image
+                let refreshes = (interval.as_secs_f64() / refresh.as_secs_f64()).round() as u64;
+                self.missed_vsyncs += refreshes.saturating_sub(1);
+            }
+            if self.intervals.len() == HISTORY {
+                self.intervals.pop_front();
+            }
+            self.intervals.push_back(interval.as_secs_f64() * 1000.0);
+        }
+        self.last_present = Some(now);
+    }
+
+    /// Smoothed CPU time from frame start to present, not counting waits on the display.
+    pub fn work(&self) -> Duration {
+        self.work
+    }
+
+    /// Call after `end_frame` when the next frame is deliberately held back, as when throttled in the
+    /// background, so the gap isn't counted as missed vsyncs or paced against.
+    pub fn pause(&mut self) {
+        self.last_present = None;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Pace frames to the display").on_hover_text(
+            "Sleeps before each frame so it starts just in time for the next vsync, reducing input lag",
+        );
+        match self.refresh {
+            Some(refresh) => ui.label(format!("Display refresh: {:.1} Hz", 1.0 / refresh.as_secs_f64())),
+            None => ui.weak("Display refresh rate unknown; missed vsyncs are not counted"),
+        };
+        ui.horizontal(|ui| {
+            ui.label(format!("Missed vsyncs: {}", self.missed_vsyncs));
+            if ui.small_button("Reset").clicked() {
+                self.missed_vsyncs = 0;
+            }
+        });
+        ui.label(format!("Frame work: {:.2} ms", self.work.as_secs_f64() * 1000.0));
+
+        let points: PlotPoints = self.intervals.iter().enumerate().map(|(i, ms)| [i as f64, *ms]).collect();
+        Plot::new("frame_pacing")
+            .height(120.0)
+            .include_x(0.0)
+            .include_x(HISTORY as f64)
+            .include_y(0.0)
+            .y_axis_label("ms")
+            .allow_scroll(false)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(points).name("Present interval"));
+                if let Some(refresh) = self.refresh {
+                    plot_ui.hline(HLine::new(refresh.as_secs_f64() * 1000.0).name("Refresh"));
+                }
+            });
+    }
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,530 @@
+// particles.rs
+
+use crate::camera::Camera;
+use crate::stats::RenderStats;
+use crate::transform::Transform;
+use crate::upload::Uploads;
+use crate::vertex_layout::{vertex_layout, VertexLayout};
+use crate::world::{self, GlobalTransform};
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::Vec3;
+use hecs::{Entity, World};
+use serde::{Deserialize, Serialize};
+
+// Particles alive at once across all emitters; past this, new particles replace the oldest
+const MAX_PARTICLES: u32 = 32768;
+// Emitters simulated at once; others wait until one is removed or disabled
+const MAX_EMITTERS: usize = 16;
+const WORKGROUP_SIZE: u32 = 64; // Matches `simulate` in particles.wgsl
+// Depth over which particles fade out in front of the scene instead of cutting into it
+const FADE_DISTANCE: f32 = 0.25;
+
+// Emits particles from its entity's position along the entity's local up axis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParticleEmitter {
+    pub enabled: bool,
+    pub rate: f32,     // Particles per second
+    pub lifetime: f32, // Seconds
+    pub speed: f32,
+    pub spread: f32, // Half angle of the cone particles leave in, in degrees
+    pub size: f32,
+    pub gravity: f32,
+    pub start_color: [f32; 4], // Unmultiplied linear RGBA at birth, blended towards `end_color` at death
+    pub end_color: [f32; 4],
+    #[serde(skip)]
+    pending: f32, // Fraction of a particle carried over to the next frame
+}
+
+impl ParticleEmitter {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            rate: 200.0,
+            lifetime: 2.0,
+            speed: 1.5,
+            spread: 25.0,
+            size: 0.08,
+            gravity: 1.0,
+            start_color: [1.0, 0.8, 0.3, 1.0],
+            end_color: [0.8, 0.1, 0.0, 0.0],
+            pending: 0.0,
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Emitting");
+        ui.add(egui::Slider::new(&mut self.rate, 1.0..=5000.0).logarithmic(true).text("Rate (/s)"));
+        ui.add(egui::Slider::new(&mut self.lifetime, 0.1..=10.0).text("Lifetime (s)"));
+        ui.add(egui::Slider::new(&mut self.speed, 0.0..=10.0).text("Speed"));
+        ui.add(egui::Slider::new(&mut self.spread, 0.0..=180.0).text("Spread (deg)"));
+        ui.add(egui::Slider::new(&mut self.size, 0.01..=1.0).logarithmic(true).text("Size"));
+        ui.add(egui::Slider::new(&mut self.gravity, -10.0..=10.0).text("Gravity"));
+        ui.horizontal(|ui| {
+            ui.label("Color over life");
+            ui.color_edit_button_rgba_unmultiplied(&mut self.start_color);
+            ui.label("→");
+            ui.color_edit_button_rgba_unmultiplied(&mut self.end_color);
+        });
+    }
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Laid out like `Particle` in particles.wgsl. The pool is also the instance buffer the billboards are drawn from
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct Particle {
+    position: [f32; 3],
+    age: f32,
+    velocity: [f32; 3],
+    lifetime: f32,
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+    size: f32,
+    gravity: f32,
+    _padding: [f32; 2],
+}
+
+impl Particle {
+    // Locations must match `ParticleInput` in particles.wgsl
+    const LAYOUT: VertexLayout = vertex_layout!(Particle, Instance {
+        0 => position: Float32x3,
+        1 => age: Float32,
+        2 => lifetime: Float32,
+        3 => start_color: Float32x4,
+        4 => end_color: Float32x4,
+        5 => size: Float32,
+    });
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const LAYOUT: &VertexLayout = &Particle::LAYOUT;
+        LAYOUT.buffer_layout()
+    }
+}
+
+// Laid out like `Emitter` in particles.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+struct EmitterUniform {
+    origin: [f32; 3],
+    speed: f32,
+    direction: [f32; 3],
+    cos_spread: f32,
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+    lifetime: f32,
+    size: f32,
+    gravity: f32,
+    first: u32,
+    count: u32,
+    _padding: [u32; 3],
+}
+
+// Laid out like `Simulation` in particles.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+struct SimulationUniform {
+    dt: f32,
+    cursor: u32,
+    spawned: u32,
+    emitters: u32,
+    seed: u32,
+    _padding: [u32; 3],
+}
+
+// Laid out like `View` in particles.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct ViewUniform {
+    view_proj: [[f32; 4]; 4],
+    right: [f32; 4],
+    up: [f32; 4],
+    near: f32,
+    far: f32,
+    fade_distance: f32,
+    _padding: f32,
+}
+
+// Simulates every `ParticleEmitter`'s particles in a compute pass and draws them as camera-facing quads. The
+// CPU only decides how many particles each emitter spawns per frame; they live and move on the GPU, in one
+// ring-buffer pool where new particles take the oldest slots
+pub struct ParticleSystem {
+    simulate_pipeline: wgpu::ComputePipeline,
+    simulate_bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    view_layout: wgpu::BindGroupLayout,
+    view_bind_group: Option<(wgpu::BindGroup, (u32, u32))>, // Remade when the scene depth changes size
+    particle_buffer: wgpu::Buffer,
+    emitter_buffer: wgpu::Buffer,
+    simulation_buffer: wgpu::Buffer,
+    view_buffer: wgpu::Buffer,
+    cursor: u32,
+    seed: u32,
+    alive_for: f32, // Seconds until the last spawned particle dies
+    spawned: u32,   // Last frame
+    emitters: usize,
+}
+
+impl ParticleSystem {
+    pub fn new(device: &wgpu::Device, stats: &mut RenderStats, format: wgpu::TextureFormat) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("particles.wgsl").into()),
+        });
+
+        let particle_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Particle Buffer"),
+                size: (MAX_PARTICLES as usize * size_of::<Particle>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+                mapped_at_creation: false, // Zeroed, so every particle starts out dead
+            },
+        );
+        let emitter_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Particle Emitter Buffer"),
+                size: (MAX_EMITTERS * size_of::<EmitterUniform>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let simulation_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Particle Simulation Buffer"),
+                size: size_of::<SimulationUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let view_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Particle View Buffer"),
+                size: size_of::<ViewUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniform = |binding, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let simulate_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle Simulation Bind Group Layout"),
+            entries: &[storage(0, false), storage(1, true), uniform(2, wgpu::ShaderStages::COMPUTE)],
+        });
+        let simulate_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle Simulation Bind Group"),
+            layout: &simulate_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: emitter_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: simulation_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let simulate_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Simulation Pipeline Layout"),
+            bind_group_layouts: &[&simulate_layout],
+            push_constant_ranges: &[],
+        });
+        let simulate_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Particle Simulation Pipeline"),
+            layout: Some(&simulate_pipeline_layout),
+            module: &module,
+            entry_point: "simulate",
+            compilation_options: Default::default(),
+        });
+
+        let view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle View Bind Group Layout"),
+            entries: &[
+                uniform(0, wgpu::ShaderStages::VERTEX_FRAGMENT),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Render Pipeline Layout"),
+            bind_group_layouts: &[&view_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Particle Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[Particle::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            // Tested against the scene by hand in the fragment shader, which reads the depth as a texture
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            simulate_pipeline,
+            simulate_bind_group,
+            render_pipeline,
+            view_layout,
+            view_bind_group: None,
+            particle_buffer,
+            emitter_buffer,
+            simulation_buffer,
+            view_buffer,
+            cursor: 0,
+            seed: 0,
+            alive_for: 0.0,
+            spawned: 0,
+            emitters: 0,
+        }
+    }
+
+    /// Whether particles are alive or being spawned, so redraw-on-demand must keep drawing.
+    pub fn is_active(&self) -> bool {
+        self.alive_for > 0.0
+    }
+
+    /// Decides this frame's spawns from the world's emitters and records the simulation step into `encoder`.
+    /// Returns false, recording nothing, while no particle is alive.
+    pub fn simulate(
+        &mut self,
+        device: &wgpu::Device,
+        uploads: &mut Uploads,
+        world: &mut World,
+        dt: f32,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> bool {
+        let mut emitters = Vec::new();
+        let mut spawned = 0;
+        for (emitter, global) in world.query_mut::<(&mut ParticleEmitter, &GlobalTransform)>() {
+            if !emitter.enabled || emitters.len() == MAX_EMITTERS {
+                emitter.pending = 0.0;
+                continue;
+            }
+            let wanted = emitter.pending + emitter.rate * dt;
+            let count = (wanted.floor() as u32).min(MAX_PARTICLES - spawned);
+            emitter.pending = wanted.fract();
+            let (_, rotation, origin) = global.0.to_scale_rotation_translation();
+            emitters.push(EmitterUniform {
+                origin: origin.into(),
+                speed: emitter.speed,
+                direction: (rotation * Vec3::Y).normalize_or(Vec3::Y).into(),
+                cos_spread: emitter.spread.to_radians().cos(),
+                start_color: emitter.start_color,
+                end_color: emitter.end_color,
+                lifetime: emitter.lifetime,
+                size: emitter.size,
+                gravity: emitter.gravity,
+                first: spawned,
+                count,
+                _padding: [0; 3],
+            });
+            spawned += count;
+            self.alive_for = self.alive_for.max(emitter.lifetime);
+        }
+        self.emitters = emitters.len();
+        self.spawned = spawned;
+        self.alive_for = (self.alive_for - dt).max(0.0);
+        if !self.is_active() && spawned == 0 {
+            return false;
+        }
+
+        self.seed = self.seed.wrapping_add(0x9e37_79b9);
+        let simulation = SimulationUniform {
+            dt,
+            cursor: self.cursor,
+            spawned,
+            emitters: emitters.len() as u32,
+            seed: self.seed,
+            _padding: [0; 3],
+        };
+        self.cursor = (self.cursor + spawned) % MAX_PARTICLES;
+        if !emitters.is_empty() {
+            uploads.write(device, &self.emitter_buffer, 0, bytemuck::cast_slice(&emitters));
+        }
+        uploads.write(device, &self.simulation_buffer, 0, bytemuck::bytes_of(&simulation));
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Particle Simulation Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.simulate_pipeline);
+        pass.set_bind_group(0, &self.simulate_bind_group, &[]);
+        pass.dispatch_workgroups(MAX_PARTICLES.div_ceil(WORKGROUP_SIZE), 1, 1);
+        true
+    }
+
+    /// Draws the particles over `target` of `size`, fading them against the scene's `depth`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        let view = camera.view_matrix();
+        let uniform = ViewUniform {
+            view_proj: camera.view_projection_matrix().to_cols_array_2d(),
+            right: view.row(0).truncate().extend(0.0).into(),
+            up: view.row(1).truncate().extend(0.0).into(),
+            near: camera.znear,
+            far: camera.zfar,
+            fade_distance: FADE_DISTANCE,
+            _padding: 0.0,
+        };
+        uploads.write(device, &self.view_buffer, 0, bytemuck::bytes_of(&uniform));
+        if self.view_bind_group.as_ref().is_none_or(|(_, sized)| *sized != size) {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Particle View Bind Group"),
+                layout: &self.view_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.view_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(depth),
+                    },
+                ],
+            });
+            self.view_bind_group = Some((bind_group, size));
+        }
+        let Some((view_bind_group, _)) = &self.view_bind_group else {
+            return;
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Particle Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.render_pipeline);
+        pass.set_bind_group(0, view_bind_group, &[]);
+        pass.set_vertex_buffer(0, self.particle_buffer.slice(..));
+        pass.draw(0..4, 0..MAX_PARTICLES);
+        stats.record_draw(6, MAX_PARTICLES); // Two triangles a quad
+    }
+
+    pub fn show(&mut self, ctx: &Context, world: &mut World) {
+        egui::Window::new("Particles")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                let mut emitters: Vec<Entity> = world
+                    .query::<(Entity, &ParticleEmitter)>()
+                    .iter()
+                    .map(|(entity, _)| entity)
+                    .collect();
+                emitters.sort();
+                ui.label(format!(
+                    "{} of {} emitters simulated, {} particles spawned last frame, pool of {MAX_PARTICLES}",
+                    self.emitters,
+                    emitters.len(),
+                    self.spawned
+                ));
+                if ui.button("Add emitter").clicked() {
+                    let transform = Transform::IDENTITY;
+                    world.spawn((transform, GlobalTransform(transform.matrix()), ParticleEmitter::new()));
+                }
+                let mut removed = None;
+                for (index, &entity) in emitters.iter().enumerate() {
+                    egui::CollapsingHeader::new(format!("Emitter {}", index + 1))
+                        .id_source(entity)
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Position");
+                                let mut transform = world::transform_mut(world, entity);
+                                for axis in 0..3 {
+                                    ui.add(egui::DragValue::new(&mut transform.translation[axis]).speed(0.01));
+                                }
+                            });
+                            if let Ok(mut emitter) = world.get::<&mut ParticleEmitter>(entity) {
+                                emitter.ui(ui);
+                            }
+                            if ui.button("Remove").clicked() {
+                                removed = Some(entity);
+                            }
+                        });
+                }
+                if let Some(entity) = removed {
+                    let _ = world.despawn(entity);
+                }
+            });
+    }
+}
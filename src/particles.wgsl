@@ -0,0 +1,167 @@
+// GPU particles: `simulate` spawns and moves them in a compute pass, `vs_main`/`fs_main` draw each live one as a
+// camera-facing quad that fades out where it meets the scene
+
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    lifetime: f32, // Dead once `age` reaches it; a zeroed particle is dead
+    start_color: vec4<f32>,
+    end_color: vec4<f32>,
+    size: f32,
+    gravity: f32,
+};
+
+struct Emitter {
+    origin: vec3<f32>,
+    speed: f32,
+    direction: vec3<f32>,
+    cos_spread: f32, // Cosine of the cone's half angle around `direction`
+    start_color: vec4<f32>,
+    end_color: vec4<f32>,
+    lifetime: f32,
+    size: f32,
+    gravity: f32,
+    first: u32, // This frame's spawns [first, first + count) belong to the emitter
+    count: u32,
+};
+
+struct Simulation {
+    dt: f32,
+    cursor: u32,  // Pool slot of the frame's first spawn; spawns overwrite the oldest particles
+    spawned: u32,
+    emitters: u32,
+    seed: u32,
+};
+
+@group(0) @binding(0)
+var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1)
+var<storage, read> emitters: array<Emitter>;
+@group(0) @binding(2)
+var<uniform> simulation: Simulation;
+
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Uniform in 0..1, from a state advanced on every call
+fn random(state: ptr<function, u32>) -> f32 {
+    *state = hash(*state);
+    return f32(*state) / 4294967295.0;
+}
+
+fn spawn(index: u32, emitter: Emitter) -> Particle {
+    var state = hash(index ^ simulation.seed);
+    // Uniform over the spherical cap around the emitter's direction
+    let cos_theta = 1.0 - random(&state) * (1.0 - emitter.cos_spread);
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = random(&state) * 6.2831853;
+    let helper = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(emitter.direction.y) > 0.9);
+    let tangent = normalize(cross(helper, emitter.direction));
+    let bitangent = cross(emitter.direction, tangent);
+    let direction = emitter.direction * cos_theta + (tangent * cos(phi) + bitangent * sin(phi)) * sin_theta;
+
+    var particle: Particle;
+    particle.velocity = direction * emitter.speed;
+    // Spread over the frame instead of leaving the emitter in one clump per frame
+    particle.age = random(&state) * simulation.dt;
+    particle.position = emitter.origin + particle.velocity * particle.age;
+    particle.lifetime = emitter.lifetime;
+    particle.start_color = emitter.start_color;
+    particle.end_color = emitter.end_color;
+    particle.size = emitter.size;
+    particle.gravity = emitter.gravity;
+    return particle;
+}
+
+@compute @workgroup_size(64)
+fn simulate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let count = arrayLength(&particles);
+    let index = id.x;
+    if index >= count {
+        return;
+    }
+    let ordinal = (index + count - simulation.cursor) % count;
+    if ordinal < simulation.spawned {
+        for (var i = 0u; i < simulation.emitters; i += 1u) {
+            let emitter = emitters[i];
+            if ordinal >= emitter.first && ordinal < emitter.first + emitter.count {
+                particles[index] = spawn(index, emitter);
+                return;
+            }
+        }
+    }
+    var particle = particles[index];
+    if particle.age >= particle.lifetime {
+        return;
+    }
+    particle.age += simulation.dt;
+    particle.velocity.y -= particle.gravity * simulation.dt;
+    particle.position += particle.velocity * simulation.dt;
+    particles[index] = particle;
+}
+
+struct View {
+    view_proj: mat4x4<f32>,
+    right: vec4<f32>, // Camera axes in world space, to face the quads towards it
+    up: vec4<f32>,
+    near: f32,
+    far: f32,
+    fade_distance: f32, // Depth over which a particle fades out in front of the scene
+};
+
+@group(0) @binding(0)
+var<uniform> view: View;
+// Bound as a plain float texture, as GL can't load texels from a depth texture
+@group(0) @binding(1)
+var scene_depth: texture_2d<f32>;
+
+struct ParticleInput {
+    @location(0) position: vec3<f32>,
+    @location(1) age: f32,
+    @location(2) lifetime: f32,
+    @location(3) start_color: vec4<f32>,
+    @location(4) end_color: vec4<f32>,
+    @location(5) size: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) corner: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, particle: ParticleInput) -> VertexOutput {
+    var out: VertexOutput;
+    if particle.age >= particle.lifetime {
+        out.clip_position = vec4<f32>(2.0, 2.0, 2.0, 1.0); // Outside the clip volume
+        return out;
+    }
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u)) * 2.0 - 1.0;
+    let offset = (view.right.xyz * corner.x + view.up.xyz * corner.y) * particle.size * 0.5;
+    out.clip_position = view.view_proj * vec4<f32>(particle.position + offset, 1.0);
+    out.color = mix(particle.start_color, particle.end_color, particle.age / particle.lifetime);
+    out.corner = corner;
+    return out;
+}
+
+// Distance from the camera of a depth buffer value
+fn linear_depth(depth: f32) -> f32 {
+    return view.near * view.far / (view.far - depth * (view.far - view.near));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let radius = length(in.corner);
+    let behind = linear_depth(textureLoad(scene_depth, vec2<i32>(in.clip_position.xy), 0).r);
+    let fade = clamp((behind - linear_depth(in.clip_position.z)) / view.fade_distance, 0.0, 1.0);
+    let alpha = in.color.a * (1.0 - smoothstep(0.5, 1.0, radius)) * fade;
+    if alpha <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, alpha);
+}
@@ -0,0 +1,629 @@
+// physics.rs
+
+use crate::animation::{FIXED_STEP, MAX_STEPS};
+use crate::bounds::Aabb;
+use crate::debug;
+use crate::transform::Transform;
+use crate::world::{self, GlobalTransform, MeshHandle, Parent};
+use egui::{Color32, Context};
+use glam::{Mat4, Quat, Vec3};
+use hecs::{Entity, World};
+use rapier3d::na::{Quaternion, Translation3, UnitQuaternion};
+use rapier3d::prelude as rapier;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// How far a body may drift from the pose last synced with its entity before an edit to the entity counts as
+// moving it, rather than as rounding in the matrix round trip
+const POSE_EPSILON: f32 = 1e-4;
+
+// How the simulation moves an entity's body
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BodyKind {
+    Dynamic,   // Moved by gravity and contacts, which write its `Transform` back
+    Fixed,     // Never moves on its own; follows its `Transform` when edited
+    Kinematic, // Follows its `Transform`, pushing dynamic bodies out of the way
+}
+
+impl BodyKind {
+    pub const ALL: [BodyKind; 3] = [BodyKind::Dynamic, BodyKind::Fixed, BodyKind::Kinematic];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BodyKind::Dynamic => "Dynamic",
+            BodyKind::Fixed => "Fixed",
+            BodyKind::Kinematic => "Kinematic",
+        }
+    }
+}
+
+// Simulates the entity, whose `Collider` gives it its shape. Bodies follow the entity's world transform
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RigidBody {
+    pub kind: BodyKind,
+}
+
+// Collision shape in the entity's local space, scaled with the entity. Without a `RigidBody`, a static
+// collider for level geometry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Collider {
+    pub shape: ColliderShape,
+    pub offset: Vec3, // Of the shape's center from the entity's origin
+    pub friction: f32,
+    pub restitution: f32, // Bounciness, 0 to 1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ColliderShape {
+    Cuboid { half_extents: Vec3 },
+    Ball { radius: f32 },
+    TriMesh { vertices: Vec<Vec3>, indices: Vec<[u32; 3]> },
+}
+
+impl ColliderShape {
+    fn label(&self) -> &'static str {
+        match self {
+            ColliderShape::Cuboid { .. } => "Box",
+            ColliderShape::Ball { .. } => "Ball",
+            ColliderShape::TriMesh { .. } => "Triangle mesh",
+        }
+    }
+
+    // None for a triangle mesh without triangles, which rapier can't build
+    fn build(&self, scale: Vec3) -> Option<rapier::SharedShape> {
+        match self {
+            ColliderShape::Cuboid { half_extents } => {
+                let half_extents = (*half_extents * scale).abs();
+                Some(rapier::SharedShape::cuboid(half_extents.x, half_extents.y, half_extents.z))
+            }
+            ColliderShape::Ball { radius } => Some(rapier::SharedShape::ball(radius * scale.abs().max_element())),
+            ColliderShape::TriMesh { vertices, indices } => {
+                let in_range = indices.iter().flatten().all(|&index| (index as usize) < vertices.len());
+                if indices.is_empty() || !in_range {
+                    return None;
+                }
+                let vertices = vertices
+                    .iter()
+                    .map(|vertex| {
+                        let vertex = *vertex * scale;
+                        rapier::Point::new(vertex.x, vertex.y, vertex.z)
+                    })
+                    .collect();
+                Some(rapier::SharedShape::trimesh(vertices, indices.clone()))
+            }
+        }
+    }
+}
+
+impl Collider {
+    pub fn new(shape: ColliderShape) -> Self {
+        Self {
+            shape,
+            offset: Vec3::ZERO,
+            friction: 0.5,
+            restitution: 0.2,
+        }
+    }
+
+    /// A box filling `bounds`, given in the entity's local space.
+    pub fn cuboid_from_bounds(bounds: Aabb) -> Self {
+        Self {
+            offset: bounds.center(),
+            ..Self::new(ColliderShape::Cuboid {
+                half_extents: (bounds.size() * 0.5).max(Vec3::splat(0.01)),
+            })
+        }
+    }
+
+    /// A ball around `bounds`, given in the entity's local space.
+    pub fn ball_from_bounds(bounds: Aabb) -> Self {
+        Self {
+            offset: bounds.center(),
+            ..Self::new(ColliderShape::Ball {
+                radius: bounds.radius().max(0.01),
+            })
+        }
+    }
+
+    /// A static triangle mesh, for level geometry that other bodies land on and collide with.
+    pub fn trimesh(vertices: Vec<Vec3>, indices: Vec<[u32; 3]>) -> Self {
+        Self::new(ColliderShape::TriMesh { vertices, indices })
+    }
+}
+
+// What an entity was last added to the simulation with, to tell when it must be rebuilt or moved
+#[derive(Clone)]
+struct Registered {
+    body: Option<rapier::RigidBodyHandle>,
+    collider: rapier::ColliderHandle,
+    kind: Option<BodyKind>,
+    shape: Collider,
+    scale: Vec3,
+    pose: (Vec3, Quat), // World pose last read from or written to the entity
+}
+
+// The simulation as it stood at one moment, for session replay to rewind to. The pipeline and the debug renderer
+// keep nothing between steps, so they are left out
+#[derive(Clone)]
+pub struct PhysicsSnapshot {
+    running: bool,
+    accumulator: f32,
+    awake: bool,
+    entities: HashMap<Entity, Registered>,
+    ground_collider: Option<(rapier::ColliderHandle, f32)>,
+    islands: rapier::IslandManager,
+    broad_phase: rapier::DefaultBroadPhase,
+    narrow_phase: rapier::NarrowPhase,
+    bodies: rapier::RigidBodySet,
+    colliders: rapier::ColliderSet,
+    impulse_joints: rapier::ImpulseJointSet,
+    multibody_joints: rapier::MultibodyJointSet,
+    ccd: rapier::CCDSolver,
+}
+
+// Steps a rapier world in fixed steps, mirroring entities with a `Collider` and writing the poses of dynamic
+// bodies back to their `Transform`
+pub struct Physics {
+    pub running: bool,
+    pub debug_draw: bool,
+    pub gravity: Vec3,
+    pub ground: bool, // Fixed plane at `ground_height` for bodies to land on
+    pub ground_height: f32,
+    accumulator: f32, // Frame time not yet taken as a fixed step
+    awake: bool,      // Whether a dynamic body was still moving after the last step
+    step_once: bool,  // Requested from the panel while paused
+    entities: HashMap<Entity, Registered>,
+    ground_collider: Option<(rapier::ColliderHandle, f32)>,
+    pipeline: rapier::PhysicsPipeline,
+    integration: rapier::IntegrationParameters,
+    islands: rapier::IslandManager,
+    broad_phase: rapier::DefaultBroadPhase,
+    narrow_phase: rapier::NarrowPhase,
+    bodies: rapier::RigidBodySet,
+    colliders: rapier::ColliderSet,
+    impulse_joints: rapier::ImpulseJointSet,
+    multibody_joints: rapier::MultibodyJointSet,
+    ccd: rapier::CCDSolver,
+    debug_render: rapier::DebugRenderPipeline,
+}
+
+impl Physics {
+    pub fn new() -> Self {
+        Self {
+            running: false,
+            debug_draw: false,
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            ground: true,
+            ground_height: -1.0,
+            accumulator: 0.0,
+            awake: false,
+            step_once: false,
+            entities: HashMap::new(),
+            ground_collider: None,
+            pipeline: rapier::PhysicsPipeline::new(),
+            integration: rapier::IntegrationParameters {
+                dt: FIXED_STEP,
+                ..Default::default()
+            },
+            islands: rapier::IslandManager::new(),
+            broad_phase: rapier::DefaultBroadPhase::new(),
+            narrow_phase: rapier::NarrowPhase::new(),
+            bodies: rapier::RigidBodySet::new(),
+            colliders: rapier::ColliderSet::new(),
+            impulse_joints: rapier::ImpulseJointSet::new(),
+            multibody_joints: rapier::MultibodyJointSet::new(),
+            ccd: rapier::CCDSolver::new(),
+            debug_render: rapier::DebugRenderPipeline::new(
+                rapier::DebugRenderStyle::default(),
+                rapier::DebugRenderMode::COLLIDER_SHAPES,
+            ),
+        }
+    }
+
+    /// Whether dynamic bodies are moving, so redraw-on-demand must keep drawing.
+    pub fn is_active(&self) -> bool {
+        self.running && self.awake
+    }
+
+    /// Fixed update: brings the simulation in line with the world's colliders, then advances it by whole steps
+    /// of frame time and writes the dynamic bodies' poses back to their entities.
+    pub fn update(&mut self, dt: f32, world: &mut World) {
+        self.sync(world);
+        let mut steps = 0;
+        if self.running {
+            self.accumulator = (self.accumulator + dt).min(FIXED_STEP * MAX_STEPS);
+            while self.accumulator >= FIXED_STEP {
+                self.accumulator -= FIXED_STEP;
+                steps += 1;
+            }
+        } else if std::mem::take(&mut self.step_once) {
+            steps = 1;
+        }
+        if steps == 0 {
+            return;
+        }
+        let gravity = rapier::Vector::new(self.gravity.x, self.gravity.y, self.gravity.z);
+        for _ in 0..steps {
+            self.pipeline.step(
+                &gravity,
+                &self.integration,
+                &mut self.islands,
+                &mut self.broad_phase,
+                &mut self.narrow_phase,
+                &mut self.bodies,
+                &mut self.colliders,
+                &mut self.impulse_joints,
+                &mut self.multibody_joints,
+                &mut self.ccd,
+                None,
+                &(),
+                &(),
+            );
+        }
+        self.awake = false;
+        for (&entity, registered) in &mut self.entities {
+            let Some(body) = registered.body.and_then(|handle| self.bodies.get(handle)) else {
+                continue;
+            };
+            if !body.is_dynamic() || body.is_sleeping() {
+                continue;
+            }
+            self.awake = true;
+            let pose = from_isometry(body.position());
+            if pose_eq(pose, registered.pose) {
+                continue;
+            }
+            registered.pose = pose;
+            write_pose(world, entity, pose);
+        }
+    }
+
+    // Adds, rebuilds, moves and removes bodies and colliders to match the entities that have a `Collider`
+    fn sync(&mut self, world: &mut World) {
+        self.sync_ground();
+        let mut seen = HashSet::new();
+        let mut query = world.query::<(Entity, &Collider, Option<&RigidBody>, &GlobalTransform)>();
+        for (entity, collider, body, global) in query.iter() {
+            seen.insert(entity);
+            let (scale, rotation, translation) = global.0.to_scale_rotation_translation();
+            let pose = (translation, rotation);
+            let kind = body.map(|body| body.kind);
+            if let Some(registered) = self.entities.get_mut(&entity) {
+                let unchanged = registered.kind == kind
+                    && registered.shape == *collider
+                    && registered.scale.abs_diff_eq(scale, POSE_EPSILON);
+                if unchanged {
+                    if !pose_eq(pose, registered.pose) {
+                        registered.pose = pose;
+                        self.teleport(entity, pose);
+                    }
+                    continue;
+                }
+            }
+            self.remove(entity);
+            let Some(shape) = collider.shape.build(scale) else {
+                log::warn!("Collider of {entity:?} has no valid triangles; leaving it out of the simulation");
+                continue;
+            };
+            let offset = collider.offset * scale;
+            let built = rapier::ColliderBuilder::new(shape)
+                .translation(rapier::Vector::new(offset.x, offset.y, offset.z))
+                .friction(collider.friction)
+                .restitution(collider.restitution);
+            let (body, collider_handle) = match kind {
+                Some(kind) => {
+                    let builder = match kind {
+                        BodyKind::Dynamic => rapier::RigidBodyBuilder::dynamic(),
+                        BodyKind::Fixed => rapier::RigidBodyBuilder::fixed(),
+                        BodyKind::Kinematic => rapier::RigidBodyBuilder::kinematic_position_based(),
+                    };
+                    let body = self.bodies.insert(builder.position(to_isometry(pose)));
+                    let collider = self.colliders.insert_with_parent(built, body, &mut self.bodies);
+                    (Some(body), collider)
+                }
+                None => (None, self.colliders.insert(built.position(to_isometry(pose)))),
+            };
+            self.entities.insert(
+                entity,
+                Registered {
+                    body,
+                    collider: collider_handle,
+                    kind,
+                    shape: collider.clone(),
+                    scale,
+                    pose,
+                },
+            );
+        }
+        drop(query);
+        let removed: Vec<Entity> = self.entities.keys().filter(|entity| !seen.contains(entity)).copied().collect();
+        for entity in removed {
+            self.remove(entity);
+        }
+    }
+
+    fn sync_ground(&mut self) {
+        let wanted = self.ground.then_some(self.ground_height);
+        if self.ground_collider.map(|(_, height)| height) == wanted {
+            return;
+        }
+        if let Some((handle, _)) = self.ground_collider.take() {
+            self.colliders.remove(handle, &mut self.islands, &mut self.bodies, true);
+        }
+        if let Some(height) = wanted {
+            let ground = rapier::ColliderBuilder::halfspace(rapier::Vector::y_axis())
+                .translation(rapier::Vector::new(0.0, height, 0.0))
+                .friction(0.7);
+            self.ground_collider = Some((self.colliders.insert(ground), height));
+        }
+    }
+
+    // Moves the entity's body or collider to where the entity was put, as by the gizmo or undo
+    fn teleport(&mut self, entity: Entity, pose: (Vec3, Quat)) {
+        let Some(registered) = self.entities.get(&entity) else {
+            return;
+        };
+        let isometry = to_isometry(pose);
+        match registered.body.and_then(|handle| self.bodies.get_mut(handle)) {
+            Some(body) if body.is_kinematic() => body.set_next_kinematic_position(isometry),
+            Some(body) => {
+                body.set_position(isometry, true);
+                body.set_linvel(rapier::Vector::zeros(), true);
+                body.set_angvel(rapier::Vector::zeros(), true);
+            }
+            None => {
+                if let Some(collider) = self.colliders.get_mut(registered.collider) {
+                    collider.set_position(isometry);
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        let Some(registered) = self.entities.remove(&entity) else {
+            return;
+        };
+        match registered.body {
+            Some(body) => {
+                self.bodies.remove(
+                    body,
+                    &mut self.islands,
+                    &mut self.colliders,
+                    &mut self.impulse_joints,
+                    &mut self.multibody_joints,
+                    true,
+                );
+            }
+            None => {
+                self.colliders.remove(registered.collider, &mut self.islands, &mut self.bodies, true);
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> PhysicsSnapshot {
+        PhysicsSnapshot {
+            running: self.running,
+            accumulator: self.accumulator,
+            awake: self.awake,
+            entities: self.entities.clone(),
+            ground_collider: self.ground_collider,
+            islands: self.islands.clone(),
+            broad_phase: self.broad_phase.clone(),
+            narrow_phase: self.narrow_phase.clone(),
+            bodies: self.bodies.clone(),
+            colliders: self.colliders.clone(),
+            impulse_joints: self.impulse_joints.clone(),
+            multibody_joints: self.multibody_joints.clone(),
+            ccd: self.ccd.clone(),
+        }
+    }
+
+    /// Puts the simulation back as it was, velocities and contacts included. Entities that were despawned and
+    /// spawned again since have new ids, so the next update drops their old bodies and adds them at rest.
+    pub fn restore(&mut self, snapshot: &PhysicsSnapshot) {
+        let snapshot = snapshot.clone();
+        self.running = snapshot.running;
+        self.accumulator = snapshot.accumulator;
+        self.awake = snapshot.awake;
+        self.step_once = false;
+        self.entities = snapshot.entities;
+        self.ground_collider = snapshot.ground_collider;
+        self.islands = snapshot.islands;
+        self.broad_phase = snapshot.broad_phase;
+        self.narrow_phase = snapshot.narrow_phase;
+        self.bodies = snapshot.bodies;
+        self.colliders = snapshot.colliders;
+        self.impulse_joints = snapshot.impulse_joints;
+        self.multibody_joints = snapshot.multibody_joints;
+        self.ccd = snapshot.ccd;
+    }
+
+    /// Queues debug lines outlining every collider, dimmed while its body sleeps.
+    pub fn draw_debug(&mut self) {
+        if !self.debug_draw {
+            return;
+        }
+        self.debug_render.render(
+            &mut DebugLines,
+            &self.bodies,
+            &self.colliders,
+            &self.impulse_joints,
+            &self.multibody_joints,
+            &self.narrow_phase,
+        );
+    }
+
+    pub fn show(&mut self, ctx: &Context, world: &mut World, object: Entity) {
+        egui::Window::new("Physics")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let label = if self.running { "⏸ Pause" } else { "▶ Simulate" };
+                    if ui.button(label).clicked() {
+                        self.running = !self.running;
+                        self.accumulator = 0.0;
+                    }
+                    if ui.add_enabled(!self.running, egui::Button::new("Step")).clicked() {
+                        self.step_once = true;
+                    }
+                });
+                ui.checkbox(&mut self.debug_draw, "Draw colliders");
+                ui.horizontal(|ui| {
+                    ui.label("Gravity");
+                    for axis in 0..3 {
+                        ui.add(egui::DragValue::new(&mut self.gravity[axis]).speed(0.1));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.ground, "Ground at");
+                    ui.add_enabled(self.ground, egui::DragValue::new(&mut self.ground_height).speed(0.05));
+                });
+                ui.label(format!("{} bodies, {} colliders", self.bodies.len(), self.colliders.len()));
+
+                ui.separator();
+                ui.strong("Object");
+                self.object_ui(ui, world, object);
+            });
+    }
+
+    // Body and collider of the object the UI edits
+    fn object_ui(&mut self, ui: &mut egui::Ui, world: &mut World, object: Entity) {
+        let bounds = world
+            .get::<&MeshHandle>(object)
+            .ok()
+            .and_then(|mesh| mesh.bounds)
+            .unwrap_or(Aabb::new(Vec3::splat(-0.5), Vec3::splat(0.5)));
+        let mut kind = world.get::<&RigidBody>(object).ok().map(|body| body.kind);
+        let previous = kind;
+        egui::ComboBox::from_label("Body")
+            .selected_text(kind.map_or("None", |kind| kind.label()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut kind, None, "None");
+                for option in BodyKind::ALL {
+                    ui.selectable_value(&mut kind, Some(option), option.label());
+                }
+            });
+        if kind != previous {
+            match kind {
+                Some(kind) => {
+                    let _ = world.insert_one(object, RigidBody { kind });
+                    if world.get::<&Collider>(object).is_err() {
+                        let _ = world.insert_one(object, Collider::cuboid_from_bounds(bounds));
+                    }
+                }
+                None => {
+                    let _ = world.remove_one::<RigidBody>(object);
+                    let _ = world.remove_one::<Collider>(object);
+                }
+            }
+        }
+
+        let Ok(mut collider) = world.get::<&mut Collider>(object) else {
+            return;
+        };
+        let mut shape = collider.shape.label();
+        egui::ComboBox::from_label("Shape").selected_text(shape).show_ui(ui, |ui| {
+            ui.selectable_value(&mut shape, "Box", "Box");
+            ui.selectable_value(&mut shape, "Ball", "Ball");
+        });
+        if shape != collider.shape.label() {
+            let fitted = match shape {
+                "Ball" => Collider::ball_from_bounds(bounds),
+                _ => Collider::cuboid_from_bounds(bounds),
+            };
+            collider.shape = fitted.shape;
+            collider.offset = fitted.offset;
+        }
+        if ui.button("Fit to mesh").on_hover_text("Resize the shape to the mesh's current bounds").clicked() {
+            let fitted = match collider.shape {
+                ColliderShape::Ball { .. } => Collider::ball_from_bounds(bounds),
+                _ => Collider::cuboid_from_bounds(bounds),
+            };
+            collider.shape = fitted.shape;
+            collider.offset = fitted.offset;
+        }
+        ui.add(egui::Slider::new(&mut collider.friction, 0.0..=2.0).text("Friction"));
+        ui.add(egui::Slider::new(&mut collider.restitution, 0.0..=1.0).text("Restitution"));
+    }
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Hands Rapier's debug lines to the debug line renderer
+struct DebugLines;
+
+impl rapier::DebugRenderBackend for DebugLines {
+    fn draw_line(
+        &mut self,
+        _object: rapier::DebugRenderObject,
+        a: rapier::Point<f32>,
+        b: rapier::Point<f32>,
+        color: [f32; 4],
+    ) {
+        debug::line(Vec3::new(a.x, a.y, a.z), Vec3::new(b.x, b.y, b.z), hsla_to_color(color));
+    }
+}
+
+// Rapier's debug colors are hue in degrees, then saturation, lightness and alpha from 0 to 1
+fn hsla_to_color([hue, saturation, lightness, alpha]: [f32; 4]) -> Color32 {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = (hue / 60.0).rem_euclid(6.0);
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    Color32::from_rgba_unmultiplied(
+        ((r + m) * 255.0) as u8,
+        ((g + m) * 255.0) as u8,
+        ((b + m) * 255.0) as u8,
+        (alpha * 255.0) as u8,
+    )
+}
+
+fn to_isometry((translation, rotation): (Vec3, Quat)) -> rapier::Isometry<f32> {
+    rapier::Isometry::from_parts(
+        Translation3::new(translation.x, translation.y, translation.z),
+        UnitQuaternion::from_quaternion(Quaternion::new(rotation.w, rotation.x, rotation.y, rotation.z)),
+    )
+}
+
+fn from_isometry(isometry: &rapier::Isometry<f32>) -> (Vec3, Quat) {
+    let translation = isometry.translation.vector;
+    let rotation = isometry.rotation.coords; // x, y, z, w
+    (
+        Vec3::new(translation.x, translation.y, translation.z),
+        Quat::from_xyzw(rotation.x, rotation.y, rotation.z, rotation.w),
+    )
+}
+
+fn pose_eq(a: (Vec3, Quat), b: (Vec3, Quat)) -> bool {
+    a.0.abs_diff_eq(b.0, POSE_EPSILON) && a.1.dot(b.1).abs() > 1.0 - POSE_EPSILON
+}
+
+// Puts the entity at a world pose, keeping its scale, through its parent's matrix if it has one
+fn write_pose(world: &World, entity: Entity, (translation, rotation): (Vec3, Quat)) {
+    let parent = world.get::<&Parent>(entity).ok().map(|parent| parent.0);
+    let local = match parent {
+        Some(parent) => {
+            let matrix = world::world_matrix(world, parent).inverse()
+                * Mat4::from_rotation_translation(rotation, translation);
+            let (_, rotation, translation) = matrix.to_scale_rotation_translation();
+            (translation, rotation)
+        }
+        None => (translation, rotation),
+    };
+    if let Ok(mut transform) = world.get::<&mut Transform>(entity) {
+        (transform.translation, transform.rotation) = local;
+    }
+}
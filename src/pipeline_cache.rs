@@ -0,0 +1,65 @@
+// pipeline_cache.rs
+
+use egui_wgpu::wgpu;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Everything a scene pipeline is built from that can change while the app runs. The layout, depth and blend
+// state follow from the label, which names one pipeline the app builds
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub label: &'static str,
+    pub program: u64, // The shader program with the vertex layout, from `pipelines::program_key`
+    pub format: wgpu::TextureFormat,
+    pub samples: u32,
+    pub polygon_mode: wgpu::PolygonMode,
+}
+
+/// Scene pipelines by what they were built from, shared with the workers compiling them. Switching back to a
+/// sample count or polygon mode drawn with before reuses its pipelines instead of compiling them again.
+pub struct PipelineCache {
+    pipelines: Mutex<HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self {
+            pipelines: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The pipeline for `key`, built with `create` if there isn't one. The lock isn't held while building, so
+    /// pipelines with different keys compile side by side.
+    pub fn get_or_create(
+        &self,
+        key: PipelineKey,
+        create: impl FnOnce() -> wgpu::RenderPipeline,
+    ) -> Arc<wgpu::RenderPipeline> {
+        if let Some(pipeline) = self.pipelines.lock().unwrap().get(&key) {
+            return pipeline.clone();
+        }
+        let pipeline = Arc::new(create());
+        log::debug!("Cached {} ({:?}, {}x, {:?})", key.label, key.format, key.samples, key.polygon_mode);
+        self.pipelines.lock().unwrap().entry(key).or_insert(pipeline).clone()
+    }
+
+    /// Forgets every pipeline labeled `label`, such as once its shader has been edited. Ones still drawing are
+    /// kept alive by their users until replaced.
+    pub fn invalidate(&self, label: &str) {
+        self.pipelines.lock().unwrap().retain(|key, _| key.label != label);
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for PipelineCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,314 @@
+// pipelines.rs
+
+use crate::depth::DepthConfig;
+use crate::jobs::{self, Job, JobState, Priority};
+use crate::pipeline_cache::{PipelineCache, PipelineKey};
+use crate::shader_cache::ShaderCache;
+use crate::shader_manifest::ShaderProgram;
+use crate::vertex::Vertex;
+use egui_wgpu::wgpu;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use web_time::Instant;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PipelineStatus {
+    Compiling,
+    Ready(Duration), // How long compilation took
+    Failed,          // The worker panicked or the pipeline raised validation errors when drawn; see the log
+}
+
+// How the scene's triangles are rasterized
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RenderMode {
+    Filled,
+    Wireframe, // Edges only; needs `POLYGON_MODE_LINE`
+    Points,    // Vertices only; needs `POLYGON_MODE_POINT`
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 3] = [RenderMode::Filled, RenderMode::Wireframe, RenderMode::Points];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RenderMode::Filled => "Filled",
+            RenderMode::Wireframe => "Wireframe",
+            RenderMode::Points => "Points",
+        }
+    }
+
+    pub fn polygon_mode(&self) -> wgpu::PolygonMode {
+        match self {
+            RenderMode::Filled => wgpu::PolygonMode::Fill,
+            RenderMode::Wireframe => wgpu::PolygonMode::Line,
+            RenderMode::Points => wgpu::PolygonMode::Point,
+        }
+    }
+
+    /// Whether a device with `features` can draw in this mode.
+    pub fn is_supported(&self, features: wgpu::Features) -> bool {
+        match self {
+            RenderMode::Filled => true,
+            RenderMode::Wireframe => features.contains(wgpu::Features::POLYGON_MODE_LINE),
+            RenderMode::Points => features.contains(wgpu::Features::POLYGON_MODE_POINT),
+        }
+    }
+}
+
+/// Identifies `program` together with the vertex layout it's drawn with.
+pub fn program_key(program: &ShaderProgram) -> u64 {
+    let layout_desc = format!("{:?}", Vertex::LAYOUT.attributes());
+    program.cache_key(layout_desc.as_bytes())
+}
+
+/// Builds a scene pipeline from `program`, drawing `samples` samples per pixel and rasterizing triangles with
+/// `polygon_mode`, which needs the matching device feature unless it is `Fill`. The vertex layout check is
+/// skipped for programs `cache` has seen pass it before.
+#[allow(clippy::too_many_arguments)]
+pub fn create_scene_pipeline(
+    device: &wgpu::Device,
+    cache: &ShaderCache,
+    label: &str,
+    program: &ShaderProgram,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    constants: &HashMap<String, f64>,
+    samples: u32,
+    polygon_mode: wgpu::PolygonMode,
+) -> wgpu::RenderPipeline {
+    let key = program_key(program);
+    if !cache.contains(key) {
+        let checked = program
+            .vertex_module()
+            .and_then(|module| Vertex::LAYOUT.check_module(&module, &program.vertex_entry));
+        match checked {
+            Ok(()) => cache.insert(key),
+            Err(err) => log::error!("{label} does not match the vertex layout: {err}"),
+        }
+    }
+    let (vertex_module, fragment_module) = program.create_modules(device, label);
+    let compilation_options = wgpu::PipelineCompilationOptions {
+        constants, // Pipeline-overridable constants
+        zero_initialize_workgroup_memory: true,
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &vertex_module,
+            entry_point: &program.vertex_entry,
+            buffers: &[Vertex::desc()],
+            compilation_options: compilation_options.clone(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: fragment_module.as_ref().unwrap_or(&vertex_module),
+            entry_point: &program.fragment_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent::REPLACE,
+                    alpha: wgpu::BlendComponent::REPLACE,
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options,
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(DepthConfig::OPAQUE.state()),
+        multisample: wgpu::MultisampleState {
+            count: samples,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+// A scene pipeline and, while MSAA is on, its multisampled twin for the scene pass. Probes, eyes and stereo
+// views draw single-sampled whatever the setting
+struct Compiled {
+    pipeline: Arc<wgpu::RenderPipeline>,
+    multisampled: Option<(Arc<wgpu::RenderPipeline>, u32)>, // With its sample count
+}
+
+// What a scene pipeline is built from; everything but the sample count and polygon mode is shared with the
+// worker building it
+#[derive(Clone)]
+struct Source {
+    label: &'static str,
+    device: Arc<wgpu::Device>,
+    cache: Arc<ShaderCache>,
+    pipelines: Arc<PipelineCache>,
+    program: Arc<ShaderProgram>,
+    layout: Arc<wgpu::PipelineLayout>,
+    format: wgpu::TextureFormat,
+    constants: Arc<HashMap<String, f64>>,
+    samples: u32,
+    polygon_mode: wgpu::PolygonMode,
+}
+
+impl Source {
+    fn compile(&self) -> Job<(Compiled, Duration)> {
+        let source = self.clone();
+        jobs::run(Priority::High, move |_| {
+            let start = Instant::now();
+            let program = program_key(&source.program);
+            let create = |samples| {
+                let key = PipelineKey {
+                    label: source.label,
+                    program,
+                    format: source.format,
+                    samples,
+                    polygon_mode: source.polygon_mode,
+                };
+                source.pipelines.get_or_create(key, || {
+                    let Source { device, cache, label, program, layout, constants, .. } = &source;
+                    let (format, mode) = (source.format, source.polygon_mode);
+                    create_scene_pipeline(device, cache, label, program, layout, format, constants, samples, mode)
+                })
+            };
+            let compiled = Compiled {
+                pipeline: create(1),
+                multisampled: (source.samples > 1).then(|| (create(source.samples), source.samples)),
+            };
+            (compiled, start.elapsed())
+        })
+    }
+}
+
+// A scene pipeline compiled on the job pool, so building it never holds up a frame. It keeps what it was built
+// from, so a new shader, sample count or polygon mode only has to name what changed
+pub struct PendingPipeline {
+    pub label: &'static str,
+    source: Source,
+    job: Job<(Compiled, Duration)>,
+    compiled: Option<Compiled>,
+    status: PipelineStatus,
+}
+
+impl PendingPipeline {
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn(
+        device: Arc<wgpu::Device>,
+        cache: Arc<ShaderCache>,
+        pipelines: Arc<PipelineCache>,
+        label: &'static str,
+        program: Arc<ShaderProgram>,
+        layout: Arc<wgpu::PipelineLayout>,
+        format: wgpu::TextureFormat,
+        constants: HashMap<String, f64>,
+        samples: u32,
+    ) -> Self {
+        let source = Source {
+            label,
+            device,
+            cache,
+            pipelines,
+            program,
+            layout,
+            format,
+            constants: Arc::new(constants),
+            samples,
+            polygon_mode: wgpu::PolygonMode::Fill,
+        };
+        Self {
+            label,
+            job: source.compile(),
+            source,
+            compiled: None,
+            status: PipelineStatus::Compiling,
+        }
+    }
+
+    /// Compiles `program` in place of the current one, which keeps drawing until the new pipeline is ready.
+    /// The cached pipelines of the old program are dropped, since nothing switches back to them.
+    pub fn respawn(&mut self, program: Arc<ShaderProgram>) {
+        self.source.pipelines.invalidate(self.label);
+        self.source.program = program;
+        self.recompile();
+    }
+
+    /// Compiles the multisampled twin for `samples` samples per pixel, if that changed, or takes it from the
+    /// cache. Until it is ready, `get_multisampled` has nothing for the new count.
+    pub fn set_samples(&mut self, samples: u32) {
+        if samples != self.source.samples {
+            self.source.samples = samples;
+            self.recompile();
+        }
+    }
+
+    /// Compiles the pipelines again to rasterize with `polygon_mode`, if that changed. The current ones keep
+    /// drawing until then.
+    pub fn set_polygon_mode(&mut self, polygon_mode: wgpu::PolygonMode) {
+        if polygon_mode != self.source.polygon_mode {
+            self.source.polygon_mode = polygon_mode;
+            self.recompile();
+        }
+    }
+
+    fn recompile(&mut self) {
+        self.job.cancel();
+        self.job = self.source.compile();
+        self.status = PipelineStatus::Compiling;
+    }
+
+    /// Picks up the pipeline if the worker has finished. Returns true on the call it becomes available.
+    pub fn poll(&mut self) -> bool {
+        if self.status != PipelineStatus::Compiling {
+            return false;
+        }
+        match self.job.poll() {
+            JobState::Done((compiled, elapsed)) => {
+                log::info!("Compiled {} in {:.1} ms", self.label, elapsed.as_secs_f64() * 1000.0);
+                self.compiled = Some(compiled);
+                self.status = PipelineStatus::Ready(elapsed);
+                true
+            }
+            JobState::Running => false,
+            JobState::Lost => {
+                log::error!("Compiling {} failed; keeping the fallback pipeline", self.label);
+                self.status = PipelineStatus::Failed;
+                false
+            }
+        }
+    }
+
+    /// Drops a pipeline that raised GPU errors when drawn, so the fallback takes over.
+    pub fn fail(&mut self) {
+        self.compiled = None;
+        self.status = PipelineStatus::Failed;
+    }
+
+    pub fn get(&self) -> Option<&wgpu::RenderPipeline> {
+        self.compiled.as_ref().map(|compiled| &*compiled.pipeline)
+    }
+
+    /// The pipeline for a pass of `samples` samples per pixel, if one that many is ready.
+    pub fn get_multisampled(&self, samples: u32) -> Option<&wgpu::RenderPipeline> {
+        if samples == 1 {
+            return self.get();
+        }
+        let (pipeline, count) = self.compiled.as_ref()?.multisampled.as_ref()?;
+        (*count == samples).then_some(&**pipeline)
+    }
+
+    pub fn status(&self) -> PipelineStatus {
+        self.status
+    }
+}
+
+// A pipeline dropped while still queued, such as on exit, is never compiled
+impl Drop for PendingPipeline {
+    fn drop(&mut self) {
+        self.job.cancel();
+    }
+}
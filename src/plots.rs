@@ -0,0 +1,110 @@
+// plots.rs
+
+use egui::Context;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use web_time::Instant;
+
+const MAX_SAMPLES: usize = 2000;
+
+static SERIES: Mutex<BTreeMap<String, VecDeque<[f64; 2]>>> = Mutex::new(BTreeMap::new());
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// Records `value` under the series `name`, timestamped with the seconds since the first sample.
+/// Callable from any thread; the Plots panel charts every series recorded this way.
+pub fn debug_plot(name: &str, value: f64) {
+    let time = START.get_or_init(Instant::now).elapsed().as_secs_f64();
+    let mut series = SERIES.lock().unwrap_or_else(|e| e.into_inner());
+    let samples = match series.get_mut(name) {
+        Some(samples) => samples,
+        None => series.entry(name.to_string()).or_default(),
+    };
+    if samples.len() == MAX_SAMPLES {
+        samples.pop_front();
+    }
+    samples.push_back([time, value]);
+}
+
+// Chart of every `debug_plot` series over a sliding time window
+pub struct PlotPanel {
+    pub window_secs: f64,
+    pub paused: bool,
+    hidden: BTreeSet<String>,
+}
+
+impl PlotPanel {
+    pub fn new() -> Self {
+        Self {
+            window_secs: 10.0,
+            paused: false,
+            hidden: BTreeSet::new(),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        egui::Window::new("Plots")
+            .default_open(false)
+            .default_size([480.0, 260.0])
+            .show(ctx, |ui| {
+                // Copy out under the lock, like the console, so recording never waits on drawing
+                let series: Vec<(String, Vec<[f64; 2]>)> = SERIES
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .iter()
+                    .map(|(name, samples)| (name.clone(), samples.iter().copied().collect()))
+                    .collect();
+                let now = START.get_or_init(Instant::now).elapsed().as_secs_f64();
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.paused, "Pause");
+                    ui.add(egui::Slider::new(&mut self.window_secs, 1.0..=60.0).text("seconds"));
+                    if ui.button("Clear").clicked() {
+                        SERIES.lock().unwrap_or_else(|e| e.into_inner()).clear();
+                    }
+                });
+                ui.horizontal_wrapped(|ui| {
+                    for (name, _) in &series {
+                        let mut visible = !self.hidden.contains(name);
+                        if ui.checkbox(&mut visible, name).changed() {
+                            if visible {
+                                self.hidden.remove(name);
+                            } else {
+                                self.hidden.insert(name.clone());
+                            }
+                        }
+                    }
+                });
+                if series.is_empty() {
+                    ui.weak("Nothing recorded yet; call debug_plot(name, value)");
+                    return;
+                }
+
+                let mut plot = Plot::new("debug_plots")
+                    .legend(Legend::default())
+                    .x_axis_label("s")
+                    .allow_scroll(false);
+                if !self.paused {
+                    plot = plot.include_x(now - self.window_secs).include_x(now);
+                }
+                plot.show(ui, |plot_ui| {
+                    for (name, samples) in series {
+                        if self.hidden.contains(&name) {
+                            continue;
+                        }
+                        let visible: Vec<[f64; 2]> = samples
+                            .into_iter()
+                            .filter(|[t, _]| self.paused || *t >= now - self.window_secs)
+                            .collect();
+                        plot_ui.line(Line::new(PlotPoints::from(visible)).name(name));
+                    }
+                });
+            });
+    }
+}
+
+impl Default for PlotPanel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
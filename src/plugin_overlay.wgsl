@@ -0,0 +1,25 @@
+// Plugin overlays: put in front of each plugin's fragment shader, which defines
+// `@fragment fn fs_main(in: OverlayVertex) -> @location(0) vec4<f32>` and is blended over the scene
+
+struct Overlay {
+    resolution: vec2<f32>, // Of the scene target, in pixels
+    time: f32, // Seconds since the plugins were loaded
+    _padding: f32,
+};
+@group(0) @binding(0)
+var<uniform> overlay: Overlay;
+
+struct OverlayVertex {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>, // 0 at the top left of the target to 1 at the bottom right
+};
+
+// One triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> OverlayVertex {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: OverlayVertex;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
@@ -0,0 +1,538 @@
+// plugins.rs
+
+use crate::application::AppContext;
+use crate::stats::RenderStats;
+use crate::transform::Transform;
+use crate::upload::Uploads;
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::{Quat, Vec3};
+use hecs::Entity;
+#[cfg(not(target_arch = "wasm32"))]
+use libloading::Library;
+use std::ffi::{c_char, c_void, CStr};
+use std::path::{Path, PathBuf};
+use web_time::Instant;
+
+/// Bumped whenever anything below that plugins see changes; plugins built against another version are refused.
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+// Every plugin exports both: `extern "C" fn() -> u32` returning the `PLUGIN_API_VERSION` it was built against,
+// and `extern "C" fn(*const PluginRegistrar)` registering what it adds
+pub const VERSION_SYMBOL: &str = "winit_egui_wgpu_plugin_api_version";
+pub const REGISTER_SYMBOL: &str = "winit_egui_wgpu_plugin_register";
+
+// Browsers can't load native libraries, so no plugin is ever loaded there
+#[cfg(target_arch = "wasm32")]
+enum Library {}
+
+// Where plugin libraries are picked up from at startup, besides the ones `--plugin <path>` names
+const PLUGIN_DIR: &str = "plugins";
+
+// Draws a panel's contents through `api`, which `ui` is only valid for during the call
+pub type PanelFn = unsafe extern "C" fn(user: *mut c_void, ui: *mut c_void, api: *const PluginUiApi);
+
+// Runs once a frame after the application's update, with the seconds since the last frame
+pub type SystemFn = unsafe extern "C" fn(user: *mut c_void, scene: *mut c_void, api: *const PluginSceneApi, dt: f32);
+
+// Handed to a plugin's register function, which calls back into it for everything it adds. `user` pointers
+// are given back unchanged to the callbacks and must stay valid for as long as the process runs, since
+// plugins are never unloaded
+#[repr(C)]
+pub struct PluginRegistrar {
+    pub host: *mut c_void,
+    pub register_panel:
+        unsafe extern "C" fn(host: *mut c_void, title: *const c_char, panel: PanelFn, user: *mut c_void),
+    pub register_system: unsafe extern "C" fn(host: *mut c_void, system: SystemFn, user: *mut c_void),
+    // `wgsl` defines `fs_main` after plugin_overlay.wgsl, which is put in front of it
+    pub register_overlay: unsafe extern "C" fn(host: *mut c_void, label: *const c_char, wgsl: *const c_char),
+}
+
+// Widgets a panel can add; each returns whether the user changed or clicked it
+#[repr(C)]
+pub struct PluginUiApi {
+    pub label: unsafe extern "C" fn(ui: *mut c_void, text: *const c_char),
+    pub button: unsafe extern "C" fn(ui: *mut c_void, text: *const c_char) -> bool,
+    pub checkbox: unsafe extern "C" fn(ui: *mut c_void, text: *const c_char, value: *mut bool) -> bool,
+    pub slider: unsafe extern "C" fn(ui: *mut c_void, text: *const c_char, value: *mut f32, min: f32, max: f32) -> bool,
+    pub separator: unsafe extern "C" fn(ui: *mut c_void),
+}
+
+// What a system can read and change in the scene. Entities are passed as their `Entity::to_bits`
+#[repr(C)]
+pub struct PluginSceneApi {
+    pub object: unsafe extern "C" fn(scene: *mut c_void) -> u64,
+    pub get_transform: unsafe extern "C" fn(scene: *mut c_void, entity: u64, transform: *mut PluginTransform) -> bool,
+    pub set_transform: unsafe extern "C" fn(scene: *mut c_void, entity: u64, transform: *const PluginTransform) -> bool,
+    pub get_camera: unsafe extern "C" fn(scene: *mut c_void, position: *mut [f32; 3], target: *mut [f32; 3]),
+    pub set_camera: unsafe extern "C" fn(scene: *mut c_void, position: *const [f32; 3], target: *const [f32; 3]),
+    pub request_redraw: unsafe extern "C" fn(scene: *mut c_void),
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PluginTransform {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4], // Quaternion as x, y, z, w
+    pub scale: [f32; 3],
+}
+
+impl From<Transform> for PluginTransform {
+    fn from(transform: Transform) -> Self {
+        Self {
+            translation: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+            scale: transform.scale.to_array(),
+        }
+    }
+}
+
+impl From<PluginTransform> for Transform {
+    fn from(transform: PluginTransform) -> Self {
+        Self {
+            translation: Vec3::from(transform.translation),
+            rotation: Quat::from_array(transform.rotation).normalize(),
+            scale: Vec3::from(transform.scale),
+        }
+    }
+}
+
+struct Panel {
+    title: String,
+    draw: PanelFn,
+    user: *mut c_void,
+    open: bool,
+}
+
+struct System {
+    run: SystemFn,
+    user: *mut c_void,
+}
+
+// What one plugin registered, gathered while its register function runs
+#[derive(Default)]
+struct Registrations {
+    panels: Vec<Panel>,
+    systems: Vec<System>,
+    overlays: Vec<(String, String)>, // Label and fragment source
+}
+
+struct LoadedPlugin {
+    path: PathBuf,
+    registrations: Registrations,
+    _library: Library, // Last, so the callbacks above never outlive the code they point into
+}
+
+// Plugin libraries loaded at startup, with the panels, systems and overlays they registered
+pub struct PluginHost {
+    plugins: Vec<LoadedPlugin>,
+    errors: Vec<String>, // Libraries that could not be loaded, kept for the Plugins window
+}
+
+impl PluginHost {
+    /// Loads every library in the plugins directory and every one named after `--plugin`. Failures are
+    /// logged and listed in the Plugins window; they are also returned, to be shown as toasts.
+    pub fn load(args: &[String]) -> (Self, Vec<String>) {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(PLUGIN_DIR)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == std::env::consts::DLL_EXTENSION))
+            .collect();
+        paths.sort();
+        paths.extend(args.windows(2).filter(|pair| pair[0] == "--plugin").map(|pair| PathBuf::from(&pair[1])));
+
+        let mut host = Self {
+            plugins: Vec::new(),
+            errors: Vec::new(),
+        };
+        for path in paths {
+            match load_plugin(&path) {
+                Ok(plugin) => {
+                    log::info!("Loaded plugin {}", path.display());
+                    host.plugins.push(plugin);
+                }
+                Err(err) => {
+                    log::warn!("{err}");
+                    host.errors.push(err);
+                }
+            }
+        }
+        let errors = host.errors.clone();
+        (host, errors)
+    }
+
+    /// The overlays every plugin registered, as labels and fragment sources.
+    pub fn overlays(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| &plugin.registrations.overlays)
+            .map(|(label, source)| (label.as_str(), source.as_str()))
+    }
+
+    /// Runs every plugin's systems.
+    pub fn update(&mut self, context: &mut AppContext, dt: f32) {
+        let scene = (context as *mut AppContext).cast::<c_void>();
+        for system in self.plugins.iter().flat_map(|plugin| &plugin.registrations.systems) {
+            // SAFETY: the plugin's library is loaded for as long as `self`, and `scene` outlives the call
+            unsafe { (system.run)(system.user, scene, &SCENE_API, dt) };
+        }
+    }
+
+    /// Shows the Plugins window and every panel that is open.
+    pub fn show(&mut self, ctx: &Context) {
+        egui::Window::new("Plugins")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                if self.plugins.is_empty() && self.errors.is_empty() {
+                    ui.label(format!("No plugins loaded; put them in {PLUGIN_DIR}/ or pass --plugin <path>"));
+                }
+                for plugin in &mut self.plugins {
+                    ui.strong(plugin.path.display().to_string());
+                    let registrations = &mut plugin.registrations;
+                    for panel in &mut registrations.panels {
+                        ui.checkbox(&mut panel.open, &panel.title);
+                    }
+                    ui.label(format!(
+                        "{} system(s), {} overlay(s)",
+                        registrations.systems.len(),
+                        registrations.overlays.len()
+                    ));
+                    ui.separator();
+                }
+                for error in &self.errors {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            });
+
+        for panel in self.plugins.iter_mut().flat_map(|plugin| &mut plugin.registrations.panels) {
+            let (draw, user) = (panel.draw, panel.user);
+            egui::Window::new(&panel.title).open(&mut panel.open).resizable(false).show(ctx, |ui| {
+                let ui = (ui as *mut egui::Ui).cast::<c_void>();
+                // SAFETY: as for systems; `ui` is only used during the call
+                unsafe { draw(user, ui, &UI_API) };
+            });
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn load_plugin(path: &Path) -> Result<LoadedPlugin, String> {
+    Err(format!("Failed to load plugin {}: plugins can't be loaded in a browser", path.display()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_plugin(path: &Path) -> Result<LoadedPlugin, String> {
+    let failed = |err: libloading::Error| format!("Failed to load plugin {}: {err}", path.display());
+    // SAFETY: loading runs the library's initializers; plugins are trusted as much as the host itself
+    let library = unsafe { Library::new(path) }.map_err(failed)?;
+    // SAFETY: the symbols are declared with these signatures by every plugin
+    let version = unsafe { library.get::<unsafe extern "C" fn() -> u32>(VERSION_SYMBOL.as_bytes()) }.map_err(failed)?;
+    let version = unsafe { version() };
+    if version != PLUGIN_API_VERSION {
+        return Err(format!(
+            "Plugin {} was built for plugin API {version}, but this build has {PLUGIN_API_VERSION}",
+            path.display()
+        ));
+    }
+    let register = unsafe { library.get::<unsafe extern "C" fn(*const PluginRegistrar)>(REGISTER_SYMBOL.as_bytes()) }
+        .map_err(failed)?;
+    let mut registrations = Registrations::default();
+    let registrar = PluginRegistrar {
+        host: (&mut registrations as *mut Registrations).cast(),
+        register_panel,
+        register_system,
+        register_overlay,
+    };
+    unsafe { register(&registrar) };
+    Ok(LoadedPlugin {
+        path: path.to_path_buf(),
+        registrations,
+        _library: library,
+    })
+}
+
+// Text a plugin passed in, or nothing for a null pointer
+unsafe fn text(text: *const c_char) -> String {
+    if text.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(text).to_string_lossy().into_owned()
+}
+
+unsafe extern "C" fn register_panel(host: *mut c_void, title: *const c_char, draw: PanelFn, user: *mut c_void) {
+    let registrations = &mut *host.cast::<Registrations>();
+    registrations.panels.push(Panel {
+        title: text(title),
+        draw,
+        user,
+        open: true,
+    });
+}
+
+unsafe extern "C" fn register_system(host: *mut c_void, run: SystemFn, user: *mut c_void) {
+    let registrations = &mut *host.cast::<Registrations>();
+    registrations.systems.push(System { run, user });
+}
+
+unsafe extern "C" fn register_overlay(host: *mut c_void, label: *const c_char, wgsl: *const c_char) {
+    let registrations = &mut *host.cast::<Registrations>();
+    registrations.overlays.push((text(label), text(wgsl)));
+}
+
+const UI_API: PluginUiApi = PluginUiApi {
+    label: ui_label,
+    button: ui_button,
+    checkbox: ui_checkbox,
+    slider: ui_slider,
+    separator: ui_separator,
+};
+
+unsafe extern "C" fn ui_label(ui: *mut c_void, label: *const c_char) {
+    (*ui.cast::<egui::Ui>()).label(text(label));
+}
+
+unsafe extern "C" fn ui_button(ui: *mut c_void, label: *const c_char) -> bool {
+    (*ui.cast::<egui::Ui>()).button(text(label)).clicked()
+}
+
+unsafe extern "C" fn ui_checkbox(ui: *mut c_void, label: *const c_char, value: *mut bool) -> bool {
+    (*ui.cast::<egui::Ui>()).checkbox(&mut *value, text(label)).changed()
+}
+
+unsafe extern "C" fn ui_slider(ui: *mut c_void, label: *const c_char, value: *mut f32, min: f32, max: f32) -> bool {
+    let slider = egui::Slider::new(&mut *value, min..=max).text(text(label));
+    (*ui.cast::<egui::Ui>()).add(slider).changed()
+}
+
+unsafe extern "C" fn ui_separator(ui: *mut c_void) {
+    (*ui.cast::<egui::Ui>()).separator();
+}
+
+const SCENE_API: PluginSceneApi = PluginSceneApi {
+    object: scene_object,
+    get_transform: scene_get_transform,
+    set_transform: scene_set_transform,
+    get_camera: scene_get_camera,
+    set_camera: scene_set_camera,
+    request_redraw: scene_request_redraw,
+};
+
+unsafe fn scene<'a>(scene: *mut c_void) -> &'a mut AppContext<'a> {
+    &mut *scene.cast::<AppContext>()
+}
+
+unsafe extern "C" fn scene_object(context: *mut c_void) -> u64 {
+    scene(context).object.to_bits().get()
+}
+
+unsafe extern "C" fn scene_get_transform(context: *mut c_void, entity: u64, out: *mut PluginTransform) -> bool {
+    let Some(entity) = Entity::from_bits(entity) else {
+        return false;
+    };
+    let Ok(transform) = scene(context).world.get::<&Transform>(entity) else {
+        return false;
+    };
+    *out = PluginTransform::from(*transform);
+    true
+}
+
+unsafe extern "C" fn scene_set_transform(context: *mut c_void, entity: u64, transform: *const PluginTransform) -> bool {
+    let context = scene(context);
+    let Some(entity) = Entity::from_bits(entity) else {
+        return false;
+    };
+    let Ok(mut current) = context.world.get::<&mut Transform>(entity) else {
+        return false;
+    };
+    *current = Transform::from(*transform);
+    drop(current);
+    context.request_redraw();
+    true
+}
+
+unsafe extern "C" fn scene_get_camera(context: *mut c_void, position: *mut [f32; 3], target: *mut [f32; 3]) {
+    let camera = &scene(context).camera;
+    *position = camera.position.to_array();
+    *target = camera.target.to_array();
+}
+
+unsafe extern "C" fn scene_set_camera(context: *mut c_void, position: *const [f32; 3], target: *const [f32; 3]) {
+    let camera = &mut scene(context).camera;
+    camera.position = Vec3::from(*position);
+    camera.target = Vec3::from(*target);
+}
+
+unsafe extern "C" fn scene_request_redraw(context: *mut c_void) {
+    scene(context).request_redraw();
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct OverlayUniform {
+    resolution: [f32; 2],
+    time: f32,
+    _padding: f32,
+}
+
+// The plugins' overlays, each a fullscreen pass blended over the scene
+pub struct PluginOverlays {
+    pipelines: Vec<wgpu::RenderPipeline>,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    started: Instant,
+}
+
+impl PluginOverlays {
+    /// Builds a pipeline for each overlay `host` has. Overlays that fail to compile are left out, and their
+    /// errors returned.
+    pub fn new(
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        format: wgpu::TextureFormat,
+        host: &PluginHost,
+    ) -> (Self, Vec<String>) {
+        let uniform_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Plugin Overlay Uniform Buffer"),
+                size: size_of::<OverlayUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Plugin Overlay Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Plugin Overlay Bind Group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Plugin Overlay Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let mut pipelines = Vec::new();
+        let mut errors = Vec::new();
+        for (label, fragment) in host.overlays() {
+            let source = format!("{}\n{fragment}", include_str!("plugin_overlay.wgsl"));
+            // Checked here first, since wgpu would only report a bad shader as a device error
+            if let Err(err) = validate_fragment(&source) {
+                let err = format!("Plugin overlay {label:?} does not compile: {err}");
+                log::warn!("{err}");
+                errors.push(err);
+                continue;
+            }
+            let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+            pipelines.push(device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            }));
+        }
+        let overlays = Self {
+            pipelines,
+            uniform_buffer,
+            bind_group,
+            started: Instant::now(),
+        };
+        (overlays, errors)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    /// Blends every overlay over `target`, which is `size` pixels.
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        let uniform = OverlayUniform {
+            resolution: [size.0 as f32, size.1 as f32],
+            time: self.started.elapsed().as_secs_f32(),
+            _padding: 0.0,
+        };
+        uploads.write(device, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Plugin Overlay Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        for pipeline in &self.pipelines {
+            pass.set_pipeline(pipeline);
+            pass.draw(0..3, 0..1);
+            stats.record_draw(3, 1);
+        }
+    }
+}
+
+// Parses and validates a fullscreen shader's full source, such as an overlay's, and checks it has `fs_main`
+pub(crate) fn validate_fragment(source: &str) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| e.emit_to_string(source))?;
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(&module)
+        .map_err(|e| e.emit_to_string(source))?;
+    let has_fragment =
+        module.entry_points.iter().any(|entry| entry.name == "fs_main" && entry.stage == naga::ShaderStage::Fragment);
+    if !has_fragment {
+        return Err("no @fragment fn fs_main".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+// Custom post-processing passes: put in front of each pass's fragment shader, which defines
+// `@fragment fn fs_main(in: PostVertex) -> @location(0) vec4<f32>` and writes every pixel of the HDR scene
+
+@group(0) @binding(0)
+var input: texture_2d<f32>; // The HDR scene as the passes before left it
+@group(0) @binding(1)
+var input_sampler: sampler;
+
+struct PostVertex {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>, // 0 at the top left of the target to 1 at the bottom right
+};
+
+// One triangle covering the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> PostVertex {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: PostVertex;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
@@ -0,0 +1,535 @@
+// post_process.rs
+
+use crate::plugins::validate_fragment;
+use crate::stats::RenderStats;
+use crate::upload::Uploads;
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use serde::{Deserialize, Serialize};
+
+/// What the scene renders into while post-processing is on, so it keeps light brighter than the surface shows.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// Horizontal and vertical blur pairs run over the half-resolution bloom; more spread it wider
+const BLOOM_BLUR_PASSES: u32 = 2;
+
+// How the HDR scene is brought into the surface's 0 to 1 range
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tonemapper {
+    None, // Clamps, as the scene looked without post-processing
+    Reinhard,
+    Aces,
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 3] = [Tonemapper::None, Tonemapper::Reinhard, Tonemapper::Aces];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Tonemapper::None => "None (clamp)",
+            Tonemapper::Reinhard => "Reinhard",
+            Tonemapper::Aces => "ACES filmic",
+        }
+    }
+
+    // What `Post` in post_process.wgsl switches on
+    fn index(&self) -> u32 {
+        match self {
+            Tonemapper::None => 0,
+            Tonemapper::Reinhard => 1,
+            Tonemapper::Aces => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostSettings {
+    pub exposure: f32, // In stops
+    pub tonemapper: Tonemapper,
+    pub bloom: bool,
+    pub bloom_threshold: f32, // Brightness past which the scene glows
+    pub bloom_strength: f32,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            tonemapper: Tonemapper::Aces,
+            bloom: true,
+            bloom_threshold: 1.0,
+            bloom_strength: 0.3,
+        }
+    }
+}
+
+// Laid out like `Post` in post_process.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct PostUniform {
+    exposure: f32,
+    bloom_threshold: f32,
+    bloom_strength: f32,
+    tonemapper: u32,
+}
+
+/// What a `PostPass` draws with. It reads `input` and writes every pixel of `output`, both `HDR_FORMAT` at `size`.
+pub struct PostContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub input: &'a wgpu::TextureView,
+    pub output: &'a wgpu::TextureView,
+    pub size: (u32, u32),
+}
+
+/// A pass over the HDR scene, run in the order added before bloom and tonemapping. Projects add theirs with
+/// `PostProcessor::add_pass` or `Application::post_passes`; `FullscreenPass` makes one from a fragment shader.
+pub trait PostPass {
+    /// Names the pass in the Post-FX window, where it can be turned off.
+    fn label(&self) -> &str;
+
+    fn run(&mut self, context: &mut PostContext);
+}
+
+/// A `PostPass` drawing a fragment shader over the whole scene. post_pass.wgsl is put in front of the shader,
+/// which reads the scene from `input`.
+pub struct FullscreenPass {
+    label: String,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl FullscreenPass {
+    /// Fails if `fragment` doesn't compile or has no `fs_main`.
+    pub fn new(device: &wgpu::Device, label: &str, fragment: &str) -> Result<Self, String> {
+        let source = format!("{}\n{fragment}", include_str!("post_pass.wgsl"));
+        // Checked here first, since wgpu would only report a bad shader as a device error
+        validate_fragment(&source).map_err(|err| format!("Post pass {label:?} does not compile: {err}"))?;
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Pass Bind Group Layout"),
+            entries: &[texture_entry(0), sampler_entry(1)],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline = fullscreen_pipeline(device, label, &[&layout], &module, "fs_main", HDR_FORMAT);
+        Ok(Self {
+            label: label.to_string(),
+            layout,
+            sampler: linear_sampler(device, "Post Pass Sampler"),
+            pipeline,
+        })
+    }
+}
+
+impl PostPass for FullscreenPass {
+    fn label(&self) -> &str {
+        &self.label
+    }
+
+    fn run(&mut self, context: &mut PostContext) {
+        let bind_group = context.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Pass Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(context.input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        draw_fullscreen(context.encoder, &self.label, &self.pipeline, &bind_group, context.output);
+    }
+}
+
+// An HDR texture the chain draws into and samples from
+struct Image {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+// The chain's textures at the scene's size, with the bind groups reading them
+struct Targets {
+    size: (u32, u32),
+    scene: Image,
+    spare: Image,      // Custom passes alternate between it and `scene`
+    bloom: [Image; 2], // Half resolution, blurred back and forth between the two
+    threshold: [wgpu::BindGroup; 2], // Reading `scene` or `spare`, whichever the custom passes left the result in
+    blur: [wgpu::BindGroup; 2],      // Reading each bloom texture
+    composite: [wgpu::BindGroup; 2], // Like `threshold`, with the bloom as well
+}
+
+// The scene is drawn into an HDR texture, which custom passes, bloom and tonemapping then turn into what the
+// surface shows. Scene pipelines draw in `HDR_FORMAT` while it's in use
+pub struct PostProcessor {
+    single_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    threshold: wgpu::RenderPipeline,
+    blur_horizontal: wgpu::RenderPipeline,
+    blur_vertical: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+    passes: Vec<(Box<dyn PostPass>, bool)>, // With whether each is on
+    targets: Option<Targets>,
+}
+
+impl PostProcessor {
+    /// Whether the adapter can render to and filter `HDR_FORMAT` textures, which WebGL may not.
+    pub fn is_supported(adapter: &wgpu::Adapter) -> bool {
+        let features = adapter.get_texture_format_features(HDR_FORMAT);
+        let usages = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+        features.allowed_usages.contains(usages) && features.flags.contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+    }
+
+    /// The tonemapped result is written in `output_format`.
+    pub fn new(device: &wgpu::Device, stats: &mut RenderStats, output_format: wgpu::TextureFormat) -> Self {
+        let uniform_entry = wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let single_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post-Processing Bind Group Layout"),
+            entries: &[texture_entry(0), sampler_entry(1), uniform_entry],
+        });
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post-Processing Composite Bind Group Layout"),
+            entries: &[texture_entry(0), sampler_entry(1), uniform_entry, texture_entry(3)],
+        });
+        let uniform_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Post-Processing Uniform Buffer"),
+                size: size_of::<PostUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Post-Processing Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("post_process.wgsl").into()),
+        });
+        let bloom_pipeline = |label, entry_point| {
+            fullscreen_pipeline(device, label, &[&single_layout], &module, entry_point, HDR_FORMAT)
+        };
+        Self {
+            threshold: bloom_pipeline("Bloom Threshold Pipeline", "fs_threshold"),
+            blur_horizontal: bloom_pipeline("Bloom Horizontal Blur Pipeline", "fs_blur_horizontal"),
+            blur_vertical: bloom_pipeline("Bloom Vertical Blur Pipeline", "fs_blur_vertical"),
+            composite: fullscreen_pipeline(
+                device,
+                "Tonemap Pipeline",
+                &[&composite_layout],
+                &module,
+                "fs_composite",
+                output_format,
+            ),
+            single_layout,
+            composite_layout,
+            sampler: linear_sampler(device, "Post-Processing Sampler"),
+            uniform_buffer,
+            passes: Vec::new(),
+            targets: None,
+        }
+    }
+
+    /// Adds a pass to run after the ones already added, turned on.
+    pub fn add_pass(&mut self, pass: Box<dyn PostPass>) {
+        self.passes.push((pass, true));
+    }
+
+    /// Makes the textures `size`, replacing them if their size differs.
+    pub fn resize(&mut self, device: &wgpu::Device, stats: &mut RenderStats, size: (u32, u32)) {
+        if self.targets.as_ref().is_some_and(|targets| targets.size == size) {
+            return;
+        }
+        self.release(stats);
+        let bloom_size = ((size.0 / 2).max(1), (size.1 / 2).max(1));
+        let scene = create_image(device, stats, "HDR Scene Texture", size);
+        let spare = create_image(device, stats, "HDR Scene Spare Texture", size);
+        let bloom = [
+            create_image(device, stats, "Bloom Texture", bloom_size),
+            create_image(device, stats, "Bloom Blur Texture", bloom_size),
+        ];
+        let single = |input: &Image| self.bind_group(device, &self.single_layout, input, None);
+        let composite = |input: &Image| self.bind_group(device, &self.composite_layout, input, Some(&bloom[0]));
+        self.targets = Some(Targets {
+            size,
+            threshold: [single(&scene), single(&spare)],
+            blur: [single(&bloom[0]), single(&bloom[1])],
+            composite: [composite(&scene), composite(&spare)],
+            scene,
+            spare,
+            bloom,
+        });
+    }
+
+    /// Frees the textures.
+    pub fn release(&mut self, stats: &mut RenderStats) {
+        if let Some(targets) = self.targets.take() {
+            for image in [&targets.scene, &targets.spare, &targets.bloom[0], &targets.bloom[1]] {
+                stats.texture_destroyed(&image.texture);
+            }
+        }
+    }
+
+    /// View the scene is drawn into, once `resize` has made it.
+    pub fn view(&self) -> Option<&wgpu::TextureView> {
+        self.targets.as_ref().map(|targets| &targets.scene.view)
+    }
+
+    /// Texture behind `view`.
+    pub fn texture(&self) -> Option<&wgpu::Texture> {
+        self.targets.as_ref().map(|targets| &targets.scene.texture)
+    }
+
+    /// Runs the custom passes, bloom and tonemapping over the scene, writing every pixel of `output`.
+    pub fn run(
+        &mut self,
+        device: &wgpu::Device,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        settings: &PostSettings,
+        output: &wgpu::TextureView,
+    ) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        let uniform = PostUniform {
+            exposure: settings.exposure.exp2(),
+            bloom_threshold: settings.bloom_threshold,
+            bloom_strength: if settings.bloom { settings.bloom_strength } else { 0.0 },
+            tonemapper: settings.tonemapper.index(),
+        };
+        uploads.write(device, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        // 0 while the result is in `scene`, 1 while it's in `spare`
+        let mut current = 0;
+        for (pass, _) in self.passes.iter_mut().filter(|(_, enabled)| *enabled) {
+            let images = [&targets.scene, &targets.spare];
+            pass.run(&mut PostContext {
+                device,
+                encoder,
+                input: &images[current].view,
+                output: &images[1 - current].view,
+                size: targets.size,
+            });
+            current = 1 - current;
+        }
+
+        if settings.bloom {
+            let [bloom, blurred] = &targets.bloom;
+            let threshold = &targets.threshold[current];
+            draw_fullscreen(encoder, "Bloom Threshold Pass", &self.threshold, threshold, &bloom.view);
+            for _ in 0..BLOOM_BLUR_PASSES {
+                draw_fullscreen(encoder, "Bloom Blur Pass", &self.blur_horizontal, &targets.blur[0], &blurred.view);
+                draw_fullscreen(encoder, "Bloom Blur Pass", &self.blur_vertical, &targets.blur[1], &bloom.view);
+            }
+        }
+        draw_fullscreen(encoder, "Tonemap Pass", &self.composite, &targets.composite[current], output);
+    }
+
+    pub fn show(&mut self, ctx: &Context, settings: &mut PostSettings) {
+        egui::Window::new("Post-FX")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.add(egui::Slider::new(&mut settings.exposure, -4.0..=4.0).text("Exposure (stops)"));
+                egui::ComboBox::from_label("Tonemapping")
+                    .selected_text(settings.tonemapper.label())
+                    .show_ui(ui, |ui| {
+                        for tonemapper in Tonemapper::ALL {
+                            ui.selectable_value(&mut settings.tonemapper, tonemapper, tonemapper.label());
+                        }
+                    });
+
+                ui.separator();
+                ui.checkbox(&mut settings.bloom, "Bloom");
+                ui.add_enabled_ui(settings.bloom, |ui| {
+                    ui.add(egui::Slider::new(&mut settings.bloom_threshold, 0.0..=4.0).text("Threshold"));
+                    ui.add(egui::Slider::new(&mut settings.bloom_strength, 0.0..=2.0).text("Strength"));
+                });
+
+                if !self.passes.is_empty() {
+                    ui.separator();
+                    ui.label("Custom passes, in the order they run");
+                    for (pass, enabled) in &mut self.passes {
+                        ui.checkbox(enabled, pass.label());
+                    }
+                }
+                ui.separator();
+                if ui.button("Reset").clicked() {
+                    *settings = PostSettings::default();
+                }
+            });
+    }
+
+    // Reads `input`, and `bloom` for the composite
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        input: &Image,
+        bloom: Option<&Image>,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&input.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: self.uniform_buffer.as_entire_binding(),
+            },
+        ];
+        if let Some(bloom) = bloom {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&bloom.view),
+            });
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post-Processing Bind Group"),
+            layout,
+            entries: &entries,
+        })
+    }
+}
+
+fn create_image(device: &wgpu::Device, stats: &mut RenderStats, label: &str, (width, height): (u32, u32)) -> Image {
+    let texture = stats.create_texture(
+        device,
+        &wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            // Copied from by passes that read the scene back, such as water
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        },
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    Image { texture, view }
+}
+
+fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+fn linear_sampler(device: &wgpu::Device, label: &str) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some(label),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+// A pipeline drawing one triangle over the whole target, with `vs_main` from `module`
+fn fullscreen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layouts: &[&wgpu::BindGroupLayout],
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: layouts,
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point,
+            targets: &[Some(format.into())],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+// Overwrites every pixel of `target` with `pipeline`
+fn draw_fullscreen(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    target: &wgpu::TextureView,
+) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
@@ -0,0 +1,92 @@
+// Post-processing of the HDR scene: bright parts are picked out at half resolution and blurred into bloom,
+// which is added back before exposure and tonemapping bring the scene into the surface's range
+
+struct Post {
+    exposure: f32, // Multiplier, from the exposure in stops
+    bloom_threshold: f32,
+    bloom_strength: f32, // 0 while bloom is off
+    tonemapper: u32, // 0 clamps, 1 is Reinhard, 2 is ACES
+};
+
+@group(0) @binding(0)
+var input: texture_2d<f32>;
+@group(0) @binding(1)
+var input_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> post: Post;
+@group(0) @binding(3)
+var bloom: texture_2d<f32>; // Only bound for the composite
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// A single triangle covering the target, so no vertex buffer is needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Drawn at half resolution, so each bilinear sample averages a 2x2 block of the scene. A soft knee below the
+// threshold fades highlights in rather than letting them pop
+@fragment
+fn fs_threshold(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(input, input_sampler, in.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = post.bloom_threshold * 0.5;
+    let soft = clamp(brightness - post.bloom_threshold + knee, 0.0, 2.0 * knee);
+    let kept = max(soft * soft / (4.0 * knee + 1e-4), brightness - post.bloom_threshold);
+    return vec4<f32>(color * kept / max(brightness, 1e-4), 1.0);
+}
+
+// Nine taps of a Gaussian, folded into five bilinear samples along `direction`
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    let texel = direction / vec2<f32>(textureDimensions(input));
+    var color = textureSample(input, input_sampler, uv).rgb * 0.2270270270;
+    color += textureSample(input, input_sampler, uv + texel * 1.3846153846).rgb * 0.3162162162;
+    color += textureSample(input, input_sampler, uv - texel * 1.3846153846).rgb * 0.3162162162;
+    color += textureSample(input, input_sampler, uv + texel * 3.2307692308).rgb * 0.0702702703;
+    color += textureSample(input, input_sampler, uv - texel * 3.2307692308).rgb * 0.0702702703;
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_blur_horizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_vertical(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+    return clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let scene = textureSample(input, input_sampler, in.uv).rgb;
+    let glow = textureSample(bloom, input_sampler, in.uv).rgb;
+    let color = (scene + glow * post.bloom_strength) * post.exposure;
+    var mapped: vec3<f32>;
+    switch post.tonemapper {
+        case 1u: {
+            mapped = color / (1.0 + color);
+        }
+        case 2u: {
+            mapped = aces(color);
+        }
+        default: {
+            mapped = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+        }
+    }
+    return vec4<f32>(mapped, 1.0);
+}
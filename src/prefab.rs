@@ -0,0 +1,538 @@
+// prefab.rs
+
+use crate::assets::{AssetEvent, Assets, Handle};
+use crate::bounds::Aabb;
+use crate::geometry::GeometryArena;
+use crate::jobs::FrameBudget;
+use crate::material::MaterialId;
+use crate::mesh_builder::MeshShape;
+use crate::particles::ParticleEmitter;
+use crate::physics::{BodyKind, Collider, RigidBody};
+use crate::stats::RenderStats;
+use crate::toasts::Toasts;
+use crate::transform::Transform;
+use crate::upload::Uploads;
+use crate::world::{self, GlobalTransform, Light, MaterialHandle, MeshHandle, Parent, Visible};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::Vec3;
+use hecs::{Entity, World};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Deepest hierarchy captured into a prefab, so a parent cycle can't loop forever
+const MAX_DEPTH: usize = 64;
+
+// A reusable entity hierarchy, saved from a subtree of the world and instantiated any number of times
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Prefab {
+    pub nodes: Vec<PrefabNode>, // The root first; every node comes after its parent
+}
+
+// One entity of a prefab and the components it is spawned with
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrefabNode {
+    pub parent: Option<usize>, // Index of an earlier node
+    pub transform: Transform,  // Relative to the parent; the root's is replaced by where an instance is placed
+    pub mesh: Option<MeshShape>,
+    pub light: Option<Light>,
+    pub emitter: Option<ParticleEmitter>,
+    pub body: Option<BodyKind>,
+    pub collider: Option<Collider>,
+}
+
+// On the root of an instance: the prefab it came from, as it was when last applied. A property of a node
+// that still matches this snapshot follows the prefab; one that differs is an override and is kept
+#[derive(Debug, Clone)]
+pub struct PrefabInstance {
+    pub path: PathBuf,
+    source: Prefab,
+}
+
+// On every entity of an instance, root included
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PrefabLink {
+    pub instance: Entity,
+    pub node: usize,
+}
+
+// An instance as saved in a scene: what its prefab was when it was saved, and what its nodes were
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedInstance {
+    pub path: PathBuf,
+    pub source: Prefab,
+    pub nodes: Vec<PrefabNode>,
+}
+
+// Gives prefab nodes meshes of their own in the shared arena, and takes them back
+pub struct PrefabMeshes<'a> {
+    pub device: &'a wgpu::Device,
+    pub uploads: &'a mut Uploads,
+    pub stats: &'a mut RenderStats,
+    pub geometry: &'a mut GeometryArena,
+}
+
+impl PrefabMeshes<'_> {
+    pub(crate) fn allocate(&mut self, shape: MeshShape) -> MeshHandle {
+        let (vertices, indices) = shape.generate();
+        let bounds = Aabb::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.position)));
+        let allocation = self.geometry.allocate(self.device, self.uploads, self.stats, &vertices, &indices);
+        MeshHandle { allocation, bounds }
+    }
+
+    pub(crate) fn free(&mut self, world: &World, entity: Entity) {
+        if let Ok(mesh) = world.get::<&MeshHandle>(entity) {
+            self.geometry.free(&mesh.allocation);
+        }
+    }
+}
+
+impl Prefab {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let prefab: Self = ron::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let ordered = prefab.nodes.iter().enumerate().all(|(index, node)| node.parent.is_none_or(|p| p < index));
+        if prefab.nodes.is_empty() || prefab.nodes[0].parent.is_some() || !ordered {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "prefab nodes must start at the root and follow their parents",
+            ));
+        }
+        Ok(prefab)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+
+    /// `root` and everything parented under it. An instance's entities are captured as they are, overrides
+    /// included, so saving an instance makes a new prefab of it.
+    pub fn capture(world: &World, root: Entity) -> Self {
+        let mut children: HashMap<Entity, Vec<Entity>> = HashMap::new();
+        for (entity, parent) in world.query::<(Entity, &Parent)>().iter() {
+            children.entry(parent.0).or_default().push(entity);
+        }
+        let mut nodes = vec![read_node(world, root, None)];
+        let mut pending = vec![(root, 0, 0)];
+        while let Some((entity, index, depth)) = pending.pop() {
+            let mut below = children.remove(&entity).unwrap_or_default();
+            below.sort();
+            for child in below.into_iter().filter(|_| depth < MAX_DEPTH) {
+                nodes.push(read_node(world, child, Some(index)));
+                pending.push((child, nodes.len() - 1, depth + 1));
+            }
+        }
+        Self { nodes }
+    }
+}
+
+// The node `entity` currently amounts to
+fn read_node(world: &World, entity: Entity, parent: Option<usize>) -> PrefabNode {
+    PrefabNode {
+        parent,
+        transform: world.get::<&Transform>(entity).map(|transform| *transform).unwrap_or_default(),
+        mesh: world.get::<&MeshShape>(entity).ok().map(|shape| *shape),
+        light: world.get::<&Light>(entity).ok().map(|light| *light),
+        emitter: world.get::<&ParticleEmitter>(entity).ok().map(|emitter| (*emitter).clone()),
+        body: world.get::<&RigidBody>(entity).ok().map(|body| body.kind),
+        collider: world.get::<&Collider>(entity).ok().map(|collider| (*collider).clone()),
+    }
+}
+
+// Makes `entity` into `node`, touching only the components that differ
+fn write_node(world: &mut World, meshes: &mut PrefabMeshes, entity: Entity, node: &PrefabNode) {
+    let current = read_node(world, entity, node.parent);
+    if current.transform != node.transform {
+        *world::transform_mut(world, entity) = node.transform;
+    }
+    if current.mesh != node.mesh {
+        meshes.free(world, entity);
+        let _ = world.remove::<(MeshHandle, MeshShape, MaterialHandle, Visible)>(entity);
+        if let Some(shape) = node.mesh {
+            let mesh = meshes.allocate(shape);
+            let _ = world.insert(entity, (mesh, shape, MaterialHandle(MaterialId::DEFAULT), Visible(true)));
+        }
+    }
+    replace(world, entity, current.light, node.light);
+    replace(world, entity, current.emitter, node.emitter.clone());
+    replace(world, entity, current.body.map(|kind| RigidBody { kind }), node.body.map(|kind| RigidBody { kind }));
+    replace(world, entity, current.collider, node.collider.clone());
+}
+
+fn replace<T: PartialEq + hecs::Component>(world: &mut World, entity: Entity, current: Option<T>, wanted: Option<T>) {
+    if current == wanted {
+        return;
+    }
+    match wanted {
+        Some(value) => {
+            let _ = world.insert_one(entity, value);
+        }
+        None => {
+            let _ = world.remove_one::<T>(entity);
+        }
+    }
+}
+
+// A property of an instance after its prefab changed from `old` to `new`: the new value unless overridden
+fn follow<T: PartialEq + Clone>(current: &T, old: &T, new: &T) -> T {
+    if current == old {
+        new.clone()
+    } else {
+        current.clone()
+    }
+}
+
+/// Spawns an instance of `prefab` with its root at `placement`, returning the root.
+pub fn instantiate(
+    world: &mut World,
+    meshes: &mut PrefabMeshes,
+    path: &Path,
+    prefab: &Prefab,
+    placement: Transform,
+) -> Entity {
+    let mut nodes = prefab.nodes.clone();
+    nodes[0].transform = placement;
+    restore(world, meshes, path, prefab.clone(), &nodes)
+}
+
+// Spawns an instance whose nodes are `nodes`, which may differ from `source` by overrides
+fn restore(world: &mut World, meshes: &mut PrefabMeshes, path: &Path, source: Prefab, nodes: &[PrefabNode]) -> Entity {
+    let root = world.spawn((Transform::IDENTITY, GlobalTransform(Transform::IDENTITY.matrix())));
+    let mut entities = vec![root];
+    for (index, node) in nodes.iter().enumerate().skip(1) {
+        let entity = world.spawn((Transform::IDENTITY, GlobalTransform(Transform::IDENTITY.matrix())));
+        let parent = node.parent.and_then(|parent| entities.get(parent)).copied().unwrap_or(root);
+        let _ = world.insert(entity, (Parent(parent), PrefabLink { instance: root, node: index }));
+        entities.push(entity);
+    }
+    for (&entity, node) in entities.iter().zip(nodes) {
+        write_node(world, meshes, entity, node);
+    }
+    let _ = world.insert(
+        root,
+        (
+            PrefabLink { instance: root, node: 0 },
+            PrefabInstance {
+                path: path.to_path_buf(),
+                source,
+            },
+        ),
+    );
+    root
+}
+
+// Every entity of the instance rooted at `root`, by node
+fn instance_entities(world: &World, root: Entity) -> HashMap<usize, Entity> {
+    let mut query = world.query::<(Entity, &PrefabLink)>();
+    query
+        .iter()
+        .filter(|(_, link)| link.instance == root)
+        .map(|(entity, link)| (link.node, entity))
+        .collect()
+}
+
+fn instances_of(world: &World, path: &Path) -> Vec<Entity> {
+    let mut query = world.query::<(Entity, &PrefabInstance)>();
+    let mut roots: Vec<Entity> =
+        query.iter().filter(|(_, instance)| instance.path == path).map(|(root, _)| root).collect();
+    roots.sort();
+    roots
+}
+
+/// Brings every instance of the prefab at `path` up to `prefab`, keeping their overrides and placement.
+/// Returns how many instances were updated.
+pub fn propagate(world: &mut World, meshes: &mut PrefabMeshes, path: &Path, prefab: &Prefab) -> usize {
+    let roots = instances_of(world, path);
+    for &root in &roots {
+        let Some(old) = world.get::<&PrefabInstance>(root).ok().map(|instance| instance.source.clone()) else {
+            continue;
+        };
+        let mut entities = instance_entities(world, root);
+        // Nodes the prefab no longer has go, children before their parents
+        let mut gone: Vec<usize> = entities.keys().copied().filter(|&node| node >= prefab.nodes.len()).collect();
+        gone.sort_by(|a, b| b.cmp(a));
+        for node in gone {
+            if let Some(entity) = entities.remove(&node) {
+                meshes.free(world, entity);
+                let _ = world.despawn(entity);
+            }
+        }
+        for (index, new) in prefab.nodes.iter().enumerate() {
+            let existed = entities.contains_key(&index);
+            let entity = match entities.get(&index) {
+                Some(&entity) => entity,
+                None => {
+                    let entity = world.spawn((Transform::IDENTITY, GlobalTransform(Transform::IDENTITY.matrix())));
+                    let _ = world.insert_one(entity, PrefabLink { instance: root, node: index });
+                    entities.insert(index, entity);
+                    entity
+                }
+            };
+            let current = read_node(world, entity, new.parent);
+            let node = match old.nodes.get(index).filter(|_| existed) {
+                Some(old) => PrefabNode {
+                    parent: new.parent,
+                    transform: if index == 0 {
+                        current.transform // Where the instance was placed
+                    } else {
+                        follow(&current.transform, &old.transform, &new.transform)
+                    },
+                    mesh: follow(&current.mesh, &old.mesh, &new.mesh),
+                    light: follow(&current.light, &old.light, &new.light),
+                    emitter: follow(&current.emitter, &old.emitter, &new.emitter),
+                    body: follow(&current.body, &old.body, &new.body),
+                    collider: follow(&current.collider, &old.collider, &new.collider),
+                },
+                None => new.clone(),
+            };
+            write_node(world, meshes, entity, &node);
+            if let Some(parent) = new.parent.and_then(|parent| entities.get(&parent)).copied() {
+                let _ = world.insert_one(entity, Parent(parent));
+            }
+        }
+        if let Ok(mut instance) = world.get::<&mut PrefabInstance>(root) {
+            instance.source = prefab.clone();
+        }
+    }
+    roots.len()
+}
+
+/// Puts every node of the instance back to its prefab, dropping its overrides but keeping its placement.
+pub fn revert(world: &mut World, meshes: &mut PrefabMeshes, root: Entity) {
+    let Some(source) = world.get::<&PrefabInstance>(root).ok().map(|instance| instance.source.clone()) else {
+        return;
+    };
+    let entities = instance_entities(world, root);
+    for (index, node) in source.nodes.iter().enumerate() {
+        let Some(&entity) = entities.get(&index) else {
+            continue;
+        };
+        let mut node = node.clone();
+        if index == 0 {
+            node.transform = world::transform(world, root);
+        }
+        write_node(world, meshes, entity, &node);
+    }
+}
+
+/// Removes the instance rooted at `root` and gives back its meshes.
+pub fn despawn_instance(world: &mut World, meshes: &mut PrefabMeshes, root: Entity) {
+    for entity in instance_entities(world, root).into_values() {
+        meshes.free(world, entity);
+        let _ = world.despawn(entity);
+    }
+}
+
+/// Every instance in the world, as a scene saves them.
+pub fn save_instances(world: &World) -> Vec<SavedInstance> {
+    instance_roots(world)
+        .into_iter()
+        .filter_map(|root| {
+            let instance = world.get::<&PrefabInstance>(root).ok()?;
+            let entities = instance_entities(world, root);
+            let nodes = (0..instance.source.nodes.len())
+                .map(|index| {
+                    let parent = instance.source.nodes[index].parent;
+                    entities.get(&index).map_or_else(PrefabNode::default, |&entity| read_node(world, entity, parent))
+                })
+                .collect();
+            Some(SavedInstance {
+                path: instance.path.clone(),
+                source: instance.source.clone(),
+                nodes,
+            })
+        })
+        .collect()
+}
+
+/// Spawns an instance saved by `save_instances`, as it was when saved.
+pub fn load_instance(world: &mut World, meshes: &mut PrefabMeshes, saved: &SavedInstance) -> Option<Entity> {
+    let valid = !saved.nodes.is_empty() && saved.nodes.len() == saved.source.nodes.len();
+    valid.then(|| restore(world, meshes, &saved.path, saved.source.clone(), &saved.nodes))
+}
+
+fn instance_roots(world: &World) -> Vec<Entity> {
+    let mut roots: Vec<Entity> = world.query::<(Entity, &PrefabInstance)>().iter().map(|(root, _)| root).collect();
+    roots.sort();
+    roots
+}
+
+// Prefab files, loaded once each and watched, so editing one on disk updates its instances
+pub struct PrefabLibrary {
+    assets: Assets<Prefab>,
+    loaded: HashMap<PathBuf, Handle<Prefab>>,
+    to_place: Vec<PathBuf>, // Instances asked for before their prefab had loaded
+}
+
+impl PrefabLibrary {
+    pub fn new() -> Self {
+        Self {
+            assets: Assets::new(Prefab::load),
+            loaded: HashMap::new(),
+            to_place: Vec::new(),
+        }
+    }
+
+    /// Places a new instance of the prefab at `path` on the next `update`, loading the prefab first if needed.
+    pub fn instantiate(&mut self, path: PathBuf) {
+        self.watch(&path);
+        self.to_place.push(path);
+    }
+
+    /// Loads the prefab at `path` if it isn't already, so its instances follow changes to it.
+    pub fn watch(&mut self, path: &Path) {
+        if !self.loaded.contains_key(path) {
+            let handle = self.assets.load(path, true);
+            self.loaded.insert(path.to_path_buf(), handle);
+        }
+    }
+
+    /// Spawns an instance saved in a scene and brings it up to its prefab if that is already loaded;
+    /// otherwise it catches up once the prefab loads.
+    pub fn restore(&mut self, world: &mut World, meshes: &mut PrefabMeshes, saved: &SavedInstance) -> Option<Entity> {
+        let root = load_instance(world, meshes, saved)?;
+        self.watch(&saved.path);
+        if let Some(prefab) = self.loaded.get(&saved.path).and_then(|&handle| self.assets.get(handle)) {
+            propagate(world, meshes, &saved.path, prefab);
+        }
+        Some(root)
+    }
+
+    /// Writes `prefab` to `path` and updates the instances already made from that file.
+    pub fn save(
+        &mut self,
+        world: &mut World,
+        meshes: &mut PrefabMeshes,
+        path: PathBuf,
+        prefab: Prefab,
+    ) -> io::Result<()> {
+        prefab.save(&path)?;
+        propagate(world, meshes, &path, &prefab);
+        // Watched afresh, so the write just made doesn't come back as an outside change
+        let handle = self.assets.insert(path.clone(), prefab, true);
+        if let Some(previous) = self.loaded.insert(path, handle) {
+            self.assets.remove(previous);
+        }
+        Ok(())
+    }
+
+    /// Applies finished loads and outside edits to the world, and places instances whose prefab is ready.
+    /// Returns whether the world changed.
+    pub fn update(
+        &mut self,
+        budget: &mut FrameBudget,
+        world: &mut World,
+        meshes: &mut PrefabMeshes,
+        toasts: &mut Toasts,
+        placement: Transform,
+    ) -> bool {
+        let mut changed = false;
+        for event in self.assets.poll(budget) {
+            match event {
+                AssetEvent::Loaded(handle) | AssetEvent::Reloaded(handle) => {
+                    let (Some(prefab), Some(path)) = (self.assets.get(handle), self.assets.path(handle)) else {
+                        continue;
+                    };
+                    let updated = propagate(world, meshes, path, prefab);
+                    if updated > 0 {
+                        toasts.info(format!("Updated {updated} instances of {}", path.display()));
+                    }
+                    changed = true;
+                }
+                AssetEvent::Failed(handle, err) => {
+                    let path = self.assets.path(handle).map(Path::to_path_buf).unwrap_or_default();
+                    log::warn!("Failed to load prefab from {}: {err}", path.display());
+                    toasts.error(format!("Failed to load prefab: {err}"));
+                    self.to_place.retain(|placing| *placing != path);
+                }
+            }
+        }
+        let mut waiting = Vec::new();
+        for path in std::mem::take(&mut self.to_place) {
+            let prefab = self.loaded.get(&path).and_then(|&handle| self.assets.get(handle));
+            match prefab {
+                Some(prefab) => {
+                    instantiate(world, meshes, &path, prefab, placement);
+                    changed = true;
+                }
+                None => waiting.push(path),
+            }
+        }
+        self.to_place = waiting;
+        changed
+    }
+
+    pub fn show(&mut self, ctx: &Context, world: &mut World, meshes: &mut PrefabMeshes) {
+        egui::Window::new("Prefabs")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                ui.label("Save the object and its children from the File menu, then place instances of it here.");
+                let mut paths: Vec<PathBuf> = self.loaded.keys().cloned().collect();
+                paths.sort();
+                for path in paths {
+                    let name = path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into());
+                    ui.horizontal(|ui| {
+                        ui.label(&name);
+                        if ui.button("Place").clicked() {
+                            self.to_place.push(path.clone());
+                        }
+                    });
+                }
+
+                ui.separator();
+                let roots = instance_roots(world);
+                if roots.is_empty() {
+                    ui.weak("No instances");
+                }
+                let mut removed = None;
+                let mut reverted = None;
+                for (index, &root) in roots.iter().enumerate() {
+                    let name = world
+                        .get::<&PrefabInstance>(root)
+                        .ok()
+                        .and_then(|instance| instance.path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+                        .unwrap_or_default();
+                    egui::CollapsingHeader::new(format!("{name} #{}", index + 1))
+                        .id_source(root)
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("Position");
+                                let mut transform = world::transform_mut(world, root);
+                                for axis in 0..3 {
+                                    ui.add(egui::DragValue::new(&mut transform.translation[axis]).speed(0.01));
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                if ui.button("Revert").on_hover_text("Drop this instance's overrides").clicked() {
+                                    reverted = Some(root);
+                                }
+                                if ui.button("Remove").clicked() {
+                                    removed = Some(root);
+                                }
+                            });
+                        });
+                }
+                if let Some(root) = reverted {
+                    revert(world, meshes, root);
+                }
+                if let Some(root) = removed {
+                    despawn_instance(world, meshes, root);
+                }
+            });
+    }
+}
+
+impl Default for PrefabLibrary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
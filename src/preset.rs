@@ -0,0 +1,143 @@
+// preset.rs
+
+use crate::commands::ShortcutMap;
+use crate::input::ActionMap;
+use crate::settings::settings_dir;
+use crate::theme::Theme;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const PRESET_EXTENSION: &str = "ron";
+
+// Everything that makes up the editor's look and feel, shareable as a single file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutPreset {
+    pub theme: Theme,
+    pub ui_scale: f32,
+    pub keybindings: ActionMap,
+    pub shortcuts: ShortcutMap,
+    pub egui_memory: egui::Memory, // Window positions, sizes and open/collapsed state
+}
+
+impl Default for LayoutPreset {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            ui_scale: 1.0,
+            keybindings: ActionMap::default(),
+            shortcuts: ShortcutMap::default(),
+            egui_memory: egui::Memory::default(),
+        }
+    }
+}
+
+impl LayoutPreset {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        ron::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, text)
+    }
+}
+
+pub fn presets_dir() -> PathBuf {
+    settings_dir().join("presets")
+}
+
+pub fn preset_path(name: &str) -> PathBuf {
+    presets_dir().join(name).with_extension(PRESET_EXTENSION)
+}
+
+// Names of the presets in `presets_dir`, sorted
+pub fn list_presets() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(presets_dir()) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == PRESET_EXTENSION))
+        .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    names
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PresetRequest {
+    Save(PathBuf),
+    Load(PathBuf),
+}
+
+// Named presets section of the Preferences panel
+#[derive(Debug, Default)]
+pub struct PresetBrowser {
+    name: String,
+    available: Option<Vec<String>>, // Listed lazily and after every change
+}
+
+impl PresetBrowser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn refresh(&mut self) {
+        self.available = None;
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<PresetRequest> {
+        let mut request = None;
+        let available = self.available.get_or_insert_with(list_presets);
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.name).hint_text("Preset name").desired_width(140.0));
+            let valid = is_valid_name(&self.name);
+            if ui.add_enabled(valid, egui::Button::new("Save")).clicked() {
+                request = Some(PresetRequest::Save(preset_path(self.name.trim())));
+            }
+        });
+
+        let mut deleted = None;
+        for name in available.iter() {
+            ui.horizontal(|ui| {
+                ui.label(name);
+                if ui.small_button("Load").clicked() {
+                    request = Some(PresetRequest::Load(preset_path(name)));
+                }
+                if ui.small_button("🗑").on_hover_text("Delete").clicked() {
+                    deleted = Some(name.clone());
+                }
+            });
+        }
+        if available.is_empty() {
+            ui.weak("No saved presets");
+        }
+
+        if let Some(name) = deleted {
+            if let Err(err) = fs::remove_file(preset_path(&name)) {
+                log::warn!("Failed to delete preset {name}: {err}");
+            }
+            self.refresh();
+        }
+        if matches!(request, Some(PresetRequest::Save(_))) {
+            self.refresh();
+        }
+        request
+    }
+}
+
+// Preset names become file names, so keep them to a portable subset
+fn is_valid_name(name: &str) -> bool {
+    let name = name.trim();
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+}
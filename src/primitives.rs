@@ -0,0 +1,303 @@
+// primitives.rs
+
+use crate::vertex::Vertex;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+use std::ops::RangeInclusive;
+
+// Ranges the UI offers; at their tops every primitive still indexes its vertices with u16
+pub const SEGMENTS_RANGE: RangeInclusive<u16> = 3..=255;
+pub const RINGS_RANGE: RangeInclusive<u16> = 2..=128;
+pub const SUBDIVISIONS_RANGE: RangeInclusive<u8> = 0..=5;
+
+const RADIUS: f32 = 0.5; // Every primitive fits the unit cube around the origin, like the cube
+const TUBE_RADIUS: f32 = 0.15; // Of the torus, whose ring is centered `RADIUS - TUBE_RADIUS` from the Y axis
+const COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+// A generated shape lit smoothly, with normals and texture coordinates, at some resolution. Round shapes go
+// around the Y axis, with U running once around it and V growing downwards
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Primitive {
+    UvSphere { segments: u16, rings: u16 },
+    Icosphere { subdivisions: u8 }, // Times each triangle of the icosahedron is split in four
+    Cylinder { segments: u16 },
+    Cone { segments: u16 },
+    Torus { segments: u16, sides: u16 }, // `sides` around the tube
+    Plane { segments: u16 },             // Facing +Z like the polygon, in `segments` by `segments` quads
+}
+
+impl Primitive {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Primitive::UvSphere { .. } => "UV sphere",
+            Primitive::Icosphere { .. } => "Icosphere",
+            Primitive::Cylinder { .. } => "Cylinder",
+            Primitive::Cone { .. } => "Cone",
+            Primitive::Torus { .. } => "Torus",
+            Primitive::Plane { .. } => "Plane",
+        }
+    }
+
+    pub fn generate(&self) -> (Vec<Vertex>, Vec<u16>) {
+        match *self {
+            Primitive::UvSphere { segments, rings } => uv_sphere(segments, rings, RADIUS),
+            Primitive::Icosphere { subdivisions } => icosphere(subdivisions),
+            Primitive::Cylinder { segments } => cylinder(segments),
+            Primitive::Cone { segments } => cone(segments),
+            Primitive::Torus { segments, sides } => torus(segments, sides),
+            Primitive::Plane { segments } => plane(segments),
+        }
+    }
+}
+
+// How finely the UI's primitives are tessellated. Primitives with the same kind of parameter share it, so
+// switching from the cylinder to the cone keeps the count around the axis
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tessellation {
+    pub segments: u16,    // Around the Y axis, or along each side of the plane
+    pub rings: u16,       // From pole to pole of the UV sphere, or around the torus's tube
+    pub subdivisions: u8, // Of the icosphere
+}
+
+impl Tessellation {
+    /// Within the UI's ranges, as for values from a file edited by hand.
+    pub fn clamped(self) -> Self {
+        Self {
+            segments: self.segments.clamp(*SEGMENTS_RANGE.start(), *SEGMENTS_RANGE.end()),
+            rings: self.rings.clamp(*RINGS_RANGE.start(), *RINGS_RANGE.end()),
+            subdivisions: self.subdivisions.clamp(*SUBDIVISIONS_RANGE.start(), *SUBDIVISIONS_RANGE.end()),
+        }
+    }
+}
+
+impl Default for Tessellation {
+    fn default() -> Self {
+        Self {
+            segments: 32,
+            rings: 16,
+            subdivisions: 3,
+        }
+    }
+}
+
+// The point `angle` radians around the Y axis at distance 1, counter-clockwise seen from above and starting
+// at +X, matching U
+fn around_y(angle: f32) -> Vec3 {
+    Vec3::new(angle.cos(), 0.0, -angle.sin())
+}
+
+fn vertex(position: Vec3, normal: Vec3, uv: [f32; 2]) -> Vertex {
+    Vertex::with_uv(position.into(), COLOR, uv).with_normal(normal.into())
+}
+
+// Two triangles for each cell of a grid stored row by row, `columns + 1` vertices to a row starting at `first`.
+// Rows run downwards and columns along U, which is counter-clockwise seen from the front
+fn grid_indices(indices: &mut Vec<u16>, first: u16, columns: u16, rows: u16) {
+    let row = columns + 1;
+    for r in 0..rows {
+        for column in 0..columns {
+            let top = first + r * row + column;
+            let bottom = top + row;
+            indices.extend_from_slice(&[top, bottom, bottom + 1, top, bottom + 1, top + 1]);
+        }
+    }
+}
+
+// A flat disc at `height` facing up or down, with the texture projected onto it from above
+fn cap(vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>, segments: u16, height: f32, up: bool) {
+    let normal = if up { Vec3::Y } else { Vec3::NEG_Y };
+    let center = vertices.len() as u16;
+    vertices.push(vertex(Vec3::new(0.0, height, 0.0), normal, [0.5, 0.5]));
+    for segment in 0..segments {
+        let direction = around_y(segment as f32 / segments as f32 * TAU);
+        let uv = [0.5 + direction.x * 0.5, 0.5 + direction.z * 0.5];
+        vertices.push(vertex(direction * RADIUS + Vec3::new(0.0, height, 0.0), normal, uv));
+    }
+    for segment in 0..segments {
+        let (a, b) = (center + 1 + segment, center + 1 + (segment + 1) % segments);
+        // The rim runs counter-clockwise seen from above, so the bottom cap takes it the other way round
+        let triangle = if up { [center, a, b] } else { [center, b, a] };
+        indices.extend_from_slice(&triangle);
+    }
+}
+
+/// A sphere of `radius` in `rings` bands from pole to pole, each split into `segments` around the Y axis. Panics
+/// if that takes more vertices than u16 indices reach.
+pub fn uv_sphere(segments: u16, rings: u16, radius: f32) -> (Vec<Vertex>, Vec<u16>) {
+    let count = (segments as usize + 1) * (rings as usize + 1);
+    assert!(count <= u16::MAX as usize + 1, "A {segments} by {rings} sphere has too many vertices for u16 indices");
+    let mut vertices = Vec::with_capacity(count);
+    for ring in 0..=rings {
+        let polar = ring as f32 / rings as f32 * PI;
+        for segment in 0..=segments {
+            let normal = around_y(segment as f32 / segments as f32 * TAU) * polar.sin() + Vec3::Y * polar.cos();
+            let uv = [segment as f32 / segments as f32, ring as f32 / rings as f32];
+            vertices.push(vertex(normal * radius, normal, uv));
+        }
+    }
+    let mut indices = Vec::with_capacity(segments as usize * rings as usize * 6);
+    grid_indices(&mut indices, 0, segments, rings);
+    (vertices, indices)
+}
+
+/// A sphere from an icosahedron whose triangles are split `subdivisions` times, all about the same size.
+/// Vertices on the texture's seam and at the poles are doubled so the texture doesn't smear across them.
+pub fn icosphere(subdivisions: u8) -> (Vec<Vertex>, Vec<u16>) {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut positions: Vec<Vec3> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(|position| Vec3::from(position).normalize())
+    .collect();
+    let mut faces: Vec<[u16; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Each edge's midpoint, made once for both triangles sharing it
+        let mut midpoints: HashMap<(u16, u16), u16> = HashMap::new();
+        let mut midpoint = |a: u16, b: u16| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let position = (positions[a as usize] + positions[b as usize]).normalize();
+                positions.push(position);
+                positions.len() as u16 - 1
+            })
+        };
+        faces = faces
+            .into_iter()
+            .flat_map(|[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let u = |position: Vec3| (-position.z).atan2(position.x).rem_euclid(TAU) / TAU;
+    let at_pole = |position: Vec3| position.x.abs() < 1e-6 && position.z.abs() < 1e-6;
+    let mut vertices: Vec<Vertex> = positions
+        .iter()
+        .map(|&normal| vertex(normal * RADIUS, normal, [u(normal), normal.y.acos() / PI]))
+        .collect();
+    let mut wrapped: HashMap<u16, u16> = HashMap::new(); // Copies with U past 1, for triangles across the seam
+    let mut indices = Vec::with_capacity(faces.len() * 3);
+    for mut face in faces {
+        let off_pole: Vec<usize> = (0..3).filter(|&corner| !at_pole(positions[face[corner] as usize])).collect();
+        let us = off_pole.iter().map(|&corner| vertices[face[corner] as usize].uv[0]);
+        let (low, high) = us.fold((f32::MAX, f32::MIN), |(low, high), u| (low.min(u), high.max(u)));
+        if high - low > 0.5 {
+            for &corner in &off_pole {
+                let index = face[corner];
+                if vertices[index as usize].uv[0] < 0.5 {
+                    face[corner] = *wrapped.entry(index).or_insert_with(|| {
+                        let mut copy = vertices[index as usize];
+                        copy.uv[0] += 1.0;
+                        vertices.push(copy);
+                        vertices.len() as u16 - 1
+                    });
+                }
+            }
+        }
+        // A pole has no U of its own, so each triangle meeting it gets a copy halfway between its other corners
+        if off_pole.len() == 2 {
+            let pole = (0..3).find(|corner| !off_pole.contains(corner)).unwrap();
+            let mut copy = vertices[face[pole] as usize];
+            copy.uv[0] = off_pole.iter().map(|&corner| vertices[face[corner] as usize].uv[0]).sum::<f32>() / 2.0;
+            vertices.push(copy);
+            face[pole] = vertices.len() as u16 - 1;
+        }
+        indices.extend_from_slice(&face);
+    }
+    (vertices, indices)
+}
+
+/// A cylinder around the Y axis with its sides split into `segments`, capped at both ends.
+pub fn cylinder(segments: u16) -> (Vec<Vertex>, Vec<u16>) {
+    let mut vertices = Vec::with_capacity((segments as usize + 1) * 4 + 2);
+    for (v, height) in [(0.0, RADIUS), (1.0, -RADIUS)] {
+        for segment in 0..=segments {
+            let normal = around_y(segment as f32 / segments as f32 * TAU);
+            let position = normal * RADIUS + Vec3::new(0.0, height, 0.0);
+            vertices.push(vertex(position, normal, [segment as f32 / segments as f32, v]));
+        }
+    }
+    let mut indices = Vec::with_capacity(segments as usize * 12);
+    grid_indices(&mut indices, 0, segments, 1);
+    cap(&mut vertices, &mut indices, segments, RADIUS, true);
+    cap(&mut vertices, &mut indices, segments, -RADIUS, false);
+    (vertices, indices)
+}
+
+/// A cone around the Y axis pointing up, with its sides split into `segments` and its base capped.
+pub fn cone(segments: u16) -> (Vec<Vertex>, Vec<u16>) {
+    let mut vertices = Vec::with_capacity((segments as usize + 1) * 3 + 1);
+    // The apex is doubled for each segment, halfway round it, so each side gets the normal of its middle
+    for (v, offset) in [(0.0, 0.5), (1.0, 0.0)] {
+        for segment in 0..=segments {
+            let u = (segment as f32 + offset) / segments as f32;
+            let outward = around_y(u * TAU);
+            // Leaning up by the slope of the sides, which rise 2 * RADIUS over RADIUS
+            let normal = (outward * 2.0 + Vec3::Y).normalize();
+            let position = outward * RADIUS * v + Vec3::new(0.0, RADIUS - 2.0 * RADIUS * v, 0.0);
+            vertices.push(vertex(position, normal, [u, v]));
+        }
+    }
+    let row = segments + 1;
+    let mut indices = Vec::with_capacity(segments as usize * 6);
+    for segment in 0..segments {
+        indices.extend_from_slice(&[segment, segment + row, segment + row + 1]);
+    }
+    cap(&mut vertices, &mut indices, segments, -RADIUS, false);
+    (vertices, indices)
+}
+
+/// A ring around the Y axis split into `segments`, its tube split into `sides` around.
+pub fn torus(segments: u16, sides: u16) -> (Vec<Vertex>, Vec<u16>) {
+    let mut vertices = Vec::with_capacity((segments as usize + 1) * (sides as usize + 1));
+    // Rows go around the tube from its top outwards and down, so V grows downwards on the outside
+    for side in 0..=sides {
+        let tube = side as f32 / sides as f32 * TAU;
+        for segment in 0..=segments {
+            let outward = around_y(segment as f32 / segments as f32 * TAU);
+            let normal = outward * tube.sin() + Vec3::Y * tube.cos();
+            let position = outward * (RADIUS - TUBE_RADIUS) + normal * TUBE_RADIUS;
+            vertices.push(vertex(position, normal, [segment as f32 / segments as f32, side as f32 / sides as f32]));
+        }
+    }
+    let mut indices = Vec::with_capacity(segments as usize * sides as usize * 6);
+    grid_indices(&mut indices, 0, segments, sides);
+    (vertices, indices)
+}
+
+/// A square of `segments` by `segments` quads facing +Z, with the texture across it once.
+pub fn plane(segments: u16) -> (Vec<Vertex>, Vec<u16>) {
+    let mut vertices = Vec::with_capacity((segments as usize + 1).pow(2));
+    for row in 0..=segments {
+        let v = row as f32 / segments as f32;
+        for column in 0..=segments {
+            let u = column as f32 / segments as f32;
+            let position = Vec3::new(u - 0.5, 0.5 - v, 0.0);
+            vertices.push(vertex(position, Vec3::Z, [u, v]));
+        }
+    }
+    let mut indices = Vec::with_capacity(segments as usize * segments as usize * 6);
+    grid_indices(&mut indices, 0, segments, segments);
+    (vertices, indices)
+}
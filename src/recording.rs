@@ -0,0 +1,132 @@
+// recording.rs
+
+use crate::input::{InputEvent, InputState};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use web_time::Instant;
+
+// Replays always advance by this much per frame so runs do not depend on the frame rate
+pub const PLAYBACK_STEP: f32 = 1.0 / 60.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimedEvent {
+    pub time: f64, // Seconds since the recording started
+    pub event: InputEvent,
+}
+
+// A captured input session, saved as RON
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InputRecording {
+    pub events: Vec<TimedEvent>,
+}
+
+impl InputRecording {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        ron::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, text)
+    }
+
+    // Seconds from the start to the last event
+    pub fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |e| e.time)
+    }
+}
+
+enum Mode {
+    Idle,
+    Recording { start: Instant, recording: InputRecording },
+    Playing { recording: InputRecording, next: usize, time: f64 },
+}
+
+// Records live input events, or feeds a recording back into InputState on a fixed timestep
+pub struct InputRecorder {
+    mode: Mode,
+}
+
+impl InputRecorder {
+    pub fn new() -> Self {
+        Self { mode: Mode::Idle }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.mode, Mode::Recording { .. })
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.mode, Mode::Playing { .. })
+    }
+
+    pub fn start_recording(&mut self) {
+        self.mode = Mode::Recording {
+            start: Instant::now(),
+            recording: InputRecording::default(),
+        };
+    }
+
+    /// Ends the recording in progress and returns it.
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        match std::mem::replace(&mut self.mode, Mode::Idle) {
+            Mode::Recording { recording, .. } => Some(recording),
+            other => {
+                self.mode = other;
+                None
+            }
+        }
+    }
+
+    // Called for every live event; ignored unless recording
+    pub fn record(&mut self, event: &InputEvent) {
+        if let Mode::Recording { start, recording } = &mut self.mode {
+            recording.events.push(TimedEvent {
+                time: start.elapsed().as_secs_f64(),
+                event: *event,
+            });
+        }
+    }
+
+    pub fn start_playback(&mut self, recording: InputRecording) {
+        self.mode = Mode::Playing {
+            recording,
+            next: 0,
+            time: 0.0,
+        };
+    }
+
+    pub fn stop_playback(&mut self) {
+        if self.is_playing() {
+            self.mode = Mode::Idle;
+        }
+    }
+
+    /// Applies the events due by the end of this frame and returns the fixed frame time to
+    /// simulate with, or None when not playing. Playback stops once every event was applied.
+    pub fn advance(&mut self, input: &mut InputState) -> Option<f32> {
+        let Mode::Playing { recording, next, time } = &mut self.mode else {
+            return None;
+        };
+        *time += PLAYBACK_STEP as f64;
+        while let Some(timed) = recording.events.get(*next).filter(|e| e.time <= *time) {
+            input.apply(&timed.event);
+            *next += 1;
+        }
+        if *next >= recording.events.len() {
+            log::info!("Input playback finished after {:.2}s", recording.duration());
+            self.mode = Mode::Idle;
+        }
+        Some(PLAYBACK_STEP)
+    }
+}
+
+impl Default for InputRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
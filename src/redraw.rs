@@ -0,0 +1,63 @@
+// redraw.rs
+
+use crate::camera::Camera;
+use crate::transform::Transform;
+use std::time::Duration;
+
+// How often an idle window still runs the frame loop without drawing, to pick up controllers, file watchers
+// and background work that can change the picture without a window event
+pub const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// What the scene is drawn from that the render loop can change without the UI noticing
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FrameInputs {
+    pub camera: Camera,
+    pub transform: Transform,
+    pub surface_size: (u32, u32),
+}
+
+// Tells whether a frame would look the same as the last one drawn, so redraw-on-demand can skip it. Changes
+// are noticed either by comparing the frame's inputs or by whatever made them calling `mark_dirty`
+#[derive(Debug)]
+pub struct RedrawTracker {
+    dirty: bool,
+    last_drawn: Option<FrameInputs>,
+    skipped: u64,
+}
+
+impl RedrawTracker {
+    pub fn new() -> Self {
+        Self {
+            dirty: true,
+            last_drawn: None,
+            skipped: 0,
+        }
+    }
+
+    /// Forces the next frame to be drawn, for changes `FrameInputs` does not cover.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Whether a frame built from `inputs` needs drawing. A frame that does is remembered as the last one
+    /// drawn; one that doesn't is counted as skipped.
+    pub fn should_draw(&mut self, inputs: FrameInputs) -> bool {
+        if !self.dirty && self.last_drawn == Some(inputs) {
+            self.skipped += 1;
+            return false;
+        }
+        self.dirty = false;
+        self.last_drawn = Some(inputs);
+        true
+    }
+
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+impl Default for RedrawTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,569 @@
+// reflection_probes.rs
+
+use crate::camera::{Camera, CameraUniform};
+use crate::depth::DEPTH_FORMAT;
+use crate::stats::RenderStats;
+use crate::transform::Transform;
+use crate::upload::Uploads;
+use crate::world::{self, GlobalTransform, Light, MaterialHandle, MeshHandle};
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::Vec3;
+use hecs::{Entity, World};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+// Probes the shader's uniform and the face texture have room for; past this they are left out
+pub const MAX_PROBES: usize = 8;
+
+// Side of each captured cube face, in texels
+const PROBE_SIZE: u32 = 128;
+
+// The cube faces as the camera looking down each axis, with the up it is drawn with. The shader's FACES list
+// must match, since it picks the face to sample the same way
+const FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::NEG_Z),
+    (Vec3::NEG_Y, Vec3::Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
+// A point the scene is captured from into a cubemap. Surfaces within its radius reflect the capture of the
+// nearest probe that reaches them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReflectionProbe {
+    pub radius: f32,       // How far from the probe surfaces use it, fading out towards the edge
+    pub intensity: f32,    // Brightness of the reflection
+    pub auto_update: bool, // Recapture whenever the scene changes, rather than only when asked
+}
+
+impl Default for ReflectionProbe {
+    fn default() -> Self {
+        Self {
+            radius: 10.0,
+            intensity: 1.0,
+            auto_update: false,
+        }
+    }
+}
+
+// A probe as laid out in the shader's `Probes` uniform
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct GpuProbe {
+    position: [f32; 4], // w: radius
+    params: [f32; 4],   // Intensity, and the face texture layer of its +X face
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct ProbesUniform {
+    count: u32,
+    environment: [f32; 2], // Intensity and rotation of the environment image, for where no probe reaches
+    _padding: u32,
+    probes: [GpuProbe; MAX_PROBES],
+}
+
+// The list of probes in the world
+pub struct ReflectionProbes {
+    requests: Vec<Entity>, // Probes to capture again, taken by the renderer
+}
+
+impl ReflectionProbes {
+    pub fn new() -> Self {
+        Self { requests: Vec::new() }
+    }
+
+    /// Spawns a probe at `position`; it is captured once it has a slot.
+    pub fn place(&mut self, world: &mut World, position: Vec3) -> Entity {
+        let transform = Transform {
+            translation: position,
+            ..Transform::IDENTITY
+        };
+        world.spawn((transform, GlobalTransform(transform.matrix()), ReflectionProbe::default()))
+    }
+
+    /// The probes the UI asked to capture since the last call.
+    pub fn take_requests(&mut self) -> Vec<Entity> {
+        std::mem::take(&mut self.requests)
+    }
+
+    /// The Reflection Probes window: lists every probe in the world, adds new ones at `placement`, and edits
+    /// and recaptures them.
+    pub fn show(&mut self, ctx: &Context, world: &mut World, placement: Vec3) {
+        egui::Window::new("Reflection Probes")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                let mut probes: Vec<Entity> =
+                    world.query::<(Entity, &ReflectionProbe)>().iter().map(|(e, _)| e).collect();
+                probes.sort();
+                ui.horizontal(|ui| {
+                    if ui.button("Add probe").clicked() {
+                        self.place(world, placement);
+                    }
+                    if ui.add_enabled(!probes.is_empty(), egui::Button::new("Capture all")).clicked() {
+                        self.requests.extend(&probes);
+                    }
+                });
+                if probes.len() > MAX_PROBES {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!("Probes past the first {MAX_PROBES} are not captured"),
+                    );
+                }
+
+                let mut removed = None;
+                for (index, entity) in probes.into_iter().enumerate() {
+                    egui::CollapsingHeader::new(format!("Probe {}", index + 1)).id_source(entity).show(ui, |ui| {
+                        ui.push_id(entity, |ui| {
+                            if let Ok(mut probe) = world.get::<&mut ReflectionProbe>(entity) {
+                                ui.add(egui::Slider::new(&mut probe.radius, 0.5..=100.0).text("Radius"));
+                                ui.add(egui::Slider::new(&mut probe.intensity, 0.0..=4.0).text("Intensity"));
+                                ui.checkbox(&mut probe.auto_update, "Recapture when the scene changes");
+                            }
+                            let mut transform = world::transform_mut(world, entity);
+                            ui.horizontal(|ui| {
+                                ui.label("Position");
+                                for axis in 0..3 {
+                                    ui.add(egui::DragValue::new(&mut transform.translation[axis]).speed(0.01));
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                if ui.button("Capture").clicked() {
+                                    self.requests.push(entity);
+                                }
+                                if ui.button("Remove").clicked() {
+                                    removed = Some(entity);
+                                }
+                            });
+                        });
+                    });
+                }
+                if let Some(entity) = removed {
+                    let _ = world.despawn(entity);
+                }
+            });
+    }
+}
+
+impl Default for ReflectionProbes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A probe's place in the face texture
+struct ProbeSlot {
+    index: usize,
+    position: Vec3,
+    captured: bool,
+    dirty: bool, // Waiting to be captured
+}
+
+// Captures probes into layers of one 2D array texture, six faces each, and binds them with their positions for
+// the main shader to reflect, along with the environment image's cubemap for where none reaches. A cube texture
+// array would read more simply but not every backend has them
+pub struct ProbeRenderer {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    face_views: Vec<wgpu::TextureView>, // One per layer, drawn into when capturing
+    faces_view: wgpu::TextureView,      // Every layer, as the main shader reads them
+    depth_view: wgpu::TextureView,
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    bind_group: wgpu::BindGroup,
+    capture_bind_group: wgpu::BindGroup, // No probes, bound while the faces are being drawn into
+    environment: [f32; 2],               // Intensity and rotation of the environment image; 0 intensity without one
+    face_cameras: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+    slots: HashMap<Entity, ProbeSlot>,
+    next: usize,    // Slot index the round of dirty probes resumes from
+    signature: u64, // Of the scene at the last update
+}
+
+impl ProbeRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        format: wgpu::TextureFormat,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights: &wgpu::Buffer,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Probe Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let face_texture = |stats: &mut RenderStats, label, size, layers| {
+            stats.create_texture(
+                device,
+                &wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: size,
+                        height: size,
+                        depth_or_array_layers: layers,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+            )
+        };
+        let layers = (MAX_PROBES * FACES.len()) as u32;
+        let faces = face_texture(stats, "Probe Face Texture", PROBE_SIZE, layers);
+        // Two layers, so the view is an array on backends that guess the view dimension from the layer count
+        let empty = face_texture(stats, "Empty Probe Face Texture", 1, 2);
+        let face_views = (0..layers)
+            .map(|layer| {
+                faces.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Probe Face View"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let array_view = |texture: &wgpu::Texture| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            })
+        };
+        let depth = stats.create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Probe Depth Texture"),
+                size: wgpu::Extent3d {
+                    width: PROBE_SIZE,
+                    height: PROBE_SIZE,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            },
+        );
+        let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+        let uniform_buffer = |stats: &mut RenderStats, label| {
+            stats.create_buffer(
+                device,
+                &wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size: size_of::<ProbesUniform>() as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                },
+            )
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Probe Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        // Black until an environment image is set
+        let no_environment = face_texture(stats, "Empty Environment Texture", 1, 6);
+        let no_environment = no_environment.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let buffer = uniform_buffer(stats, "Probe Uniform Buffer");
+        let faces_view = array_view(&faces);
+        let bind_group =
+            probe_bind_group(device, &bind_group_layout, &buffer, &faces_view, &no_environment, &sampler);
+        // Zeroed on creation, so it holds no probes and no environment
+        let empty_buffer = uniform_buffer(stats, "Empty Probe Uniform Buffer");
+        let capture_bind_group = probe_bind_group(
+            device,
+            &bind_group_layout,
+            &empty_buffer,
+            &array_view(&empty),
+            &no_environment,
+            &sampler,
+        );
+
+        // A camera of its own per face, since every face is drawn before the frame's writes land
+        let face_cameras = FACES
+            .iter()
+            .map(|_| {
+                let buffer = stats.create_buffer(
+                    device,
+                    &wgpu::BufferDescriptor {
+                        label: Some("Probe Camera Buffer"),
+                        size: size_of::<CameraUniform>() as wgpu::BufferAddress,
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    },
+                );
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Probe Camera Bind Group"),
+                    layout: camera_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: lights.as_entire_binding(),
+                        },
+                    ],
+                });
+                (buffer, bind_group)
+            })
+            .collect();
+        Self {
+            bind_group_layout,
+            face_views,
+            faces_view,
+            depth_view,
+            uniform_buffer: buffer,
+            sampler,
+            bind_group,
+            capture_bind_group,
+            environment: [0.0; 2],
+            face_cameras,
+            slots: HashMap::new(),
+            next: 0,
+            signature: 0,
+        }
+    }
+
+    /// The probes for the main shader's group 3.
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Binds the environment image's cubemap `view` in place of the last one. Anything recorded against the
+    /// old bind group still shows the old image.
+    pub fn set_environment_map(&mut self, device: &wgpu::Device, view: &wgpu::TextureView) {
+        let layout = &self.bind_group_layout;
+        self.bind_group =
+            probe_bind_group(device, layout, &self.uniform_buffer, &self.faces_view, view, &self.sampler);
+    }
+
+    /// How bright the environment image shows where no probe reaches, 0 to leave it out, and how many radians
+    /// it is turned about the vertical.
+    pub fn set_environment_light(&mut self, intensity: f32, rotation: f32) {
+        self.environment = [intensity, rotation];
+    }
+
+    /// Gives new probes in `world` a slot and marks the ones that need capturing: new probes, those in
+    /// `requests`, and those updating automatically once the scene has changed. Returns whether one is waiting.
+    pub fn update(&mut self, world: &World, background: Vec3, requests: &[Entity]) -> bool {
+        let signature = scene_signature(world, background);
+        let changed = signature != self.signature;
+        self.signature = signature;
+
+        let mut query = world.query::<(Entity, &ReflectionProbe, &GlobalTransform)>();
+        let mut probes: Vec<(Entity, &ReflectionProbe, &GlobalTransform)> = query.iter().collect();
+        probes.sort_by_key(|(entity, _, _)| *entity);
+        self.slots.retain(|entity, _| probes.iter().any(|(probe, _, _)| probe == entity));
+        for (entity, probe, global) in probes {
+            let position = global.0.w_axis.truncate();
+            if !self.slots.contains_key(&entity) {
+                let Some(index) = (0..MAX_PROBES).find(|index| self.slots.values().all(|slot| slot.index != *index))
+                else {
+                    continue;
+                };
+                let slot = ProbeSlot {
+                    index,
+                    position,
+                    captured: false,
+                    dirty: true,
+                };
+                self.slots.insert(entity, slot);
+            }
+            let slot = self.slots.get_mut(&entity).expect("slot assigned above");
+            let moved = slot.position != position;
+            slot.position = position;
+            if requests.contains(&entity) || (probe.auto_update && (changed || moved)) {
+                slot.dirty = true;
+            }
+        }
+        self.slots.values().any(|slot| slot.dirty)
+    }
+
+    /// Captures the next waiting probe, one a frame, drawing each face with the bundles `record` returns for a
+    /// face's camera group and the probe group to draw with. Then binds every captured probe in `world` for the
+    /// main shader. Returns whether a probe was captured.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        world: &World,
+        background: Vec3,
+        far: f32,
+        mut record: impl FnMut(&wgpu::BindGroup, &wgpu::BindGroup) -> Vec<wgpu::RenderBundle>,
+    ) -> bool {
+        // Round robin from the slot after the last capture, so probes recapturing every frame take turns
+        let next = (0..MAX_PROBES).map(|offset| (self.next + offset) % MAX_PROBES).find_map(|index| {
+            self.slots.iter().find(|(_, slot)| slot.index == index && slot.dirty).map(|(entity, _)| *entity)
+        });
+        let captured = next.is_some();
+        if let Some(slot) = next.and_then(|entity| self.slots.get_mut(&entity)) {
+            for (face, ((forward, up), (buffer, camera_group))) in FACES.iter().zip(&self.face_cameras).enumerate() {
+                let camera = Camera {
+                    up: *up,
+                    aspect: 1.0,
+                    fovy: 90.0,
+                    znear: 0.05,
+                    zfar: far,
+                    ..Camera::new(slot.position, slot.position + *forward, 0.0)
+                };
+                let mut uniform = CameraUniform::new();
+                uniform.update_view_proj(&camera);
+                uploads.write(device, buffer, 0, bytemuck::bytes_of(&uniform));
+                let bundles = record(camera_group, &self.capture_bind_group);
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Probe Capture Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &self.face_views[slot.index * FACES.len() + face],
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color {
+                                r: background.x as f64,
+                                g: background.y as f64,
+                                b: background.z as f64,
+                                a: 1.0,
+                            }),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.depth_view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
+                pass.execute_bundles(&bundles);
+            }
+            slot.captured = true;
+            slot.dirty = false;
+            self.next = slot.index + 1;
+        }
+
+        let mut uniform = ProbesUniform::zeroed();
+        uniform.environment = self.environment;
+        for (entity, slot) in self.slots.iter().filter(|(_, slot)| slot.captured) {
+            let Ok(probe) = world.get::<&ReflectionProbe>(*entity) else {
+                continue;
+            };
+            uniform.probes[uniform.count as usize] = GpuProbe {
+                position: slot.position.extend(probe.radius).to_array(),
+                params: [probe.intensity, (slot.index * FACES.len()) as f32, 0.0, 0.0],
+            };
+            uniform.count += 1;
+        }
+        uploads.write(device, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        captured
+    }
+}
+
+fn probe_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffer: &wgpu::Buffer,
+    faces: &wgpu::TextureView,
+    environment: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Probe Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(faces),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(environment),
+            },
+        ],
+    })
+}
+
+// Changes whenever a mesh, its material, a light or the background does, so automatic probes know to recapture
+fn scene_signature(world: &World, background: Vec3) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for (entity, _, material, global) in
+        world.query::<(Entity, &MeshHandle, &MaterialHandle, &GlobalTransform)>().iter()
+    {
+        entity.hash(&mut hasher);
+        material.0.hash(&mut hasher);
+        global.0.to_cols_array().map(f32::to_bits).hash(&mut hasher);
+    }
+    for (light, global) in world.query::<(&Light, &GlobalTransform)>().iter() {
+        light.enabled.hash(&mut hasher);
+        [light.color.x, light.color.y, light.color.z, light.intensity].map(f32::to_bits).hash(&mut hasher);
+        global.0.to_cols_array().map(f32::to_bits).hash(&mut hasher);
+    }
+    background.to_array().map(f32::to_bits).hash(&mut hasher);
+    hasher.finish()
+}
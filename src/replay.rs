@@ -0,0 +1,355 @@
+// replay.rs
+
+use crate::animation::Animation;
+use crate::camera_controller::CameraController;
+use crate::input::{InputEvent, InputState};
+use crate::morph::PolygonMorph;
+use crate::physics::PhysicsSnapshot;
+use crate::scene::SceneFile;
+use crate::sequencer::Sequencer;
+use egui::Context;
+
+// Frames between keyframes. Seeking restores the keyframe at or before the target and simulates the rest, so
+// this bounds how many frames a seek takes to catch up
+const KEYFRAME_INTERVAL: usize = 15;
+
+// Ten minutes at 60 frames per second; recording stops there so keyframes can't use up memory
+const MAX_FRAMES: usize = 36_000;
+
+// Everything the fixed update reads and writes that isn't rebuilt from the scene, as it stood at the start of a
+// frame before the frame's input was applied
+#[derive(Clone)]
+pub struct SessionSnapshot {
+    pub scene: SceneFile,
+    pub input: InputState,
+    pub camera_controller: CameraController,
+    pub morph: PolygonMorph,
+    pub animation: Animation,
+    pub sequencer: Sequencer,
+    pub physics: PhysicsSnapshot,
+    pub hour: f32, // Of the day/night cycle
+}
+
+// One recorded frame: the input that arrived before it and the time step it was simulated with
+struct SessionFrame {
+    time: f64, // Seconds since the session started, at the start of the frame
+    dt: f32,
+    events: Vec<InputEvent>,
+}
+
+/// What the render loop does with this frame's update.
+pub enum SessionStep {
+    /// Simulate live with this time step.
+    Live(f32),
+    /// Simulate live, after passing a snapshot of the frame's starting state to `add_keyframe`.
+    Keyframe(f32),
+    /// Simulate a recorded frame with its own time step.
+    Replay(f32),
+    /// Simulate a recorded frame that has a keyframe, after passing the scene to `verify`.
+    Verify(f32),
+    /// Restore the snapshot and hold still this frame.
+    Restore(Box<SessionSnapshot>),
+    /// Hold still: reviewing, and the cursor isn't moving.
+    Paused,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Mode {
+    Live,
+    Recording,
+    Reviewing,
+}
+
+// Records a session frame by frame, with its input and time steps plus keyframes of the simulation, so it can be
+// scrubbed back and forth and resumed from any frame. Replayed frames take the same steps through the same
+// fixed update as when they were recorded, so they come out the same
+pub struct SessionReplay {
+    mode: Mode,
+    frames: Vec<SessionFrame>,
+    keyframes: Vec<(usize, SessionSnapshot)>, // By frame, ascending
+    pending: Vec<InputEvent>,                 // Arrived since the last frame was recorded
+    cursor: usize,                            // Frames simulated; the next one to replay
+    current: Option<usize>,                   // Frame being replayed this update
+    playing: bool,
+    seek: Option<usize>,   // Frame to restore the nearest keyframe for
+    target: Option<usize>, // Frame being caught up to after a restore
+    restored: Option<usize>, // Keyframe restored last, which needs no verifying against itself
+    diverged: Option<usize>, // First keyframe the replay didn't match
+}
+
+impl SessionReplay {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Live,
+            frames: Vec::new(),
+            keyframes: Vec::new(),
+            pending: Vec::new(),
+            cursor: 0,
+            current: None,
+            playing: false,
+            seek: None,
+            target: None,
+            restored: None,
+            diverged: None,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.mode == Mode::Recording
+    }
+
+    /// Whether a recorded session drives the simulation, so live input must be left out.
+    pub fn is_reviewing(&self) -> bool {
+        self.mode == Mode::Reviewing
+    }
+
+    /// Whether replayed frames are coming, so redraw-on-demand must keep drawing.
+    pub fn is_advancing(&self) -> bool {
+        self.is_reviewing() && (self.playing || self.seek.is_some() || self.target.is_some())
+    }
+
+    /// Starts a new session from the current state, or stops the one being recorded to review it.
+    pub fn toggle_recording(&mut self) {
+        match self.mode {
+            Mode::Recording => self.stop_recording(),
+            Mode::Live | Mode::Reviewing => {
+                *self = Self::new();
+                self.mode = Mode::Recording;
+            }
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        // Input that came after the last frame was never simulated
+        self.pending.clear();
+        self.mode = Mode::Reviewing;
+        self.cursor = self.frames.len();
+        self.playing = false;
+        log::info!("Recorded a session of {} frames over {:.1}s", self.frames.len(), self.duration());
+    }
+
+    /// Live input event. Recorded and held back until the frame's update while recording, applied at once
+    /// otherwise.
+    pub fn feed(&mut self, event: &InputEvent, input: &mut InputState) {
+        if self.mode == Mode::Recording {
+            self.pending.push(*event);
+        } else {
+            input.apply(event);
+        }
+    }
+
+    /// Decides this frame's update; call where the simulation takes its time step, then `apply_events` once
+    /// the step was handled.
+    pub fn begin_frame(&mut self, dt: f32) -> SessionStep {
+        self.current = None;
+        match self.mode {
+            Mode::Live => SessionStep::Live(dt),
+            Mode::Recording => {
+                if self.frames.len() >= MAX_FRAMES {
+                    log::warn!("Session recording reached {MAX_FRAMES} frames; stopping it");
+                    self.stop_recording();
+                    return SessionStep::Paused;
+                }
+                let index = self.frames.len();
+                let time = self.frames.last().map_or(0.0, |frame| frame.time + frame.dt as f64);
+                self.frames.push(SessionFrame { time, dt, events: Vec::new() });
+                self.current = Some(index);
+                if index.is_multiple_of(KEYFRAME_INTERVAL) {
+                    SessionStep::Keyframe(dt)
+                } else {
+                    SessionStep::Live(dt)
+                }
+            }
+            Mode::Reviewing => {
+                if let Some(target) = self.seek.take() {
+                    let Some(index) = self.keyframes.iter().rposition(|(frame, _)| *frame <= target) else {
+                        return SessionStep::Paused;
+                    };
+                    let (frame, snapshot) = &self.keyframes[index];
+                    self.cursor = *frame;
+                    self.target = Some(target);
+                    self.restored = Some(*frame);
+                    return SessionStep::Restore(Box::new(snapshot.clone()));
+                }
+                let catching_up = self.target.is_some_and(|target| self.cursor < target);
+                if !catching_up {
+                    self.target = None;
+                }
+                if !(catching_up || self.playing) {
+                    return SessionStep::Paused;
+                }
+                let Some(frame) = self.frames.get(self.cursor) else {
+                    self.playing = false;
+                    return SessionStep::Paused;
+                };
+                let dt = frame.dt;
+                self.current = Some(self.cursor);
+                self.cursor += 1;
+                let restored = self.restored.take() == Some(self.cursor - 1);
+                if self.keyframe(self.cursor - 1).is_some() && !restored {
+                    SessionStep::Verify(dt)
+                } else {
+                    SessionStep::Replay(dt)
+                }
+            }
+        }
+    }
+
+    /// Applies the input of the frame being recorded or replayed.
+    pub fn apply_events(&mut self, input: &mut InputState) {
+        let Some(index) = self.current else {
+            return;
+        };
+        match self.mode {
+            Mode::Recording => {
+                for event in &self.pending {
+                    input.apply(event);
+                }
+                self.frames[index].events = std::mem::take(&mut self.pending);
+            }
+            Mode::Reviewing => {
+                for event in &self.frames[index].events {
+                    input.apply(event);
+                }
+            }
+            Mode::Live => {}
+        }
+    }
+
+    pub fn add_keyframe(&mut self, snapshot: SessionSnapshot) {
+        if let Some(index) = self.current {
+            self.keyframes.push((index, snapshot));
+        }
+    }
+
+    /// Compares the scene at the start of a replayed frame with the one recorded for it, and reports the first
+    /// frame where they differ: something the replay doesn't cover changed the simulation.
+    pub fn verify(&mut self, scene: &SceneFile) {
+        let Some(index) = self.current else {
+            return;
+        };
+        let Some(recorded) = self.keyframe(index) else {
+            return;
+        };
+        if self.diverged.is_some_and(|diverged| diverged <= index) {
+            return;
+        }
+        let text = |scene: &SceneFile| ron::to_string(scene).unwrap_or_default();
+        if text(scene) != text(&recorded.scene) {
+            log::warn!("Session replay diverged from the recording by frame {index}");
+            self.diverged = Some(index);
+        }
+    }
+
+    fn keyframe(&self, frame: usize) -> Option<&SessionSnapshot> {
+        let index = self.keyframes.binary_search_by_key(&frame, |(frame, _)| *frame).ok()?;
+        Some(&self.keyframes[index].1)
+    }
+
+    fn duration(&self) -> f64 {
+        self.frames.last().map_or(0.0, |frame| frame.time + frame.dt as f64)
+    }
+
+    fn time_at(&self, frame: usize) -> f64 {
+        self.frames.get(frame).map_or(self.duration(), |frame| frame.time)
+    }
+
+    fn seek(&mut self, frame: usize) {
+        self.mode = Mode::Reviewing;
+        self.seek = Some(frame.min(self.frames.len()));
+        self.playing = false;
+    }
+
+    // Records on from the cursor, dropping the frames after it
+    fn resume(&mut self) {
+        self.frames.truncate(self.cursor);
+        self.keyframes.retain(|(frame, _)| *frame < self.cursor);
+        self.diverged = self.diverged.filter(|diverged| *diverged < self.cursor);
+        self.pending.clear();
+        self.playing = false;
+        self.target = None;
+        self.mode = Mode::Recording;
+    }
+
+    /// The Session Replay window: records a session, scrubs through it, and resumes recording from any frame.
+    pub fn show(&mut self, ctx: &Context) {
+        egui::Window::new("Session Replay")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let record = if self.is_recording() { "⏹ Stop" } else { "⏺ Record session" };
+                    if ui.button(record).clicked() {
+                        self.toggle_recording();
+                    }
+                    if self.is_recording() {
+                        ui.colored_label(egui::Color32::from_rgb(230, 70, 70), "● REC");
+                        ui.label(format!("{} frames, {:.1}s", self.frames.len(), self.duration()));
+                    }
+                });
+                if self.frames.is_empty() || self.is_recording() {
+                    ui.weak("Records input and time steps each frame, with keyframes to scrub back to");
+                    return;
+                }
+
+                let seeking = self.seek.is_some() || self.target.is_some();
+                let mut frame = self.target.unwrap_or(self.cursor);
+                let slider = egui::Slider::new(&mut frame, 0..=self.frames.len()).text("frame");
+                if ui.add(slider).changed() {
+                    self.seek(frame);
+                }
+                ui.label(format!("{:.2}s of {:.2}s", self.time_at(frame), self.duration()));
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(frame > 0, egui::Button::new("⏮")).on_hover_text("Previous frame").clicked() {
+                        self.seek(frame - 1);
+                    }
+                    let play = if self.playing && self.is_reviewing() { "⏸" } else { "▶" };
+                    let at_end = self.cursor >= self.frames.len();
+                    if ui.add_enabled(!seeking && !at_end, egui::Button::new(play)).clicked() {
+                        self.playing = !(self.playing && self.is_reviewing());
+                        self.mode = Mode::Reviewing;
+                    }
+                    if ui.add_enabled(!at_end, egui::Button::new("⏭")).on_hover_text("Next frame").clicked() {
+                        if self.is_reviewing() && !seeking {
+                            // Already at the cursor's state, so one more replayed frame gets there
+                            self.target = Some(self.cursor + 1);
+                            self.playing = false;
+                        } else {
+                            self.seek(frame + 1);
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let resume = ui
+                        .add_enabled(self.is_reviewing() && !seeking, egui::Button::new("Resume from here"))
+                        .on_hover_text("Record on from this frame, dropping the frames after it");
+                    if resume.clicked() {
+                        self.resume();
+                    }
+                    if ui.add_enabled(self.is_reviewing(), egui::Button::new("Go live")).clicked() {
+                        self.mode = Mode::Live;
+                        self.playing = false;
+                        self.target = None;
+                    }
+                    if ui.button("Discard").clicked() {
+                        *self = Self::new();
+                    }
+                });
+                if let Some(diverged) = self.diverged {
+                    ui.colored_label(
+                        egui::Color32::from_rgb(230, 160, 60),
+                        format!("Replay diverged from the recording by frame {diverged}"),
+                    );
+                }
+                if !self.is_reviewing() {
+                    ui.weak("Live; scrubbing rewinds to the session");
+                }
+            });
+    }
+}
+
+impl Default for SessionReplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
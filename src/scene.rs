@@ -0,0 +1,290 @@
+// scene.rs
+
+use crate::billboards::Billboard;
+use crate::camera::{Camera, Projection, ORTHO_HEIGHT_RANGE};
+use crate::decals::Decal;
+use crate::mesh_builder::MeshShape;
+use crate::particles::ParticleEmitter;
+use crate::physics::{BodyKind, Collider};
+use crate::prefab::SavedInstance;
+use crate::primitives::Tessellation;
+use crate::reflection_probes::ReflectionProbe;
+use crate::transform::Transform;
+use crate::ui::RenderingStyle;
+use crate::world::LightKind;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Written to every saved scene. Files from before the scene was versioned count as version 1
+pub const SCENE_VERSION: u32 = 5;
+
+// The editable scene as written to disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneFile {
+    #[serde(default = "unversioned")]
+    pub version: u32,
+    // The edited object
+    pub rendering_style: RenderingStyle,
+    pub sides: u16,
+    pub radius: f32,
+    pub tessellation: Tessellation, // Of the primitive rendering styles
+    pub transform: Transform,
+    pub shader: Option<String>, // None keeps the current shader
+    pub body: Option<BodyKind>,
+    pub collider: Option<Collider>,
+    pub script: Option<PathBuf>,
+    pub material: Option<usize>, // Into `materials`; None keeps the current one
+    // The rest of the world
+    pub lights: Vec<SceneLight>, // The first is the sun
+    pub camera: Option<SceneCamera>, // None keeps the current view
+    pub background: Vec3,
+    pub emitters: Vec<SceneEmitter>,
+    pub decals: Vec<SceneDecal>,
+    pub billboards: Vec<SceneBillboard>,
+    pub probes: Vec<SceneProbe>,
+    pub prefabs: Vec<SavedInstance>,
+    pub materials: Vec<SceneMaterial>, // Every material, matched to the loaded ones by name and shader
+    pub nodes: Vec<SceneNode>, // Added in the Scene Tree
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneLight {
+    pub transform: Transform,
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub enabled: bool,
+    pub casts_shadows: bool,
+}
+
+// A material's parameters by name, ints as floats, and the file each of its texture slots shows by slot name.
+// Slots left out show plain white
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneMaterial {
+    pub name: String,
+    pub shader: String,
+    pub params: Vec<(String, Vec<f32>)>,
+    pub textures: Vec<(String, PathBuf)>,
+}
+
+// A node added in the Scene Tree. Its mesh is made again from its shape, and empty nodes have none
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneNode {
+    pub name: String,
+    pub parent: Option<NodeParent>,
+    pub transform: Transform, // Relative to the parent
+    pub shape: Option<MeshShape>,
+    pub material: Option<usize>, // Into `materials`
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeParent {
+    Object,      // The edited object
+    Node(usize), // Into `nodes`
+}
+
+// The camera's placement and lens; its aspect follows the window instead
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneCamera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub speed: f32,
+    #[serde(default)]
+    pub projection: Projection,
+    #[serde(default = "default_ortho_height")]
+    pub ortho_height: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneEmitter {
+    pub transform: Transform,
+    pub emitter: ParticleEmitter,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneDecal {
+    pub transform: Transform,
+    pub decal: Decal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneBillboard {
+    pub transform: Transform,
+    pub billboard: Billboard,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneProbe {
+    pub transform: Transform,
+    pub probe: ReflectionProbe,
+}
+
+// Scenes are RON unless saved with a .json extension
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SceneFormat {
+    Ron,
+    Json,
+}
+
+impl SceneFormat {
+    fn of(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => SceneFormat::Json,
+            _ => SceneFormat::Ron,
+        }
+    }
+}
+
+fn unversioned() -> u32 {
+    1
+}
+
+fn default_ortho_height() -> f32 {
+    5.0
+}
+
+impl Default for SceneFile {
+    fn default() -> Self {
+        Self {
+            version: SCENE_VERSION,
+            rendering_style: RenderingStyle::Polygon,
+            sides: 5,
+            radius: 0.5,
+            tessellation: Tessellation::default(),
+            transform: Transform::IDENTITY,
+            shader: None,
+            body: None,
+            collider: None,
+            script: None,
+            material: None,
+            lights: Vec::new(),
+            camera: None,
+            background: Vec3::new(0.1, 0.2, 0.3),
+            emitters: Vec::new(),
+            decals: Vec::new(),
+            billboards: Vec::new(),
+            probes: Vec::new(),
+            prefabs: Vec::new(),
+            materials: Vec::new(),
+            nodes: Vec::new(),
+        }
+    }
+}
+
+impl Default for SceneLight {
+    fn default() -> Self {
+        Self {
+            transform: Transform::IDENTITY,
+            kind: LightKind::Directional,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            enabled: true,
+            casts_shadows: false,
+        }
+    }
+}
+
+impl From<&Camera> for SceneCamera {
+    fn from(camera: &Camera) -> Self {
+        Self {
+            position: camera.position,
+            target: camera.target,
+            up: camera.up,
+            fovy: camera.fovy,
+            znear: camera.znear,
+            zfar: camera.zfar,
+            speed: camera.speed,
+            projection: camera.projection,
+            ortho_height: camera.ortho_height,
+        }
+    }
+}
+
+impl SceneCamera {
+    /// `camera` placed and shaped like this one, keeping its aspect.
+    pub fn apply(&self, camera: &Camera) -> Camera {
+        Camera {
+            position: self.position,
+            target: self.target,
+            up: self.up,
+            fovy: self.fovy,
+            znear: self.znear,
+            zfar: self.zfar,
+            speed: self.speed,
+            aspect: camera.aspect,
+            projection: self.projection,
+            ortho_height: self.ortho_height.clamp(*ORTHO_HEIGHT_RANGE.start(), *ORTHO_HEIGHT_RANGE.end()),
+        }
+    }
+}
+
+impl SceneFile {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let scene: Self = match SceneFormat::of(path) {
+            SceneFormat::Ron => ron::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            SceneFormat::Json => serde_json::from_str(&text)?,
+        };
+        scene.migrate()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = match SceneFormat::of(path) {
+            SceneFormat::Ron => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            SceneFormat::Json => serde_json::to_string_pretty(self)?,
+        };
+        fs::write(path, text)
+    }
+
+    /// Drops everything that names a file: the script, prefab instances, and decal, billboard and material
+    /// textures, which fall back to their built-in ones. Returns how many were dropped.
+    pub fn strip_paths(&mut self) -> usize {
+        let mut stripped = usize::from(self.script.take().is_some()) + self.prefabs.len();
+        self.prefabs.clear();
+        for material in &mut self.materials {
+            stripped += material.textures.len();
+            material.textures.clear();
+        }
+        let textures = self.decals.iter_mut().map(|decal| &mut decal.decal.texture);
+        for texture in textures.chain(self.billboards.iter_mut().map(|billboard| &mut billboard.billboard.texture)) {
+            stripped += usize::from(texture.take().is_some());
+        }
+        stripped
+    }
+
+    // Brings a scene written by an older version up to the current one
+    fn migrate(mut self) -> io::Result<Self> {
+        if self.version > SCENE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("scene is version {}, newer than the {SCENE_VERSION} this build reads", self.version),
+            ));
+        }
+        if self.version < 2 {
+            // Version 1 only saved the object; its scenes were lit by the default sun
+            self.lights = vec![SceneLight::default()];
+        }
+        if self.version < 4 {
+            // Version 3 and earlier saved no materials, so the object keeps the one it has
+            self.materials.clear();
+            self.material = None;
+        }
+        if self.version < 5 {
+            // Nodes added in the Scene Tree were only saved from version 5
+            self.nodes.clear();
+        }
+        self.version = SCENE_VERSION;
+        Ok(self)
+    }
+}
@@ -0,0 +1,400 @@
+// scene_tree.rs
+
+use crate::material::{MaterialId, Materials};
+use crate::mesh_builder::MeshShape;
+use crate::prefab::PrefabMeshes;
+use crate::primitives::{Primitive, Tessellation};
+use crate::scene::{NodeParent, SceneNode};
+use crate::transform::Transform;
+use crate::ui::transform_grid;
+use crate::world::{self, GlobalTransform, Light, MaterialHandle, MeshHandle, Name, Parent, Visible};
+use egui::Context;
+use glam::Vec3;
+use hecs::{Entity, World};
+use std::collections::{HashMap, HashSet};
+
+// Deepest hierarchy shown or removed, so a parent cycle can't loop forever
+const MAX_DEPTH: usize = 64;
+
+// What the Add menu can put in the scene
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum NewNode {
+    Empty,
+    Mesh(MeshShape),
+}
+
+// On the nodes added here, which are saved with the scene, numbered in the order they were added
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct TreeNode(u32);
+
+// The Scene Tree window: every entity with a transform, nested under its parent, and an inspector for the
+// selected one
+pub struct SceneTree {
+    selected: Option<Entity>,
+    under_selection: bool, // Whether added nodes become children of the selected node
+}
+
+impl SceneTree {
+    pub fn new() -> Self {
+        Self {
+            selected: None,
+            under_selection: true,
+        }
+    }
+
+    pub fn selected(&self) -> Option<Entity> {
+        self.selected
+    }
+
+    /// Selects `entity` in the tree, or nothing, as when it's clicked in the viewport.
+    pub fn select(&mut self, entity: Option<Entity>) {
+        self.selected = entity;
+    }
+
+    /// Shows the window. Roots are added at `placement`, children at their parent's origin. `protected`
+    /// entities, which the render loop holds on to, can be edited but not removed.
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        world: &mut World,
+        meshes: &mut PrefabMeshes,
+        materials: &Materials,
+        placement: Vec3,
+        protected: &[Entity],
+    ) {
+        egui::Window::new("Scene Tree")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                self.selected = self.selected.filter(|&entity| world.contains(entity));
+                let mut added = None;
+                ui.horizontal(|ui| {
+                    ui.menu_button("Add", |ui| {
+                        let Tessellation { segments, rings, .. } = Tessellation::default();
+                        let nodes = [
+                            ("Empty", NewNode::Empty),
+                            ("Cube", NewNode::Mesh(MeshShape::Cube)),
+                            ("Hexagon", NewNode::Mesh(MeshShape::Polygon { sides: 6, radius: 0.5 })),
+                            ("Sphere", NewNode::Mesh(MeshShape::Primitive(Primitive::UvSphere { segments, rings }))),
+                            ("Torus", NewNode::Mesh(MeshShape::Primitive(Primitive::Torus { segments, sides: rings }))),
+                        ];
+                        for (label, node) in nodes {
+                            if ui.button(label).clicked() {
+                                added = Some((label, node));
+                                ui.close_menu();
+                            }
+                        }
+                    });
+                    ui.add_enabled(
+                        self.selected.is_some(),
+                        egui::Checkbox::new(&mut self.under_selection, "Under selection"),
+                    );
+                });
+                if let Some((label, node)) = added {
+                    let parent = self.selected.filter(|_| self.under_selection);
+                    self.selected = Some(add_node(world, meshes, label, node, parent, placement));
+                }
+
+                ui.separator();
+                let children = children(world);
+                let mut roots: Vec<Entity> = world
+                    .query::<(Entity, &Transform, Option<&Parent>)>()
+                    .iter()
+                    .filter(|(_, _, parent)| parent.is_none_or(|parent| !world.contains(parent.0)))
+                    .map(|(entity, _, _)| entity)
+                    .collect();
+                roots.sort();
+                for root in roots {
+                    self.node_ui(ui, world, &children, root, 0);
+                }
+
+                ui.separator();
+                let Some(entity) = self.selected else {
+                    ui.weak("Select a node to edit it");
+                    return;
+                };
+                ui.push_id(entity, |ui| inspector_ui(ui, world, &children, materials, entity));
+                let removable = !protected.contains(&entity);
+                let remove = ui.add_enabled(removable, egui::Button::new("Remove")).on_hover_text(if removable {
+                    "Removes the node and everything under it"
+                } else {
+                    "The template's own entities can't be removed"
+                });
+                if remove.clicked() {
+                    for entity in subtree(&children, entity) {
+                        if !protected.contains(&entity) {
+                            meshes.free(world, entity);
+                            let _ = world.despawn(entity);
+                        }
+                    }
+                    self.selected = None;
+                }
+            });
+    }
+
+    fn node_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        world: &World,
+        children: &HashMap<Entity, Vec<Entity>>,
+        entity: Entity,
+        depth: usize,
+    ) {
+        let name = node_name(world, entity);
+        let selected = self.selected == Some(entity);
+        let below = children.get(&entity).filter(|_| depth < MAX_DEPTH);
+        let Some(below) = below else {
+            ui.horizontal(|ui| {
+                // Lines leaves up with the labels of nodes that have an expand arrow
+                ui.add_space(ui.spacing().indent);
+                if ui.selectable_label(selected, name).clicked() {
+                    self.selected = Some(entity);
+                }
+            });
+            return;
+        };
+        let id = ui.make_persistent_id(entity);
+        egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, true)
+            .show_header(ui, |ui| {
+                if ui.selectable_label(selected, name).clicked() {
+                    self.selected = Some(entity);
+                }
+            })
+            .body(|ui| {
+                for &child in below {
+                    self.node_ui(ui, world, children, child, depth + 1);
+                }
+            });
+    }
+}
+
+impl Default for SceneTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Name, parent, transform and material of the selected node
+fn inspector_ui(
+    ui: &mut egui::Ui,
+    world: &mut World,
+    children: &HashMap<Entity, Vec<Entity>>,
+    materials: &Materials,
+    entity: Entity,
+) {
+    ui.horizontal(|ui| {
+        ui.label("Name");
+        let mut name = node_name(world, entity);
+        if ui.text_edit_singleline(&mut name).changed() {
+            let _ = world.insert_one(entity, Name(name));
+        }
+    });
+
+    // Anything but the node itself and what's under it, which would make a cycle
+    let current = world.get::<&Parent>(entity).ok().map(|parent| parent.0).filter(|p| world.contains(*p));
+    let excluded: HashSet<Entity> = subtree(children, entity).into_iter().collect();
+    let mut candidates: Vec<Entity> = world
+        .query::<(Entity, &Transform)>()
+        .iter()
+        .map(|(candidate, _)| candidate)
+        .filter(|candidate| !excluded.contains(candidate))
+        .collect();
+    candidates.sort();
+    let mut parent = current;
+    egui::ComboBox::from_label("Parent")
+        .selected_text(current.map_or("None".into(), |parent| node_name(world, parent)))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut parent, None, "None");
+            for candidate in candidates {
+                ui.selectable_value(&mut parent, Some(candidate), node_name(world, candidate));
+            }
+        });
+    if parent != current {
+        // The local transform is kept, so the node moves with its new parent
+        if let Some(parent) = parent {
+            let _ = world.insert_one(entity, Parent(parent));
+        } else {
+            let _ = world.remove_one::<Parent>(entity);
+        }
+    }
+
+    if let Ok(mut transform) = world.get::<&mut Transform>(entity) {
+        transform_grid(ui, &mut transform);
+        if ui.button("Reset transform").clicked() {
+            *transform = Transform::IDENTITY;
+        }
+    }
+
+    if let Ok(mut material) = world.get::<&mut MaterialHandle>(entity) {
+        egui::ComboBox::from_label("Material")
+            .selected_text(&materials.get(material.0).name)
+            .show_ui(ui, |ui| {
+                for (id, candidate) in materials.iter() {
+                    ui.selectable_value(&mut material.0, id, &candidate.name);
+                }
+            });
+    }
+    if let Ok(shape) = world.get::<&MeshShape>(entity) {
+        let shape = match *shape {
+            MeshShape::Polygon { sides, .. } => format!("{sides}-sided polygon"),
+            MeshShape::Cube | MeshShape::TexturedCube => "Cube".to_string(),
+            MeshShape::Primitive(primitive) => primitive.label().to_string(),
+        };
+        ui.weak(format!("Mesh: {shape}"));
+    } else if world.get::<&MeshHandle>(entity).is_ok() {
+        ui.weak("Mesh: loaded from a file");
+    }
+    if let Ok(visible) = world.get::<&Visible>(entity) {
+        if !visible.0 {
+            ui.weak("Outside the view");
+        }
+    }
+}
+
+// Spawns a named node, under `parent` if given
+fn add_node(
+    world: &mut World,
+    meshes: &mut PrefabMeshes,
+    label: &str,
+    node: NewNode,
+    parent: Option<Entity>,
+    placement: Vec3,
+) -> Entity {
+    let transform = Transform {
+        translation: if parent.is_some() { Vec3::ZERO } else { placement },
+        ..Transform::IDENTITY
+    };
+    let global = GlobalTransform(transform.matrix());
+    let entity = match node {
+        NewNode::Empty => world.spawn((transform, global)),
+        NewNode::Mesh(shape) => {
+            // Drawn like the parent, or with the default material at the root
+            let material = parent
+                .and_then(|parent| world.get::<&MaterialHandle>(parent).ok().map(|material| *material))
+                .unwrap_or(MaterialHandle(MaterialId::DEFAULT));
+            let entity = world::spawn_object(world, transform, meshes.allocate(shape), material);
+            let _ = world.insert_one(entity, shape);
+            entity
+        }
+    };
+    let order = world.query::<&TreeNode>().iter().map(|node| node.0 + 1).max().unwrap_or(0);
+    let _ = world.insert(entity, (Name(format!("{label} {}", entity.id())), TreeNode(order)));
+    if let Some(parent) = parent {
+        let _ = world.insert_one(entity, Parent(parent));
+    }
+    entity
+}
+
+/// The nodes added in the Scene Tree, in the order they were added. A node under anything but another node or
+/// `object` is saved as a root.
+pub fn save_nodes(world: &World, object: Entity) -> Vec<SceneNode> {
+    let mut nodes: Vec<(TreeNode, Entity)> =
+        world.query::<(Entity, &TreeNode)>().iter().map(|(entity, node)| (*node, entity)).collect();
+    nodes.sort();
+    let indices: HashMap<Entity, usize> =
+        nodes.iter().enumerate().map(|(index, &(_, entity))| (entity, index)).collect();
+    nodes
+        .iter()
+        .map(|&(_, entity)| {
+            let parent = world.get::<&Parent>(entity).ok().and_then(|parent| match parent.0 {
+                parent if parent == object => Some(NodeParent::Object),
+                parent => indices.get(&parent).map(|&index| NodeParent::Node(index)),
+            });
+            SceneNode {
+                name: node_name(world, entity),
+                parent,
+                transform: world::transform(world, entity),
+                shape: world.get::<&MeshShape>(entity).ok().map(|shape| *shape),
+                material: world.get::<&MaterialHandle>(entity).ok().map(|material| material.0.index()),
+            }
+        })
+        .collect()
+}
+
+/// Replaces the nodes added in the Scene Tree with `nodes`, looking up their materials by index with `material`.
+/// Nodes whose material is gone draw with the default one.
+pub fn restore_nodes(
+    world: &mut World,
+    meshes: &mut PrefabMeshes,
+    nodes: &[SceneNode],
+    object: Entity,
+    material: impl Fn(usize) -> Option<MaterialId>,
+) {
+    let previous: Vec<Entity> = world.query::<(Entity, &TreeNode)>().iter().map(|(entity, _)| entity).collect();
+    for entity in previous {
+        meshes.free(world, entity);
+        let _ = world.despawn(entity);
+    }
+    let entities: Vec<Entity> = nodes
+        .iter()
+        .enumerate()
+        .map(|(order, node)| {
+            let entity = match node.shape {
+                None => world.spawn((node.transform, GlobalTransform(node.transform.matrix()))),
+                Some(shape) => {
+                    let id = node.material.and_then(&material).unwrap_or(MaterialId::DEFAULT);
+                    let mesh = meshes.allocate(shape);
+                    let entity = world::spawn_object(world, node.transform, mesh, MaterialHandle(id));
+                    let _ = world.insert_one(entity, shape);
+                    entity
+                }
+            };
+            let _ = world.insert(entity, (Name(node.name.clone()), TreeNode(order as u32)));
+            entity
+        })
+        .collect();
+    // Parents may come after their children, once a node has been moved under a later one
+    for (node, &entity) in nodes.iter().zip(&entities) {
+        let parent = match node.parent {
+            Some(NodeParent::Object) => Some(object),
+            Some(NodeParent::Node(index)) => entities.get(index).copied().filter(|&parent| parent != entity),
+            None => None,
+        };
+        if let Some(parent) = parent {
+            let _ = world.insert_one(entity, Parent(parent));
+        }
+    }
+}
+
+// Each entity's children, in spawn order
+fn children(world: &World) -> HashMap<Entity, Vec<Entity>> {
+    let mut children: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    for (entity, parent) in world.query::<(Entity, &Parent)>().iter() {
+        children.entry(parent.0).or_default().push(entity);
+    }
+    for below in children.values_mut() {
+        below.sort();
+    }
+    children
+}
+
+// `root` and everything under it
+fn subtree(children: &HashMap<Entity, Vec<Entity>>, root: Entity) -> Vec<Entity> {
+    let mut entities = vec![root];
+    let mut pending = vec![(root, 0)];
+    while let Some((entity, depth)) = pending.pop() {
+        for &child in children.get(&entity).into_iter().flatten().filter(|_| depth < MAX_DEPTH) {
+            if !entities.contains(&child) {
+                entities.push(child);
+                pending.push((child, depth + 1));
+            }
+        }
+    }
+    entities
+}
+
+// The entity's `Name`, or else what it is
+fn node_name(world: &World, entity: Entity) -> String {
+    if let Ok(name) = world.get::<&Name>(entity) {
+        return name.0.clone();
+    }
+    let kind = if let Ok(light) = world.get::<&Light>(entity) {
+        format!("{} light", light.kind.label())
+    } else if world.get::<&MeshHandle>(entity).is_ok() {
+        "Mesh".to_string()
+    } else {
+        "Node".to_string()
+    };
+    format!("{kind} {}", entity.id())
+}
@@ -0,0 +1,374 @@
+// scene_windows.rs
+
+use crate::camera::{Camera, CameraUniform};
+use crate::depth::DEPTH_FORMAT;
+use crate::egui_tools::{EguiRenderer, UserEvent};
+use crate::stats::RenderStats;
+use crate::upload::Uploads;
+use egui_wgpu::{wgpu, ScreenDescriptor};
+use glam::{Quat, Vec3};
+use std::collections::HashMap;
+use std::sync::Arc;
+use winit::event::WindowEvent;
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::{Window, WindowBuilder, WindowId};
+
+// Radians per point dragged, and the share of the distance to the target each point of scrolling closes
+const ORBIT_SPEED: f32 = 0.01;
+const ZOOM_SPEED: f32 = 0.002;
+
+// Where the scene is drawn before the window's UI shows it
+struct SceneTarget {
+    texture: wgpu::Texture,
+    depth: wgpu::Texture,
+    view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+    texture_id: egui::TextureId,
+}
+
+// A window of its own onto the scene, with its own camera and UI
+struct WindowContext {
+    window: Arc<Window>,
+    surface: wgpu::Surface<'static>,
+    config: wgpu::SurfaceConfiguration,
+    egui: EguiRenderer,
+    camera: Camera,
+    camera_buffer: wgpu::Buffer,
+    camera_group: wgpu::BindGroup,
+    target: Option<SceneTarget>,
+}
+
+/// Extra windows showing the scene from cameras of their own, like a second viewport from another angle. Each
+/// owns its surface and UI; events for them are routed here by window id, and they're drawn every frame.
+pub struct SceneWindows {
+    format: wgpu::TextureFormat, // The scene's, which the bundles drawn into the windows are recorded for
+    windows: HashMap<WindowId, WindowContext>,
+    opened: usize, // For the titles
+}
+
+impl SceneWindows {
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            windows: HashMap::new(),
+            opened: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    pub fn owns(&self, window_id: WindowId) -> bool {
+        self.windows.contains_key(&window_id)
+    }
+
+    /// Opens a window looking at the scene from `camera`, which it then moves on its own.
+    #[allow(clippy::too_many_arguments)]
+    pub fn open(
+        &mut self,
+        elwt: &EventLoopWindowTarget<UserEvent>,
+        instance: &wgpu::Instance,
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights: &wgpu::Buffer,
+        surface_format: wgpu::TextureFormat,
+        camera: Camera,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.opened += 1;
+        let window = WindowBuilder::new()
+            .with_title(format!("Scene View {}", self.opened))
+            .with_inner_size(winit::dpi::LogicalSize::new(640.0, 480.0))
+            .build(elwt)?;
+        let window = Arc::new(window);
+        let surface = instance.create_surface(window.clone())?;
+        let size = window.inner_size();
+        let capabilities = surface.get_capabilities(adapter);
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: wgpu::PresentMode::AutoVsync,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: capabilities.alpha_modes[0],
+            view_formats: vec![],
+        };
+        surface.configure(device, &config);
+
+        let camera_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Scene Window Camera Buffer"),
+                size: size_of::<CameraUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let camera_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Scene Window Camera Bind Group"),
+            layout: camera_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lights.as_entire_binding(),
+                },
+            ],
+        });
+
+        let egui = EguiRenderer::new(device, surface_format, None, 1, &window);
+        self.windows.insert(
+            window.id(),
+            WindowContext {
+                window,
+                surface,
+                config,
+                egui,
+                camera,
+                camera_buffer,
+                camera_group,
+                target: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Handles an event for one of the windows: resizing its surface, closing it, or passing it to its UI.
+    pub fn handle_event(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        window_id: WindowId,
+        event: &WindowEvent,
+    ) {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        match event {
+            WindowEvent::CloseRequested => {
+                if let Some(mut context) = self.windows.remove(&window_id) {
+                    context.release(stats);
+                    stats.buffer_destroyed(&context.camera_buffer);
+                }
+                return;
+            }
+            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                (context.config.width, context.config.height) = (size.width, size.height);
+                context.surface.configure(device, &context.config);
+            }
+            _ => {}
+        }
+        context.egui.handle_input(&context.window, event);
+    }
+
+    /// Draws the bundles `record` makes for each window's camera group into the window's scene target, sized to
+    /// the window. The targets are shown when the windows are presented.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        background: Vec3,
+        mut record: impl FnMut(&wgpu::BindGroup) -> Vec<wgpu::RenderBundle>,
+    ) {
+        for context in self.windows.values_mut() {
+            let size = (context.config.width, context.config.height);
+            if context.target.as_ref().is_none_or(|target| (target.texture.width(), target.texture.height()) != size) {
+                context.resize_target(device, stats, self.format, size);
+            }
+            let Some(target) = &context.target else {
+                continue;
+            };
+
+            context.camera.aspect = size.0 as f32 / size.1 as f32;
+            let mut uniform = CameraUniform::new();
+            uniform.update_view_proj(&context.camera);
+            uploads.write(device, &context.camera_buffer, 0, bytemuck::bytes_of(&uniform));
+            let bundles = record(&context.camera_group);
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Scene Window Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: background.x as f64,
+                            g: background.y as f64,
+                            b: background.z as f64,
+                            a: 1.0,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &target.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.execute_bundles(&bundles);
+        }
+    }
+
+    /// Draws each window's UI over its scene and presents it. Call after the frame drawing the scenes has been
+    /// submitted. `main_camera` is what "Match main view" copies.
+    pub fn present(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, main_camera: &Camera) {
+        puffin::profile_function!();
+        for context in self.windows.values_mut() {
+            let acquired = match context.surface.get_current_texture() {
+                Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                    context.surface.configure(device, &context.config);
+                    context.surface.get_current_texture()
+                }
+                acquired => acquired,
+            };
+            let surface_texture = match acquired {
+                Ok(texture) => texture,
+                Err(err) => {
+                    log::warn!("Skipping {} frame: {err}", context.window.title());
+                    continue;
+                }
+            };
+            let view = surface_texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let screen_descriptor = ScreenDescriptor {
+                size_in_pixels: [context.config.width, context.config.height],
+                pixels_per_point: context.window.scale_factor() as f32,
+            };
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Scene Window Encoder"),
+            });
+            let texture_id = context.target.as_ref().map(|target| target.texture_id);
+            let camera = &mut context.camera;
+            context.egui.draw(device, queue, &mut encoder, &context.window, &view, screen_descriptor, |ctx| {
+                scene_window_ui(ctx, texture_id, camera, main_camera);
+            });
+            queue.submit(Some(encoder.finish()));
+            surface_texture.present();
+        }
+    }
+}
+
+impl WindowContext {
+    fn resize_target(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        format: wgpu::TextureFormat,
+        (width, height): (u32, u32),
+    ) {
+        let previous = self.target.as_ref().map(|target| target.texture_id);
+        self.release(stats);
+        let descriptor = wgpu::TextureDescriptor {
+            label: Some("Scene Window Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        };
+        let texture = stats.create_texture(device, &descriptor);
+        let depth = stats.create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Scene Window Depth Texture"),
+                format: DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                ..descriptor
+            },
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let texture_id = match previous {
+            Some(id) => {
+                self.egui.update_texture(device, &view, wgpu::FilterMode::Linear, id);
+                id
+            }
+            None => self.egui.register_texture(device, &view, wgpu::FilterMode::Linear),
+        };
+        self.target = Some(SceneTarget {
+            depth_view: depth.create_view(&wgpu::TextureViewDescriptor::default()),
+            texture,
+            depth,
+            view,
+            texture_id,
+        });
+    }
+
+    fn release(&mut self, stats: &mut RenderStats) {
+        if let Some(target) = self.target.take() {
+            stats.texture_destroyed(&target.texture);
+            stats.texture_destroyed(&target.depth);
+        }
+    }
+}
+
+// The scene filling the window, orbited by dragging with the right or middle button and zoomed by scrolling, with
+// buttons to line the camera up
+fn scene_window_ui(ctx: &egui::Context, texture_id: Option<egui::TextureId>, camera: &mut Camera, main: &Camera) {
+    egui::CentralPanel::default().frame(egui::Frame::none()).show(ctx, |ui| {
+        let Some(texture_id) = texture_id else {
+            return;
+        };
+        let size = ui.available_size();
+        let image = egui::Image::new((texture_id, size)).sense(egui::Sense::drag());
+        let response = ui.add(image);
+        if response.dragged_by(egui::PointerButton::Secondary) || response.dragged_by(egui::PointerButton::Middle) {
+            let delta = response.drag_delta() * ORBIT_SPEED;
+            orbit(camera, -delta.x, -delta.y);
+        }
+        if response.hovered() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            let offset = camera.position - camera.target;
+            camera.position = camera.target + offset * (1.0 - ZOOM_SPEED).powf(scroll);
+        }
+    });
+    egui::Window::new("View").resizable(false).show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            let distance = (camera.position - camera.target).length();
+            for (label, direction) in [("Front", Vec3::Z), ("Top", Vec3::Y), ("Side", Vec3::X)] {
+                if ui.button(label).clicked() {
+                    camera.position = camera.target + direction * distance;
+                    // Looking straight down needs another up
+                    camera.up = if direction == Vec3::Y { Vec3::NEG_Z } else { Vec3::Y };
+                }
+            }
+        });
+        if ui.button("Match main view").clicked() {
+            *camera = *main;
+        }
+    });
+}
+
+// Turns the camera around its target by `yaw` about the up axis and `pitch` about its right axis, stopping short of
+// the poles
+fn orbit(camera: &mut Camera, yaw: f32, pitch: f32) {
+    let turned = Quat::from_axis_angle(camera.up, yaw) * (camera.position - camera.target);
+    let right = turned.cross(camera.up).normalize_or_zero();
+    let tilted = Quat::from_axis_angle(right, pitch) * turned;
+    if tilted.normalize_or_zero().dot(camera.up).abs() < 0.99 {
+        camera.position = camera.target + tilted;
+    } else {
+        camera.position = camera.target + turned;
+    }
+}
@@ -0,0 +1,173 @@
+// screenshot.rs
+
+use crate::buffer_pool::BufferPool;
+use crate::settings;
+use crate::stats::RenderStats;
+use egui_wgpu::wgpu;
+use image::RgbaImage;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use web_time::{SystemTime, UNIX_EPOCH};
+
+// A frame being copied into a CPU-readable buffer
+pub struct Screenshot {
+    buffer: Arc<wgpu::Buffer>, // Back in the pool once the screenshot is dropped
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    bgra: bool,
+}
+
+impl Screenshot {
+    /// Records a copy of `texture` into `encoder`. The texture needs `COPY_SRC` usage and a
+    /// 4-byte RGBA or BGRA format; read the result back once the encoder was submitted.
+    pub fn record(
+        device: &wgpu::Device,
+        pool: &mut BufferPool,
+        stats: &mut RenderStats,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> Self {
+        let (width, height) = (texture.width(), texture.height());
+        // Rows in a copy must start on 256-byte boundaries
+        let padded_bytes_per_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let size = padded_bytes_per_row as u64 * height as u64;
+        let usage = wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ;
+        let buffer = pool.acquire(device, stats, "Screenshot Buffer", usage, size);
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        let bgra = matches!(
+            texture.format(),
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            bgra,
+        }
+    }
+
+    /// Blocks until the copy finished and returns its pixels, still in the copy's padded layout. Browsers
+    /// can't block on the GPU, so there this fails.
+    pub fn read(self, device: &wgpu::Device) -> Result<ScreenshotPixels, wgpu::BufferAsyncError> {
+        let mapping = self.map();
+        // The mapping has finished once this returns, except in a browser, where it finishes later and the
+        // screenshot fails
+        device.poll(wgpu::Maintain::Wait);
+        mapping.try_read().unwrap_or(Err(wgpu::BufferAsyncError))
+    }
+
+    /// Starts mapping the copy without waiting for it, once the encoder was submitted.
+    pub fn map(self) -> MappingScreenshot {
+        // Pooled buffers can be larger than the copy
+        let size = self.padded_bytes_per_row as u64 * self.height as u64;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.buffer.slice(..size).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        MappingScreenshot {
+            screenshot: self,
+            receiver,
+        }
+    }
+}
+
+// A copy whose buffer is being mapped, readable once the device has been polled past it
+pub struct MappingScreenshot {
+    screenshot: Screenshot,
+    receiver: std::sync::mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl MappingScreenshot {
+    /// The copy's pixels, still in its padded layout, or None while the buffer is still being mapped.
+    pub fn try_read(&self) -> Option<Result<ScreenshotPixels, wgpu::BufferAsyncError>> {
+        let result = match self.receiver.try_recv() {
+            Ok(result) => result,
+            Err(std::sync::mpsc::TryRecvError::Empty) => return None,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => Err(wgpu::BufferAsyncError),
+        };
+        let shot = &self.screenshot;
+        Some(result.map(|()| {
+            let size = shot.padded_bytes_per_row as u64 * shot.height as u64;
+            let data = shot.buffer.slice(..size).get_mapped_range().to_vec();
+            shot.buffer.unmap();
+            ScreenshotPixels {
+                data,
+                width: shot.width,
+                height: shot.height,
+                padded_bytes_per_row: shot.padded_bytes_per_row,
+                bgra: shot.bgra,
+            }
+        }))
+    }
+}
+
+// A screenshot copied off the GPU, converted to RGBA8 on the job pool rather than the render loop
+pub struct ScreenshotPixels {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    bgra: bool,
+}
+
+impl ScreenshotPixels {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Tightly packed RGBA8 pixels.
+    pub fn into_rgba(self) -> Vec<u8> {
+        let row_bytes = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        for row in self.data.chunks(self.padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..row_bytes]);
+        }
+        if self.bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        pixels
+    }
+}
+
+// A screenshot converted on the job pool, and where it was written if it was meant for a file
+pub struct ConvertedScreenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    pub saved: Option<Result<PathBuf, String>>,
+}
+
+/// A new file in the `screenshots` directory next to the settings, named after the current time.
+pub fn screenshot_path() -> PathBuf {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    settings::settings_path("screenshots").join(format!("screenshot-{millis}.png"))
+}
+
+/// Writes tightly packed RGBA8 `pixels` to `path` as a PNG, creating its directory.
+pub fn save_png(path: &Path, width: u32, height: u32, pixels: Vec<u8>) -> Result<(), String> {
+    let image = RgbaImage::from_raw(width, height, pixels).ok_or("pixel data doesn't match the size")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+    image.save(path).map_err(|err| err.to_string())
+}
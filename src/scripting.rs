@@ -0,0 +1,338 @@
+// scripting.rs
+
+use crate::assets::{AssetEvent, Assets, Handle};
+use crate::camera::Camera;
+use crate::file_dialog::{FileDialogs, FileKind};
+use crate::jobs::FrameBudget;
+use crate::toasts::Toasts;
+use crate::transform::Transform;
+use crate::world::{self, Light};
+use egui::Context;
+use glam::{EulerRot, Quat, Vec3};
+use hecs::{Entity, World};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, FLOAT};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Operations one call may run before it is stopped, so a script stuck in a loop can't hang the frame
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+// The function every script defines; called once per frame with the frame time in seconds
+const UPDATE_FN: &str = "update";
+
+// Runs `path` each frame on the entity it is attached to. `state` keeps whatever the script stores in
+// `this.state` from one frame to the next
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub path: PathBuf,
+    state: Map,
+    started: bool,         // The script's top-level statements have run
+    error: Option<String>, // Set when the script fails; it is skipped until its file changes
+}
+
+// What a script sees as `this`: a copy of its entity's properties and the camera, written back after the call
+#[derive(Debug, Clone)]
+struct ScriptObject {
+    transform: Transform,
+    light: Option<Light>,
+    camera: ScriptCamera,
+    state: Map,
+    time: FLOAT,
+}
+
+#[derive(Debug, Clone)]
+struct ScriptCamera {
+    position: Vec3,
+    target: Vec3,
+    fovy: FLOAT, // Degrees
+}
+
+fn compile(path: &Path) -> io::Result<AST> {
+    let source = std::fs::read_to_string(path)?;
+    Engine::new()
+        .compile(source)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+fn no_light() -> Box<EvalAltResult> {
+    "this entity has no light".into()
+}
+
+// The API scripts are written against: `Vec3` math, and the entity, its light and the camera through `this`
+fn create_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| log::info!("script: {text}"));
+    engine.on_debug(|text, _, position| log::debug!("script at {position}: {text}"));
+
+    engine
+        .register_type_with_name::<Vec3>("Vec3")
+        .register_fn("vec3", |x: FLOAT, y: FLOAT, z: FLOAT| Vec3::new(x as f32, y as f32, z as f32))
+        .register_get_set("x", |v: &mut Vec3| v.x as FLOAT, |v: &mut Vec3, x: FLOAT| v.x = x as f32)
+        .register_get_set("y", |v: &mut Vec3| v.y as FLOAT, |v: &mut Vec3, y: FLOAT| v.y = y as f32)
+        .register_get_set("z", |v: &mut Vec3| v.z as FLOAT, |v: &mut Vec3, z: FLOAT| v.z = z as f32)
+        .register_fn("+", |a: Vec3, b: Vec3| a + b)
+        .register_fn("-", |a: Vec3, b: Vec3| a - b)
+        .register_fn("-", |v: Vec3| -v)
+        .register_fn("*", |v: Vec3, s: FLOAT| v * s as f32)
+        .register_fn("*", |s: FLOAT, v: Vec3| v * s as f32)
+        .register_fn("/", |v: Vec3, s: FLOAT| v / s as f32)
+        .register_fn("length", |v: Vec3| v.length() as FLOAT)
+        .register_fn("normalize", |v: Vec3| v.normalize_or_zero())
+        .register_fn("dot", |a: Vec3, b: Vec3| a.dot(b) as FLOAT)
+        .register_fn("cross", |a: Vec3, b: Vec3| a.cross(b))
+        .register_fn("to_string", |v: &mut Vec3| format!("({}, {}, {})", v.x, v.y, v.z))
+        .register_fn("to_debug", |v: &mut Vec3| format!("{v:?}"));
+
+    engine
+        .register_type_with_name::<ScriptObject>("Object")
+        .register_get_set(
+            "position",
+            |o: &mut ScriptObject| o.transform.translation,
+            |o: &mut ScriptObject, v: Vec3| o.transform.translation = v,
+        )
+        .register_get_set(
+            "scale",
+            |o: &mut ScriptObject| o.transform.scale,
+            |o: &mut ScriptObject, v: Vec3| o.transform.scale = v,
+        )
+        // Euler angles in radians, applied X then Y then Z
+        .register_get_set(
+            "rotation",
+            |o: &mut ScriptObject| Vec3::from(o.transform.rotation.to_euler(EulerRot::XYZ)),
+            |o: &mut ScriptObject, v: Vec3| o.transform.rotation = Quat::from_euler(EulerRot::XYZ, v.x, v.y, v.z),
+        )
+        .register_fn("rotate_x", |o: &mut ScriptObject, angle: FLOAT| {
+            o.transform.rotation = Quat::from_rotation_x(angle as f32) * o.transform.rotation;
+        })
+        .register_fn("rotate_y", |o: &mut ScriptObject, angle: FLOAT| {
+            o.transform.rotation = Quat::from_rotation_y(angle as f32) * o.transform.rotation;
+        })
+        .register_fn("rotate_z", |o: &mut ScriptObject, angle: FLOAT| {
+            o.transform.rotation = Quat::from_rotation_z(angle as f32) * o.transform.rotation;
+        })
+        .register_get("time", |o: &mut ScriptObject| o.time)
+        .register_get_set("state", |o: &mut ScriptObject| o.state.clone(), |o: &mut ScriptObject, m: Map| o.state = m)
+        .register_get("has_light", |o: &mut ScriptObject| o.light.is_some())
+        .register_get_set(
+            "light_color",
+            |o: &mut ScriptObject| o.light.map(|light| light.color).ok_or_else(no_light),
+            |o: &mut ScriptObject, v: Vec3| o.light.as_mut().map(|light| light.color = v).ok_or_else(no_light),
+        )
+        .register_get_set(
+            "light_intensity",
+            |o: &mut ScriptObject| o.light.map(|light| light.intensity as FLOAT).ok_or_else(no_light),
+            |o: &mut ScriptObject, s: FLOAT| {
+                o.light.as_mut().map(|light| light.intensity = s as f32).ok_or_else(no_light)
+            },
+        )
+        .register_get_set(
+            "light_enabled",
+            |o: &mut ScriptObject| o.light.map(|light| light.enabled).ok_or_else(no_light),
+            |o: &mut ScriptObject, b: bool| o.light.as_mut().map(|light| light.enabled = b).ok_or_else(no_light),
+        )
+        .register_get_set("camera", |o: &mut ScriptObject| o.camera.clone(), |o: &mut ScriptObject, c| o.camera = c);
+
+    engine
+        .register_type_with_name::<ScriptCamera>("Camera")
+        .register_get_set("position", |c: &mut ScriptCamera| c.position, |c: &mut ScriptCamera, v| c.position = v)
+        .register_get_set("target", |c: &mut ScriptCamera| c.target, |c: &mut ScriptCamera, v| c.target = v)
+        .register_get_set("fov", |c: &mut ScriptCamera| c.fovy, |c: &mut ScriptCamera, f| c.fovy = f);
+    engine
+}
+
+impl Script {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            state: Map::new(),
+            started: false,
+            error: None,
+        }
+    }
+}
+
+// Compiles the scripts attached to entities, reloads them when their files change, and runs them each frame
+pub struct Scripts {
+    engine: Engine,
+    assets: Assets<AST>,
+    loaded: HashMap<PathBuf, Handle<AST>>,
+    pub running: bool,
+    time: f32,  // Seconds the scripts have been running
+    ran: usize, // Scripts that ran at the last update, so redraw on demand keeps drawing while any do
+}
+
+impl Scripts {
+    pub fn new() -> Self {
+        Self {
+            engine: create_engine(),
+            assets: Assets::new(compile),
+            loaded: HashMap::new(),
+            running: true,
+            time: 0.0,
+            ran: 0,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.running && self.ran > 0
+    }
+
+    /// Runs the script at `path` on `entity` from the next frame on, replacing any script it had.
+    pub fn attach(&mut self, world: &mut World, entity: Entity, path: PathBuf) {
+        if !self.loaded.contains_key(&path) {
+            let handle = self.assets.load(&path, true);
+            self.loaded.insert(path.clone(), handle);
+        }
+        let _ = world.insert_one(entity, Script::new(path));
+    }
+
+    pub fn detach(&mut self, world: &mut World, entity: Entity) {
+        let _ = world.remove_one::<Script>(entity);
+    }
+
+    /// Applies finished compiles. A script whose file changed starts over with its top-level statements and
+    /// runs again even if it had failed; its `this.state` is kept.
+    pub fn poll(&mut self, budget: &mut FrameBudget, world: &mut World, toasts: &mut Toasts) {
+        for event in self.assets.poll(budget) {
+            let (handle, error) = match event {
+                AssetEvent::Loaded(handle) => (handle, None),
+                AssetEvent::Reloaded(handle) => {
+                    if let Some(path) = self.assets.path(handle) {
+                        toasts.info(format!("Reloaded script {}", path.display()));
+                    }
+                    (handle, None)
+                }
+                AssetEvent::Failed(handle, err) => (handle, Some(err)),
+            };
+            let Some(path) = self.assets.path(handle).map(Path::to_path_buf) else {
+                continue;
+            };
+            if let Some(err) = &error {
+                log::warn!("Failed to compile script {}: {err}", path.display());
+                toasts.error(format!("Failed to compile script: {err}"));
+            }
+            for script in world.query_mut::<&mut Script>().into_iter().filter(|script| script.path == path) {
+                script.started = false;
+                script.error.clone_from(&error);
+            }
+        }
+    }
+
+    /// Calls each attached script's `update(dt)` with `this` bound to its entity, then writes back what it
+    /// changed on the entity and `camera`.
+    pub fn update(&mut self, dt: f32, world: &mut World, camera: &mut Camera, toasts: &mut Toasts) {
+        self.ran = 0;
+        if !self.running {
+            return;
+        }
+        self.time += dt;
+        let mut scripted: Vec<(Entity, PathBuf)> = world
+            .query::<(Entity, &Script)>()
+            .iter()
+            .filter(|(_, script)| script.error.is_none())
+            .map(|(entity, script)| (entity, script.path.clone()))
+            .collect();
+        scripted.sort_by_key(|(entity, _)| *entity);
+        for (entity, path) in scripted {
+            let Some(ast) = self.loaded.get(&path).and_then(|&handle| self.assets.get(handle)) else {
+                continue; // Still compiling
+            };
+            let Ok(mut script) = world.get::<&mut Script>(entity).map(|script| (*script).clone()) else {
+                continue;
+            };
+            let object = ScriptObject {
+                transform: world::transform(world, entity),
+                light: world.get::<&Light>(entity).ok().map(|light| *light),
+                camera: ScriptCamera {
+                    position: camera.position,
+                    target: camera.target,
+                    fovy: camera.fovy as FLOAT,
+                },
+                state: std::mem::take(&mut script.state),
+                time: self.time as FLOAT,
+            };
+            let mut this = Dynamic::from(object);
+            // Top-level statements run before the first update after each compile
+            let options = CallFnOptions::new().eval_ast(!script.started).bind_this_ptr(&mut this);
+            let result =
+                self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, UPDATE_FN, (dt as FLOAT,));
+            script.started = true;
+            self.ran += 1;
+            let object = this.cast::<ScriptObject>();
+            script.state = object.state;
+            if let Err(err) = result {
+                log::warn!("Script {} failed: {err}", path.display());
+                toasts.error(format!("Script {} failed: {err}", file_name(&path)));
+                script.error = Some(err.to_string());
+            } else {
+                *world::transform_mut(world, entity) = object.transform;
+                if let (Some(new), Ok(mut light)) = (object.light, world.get::<&mut Light>(entity)) {
+                    *light = new;
+                }
+                camera.position = object.camera.position;
+                camera.target = object.camera.target;
+                camera.fovy = object.camera.fovy as f32;
+            }
+            if let Ok(mut current) = world.get::<&mut Script>(entity) {
+                *current = script;
+            }
+        }
+    }
+
+    /// The Scripts window: runs or pauses every script and attaches one to `object`.
+    pub fn show(&mut self, ctx: &Context, world: &mut World, object: Entity, file_dialogs: &mut FileDialogs) {
+        egui::Window::new("Scripts")
+            .default_open(false)
+            .resizable(false)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let label = if self.running { "⏸ Pause" } else { "▶ Run" };
+                    if ui.button(label).clicked() {
+                        self.running = !self.running;
+                    }
+                    ui.label(format!("{:.1} s", self.time));
+                });
+
+                ui.separator();
+                ui.strong("Object");
+                let attached = world.get::<&Script>(object).ok().map(|script| script.path.clone());
+                ui.horizontal(|ui| {
+                    match &attached {
+                        Some(path) => ui.label(file_name(path)),
+                        None => ui.weak("No script"),
+                    };
+                    if ui.add_enabled(!file_dialogs.is_pending(), egui::Button::new("Attach...")).clicked() {
+                        file_dialogs.open(FileKind::Script);
+                    }
+                    if attached.is_some() && ui.button("Detach").clicked() {
+                        self.detach(world, object);
+                    }
+                });
+                ui.weak("Scripts define fn update(dt) and change this.position, this.rotation, this.light_color, …");
+
+                let mut failed: Vec<(Entity, String, String)> = world
+                    .query::<(Entity, &Script)>()
+                    .iter()
+                    .filter_map(|(entity, script)| Some((entity, file_name(&script.path), script.error.clone()?)))
+                    .collect();
+                failed.sort_by_key(|(entity, _, _)| *entity);
+                for (_, name, error) in failed {
+                    ui.separator();
+                    ui.colored_label(ui.visuals().error_fg_color, format!("{name}: {error}"));
+                }
+            });
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,540 @@
+// sequencer.rs
+
+use crate::animation::{Animation, AnimationTargets, Property, Value, FIXED_STEP, MAX_STEPS};
+use crate::morph::Easing;
+use crate::skinning::SkinnedMesh;
+use egui::{Color32, Context, Sense, Stroke};
+use glam::Vec3;
+
+const TIMELINE_WIDTH: f32 = 360.0;
+const ROW_HEIGHT: f32 = 18.0;
+const HANDLE_WIDTH: f32 = 6.0; // Grabbing a strip this close to its end resizes it
+const DEFAULT_LENGTH: f32 = 2.0;
+
+// Everything a sequence drives: the animated properties, the keys of the Animation window and the skinned model
+pub struct SequencerTargets<'a> {
+    pub properties: AnimationTargets<'a>,
+    pub animation: &'a Animation,
+    pub skinned: Option<&'a mut SkinnedMesh>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct CameraPoint {
+    position: Vec3,
+    target: Vec3,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum StripKind {
+    Animation,            // The Animation window's keys, played from their start
+    Clip { clip: usize }, // A clip of the skinned model
+    // The camera passes through the points, spread evenly over the strip
+    CameraPath { points: Vec<CameraPoint>, easing: Easing },
+    // Eases one property from `from` to `to` over the strip; a strip of no length sets it at once
+    Change { property: Property, from: Value, to: Value, easing: Easing },
+}
+
+// What a strip writes to. Strips of the same target override each other, the one started last winning
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Target {
+    Animation,
+    Clip,
+    Camera,
+    Property(Property),
+}
+
+impl StripKind {
+    fn label(&self) -> String {
+        match self {
+            StripKind::Animation => "Animation".to_string(),
+            StripKind::Clip { clip } => format!("Clip {}", clip + 1),
+            StripKind::CameraPath { points, .. } => format!("Camera path ({} points)", points.len()),
+            StripKind::Change { property, .. } => property.label().to_string(),
+        }
+    }
+
+    fn target(&self) -> Target {
+        match self {
+            StripKind::Animation => Target::Animation,
+            StripKind::Clip { .. } => Target::Clip,
+            StripKind::CameraPath { .. } => Target::Camera,
+            StripKind::Change { property, .. } => Target::Property(*property),
+        }
+    }
+
+    fn color(&self) -> Color32 {
+        match self {
+            StripKind::Animation => Color32::from_rgb(90, 140, 220),
+            StripKind::Clip { .. } => Color32::from_rgb(150, 110, 210),
+            StripKind::CameraPath { .. } => Color32::from_rgb(80, 170, 120),
+            StripKind::Change { .. } => Color32::from_rgb(210, 150, 70),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Strip {
+    kind: StripKind,
+    start: f32,  // Seconds from the start of the sequence
+    length: f32, // Seconds; the strip holds its last state after it ends
+}
+
+impl Strip {
+    fn end(&self) -> f32 {
+        self.start + self.length
+    }
+
+    // Writes the strip's state `time` seconds after its start
+    fn apply(&self, time: f32, targets: &mut SequencerTargets) {
+        let t = if self.length > 0.0 { (time / self.length).clamp(0.0, 1.0) } else { 1.0 };
+        match &self.kind {
+            StripKind::Animation => targets.animation.apply_at(time, &mut targets.properties),
+            StripKind::Clip { clip } => {
+                if let Some(mesh) = targets.skinned.as_deref_mut() {
+                    mesh.pose(*clip, time);
+                }
+            }
+            StripKind::CameraPath { points, easing } => {
+                if let Some(point) = sample_path(points, easing.apply(t)) {
+                    targets.properties.camera.position = point.position;
+                    targets.properties.camera.target = point.target;
+                }
+            }
+            StripKind::Change {
+                property,
+                from,
+                to,
+                easing,
+            } => property.write(&mut targets.properties, from.interpolate(*to, easing.apply(t))),
+        }
+    }
+}
+
+// Catmull-Rom through the points, so the camera doesn't stop at each one
+fn sample_path(points: &[CameraPoint], t: f32) -> Option<CameraPoint> {
+    let last = points.len().checked_sub(1)?;
+    let along = t * last as f32;
+    let index = (along.floor() as usize).min(last.saturating_sub(1));
+    let t = along - index as f32;
+    let at = |offset: isize| points[(index as isize + offset).clamp(0, last as isize) as usize];
+    let (p0, p1, p2, p3) = (at(-1), at(0), at(1), at(2));
+    let spline = |a: Vec3, b: Vec3, c: Vec3, d: Vec3| {
+        let (t2, t3) = (t * t, t * t * t);
+        0.5 * (2.0 * b + (c - a) * t + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2 + (3.0 * b - a - 3.0 * c + d) * t3)
+    };
+    Some(CameraPoint {
+        position: spline(p0.position, p1.position, p2.position, p3.position),
+        target: spline(p0.target, p1.target, p2.target, p3.target),
+    })
+}
+
+// Arranges animations, model clips, camera paths and property changes on one timeline. Playback advances in
+// fixed steps and the state at any time depends on nothing but the strips, so a sequence plays back the same in
+// every run and demo recordings can be made again
+#[derive(Debug, Clone)]
+pub struct Sequencer {
+    pub playing: bool,
+    pub looping: bool,
+    pub duration: f32,
+    pub follow_recordings: bool, // Restart the sequence whenever an input recording starts or plays back
+    time: f32,
+    accumulator: f32, // Frame time not yet taken as a fixed step
+    moved: bool,      // Time or strips changed since the sequence was last applied
+    strips: Vec<Strip>,
+    selected: Option<usize>,
+    dragging: Option<(usize, Drag)>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Drag {
+    Move { grab: f32 }, // Seconds between the strip's start and the pointer
+    Resize,
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Self {
+            playing: false,
+            looping: false,
+            duration: 10.0,
+            follow_recordings: true,
+            time: 0.0,
+            accumulator: 0.0,
+            moved: false,
+            strips: Vec::new(),
+            selected: None,
+            dragging: None,
+        }
+    }
+
+    /// Plays the sequence from its start, if it has any strips.
+    pub fn restart(&mut self) {
+        self.seek(0.0);
+        self.accumulator = 0.0;
+        self.playing = !self.strips.is_empty();
+    }
+
+    /// Fixed update: advances playback by whole steps of frame time, then writes the state of every strip at
+    /// the new time into `targets`. Does nothing while paused unless the panel moved the time or edited strips.
+    pub fn update(&mut self, dt: f32, targets: &mut SequencerTargets) {
+        if self.playing {
+            self.accumulator = (self.accumulator + dt).min(FIXED_STEP * MAX_STEPS);
+            while self.playing && self.accumulator >= FIXED_STEP {
+                self.accumulator -= FIXED_STEP;
+                self.step();
+            }
+        }
+        if std::mem::take(&mut self.moved) {
+            self.apply(targets);
+        }
+    }
+
+    // Strips apply in start order, so a later one overrides an earlier one with the same target. Before its
+    // first strip starts, a target holds that strip's opening state
+    fn apply(&self, targets: &mut SequencerTargets) {
+        let mut order: Vec<&Strip> = self.strips.iter().collect();
+        order.sort_by(|a, b| a.start.total_cmp(&b.start));
+        let mut reached = Vec::new();
+        for strip in &order {
+            let target = strip.kind.target();
+            if strip.start <= self.time {
+                strip.apply(self.time - strip.start, targets);
+                reached.push(target);
+            } else if !reached.contains(&target) {
+                strip.apply(0.0, targets);
+                reached.push(target);
+            }
+        }
+    }
+
+    fn step(&mut self) {
+        self.time += FIXED_STEP;
+        self.moved = true;
+        if self.time >= self.duration {
+            if self.looping {
+                self.time %= self.duration;
+            } else {
+                self.time = self.duration;
+                self.playing = false;
+                self.accumulator = 0.0;
+            }
+        }
+    }
+
+    fn seek(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.duration);
+        self.moved = true;
+    }
+
+    fn add(&mut self, kind: StripKind, length: f32) {
+        self.strips.push(Strip {
+            kind,
+            start: self.time,
+            length,
+        });
+        self.selected = Some(self.strips.len() - 1);
+        self.moved = true;
+    }
+
+    /// The Sequencer window: transport controls, the strips on a shared timeline and the selected strip.
+    pub fn show(&mut self, ctx: &Context, targets: &mut SequencerTargets) {
+        egui::Window::new("Sequencer")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.transport(ui);
+                ui.separator();
+                self.add_menu(ui, targets);
+                egui::Grid::new("sequencer_strips").num_columns(2).show(ui, |ui| {
+                    ui.label("");
+                    self.ruler(ui);
+                    ui.end_row();
+                    for index in 0..self.strips.len() {
+                        ui.label(self.strips[index].kind.label());
+                        self.strip_row(ui, index);
+                        ui.end_row();
+                    }
+                });
+                if self.strips.is_empty() {
+                    ui.weak("Add strips to arrange them on the timeline");
+                }
+                ui.separator();
+                self.selected_strip(ui, targets);
+            });
+    }
+
+    fn transport(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("⏮").on_hover_text("Back to the start").clicked() {
+                self.seek(0.0);
+            }
+            let label = if self.playing { "⏸ Pause" } else { "▶ Play" };
+            if ui.button(label).clicked() {
+                self.playing = !self.playing;
+                // Playing a finished sequence starts it over
+                if self.playing && !self.looping && self.time >= self.duration {
+                    self.seek(0.0);
+                }
+            }
+            ui.checkbox(&mut self.looping, "Loop");
+            ui.add(
+                egui::DragValue::new(&mut self.duration)
+                    .speed(0.05)
+                    .range(0.1..=3600.0)
+                    .suffix(" s"),
+            )
+            .on_hover_text("Length");
+            let end = self.strips.iter().map(Strip::end).fold(0.0, f32::max);
+            if ui.add_enabled(end > 0.0, egui::Button::new("Fit")).on_hover_text("End with the last strip").clicked() {
+                self.duration = end;
+                self.seek(self.time);
+            }
+        });
+        let mut time = self.time;
+        let slider = egui::Slider::new(&mut time, 0.0..=self.duration).suffix(" s").text("Time");
+        if ui.add(slider).changed() {
+            self.seek(time);
+        }
+        ui.checkbox(&mut self.follow_recordings, "Restart with input recordings")
+            .on_hover_text("Recording or replaying input plays the sequence from its start");
+    }
+
+    fn add_menu(&mut self, ui: &mut egui::Ui, targets: &SequencerTargets) {
+        ui.horizontal(|ui| {
+            ui.label("Add at playhead");
+            if ui.button("Animation").clicked() {
+                self.add(StripKind::Animation, targets.animation.duration);
+            }
+            let clips: Vec<(String, f32)> = targets.skinned.as_deref().map_or_else(Vec::new, |mesh| {
+                mesh.model.clips.iter().map(|clip| (clip.name.clone(), clip.duration)).collect()
+            });
+            ui.add_enabled_ui(!clips.is_empty(), |ui| {
+                ui.menu_button("Clip", |ui| {
+                    for (clip, (name, duration)) in clips.into_iter().enumerate() {
+                        if ui.button(name).clicked() {
+                            self.add(StripKind::Clip { clip }, duration.max(FIXED_STEP));
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+            if ui.button("Camera path").on_hover_text("Starts at the current view").clicked() {
+                let camera = &targets.properties.camera;
+                let points = vec![CameraPoint {
+                    position: camera.position,
+                    target: camera.target,
+                }];
+                self.add(
+                    StripKind::CameraPath {
+                        points,
+                        easing: Easing::SmoothStep,
+                    },
+                    DEFAULT_LENGTH,
+                );
+            }
+            ui.menu_button("Change", |ui| {
+                for property in Property::ALL {
+                    if ui.button(property.label()).clicked() {
+                        let value = property.read(&targets.properties);
+                        let kind = StripKind::Change {
+                            property,
+                            from: value,
+                            to: value,
+                            easing: Easing::SmoothStep,
+                        };
+                        self.add(kind, DEFAULT_LENGTH);
+                        ui.close_menu();
+                    }
+                }
+            });
+        });
+    }
+
+    fn to_x(&self, rect: egui::Rect, time: f32) -> f32 {
+        rect.left() + (time / self.duration).clamp(0.0, 1.0) * rect.width()
+    }
+
+    fn to_time(&self, rect: egui::Rect, x: f32) -> f32 {
+        ((x - rect.left()) / rect.width()).clamp(0.0, 1.0) * self.duration
+    }
+
+    // Second marks above the strips; clicking or dragging scrubs
+    fn ruler(&mut self, ui: &mut egui::Ui) {
+        let (rect, response) =
+            ui.allocate_exact_size(egui::vec2(TIMELINE_WIDTH, ROW_HEIGHT), Sense::click_and_drag());
+        if let Some(pointer) = response.interact_pointer_pos().filter(|_| response.clicked() || response.dragged()) {
+            self.seek(self.to_time(rect, pointer.x));
+        }
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 2.0, visuals.faint_bg_color);
+        // A mark every second, or every ten when that would crowd them
+        let spacing = if self.duration > TIMELINE_WIDTH / 8.0 { 10.0 } else { 1.0 };
+        let stroke = visuals.widgets.noninteractive.fg_stroke;
+        for mark in 0..=(self.duration / spacing) as usize {
+            let x = self.to_x(rect, mark as f32 * spacing);
+            let top = if mark % 5 == 0 { rect.top() } else { rect.center().y };
+            painter.vline(x, top..=rect.bottom(), stroke);
+        }
+        self.playhead(ui, rect);
+    }
+
+    // One strip on its own row: dragging it moves it in time, dragging its end changes its length, and clicking
+    // empty space scrubs
+    fn strip_row(&mut self, ui: &mut egui::Ui, index: usize) {
+        let (rect, response) =
+            ui.allocate_exact_size(egui::vec2(TIMELINE_WIDTH, ROW_HEIGHT), Sense::click_and_drag());
+        let strip = &self.strips[index];
+        let left = self.to_x(rect, strip.start);
+        let right = self.to_x(rect, strip.end()).max(left + 2.0); // Strips of no length stay visible
+
+        if let Some(pointer) = response.interact_pointer_pos() {
+            let on_strip = (left..=right + HANDLE_WIDTH / 2.0).contains(&pointer.x);
+            if response.drag_started() && on_strip {
+                let drag = if (pointer.x - right).abs() <= HANDLE_WIDTH {
+                    Drag::Resize
+                } else {
+                    Drag::Move {
+                        grab: self.to_time(rect, pointer.x) - strip.start,
+                    }
+                };
+                self.dragging = Some((index, drag));
+            }
+            let time = self.to_time(rect, pointer.x);
+            match self.dragging.filter(|(dragged, _)| *dragged == index && response.dragged()) {
+                Some((_, Drag::Move { grab })) => {
+                    let strip = &mut self.strips[index];
+                    strip.start = (time - grab).clamp(0.0, self.duration);
+                    self.selected = Some(index);
+                    self.moved = true;
+                }
+                Some((_, Drag::Resize)) => {
+                    let strip = &mut self.strips[index];
+                    strip.length = (time - strip.start).max(0.0);
+                    self.selected = Some(index);
+                    self.moved = true;
+                }
+                None if response.clicked() && on_strip => self.selected = Some(index),
+                None if response.clicked() || response.dragged() => self.seek(time),
+                None => {}
+            }
+        }
+        if response.drag_stopped() {
+            self.dragging = None;
+        }
+
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+        let strip = &self.strips[index];
+        let bar = egui::Rect::from_x_y_ranges(left..=right, rect.shrink(2.0).y_range());
+        painter.rect_filled(bar, 3.0, strip.kind.color());
+        if self.selected == Some(index) {
+            painter.rect_stroke(bar, 3.0, visuals.selection.stroke);
+        }
+        self.playhead(ui, rect);
+    }
+
+    fn playhead(&self, ui: &egui::Ui, rect: egui::Rect) {
+        let x = self.to_x(rect, self.time);
+        ui.painter_at(rect).vline(x, rect.y_range(), Stroke::new(1.5, Color32::from_rgb(230, 70, 70)));
+    }
+
+    fn selected_strip(&mut self, ui: &mut egui::Ui, targets: &SequencerTargets) {
+        let Some(strip) = self.selected.and_then(|index| self.strips.get_mut(index)) else {
+            self.selected = None;
+            ui.weak("Click a strip to edit it");
+            return;
+        };
+        let time = self.time;
+        let mut changed = false;
+        ui.strong(strip.kind.label());
+        egui::Grid::new("sequencer_strip").num_columns(2).show(ui, |ui| {
+            ui.label("Start");
+            changed |= ui
+                .add(egui::DragValue::new(&mut strip.start).speed(0.01).range(0.0..=3600.0).suffix(" s"))
+                .changed();
+            ui.end_row();
+            ui.label("Length");
+            changed |= ui
+                .add(egui::DragValue::new(&mut strip.length).speed(0.01).range(0.0..=3600.0).suffix(" s"))
+                .changed();
+            ui.end_row();
+            match &mut strip.kind {
+                StripKind::Animation | StripKind::Clip { .. } => {}
+                StripKind::CameraPath { easing, .. } | StripKind::Change { easing, .. } => {
+                    ui.label("Easing");
+                    egui::ComboBox::from_id_source("sequencer_easing")
+                        .selected_text(easing.label())
+                        .show_ui(ui, |ui| {
+                            for candidate in Easing::ALL {
+                                changed |= ui.selectable_value(easing, candidate, candidate.label()).changed();
+                            }
+                        });
+                    ui.end_row();
+                }
+            }
+        });
+
+        let mut delete = false;
+        match &mut strip.kind {
+            StripKind::CameraPath { points, .. } => {
+                let camera = &targets.properties.camera;
+                let mut removed = None;
+                for (index, point) in points.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let p = point.position;
+                        ui.label(format!("{}: ({:.2}, {:.2}, {:.2})", index + 1, p.x, p.y, p.z));
+                        if ui.small_button("🗑").on_hover_text("Remove this point").clicked() {
+                            removed = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = removed.filter(|_| points.len() > 1) {
+                    points.remove(index);
+                    changed = true;
+                }
+                if ui.button("Add current view").clicked() {
+                    points.push(CameraPoint {
+                        position: camera.position,
+                        target: camera.target,
+                    });
+                    changed = true;
+                }
+            }
+            StripKind::Change { property, from, to, .. } => {
+                ui.label(format!("From {}", from.describe()));
+                ui.label(format!("To {}", to.describe()));
+                ui.horizontal(|ui| {
+                    if ui.button("Start from current").clicked() {
+                        *from = property.read(&targets.properties);
+                        changed = true;
+                    }
+                    if ui.button("End at current").clicked() {
+                        *to = property.read(&targets.properties);
+                        changed = true;
+                    }
+                });
+            }
+            StripKind::Animation | StripKind::Clip { .. } => {}
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Move to playhead").clicked() {
+                strip.start = time;
+                changed = true;
+            }
+            delete = ui.button("Delete").clicked();
+        });
+        if delete {
+            self.strips.remove(self.selected.take().expect("A strip is selected"));
+            self.dragging = None;
+        }
+        self.moved |= changed || delete;
+    }
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,233 @@
+// settings.rs
+
+use crate::commands::ShortcutMap;
+use crate::config::Backend;
+use crate::dynamic_resolution::{UpscaleFilter, DEFAULT_TARGET_FPS};
+use crate::input::ActionMap;
+use crate::msaa;
+use crate::post_process::PostSettings;
+use crate::primitives::Tessellation;
+use crate::stats::DEFAULT_MEMORY_BUDGET_MIB;
+use crate::stereo::StereoSettings;
+use crate::theme::Theme;
+use crate::ui::RenderingStyle;
+use egui_wgpu::wgpu;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+const APP_DIR_NAME: &str = "winit_egui_wgpu";
+const SETTINGS_FILE_NAME: &str = "settings.ron";
+
+// Frames the CPU may queue ahead of the display. Fewer cuts input lag, more keeps the GPU fed when frame
+// times vary
+pub const DEFAULT_FRAME_LATENCY: u32 = 2;
+pub const FRAME_LATENCY_RANGE: RangeInclusive<u32> = 1..=3;
+
+pub const DEFAULT_BACKGROUND_FPS: u32 = 10;
+pub const BACKGROUND_FPS_RANGE: RangeInclusive<u32> = 1..=60;
+
+// How the window renders while another window has focus
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackgroundMode {
+    FullSpeed,
+    Throttled, // At the background frame rate
+    OnDemand,  // Only when an event arrives for the window
+}
+
+impl BackgroundMode {
+    pub const ALL: [BackgroundMode; 3] =
+        [BackgroundMode::FullSpeed, BackgroundMode::Throttled, BackgroundMode::OnDemand];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BackgroundMode::FullSpeed => "Full speed",
+            BackgroundMode::Throttled => "Throttled",
+            BackgroundMode::OnDemand => "On demand",
+        }
+    }
+}
+
+pub const DEFAULT_FPS_CAP: u32 = 60;
+pub const FPS_CAP_RANGE: RangeInclusive<u32> = 10..=500;
+
+// How frames are paced while the window has focus
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PacingMode {
+    Uncapped,
+    Vsync,
+    Capped, // Sleeps to hold the FPS cap, without waiting on the display
+}
+
+impl PacingMode {
+    pub const ALL: [PacingMode; 3] = [PacingMode::Uncapped, PacingMode::Vsync, PacingMode::Capped];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PacingMode::Uncapped => "Uncapped",
+            PacingMode::Vsync => "Vsync",
+            PacingMode::Capped => "FPS cap",
+        }
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        match self {
+            PacingMode::Vsync => wgpu::PresentMode::AutoVsync,
+            PacingMode::Uncapped | PacingMode::Capped => wgpu::PresentMode::AutoNoVsync,
+        }
+    }
+}
+
+// The present mode asked of the surface; automatic leaves it to the pacing mode
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresentModePreference {
+    #[default]
+    Automatic,
+    Fifo,
+    FifoRelaxed,
+    Mailbox,
+    Immediate,
+}
+
+impl PresentModePreference {
+    pub const ALL: [PresentModePreference; 5] = [
+        PresentModePreference::Automatic,
+        PresentModePreference::Fifo,
+        PresentModePreference::FifoRelaxed,
+        PresentModePreference::Mailbox,
+        PresentModePreference::Immediate,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PresentModePreference::Automatic => "Automatic",
+            PresentModePreference::Fifo => "Fifo (vsync)",
+            PresentModePreference::FifoRelaxed => "Fifo relaxed (adaptive vsync)",
+            PresentModePreference::Mailbox => "Mailbox (triple buffered)",
+            PresentModePreference::Immediate => "Immediate (may tear)",
+        }
+    }
+
+    pub fn wgpu(&self) -> Option<wgpu::PresentMode> {
+        match self {
+            PresentModePreference::Automatic => None,
+            PresentModePreference::Fifo => Some(wgpu::PresentMode::Fifo),
+            PresentModePreference::FifoRelaxed => Some(wgpu::PresentMode::FifoRelaxed),
+            PresentModePreference::Mailbox => Some(wgpu::PresentMode::Mailbox),
+            PresentModePreference::Immediate => Some(wgpu::PresentMode::Immediate),
+        }
+    }
+}
+
+// Per-user directory holding everything persisted between runs
+pub fn settings_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(APP_DIR_NAME)
+}
+
+pub fn settings_path(file_name: &str) -> PathBuf {
+    settings_dir().join(file_name)
+}
+
+// Per-user directory for data that only speeds things up and is safe to delete
+pub fn cache_path(file_name: &str) -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(APP_DIR_NAME)
+        .join(file_name)
+}
+
+// User preferences stored in the config file; missing fields fall back to their defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub theme: Theme,
+    pub ui_scale: f32, // Multiplied with the monitor's scale factor
+    pub display_scale: Option<f32>, // Used instead of the monitor's scale factor when set
+    pub keybindings: ActionMap,
+    pub shortcuts: ShortcutMap,
+    pub memory_budget_mib: u32, // Renderer buffers and textures past this trigger a warning
+    pub frame_latency: u32,
+    pub background_mode: BackgroundMode,
+    pub background_fps: u32,
+    pub pacing_mode: PacingMode,
+    pub fps_cap: u32, // Frame rate held by `PacingMode::Capped`
+    pub present_mode: PresentModePreference,
+    pub redraw_on_demand: bool, // Skip frames that would look the same as the last one
+    pub dynamic_resolution: bool,
+    pub target_fps: u32, // Frame rate dynamic resolution lowers the scene's resolution to keep
+    pub upscale_filter: UpscaleFilter,
+    pub render_scale: f32,
+    pub msaa_samples: u32, // Per pixel of the scene pass; 1 turns MSAA off
+    pub stereo: StereoSettings,
+    pub post_fx: PostSettings,
+    pub sides: u16, // Of the polygon shape
+    pub tessellation: Tessellation,
+    pub rendering_style: RenderingStyle,
+    pub active_shader: String,
+    pub scene_in_panel: bool,
+    pub backend: Backend,        // Picked in the About / Diagnostics window; the app config's takes precedence
+    pub adapter: Option<String>, // The adapter's name, likewise
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            ui_scale: 1.0,
+            display_scale: None,
+            keybindings: ActionMap::default(),
+            shortcuts: ShortcutMap::default(),
+            memory_budget_mib: DEFAULT_MEMORY_BUDGET_MIB,
+            frame_latency: DEFAULT_FRAME_LATENCY,
+            background_mode: BackgroundMode::Throttled,
+            background_fps: DEFAULT_BACKGROUND_FPS,
+            pacing_mode: PacingMode::Vsync,
+            fps_cap: DEFAULT_FPS_CAP,
+            present_mode: PresentModePreference::Automatic,
+            redraw_on_demand: false,
+            dynamic_resolution: false,
+            target_fps: DEFAULT_TARGET_FPS,
+            upscale_filter: UpscaleFilter::Sharpened,
+            render_scale: 1.0,
+            msaa_samples: msaa::DEFAULT_SAMPLES,
+            stereo: StereoSettings::new(),
+            post_fx: PostSettings::default(),
+            sides: 5,
+            tessellation: Tessellation::default(),
+            rendering_style: RenderingStyle::Polygon,
+            active_shader: "main".to_string(),
+            scene_in_panel: false,
+            backend: Backend::Auto,
+            adapter: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let path = settings_path(SETTINGS_FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+                log::warn!("Ignoring invalid settings file {}: {err}", path.display());
+                Self::default()
+            }),
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    log::warn!("Failed to read settings from {}: {err}", path.display());
+                }
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::create_dir_all(settings_dir())?;
+        fs::write(settings_path(SETTINGS_FILE_NAME), text)
+    }
+}
@@ -1,14 +1,96 @@
 // Vertex shader
 
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Every enabled light in the scene, gathered each frame from the world's Light components
+struct Light {
+    position: vec4<f32>, // w: 0 directional, 1 point, 2 spot
+    direction: vec4<f32>, // w: range
+    color: vec4<f32>, // Times intensity
+    cone: vec4<f32>, // Cosines of the spot's inner and outer angles
+};
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    fog_color: vec3<f32>,
+    fog_mode: u32, // 0 off, 1 linear, 2 exponential
+    fog: vec4<f32>, // Start, end, density and height falloff
+    lights: array<Light, 16>,
+};
+@group(0) @binding(1)
+var<uniform> lights: Lights;
+
+struct ModelUniform {
+    model: mat4x4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> model_uniform: ModelUniform;
+
+// Editable per material from the Materials panel
+struct Params {
+    tint: vec3<f32>,
+    brightness: f32,
+    reflectivity: f32, // How much of the nearest reflection probe shows, looking straight on
+    specular: f32, // Strength of the lights' highlights
+    shininess: f32, // Blinn-Phong exponent; higher gives smaller, sharper highlights
+};
+@group(2) @binding(0)
+var<uniform> params: Params;
+
+// Set per material from the Materials panel; white until one is chosen
+@group(2) @binding(1)
+var albedo: texture_2d<f32>;
+@group(2) @binding(2)
+var albedo_sampler: sampler;
+
+// Captured reflection probes, each six faces in consecutive layers of the face texture
+struct Probe {
+    position: vec4<f32>, // w: radius
+    params: vec4<f32>, // Intensity, and the layer of its +X face
+};
+struct Probes {
+    count: u32,
+    environment_intensity: f32, // Of the environment image where no probe reaches; 0 without one
+    environment_rotation: f32, // Radians the image is turned about the vertical
+    probes: array<Probe, 8>,
+};
+@group(3) @binding(0)
+var<uniform> probes: Probes;
+@group(3) @binding(1)
+var probe_faces: texture_2d_array<f32>;
+@group(3) @binding(2)
+var probe_sampler: sampler;
+@group(3) @binding(3)
+var environment: texture_cube<f32>;
+
+// The cube faces in the order they are captured, each the axis looked down and the up it was drawn with
+const FACES = array<mat2x3<f32>, 6>(
+    mat2x3<f32>(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0)),
+    mat2x3<f32>(vec3<f32>(-1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0)),
+    mat2x3<f32>(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, -1.0)),
+    mat2x3<f32>(vec3<f32>(0.0, -1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0)),
+    mat2x3<f32>(vec3<f32>(0.0, 0.0, 1.0), vec3<f32>(0.0, 1.0, 0.0)),
+    mat2x3<f32>(vec3<f32>(0.0, 0.0, -1.0), vec3<f32>(0.0, 1.0, 0.0)),
+);
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) normal: vec3<f32>, // Zero when the mesh has none
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) normal: vec3<f32>,
 };
 
 @vertex
@@ -17,13 +99,137 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
     out.color = model.color;
-    out.clip_position = vec4<f32>(model.position, 1.0);
+    out.uv = model.uv;
+    // Off by a little under non-uniform scales, which would need the inverse transpose
+    out.normal = (model_uniform.model * vec4<f32>(model.normal, 0.0)).xyz;
+    let world_position = model_uniform.model * vec4<f32>(model.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
 // Fragment shader
 
+// Light reaching a surface, split into what the surface's color scatters and the highlights it reflects
+struct Shading {
+    diffuse: vec3<f32>,
+    specular: vec3<f32>,
+};
+
+// Blinn-Phong shading of a surface at `position` facing `normal`, seen from along `to_camera`
+fn shade(position: vec3<f32>, normal: vec3<f32>, to_camera: vec3<f32>) -> Shading {
+    var shading = Shading(lights.ambient, vec3<f32>(0.0));
+    for (var i = 0u; i < lights.count; i++) {
+        let light = lights.lights[i];
+        var to_light = -light.direction.xyz;
+        var falloff = 1.0;
+        if light.position.w > 0.5 {
+            let offset = light.position.xyz - position;
+            let distance = length(offset);
+            to_light = offset / max(distance, 0.0001);
+            falloff = clamp(1.0 - distance / light.direction.w, 0.0, 1.0);
+            falloff *= falloff;
+            if light.position.w > 1.5 {
+                let cos_angle = dot(-to_light, light.direction.xyz);
+                falloff *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+            }
+        }
+        let lambert = max(dot(normal, to_light), 0.0);
+        shading.diffuse += light.color.rgb * lambert * falloff;
+        // Halfway between the light and the camera; surfaces facing away from the light get no highlight
+        let halfway = normalize(to_light + to_camera);
+        let highlight = pow(max(dot(normal, halfway), 0.0), max(params.shininess, 1.0)) * step(0.0001, lambert);
+        shading.specular += light.color.rgb * highlight * falloff;
+    }
+    return shading;
+}
+
+// How much of a surface at `position` the fog hides, 0 to 1
+fn fog_amount(position: vec3<f32>) -> f32 {
+    let distance = length(position - camera.position.xyz);
+    var amount = 0.0;
+    if lights.fog_mode == 1u {
+        amount = clamp((distance - lights.fog.x) / (lights.fog.y - lights.fog.x), 0.0, 1.0);
+    } else if lights.fog_mode == 2u {
+        amount = 1.0 - exp(-lights.fog.z * distance);
+    }
+    // Thinner the higher the surface stands above the ground plane
+    return amount * exp(-lights.fog.w * max(position.y, 0.0));
+}
+
+// The environment image along `direction`, turned about the vertical by its rotation
+fn environment_reflection(direction: vec3<f32>) -> vec3<f32> {
+    let c = cos(probes.environment_rotation);
+    let s = sin(probes.environment_rotation);
+    let turned = vec3<f32>(c * direction.x + s * direction.z, direction.y, c * direction.z - s * direction.x);
+    return textureSampleLevel(environment, probe_sampler, turned, 0.0).rgb * probes.environment_intensity;
+}
+
+// What the nearest probe reaching `position` sees along `direction`, and in a how strongly it shows there. Where
+// the probe fades out, or none reaches, the environment image shows instead if there is one
+fn reflection(position: vec3<f32>, direction: vec3<f32>) -> vec4<f32> {
+    var nearest = -1;
+    var nearest_distance = 0.0;
+    for (var i = 0u; i < probes.count; i++) {
+        let distance = length(probes.probes[i].position.xyz - position);
+        if distance < probes.probes[i].position.w && (nearest < 0 || distance < nearest_distance) {
+            nearest = i32(i);
+            nearest_distance = distance;
+        }
+    }
+    let has_environment = probes.environment_intensity > 0.0;
+    if nearest < 0 {
+        if has_environment {
+            return vec4<f32>(environment_reflection(direction), 1.0);
+        }
+        return vec4<f32>(0.0);
+    }
+    let probe = probes.probes[nearest];
+
+    // The face the direction leaves the cube through, then where on it
+    let a = abs(direction);
+    var face = 4u + u32(direction.z < 0.0);
+    if a.x >= a.y && a.x >= a.z {
+        face = u32(direction.x < 0.0);
+    } else if a.y >= a.z {
+        face = 2u + u32(direction.y < 0.0);
+    }
+    var faces = FACES;
+    let forward = faces[face][0];
+    let up = faces[face][1];
+    let on_face = direction / dot(direction, forward);
+    let uv = vec2<f32>(dot(on_face, cross(forward, up)), -dot(on_face, up)) * 0.5 + 0.5;
+    let layer = i32(probe.params.y) + i32(face);
+    let color = textureSampleLevel(probe_faces, probe_sampler, uv, layer, 0.0).rgb;
+    let fade = 1.0 - smoothstep(probe.position.w * 0.75, probe.position.w, nearest_distance);
+    if has_environment {
+        return vec4<f32>(mix(environment_reflection(direction), color * probe.params.x, fade), 1.0);
+    }
+    return vec4<f32>(color * probe.params.x, fade);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
+    let texel = textureSample(albedo, albedo_sampler, in.uv).rgb;
+    // Meshes without normals get each face's from how its position changes across the screen, turned to face
+    // the camera so both sides of flat shapes are lit
+    var normal = normalize(cross(dpdx(in.world_position), dpdy(in.world_position)));
+    if dot(normal, camera.position.xyz - in.world_position) < 0.0 {
+        normal = -normal;
+    }
+    if dot(in.normal, in.normal) > 0.0 {
+        normal = normalize(in.normal);
+    }
+    let to_camera = normalize(camera.position.xyz - in.world_position);
+    let light = shade(in.world_position, normal, to_camera);
+    let albedo_color = in.color * texel * params.tint * params.brightness;
+    var color = albedo_color * light.diffuse + light.specular * params.specular;
+
+    // Specular from the nearest probe, stronger at grazing angles by Schlick's Fresnel; the boost scales with
+    // the reflectivity so matte materials stay matte
+    let grazing = pow(1.0 - max(dot(normal, to_camera), 0.0), 5.0);
+    let fresnel = params.reflectivity + (1.0 - params.reflectivity) * grazing * params.reflectivity;
+    let reflected = reflection(in.world_position, reflect(-to_camera, normal));
+    color = mix(color, reflected.rgb, fresnel * reflected.a);
+    return vec4<f32>(mix(color, lights.fog_color, fog_amount(in.world_position)), 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,117 @@
+// shader_cache.rs
+
+use crate::settings;
+use egui_wgpu::wgpu;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+
+const CACHE_FILE_NAME: &str = "shader_cache.json";
+// Bump when what an entry vouches for changes, so entries written by older builds are dropped
+const CACHE_VERSION: u32 = 1;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    adapters: HashMap<String, BTreeSet<u64>>, // Validated shader hashes per adapter and driver
+}
+
+#[derive(Debug, Default)]
+struct State {
+    file: CacheFile,
+    dirty: bool,
+    hits: u32,
+    misses: u32,
+}
+
+// Hashes of shaders that already passed naga validation against the vertex layout, kept on disk between
+// runs and keyed by adapter, so later launches skip parsing every pipeline permutation's WGSL again. wgpu
+// 0.20 has no pipeline cache objects, so compiled pipeline blobs are left to the driver's own cache
+pub struct ShaderCache {
+    adapter: String,
+    state: Mutex<State>,
+}
+
+impl ShaderCache {
+    pub fn load(adapter: &wgpu::AdapterInfo) -> Self {
+        let adapter = format!(
+            "{} ({:?}, {} {})",
+            adapter.name, adapter.backend, adapter.driver, adapter.driver_info
+        );
+        let path = settings::cache_path(CACHE_FILE_NAME);
+        let file = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str::<CacheFile>(&text)
+                .inspect_err(|err| log::warn!("Ignoring invalid shader cache {}: {err}", path.display()))
+                .unwrap_or_default(),
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    log::warn!("Failed to read shader cache from {}: {err}", path.display());
+                }
+                CacheFile::default()
+            }
+        };
+        let file = if file.version == CACHE_VERSION {
+            file
+        } else {
+            CacheFile {
+                version: CACHE_VERSION,
+                adapters: HashMap::new(),
+            }
+        };
+        Self {
+            adapter,
+            state: Mutex::new(State {
+                file,
+                ..State::default()
+            }),
+        }
+    }
+
+    /// Stable hash of everything a validation result depends on. Unlike `std`'s hashers it doesn't change
+    /// between builds, so entries stay valid across runs.
+    pub fn key(parts: &[&[u8]]) -> u64 {
+        // 64-bit FNV-1a, with a separator so ("ab", "c") and ("a", "bc") differ
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        for byte in parts.iter().flat_map(|part| part.iter().chain([&0xff])) {
+            hash = (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
+    }
+
+    pub fn contains(&self, key: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let hit = state.file.adapters.get(&self.adapter).is_some_and(|keys| keys.contains(&key));
+        if hit {
+            state.hits += 1;
+        } else {
+            state.misses += 1;
+        }
+        hit
+    }
+
+    pub fn insert(&self, key: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.file.adapters.entry(self.adapter.clone()).or_default().insert(key) {
+            state.dirty = true;
+        }
+    }
+
+    /// Writes the cache if anything was added since it was loaded.
+    pub fn save(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        log::info!("Shader cache: {} hit(s), {} miss(es)", state.hits, state.misses);
+        if !state.dirty {
+            return Ok(());
+        }
+        let text = serde_json::to_string(&state.file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let path = settings::cache_path(CACHE_FILE_NAME);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)?;
+        state.dirty = false;
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+// shader_manifest.rs
+
+use crate::shader_cache::ShaderCache;
+use egui_wgpu::wgpu;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Compiled into the binary, and used for every name the manifest doesn't replace
+const BUILTIN_SHADERS: [(&str, &str); 3] = [
+    ("main", include_str!("shader.wgsl")),
+    ("challenge", include_str!("challenge_shader.wgsl")),
+    ("fallback", include_str!("fallback_shader.wgsl")),
+];
+
+// First word of every SPIR-V module
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+pub enum ShaderFormat {
+    Wgsl,
+    SpirV,
+    Naga, // A naga::Module serialized as RON
+}
+
+impl ShaderFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "wgsl" => Some(ShaderFormat::Wgsl),
+            "spv" => Some(ShaderFormat::SpirV),
+            "ron" => Some(ShaderFormat::Naga),
+            _ => None,
+        }
+    }
+}
+
+// One shader module in any of the supported formats. SPIR-V and naga modules are already parsed, so neither
+// reflection nor wgpu has to read text
+#[derive(Debug)]
+pub enum ShaderCode {
+    Wgsl(Cow<'static, str>),
+    Parsed { module: Box<naga::Module>, bytes: Vec<u8> }, // From SPIR-V or RON; the file's bytes key the cache
+}
+
+impl ShaderCode {
+    /// Reads a module, in `format` or else the one its extension names.
+    pub fn load(path: &Path, format: Option<ShaderFormat>) -> Result<Self, String> {
+        let format = format
+            .or_else(|| ShaderFormat::from_path(path))
+            .ok_or_else(|| format!("Unknown shader format for {}; use .wgsl, .spv or .ron", path.display()))?;
+        let bytes = fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let module = match format {
+            ShaderFormat::Wgsl => {
+                let source = String::from_utf8(bytes).map_err(|e| format!("{} is not UTF-8: {e}", path.display()))?;
+                return Ok(ShaderCode::Wgsl(source.into()));
+            }
+            ShaderFormat::SpirV => {
+                if bytes.len() % 4 != 0 || bytes.get(..4) != Some(&SPIRV_MAGIC.to_le_bytes()) {
+                    return Err(format!("{} is not a little-endian SPIR-V module", path.display()));
+                }
+                // The options wgpu itself parses SPIR-V with
+                let options = naga::front::spv::Options {
+                    adjust_coordinate_space: false,
+                    strict_capabilities: true,
+                    block_ctx_dump_prefix: None,
+                };
+                naga::front::spv::parse_u8_slice(&bytes, &options).map_err(|e| format!("{}: {e}", path.display()))?
+            }
+            ShaderFormat::Naga => {
+                let text = std::str::from_utf8(&bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+                ron::from_str(text).map_err(|e| format!("{}: {e}", path.display()))?
+            }
+        };
+        Ok(ShaderCode::Parsed {
+            module: Box::new(module),
+            bytes,
+        })
+    }
+
+    /// The naga module, for reflection and the vertex layout check. WGSL is parsed on every call.
+    pub fn module(&self) -> Result<Cow<'_, naga::Module>, String> {
+        match self {
+            ShaderCode::Wgsl(source) => naga::front::wgsl::parse_str(source)
+                .map(Cow::Owned)
+                .map_err(|e| e.emit_to_string(source)),
+            ShaderCode::Parsed { module, .. } => Ok(Cow::Borrowed(module)),
+        }
+    }
+
+    // Validates the module with naga, so a broken one is caught before wgpu is handed it
+    fn validate(&self) -> Result<(), String> {
+        let module = self.module()?;
+        let result = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+            .validate(&module);
+        match (result, self) {
+            (Ok(_), _) => Ok(()),
+            (Err(err), ShaderCode::Wgsl(source)) => Err(err.emit_to_string(source)),
+            (Err(err), ShaderCode::Parsed { .. }) => Err(err.into_inner().to_string()), // No source to point into
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            ShaderCode::Wgsl(source) => source.as_bytes(),
+            ShaderCode::Parsed { bytes, .. } => bytes,
+        }
+    }
+
+    fn create(&self, device: &wgpu::Device, label: &str) -> wgpu::ShaderModule {
+        let source = match self {
+            ShaderCode::Wgsl(source) => wgpu::ShaderSource::Wgsl(source.clone()),
+            ShaderCode::Parsed { module, .. } => wgpu::ShaderSource::Naga(Cow::Owned(naga::Module::clone(module))),
+        };
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source,
+        })
+    }
+}
+
+// The vertex and fragment stages of a scene shader, either in one module or, as GLSL and HLSL toolchains
+// produce them, one module per stage
+#[derive(Debug)]
+pub struct ShaderProgram {
+    vertex: ShaderCode,
+    fragment: Option<ShaderCode>, // None when the vertex module holds the fragment stage too
+    pub vertex_entry: String,
+    pub fragment_entry: String,
+}
+
+impl ShaderProgram {
+    pub fn wgsl(source: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            vertex: ShaderCode::Wgsl(source.into()),
+            fragment: None,
+            vertex_entry: "vs_main".to_string(),
+            fragment_entry: "fs_main".to_string(),
+        }
+    }
+
+    /// Reads a program with both stages in one file, in the format its extension names, with the entry points
+    /// `vs_main` and `fs_main`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Ok(Self {
+            vertex: ShaderCode::load(path, None)?,
+            fragment: None,
+            vertex_entry: "vs_main".to_string(),
+            fragment_entry: "fs_main".to_string(),
+        })
+    }
+
+    /// Validates every module and checks the entry points are there, with the stages they're used as.
+    pub fn validate(&self) -> Result<(), String> {
+        self.vertex.validate()?;
+        if let Some(fragment) = &self.fragment {
+            fragment.validate()?;
+        }
+        let fragment = self.fragment.as_ref().unwrap_or(&self.vertex);
+        let entries = [
+            (self.vertex.module()?, &self.vertex_entry, naga::ShaderStage::Vertex),
+            (fragment.module()?, &self.fragment_entry, naga::ShaderStage::Fragment),
+        ];
+        for (module, name, stage) in entries {
+            if !module.entry_points.iter().any(|entry| entry.name == *name && entry.stage == stage) {
+                return Err(format!("no {stage:?} entry point {name:?}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// The vertex stage's module, checked against the vertex layout.
+    pub fn vertex_module(&self) -> Result<Cow<'_, naga::Module>, String> {
+        self.vertex.module()
+    }
+
+    /// Every distinct module of the program, for reflecting the resources either stage uses.
+    pub fn modules(&self) -> Result<Vec<Cow<'_, naga::Module>>, String> {
+        let mut modules = vec![self.vertex.module()?];
+        if let Some(fragment) = &self.fragment {
+            modules.push(fragment.module()?);
+        }
+        Ok(modules)
+    }
+
+    /// Hash of the program's code and entry points for the shader cache.
+    pub fn cache_key(&self, extra: &[u8]) -> u64 {
+        let fragment = self.fragment.as_ref().map_or(&[][..], |fragment| fragment.bytes());
+        ShaderCache::key(&[
+            self.vertex.bytes(),
+            fragment,
+            self.vertex_entry.as_bytes(),
+            self.fragment_entry.as_bytes(),
+            extra,
+        ])
+    }
+
+    /// Creates the vertex module and, if the fragment stage lives apart, the fragment module.
+    pub fn create_modules(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+    ) -> (wgpu::ShaderModule, Option<wgpu::ShaderModule>) {
+        let vertex = self.vertex.create(device, label);
+        let fragment = self.fragment.as_ref().map(|fragment| fragment.create(device, label));
+        (vertex, fragment)
+    }
+}
+
+// A manifest entry; paths are relative to the manifest file
+#[derive(Debug, Deserialize)]
+struct ManifestEntry {
+    vertex: PathBuf,
+    #[serde(default)]
+    fragment: Option<PathBuf>,
+    #[serde(default)]
+    format: Option<ShaderFormat>, // Otherwise taken from each file's extension
+    #[serde(default = "default_vertex_entry")]
+    vertex_entry: String,
+    #[serde(default = "default_fragment_entry")]
+    fragment_entry: String,
+}
+
+fn default_vertex_entry() -> String {
+    "vs_main".to_string()
+}
+
+fn default_fragment_entry() -> String {
+    "fs_main".to_string()
+}
+
+// `--shaders <manifest.ron>` file replacing built-in shaders by name with WGSL, precompiled SPIR-V or
+// serialized naga modules, e.g.
+//
+// (shaders: {
+//     "main": (
+//         vertex: "main.vert.spv",
+//         fragment: Some("main.frag.spv"),
+//         vertex_entry: "main",
+//         fragment_entry: "main",
+//     ),
+//     "challenge": (vertex: "challenge.ron"),
+// })
+#[derive(Debug, Default, Deserialize)]
+struct ShaderManifest {
+    shaders: HashMap<String, ManifestEntry>,
+}
+
+// The scene shaders by name: "main", "challenge" and "fallback"
+pub struct ShaderLibrary {
+    programs: HashMap<String, Arc<ShaderProgram>>,
+    errors: Vec<String>, // Why the manifest, or entries of it, were left out
+}
+
+impl ShaderLibrary {
+    /// The built-in shaders, with those named in the manifest at `manifest` replaced. An entry that fails
+    /// to load or validate is recorded in `errors` and the built-in shader kept.
+    pub fn load(manifest: Option<&Path>) -> Self {
+        let programs: HashMap<String, Arc<ShaderProgram>> =
+            BUILTIN_SHADERS.iter().map(|(name, _)| (name.to_string(), Self::builtin(name))).collect();
+        let mut library = Self {
+            programs,
+            errors: Vec::new(),
+        };
+        let Some(path) = manifest else {
+            return library;
+        };
+        let manifest = match read_manifest(path) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                library.error(format!("Ignoring shader manifest {}: {err}", path.display()));
+                return library;
+            }
+        };
+        let dir = path.parent().unwrap_or(Path::new("."));
+        for (name, entry) in manifest.shaders {
+            if !library.programs.contains_key(&name) {
+                library.error(format!("Shader manifest names unknown shader {name:?}"));
+                continue;
+            }
+            let load = |path: &Path| ShaderCode::load(&dir.join(path), entry.format);
+            let program = load(&entry.vertex).and_then(|vertex| {
+                Ok(ShaderProgram {
+                    vertex,
+                    fragment: entry.fragment.as_deref().map(load).transpose()?,
+                    vertex_entry: entry.vertex_entry.clone(),
+                    fragment_entry: entry.fragment_entry.clone(),
+                })
+            });
+            match program.and_then(|program| program.validate().map(|()| program)) {
+                Ok(program) => {
+                    log::info!("Loaded shader {name:?} from the manifest");
+                    library.programs.insert(name, Arc::new(program));
+                }
+                Err(err) => library.error(format!("Keeping the built-in {name:?} shader: {err}")),
+            }
+        }
+        library
+    }
+
+    pub fn get(&self, name: &str) -> Arc<ShaderProgram> {
+        self.programs.get(name).cloned().unwrap_or_else(|| panic!("No built-in shader named {name:?}"))
+    }
+
+    /// The shader compiled into the binary as `name`, whatever the manifest replaced it with.
+    pub fn builtin(name: &str) -> Arc<ShaderProgram> {
+        let (_, source) = BUILTIN_SHADERS
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .unwrap_or_else(|| panic!("No built-in shader named {name:?}"));
+        Arc::new(ShaderProgram::wgsl(*source))
+    }
+
+    /// Why the manifest, or entries of it, were left out, for the UI to show.
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+
+    fn error(&mut self, err: String) {
+        log::warn!("{err}");
+        self.errors.push(err);
+    }
+}
+
+fn read_manifest(path: &Path) -> io::Result<ShaderManifest> {
+    let text = fs::read_to_string(path)?;
+    ron::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,336 @@
+// shader_params.rs
+
+use crate::shader_manifest::ShaderProgram;
+use crate::texture;
+use egui_wgpu::wgpu;
+use naga::{AddressSpace, ImageClass, ImageDimension, ScalarKind, TypeInner};
+
+// Uniforms in this bind group are user parameters; lower groups are owned by the renderer
+pub const PARAMS_GROUP: u32 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldKind {
+    Float,
+    Sint,
+    Uint,
+}
+
+// A scalar or vector member of a uniform struct
+#[derive(Debug, Clone)]
+pub struct UniformField {
+    pub name: String,
+    pub kind: FieldKind,
+    pub components: usize,
+    offset: usize, // In 4-byte words
+}
+
+// One `var<uniform>` struct with CPU-side contents laid out as naga reports
+#[derive(Debug, Clone)]
+pub struct UniformBlock {
+    pub name: String,
+    pub binding: u32,
+    pub fields: Vec<UniformField>,
+    pub skipped: Vec<String>, // Members without a widget, e.g. matrices or arrays
+    data: Vec<u32>,
+}
+
+impl UniformBlock {
+    pub fn bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.data)
+    }
+
+    /// Sets a float field by name; extra values are ignored. Returns false if there is no such float field.
+    pub fn set(&mut self, name: &str, values: &[f32]) -> bool {
+        let Some(field) = self.fields.iter().find(|f| f.name == name && f.kind == FieldKind::Float) else {
+            return false;
+        };
+        for (word, value) in self.data[field.offset..field.offset + field.components].iter_mut().zip(values) {
+            *word = value.to_bits();
+        }
+        true
+    }
+
+    /// Reads a float field by name, or None if there is no such float field.
+    pub fn get(&self, name: &str) -> Option<Vec<f32>> {
+        let field = self.fields.iter().find(|f| f.name == name && f.kind == FieldKind::Float)?;
+        let words = &self.data[field.offset..field.offset + field.components];
+        Some(words.iter().map(|word| f32::from_bits(*word)).collect())
+    }
+
+    /// Every field by name, ints converted to floats, as saved with a material.
+    pub fn values(&self) -> Vec<(String, Vec<f32>)> {
+        self.fields
+            .iter()
+            .map(|field| {
+                let words = &self.data[field.offset..field.offset + field.components];
+                let values = words.iter().map(|&word| match field.kind {
+                    FieldKind::Float => f32::from_bits(word),
+                    FieldKind::Sint => word as i32 as f32,
+                    FieldKind::Uint => word as f32,
+                });
+                (field.name.clone(), values.collect())
+            })
+            .collect()
+    }
+
+    /// Sets any field by name, rounding for ints; the counterpart of `values`. Returns false if there is no such
+    /// field.
+    pub fn set_value(&mut self, name: &str, values: &[f32]) -> bool {
+        let Some(field) = self.fields.iter().find(|f| f.name == name) else {
+            return false;
+        };
+        for (word, value) in self.data[field.offset..field.offset + field.components].iter_mut().zip(values) {
+            *word = match field.kind {
+                FieldKind::Float => value.to_bits(),
+                FieldKind::Sint => value.round() as i32 as u32,
+                FieldKind::Uint => value.round() as u32,
+            };
+        }
+        true
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        egui::Grid::new(("uniform_block", &self.name)).num_columns(2).show(ui, |ui| {
+            for field in &self.fields {
+                ui.label(&field.name);
+                let words = &mut self.data[field.offset..field.offset + field.components];
+                changed |= field_ui(ui, field, words);
+                ui.end_row();
+            }
+        });
+        for name in &self.skipped {
+            ui.weak(format!("{name}: no editor for this type"));
+        }
+        changed
+    }
+}
+
+fn field_ui(ui: &mut egui::Ui, field: &UniformField, words: &mut [u32]) -> bool {
+    let lower = field.name.to_lowercase();
+    let is_color = lower.contains("color") || lower.contains("colour") || lower.contains("tint");
+
+    match (field.kind, field.components) {
+        (FieldKind::Float, 3) if is_color => {
+            let mut rgb = [0.0; 3];
+            for (c, w) in rgb.iter_mut().zip(words.iter()) {
+                *c = f32::from_bits(*w);
+            }
+            let changed = ui.color_edit_button_rgb(&mut rgb).changed();
+            for (w, c) in words.iter_mut().zip(rgb) {
+                *w = c.to_bits();
+            }
+            changed
+        }
+        (FieldKind::Float, 4) if is_color => {
+            let mut rgba = [0.0; 4];
+            for (c, w) in rgba.iter_mut().zip(words.iter()) {
+                *c = f32::from_bits(*w);
+            }
+            let changed = ui.color_edit_button_rgba_unmultiplied(&mut rgba).changed();
+            for (w, c) in words.iter_mut().zip(rgba) {
+                *w = c.to_bits();
+            }
+            changed
+        }
+        _ => {
+            ui.horizontal(|ui| {
+                let mut changed = false;
+                for word in words.iter_mut() {
+                    changed |= match field.kind {
+                        FieldKind::Float => {
+                            let mut value = f32::from_bits(*word);
+                            let response = ui.add(egui::DragValue::new(&mut value).speed(0.01));
+                            *word = value.to_bits();
+                            response.changed()
+                        }
+                        FieldKind::Sint => {
+                            let mut value = *word as i32;
+                            let response = ui.add(egui::DragValue::new(&mut value));
+                            *word = value as u32;
+                            response.changed()
+                        }
+                        FieldKind::Uint => ui.add(egui::DragValue::new(word)).changed(),
+                    };
+                }
+                changed
+            })
+            .inner
+        }
+    }
+}
+
+/// Reflects every uniform struct bound in `group` of a shader module.
+pub fn reflect_uniforms(module: &naga::Module, group: u32) -> Vec<UniformBlock> {
+    let mut blocks = Vec::new();
+    for (_, variable) in module.global_variables.iter() {
+        let Some(binding) = &variable.binding else {
+            continue;
+        };
+        if variable.space != AddressSpace::Uniform || binding.group != group {
+            continue;
+        }
+        let TypeInner::Struct { members, span } = &module.types[variable.ty].inner else {
+            continue;
+        };
+
+        let mut fields = Vec::new();
+        let mut skipped = Vec::new();
+        for member in members {
+            let name = member.name.clone().unwrap_or_default();
+            let (scalar, components) = match module.types[member.ty].inner {
+                TypeInner::Scalar(scalar) => (scalar, 1),
+                TypeInner::Vector { size, scalar } => (scalar, size as usize),
+                _ => {
+                    skipped.push(name);
+                    continue;
+                }
+            };
+            let kind = match (scalar.kind, scalar.width) {
+                (ScalarKind::Float, 4) => FieldKind::Float,
+                (ScalarKind::Sint, 4) => FieldKind::Sint,
+                (ScalarKind::Uint, 4) => FieldKind::Uint,
+                _ => {
+                    skipped.push(name);
+                    continue;
+                }
+            };
+            fields.push(UniformField {
+                name,
+                kind,
+                components,
+                offset: member.offset as usize / 4,
+            });
+        }
+
+        // Uniform buffers are bound in 16 byte multiples
+        let size = (*span as usize).div_ceil(16) * 16;
+        blocks.push(UniformBlock {
+            name: variable.name.clone().unwrap_or_default(),
+            binding: binding.binding,
+            fields,
+            skipped,
+            data: vec![0; size / 4],
+        });
+    }
+    blocks.sort_by_key(|block| block.binding);
+    blocks
+}
+
+// A texture or sampler bound in the parameter group
+#[derive(Debug, Clone)]
+pub struct ResourceBinding {
+    pub name: String,
+    pub binding: u32,
+}
+
+/// Reflects the filterable 2D float textures bound in `group` of a shader module.
+pub fn reflect_textures(module: &naga::Module, group: u32) -> Vec<ResourceBinding> {
+    reflect_resources(module, group, |inner| {
+        matches!(
+            inner,
+            TypeInner::Image {
+                dim: ImageDimension::D2,
+                arrayed: false,
+                class: ImageClass::Sampled {
+                    kind: ScalarKind::Float,
+                    multi: false,
+                },
+            }
+        )
+    })
+}
+
+/// Reflects the non-comparison samplers bound in `group` of a shader module.
+pub fn reflect_samplers(module: &naga::Module, group: u32) -> Vec<ResourceBinding> {
+    reflect_resources(module, group, |inner| matches!(inner, TypeInner::Sampler { comparison: false }))
+}
+
+fn reflect_resources(module: &naga::Module, group: u32, wanted: fn(&TypeInner) -> bool) -> Vec<ResourceBinding> {
+    let mut resources: Vec<ResourceBinding> = module
+        .global_variables
+        .iter()
+        .filter_map(|(_, variable)| {
+            let binding = variable.binding.as_ref()?;
+            (binding.group == group && wanted(&module.types[variable.ty].inner)).then(|| ResourceBinding {
+                name: variable.name.clone().unwrap_or_default(),
+                binding: binding.binding,
+            })
+        })
+        .collect();
+    resources.sort_by_key(|resource| resource.binding);
+    resources
+}
+
+// What one shader's parameter group holds: its uniforms with the values new materials start from, its
+// textures and samplers, and the layout every material of the shader binds through
+pub struct ShaderParams {
+    pub label: &'static str,
+    pub blocks: Vec<UniformBlock>,
+    pub textures: Vec<ResourceBinding>,
+    pub samplers: Vec<ResourceBinding>,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl ShaderParams {
+    pub fn new(device: &wgpu::Device, label: &'static str, program: &ShaderProgram) -> Result<Self, String> {
+        // A binding used by both stages of a split program is reflected from each
+        let mut blocks: Vec<UniformBlock> = Vec::new();
+        let mut textures: Vec<ResourceBinding> = Vec::new();
+        let mut samplers: Vec<ResourceBinding> = Vec::new();
+        for module in program.modules()? {
+            for block in reflect_uniforms(&module, PARAMS_GROUP) {
+                if !blocks.iter().any(|existing| existing.binding == block.binding) {
+                    blocks.push(block);
+                }
+            }
+            for (found, reflected) in [
+                (&mut textures, reflect_textures(&module, PARAMS_GROUP)),
+                (&mut samplers, reflect_samplers(&module, PARAMS_GROUP)),
+            ] {
+                for resource in reflected {
+                    if !found.iter().any(|existing| existing.binding == resource.binding) {
+                        found.push(resource);
+                    }
+                }
+            }
+        }
+        blocks.sort_by_key(|block| block.binding);
+        textures.sort_by_key(|texture| texture.binding);
+        samplers.sort_by_key(|sampler| sampler.binding);
+
+        let buffer_entries = blocks.iter().map(|block| wgpu::BindGroupLayoutEntry {
+            binding: block.binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
+        let texture_entries = textures.iter().map(|texture| texture::texture_layout_entry(texture.binding));
+        let sampler_entries = samplers.iter().map(|sampler| texture::sampler_layout_entry(sampler.binding));
+        let layout_entries: Vec<wgpu::BindGroupLayoutEntry> =
+            buffer_entries.chain(texture_entries).chain(sampler_entries).collect();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shader Params Bind Group Layout"),
+            entries: &layout_entries,
+        });
+
+        Ok(Self {
+            label,
+            blocks,
+            textures,
+            samplers,
+            bind_group_layout,
+        })
+    }
+
+    /// Sets the starting value of a float field in whichever block declares it.
+    pub fn set(&mut self, name: &str, values: &[f32]) {
+        for block in &mut self.blocks {
+            block.set(name, values);
+        }
+    }
+}
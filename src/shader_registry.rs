@@ -0,0 +1,175 @@
+// shader_registry.rs
+
+use crate::shader_manifest::{ShaderLibrary, ShaderProgram};
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Names a scene shader in a `ShaderRegistry`. Only the registry hands them out, so a selection can't name a
+/// shader that was never registered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShaderId(&'static str);
+
+impl ShaderId {
+    /// The built-in lit shader, always registered, and what unknown names fall back to.
+    pub const MAIN: ShaderId = ShaderId("main");
+
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+}
+
+// A scene shader and what the app needs to know to draw with it. Every scene shader takes the camera and lights in
+// group 0, the object in group 1 and its parameters in group 2
+pub struct ShaderVariant {
+    pub id: ShaderId,
+    pub label: &'static str, // Shown in the shader dropdown
+    pub description: &'static str,
+    pub program: Arc<ShaderProgram>,
+    pub reflections: bool, // Reads the reflection probes in group 3
+    pub defaults: Vec<(&'static str, Vec<f32>)>, // Parameter values every new material of the shader starts from
+    pub reload_file: Option<&'static str>,       // Its file in `shaders/`, read again whenever it's saved
+}
+
+impl ShaderVariant {
+    pub fn new(name: &'static str, label: &'static str, program: Arc<ShaderProgram>) -> Self {
+        Self {
+            id: ShaderId(name),
+            label,
+            description: "",
+            program,
+            reflections: false,
+            defaults: Vec::new(),
+            reload_file: None,
+        }
+    }
+
+    pub fn description(mut self, description: &'static str) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn reflections(mut self) -> Self {
+        self.reflections = true;
+        self
+    }
+
+    pub fn default_param(mut self, name: &'static str, values: &[f32]) -> Self {
+        self.defaults.push((name, values.to_vec()));
+        self
+    }
+
+    pub fn reload_file(mut self, file: &'static str) -> Self {
+        self.reload_file = Some(file);
+        self
+    }
+}
+
+/// The scene shaders the app can draw with, in the order the UI lists and cycles through them. The built-in ones
+/// come first; projects built on the template add theirs with `Application::shaders`.
+pub struct ShaderRegistry {
+    variants: Vec<ShaderVariant>,
+}
+
+impl ShaderRegistry {
+    /// The built-in "main" and "challenge" shaders, taken from `library` so a manifest can replace their code.
+    pub fn new(library: &ShaderLibrary) -> Self {
+        let main = ShaderVariant::new("main", "Main", library.get("main"))
+            .description("Lit by the scene's lights, reflecting the environment")
+            .reflections()
+            .default_param("tint", &[1.0, 1.0, 1.0])
+            .default_param("brightness", &[1.0])
+            .default_param("reflectivity", &[0.2])
+            .default_param("specular", &[0.5])
+            .default_param("shininess", &[32.0])
+            .reload_file("shader.wgsl");
+        let challenge = ShaderVariant::new("challenge", "Challenge", library.get("challenge"))
+            .description("Unlit vertex colors, inverted and shifted per material")
+            .default_param("inversion", &[1.0])
+            .reload_file("challenge_shader.wgsl");
+        Self {
+            variants: vec![main, challenge],
+        }
+    }
+
+    /// Adds `variant`, or replaces the shader registered under its name. A replacement "main" must read the
+    /// reflection probes, since the fallback pipeline shares its layout.
+    pub fn register(&mut self, mut variant: ShaderVariant) -> ShaderId {
+        let id = variant.id;
+        if id == ShaderId::MAIN && !variant.reflections {
+            log::warn!("The main shader must read the reflection probes; binding them anyway");
+            variant.reflections = true;
+        }
+        match self.variants.iter_mut().find(|v| v.id == id) {
+            Some(existing) => *existing = variant,
+            None => self.variants.push(variant),
+        }
+        id
+    }
+
+    /// Adds a WGSL shader with the entry points `vs_main` and `fs_main`.
+    pub fn register_wgsl(
+        &mut self,
+        name: &'static str,
+        label: &'static str,
+        source: impl Into<Cow<'static, str>>,
+    ) -> ShaderId {
+        self.register(ShaderVariant::new(name, label, Arc::new(ShaderProgram::wgsl(source))))
+    }
+
+    /// Adds the shader in the WGSL, SPIR-V or naga file at `path`, with the entry points `vs_main` and `fs_main`.
+    pub fn register_file(&mut self, name: &'static str, label: &'static str, path: &Path) -> Result<ShaderId, String> {
+        let program = ShaderProgram::load(path)?;
+        Ok(self.register(ShaderVariant::new(name, label, Arc::new(program))))
+    }
+
+    /// Swaps the code of the shader registered as `id`, keeping its label, defaults and the rest.
+    pub fn set_program(&mut self, id: ShaderId, program: Arc<ShaderProgram>) {
+        self.variants.iter_mut().filter(|v| v.id == id).for_each(|v| v.program = program.clone());
+    }
+
+    /// Drops a shader that can't be drawn with, such as one whose parameters don't reflect. The main shader stays.
+    pub fn unregister(&mut self, id: ShaderId) {
+        if id != ShaderId::MAIN {
+            self.variants.retain(|v| v.id != id);
+        }
+    }
+
+    /// The shader registered as `name`, if there is one.
+    pub fn id(&self, name: &str) -> Option<ShaderId> {
+        self.variants.iter().map(|v| v.id).find(|id| id.name() == name)
+    }
+
+    /// The shader registered as `name`, or the main shader if there isn't one, as for a scene saved with a
+    /// shader this build doesn't have.
+    pub fn resolve(&self, name: &str) -> ShaderId {
+        self.id(name).unwrap_or_else(|| {
+            log::warn!("Unknown shader {name:?}; using the main shader");
+            ShaderId::MAIN
+        })
+    }
+
+    pub fn get(&self, id: ShaderId) -> &ShaderVariant {
+        self.variants
+            .iter()
+            .find(|v| v.id == id)
+            .unwrap_or_else(|| panic!("Shader {:?} is not registered", id.name()))
+    }
+
+    pub fn variants(&self) -> &[ShaderVariant] {
+        &self.variants
+    }
+
+    /// Each shader with its label and description, for the UI to list.
+    pub fn choices(&self) -> Vec<(ShaderId, &'static str, &'static str)> {
+        self.variants.iter().map(|v| (v.id, v.label, v.description)).collect()
+    }
+
+    /// The shaders whose files in `shaders/` are watched, by name.
+    pub fn reload_files(&self) -> Vec<(&'static str, &'static str)> {
+        self.variants
+            .iter()
+            .filter_map(|v| v.reload_file.map(|file| (v.id.name(), file)))
+            .collect()
+    }
+}
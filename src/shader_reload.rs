@@ -0,0 +1,124 @@
+// shader_reload.rs
+
+use crate::assets::{AssetEvent, Assets, Handle};
+use crate::jobs::FrameBudget;
+use crate::shader_manifest::ShaderProgram;
+use crate::toasts::Toasts;
+use egui::Context;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Where WGSL overriding the built-in scene shaders is looked for, relative to the working directory
+pub const SHADER_DIR: &str = "shaders";
+
+// Loads the scene shaders from `SHADER_DIR` when it has them and reads them again whenever they are saved. A
+// source that doesn't parse or validate is reported here and the pipeline keeps its last good shader
+pub struct ShaderReloader {
+    sources: Assets<String>,
+    watched: Vec<(Handle<String>, &'static str)>,
+    errors: BTreeMap<&'static str, String>, // By shader name, with naga's annotated message
+}
+
+impl ShaderReloader {
+    pub fn new() -> Self {
+        Self {
+            sources: Assets::new(read_source),
+            watched: Vec::new(),
+            errors: BTreeMap::new(),
+        }
+    }
+
+    /// Starts watching whichever of `shaders`, by name and file, `dir` has a file for.
+    pub fn watch(&mut self, dir: &Path, shaders: Vec<(&'static str, &'static str)>) {
+        for (name, file) in shaders {
+            let path = dir.join(file);
+            if path.is_file() {
+                log::info!("Watching {} for the {name:?} shader", path.display());
+                let handle = self.sources.load(path, true);
+                self.watched.push((handle, name));
+            }
+        }
+    }
+
+    /// Picks up loaded and changed sources, returning each shader that validated, by name, for its pipeline to
+    /// be rebuilt.
+    pub fn poll(&mut self, budget: &mut FrameBudget, toasts: &mut Toasts) -> Vec<(&'static str, Arc<ShaderProgram>)> {
+        let mut changed = Vec::new();
+        for event in self.sources.poll(budget) {
+            let (handle, reloaded) = match event {
+                AssetEvent::Loaded(handle) => (handle, false),
+                AssetEvent::Reloaded(handle) => (handle, true),
+                // The file stays watched, so saving it again retries the read
+                AssetEvent::Failed(handle, err) => {
+                    if let Some(&(_, name)) = self.watched.iter().find(|(watched, _)| *watched == handle) {
+                        log::warn!("Failed to read the {name} shader: {err}");
+                        toasts.error(format!("Failed to read the {name} shader"));
+                        self.errors.insert(name, err);
+                    }
+                    continue;
+                }
+            };
+            let Some(&(_, name)) = self.watched.iter().find(|(watched, _)| *watched == handle) else {
+                continue;
+            };
+            let Some(source) = self.sources.get(handle) else {
+                continue;
+            };
+            match validate(source) {
+                Ok(()) => {
+                    if self.errors.remove(name).is_some() || reloaded {
+                        toasts.info(format!("Reloaded the {name} shader"));
+                    }
+                    changed.push((name, Arc::new(ShaderProgram::wgsl(source.clone()))));
+                }
+                Err(err) => {
+                    log::warn!("The {name} shader failed to compile; keeping the last good one:\n{err}");
+                    toasts.error(format!("The {name} shader failed to compile"));
+                    self.errors.insert(name, err);
+                }
+            }
+        }
+        changed
+    }
+
+    /// The Shader Errors window, open while an edited shader doesn't compile.
+    pub fn show(&mut self, ctx: &Context) {
+        if self.errors.is_empty() {
+            return;
+        }
+        egui::Window::new("Shader Errors")
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                ui.weak(format!("Edit the files in {SHADER_DIR}/; the last good shaders draw until they compile"));
+                egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    for (name, err) in &self.errors {
+                        ui.strong(*name);
+                        ui.label(egui::RichText::new(err).monospace().color(ui.visuals().error_fg_color));
+                        ui.separator();
+                    }
+                });
+            });
+    }
+}
+
+impl Default for ShaderReloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_source(path: &Path) -> io::Result<String> {
+    fs::read_to_string(path)
+}
+
+// Parses and validates `source` the way wgpu would, so a broken edit is reported instead of raising a device error
+fn validate(source: &str) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| e.emit_to_string(source))?;
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(&module)
+        .map_err(|e| e.emit_to_string(source))?;
+    Ok(())
+}
@@ -0,0 +1,663 @@
+// skinning.rs
+
+use crate::bounds::Aabb;
+use crate::depth::DepthConfig;
+use crate::stats::RenderStats;
+use crate::transform::Transform;
+use crate::upload::Uploads;
+use crate::vertex_layout::{vertex_layout, VertexLayout};
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::{Mat4, Quat, Vec3, Vec4};
+use std::io;
+use std::path::Path;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct SkinnedVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub joints: [u32; 4],  // Indices into the skin's joints, as JOINTS_0 numbers them
+    pub weights: [f32; 4], // Summing to one
+}
+
+impl SkinnedVertex {
+    // Locations must match `VertexInput` in skinning.wgsl
+    pub const LAYOUT: VertexLayout = vertex_layout!(SkinnedVertex, Vertex {
+        0 => position: Float32x3,
+        1 => normal: Float32x3,
+        2 => joints: Uint32x4,
+        3 => weights: Float32x4,
+    });
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const LAYOUT: &VertexLayout = &SkinnedVertex::LAYOUT;
+        LAYOUT.buffer_layout()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    name: String,
+    parent: Option<usize>, // Always before the node itself
+    rest: Transform,
+}
+
+// Every node of the file in parent-before-child order, so world matrices come out of a single forward pass.
+// Nodes that aren't joints still count: an armature's root often is one and moves the whole rig
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    nodes: Vec<Node>,
+    joints: Vec<usize>, // Node of each joint
+    inverse_bind: Vec<Mat4>,
+}
+
+impl Skeleton {
+    pub fn joint_count(&self) -> usize {
+        self.joints.len()
+    }
+
+    /// Skinning matrices for the pose `clip` takes at `time`, or for the rest pose without a clip.
+    pub fn joint_matrices(&self, clip: Option<&Clip>, time: f32, matrices: &mut Vec<Mat4>) {
+        let mut locals: Vec<Transform> = self.nodes.iter().map(|node| node.rest).collect();
+        if let Some(clip) = clip {
+            clip.apply(time, &mut locals);
+        }
+        let mut globals: Vec<Mat4> = Vec::with_capacity(locals.len());
+        for (node, local) in self.nodes.iter().zip(&locals) {
+            let parent = node.parent.map_or(Mat4::IDENTITY, |parent| globals[parent]);
+            globals.push(parent * local.matrix());
+        }
+        matrices.clear();
+        matrices.extend(
+            self.joints
+                .iter()
+                .zip(&self.inverse_bind)
+                .map(|(&node, inverse_bind)| globals[node] * *inverse_bind),
+        );
+    }
+
+    pub fn joint_names(&self) -> impl Iterator<Item = &str> {
+        self.joints.iter().map(|&node| self.nodes[node].name.as_str())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Channel {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Interpolation {
+    Step,
+    Linear,
+    CubicSpline, // Each key holds an in-tangent, the value and an out-tangent
+}
+
+// Keys of one node property; rotations are quaternions as xyzw, translations and scales leave w unused
+#[derive(Debug, Clone)]
+struct Sampler {
+    node: usize,
+    channel: Channel,
+    interpolation: Interpolation,
+    times: Vec<f32>,
+    values: Vec<Vec4>,
+}
+
+impl Sampler {
+    fn value(&self, key: usize) -> Vec4 {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values[key * 3 + 1],
+            _ => self.values[key],
+        }
+    }
+
+    fn sample(&self, time: f32) -> Vec4 {
+        let next = self.times.partition_point(|&key| key <= time);
+        if next == 0 {
+            return self.value(0);
+        }
+        if next == self.times.len() {
+            return self.value(next - 1);
+        }
+        let (from, to) = (next - 1, next);
+        let span = self.times[to] - self.times[from];
+        let t = (time - self.times[from]) / span.max(f32::EPSILON);
+        let (a, b) = (self.value(from), self.value(to));
+        match self.interpolation {
+            Interpolation::Step => a,
+            Interpolation::Linear if self.channel == Channel::Rotation => {
+                Vec4::from(Quat::from_vec4(a).slerp(Quat::from_vec4(b), t))
+            }
+            Interpolation::Linear => a.lerp(b, t),
+            Interpolation::CubicSpline => {
+                let out_tangent = self.values[from * 3 + 2] * span;
+                let in_tangent = self.values[to * 3] * span;
+                let (t2, t3) = (t * t, t * t * t);
+                let value = a * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * (t3 - 2.0 * t2 + t)
+                    + b * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * (t3 - t2);
+                if self.channel == Channel::Rotation {
+                    value.normalize_or_zero()
+                } else {
+                    value
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Clip {
+    pub name: String,
+    pub duration: f32,
+    samplers: Vec<Sampler>,
+}
+
+impl Clip {
+    fn apply(&self, time: f32, locals: &mut [Transform]) {
+        for sampler in &self.samplers {
+            let value = sampler.sample(time);
+            let local = &mut locals[sampler.node];
+            match sampler.channel {
+                Channel::Translation => local.translation = value.truncate(),
+                Channel::Rotation => local.rotation = Quat::from_vec4(value).normalize(),
+                Channel::Scale => local.scale = value.truncate(),
+            }
+        }
+    }
+}
+
+// The first skinned mesh of a glTF file with its skeleton and animations. Other meshes and materials are not
+// imported
+#[derive(Debug, Clone)]
+pub struct SkinnedModel {
+    pub vertices: Vec<SkinnedVertex>,
+    pub indices: Vec<u32>,
+    pub bounds: Option<Aabb>, // In the rest pose
+    pub skeleton: Skeleton,
+    pub clips: Vec<Clip>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+impl SkinnedModel {
+    /// Reads a .gltf or .glb file, with a .gltf's buffers from next to it.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let gltf::Gltf { document, blob } = gltf::Gltf::open(path).map_err(invalid_gltf)?;
+        let buffers = gltf::import_buffers(&document, path.parent(), blob).map_err(invalid_gltf)?;
+
+        // Parent-before-child order from a walk down from the roots. A malformed file can list a node as the
+        // child of several others; it is kept under the first one
+        let all: Vec<gltf::Node> = document.nodes().collect();
+        let mut parents = vec![None; all.len()];
+        for node in &all {
+            for child in node.children() {
+                parents[child.index()].get_or_insert(node.index());
+            }
+        }
+        let mut order = Vec::with_capacity(all.len());
+        let mut remap = vec![usize::MAX; all.len()];
+        let mut pending: Vec<usize> = (0..all.len()).rev().filter(|&i| parents[i].is_none()).collect();
+        while let Some(index) = pending.pop() {
+            if remap[index] != usize::MAX {
+                continue;
+            }
+            remap[index] = order.len();
+            order.push(index);
+            let children: Vec<usize> = all[index].children().map(|child| child.index()).collect();
+            pending.extend(children.into_iter().rev().filter(|&child| parents[child] == Some(index)));
+        }
+        let node_at = |index: usize| match remap[index] {
+            usize::MAX => Err(invalid(format!("Node {index} is not reachable from a root"))),
+            new => Ok(new),
+        };
+        let nodes = order
+            .iter()
+            .map(|&old| {
+                let node = &all[old];
+                let (translation, rotation, scale) = node.transform().decomposed();
+                Node {
+                    name: node.name().map_or_else(|| format!("Node {old}"), str::to_string),
+                    parent: parents[old].map(|parent| remap[parent]),
+                    rest: Transform {
+                        translation: Vec3::from(translation),
+                        rotation: Quat::from_array(rotation),
+                        scale: Vec3::from(scale),
+                    },
+                }
+            })
+            .collect();
+
+        let (mesh, skin) = document
+            .nodes()
+            .find_map(|node| Some((node.mesh()?, node.skin()?)))
+            .ok_or_else(|| invalid("The file has no skinned mesh"))?;
+
+        let joints = skin.joints().map(|joint| node_at(joint.index())).collect::<io::Result<Vec<_>>>()?;
+        if joints.is_empty() {
+            return Err(invalid("The skin has no joints"));
+        }
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        let inverse_bind: Vec<Mat4> = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(|matrix| Mat4::from_cols_array_2d(&matrix)).collect(),
+            None => vec![Mat4::IDENTITY; joints.len()],
+        };
+        if inverse_bind.len() != joints.len() {
+            return Err(invalid("The skin has a different number of inverse bind matrices than joints"));
+        }
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<[f32; 3]> = reader
+                .read_positions()
+                .ok_or_else(|| invalid("A primitive has no positions"))?
+                .collect();
+            let normals: Vec<[f32; 3]> = match reader.read_normals() {
+                Some(normals) => normals.collect(),
+                None => vec![[0.0, 1.0, 0.0]; positions.len()],
+            };
+            let skin_joints: Vec<[u16; 4]> = reader
+                .read_joints(0)
+                .ok_or_else(|| invalid("A primitive of the skinned mesh has no JOINTS_0"))?
+                .into_u16()
+                .collect();
+            let weights: Vec<[f32; 4]> = reader
+                .read_weights(0)
+                .ok_or_else(|| invalid("A primitive of the skinned mesh has no WEIGHTS_0"))?
+                .into_f32()
+                .collect();
+            let base = vertices.len() as u32;
+            for (i, &position) in positions.iter().enumerate() {
+                let joint = skin_joints.get(i).copied().unwrap_or_default().map(u32::from);
+                if joint.iter().any(|&joint| joint as usize >= joints.len()) {
+                    return Err(invalid("A vertex refers to a joint the skin doesn't have"));
+                }
+                vertices.push(SkinnedVertex {
+                    position,
+                    normal: normals.get(i).copied().unwrap_or([0.0, 1.0, 0.0]),
+                    joints: joint,
+                    weights: weights.get(i).copied().unwrap_or([1.0, 0.0, 0.0, 0.0]),
+                });
+            }
+            match reader.read_indices() {
+                Some(read) => indices.extend(read.into_u32().map(|index| base + index)),
+                None => indices.extend(base..vertices.len() as u32),
+            }
+        }
+        if indices.is_empty() {
+            return Err(invalid("The skinned mesh has no triangles"));
+        }
+        if indices.iter().any(|&index| index as usize >= vertices.len()) {
+            return Err(invalid("The skinned mesh has an index past its vertices"));
+        }
+
+        let mut clips = Vec::new();
+        for animation in document.animations() {
+            let mut samplers = Vec::new();
+            let mut duration: f32 = 0.0;
+            for channel in animation.channels() {
+                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+                    continue;
+                };
+                let times: Vec<f32> = times.collect();
+                let (channel_kind, values): (Channel, Vec<Vec4>) = match outputs {
+                    gltf::animation::util::ReadOutputs::Translations(values) => {
+                        (Channel::Translation, values.map(|v| Vec3::from(v).extend(0.0)).collect())
+                    }
+                    gltf::animation::util::ReadOutputs::Rotations(values) => {
+                        (Channel::Rotation, values.into_f32().map(Vec4::from).collect())
+                    }
+                    gltf::animation::util::ReadOutputs::Scales(values) => {
+                        (Channel::Scale, values.map(|v| Vec3::from(v).extend(0.0)).collect())
+                    }
+                    gltf::animation::util::ReadOutputs::MorphTargetWeights(_) => continue,
+                };
+                let interpolation = match channel.sampler().interpolation() {
+                    gltf::animation::Interpolation::Step => Interpolation::Step,
+                    gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                    gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+                };
+                let keys_per_time = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+                if times.is_empty() || values.len() != times.len() * keys_per_time {
+                    return Err(invalid("An animation channel has a different number of times and values"));
+                }
+                duration = duration.max(*times.last().unwrap_or(&0.0));
+                samplers.push(Sampler {
+                    node: node_at(channel.target().node().index())?,
+                    channel: channel_kind,
+                    interpolation,
+                    times,
+                    values,
+                });
+            }
+            clips.push(Clip {
+                name: animation.name().map_or_else(|| format!("Clip {}", clips.len()), str::to_string),
+                duration,
+                samplers,
+            });
+        }
+
+        let bounds = Aabb::from_points(vertices.iter().map(|v| Vec3::from(v.position)));
+        Ok(Self {
+            vertices,
+            indices,
+            bounds,
+            skeleton: Skeleton {
+                nodes,
+                joints,
+                inverse_bind,
+            },
+            clips,
+        })
+    }
+}
+
+fn invalid_gltf(err: gltf::Error) -> io::Error {
+    match err {
+        gltf::Error::Io(err) => err,
+        err => invalid(err.to_string()),
+    }
+}
+
+// Pipeline drawing skinned meshes, in a pass after the scene's that shares its depth buffer. The vertex shader
+// blends each vertex between up to four joint matrices read from a storage buffer, so it needs an adapter with
+// storage buffers in the vertex stage
+pub struct SkinningRenderer {
+    pipeline: wgpu::RenderPipeline,
+    joint_layout: wgpu::BindGroupLayout,
+}
+
+impl SkinningRenderer {
+    /// `camera_layout` and `object_layout` are the scene's first two bind group layouts; the joints are the
+    /// third group.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_layout: &wgpu::BindGroupLayout,
+        object_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Skinning Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("skinning.wgsl").into()),
+        });
+        let joint_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Joint Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Skinning Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, object_layout, &joint_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Skinning Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[SkinnedVertex::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(DepthConfig::OPAQUE.state()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self { pipeline, joint_layout }
+    }
+
+    /// Uploads `model`'s geometry and a joint buffer sized for its skeleton.
+    pub fn create_mesh(
+        &self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        name: String,
+        model: SkinnedModel,
+    ) -> SkinnedMesh {
+        let vertex_buffer = stats.create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Skinned Vertex Buffer"),
+                contents: bytemuck::cast_slice(&model.vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            },
+        );
+        let index_buffer = stats.create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Skinned Index Buffer"),
+                contents: bytemuck::cast_slice(&model.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            },
+        );
+        let mut matrices = Vec::new();
+        model.skeleton.joint_matrices(None, 0.0, &mut matrices);
+        let joint_buffer = stats.create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Joint Matrix Buffer"),
+                contents: bytemuck::cast_slice(&matrices.iter().map(Mat4::to_cols_array).collect::<Vec<_>>()),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+        let joint_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Joint Bind Group"),
+            layout: &self.joint_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: joint_buffer.as_entire_binding(),
+            }],
+        });
+        SkinnedMesh {
+            name,
+            num_indices: model.indices.len() as u32,
+            model,
+            vertex_buffer,
+            index_buffer,
+            joint_buffer,
+            joint_bind_group,
+            matrices,
+            clip: None,
+            time: 0.0,
+            playing: true,
+            posed: false,
+        }
+    }
+
+    /// Draws `mesh` over the scene in `target`, depth tested against the scene's `depth`, with the given camera
+    /// and object bind groups.
+    pub fn draw(
+        &self,
+        stats: &mut RenderStats,
+        encoder: &mut wgpu::CommandEncoder,
+        mesh: &SkinnedMesh,
+        groups: &[(&wgpu::BindGroup, &[wgpu::DynamicOffset]); 2],
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Skinning Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        for (index, (bind_group, offsets)) in groups.iter().enumerate() {
+            pass.set_bind_group(index as u32, bind_group, offsets);
+        }
+        pass.set_bind_group(2, &mesh.joint_bind_group, &[]);
+        pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+        stats.record_draw(mesh.num_indices, 1);
+    }
+}
+
+// A loaded rigged model on the GPU, with the playback state of its clips
+pub struct SkinnedMesh {
+    pub name: String,
+    pub model: SkinnedModel,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+    joint_buffer: wgpu::Buffer,
+    joint_bind_group: wgpu::BindGroup,
+    matrices: Vec<Mat4>, // Joint matrices of the current pose
+    clip: Option<usize>, // None shows the rest pose
+    time: f32,
+    playing: bool,
+    posed: bool, // `matrices` match the clip and time
+}
+
+impl SkinnedMesh {
+    pub fn is_playing(&self) -> bool {
+        self.playing && self.clip.is_some()
+    }
+
+    /// Starts the first clip, if the model has any.
+    pub fn play_first_clip(&mut self) {
+        self.clip = (!self.model.clips.is_empty()).then_some(0);
+        self.time = 0.0;
+        self.posed = false;
+    }
+
+    /// Moves the playing clip on by `dt`, looping at its end.
+    pub fn advance(&mut self, dt: f32) {
+        let Some(clip) = self.clip.and_then(|clip| self.model.clips.get(clip)) else {
+            return;
+        };
+        if self.playing && clip.duration > 0.0 {
+            self.time = (self.time + dt) % clip.duration;
+            self.posed = false;
+        }
+    }
+
+    /// Holds the pose `clip` takes at `time`, wrapped to its length, pausing playback.
+    pub fn pose(&mut self, clip: usize, time: f32) {
+        let Some(duration) = self.model.clips.get(clip).map(|clip| clip.duration) else {
+            return;
+        };
+        let time = if duration > 0.0 { time % duration } else { 0.0 };
+        if self.clip != Some(clip) || self.time != time {
+            self.clip = Some(clip);
+            self.time = time;
+            self.posed = false;
+        }
+        self.playing = false;
+    }
+
+    /// Samples the pose and stages the joint matrices for the GPU when the time or clip changed.
+    pub fn upload(&mut self, device: &wgpu::Device, uploads: &mut Uploads) {
+        if self.posed {
+            return;
+        }
+        let clip = self.clip.and_then(|clip| self.model.clips.get(clip));
+        self.model.skeleton.joint_matrices(clip, self.time, &mut self.matrices);
+        let columns: Vec<[f32; 16]> = self.matrices.iter().map(Mat4::to_cols_array).collect();
+        uploads.write(device, &self.joint_buffer, 0, bytemuck::cast_slice(&columns));
+        self.posed = true;
+    }
+
+    pub fn destroy(self, stats: &mut RenderStats) {
+        for buffer in [&self.vertex_buffer, &self.index_buffer, &self.joint_buffer] {
+            stats.buffer_destroyed(buffer);
+            buffer.destroy();
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        egui::Window::new("Skinned Model")
+            .default_open(true)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.strong(&self.name);
+                ui.label(format!(
+                    "{} vertices, {} triangles, {} joints",
+                    self.model.vertices.len(),
+                    self.num_indices / 3,
+                    self.model.skeleton.joint_count()
+                ));
+                let selected = self
+                    .clip
+                    .and_then(|clip| self.model.clips.get(clip))
+                    .map_or("Rest pose", |clip| clip.name.as_str());
+                let mut clip = self.clip;
+                egui::ComboBox::from_label("Clip").selected_text(selected).show_ui(ui, |ui| {
+                    ui.selectable_value(&mut clip, None, "Rest pose");
+                    for (index, candidate) in self.model.clips.iter().enumerate() {
+                        ui.selectable_value(&mut clip, Some(index), &candidate.name);
+                    }
+                });
+                if clip != self.clip {
+                    self.clip = clip;
+                    self.time = 0.0;
+                    self.posed = false;
+                }
+                if let Some(duration) = self.clip.and_then(|clip| self.model.clips.get(clip)).map(|c| c.duration) {
+                    ui.horizontal(|ui| {
+                        let label = if self.playing { "⏸ Pause" } else { "▶ Play" };
+                        if ui.button(label).clicked() {
+                            self.playing = !self.playing;
+                        }
+                        let slider = egui::Slider::new(&mut self.time, 0.0..=duration).suffix(" s");
+                        if ui.add(slider).changed() {
+                            self.posed = false;
+                        }
+                    });
+                }
+                ui.collapsing("Joints", |ui| {
+                    egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                        for name in self.model.skeleton.joint_names() {
+                            ui.label(name);
+                        }
+                    });
+                });
+            });
+    }
+}
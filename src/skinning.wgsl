@@ -0,0 +1,107 @@
+// Skinned meshes: each vertex is moved by up to four joints of the skeleton, weighted
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Every enabled light in the scene, gathered each frame from the world's Light components
+struct Light {
+    position: vec4<f32>, // w: 0 directional, 1 point, 2 spot
+    direction: vec4<f32>, // w: range
+    color: vec4<f32>, // Times intensity
+    cone: vec4<f32>, // Cosines of the spot's inner and outer angles
+};
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    fog_color: vec3<f32>,
+    fog_mode: u32, // 0 off, 1 linear, 2 exponential
+    fog: vec4<f32>, // Start, end, density and height falloff
+    lights: array<Light, 16>,
+};
+@group(0) @binding(1)
+var<uniform> lights: Lights;
+
+struct ModelUniform {
+    model: mat4x4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> model_uniform: ModelUniform;
+
+// Joint world matrix times inverse bind matrix, one per joint of the skin
+@group(2) @binding(0)
+var<storage, read> joints: array<mat4x4<f32>>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) joints: vec4<u32>,
+    @location(3) weights: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let skin = joints[in.joints.x] * in.weights.x
+        + joints[in.joints.y] * in.weights.y
+        + joints[in.joints.z] * in.weights.z
+        + joints[in.joints.w] * in.weights.w;
+    let world = model_uniform.model * skin;
+    var out: VertexOutput;
+    let world_position = world * vec4<f32>(in.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    // Good enough for previewing without non-uniform scale
+    out.normal = (world * vec4<f32>(in.normal, 0.0)).xyz;
+    return out;
+}
+
+// Diffuse light reaching a surface at `position` facing `normal`
+fn shade(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var total = lights.ambient;
+    for (var i = 0u; i < lights.count; i++) {
+        let light = lights.lights[i];
+        var to_light = -light.direction.xyz;
+        var falloff = 1.0;
+        if light.position.w > 0.5 {
+            let offset = light.position.xyz - position;
+            let distance = length(offset);
+            to_light = offset / max(distance, 0.0001);
+            falloff = clamp(1.0 - distance / light.direction.w, 0.0, 1.0);
+            falloff *= falloff;
+            if light.position.w > 1.5 {
+                let cos_angle = dot(-to_light, light.direction.xyz);
+                falloff *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+            }
+        }
+        total += light.color.rgb * max(dot(normal, to_light), 0.0) * falloff;
+    }
+    return total;
+}
+
+// How much of a surface at `position` the fog hides, 0 to 1
+fn fog_amount(position: vec3<f32>) -> f32 {
+    let distance = length(position - camera.position.xyz);
+    var amount = 0.0;
+    if lights.fog_mode == 1u {
+        amount = clamp((distance - lights.fog.x) / (lights.fog.y - lights.fog.x), 0.0, 1.0);
+    } else if lights.fog_mode == 2u {
+        amount = 1.0 - exp(-lights.fog.z * distance);
+    }
+    // Thinner the higher the surface stands above the ground plane
+    return amount * exp(-lights.fog.w * max(position.y, 0.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = vec3<f32>(0.8) * shade(in.world_position, normalize(in.normal));
+    return vec4<f32>(mix(color, lights.fog_color, fog_amount(in.world_position)), 1.0);
+}
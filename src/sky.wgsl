@@ -0,0 +1,93 @@
+// Sky behind the scene: drawn at the far plane wherever nothing else was, blending from the horizon color up to
+// the zenith and down to the ground, with a glow around the sun. By day the Preetham analytic model can take
+// over from the gradient above the horizon. An environment image replaces all of it when one is shown
+
+struct Sky {
+    inverse_view_proj: mat4x4<f32>,
+    zenith: vec4<f32>,
+    horizon: vec4<f32>,
+    ground: vec4<f32>,
+    sun_direction: vec4<f32>, // Towards the sun; w: how bright its glow is
+    perez: array<vec4<f32>, 5>, // A to E of the Perez distribution, for luminance and the two chromaticities
+    sky_zenith: vec4<f32>, // Luminance and chromaticity at the zenith over the distribution there; w: how much shows
+    image: vec4<f32>, // 1 if the environment image shows, its intensity and its rotation in radians
+};
+@group(0) @binding(0)
+var<uniform> sky: Sky;
+
+@group(1) @binding(0)
+var environment: texture_cube<f32>;
+@group(1) @binding(1)
+var environment_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// A single triangle covering the screen at the far plane, so no vertex buffer is needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+// Relative brightness of the sky at `cos_theta` from the zenith and `gamma` radians from the sun
+fn perez(cos_theta: f32, gamma: f32) -> vec3<f32> {
+    let a = sky.perez[0].xyz;
+    let b = sky.perez[1].xyz;
+    let c = sky.perez[2].xyz;
+    let d = sky.perez[3].xyz;
+    let e = sky.perez[4].xyz;
+    let cos_gamma = cos(gamma);
+    return (1.0 + a * exp(b / cos_theta)) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+// Linear RGB of the analytic sky along `direction`, which is clamped to the horizon
+fn analytic_sky(direction: vec3<f32>) -> vec3<f32> {
+    let to_sun = normalize(vec3<f32>(sky.sun_direction.x, max(sky.sun_direction.y, 0.02), sky.sun_direction.z));
+    let gamma = acos(clamp(dot(direction, to_sun), -1.0, 1.0));
+    let yxy = sky.sky_zenith.xyz * perez(max(direction.y, 0.01), gamma);
+    // Chromaticity to XYZ, then XYZ to linear sRGB
+    let xyz = vec3<f32>(yxy.y / yxy.z * yxy.x, yxy.x, (1.0 - yxy.y - yxy.z) / yxy.z * yxy.x);
+    let rgb = mat3x3<f32>(
+        vec3<f32>(3.2406, -0.9689, 0.0557),
+        vec3<f32>(-1.5372, 1.8758, -0.2040),
+        vec3<f32>(-0.4986, 0.0415, 1.0570),
+    ) * xyz;
+    return max(rgb, vec3<f32>(0.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let near = sky.inverse_view_proj * vec4<f32>(in.ndc, 0.0, 1.0);
+    let far = sky.inverse_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let direction = normalize(far.xyz / far.w - near.xyz / near.w);
+    if sky.image.x > 0.0 {
+        // Turned about the vertical by the image's rotation
+        let c = cos(sky.image.z);
+        let s = sin(sky.image.z);
+        let turned = vec3<f32>(c * direction.x + s * direction.z, direction.y, c * direction.z - s * direction.x);
+        return vec4<f32>(textureSampleLevel(environment, environment_sampler, turned, 0.0).rgb * sky.image.y, 1.0);
+    }
+
+    var color: vec3<f32>;
+    if direction.y >= 0.0 {
+        color = mix(sky.horizon.rgb, sky.zenith.rgb, pow(direction.y, 0.5));
+    } else {
+        color = mix(sky.horizon.rgb, sky.ground.rgb, pow(-direction.y, 0.4));
+    }
+    if sky.sky_zenith.w > 0.0 {
+        // Below the horizon, the ground darkens from the analytic horizon instead
+        let analytic = analytic_sky(vec3<f32>(direction.x, max(direction.y, 0.0), direction.z));
+        let ground = mix(analytic, analytic * 0.35, pow(max(-direction.y, 0.0), 0.4));
+        color = mix(color, ground, sky.sky_zenith.w);
+    }
+    let glow = pow(max(dot(direction, sky.sun_direction.xyz), 0.0), 64.0) * sky.sun_direction.w;
+    color += vec3<f32>(1.0, 0.8, 0.5) * glow;
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,111 @@
+// spacemouse.rs
+
+use crate::input::InputEvent;
+
+// Largest axis value reported by common 3Dconnexion devices, used to normalize to -1..1
+#[cfg(all(feature = "spacemouse", unix))]
+const FULL_DEFLECTION: f32 = 350.0;
+
+// 6DOF devices read through the spacenavd daemon's socket; a no-op unless built with the
+// `spacemouse` feature on a Unix platform
+pub struct SpaceMouse {
+    #[cfg(all(feature = "spacemouse", unix))]
+    connection: Option<Connection>,
+}
+
+#[cfg(all(feature = "spacemouse", unix))]
+struct Connection {
+    stream: std::os::unix::net::UnixStream,
+    pending: Vec<u8>, // Bytes of a packet that arrived only partly
+}
+
+impl SpaceMouse {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(all(feature = "spacemouse", unix))]
+            connection: Connection::open()
+                .map_err(|err| log::warn!("Space mouse unavailable, is spacenavd running? {err}"))
+                .ok(),
+        }
+    }
+
+    #[cfg(not(all(feature = "spacemouse", unix)))]
+    pub fn poll(&mut self) -> Vec<InputEvent> {
+        Vec::new()
+    }
+
+    /// Drains pending device motion, to be applied to `InputState`.
+    #[cfg(all(feature = "spacemouse", unix))]
+    pub fn poll(&mut self) -> Vec<InputEvent> {
+        let Some(connection) = &mut self.connection else {
+            return Vec::new();
+        };
+        match connection.read_packets() {
+            Ok(packets) => packets.into_iter().filter_map(motion_event).collect(),
+            Err(err) => {
+                log::warn!("Lost connection to spacenavd: {err}");
+                self.connection = None;
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl Default for SpaceMouse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(feature = "spacemouse", unix))]
+impl Connection {
+    const SOCKET_PATH: &'static str = "/var/run/spnav.sock";
+    const PACKET_SIZE: usize = 8 * 4; // Eight native-endian i32s
+
+    fn open() -> std::io::Result<Self> {
+        let stream = std::os::unix::net::UnixStream::connect(Self::SOCKET_PATH)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            pending: Vec::new(),
+        })
+    }
+
+    fn read_packets(&mut self) -> std::io::Result<Vec<[i32; 8]>> {
+        use std::io::{ErrorKind, Read};
+
+        let mut buffer = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.pending.extend_from_slice(&buffer[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let complete = self.pending.len() / Self::PACKET_SIZE * Self::PACKET_SIZE;
+        let packets = self.pending[..complete]
+            .chunks_exact(Self::PACKET_SIZE)
+            .map(|packet| std::array::from_fn(|i| i32::from_ne_bytes(packet[i * 4..i * 4 + 4].try_into().unwrap())))
+            .collect();
+        self.pending.drain(..complete);
+        Ok(packets)
+    }
+}
+
+// spacenavd packets are a type (0 for motion, 1 and 2 for button press and release) followed by
+// x, y, z, rx, ry, rz and the time since the last motion; only motion is used
+#[cfg(all(feature = "spacemouse", unix))]
+fn motion_event(packet: [i32; 8]) -> Option<InputEvent> {
+    use glam::Vec3;
+
+    if packet[0] != 0 {
+        return None;
+    }
+    let axis = |i: usize| (packet[i] as f32 / FULL_DEFLECTION).clamp(-1.0, 1.0);
+    Some(InputEvent::SpaceMotion {
+        translation: Vec3::new(axis(1), axis(2), axis(3)),
+        rotation: Vec3::new(axis(4), axis(5), axis(6)),
+    })
+}
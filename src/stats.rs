@@ -0,0 +1,224 @@
+// stats.rs
+
+use crate::buffer_pool::BufferPoolStats;
+use crate::jobs::JobStats;
+use crate::pacing::FramePacer;
+use egui_wgpu::wgpu;
+use wgpu::util::DeviceExt;
+
+// Generous for this renderer's own resources, so going over points at a leak rather than a big scene
+pub const DEFAULT_MEMORY_BUDGET_MIB: u32 = 512;
+
+// Counters reset at the start of every frame
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FrameStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+    pub buffer_allocations: u32,
+    pub texture_allocations: u32,
+    pub objects_culled: u32,   // Skipped because they were entirely outside the view
+    pub objects_occluded: u32, // Skipped because they were entirely behind other meshes or the terrain
+}
+
+// Tracks what the renderer draws and allocates; egui's own resources are not included
+#[derive(Debug, Default)]
+pub struct RenderStats {
+    frame: FrameStats,
+    last_frame: FrameStats,
+    pub live_buffers: u32,
+    pub buffer_bytes: u64,
+    pub live_textures: u32,
+    pub texture_bytes: u64,
+    pub cached_bind_groups: u32,
+    pub buffer_pool: BufferPoolStats,
+    pub jobs: JobStats,
+    pub skipped_frames: u64, // Identical to the one before, so never drawn
+    pub scene_size: (u32, u32), // Below the surface size while dynamic resolution scales the scene down
+    pub budget: Option<u64>, // Bytes of buffers and textures allowed before warning
+    over_budget: bool,
+}
+
+impl RenderStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.last_frame = std::mem::take(&mut self.frame);
+    }
+
+    /// Undoes `begin_frame` for a frame that ended up not being drawn, so the counters keep showing the
+    /// last frame that was.
+    pub fn cancel_frame(&mut self) {
+        self.frame = std::mem::take(&mut self.last_frame);
+    }
+
+    // Counters of the last completed frame
+    pub fn last_frame(&self) -> FrameStats {
+        self.last_frame
+    }
+
+    pub fn record_draw(&mut self, index_count: u32, instance_count: u32) {
+        self.frame.draw_calls += 1;
+        self.frame.triangles += (index_count / 3) as u64 * instance_count as u64;
+    }
+
+    pub fn record_culled(&mut self) {
+        self.frame.objects_culled += 1;
+    }
+
+    pub fn record_occluded(&mut self) {
+        self.frame.objects_occluded += 1;
+    }
+
+    /// Creates a buffer counted in the live totals. All renderer allocations go through these so the
+    /// totals cover everything.
+    pub fn create_buffer(&mut self, device: &wgpu::Device, descriptor: &wgpu::BufferDescriptor) -> wgpu::Buffer {
+        let buffer = device.create_buffer(descriptor);
+        self.buffer_created(&buffer);
+        buffer
+    }
+
+    pub fn create_buffer_init(
+        &mut self,
+        device: &wgpu::Device,
+        descriptor: &wgpu::util::BufferInitDescriptor,
+    ) -> wgpu::Buffer {
+        let buffer = device.create_buffer_init(descriptor);
+        self.buffer_created(&buffer);
+        buffer
+    }
+
+    pub fn create_texture(&mut self, device: &wgpu::Device, descriptor: &wgpu::TextureDescriptor) -> wgpu::Texture {
+        let texture = device.create_texture(descriptor);
+        self.texture_created(&texture);
+        texture
+    }
+
+    fn buffer_created(&mut self, buffer: &wgpu::Buffer) {
+        self.frame.buffer_allocations += 1;
+        self.live_buffers += 1;
+        self.buffer_bytes += buffer.size();
+    }
+
+    pub fn buffer_destroyed(&mut self, buffer: &wgpu::Buffer) {
+        self.live_buffers = self.live_buffers.saturating_sub(1);
+        self.buffer_bytes = self.buffer_bytes.saturating_sub(buffer.size());
+    }
+
+    fn texture_created(&mut self, texture: &wgpu::Texture) {
+        self.frame.texture_allocations += 1;
+        self.live_textures += 1;
+        self.texture_bytes += texture_size_bytes(texture);
+    }
+
+    pub fn texture_destroyed(&mut self, texture: &wgpu::Texture) {
+        self.live_textures = self.live_textures.saturating_sub(1);
+        self.texture_bytes = self.texture_bytes.saturating_sub(texture_size_bytes(texture));
+    }
+
+    pub fn memory_bytes(&self) -> u64 {
+        self.buffer_bytes + self.texture_bytes
+    }
+
+    /// True only on the frame the live total first goes over the budget, so the caller warns once.
+    pub fn exceeded_budget(&mut self) -> bool {
+        let over = self.budget.is_some_and(|budget| self.memory_bytes() > budget);
+        let exceeded = over && !self.over_budget;
+        self.over_budget = over;
+        exceeded
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui, pacer: &mut FramePacer) {
+        let frame = self.last_frame;
+        egui::Grid::new("stats_grid").num_columns(2).show(ui, |ui| {
+            ui.label("Draw calls");
+            ui.label(frame.draw_calls.to_string());
+            ui.end_row();
+            ui.label("Triangles");
+            ui.label(frame.triangles.to_string());
+            ui.end_row();
+            ui.label("Objects culled");
+            ui.label(frame.objects_culled.to_string());
+            ui.end_row();
+            ui.label("Objects occluded");
+            ui.label(frame.objects_occluded.to_string());
+            ui.end_row();
+            ui.label("Buffer allocations / frame");
+            ui.label(frame.buffer_allocations.to_string());
+            ui.end_row();
+            ui.label("Texture allocations / frame");
+            ui.label(frame.texture_allocations.to_string());
+            ui.end_row();
+            ui.label("Live buffers");
+            ui.label(format!("{} ({})", self.live_buffers, format_bytes(self.buffer_bytes)));
+            ui.end_row();
+            ui.label("Live textures");
+            ui.label(format!("{} ({})", self.live_textures, format_bytes(self.texture_bytes)));
+            ui.end_row();
+            ui.label("Cached bind groups");
+            ui.label(self.cached_bind_groups.to_string());
+            ui.end_row();
+            ui.label("Scene resolution");
+            ui.label(format!("{}x{}", self.scene_size.0, self.scene_size.1));
+            ui.end_row();
+            ui.label("Identical frames skipped");
+            ui.label(self.skipped_frames.to_string());
+            ui.end_row();
+            let pool = self.buffer_pool;
+            ui.label("Pooled buffers");
+            ui.label(format!("{} ({})", pool.buffers, format_bytes(pool.bytes)));
+            ui.end_row();
+            ui.label("Pool reuse rate");
+            ui.label(format!("{:.0}% of {}", pool.reuse_rate() * 100.0, pool.acquired));
+            ui.end_row();
+            ui.label("Background jobs");
+            let jobs = self.jobs;
+            ui.label(format!("{} running, {} queued ({} workers)", jobs.running, jobs.queued, jobs.workers));
+            ui.end_row();
+            ui.label("Approx. GPU memory");
+            match self.budget {
+                Some(budget) => {
+                    let text = format!("{} of {}", format_bytes(self.memory_bytes()), format_bytes(budget));
+                    if self.over_budget {
+                        ui.colored_label(ui.visuals().warn_fg_color, text);
+                    } else {
+                        ui.label(text);
+                    }
+                }
+                None => {
+                    ui.label(format_bytes(self.memory_bytes()));
+                }
+            }
+            ui.end_row();
+        });
+        egui::CollapsingHeader::new("Frame pacing").show(ui, |ui| pacer.ui(ui));
+    }
+}
+
+// Approximate size including every mip level and sample; compressed formats use their block size
+fn texture_size_bytes(texture: &wgpu::Texture) -> u64 {
+    let format = texture.format();
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(4) as u64;
+    let size = texture.size();
+    (0..texture.mip_level_count())
+        .map(|level| {
+            let width = (size.width >> level).max(1).div_ceil(block_width) as u64;
+            let height = (size.height >> level).max(1).div_ceil(block_height) as u64;
+            width * height * size.depth_or_array_layers as u64 * block_size
+        })
+        .sum::<u64>()
+        * texture.sample_count() as u64
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
@@ -0,0 +1,383 @@
+// stereo.rs
+
+use crate::camera::{off_center_projection, Camera, CameraUniform};
+use crate::depth::DEPTH_FORMAT;
+use crate::stats::RenderStats;
+use crate::upload::Uploads;
+use egui_wgpu::wgpu;
+use glam::{Mat4, Vec3};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+// In world units, like the distance between the eyes and the depth that appears at the screen
+pub const EYE_SEPARATION_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+pub const CONVERGENCE_RANGE: RangeInclusive<f32> = 0.1..=100.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StereoMode {
+    Off,
+    Anaglyph,   // Red/cyan, for glasses with a red filter over the left eye
+    SideBySide, // Each eye gets half the window's width
+}
+
+impl StereoMode {
+    pub const ALL: [StereoMode; 3] = [StereoMode::Off, StereoMode::Anaglyph, StereoMode::SideBySide];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            StereoMode::Off => "Off",
+            StereoMode::Anaglyph => "Anaglyph",
+            StereoMode::SideBySide => "Side by side",
+        }
+    }
+}
+
+// How the scene is split between two eyes, set in the preferences and saved with the settings
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StereoSettings {
+    pub mode: StereoMode,
+    pub eye_separation: f32,
+    pub convergence: f32, // Distance at which the eyes' images line up, which seems to lie at the screen
+}
+
+impl StereoSettings {
+    pub fn new() -> Self {
+        Self {
+            mode: StereoMode::Off,
+            eye_separation: 0.06,
+            convergence: 2.0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mode != StereoMode::Off
+    }
+
+    /// The left and right eye's uniforms for `camera`, each drawing an image of `aspect`. The eyes are shifted
+    /// apart along the camera's right axis and their frusta skewed back towards each other, so they share one
+    /// plane at the convergence distance instead of turning in, which would distort it.
+    pub fn eye_uniforms(&self, camera: &Camera, aspect: f32) -> [CameraUniform; 2] {
+        let right = (camera.target - camera.position).cross(camera.up).normalize_or_zero();
+        let top = (camera.fovy.to_radians() / 2.0).tan();
+        let half_separation = self.eye_separation / 2.0;
+        [-half_separation, half_separation].map(|offset| {
+            let skew = offset / self.convergence.max(f32::EPSILON);
+            let tangents = (-top * aspect - skew, top * aspect - skew, top, -top);
+            let projection = off_center_projection(tangents, camera.znear, camera.zfar);
+            let view = Mat4::from_translation(Vec3::new(-offset, 0.0, 0.0)) * camera.view_matrix();
+            CameraUniform {
+                view_proj: (projection * view).to_cols_array_2d(),
+                position: (camera.position + right * offset).extend(1.0).to_array(),
+            }
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Stereo");
+            for mode in StereoMode::ALL {
+                ui.radio_value(&mut self.mode, mode, mode.label());
+            }
+        })
+        .response
+        .on_hover_text("Draws the scene's meshes once per eye; the sky, terrain and other overlays are left out");
+        if self.is_enabled() {
+            ui.horizontal(|ui| {
+                ui.label("Eye separation");
+                ui.add(egui::DragValue::new(&mut self.eye_separation).speed(0.005).range(EYE_SEPARATION_RANGE));
+                ui.label("Convergence");
+                ui.add(egui::DragValue::new(&mut self.convergence).speed(0.05).range(CONVERGENCE_RANGE))
+                    .on_hover_text("How far in front of the camera objects appear level with the screen");
+            });
+        }
+    }
+}
+
+impl Default for StereoSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Where the eyes are drawn, at the size each eye takes up
+struct EyeTargets {
+    textures: [wgpu::Texture; 2],
+    views: [wgpu::TextureView; 2],
+    depth: wgpu::Texture,
+    depth_view: wgpu::TextureView, // Shared by the eyes, which are drawn one after the other
+    bind_group: wgpu::BindGroup,
+}
+
+// Draws the scene's bundles from both eyes into offscreen targets, then combines them over the scene
+pub struct StereoRenderer {
+    format: wgpu::TextureFormat,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    anaglyph: wgpu::RenderPipeline,
+    side_by_side: wgpu::RenderPipeline,
+    cameras: Vec<(wgpu::Buffer, wgpu::BindGroup)>, // Left then right, in place of the scene's camera group
+    targets: Option<EyeTargets>,
+}
+
+impl StereoRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        format: wgpu::TextureFormat,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights: &wgpu::Buffer,
+    ) -> Self {
+        let eye_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Stereo Bind Group Layout"),
+            entries: &[
+                eye_entry(0),
+                eye_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Stereo Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Stereo Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("stereo.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Stereo Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label, entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point,
+                    targets: &[Some(format.into())],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let cameras = (0..2)
+            .map(|_| {
+                let buffer = stats.create_buffer(
+                    device,
+                    &wgpu::BufferDescriptor {
+                        label: Some("Stereo Camera Buffer"),
+                        size: size_of::<CameraUniform>() as wgpu::BufferAddress,
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    },
+                );
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Stereo Camera Bind Group"),
+                    layout: camera_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: lights.as_entire_binding(),
+                        },
+                    ],
+                });
+                (buffer, bind_group)
+            })
+            .collect();
+        Self {
+            format,
+            anaglyph: pipeline("Anaglyph Pipeline", "fs_anaglyph"),
+            side_by_side: pipeline("Side By Side Pipeline", "fs_side_by_side"),
+            layout,
+            sampler,
+            cameras,
+            targets: None,
+        }
+    }
+
+    /// Draws the bundles `record` makes for each eye's camera group, then combines the eyes into `target`, which
+    /// is `size`. Does nothing while `settings` has stereo off.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        settings: &StereoSettings,
+        camera: &Camera,
+        background: Vec3,
+        mut record: impl FnMut(&wgpu::BindGroup) -> Vec<wgpu::RenderBundle>,
+        target: &wgpu::TextureView,
+        (width, height): (u32, u32),
+    ) -> bool {
+        let eye_size = match settings.mode {
+            StereoMode::Off => return false,
+            StereoMode::Anaglyph => (width, height),
+            StereoMode::SideBySide => ((width / 2).max(1), height),
+        };
+        if self.targets.as_ref().is_none_or(|targets| {
+            (targets.depth.width(), targets.depth.height()) != eye_size
+        }) {
+            self.release(stats);
+            self.targets = Some(self.create_targets(device, stats, eye_size));
+        }
+        let Some(targets) = &self.targets else {
+            return false;
+        };
+
+        let uniforms = settings.eye_uniforms(camera, eye_size.0 as f32 / eye_size.1 as f32);
+        for ((uniform, (buffer, camera_group)), view) in uniforms.iter().zip(&self.cameras).zip(&targets.views) {
+            uploads.write(device, buffer, 0, bytemuck::bytes_of(uniform));
+            let bundles = record(camera_group);
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Stereo Eye Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: background.x as f64,
+                            g: background.y as f64,
+                            b: background.z as f64,
+                            a: 1.0,
+                        }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &targets.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            pass.execute_bundles(&bundles);
+        }
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Stereo Composite Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), // Every pixel is overwritten
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(match settings.mode {
+            StereoMode::SideBySide => &self.side_by_side,
+            _ => &self.anaglyph,
+        });
+        pass.set_bind_group(0, &targets.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+        true
+    }
+
+    /// Frees the eye targets until stereo is turned on again.
+    pub fn release(&mut self, stats: &mut RenderStats) {
+        if let Some(targets) = self.targets.take() {
+            for texture in targets.textures.iter().chain([&targets.depth]) {
+                stats.texture_destroyed(texture);
+            }
+        }
+    }
+
+    fn create_targets(
+        &self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        (width, height): (u32, u32),
+    ) -> EyeTargets {
+        let descriptor = wgpu::TextureDescriptor {
+            label: Some("Stereo Eye Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        };
+        let textures = [0, 1].map(|_| stats.create_texture(device, &descriptor));
+        let views = [0, 1].map(|eye| textures[eye].create_view(&wgpu::TextureViewDescriptor::default()));
+        let depth = stats.create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Stereo Depth Texture"),
+                format: DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                ..descriptor
+            },
+        );
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Stereo Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&views[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&views[1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        EyeTargets {
+            textures,
+            views,
+            depth_view: depth.create_view(&wgpu::TextureViewDescriptor::default()),
+            depth,
+            bind_group,
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Combines the two eye images of the stereo mode into the one the window shows
+
+@group(0) @binding(0)
+var left_eye: texture_2d<f32>;
+@group(0) @binding(1)
+var right_eye: texture_2d<f32>;
+@group(0) @binding(2)
+var eye_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// A single triangle covering the screen, so no vertex buffer is needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Half-color red/cyan: the left eye's brightness goes to the red filter and the right eye's own green and blue
+// to the cyan one. Full color in the red channel would make saturated reds flicker between the eyes
+@fragment
+fn fs_anaglyph(in: VertexOutput) -> @location(0) vec4<f32> {
+    let left = textureSample(left_eye, eye_sampler, in.uv).rgb;
+    let right = textureSample(right_eye, eye_sampler, in.uv).rgb;
+    let luminance = dot(left, vec3<f32>(0.299, 0.587, 0.114));
+    return vec4<f32>(luminance, right.g, right.b, 1.0);
+}
+
+// The left eye on the left half of the window and the right eye on the right, for parallel viewing or a display
+// that splits the halves itself. Sampled at level 0 since the branch isn't uniform
+@fragment
+fn fs_side_by_side(in: VertexOutput) -> @location(0) vec4<f32> {
+    if in.uv.x < 0.5 {
+        return textureSampleLevel(left_eye, eye_sampler, vec2<f32>(in.uv.x * 2.0, in.uv.y), 0.0);
+    }
+    return textureSampleLevel(right_eye, eye_sampler, vec2<f32>(in.uv.x * 2.0 - 1.0, in.uv.y), 0.0);
+}
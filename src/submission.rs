@@ -0,0 +1,58 @@
+// submission.rs
+
+use crate::upload::Uploads;
+use egui_wgpu::wgpu;
+
+// Where a command buffer runs within a frame's submit. Buffers go to the queue in this order, so compute work
+// finishes writing before any pass that reads its results, without barriers between them
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Compute, // Particles, culling and other work feeding this frame's passes
+    Scene,
+    Ui,
+}
+
+// Collects a frame's command buffers from whichever part of the loop encodes them and submits them in stage
+// order. wgpu hands out a single queue per device, so compute cannot run on a queue of its own yet; keeping
+// it in a separate stage means it only needs moving here once a second queue is exposed
+pub struct SubmissionScheduler {
+    pending: Vec<(Stage, wgpu::CommandBuffer)>,
+    last: Option<wgpu::SubmissionIndex>,
+}
+
+impl SubmissionScheduler {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            last: None,
+        }
+    }
+
+    /// Queues `commands` for the next submit.
+    pub fn push(&mut self, stage: Stage, commands: wgpu::CommandBuffer) {
+        self.pending.push((stage, commands));
+    }
+
+    /// Submits the staged uploads, then every queued command buffer by stage, keeping the order they were
+    /// pushed in within a stage.
+    pub fn submit(&mut self, queue: &wgpu::Queue, uploads: &mut Uploads) {
+        // Stable, so buffers of one stage keep their relative order
+        self.pending.sort_by_key(|(stage, _)| *stage);
+        let commands = self.pending.drain(..).map(|(_, commands)| commands);
+        self.last = Some(uploads.submit(queue, commands));
+    }
+
+    /// Blocks until everything submitted so far has finished on the GPU, for CPU work that reads back
+    /// results without a mapping callback.
+    pub fn wait(&self, device: &wgpu::Device) {
+        if let Some(index) = &self.last {
+            device.poll(wgpu::Maintain::WaitForSubmissionIndex(index.clone()));
+        }
+    }
+}
+
+impl Default for SubmissionScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,787 @@
+// terrain.rs
+
+use crate::assets::{AssetEvent, Assets};
+use crate::depth::DepthConfig;
+use crate::file_dialog::{FileDialogs, FileKind};
+use crate::jobs::FrameBudget;
+use crate::texture::{GpuTexture, TextureImage};
+use crate::stats::RenderStats;
+use crate::toasts::Toasts;
+use crate::upload::Uploads;
+use crate::vertex_layout::{vertex_layout, VertexLayout};
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::{Vec2, Vec3, Vec3Swizzles};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Cells along each side of the heightmap; there is one more vertex than cells
+const GRID: usize = 128;
+
+// Heightmap cells along each side of one cell of the occluder, which is far cheaper to rasterize than the ground
+const OCCLUDER_CELL: usize = 4;
+
+// Texels along each side of the painted blend map, which covers the whole terrain
+const BLEND_MAP_SIZE: usize = 256;
+
+// Side of the built-in layer textures, in texels
+const LAYER_TEXTURE_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct TerrainVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+impl TerrainVertex {
+    // Locations must match `VertexInput` in terrain.wgsl
+    const LAYOUT: VertexLayout = vertex_layout!(TerrainVertex, Vertex {
+        0 => position: Float32x3,
+        1 => normal: Float32x3,
+    });
+}
+
+// Laid out like `Terrain` in terrain.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct TerrainUniform {
+    layers: [[f32; 4]; 3], // Tint, and repeats per world unit in w
+    rules: [f32; 4],       // Rock slope, slope blend, snow height and height blend
+    size: f32,
+    _padding: [f32; 3],
+}
+
+// One of the textures splatted over the terrain
+#[derive(Debug, Clone)]
+pub struct TerrainLayer {
+    pub name: &'static str,
+    pub texture: Option<PathBuf>, // None shows a built-in noise texture
+    pub tint: Vec3,               // Linear
+    pub tiling: f32,              // Repeats of the texture per world unit
+}
+
+// Rolling hills from a seeded heightmap, textured with grass, rock and snow. Which layer shows where follows the
+// slope and height of the ground, unless painted over in the blend map
+#[derive(Debug, Clone)]
+pub struct Terrain {
+    pub enabled: bool,
+    pub size: f32,                 // Side of the square, centered on the origin
+    pub base: f32,                 // Height of the lowest ground the hills can have
+    pub height: f32,               // From the lowest ground the hills can have to the highest
+    pub feature_size: f32,         // Width of the largest hills
+    pub seed: u32,
+    pub layers: [TerrainLayer; 3], // Grass, rock and snow
+    pub rock_slope: f32,           // Steepness, 0 flat to 1 vertical, past which rock shows instead of grass
+    pub slope_blend: f32,          // Steepness over which grass fades into rock
+    pub snow_height: f32,          // Above which snow covers the ground
+    pub height_blend: f32,         // Height over which the ground fades into snow
+    pub painting: bool,            // Left dragging in the viewport paints the blend map
+    pub brush: Option<usize>,      // Layer painted; None paints back to the slope and height rules
+    pub brush_radius: f32,
+    pub brush_strength: f32,       // Per second of painting
+    heights: Vec<f32>,             // Of every vertex, row by row from -Z, each row from -X
+    blend_map: Vec<[f32; 4]>,      // Painted weights of the layers, and how much they override the rules in w
+    mesh_version: u64,             // Bumped whenever `heights` changes
+    blend_version: u64,            // Bumped whenever `blend_map` changes
+    choosing: Option<usize>,       // Layer waiting on the texture dialog
+}
+
+impl Terrain {
+    pub fn new() -> Self {
+        let layer = |name, tint, tiling| TerrainLayer {
+            name,
+            texture: None,
+            tint,
+            tiling,
+        };
+        let mut terrain = Self {
+            enabled: false,
+            size: 100.0,
+            base: -2.0,
+            height: 16.0,
+            feature_size: 40.0,
+            seed: 1,
+            layers: [
+                layer("Grass", Vec3::ONE, 0.5),
+                layer("Rock", Vec3::ONE, 0.25),
+                layer("Snow", Vec3::ONE, 0.25),
+            ],
+            rock_slope: 0.08,
+            slope_blend: 0.04,
+            snow_height: 8.0,
+            height_blend: 1.0,
+            painting: false,
+            brush: Some(0),
+            brush_radius: 3.0,
+            brush_strength: 2.0,
+            heights: Vec::new(),
+            blend_map: vec![[0.0; 4]; BLEND_MAP_SIZE * BLEND_MAP_SIZE],
+            mesh_version: 0,
+            blend_version: 1,
+            choosing: None,
+        };
+        terrain.generate();
+        terrain
+    }
+
+    // Rebuilds the heightmap from the shape settings
+    fn generate(&mut self) {
+        let cell = self.size / GRID as f32;
+        self.heights.clear();
+        for z in 0..=GRID {
+            for x in 0..=GRID {
+                let position = (Vec2::new(x as f32, z as f32) * cell - self.size * 0.5) / self.feature_size.max(0.01);
+                self.heights.push(self.base + hills(position, self.seed) * self.height);
+            }
+        }
+        self.mesh_version += 1;
+    }
+
+    /// Height of the ground at `x`, `z`, or None off the terrain.
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let grid = (Vec2::new(x, z) / self.size + 0.5) * GRID as f32;
+        if !self.enabled || grid.min_element() < 0.0 || grid.max_element() > GRID as f32 {
+            return None;
+        }
+        let cell = grid.floor().min(Vec2::splat(GRID as f32 - 1.0));
+        let f = grid - cell;
+        let (x, z) = (cell.x as usize, cell.y as usize);
+        let at = |x: usize, z: usize| self.heights[z * (GRID + 1) + x];
+        let near = at(x, z) + (at(x + 1, z) - at(x, z)) * f.x;
+        let far = at(x, z + 1) + (at(x + 1, z + 1) - at(x, z + 1)) * f.x;
+        Some(near + (far - near) * f.y)
+    }
+
+    /// A coarse copy of the ground for occlusion culling, seen from `eye`. Each corner sits at the lowest height
+    /// of the cells around it, so the copy never rises above the ground and hides nothing the ground doesn't.
+    /// Empty while the terrain is off or `eye` is below it.
+    pub fn occluder(&self, eye: Vec3) -> Vec<[Vec3; 3]> {
+        if !self.enabled || self.height_at(eye.x, eye.z).is_some_and(|height| eye.y <= height) {
+            return Vec::new();
+        }
+        let corners = GRID / OCCLUDER_CELL;
+        let cell = self.size / corners as f32;
+        let corner = |x: usize, z: usize| {
+            let (xs, zs) = (x.saturating_sub(1) * OCCLUDER_CELL, z.saturating_sub(1) * OCCLUDER_CELL);
+            let (xe, ze) = (((x + 1) * OCCLUDER_CELL).min(GRID), ((z + 1) * OCCLUDER_CELL).min(GRID));
+            let lowest = (zs..=ze)
+                .flat_map(|z| (xs..=xe).map(move |x| (x, z)))
+                .map(|(x, z)| self.heights[z * (GRID + 1) + x])
+                .fold(f32::INFINITY, f32::min);
+            let position = Vec2::new(x as f32, z as f32) * cell - self.size * 0.5;
+            Vec3::new(position.x, lowest, position.y)
+        };
+        let mut triangles = Vec::with_capacity(corners * corners * 2);
+        for z in 0..corners {
+            for x in 0..corners {
+                let (a, b, c, d) = (corner(x, z), corner(x + 1, z), corner(x, z + 1), corner(x + 1, z + 1));
+                triangles.push([a, c, b]);
+                triangles.push([b, c, d]);
+            }
+        }
+        triangles
+    }
+
+    /// Where a ray from `origin` along `direction` first meets the ground, marched a cell at a time.
+    pub fn ray_hit(&self, origin: Vec3, direction: Vec3) -> Option<Vec3> {
+        let step = self.size / GRID as f32;
+        let below = |t: f32| {
+            let point = origin + direction * t;
+            self.height_at(point.x, point.z).is_some_and(|height| point.y <= height)
+        };
+        let mut t = 0.0;
+        for _ in 0..GRID * 4 {
+            if below(t + step) {
+                // Narrow down on the crossing within the step
+                let (mut above, mut under) = (t, t + step);
+                for _ in 0..8 {
+                    let middle = (above + under) * 0.5;
+                    if below(middle) {
+                        under = middle;
+                    } else {
+                        above = middle;
+                    }
+                }
+                return Some(origin + direction * under);
+            }
+            t += step;
+        }
+        None
+    }
+
+    /// Paints the brush into the blend map around `point` for `dt` seconds at `pressure` 0..1. A light touch of
+    /// the pen paints a smaller, fainter spot; the mouse always presses fully.
+    pub fn paint(&mut self, point: Vec3, dt: f32, pressure: f32) {
+        let texel = self.size / BLEND_MAP_SIZE as f32;
+        let center = (point.xz() / self.size + 0.5) * BLEND_MAP_SIZE as f32 - 0.5;
+        let radius = self.brush_radius * (0.5 + 0.5 * pressure) / texel;
+        let low = (center - radius).max(Vec2::ZERO).as_uvec2();
+        let high = (center + radius).min(Vec2::splat(BLEND_MAP_SIZE as f32 - 1.0)).as_uvec2();
+        for y in low.y..=high.y {
+            for x in low.x..=high.x {
+                let distance = center.distance(Vec2::new(x as f32, y as f32)) / radius;
+                if distance >= 1.0 {
+                    continue;
+                }
+                let falloff = (1.0 - distance * distance).powi(2);
+                let amount = (self.brush_strength * pressure * dt * falloff).min(1.0);
+                let weights = &mut self.blend_map[y as usize * BLEND_MAP_SIZE + x as usize];
+                match self.brush {
+                    Some(layer) => {
+                        for (index, weight) in weights.iter_mut().enumerate() {
+                            let target = if index == layer || index == 3 { 1.0 } else { 0.0 };
+                            *weight += (target - *weight) * amount;
+                        }
+                    }
+                    None => weights[3] -= weights[3] * amount,
+                }
+            }
+        }
+        self.blend_version += 1;
+    }
+
+    pub fn is_choosing(&self) -> bool {
+        self.choosing.is_some()
+    }
+
+    /// Shows the image picked in the texture dialog on the layer whose Choose button opened it.
+    pub fn place_texture(&mut self, path: PathBuf) {
+        if let Some(layer) = self.choosing.take() {
+            self.layers[layer].texture = Some(path);
+        }
+    }
+
+    /// The Terrain window: switches the terrain on and sets its shape, its layers and the rules between them,
+    /// and the brush that paints over the rules.
+    pub fn show(&mut self, ctx: &Context, file_dialogs: &mut FileDialogs) {
+        egui::Window::new("Terrain")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.enabled, "Terrain");
+                ui.add_enabled_ui(self.enabled, |ui| {
+                    let mut reshaped = false;
+                    reshaped |= ui
+                        .add(egui::Slider::new(&mut self.size, 10.0..=1000.0).logarithmic(true).text("Size"))
+                        .changed();
+                    reshaped |= ui.add(egui::Slider::new(&mut self.base, -20.0..=20.0).text("Base")).changed();
+                    reshaped |= ui.add(egui::Slider::new(&mut self.height, 0.0..=50.0).text("Height")).changed();
+                    reshaped |= ui
+                        .add(
+                            egui::Slider::new(&mut self.feature_size, 1.0..=200.0)
+                                .logarithmic(true)
+                                .text("Hill size"),
+                        )
+                        .changed();
+                    reshaped |= ui.add(egui::DragValue::new(&mut self.seed).prefix("Seed ")).changed();
+                    if reshaped {
+                        self.generate();
+                    }
+
+                    ui.separator();
+                    egui::Grid::new("terrain_layers").num_columns(4).show(ui, |ui| {
+                        for (index, layer) in self.layers.iter_mut().enumerate() {
+                            ui.label(layer.name);
+                            let name = layer.texture.as_deref().and_then(Path::file_name);
+                            let name = name.map_or("Built-in".into(), |name| name.to_string_lossy().into_owned());
+                            ui.horizontal(|ui| {
+                                ui.label(name);
+                                if ui.button("Choose…").clicked() {
+                                    self.choosing = Some(index);
+                                    file_dialogs.open(FileKind::Texture);
+                                }
+                                if layer.texture.is_some() && ui.button("Clear").clicked() {
+                                    layer.texture = None;
+                                }
+                            });
+                            let mut tint = layer.tint.to_array();
+                            if ui.color_edit_button_rgb(&mut tint).changed() {
+                                layer.tint = Vec3::from(tint);
+                            }
+                            let tiling = egui::DragValue::new(&mut layer.tiling).speed(0.01).prefix("Tiling ");
+                            ui.add(tiling.range(0.01..=10.0));
+                            ui.end_row();
+                        }
+                    });
+                    ui.add(egui::Slider::new(&mut self.rock_slope, 0.0..=1.0).text("Rock slope"));
+                    ui.add(egui::Slider::new(&mut self.slope_blend, 0.01..=0.5).text("Slope blend"));
+                    ui.add(egui::Slider::new(&mut self.snow_height, -20.0..=70.0).text("Snow height"));
+                    ui.add(egui::Slider::new(&mut self.height_blend, 0.01..=10.0).text("Height blend"));
+
+                    ui.separator();
+                    ui.toggle_value(&mut self.painting, "Paint by dragging");
+                    ui.horizontal(|ui| {
+                        for (index, layer) in self.layers.iter().enumerate() {
+                            ui.radio_value(&mut self.brush, Some(index), layer.name);
+                        }
+                        ui.radio_value(&mut self.brush, None, "Rules")
+                            .on_hover_text("Paints back to the slope and height rules");
+                    });
+                    ui.add(egui::Slider::new(&mut self.brush_radius, 0.1..=50.0).logarithmic(true).text("Radius"));
+                    ui.add(egui::Slider::new(&mut self.brush_strength, 0.1..=10.0).text("Strength"));
+                    if ui.button("Clear paint").clicked() {
+                        self.blend_map.fill([0.0; 4]);
+                        self.blend_version += 1;
+                    }
+                });
+            });
+    }
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The terrain's vertices and triangles on the GPU, from one version of its heightmap
+struct TerrainMesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+}
+
+// Draws the terrain in a pass after the scene's that shares its depth buffer. The shader picks each layer's
+// weight from the slope and height of the ground, then mixes in the painted blend map
+pub struct TerrainRenderer {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    blend_map: GpuTexture,
+    blend_sampler: wgpu::Sampler,
+    layer_sampler: wgpu::Sampler,
+    built_in: [GpuTexture; 3],
+    images: Assets<TextureImage>,
+    textures: HashMap<PathBuf, Option<GpuTexture>>, // None until the file has loaded
+    bind_group: Option<(wgpu::BindGroup, [Option<PathBuf>; 3])>, // With the layer files it binds
+    mesh: Option<TerrainMesh>,
+    mesh_version: u64,
+    blend_version: u64,
+}
+
+impl TerrainRenderer {
+    /// `camera_layout` is the scene's first bind group layout, which also holds the lights; the terrain's own
+    /// uniform and textures are the second group.
+    pub fn new(
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        format: wgpu::TextureFormat,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Terrain Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("terrain.wgsl").into()),
+        });
+        let uniform_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Terrain Uniform Buffer"),
+                size: size_of::<TerrainUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Terrain Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(1),
+                sampler(2),
+                texture(3),
+                texture(4),
+                texture(5),
+                sampler(6),
+            ],
+        });
+        let blend_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Terrain Blend Map Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let layer_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Terrain Layer Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Terrain Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Terrain Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[TerrainVertex::LAYOUT.buffer_layout()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(DepthConfig::OPAQUE.state()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let blend_texture = stats.create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Terrain Blend Map"),
+                size: wgpu::Extent3d {
+                    width: BLEND_MAP_SIZE as u32,
+                    height: BLEND_MAP_SIZE as u32,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                // Weights, not colors, so they are read back as written
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+        );
+        let blend_map = GpuTexture {
+            view: blend_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            texture: blend_texture,
+        };
+        let built_in = [
+            layer_texture([0.3, 0.5, 0.2], 1),
+            layer_texture([0.5, 0.48, 0.45], 2),
+            layer_texture([0.9, 0.92, 0.95], 3),
+        ];
+        Self {
+            pipeline,
+            layout,
+            uniform_buffer,
+            blend_map,
+            blend_sampler,
+            layer_sampler,
+            built_in: built_in.map(|image| GpuTexture::new(device, stats, uploads, &image)),
+            images: Assets::new(TextureImage::load),
+            textures: HashMap::new(),
+            bind_group: None,
+            mesh: None,
+            mesh_version: 0,
+            blend_version: 0,
+        }
+    }
+
+    /// Rebuilds the mesh and re-uploads the blend map when `terrain` changed them, and loads the textures of
+    /// its layers, reloading them when edited on disk. Returns whether anything drawn changed.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        budget: &mut FrameBudget,
+        terrain: &Terrain,
+        toasts: &mut Toasts,
+    ) -> bool {
+        if !terrain.enabled {
+            return false;
+        }
+        let mut changed = false;
+        // The grid never changes size, so sculpting only rewrites the vertices in place
+        if self.mesh_version != terrain.mesh_version {
+            let vertices = Self::vertices(terrain);
+            let mesh = self.mesh.get_or_insert_with(|| Self::create_mesh(device, stats));
+            uploads.write(device, &mesh.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+            self.mesh_version = terrain.mesh_version;
+            changed = true;
+        }
+        if self.blend_version != terrain.blend_version {
+            let pixels: Vec<u8> =
+                terrain.blend_map.iter().flatten().map(|weight| (weight.clamp(0.0, 1.0) * 255.0) as u8).collect();
+            uploads.write_texture(device, &self.blend_map.texture, &pixels);
+            self.blend_version = terrain.blend_version;
+            changed = true;
+        }
+
+        for layer in &terrain.layers {
+            if let Some(path) = layer.texture.as_ref().filter(|path| !self.textures.contains_key(*path)) {
+                self.images.load(path, true);
+                self.textures.insert(path.clone(), None);
+            }
+        }
+        for event in self.images.poll(budget) {
+            match event {
+                AssetEvent::Loaded(handle) | AssetEvent::Reloaded(handle) => {
+                    let (Some(image), Some(path)) = (self.images.get(handle), self.images.path(handle)) else {
+                        continue;
+                    };
+                    let texture = GpuTexture::new(device, stats, uploads, image);
+                    if let Some(Some(previous)) = self.textures.insert(path.to_path_buf(), Some(texture)) {
+                        stats.texture_destroyed(&previous.texture);
+                    }
+                    self.bind_group = None;
+                    changed = true;
+                }
+                AssetEvent::Failed(handle, err) => {
+                    let path = self.images.path(handle).map(Path::to_path_buf).unwrap_or_default();
+                    log::warn!("Failed to load terrain texture from {}: {err}", path.display());
+                    toasts.error(format!("Failed to load terrain texture: {err}"));
+                }
+            }
+        }
+        changed
+    }
+
+    // One vertex per heightmap sample, with normals from the heights around it
+    fn vertices(terrain: &Terrain) -> Vec<TerrainVertex> {
+        let cell = terrain.size / GRID as f32;
+        let at = |x: usize, z: usize| terrain.heights[z.min(GRID) * (GRID + 1) + x.min(GRID)];
+        let mut vertices = Vec::with_capacity((GRID + 1) * (GRID + 1));
+        for z in 0..=GRID {
+            for x in 0..=GRID {
+                let corner = Vec2::new(x as f32, z as f32) * cell - terrain.size * 0.5;
+                let position = Vec3::new(corner.x, at(x, z), corner.y);
+                let slope_x = (at(x + 1, z) - at(x.saturating_sub(1), z)) / cell;
+                let slope_z = (at(x, z + 1) - at(x, z.saturating_sub(1))) / cell;
+                let normal = Vec3::new(-slope_x, 2.0, -slope_z).normalize();
+                vertices.push(TerrainVertex {
+                    position: position.to_array(),
+                    normal: normal.to_array(),
+                });
+            }
+        }
+        vertices
+    }
+
+    // Two triangles per heightmap cell, with room for the vertices `vertices` fills in
+    fn create_mesh(device: &wgpu::Device, stats: &mut RenderStats) -> TerrainMesh {
+        let mut indices: Vec<u32> = Vec::with_capacity(GRID * GRID * 6);
+        for z in 0..GRID as u32 {
+            for x in 0..GRID as u32 {
+                let corner = z * (GRID as u32 + 1) + x;
+                let next_row = corner + GRID as u32 + 1;
+                indices.extend_from_slice(&[corner, next_row, corner + 1, corner + 1, next_row, next_row + 1]);
+            }
+        }
+        let vertex_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Terrain Vertex Buffer"),
+                size: ((GRID + 1) * (GRID + 1) * size_of::<TerrainVertex>()) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let index_buffer = stats.create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Terrain Index Buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            },
+        );
+        TerrainMesh {
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+        }
+    }
+
+    /// Draws `terrain` over the scene in `target`, depth tested against the scene's `depth`, with the scene's
+    /// camera bind group.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &wgpu::BindGroup,
+        terrain: &Terrain,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) {
+        let Some(mesh) = self.mesh.as_ref().filter(|_| terrain.enabled) else {
+            return;
+        };
+        let uniform = TerrainUniform {
+            layers: terrain.layers.each_ref().map(|layer| layer.tint.extend(layer.tiling).to_array()),
+            rules: [terrain.rock_slope, terrain.slope_blend, terrain.snow_height, terrain.height_blend],
+            size: terrain.size,
+            _padding: [0.0; 3],
+        };
+        uploads.write(device, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        // Layers whose file is still loading, or failed to, show their built-in texture
+        let loaded = |layer: &TerrainLayer| {
+            layer.texture.clone().filter(|path| self.textures.get(path).is_some_and(Option::is_some))
+        };
+        let bound = terrain.layers.each_ref().map(loaded);
+        if self.bind_group.as_ref().is_none_or(|(_, previous)| *previous != bound) {
+            let views: Vec<&wgpu::TextureView> = bound
+                .iter()
+                .zip(&self.built_in)
+                .map(|(path, built_in)| {
+                    let texture = path.as_ref().and_then(|path| self.textures.get(path)?.as_ref());
+                    &texture.unwrap_or(built_in).view
+                })
+                .collect();
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Terrain Bind Group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&self.blend_map.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&self.blend_sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(views[0]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(views[1]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: wgpu::BindingResource::TextureView(views[2]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: wgpu::BindingResource::Sampler(&self.layer_sampler),
+                    },
+                ],
+            });
+            self.bind_group = Some((bind_group, bound));
+        }
+        let Some((bind_group, _)) = &self.bind_group else {
+            return;
+        };
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Terrain Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, camera_bind_group, &[]);
+        pass.set_bind_group(1, bind_group, &[]);
+        pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+        stats.record_draw(mesh.num_indices, 1);
+    }
+}
+
+// Random value from 0 to 1 for each whole coordinate
+fn lattice(x: i32, z: i32, seed: u32) -> f32 {
+    let mut hash = (x as u32).wrapping_mul(0x27d4_eb2d) ^ (z as u32).wrapping_mul(0x1656_67b1) ^ seed;
+    hash = (hash ^ (hash >> 15)).wrapping_mul(0x2c1b_3c6d);
+    hash = (hash ^ (hash >> 12)).wrapping_mul(0x297a_2d39);
+    (hash ^ (hash >> 15)) as f32 / u32::MAX as f32
+}
+
+// Smooth interpolation between the values `lattice` gives whole coordinates
+fn value_noise(position: Vec2, lattice: impl Fn(i32, i32) -> f32) -> f32 {
+    let cell = position.floor();
+    let f = position - cell;
+    let f = f * f * (3.0 - 2.0 * f);
+    let (x, z) = (cell.x as i32, cell.y as i32);
+    let near = lattice(x, z) + (lattice(x + 1, z) - lattice(x, z)) * f.x;
+    let far = lattice(x, z + 1) + (lattice(x + 1, z + 1) - lattice(x, z + 1)) * f.x;
+    near + (far - near) * f.y
+}
+
+// Five octaves of noise, each half the size and height of the last, 0 to 1
+fn hills(position: Vec2, seed: u32) -> f32 {
+    let (mut total, mut amplitude, mut frequency) = (0.0, 0.5, 1.0);
+    for octave in 0..5 {
+        let seed = seed.wrapping_add(octave);
+        total += value_noise(position * frequency, |x, z| lattice(x, z, seed)) * amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    total / (1.0 - 0.5f32.powi(5))
+}
+
+// A layer's texture, speckled around the sRGB `color` so tiling shows before any texture is chosen
+fn layer_texture(color: [f32; 3], seed: u32) -> TextureImage {
+    // Noise that wraps every `period` cells of `cell` texels, so the texture repeats without seams
+    let tiled = |x: u32, y: u32, cell: u32| {
+        let period = (LAYER_TEXTURE_SIZE / cell) as i32;
+        let position = Vec2::new(x as f32, y as f32) / cell as f32;
+        value_noise(position, |x, z| lattice(x.rem_euclid(period), z.rem_euclid(period), seed))
+    };
+    let mut pixels = Vec::with_capacity((LAYER_TEXTURE_SIZE * LAYER_TEXTURE_SIZE * 4) as usize);
+    for y in 0..LAYER_TEXTURE_SIZE {
+        for x in 0..LAYER_TEXTURE_SIZE {
+            let shade = 0.7 + 0.35 * tiled(x, y, 8) + 0.2 * tiled(x, y, 2);
+            for channel in color {
+                pixels.push(((channel * shade).clamp(0.0, 1.0) * 255.0) as u8);
+            }
+            pixels.push(255);
+        }
+    }
+    TextureImage::new(LAYER_TEXTURE_SIZE, LAYER_TEXTURE_SIZE, pixels)
+}
@@ -0,0 +1,130 @@
+// Terrain: grass, rock and snow splatted over the heightmap. Rules pick each layer's weight from the slope and
+// height of the ground, and the painted blend map overrides them where it has been painted
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Every enabled light in the scene, gathered each frame from the world's Light components
+struct Light {
+    position: vec4<f32>, // w: 0 directional, 1 point, 2 spot
+    direction: vec4<f32>, // w: range
+    color: vec4<f32>, // Times intensity
+    cone: vec4<f32>, // Cosines of the spot's inner and outer angles
+};
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    fog_color: vec3<f32>,
+    fog_mode: u32, // 0 off, 1 linear, 2 exponential
+    fog: vec4<f32>, // Start, end, density and height falloff
+    lights: array<Light, 16>,
+};
+@group(0) @binding(1)
+var<uniform> lights: Lights;
+
+struct Terrain {
+    layers: array<vec4<f32>, 3>, // Tint, and repeats per world unit in w
+    rules: vec4<f32>, // Rock slope, slope blend, snow height and height blend
+    size: f32, // Side of the square the blend map covers
+};
+@group(1) @binding(0)
+var<uniform> terrain: Terrain;
+// Painted weights of grass, rock and snow, and in a how much they override the rules
+@group(1) @binding(1)
+var blend_map: texture_2d<f32>;
+@group(1) @binding(2)
+var blend_sampler: sampler;
+@group(1) @binding(3)
+var grass: texture_2d<f32>;
+@group(1) @binding(4)
+var rock: texture_2d<f32>;
+@group(1) @binding(5)
+var snow: texture_2d<f32>;
+@group(1) @binding(6)
+var layer_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.world_position = in.position;
+    out.clip_position = camera.view_proj * vec4<f32>(in.position, 1.0);
+    out.normal = in.normal;
+    return out;
+}
+
+// Diffuse light reaching a surface at `position` facing `normal`
+fn shade(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    var total = lights.ambient;
+    for (var i = 0u; i < lights.count; i++) {
+        let light = lights.lights[i];
+        var to_light = -light.direction.xyz;
+        var falloff = 1.0;
+        if light.position.w > 0.5 {
+            let offset = light.position.xyz - position;
+            let distance = length(offset);
+            to_light = offset / max(distance, 0.0001);
+            falloff = clamp(1.0 - distance / light.direction.w, 0.0, 1.0);
+            falloff *= falloff;
+            if light.position.w > 1.5 {
+                let cos_angle = dot(-to_light, light.direction.xyz);
+                falloff *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+            }
+        }
+        total += light.color.rgb * max(dot(normal, to_light), 0.0) * falloff;
+    }
+    return total;
+}
+
+// How much of a surface at `position` the fog hides, 0 to 1
+fn fog_amount(position: vec3<f32>) -> f32 {
+    let distance = length(position - camera.position.xyz);
+    var amount = 0.0;
+    if lights.fog_mode == 1u {
+        amount = clamp((distance - lights.fog.x) / (lights.fog.y - lights.fog.x), 0.0, 1.0);
+    } else if lights.fog_mode == 2u {
+        amount = 1.0 - exp(-lights.fog.z * distance);
+    }
+    // Thinner the higher the surface stands above the ground plane
+    return amount * exp(-lights.fog.w * max(position.y, 0.0));
+}
+
+// Weights of grass, rock and snow by the rules: rock where the ground is steep, snow on top of either high up
+fn rule_weights(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let slope = 1.0 - normal.y;
+    let rock = smoothstep(terrain.rules.x - terrain.rules.y, terrain.rules.x + terrain.rules.y, slope);
+    let snow = smoothstep(terrain.rules.z - terrain.rules.w, terrain.rules.z + terrain.rules.w, position.y);
+    return vec3<f32>((1.0 - rock) * (1.0 - snow), rock * (1.0 - snow), snow);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = normalize(in.normal);
+    let painted = textureSample(blend_map, blend_sampler, in.world_position.xz / terrain.size + 0.5);
+    let painted_total = painted.r + painted.g + painted.b;
+    var weights = rule_weights(in.world_position, normal);
+    if painted_total > 0.001 {
+        weights = mix(weights, painted.rgb / painted_total, painted.a);
+    }
+
+    let uv = in.world_position.xz;
+    let color = textureSample(grass, layer_sampler, uv * terrain.layers[0].w).rgb * terrain.layers[0].rgb * weights.x
+        + textureSample(rock, layer_sampler, uv * terrain.layers[1].w).rgb * terrain.layers[1].rgb * weights.y
+        + textureSample(snow, layer_sampler, uv * terrain.layers[2].w).rgb * terrain.layers[2].rgb * weights.z;
+    let lit = color * shade(in.world_position, normal);
+    return vec4<f32>(mix(lit, lights.fog_color, fog_amount(in.world_position)), 1.0);
+}
@@ -0,0 +1,206 @@
+// texture.rs
+
+use crate::compressed_texture::CompressedImage;
+use crate::stats::RenderStats;
+use crate::upload::Uploads;
+use egui_wgpu::wgpu;
+use std::io;
+use std::path::Path;
+
+// An image ready to upload: decoded sRGB RGBA texels, or the blocks of a compressed texture file
+pub enum TextureImage {
+    Rgba(RgbaImage),
+    Compressed(CompressedImage),
+}
+
+impl TextureImage {
+    /// `pixels` are `width` by `height` sRGB RGBA texels, row by row.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        Self::Rgba(RgbaImage { width, height, pixels })
+    }
+
+    /// Reads the BC1 to BC7 blocks of a DDS or KTX2 file, or decodes a PNG, JPEG or any other format the
+    /// `image` crate reads.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        if CompressedImage::reads(path) {
+            return CompressedImage::load(path).map(Self::Compressed);
+        }
+        let image = image::open(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?.to_rgba8();
+        Ok(Self::new(image.width(), image.height(), image.into_raw()))
+    }
+}
+
+// Decoded sRGB RGBA texels, row by row
+pub struct RgbaImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    // Levels in a full mip chain, down to 1x1
+    fn mip_level_count(&self) -> u32 {
+        u32::BITS - self.width.max(self.height).max(1).leading_zeros()
+    }
+
+    // The image at half the size in each direction, averaging each 2x2 block in linear color so darker and
+    // lighter texels blend as they would on screen. An odd last row or column is folded into the one before
+    fn downsampled(&self) -> RgbaImage {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let to_linear: Vec<f32> = (0..=255).map(|value| srgb_to_linear(value as f32 / 255.0)).collect();
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0.0f32; 4];
+                for (sx, sy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let source_x = (x * 2 + sx).min(self.width - 1);
+                    let source_y = (y * 2 + sy).min(self.height - 1);
+                    let texel = ((source_y * self.width + source_x) * 4) as usize;
+                    for (channel, total) in sum.iter_mut().enumerate() {
+                        let value = self.pixels[texel + channel];
+                        *total += if channel < 3 { to_linear[value as usize] } else { value as f32 / 255.0 };
+                    }
+                }
+                for (channel, total) in sum.into_iter().enumerate() {
+                    let average = total / 4.0;
+                    let encoded = if channel < 3 { linear_to_srgb(average) } else { average };
+                    pixels.push((encoded * 255.0).round() as u8);
+                }
+            }
+        }
+        RgbaImage { width, height, pixels }
+    }
+}
+
+// An uploaded `TextureImage`
+pub struct GpuTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl GpuTexture {
+    /// Uploads `image`. RGBA images get a full chain of mip levels, downsampled on the CPU, so they stay smooth
+    /// from far away. Compressed ones keep the levels their file has, and upload as they are where the device
+    /// has `TEXTURE_COMPRESSION_BC`; elsewhere they are decoded on the CPU first.
+    pub fn new(device: &wgpu::Device, stats: &mut RenderStats, uploads: &mut Uploads, image: &TextureImage) -> Self {
+        match image {
+            TextureImage::Rgba(image) => {
+                let mip_level_count = image.mip_level_count();
+                let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+                let texture = create_texture(device, stats, image.width, image.height, mip_level_count, format);
+                uploads.write_texture(device, &texture, &image.pixels);
+                let mut level_image = None;
+                for level in 1..mip_level_count {
+                    let next = level_image.as_ref().unwrap_or(image).downsampled();
+                    uploads.write_texture_level(device, &texture, level, &next.pixels);
+                    level_image = Some(next);
+                }
+                Self::from_texture(texture)
+            }
+            TextureImage::Compressed(image) => {
+                // BC textures have to be whole blocks in size
+                let blocks = device.features().contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+                    && image.width % 4 == 0
+                    && image.height % 4 == 0
+                    && image.samples_as_stored();
+                let decoded;
+                let (format, levels) = if blocks {
+                    (image.format, &image.levels)
+                } else {
+                    decoded = image.decoded_levels();
+                    (image.decoded_format(), &decoded)
+                };
+                let texture = create_texture(device, stats, image.width, image.height, levels.len() as u32, format);
+                for (level, data) in levels.iter().enumerate() {
+                    uploads.write_texture_level(device, &texture, level as u32, data);
+                }
+                Self::from_texture(texture)
+            }
+        }
+    }
+
+    fn from_texture(texture: wgpu::Texture) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view }
+    }
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    stats: &mut RenderStats,
+    width: u32,
+    height: u32,
+    mip_level_count: u32,
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    stats.create_texture(
+        device,
+        &wgpu::TextureDescriptor {
+            label: Some("Material Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+    )
+}
+
+/// Repeats and filters linearly between texels and between mip levels, as textured meshes want.
+pub fn create_sampler(device: &wgpu::Device, label: &str) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some(label),
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+/// Layout entry for a filterable 2D texture at `binding`, as `GpuTexture` makes them.
+pub fn texture_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+/// Layout entry for a filtering sampler at `binding`, as `create_sampler` makes them.
+pub fn sampler_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -0,0 +1,122 @@
+// theme.rs
+
+use egui::{Color32, Context, FontId, Rounding, Style, TextStyle, Visuals};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThemeKind {
+    Dark,
+    Light,
+    Custom,
+}
+
+impl ThemeKind {
+    pub const ALL: [ThemeKind; 3] = [ThemeKind::Dark, ThemeKind::Light, ThemeKind::Custom];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ThemeKind::Dark => "Dark",
+            ThemeKind::Light => "Light",
+            ThemeKind::Custom => "Custom",
+        }
+    }
+}
+
+// User-editable parameters layered over the dark or light base visuals
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CustomStyle {
+    pub dark_base: bool,
+    pub accent: [u8; 3],
+    pub rounding: f32,
+    pub font_size: f32, // Body text size in points; other text styles scale with it
+}
+
+impl Default for CustomStyle {
+    fn default() -> Self {
+        Self {
+            dark_base: true,
+            accent: [90, 170, 255],
+            rounding: 4.0,
+            font_size: 14.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub kind: ThemeKind,
+    pub custom: CustomStyle,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            kind: ThemeKind::Dark,
+            custom: CustomStyle::default(),
+        }
+    }
+}
+
+impl Theme {
+    pub fn style(&self) -> Style {
+        match self.kind {
+            ThemeKind::Dark => Style {
+                visuals: Visuals::dark(),
+                ..Style::default()
+            },
+            ThemeKind::Light => Style {
+                visuals: Visuals::light(),
+                ..Style::default()
+            },
+            ThemeKind::Custom => self.custom_style(),
+        }
+    }
+
+    pub fn apply(&self, ctx: &Context) {
+        ctx.set_style(self.style());
+    }
+
+    fn custom_style(&self) -> Style {
+        let custom = &self.custom;
+        let mut style = Style {
+            visuals: if custom.dark_base { Visuals::dark() } else { Visuals::light() },
+            ..Style::default()
+        };
+
+        let [r, g, b] = custom.accent;
+        let accent = Color32::from_rgb(r, g, b);
+        let visuals = &mut style.visuals;
+        visuals.selection.bg_fill = accent.linear_multiply(0.6);
+        visuals.selection.stroke.color = accent;
+        visuals.hyperlink_color = accent;
+        visuals.widgets.hovered.bg_stroke.color = accent;
+        visuals.widgets.active.bg_stroke.color = accent;
+
+        let rounding = Rounding::same(custom.rounding);
+        visuals.window_rounding = rounding;
+        visuals.menu_rounding = rounding;
+        for widget in [
+            &mut visuals.widgets.noninteractive,
+            &mut visuals.widgets.inactive,
+            &mut visuals.widgets.hovered,
+            &mut visuals.widgets.active,
+            &mut visuals.widgets.open,
+        ] {
+            widget.rounding = rounding;
+        }
+
+        let body = custom.font_size;
+        style.text_styles = [
+            (TextStyle::Small, FontId::proportional(body * 0.7)),
+            (TextStyle::Body, FontId::proportional(body)),
+            (TextStyle::Button, FontId::proportional(body)),
+            (TextStyle::Heading, FontId::proportional(body * 1.4)),
+            (TextStyle::Monospace, FontId::monospace(body)),
+        ]
+        .into();
+
+        style
+    }
+}
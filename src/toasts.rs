@@ -0,0 +1,113 @@
+// toasts.rs
+
+use egui::{Align2, Color32, Context, Id};
+use std::collections::VecDeque;
+use std::time::Duration;
+use web_time::Instant;
+
+const MAX_VISIBLE: usize = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn color(&self) -> Color32 {
+        match self {
+            Severity::Info => Color32::from_rgb(90, 170, 255),
+            Severity::Success => Color32::from_rgb(90, 200, 110),
+            Severity::Warning => Color32::from_rgb(255, 200, 80),
+            Severity::Error => Color32::from_rgb(255, 90, 90),
+        }
+    }
+
+    // Errors linger longer so they are not missed
+    pub fn duration(&self) -> Duration {
+        match self {
+            Severity::Info | Severity::Success => Duration::from_secs(3),
+            Severity::Warning => Duration::from_secs(5),
+            Severity::Error => Duration::from_secs(8),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Toast {
+    pub severity: Severity,
+    pub text: String,
+    expires_at: Instant,
+}
+
+// Short-lived notifications stacked in the bottom-right corner
+#[derive(Debug, Default)]
+pub struct Toasts {
+    queue: VecDeque<Toast>,
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, severity: Severity, text: impl Into<String>) {
+        self.queue.push_back(Toast {
+            severity,
+            text: text.into(),
+            expires_at: Instant::now() + severity.duration(),
+        });
+    }
+
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(Severity::Info, text);
+    }
+
+    pub fn success(&mut self, text: impl Into<String>) {
+        self.push(Severity::Success, text);
+    }
+
+    pub fn warning(&mut self, text: impl Into<String>) {
+        self.push(Severity::Warning, text);
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push(Severity::Error, text);
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        let now = Instant::now();
+        self.queue.retain(|toast| toast.expires_at > now);
+        // Wakes the UI to remove the next toast even if nothing else changes until then
+        if let Some(expires_at) = self.queue.iter().map(|toast| toast.expires_at).min() {
+            ctx.request_repaint_after(expires_at - now);
+        }
+
+        let mut dismissed = None;
+        egui::Area::new(Id::new("toasts"))
+            .anchor(Align2::RIGHT_BOTTOM, [-12.0, -12.0])
+            .order(egui::Order::Foreground)
+            .interactable(true)
+            .show(ctx, |ui| {
+                for (i, toast) in self.queue.iter().enumerate().rev().take(MAX_VISIBLE) {
+                    let response = egui::Frame::popup(ui.style())
+                        .stroke(egui::Stroke::new(1.0, toast.severity.color()))
+                        .show(ui, |ui| {
+                            ui.set_max_width(320.0);
+                            ui.colored_label(toast.severity.color(), &toast.text);
+                        })
+                        .response;
+                    // Clicking a toast dismisses it early
+                    if response.interact(egui::Sense::click()).clicked() {
+                        dismissed = Some(i);
+                    }
+                }
+            });
+
+        if let Some(i) = dismissed {
+            self.queue.remove(i);
+        }
+    }
+}
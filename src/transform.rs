@@ -0,0 +1,61 @@
+// transform.rs
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+// Per-object data as laid out in the shaders' model uniform buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct ModelUniform {
+    pub model: [[f32; 4]; 4],
+}
+
+impl ModelUniform {
+    pub fn new() -> Self {
+        Self {
+            model: Mat4::IDENTITY.to_cols_array_2d(),
+        }
+    }
+
+    pub fn from_matrix(model: Mat4) -> Self {
+        Self {
+            model: model.to_cols_array_2d(),
+        }
+    }
+
+    pub fn update_model(&mut self, transform: &Transform) {
+        self.model = transform.matrix().to_cols_array_2d();
+    }
+}
+
+impl Default for ModelUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
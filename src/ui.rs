@@ -1,103 +1,1561 @@
 // ui.rs
 
 use egui::Context;
-use egui_wgpu::{wgpu::{self, Device}, ScreenDescriptor};
+use egui_wgpu::wgpu;
 
-use crate::egui_tools::*;
+use crate::animation::Animation;
+use crate::assets::LoadProgress;
+use crate::billboards::Billboards;
+use crate::bounds::Aabb;
+use crate::camera::{Camera, Projection, FOV_RANGE, ORTHO_HEIGHT_RANGE};
+use crate::camera_controller::{CameraController, CameraMode};
+use crate::commands::{Command, CommandPalette, Shortcut, ShortcutMap, PALETTE_SHORTCUT};
+use crate::console::Console;
+use crate::debug;
+use crate::decals::Decals;
+use crate::diagnostics::Diagnostics;
+use crate::dock::{BuiltinPanel, Dock, Panel};
+use crate::dynamic_resolution::ResolutionController;
+use crate::file_dialog::{FileDialogs, FileKind};
+use crate::frame_capture::FrameCapture;
+use crate::environment::Environment;
+use crate::events::{AppEvent, EventBus};
+use crate::gizmo::{Gizmo, GizmoMode};
+use crate::grid::GridSettings;
+use crate::labels::{Labels, WorldLabel};
+use crate::history::{History, SceneEdit, SceneSnapshot};
+use crate::hud::Hud;
+use crate::input::{Action, ActionMap, Rebinding};
+use crate::instancing::InstanceGrid;
+use crate::plots::PlotPanel;
+use crate::preset::{PresetBrowser, PresetRequest};
+use crate::reflection_probes::ReflectionProbes;
+use crate::scene::SceneNode;
+use crate::scene_tree::SceneTree;
+use crate::replay::SessionReplay;
+use crate::shader_registry::ShaderId;
+use crate::shader_reload::ShaderReloader;
+use crate::morph::PolygonMorph;
+use crate::msaa::{DEFAULT_SAMPLES, SAMPLE_COUNTS};
+use crate::network::Network;
+use crate::occlusion::OcclusionCuller;
+use crate::object_drag::ObjectDrag;
+use crate::frame_timer::FrameTimer;
+use crate::pacing::FramePacer;
+use crate::physics::Physics;
+use crate::pipelines::RenderMode;
+use crate::post_process::PostSettings;
+use crate::primitives::{Primitive, Tessellation, RINGS_RANGE, SEGMENTS_RANGE, SUBDIVISIONS_RANGE};
+use crate::scripting::Scripts;
+use crate::sequencer::Sequencer;
+use crate::settings::{
+    BackgroundMode, PacingMode, PresentModePreference, BACKGROUND_FPS_RANGE, DEFAULT_BACKGROUND_FPS, DEFAULT_FPS_CAP,
+    DEFAULT_FRAME_LATENCY, FPS_CAP_RANGE, FRAME_LATENCY_RANGE,
+};
+use crate::stats::{RenderStats, DEFAULT_MEMORY_BUDGET_MIB};
+use crate::stereo::StereoSettings;
+use crate::terrain::Terrain;
+use crate::theme::{Theme, ThemeKind};
+use crate::toasts::Toasts;
+use crate::transform::Transform;
+use crate::video_capture::VideoCapture;
+use crate::water::Water;
+use glam::{EulerRot, Quat};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use winit::event::MouseButton;
 
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 3.0;
+const UI_SCALE_STEP: f32 = 0.1;
+pub const DISPLAY_SCALE_RANGE: RangeInclusive<f32> = 0.5..=4.0;
+
+// Buttons an action can be bound to; the others are kept for picking and the camera
+const MOUSE_BINDINGS: [MouseButton; 2] = [MouseButton::Back, MouseButton::Forward];
 
 pub struct UIState {
     pub sides: u16,
+    pub radius: f32,
+    pub tessellation: Tessellation, // Of the primitive rendering styles
+    pub morph: PolygonMorph,
+    pub animation: Animation,     // Driven by the render loop, which owns the properties it animates
+    pub sequencer: Sequencer,     // Likewise driven by the render loop
+    pub physics: Physics,         // Stepped by the render loop, which owns the world
+    pub environment: Environment, // Turns the sun, which the render loop owns
+    pub water: Water,             // Its waves are moved by the render loop
+    pub instancing: InstanceGrid, // Drawn by the render loop around the object
+    pub terrain: Terrain,         // Painted by the render loop from viewport drags
+    pub scripts: Scripts,         // Run by the render loop, like physics
+    pub session: SessionReplay,   // Records and replays the render loop's fixed update
+    pub shader_reload: ShaderReloader, // Polled by the render loop, which rebuilds the pipelines
     pub rendering_style: RenderingStyle,
+    pub render_mode: RenderMode, // Applied to the scene pipelines by the render loop
+    pub render_modes: Vec<RenderMode>, // What the device supports, filled in at startup
+    pub compute: bool, // Whether the adapter runs the compute shaders the Particles style needs; set at startup
+    pub loaded_mesh: Option<String>, // The file the object shows in place of its shape; set by the render loop
     pub scale_factor: f32,
-    pub active_shader: &'static str,
+    pub display_scale: Option<f32>, // Replaces the scale the OS reports, for setups that get it wrong
+    pub active_shader: ShaderId,
+    // Every registered scene shader with its label and description, filled in at startup
+    pub shaders: Vec<(ShaderId, &'static str, &'static str)>,
+    pub theme: Theme,
+    pub file_dialogs: FileDialogs,
+    pub console: Console,
+    pub gizmo: Gizmo,
+    pub grid: GridSettings,
+    pub hud: Hud, // Aimed by the render loop each frame
+    pub decals: Decals,
+    pub billboards: Billboards,
+    pub reflection_probes: ReflectionProbes,
+    pub scene_tree: SceneTree,
+    dock: Dock,
+    pub network: Network, // Polled and published to by the render loop
+    pub object_drag: ObjectDrag, // Driven by the render loop from viewport drags
+    pub labels: Labels,
+    pub occlusion: OcclusionCuller, // Run by the render loop after frustum culling
+    pub plots: PlotPanel,
+    pub toasts: Toasts,
+    pub command_palette: CommandPalette,
+    pub history: History,
+    pub scene_path: Option<PathBuf>, // Where Save Scene writes without asking
+    pub exit_after_save: bool,       // The exit prompt's Save is waiting for the scene to be written
+    pub exit_confirmed: bool,
+    exit_prompt: bool,
+    pub presets: PresetBrowser,
+    pub preset_request: Option<PresetRequest>, // Handled by the render loop once the frame is done
+    pub commands: Vec<Command>, // Queued for the render loop to execute after the UI pass
+    pub events: EventBus,       // Applied by the render loop along with the commands
+    pub shortcuts: ShortcutMap,
+    pub show_profiler: bool,
+    pub show_bounds: bool,    // Debug lines around every mesh's culling bounds
+    pub show_pick_rays: bool, // Debug lines along the last click's ray
+    pub diagnostics: Option<Diagnostics>, // Filled in once the GPU is initialized
+    pub rebinding: Option<Rebinding>, // Binding waiting for a key or controller press in the keybinding editor
+    pub selected: bool, // Whether the object is selected for the inspector and gizmo
+    pub input_capture: InputCapture, // Mirrors the render loop's input recorder for the menu
+    pub nudging: bool, // A nudge key is held, so its repeated steps form one undoable edit
+    pub ui_hidden: bool, // Everything but toasts and the exit prompt is hidden, for a clear view of the scene
+    pub scene_in_panel: bool, // The scene is shown in the central panel rather than under the UI
+    pub memory_budget_mib: u32,
+    pub frame_latency: u32, // Applied to the surface by the render loop when it changes
+    pub background_mode: BackgroundMode,
+    pub background_fps: u32,
+    pub pacing_mode: PacingMode, // Applied to the surface and the pacer by the render loop when it changes
+    pub fps_cap: u32,
+    pub present_mode: PresentModePreference, // Overrides the pacing mode's present mode unless automatic
+    pub present_modes: Vec<wgpu::PresentMode>, // What the surface supports, filled in at startup
+    pub redraw_on_demand: bool,
+    pub resolution: ResolutionController,
+    pub msaa_samples: u32, // Applied to the scene pass by the render loop when it changes
+    pub sample_counts: Vec<u32>, // What the adapter supports, filled in at startup
+    pub stereo: StereoSettings,
+    pub post_fx: PostSettings, // Applied by the render loop's post-processing
+    pub pacer: FramePacer,
+    pub frame_timer: FrameTimer,
+    pub compiling_pipelines: Vec<&'static str>, // Filled in by the render loop each frame
+    pub loading_assets: LoadProgress,           // Filled in by the render loop each frame
+    pub frame_capture: FrameCapture,
+    pub video: VideoCapture,
+    applied_theme: Option<Theme>,
 }
 
 impl UIState {
     pub fn new() -> Self {
         Self {
             sides: 5,
+            radius: 0.5,
+            tessellation: Tessellation::default(),
+            morph: PolygonMorph::new(),
+            animation: Animation::new(),
+            sequencer: Sequencer::new(),
+            physics: Physics::new(),
+            environment: Environment::new(),
+            water: Water::new(),
+            instancing: InstanceGrid::new(),
+            terrain: Terrain::new(),
+            scripts: Scripts::new(),
+            session: SessionReplay::new(),
+            shader_reload: ShaderReloader::new(),
             rendering_style: RenderingStyle::Polygon,
+            render_mode: RenderMode::Filled,
+            render_modes: vec![RenderMode::Filled],
+            compute: false,
+            loaded_mesh: None,
             scale_factor: 1.0,
-            active_shader: "main",
+            display_scale: None,
+            active_shader: ShaderId::MAIN,
+            shaders: vec![(ShaderId::MAIN, "Main", "")],
+            theme: Theme::default(),
+            file_dialogs: FileDialogs::new(),
+            console: Console::new(),
+            gizmo: Gizmo::new(),
+            grid: GridSettings::new(),
+            hud: Hud::new(),
+            decals: Decals::new(),
+            billboards: Billboards::new(),
+            reflection_probes: ReflectionProbes::new(),
+            scene_tree: SceneTree::new(),
+            dock: Dock::new(),
+            network: Network::new(),
+            object_drag: ObjectDrag::new(),
+            labels: Labels::new(),
+            occlusion: OcclusionCuller::new(),
+            plots: PlotPanel::new(),
+            toasts: Toasts::new(),
+            command_palette: CommandPalette::new(),
+            history: History::new(),
+            scene_path: None,
+            exit_after_save: false,
+            exit_confirmed: false,
+            exit_prompt: false,
+            presets: PresetBrowser::new(),
+            preset_request: None,
+            commands: Vec::new(),
+            events: EventBus::new(),
+            shortcuts: ShortcutMap::new(),
+            show_profiler: false,
+            show_bounds: false,
+            show_pick_rays: false,
+            diagnostics: None,
+            rebinding: None,
+            selected: true,
+            nudging: false,
+            ui_hidden: false,
+            scene_in_panel: false,
+            input_capture: InputCapture::Idle,
+            memory_budget_mib: DEFAULT_MEMORY_BUDGET_MIB,
+            frame_latency: DEFAULT_FRAME_LATENCY,
+            background_mode: BackgroundMode::Throttled,
+            background_fps: DEFAULT_BACKGROUND_FPS,
+            pacing_mode: PacingMode::Vsync,
+            fps_cap: DEFAULT_FPS_CAP,
+            present_mode: PresentModePreference::Automatic,
+            present_modes: Vec::new(),
+            redraw_on_demand: false,
+            resolution: ResolutionController::new(),
+            msaa_samples: DEFAULT_SAMPLES,
+            sample_counts: vec![1],
+            stereo: StereoSettings::new(),
+            post_fx: PostSettings::default(),
+            pacer: FramePacer::new(),
+            frame_timer: FrameTimer::new(),
+            compiling_pipelines: Vec::new(),
+            loading_assets: LoadProgress::default(),
+            frame_capture: FrameCapture::new(),
+            video: VideoCapture::new(),
+            applied_theme: None,
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_ui(
         &mut self,
         ctx: &Context,
-        egui_renderer: &mut EguiRenderer,
-        window: &winit::window::Window,
-        device: &Device,
-        surface_view: &wgpu::TextureView,
-        screen_descriptor: ScreenDescriptor,
+        camera: &mut Camera,
+        camera_controller: &mut CameraController,
+        object_bounds: Option<Aabb>, // World-space bounds of the rendered object
+        object_transform: &mut Transform,
+        render_stats: &RenderStats,
+        action_map: &mut ActionMap,
+        app_menu: &mut dyn FnMut(&mut egui::Ui), // Adds the running `Application`'s menus
     ) {
-        egui_renderer.draw(
-            device,
-            &egui_renderer.queue,
-            &mut egui_renderer.encoder,
-            window,
-            surface_view,
-            screen_descriptor,
-            |ctx| {
-                egui::Window::new("UI Window")
-                    .resizable(true)
-                    .vscroll(true)
-                    .default_open(true)
-                    .show(ctx, |ui| {
-                        ui.label("Vertex and Shader control");
-
-                        if ui.button("Switch Shader").clicked() {
-                            if self.active_shader == "main" {
-                                self.active_shader = "challenge"; // Switch to challenge shader
-                            } else {
-                                self.active_shader = "main"; // Switch back to main shader
+        // Only push a new style to egui when the theme was actually edited
+        if self.applied_theme.as_ref() != Some(&self.theme) {
+            self.theme.apply(ctx);
+            self.applied_theme = Some(self.theme.clone());
+        }
+        self.zoom_shortcuts(ctx);
+        self.dispatch_shortcuts(ctx);
+        self.shortcuts.show_pending(ctx);
+        if self.ui_hidden {
+            self.toasts.show(ctx);
+            self.exit_prompt(ctx);
+            return;
+        }
+
+        egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    // One dialog at a time; the native dialog is modal anyway
+                    ui.add_enabled_ui(!self.file_dialogs.is_pending(), |ui| {
+                        for (label, command) in [
+                            ("Open Scene...", Command::OpenScene),
+                            ("Open Model...", Command::OpenModel),
+                            ("Open Texture...", Command::OpenTexture),
+                            ("Open World...", Command::OpenWorld),
+                            ("Place Prefab...", Command::PlacePrefab),
+                        ] {
+                            if ui.button(label).clicked() {
+                                self.commands.push(command);
+                                ui.close_menu();
                             }
                         }
-
                         ui.separator();
+                        let save =
+                            egui::Button::new("Save Scene").shortcut_text(self.shortcuts.label(ctx, Command::SaveScene));
+                        if ui.add(save).clicked() {
+                            self.commands.push(Command::SaveScene);
+                            ui.close_menu();
+                        }
+                        if ui.button("Save Scene As...").clicked() {
+                            self.commands.push(Command::SaveSceneAs);
+                            ui.close_menu();
+                        }
+                        if ui.button("Save Object as Prefab...").clicked() {
+                            self.commands.push(Command::SavePrefab);
+                            ui.close_menu();
+                        }
+                        if ui.button("Save World...").clicked() {
+                            self.commands.push(Command::SaveWorld);
+                            ui.close_menu();
+                        }
+                        if ui.button("Export glTF...").clicked() {
+                            self.commands.push(Command::ExportGltf);
+                            ui.close_menu();
+                        }
+                    });
+                    ui.separator();
+                    if ui.button("Quit").clicked() {
+                        self.commands.push(Command::Quit);
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Edit", |ui| {
+                    let undo = egui::Button::new("Undo").shortcut_text(self.shortcuts.label(ctx, Command::Undo));
+                    if ui.add_enabled(self.history.can_undo(), undo).clicked() {
+                        self.commands.push(Command::Undo);
+                        ui.close_menu();
+                    }
+                    let redo = egui::Button::new("Redo").shortcut_text(self.shortcuts.label(ctx, Command::Redo));
+                    if ui.add_enabled(self.history.can_redo(), redo).clicked() {
+                        self.commands.push(Command::Redo);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    let video = if self.video.is_recording() {
+                        "Stop Video Capture"
+                    } else {
+                        "Start Video Capture"
+                    };
+                    for (label, command, enabled) in [
+                        ("Copy Transform", Command::CopyTransform, self.selected),
+                        ("Paste Transform", Command::PasteTransform, self.selected),
+                        ("Copy Screenshot", Command::CopyScreenshot, true),
+                        ("Save Screenshot", Command::SaveScreenshot, true),
+                        (video, Command::ToggleVideoCapture, true),
+                    ] {
+                        let button = egui::Button::new(label).shortcut_text(self.shortcuts.label(ctx, command));
+                        if ui.add_enabled(enabled, button).clicked() {
+                            self.commands.push(command);
+                            ui.close_menu();
+                        }
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    let mut show_profiler = self.show_profiler;
+                    if ui.checkbox(&mut show_profiler, "Profiler").changed() {
+                        self.commands.push(Command::ToggleProfiler);
+                    }
+                    ui.checkbox(&mut self.frame_timer.show_overlay, "FPS Counter");
+                    if ui.button("Export GPU Trace...").clicked() {
+                        self.commands.push(Command::ExportGpuTrace);
+                        ui.close_menu();
+                    }
+                    let capture = egui::Button::new("Capture Frame (RenderDoc)");
+                    if ui
+                        .add_enabled(self.frame_capture.is_attached(), capture)
+                        .on_disabled_hover_text(self.frame_capture.status())
+                        .clicked()
+                    {
+                        self.commands.push(Command::CaptureFrame);
+                        ui.close_menu();
+                    }
+                    ui.menu_button("Panels", |ui| self.dock.menu_ui(ui));
+                    ui.checkbox(&mut self.scene_in_panel, "Scene in a panel")
+                        .on_hover_text("Shows the scene between the docked panels instead of under them");
+                    if ui.button("New Scene Window").clicked() {
+                        self.commands.push(Command::OpenSceneWindow);
+                        ui.close_menu();
+                    }
+                    ui.checkbox(&mut self.labels.enabled, "World labels");
+                    ui.add_enabled_ui(self.labels.enabled, |ui| {
+                        ui.checkbox(&mut self.labels.show_names, "Entity names");
+                    });
+                    ui.menu_button("Grid", |ui| {
+                        ui.checkbox(&mut self.grid.enabled, "Ground grid");
+                        ui.checkbox(&mut self.grid.axes, "Axis indicator");
+                        self.grid.settings_ui(ui);
+                    });
+                    ui.menu_button("HUD", |ui| self.hud.menu_ui(ui));
+                    ui.checkbox(&mut self.occlusion.enabled, "Occlusion culling")
+                        .on_hover_text("Skips meshes hidden behind nearer meshes or the terrain");
+                    ui.menu_button("Debug Draw", |ui| {
+                        ui.checkbox(&mut self.physics.debug_draw, "Colliders");
+                        ui.checkbox(&mut self.show_bounds, "Culling bounds");
+                        ui.checkbox(&mut self.show_pick_rays, "Pick rays");
+                        // Categories the app's own code draws in, once it has
+                        let categories = debug::categories();
+                        if !categories.is_empty() {
+                            ui.separator();
+                        }
+                        for (name, mut shown) in categories {
+                            if ui.checkbox(&mut shown, name).changed() {
+                                debug::set_category_shown(name, shown);
+                            }
+                        }
+                    });
+                    ui.separator();
+                    let record = if self.input_capture == InputCapture::Recording {
+                        "Stop Input Recording"
+                    } else {
+                        "Record Input"
+                    };
+                    let idle_or_recording = self.input_capture != InputCapture::Playing;
+                    if ui.add_enabled(idle_or_recording, egui::Button::new(record)).clicked() {
+                        self.commands.push(Command::ToggleInputRecording);
+                        ui.close_menu();
+                    }
+                    let replay = egui::Button::new("Replay Input...");
+                    if ui.add_enabled(self.input_capture == InputCapture::Idle, replay).clicked() {
+                        self.commands.push(Command::ReplayInput);
+                        ui.close_menu();
+                    }
+                });
+                app_menu(ui);
+                ui.menu_button("Help", |ui| {
+                    if ui.button("About / Diagnostics").clicked() {
+                        self.commands.push(Command::ShowDiagnostics);
+                        ui.close_menu();
+                    }
+                });
+                if ui
+                    .add(egui::Button::new("Commands").shortcut_text(ctx.format_shortcut(&PALETTE_SHORTCUT)))
+                    .clicked()
+                {
+                    self.command_palette.toggle();
+                }
+                match self.input_capture {
+                    InputCapture::Recording => {
+                        ui.colored_label(egui::Color32::from_rgb(230, 70, 70), "● REC");
+                    }
+                    InputCapture::Playing => {
+                        ui.colored_label(egui::Color32::from_rgb(80, 200, 80), "▶ Replaying input");
+                    }
+                    InputCapture::Idle => {}
+                }
+                if self.frame_capture.is_pending() {
+                    ui.colored_label(egui::Color32::from_rgb(230, 160, 60), "◉ Capturing frame");
+                }
+                if self.video.is_recording() {
+                    ui.colored_label(egui::Color32::from_rgb(230, 70, 70), "● Capturing video");
+                }
+                if !self.compiling_pipelines.is_empty() {
+                    ui.spinner();
+                    ui.weak("Compiling shaders").on_hover_text(format!(
+                        "Drawing with a fallback shader until these are ready:\n{}",
+                        self.compiling_pipelines.join("\n")
+                    ));
+                }
+                if !self.loading_assets.is_empty() {
+                    let progress = &self.loading_assets;
+                    let files: Vec<String> = progress.files.iter().map(|path| path.display().to_string()).collect();
+                    ui.add(
+                        egui::ProgressBar::new(progress.fraction())
+                            .desired_width(120.0)
+                            .text(format!("Loading {}/{}", progress.done, progress.total)),
+                    )
+                    .on_hover_text(format!("Reading in the background:\n{}", files.join("\n")));
+                }
+            });
+        });
 
-                        // Add the UI component to adjust the number of sides for polygons
-                        if let RenderingStyle::Polygon = self.rendering_style {
-                            ui.horizontal(|ui| {
-                                ui.label(format!("Polygon sides: {}", self.sides));
-                                if ui.button("-").clicked() {
-                                    self.sides = (self.sides - 1).max(3); // Ensure a minimum of 3 sides
-                                }
-                                if ui.button("+").clicked() {
-                                    self.sides = (self.sides + 1).min(12); // Set a max number of sides, for example, 12
-                                }
-                            });
+        puffin::profile_function!();
+
+        let selection_bounds = object_bounds.filter(|_| self.selected);
+        // Taken out while it draws, since the built-in panels edit the rest of the state
+        let mut dock = std::mem::take(&mut self.dock);
+        dock.show(ctx, |panel, ui| match panel {
+            BuiltinPanel::Scene => self.scene_ui(ui),
+            BuiltinPanel::Shader => self.shader_ui(ui),
+            BuiltinPanel::Camera => camera_ui(ui, camera, camera_controller, selection_bounds),
+            BuiltinPanel::Stats => render_stats.ui(ui, &mut self.pacer),
+        });
+        self.dock = dock;
+
+        theme_panel(ctx, &mut self.theme);
+        transform_panel(
+            ctx,
+            self.selected.then_some(&mut *object_transform),
+            &mut self.gizmo,
+            &mut self.object_drag,
+        );
+        if selection_bounds.is_some() {
+            self.object_drag
+                .show(ctx, camera.view_projection_matrix(), camera.position, object_transform);
+            // The gizmo stays out of the way while the object itself is dragged
+            if !self.object_drag.is_dragging() {
+                self.gizmo
+                    .show(ctx, camera.view_projection_matrix(), camera.position, object_transform);
+            }
+        }
+
+        self.labels
+            .add(WorldLabel::new(glam::Vec3::ZERO, "Origin").icon("✚").color(egui::Color32::GRAY));
+        if let Some(bounds) = object_bounds {
+            let top = glam::Vec3::new(bounds.center().x, bounds.max.y, bounds.center().z);
+            self.labels.add(WorldLabel::new(top, "Object").icon("◆"));
+        }
+        if camera_controller.mode == CameraMode::Orbit {
+            self.labels.add(
+                WorldLabel::new(camera.target, "Target")
+                    .icon("⊙")
+                    .color(egui::Color32::from_rgb(255, 200, 80)),
+            );
+        }
+        self.network.mark_cameras(&mut self.labels);
+        let occluders: Vec<Aabb> = object_bounds.into_iter().collect();
+        self.labels
+            .show(ctx, camera.view_projection_matrix(), camera.position, &occluders);
+        self.grid.show_axes(ctx, camera);
+        self.hud.show(ctx, camera, &action_map.key_text(Action::NextBlock));
+        self.console.show(ctx);
+        self.frame_timer.show_overlay(ctx);
+        self.plots.show(ctx);
+        self.network.show(ctx, &mut self.toasts);
+        self.video.show(ctx, &mut self.toasts, &mut self.file_dialogs);
+        self.environment.show(ctx, &mut self.file_dialogs);
+        self.water.show(ctx);
+        self.instancing.show(ctx);
+        self.terrain.show(ctx, &mut self.file_dialogs);
+        self.session.show(ctx);
+        self.shader_reload.show(ctx);
+        if let Some(request) = preferences_panel(
+            ctx,
+            action_map,
+            &mut self.shortcuts,
+            &mut self.rebinding,
+            &mut self.presets,
+            &mut self.commands,
+            &mut self.memory_budget_mib,
+            &mut self.frame_latency,
+            &mut self.background_mode,
+            &mut self.background_fps,
+            &mut self.pacing_mode,
+            &mut self.fps_cap,
+            &mut self.present_mode,
+            &self.present_modes,
+            &mut self.redraw_on_demand,
+            &mut self.resolution,
+            &mut self.msaa_samples,
+            &self.sample_counts,
+            &mut self.stereo,
+        ) {
+            self.preset_request = Some(request);
+        }
+        self.toasts.show(ctx);
+
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.show(ctx);
+        }
+
+        let steps = self.history.show(ctx);
+        let step_command = if steps < 0 { Command::Undo } else { Command::Redo };
+        self.commands
+            .extend(std::iter::repeat_n(step_command, steps.unsigned_abs() as usize));
+
+        if self.show_profiler && !puffin_egui::profiler_window(ctx) {
+            self.set_profiler_visible(false);
+        }
+
+        if let Some(command) = self.command_palette.show(ctx, &self.shortcuts) {
+            self.commands.push(command);
+        }
+
+        self.exit_prompt(ctx);
+
+        // A drag in progress is committed as a single edit once released
+        let interacting = ctx.input(|i| i.pointer.any_down())
+            || self.gizmo.is_dragging()
+            || self.morph.playing
+            || self.animation.playing
+            || self.sequencer.playing
+            || self.physics.is_active()
+            || self.environment.is_active()
+            || self.water.is_active()
+            || self.scripts.is_active()
+            || self.nudging
+            || self.events.receive(); // Sent edits, like undone sides, only land once the loop applies them
+        self.history.track(
+            SceneSnapshot {
+                transform: *object_transform,
+                polygon: (self.sides, self.radius),
+            },
+            interacting,
+        );
+    }
+
+    /// Adds a panel to the dock, floating until it's docked. Its placement is saved under its title.
+    pub fn register_panel(&mut self, panel: Box<dyn Panel>) {
+        self.dock.register(panel);
+    }
+
+    /// The registered shader called `name`, if there is one.
+    pub fn shader_named(&self, name: &str) -> Option<ShaderId> {
+        self.shaders.iter().map(|&(id, ..)| id).find(|id| id.name() == name)
+    }
+
+    // The object's shape, and the UI scale
+    fn scene_ui(&mut self, ui: &mut egui::Ui) {
+        if let RenderingStyle::Polygon = self.rendering_style {
+            ui.horizontal(|ui| {
+                ui.label(format!("Polygon sides: {}", self.sides));
+                ui.add_enabled_ui(!self.morph.playing, |ui| {
+                    if ui.button("-").clicked() {
+                        self.events.send(AppEvent::SetSides(self.sides.saturating_sub(1)));
+                    }
+                    if ui.button("+").clicked() {
+                        self.events.send(AppEvent::SetSides(self.sides.saturating_add(1)));
+                    }
+                });
+            });
+            ui.add_enabled(
+                !self.morph.playing,
+                egui::Slider::new(&mut self.radius, 0.1..=1.0).text("Radius"),
+            );
+            self.morph.ui(ui);
+            ui.separator();
+        }
+
+        ui.horizontal(|ui| {
+            let open = ui.add_enabled(!self.file_dialogs.is_pending(), egui::Button::new("Open mesh..."));
+            if open.on_hover_text("An .obj, .gltf or .glb file to show in place of the shape").clicked() {
+                self.file_dialogs.open(FileKind::Model);
+            }
+            if let Some(name) = &self.loaded_mesh {
+                ui.label(format!("Showing {name}"));
+                if ui.button("Back to shape").clicked() {
+                    self.loaded_mesh = None;
+                }
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label(format!("Pixels per point: {}", ui.ctx().pixels_per_point()));
+            if ui.button("-").on_hover_text("Ctrl+-").clicked() {
+                self.set_ui_scale(self.scale_factor - UI_SCALE_STEP);
+            }
+            if ui.button("+").on_hover_text("Ctrl+=").clicked() {
+                self.set_ui_scale(self.scale_factor + UI_SCALE_STEP);
+            }
+            if ui.button("Reset").on_hover_text("Ctrl+0").clicked() {
+                self.set_ui_scale(1.0);
+            }
+        });
+        ui.horizontal(|ui| {
+            let mut overridden = self.display_scale.is_some();
+            let response = ui
+                .checkbox(&mut overridden, "Override display scale")
+                .on_hover_text("Ignores the scale the OS reports for the monitor, which some setups get wrong");
+            if response.changed() {
+                // Starts from what the OS reports, so turning it on changes nothing yet
+                self.display_scale = overridden.then(|| ui.ctx().native_pixels_per_point().unwrap_or(1.0));
+            }
+            if let Some(scale) = &mut self.display_scale {
+                ui.add(egui::DragValue::new(scale).speed(0.05).range(DISPLAY_SCALE_RANGE));
+            }
+        });
+    }
+
+    // Which shader the scene draws with, and how
+    fn shader_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let active = self.shaders.iter().find(|(id, ..)| *id == self.active_shader);
+            egui::ComboBox::from_label("Shader")
+                .selected_text(active.map_or("", |(_, label, _)| *label))
+                .show_ui(ui, |ui| {
+                    for &(id, label, description) in &self.shaders {
+                        let response = ui.selectable_label(id == self.active_shader, label);
+                        if response.clicked() {
+                            self.events.send(AppEvent::SetShader(id.name().to_string()));
                         }
+                        if !description.is_empty() {
+                            response.on_hover_text(description);
+                        }
+                    }
+                });
+            if ui.button("Switch Shader").clicked() {
+                self.commands.push(Command::SwitchShader);
+            }
+        });
 
-                        // Add button to switch rendering style
-                        ui.separator();
-                        if ui.button("Switch to Cube").clicked() {
-                            self.rendering_style = match self.rendering_style {
-                                RenderingStyle::Polygon => RenderingStyle::Cube,
-                                RenderingStyle::Cube => RenderingStyle::Polygon,
-                            };
+        ui.separator();
+        egui::ComboBox::from_label("Rendering style")
+            .selected_text(self.rendering_style.label())
+            .show_ui(ui, |ui| {
+                for style in RenderingStyle::ALL {
+                    ui.add_enabled_ui(self.compute || style != RenderingStyle::Particles, |ui| {
+                        ui.selectable_value(&mut self.rendering_style, style, style.label())
+                            .on_disabled_hover_text("Needs compute shaders, which this GPU lacks");
+                    });
+                }
+            });
+        egui::ComboBox::from_label("Render mode")
+            .selected_text(self.render_mode.label())
+            .show_ui(ui, |ui| {
+                for mode in RenderMode::ALL {
+                    ui.add_enabled_ui(self.render_modes.contains(&mode), |ui| {
+                        ui.selectable_value(&mut self.render_mode, mode, mode.label())
+                            .on_disabled_hover_text("Not supported by this GPU");
+                    });
+                }
+            });
+        match self.rendering_style {
+            RenderingStyle::Textured => {
+                ui.weak("Choose the albedo texture of the object's material in the Materials window");
+            }
+            RenderingStyle::Particles => {
+                ui.weak("Simulated in a compute pass; tune the emitter in the Particles window");
+            }
+            style => {
+                if let Some(primitive) = style.primitive(&self.tessellation) {
+                    self.tessellation_ui(ui, primitive);
+                }
+            }
+        }
+    }
+
+    // Sliders for the parameters `primitive` is tessellated with; changes rebuild the mesh as they're dragged
+    fn tessellation_ui(&mut self, ui: &mut egui::Ui, primitive: Primitive) {
+        let tessellation = &mut self.tessellation;
+        match primitive {
+            Primitive::UvSphere { .. } => {
+                ui.add(egui::Slider::new(&mut tessellation.segments, SEGMENTS_RANGE).text("Segments"));
+                ui.add(egui::Slider::new(&mut tessellation.rings, RINGS_RANGE).text("Rings"));
+            }
+            Primitive::Icosphere { .. } => {
+                ui.add(egui::Slider::new(&mut tessellation.subdivisions, SUBDIVISIONS_RANGE).text("Subdivisions"));
+            }
+            Primitive::Cylinder { .. } | Primitive::Cone { .. } | Primitive::Plane { .. } => {
+                ui.add(egui::Slider::new(&mut tessellation.segments, SEGMENTS_RANGE).text("Segments"));
+            }
+            Primitive::Torus { .. } => {
+                ui.add(egui::Slider::new(&mut tessellation.segments, SEGMENTS_RANGE).text("Segments"));
+                ui.add(egui::Slider::new(&mut tessellation.rings, RINGS_RANGE).text("Tube sides"));
+            }
+        }
+    }
+
+    // Scopes are only recorded while the profiler is visible
+    fn set_profiler_visible(&mut self, visible: bool) {
+        self.show_profiler = visible;
+        puffin::set_scopes_on(visible);
+    }
+
+    /// Whether something keeps changing the picture without any input, so redraw-on-demand must keep drawing.
+    pub fn is_animating(&self) -> bool {
+        (self.morph.playing && matches!(self.rendering_style, RenderingStyle::Polygon))
+            || self.animation.playing
+            || self.sequencer.playing
+            || self.physics.is_active()
+            || self.environment.is_active()
+            || self.water.is_active()
+            || self.scripts.is_active()
+            || !self.compiling_pipelines.is_empty() // Their first frame is drawn as soon as they are ready
+            || !self.loading_assets.is_empty() // Loads are applied by the frames drawn while they finish
+            || self.frame_capture.is_pending() // Only a presented frame is captured
+            || self.video.is_active() // Every frame goes into the clip, whether or not anything moved
+            || self.input_capture == InputCapture::Playing
+            || self.session.is_advancing()
+    }
+
+    // While playing, the morph owns the polygon's shape; pausing keeps the last animated shape
+    pub fn update_morph(&mut self, dt: f32) {
+        if self.morph.playing && matches!(self.rendering_style, RenderingStyle::Polygon) {
+            self.morph.advance(dt);
+            if self.morph.sides() != self.sides {
+                self.events.send(AppEvent::SetSides(self.morph.sides()));
+            }
+            self.radius = self.morph.radius();
+        }
+    }
+
+    /// UI pixels per point for a window the OS gives `os_scale`, unless overridden.
+    pub fn pixels_per_point(&self, os_scale: f32) -> f32 {
+        self.display_scale.unwrap_or(os_scale) * self.scale_factor
+    }
+
+    // Rounded to the step so repeated zooming does not drift, e.g. 1.2000001
+    pub fn set_ui_scale(&mut self, scale: f32) {
+        self.scale_factor = ((scale / UI_SCALE_STEP).round() * UI_SCALE_STEP).clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+    }
+
+    /// Undoes the last edit. An edit of the Scene Tree returns the nodes for the render loop to restore.
+    pub fn undo(&mut self, transform: &mut Transform) -> Option<Vec<SceneNode>> {
+        let edit = self.history.undo()?;
+        self.apply_edit(edit, transform)
+    }
+
+    pub fn redo(&mut self, transform: &mut Transform) -> Option<Vec<SceneNode>> {
+        let edit = self.history.redo()?;
+        self.apply_edit(edit, transform)
+    }
+
+    fn apply_edit(&mut self, edit: SceneEdit, transform: &mut Transform) -> Option<Vec<SceneNode>> {
+        match edit {
+            SceneEdit::Transform { after, .. } => *transform = after,
+            SceneEdit::Polygon { after: (sides, radius), .. } => {
+                self.morph.playing = false;
+                self.events.send(AppEvent::SetSides(sides));
+                self.radius = radius;
+            }
+            SceneEdit::Nodes { after, .. } => return Some(after),
+        }
+        None
+    }
+
+    // All rebindable command shortcuts, plus Ctrl+C / Ctrl+V on the selection
+    fn dispatch_shortcuts(&mut self, ctx: &Context) {
+        // Focused text fields keep their own Ctrl+Z, Ctrl+C and plain letters; the editor records keys instead
+        if ctx.wants_keyboard_input() || self.rebinding.is_some() {
+            return;
+        }
+        self.commands.extend(self.shortcuts.dispatch(ctx));
+
+        // egui-winit turns Ctrl+C / Ctrl+V into these events; outside text fields they act on the selection
+        if self.selected {
+            ctx.input(|i| {
+                for event in &i.events {
+                    match event {
+                        egui::Event::Copy => self.commands.push(Command::CopyTransform),
+                        egui::Event::Paste(_) => self.commands.push(Command::PasteTransform),
+                        _ => {}
+                    }
+                }
+            });
+        }
+    }
+
+    // Ctrl+= / Ctrl+- / Ctrl+0, replacing egui's built-in zoom which would fight our pixels-per-point
+    fn zoom_shortcuts(&mut self, ctx: &Context) {
+        use egui::gui_zoom::kb_shortcuts::{ZOOM_IN, ZOOM_IN_SECONDARY, ZOOM_OUT, ZOOM_RESET};
+        let (zoom_in, zoom_out, reset) = ctx.input_mut(|i| {
+            (
+                i.consume_shortcut(&ZOOM_IN) || i.consume_shortcut(&ZOOM_IN_SECONDARY),
+                i.consume_shortcut(&ZOOM_OUT),
+                i.consume_shortcut(&ZOOM_RESET),
+            )
+        });
+        if zoom_in {
+            self.set_ui_scale(self.scale_factor + UI_SCALE_STEP);
+        }
+        if zoom_out {
+            self.set_ui_scale(self.scale_factor - UI_SCALE_STEP);
+        }
+        if reset {
+            self.set_ui_scale(1.0);
+        }
+    }
+
+    /// Returns true if the app may close right away, otherwise asks about the unsaved changes first.
+    pub fn request_exit(&mut self) -> bool {
+        if self.exit_confirmed || !self.history.is_dirty() {
+            return true;
+        }
+        self.exit_prompt = true;
+        false
+    }
+
+    /// Whether quitting is underway, though perhaps still waiting on the unsaved-changes prompt.
+    pub fn is_exiting(&self) -> bool {
+        self.exit_prompt || self.exit_confirmed
+    }
+
+    // Modal Save / Discard / Cancel dialog shown while exiting with unsaved changes
+    fn exit_prompt(&mut self, ctx: &Context) {
+        // A save dialog that was cancelled leaves nothing to wait for
+        if self.exit_after_save && !self.file_dialogs.is_pending() && !self.commands.contains(&Command::SaveScene) {
+            self.exit_after_save = false;
+        }
+        if !self.exit_prompt {
+            return;
+        }
+
+        // Dim and block everything behind the dialog
+        let screen = ctx.screen_rect();
+        egui::Area::new(egui::Id::new("exit_prompt_backdrop"))
+            .order(egui::Order::Middle)
+            .fixed_pos(screen.min)
+            .show(ctx, |ui| {
+                ui.allocate_response(screen.size(), egui::Sense::click());
+                ui.painter()
+                    .rect_filled(screen, 0.0, egui::Color32::from_black_alpha(150));
+            });
+
+        egui::Window::new("Unsaved changes")
+            .order(egui::Order::Foreground)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("The scene has unsaved changes. Save before quitting?");
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if self.exit_after_save {
+                        ui.spinner();
+                        ui.label("Saving...");
+                        return;
+                    }
+                    if ui.button("Save").clicked() {
+                        self.exit_after_save = true;
+                        self.commands.push(Command::SaveScene);
+                    }
+                    if ui.button("Discard").clicked() {
+                        self.exit_prompt = false;
+                        self.exit_confirmed = true;
+                    }
+                    if ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                        self.exit_prompt = false;
+                    }
+                });
+            });
+    }
+
+    // Called once the scene was written; finishes a pending save-and-quit
+    pub fn scene_saved(&mut self, path: PathBuf) {
+        self.history.mark_saved();
+        self.scene_path = Some(path);
+        if self.exit_after_save {
+            self.exit_after_save = false;
+            self.exit_prompt = false;
+            self.exit_confirmed = true;
+        }
+    }
+
+    // True while a UI element needs raw key presses that would otherwise trigger actions
+    pub fn captures_keyboard(&self) -> bool {
+        self.rebinding.is_some() || self.command_palette.is_open() || self.exit_prompt
+    }
+
+    // Applies the commands that only touch UI-owned state; returns the rest for the caller
+    pub fn apply_command(&mut self, command: Command) -> Option<Command> {
+        match command {
+            // To the next registered shader, back to the first after the last
+            Command::SwitchShader => {
+                let index = self.shaders.iter().position(|(id, ..)| *id == self.active_shader);
+                let next = index.map_or(0, |index| (index + 1) % self.shaders.len());
+                if let Some(&(id, ..)) = self.shaders.get(next) {
+                    self.active_shader = id;
+                }
+            }
+            Command::ToggleRenderingStyle => {
+                self.rendering_style = self.rendering_style.next();
+                if self.rendering_style == RenderingStyle::Particles && !self.compute {
+                    self.rendering_style = self.rendering_style.next();
+                }
+            }
+            Command::ToggleGizmo => self.gizmo.enabled = !self.gizmo.enabled,
+            Command::GizmoTranslate => self.gizmo.mode = GizmoMode::Translate,
+            Command::GizmoRotate => self.gizmo.mode = GizmoMode::Rotate,
+            Command::GizmoScale => self.gizmo.mode = GizmoMode::Scale,
+            Command::ToggleObjectDrag => self.object_drag.enabled = !self.object_drag.enabled,
+            Command::ToggleGrid => self.grid.enabled = !self.grid.enabled,
+            Command::OpenModel => self.file_dialogs.open(FileKind::Model),
+            Command::OpenTexture => self.file_dialogs.open(FileKind::Texture),
+            Command::OpenWorld => self.file_dialogs.open(FileKind::World),
+            Command::SaveWorld => self.file_dialogs.save(FileKind::World),
+            Command::ExportGltf => self.file_dialogs.save(FileKind::Gltf),
+            Command::OpenScene => self.file_dialogs.open(FileKind::Scene),
+            Command::SaveScene if self.scene_path.is_none() => self.file_dialogs.save(FileKind::Scene),
+            Command::SaveSceneAs => self.file_dialogs.save(FileKind::Scene),
+            Command::PlacePrefab => self.file_dialogs.open(FileKind::Prefab),
+            Command::SavePrefab => self.file_dialogs.save(FileKind::Prefab),
+            Command::ImportPreset => self.file_dialogs.open(FileKind::Preset),
+            Command::ExportPreset => self.file_dialogs.save(FileKind::Preset),
+            Command::ReplayInput => self.file_dialogs.open(FileKind::Recording),
+            Command::ToggleProfiler => self.set_profiler_visible(!self.show_profiler),
+            Command::ExportGpuTrace => self.file_dialogs.save(FileKind::GpuTrace),
+            Command::CaptureFrame => {
+                if !self.frame_capture.trigger() {
+                    self.toasts.warning(self.frame_capture.status());
+                }
+            }
+            Command::ToggleVideoCapture => self.video.toggle(&mut self.toasts),
+            Command::ShowDiagnostics => {
+                if let Some(diagnostics) = &mut self.diagnostics {
+                    diagnostics.open = true;
+                }
+            }
+            _ => return Some(command),
+        }
+        None
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputCapture {
+    Idle,
+    Recording,
+    Playing,
+}
+
+impl Default for UIState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Camera settings, edited in place on the camera and its active controller
+fn camera_ui(
+    ui: &mut egui::Ui,
+    camera: &mut Camera,
+    controller: &mut CameraController,
+    selection_bounds: Option<Aabb>,
+) {
+    let mut mode = controller.mode;
+    egui::ComboBox::from_label("Mode")
+        .selected_text(mode.label())
+        .show_ui(ui, |ui| {
+            for candidate in CameraMode::ALL {
+                ui.selectable_value(&mut mode, candidate, candidate.label());
+            }
+        });
+    if mode != controller.mode {
+        controller.set_mode(mode, camera);
+    }
+
+    ui.add(egui::Slider::new(&mut controller.speed, 0.1..=20.0).text("Speed"));
+    ui.add(egui::Slider::new(&mut controller.sensitivity, 0.05..=2.0).text("Sensitivity"));
+    ui.checkbox(&mut controller.smooth_scroll, "Smooth scroll zoom")
+        .on_hover_text("Mouse wheel zoom glides to a stop; trackpads are never smoothed");
+    ui.checkbox(&mut controller.smooth_movement, "Smooth movement")
+        .on_hover_text("FPS and fly movement eases in and out instead of starting and stopping at once");
+    let mut projection = camera.projection;
+    egui::ComboBox::from_label("Projection")
+        .selected_text(projection.label())
+        .show_ui(ui, |ui| {
+            for candidate in Projection::ALL {
+                ui.selectable_value(&mut projection, candidate, candidate.label());
+            }
+        })
+        .response
+        .on_hover_text("2D (pixels) draws one world unit per pixel, so positions can be given in screen pixels");
+    if projection != camera.projection {
+        camera.set_projection(projection);
+    }
+    match camera.projection {
+        Projection::Perspective => {
+            ui.add(egui::Slider::new(&mut camera.fovy, FOV_RANGE).text("FOV (deg)"));
+        }
+        Projection::Orthographic => {
+            ui.add(
+                egui::Slider::new(&mut camera.ortho_height, ORTHO_HEIGHT_RANGE)
+                    .logarithmic(true)
+                    .text("View height"),
+            );
+        }
+        Projection::Pixels => {
+            ui.weak(format!("{:.0} pixels tall", camera.ortho_height));
+        }
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Near");
+        ui.add(
+            egui::DragValue::new(&mut camera.znear)
+                .speed(0.01)
+                .range(0.001..=camera.zfar - 0.001),
+        );
+        ui.label("Far");
+        ui.add(
+            egui::DragValue::new(&mut camera.zfar)
+                .speed(1.0)
+                .range(camera.znear + 0.001..=10_000.0),
+        );
+    });
+
+    ui.separator();
+    // Edited as copies, so an edit that would put the eye on the target and leave no view direction is dropped
+    let (mut position, mut target) = (camera.position, camera.target);
+    egui::Grid::new("camera_placement").num_columns(4).show(ui, |ui| {
+        for (label, point) in [("Position", &mut position), ("Target", &mut target)] {
+            ui.label(label);
+            for axis in 0..3 {
+                ui.add(egui::DragValue::new(&mut point[axis]).speed(0.05).max_decimals(2));
+            }
+            ui.end_row();
+        }
+    });
+    if position.distance_squared(target) > 1e-6 {
+        (camera.position, camera.target) = (position, target);
+    }
+    let frame_button = ui.add_enabled(selection_bounds.is_some(), egui::Button::new("Frame selection"));
+    if let (true, Some(bounds)) = (frame_button.clicked(), selection_bounds) {
+        camera.frame_bounds(&bounds);
+    }
+}
+
+fn shortcut_editor(ui: &mut egui::Ui, shortcuts: &mut ShortcutMap, rebinding: &mut Option<Rebinding>) {
+    // Record a key press with its modifiers, then optionally a second one to form a chord.
+    // Enter after the first key keeps it a single shortcut
+    if let Some(Rebinding::Shortcut(command, first)) = *rebinding {
+        let pressed = ui.input(|i| {
+            i.events.iter().find_map(|event| match event {
+                egui::Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => Some((*key, *modifiers)),
+                _ => None,
+            })
+        });
+        match (pressed, first) {
+            (Some((egui::Key::Escape, _)), _) => *rebinding = None,
+            (Some((egui::Key::Enter, _)), Some(first)) => {
+                shortcuts.bind(command, Some(Shortcut::single(first)));
+                *rebinding = None;
+            }
+            (Some((key, modifiers)), None) => {
+                *rebinding = Some(Rebinding::Shortcut(command, Some(shortcut_from_key(key, modifiers))));
+            }
+            (Some((key, modifiers)), Some(first)) => {
+                shortcuts.bind(command, Some(Shortcut::chord(first, shortcut_from_key(key, modifiers))));
+                *rebinding = None;
+            }
+            (None, _) => {}
+        }
+    }
+
+    egui::ScrollArea::vertical()
+        .id_source("shortcut_scroll")
+        .max_height(200.0)
+        .show(ui, |ui| {
+            egui::Grid::new("shortcut_grid").num_columns(3).show(ui, |ui| {
+                for command in Command::ALL {
+                    ui.label(command.label());
+
+                    let text = match *rebinding {
+                        Some(Rebinding::Shortcut(c, None)) if c == command => "Press a shortcut...".to_string(),
+                        Some(Rebinding::Shortcut(c, Some(first))) if c == command => {
+                            format!("{} then... (Enter to finish)", ui.ctx().format_shortcut(&first))
                         }
+                        _ => shortcuts.get(command).map_or("-".to_string(), |s| s.format(ui.ctx())),
+                    };
+                    let response = ui.button(text).on_hover_text("Right-click to unbind");
+                    if response.clicked() {
+                        *rebinding = Some(Rebinding::Shortcut(command, None));
+                    }
+                    if response.secondary_clicked() {
+                        shortcuts.bind(command, None);
+                    }
 
-                        ui.separator();
-                        ui.horizontal(|ui| {
-                            ui.label(format!("Pixels per point: {}", ctx.pixels_per_point()));
-                            if ui.button("-").clicked() {
-                                self.scale_factor = (self.scale_factor - 0.1).max(0.3);
-                            }
-                            if ui.button("+").clicked() {
-                                self.scale_factor = (self.scale_factor + 0.1).min(3.0);
+                    let conflicts = shortcuts.conflicts(command);
+                    if !conflicts.is_empty() {
+                        let names: Vec<&str> = conflicts.iter().map(|c| c.label()).collect();
+                        ui.colored_label(egui::Color32::from_rgb(255, 200, 80), "⚠")
+                            .on_hover_text(format!("Also bound to: {}", names.join(", ")));
+                    } else if shortcuts.get(command) != command.default_shortcut() {
+                        if ui.small_button("Reset").clicked() {
+                            shortcuts.reset(command);
+                        }
+                    } else {
+                        ui.label("");
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    if ui.button("Reset shortcuts").clicked() {
+        shortcuts.reset_all();
+        *rebinding = None;
+    }
+}
+
+// Stores Ctrl as the platform command key so saved shortcuts work on macOS too
+fn shortcut_from_key(key: egui::Key, modifiers: egui::Modifiers) -> egui::KeyboardShortcut {
+    let modifiers = if modifiers.command || modifiers.ctrl {
+        egui::Modifiers {
+            ctrl: false,
+            mac_cmd: false,
+            command: true,
+            ..modifiers
+        }
+    } else {
+        modifiers
+    };
+    egui::KeyboardShortcut::new(modifiers, key)
+}
+
+// Inspector for the selected object; `None` when nothing is selected
+fn transform_panel(ctx: &Context, transform: Option<&mut Transform>, gizmo: &mut Gizmo, object_drag: &mut ObjectDrag) {
+    egui::Window::new("Transform")
+        .resizable(false)
+        .default_open(true)
+        .show(ctx, |ui| {
+            let Some(transform) = transform else {
+                ui.weak("Nothing selected. Click the object in the viewport to select it.");
+                return;
+            };
+            transform_grid(ui, transform);
+
+            if ui.button("Reset").clicked() {
+                *transform = Transform::IDENTITY;
+            }
+            ui.separator();
+            gizmo.settings_ui(ui);
+            object_drag.settings_ui(ui);
+        });
+}
+
+/// Position, rotation in degrees and scale of `transform` as drag values.
+pub fn transform_grid(ui: &mut egui::Ui, transform: &mut Transform) {
+    egui::Grid::new("transform_grid").num_columns(4).show(ui, |ui| {
+        ui.label("Position");
+        for value in transform.translation.as_mut() {
+            ui.add(egui::DragValue::new(value).speed(0.01));
+        }
+        ui.end_row();
+
+        ui.label("Rotation");
+        let (x, y, z) = transform.rotation.to_euler(EulerRot::XYZ);
+        let mut degrees = [x.to_degrees(), y.to_degrees(), z.to_degrees()];
+        let mut rotated = false;
+        for value in &mut degrees {
+            rotated |= ui.add(egui::DragValue::new(value).speed(1.0).suffix("°")).changed();
+        }
+        if rotated {
+            let [x, y, z] = degrees.map(f32::to_radians);
+            transform.rotation = Quat::from_euler(EulerRot::XYZ, x, y, z);
+        }
+        ui.end_row();
+
+        ui.label("Scale");
+        for value in transform.scale.as_mut() {
+            ui.add(egui::DragValue::new(value).speed(0.01).range(0.01..=100.0));
+        }
+        ui.end_row();
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn preferences_panel(
+    ctx: &Context,
+    action_map: &mut ActionMap,
+    shortcuts: &mut ShortcutMap,
+    rebinding: &mut Option<Rebinding>,
+    presets: &mut PresetBrowser,
+    commands: &mut Vec<Command>,
+    memory_budget_mib: &mut u32,
+    frame_latency: &mut u32,
+    background_mode: &mut BackgroundMode,
+    background_fps: &mut u32,
+    pacing_mode: &mut PacingMode,
+    fps_cap: &mut u32,
+    present_mode: &mut PresentModePreference,
+    present_modes: &[wgpu::PresentMode],
+    redraw_on_demand: &mut bool,
+    resolution: &mut ResolutionController,
+    msaa_samples: &mut u32,
+    sample_counts: &[u32],
+    stereo: &mut StereoSettings,
+) -> Option<PresetRequest> {
+    let mut request = None;
+    egui::Window::new("Preferences")
+        .resizable(false)
+        .default_open(false)
+        .show(ctx, |ui| {
+            ui.heading("Keybindings");
+            egui::Grid::new("keybinding_grid").num_columns(5).show(ui, |ui| {
+                ui.label("");
+                ui.strong("Key");
+                ui.strong("Mouse");
+                ui.strong("Controller");
+                ui.end_row();
+                for action in Action::ALL {
+                    ui.label(action.label());
+
+                    let text = if *rebinding == Some(Rebinding::Key(action)) {
+                        "Press a key...".to_string()
+                    } else {
+                        action_map.key_text(action)
+                    };
+                    let response = ui.button(text).on_hover_text("Hold Ctrl, Alt or Shift to bind them with the key");
+                    if response.clicked() {
+                        *rebinding = Some(Rebinding::Key(action));
+                    }
+
+                    let mut button = action_map.mouse(action);
+                    egui::ComboBox::from_id_source(("mouse_binding", action))
+                        .selected_text(button.map_or("-".to_string(), |b| format!("{b:?}")))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut button, None, "-");
+                            for choice in MOUSE_BINDINGS {
+                                ui.selectable_value(&mut button, Some(choice), format!("{choice:?}"));
                             }
                         });
+                    if button != action_map.mouse(action) {
+                        action_map.bind_mouse(action, button);
+                    }
+
+                    let text = if *rebinding == Some(Rebinding::Gamepad(action)) {
+                        "Press a button...".to_string()
+                    } else {
+                        action_map.gamepad(action).map_or("-".to_string(), |input| input.label())
+                    };
+                    let response = ui.button(text).on_hover_text("Right-click to unbind");
+                    if response.clicked() {
+                        *rebinding = Some(Rebinding::Gamepad(action));
+                    }
+                    if response.secondary_clicked() {
+                        action_map.bind_gamepad(action, None);
+                    }
+
+                    let conflicts = action_map.conflicts(action);
+                    if !conflicts.is_empty() {
+                        let names: Vec<&str> = conflicts.iter().map(|a| a.label()).collect();
+                        ui.colored_label(egui::Color32::from_rgb(255, 200, 80), "⚠")
+                            .on_hover_text(format!("Also bound to: {}", names.join(", ")));
+                    } else if !action_map.is_default(action) {
+                        if ui.small_button("Reset").clicked() {
+                            action_map.reset(action);
+                        }
+                    } else {
+                        ui.label("");
+                    }
+                    ui.end_row();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("Reset all").clicked() {
+                    action_map.reset_all();
+                    *rebinding = None;
+                }
+                if rebinding.is_some() {
+                    ui.label("Esc cancels");
+                }
+            });
+
+            // Applies to stepping actions such as nudges, the same on every OS
+            ui.horizontal(|ui| {
+                ui.label("Key repeat");
+                ui.add(
+                    egui::DragValue::new(&mut action_map.repeat.delay)
+                        .speed(0.01)
+                        .range(0.05..=2.0)
+                        .suffix(" s delay"),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut action_map.repeat.rate)
+                        .speed(0.1)
+                        .range(1.0..=60.0)
+                        .suffix(" /s"),
+                );
+            });
+
+            ui.separator();
+            ui.heading("Shortcuts");
+            shortcut_editor(ui, shortcuts, rebinding);
+
+            ui.separator();
+            ui.heading("Rendering");
+            ui.horizontal(|ui| {
+                ui.label("GPU memory budget");
+                ui.add(egui::DragValue::new(memory_budget_mib).range(16..=65536).suffix(" MiB"))
+                    .on_hover_text("Warns when the renderer's buffers and textures use more than this");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Frame latency");
+                for latency in FRAME_LATENCY_RANGE {
+                    ui.radio_value(frame_latency, latency, latency.to_string());
+                }
+            })
+            .response
+            .on_hover_text(
+                "Frames queued ahead of the display. 1 responds fastest to input but may stutter when \
+                 frame times vary; 3 gives the smoothest throughput with up to two frames of extra lag.",
+            );
+            ui.horizontal(|ui| {
+                ui.label("In the background");
+                for mode in BackgroundMode::ALL {
+                    ui.radio_value(background_mode, mode, mode.label());
+                }
+                if *background_mode == BackgroundMode::Throttled {
+                    ui.add(egui::DragValue::new(background_fps).range(BACKGROUND_FPS_RANGE).suffix(" FPS"));
+                }
+            })
+            .response
+            .on_hover_text("How fast to render while another window has focus, to save power");
+            ui.horizontal(|ui| {
+                ui.label("Frame pacing");
+                for mode in PacingMode::ALL {
+                    ui.radio_value(pacing_mode, mode, mode.label());
+                }
+                if *pacing_mode == PacingMode::Capped {
+                    ui.add(egui::DragValue::new(fps_cap).range(FPS_CAP_RANGE).suffix(" FPS"));
+                }
+            })
+            .response
+            .on_hover_text(
+                "Uncapped draws as fast as the GPU allows and may tear; vsync waits for the display; the FPS \
+                 cap sleeps between frames to save power without the display's latency.",
+            );
+            ui.horizontal(|ui| {
+                ui.label("Present mode");
+                egui::ComboBox::from_id_source("present_mode")
+                    .selected_text(present_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in PresentModePreference::ALL {
+                            let supported = mode.wgpu().is_none_or(|mode| present_modes.contains(&mode));
+                            ui.add_enabled_ui(supported, |ui| {
+                                ui.selectable_value(present_mode, mode, mode.label())
+                                    .on_disabled_hover_text("Not supported by this display");
+                            });
+                        }
                     });
-            },
-        );
-    }
+            })
+            .response
+            .on_hover_text("How finished frames reach the display; automatic follows the frame pacing");
+            ui.checkbox(redraw_on_demand, "Only redraw when something changes")
+                .on_hover_text("Skips frames identical to the last one, so an idle window uses almost no power");
+            resolution.ui(ui);
+            ui.horizontal(|ui| {
+                ui.label("Anti-aliasing");
+                for samples in SAMPLE_COUNTS {
+                    let label = if samples == 1 { "Off".to_string() } else { format!("{samples}x") };
+                    ui.add_enabled_ui(sample_counts.contains(&samples), |ui| {
+                        ui.radio_value(msaa_samples, samples, label)
+                            .on_disabled_hover_text("Not supported by this GPU");
+                    });
+                }
+            })
+            .response
+            .on_hover_text("MSAA samples per pixel for the scene's meshes; more smooths edges but costs more");
+            stereo.ui(ui);
+
+            ui.separator();
+            ui.heading("Layout presets");
+            ui.weak("Panel layout, theme, UI scale, keybindings and shortcuts");
+            request = presets.ui(ui);
+            ui.horizontal(|ui| {
+                if ui.button("Import...").clicked() {
+                    commands.push(Command::ImportPreset);
+                }
+                if ui.button("Export...").clicked() {
+                    commands.push(Command::ExportPreset);
+                }
+            });
+        });
+    request
+}
+
+fn theme_panel(ctx: &Context, theme: &mut Theme) {
+    egui::Window::new("Theme")
+        .resizable(false)
+        .default_open(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for kind in ThemeKind::ALL {
+                    ui.radio_value(&mut theme.kind, kind, kind.label());
+                }
+            });
+
+            if theme.kind == ThemeKind::Custom {
+                let custom = &mut theme.custom;
+                ui.separator();
+                ui.checkbox(&mut custom.dark_base, "Dark base");
+                ui.horizontal(|ui| {
+                    ui.label("Accent color");
+                    ui.color_edit_button_srgb(&mut custom.accent);
+                });
+                ui.add(egui::Slider::new(&mut custom.rounding, 0.0..=12.0).text("Rounding"));
+                ui.add(egui::Slider::new(&mut custom.font_size, 10.0..=24.0).text("Font size"));
+                if ui.button("Reset").clicked() {
+                    *custom = Default::default();
+                }
+            }
+        });
 }
 
-// Rendering styles enum should probably stay here or be moved to a shared file
-#[derive(Clone, Copy, Debug)]
+// Rendering styles enum
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RenderingStyle {
     Polygon,
     Cube,
+    Textured, // A cube with texture coordinates, showing the material's albedo texture on each face
+    // Smoothly lit primitives, tessellated as `UIState::tessellation` says
+    Sphere,
+    Icosphere,
+    Cylinder,
+    Cone,
+    Torus,
+    Plane,
+    Particles, // GPU particles emitted from the object, which is hidden
+}
+
+impl RenderingStyle {
+    pub const ALL: [RenderingStyle; 10] = [
+        RenderingStyle::Polygon,
+        RenderingStyle::Cube,
+        RenderingStyle::Textured,
+        RenderingStyle::Sphere,
+        RenderingStyle::Icosphere,
+        RenderingStyle::Cylinder,
+        RenderingStyle::Cone,
+        RenderingStyle::Torus,
+        RenderingStyle::Plane,
+        RenderingStyle::Particles,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RenderingStyle::Polygon => "Polygon",
+            RenderingStyle::Cube => "Cube",
+            RenderingStyle::Textured => "Textured cube",
+            RenderingStyle::Sphere => "UV sphere",
+            RenderingStyle::Icosphere => "Icosphere",
+            RenderingStyle::Cylinder => "Cylinder",
+            RenderingStyle::Cone => "Cone",
+            RenderingStyle::Torus => "Torus",
+            RenderingStyle::Plane => "Plane",
+            RenderingStyle::Particles => "Particles",
+        }
+    }
+
+    /// The primitive this style draws at `tessellation`, if it draws one.
+    pub fn primitive(&self, tessellation: &Tessellation) -> Option<Primitive> {
+        let Tessellation {
+            segments,
+            rings,
+            subdivisions,
+        } = *tessellation;
+        match self {
+            RenderingStyle::Sphere => Some(Primitive::UvSphere { segments, rings }),
+            RenderingStyle::Icosphere => Some(Primitive::Icosphere { subdivisions }),
+            RenderingStyle::Cylinder => Some(Primitive::Cylinder { segments }),
+            RenderingStyle::Cone => Some(Primitive::Cone { segments }),
+            RenderingStyle::Torus => Some(Primitive::Torus { segments, sides: rings }),
+            RenderingStyle::Plane => Some(Primitive::Plane { segments }),
+            RenderingStyle::Polygon | RenderingStyle::Cube | RenderingStyle::Textured | RenderingStyle::Particles => {
+                None
+            }
+        }
+    }
+
+    pub fn next(&self) -> RenderingStyle {
+        match self {
+            RenderingStyle::Polygon => RenderingStyle::Cube,
+            RenderingStyle::Cube => RenderingStyle::Textured,
+            RenderingStyle::Textured => RenderingStyle::Sphere,
+            RenderingStyle::Sphere => RenderingStyle::Icosphere,
+            RenderingStyle::Icosphere => RenderingStyle::Cylinder,
+            RenderingStyle::Cylinder => RenderingStyle::Cone,
+            RenderingStyle::Cone => RenderingStyle::Torus,
+            RenderingStyle::Torus => RenderingStyle::Plane,
+            RenderingStyle::Plane => RenderingStyle::Particles,
+            RenderingStyle::Particles => RenderingStyle::Polygon,
+        }
+    }
 }
@@ -0,0 +1,121 @@
+// upload.rs
+
+use egui_wgpu::wgpu;
+use std::num::NonZeroU64;
+use wgpu::util::{DeviceExt, StagingBelt};
+
+// Big enough for every per-frame write plus a full mesh rebuild, so a frame normally fills one chunk
+const CHUNK_SIZE: wgpu::BufferAddress = 64 * 1024;
+
+// Per-frame buffer writes staged through reused mapped buffers instead of fresh allocations.
+// The copies are encoded separately and submitted ahead of the frame's own commands, so data
+// written at any point before `submit` is visible to the whole frame
+pub struct Uploads {
+    belt: StagingBelt,
+    encoder: Option<wgpu::CommandEncoder>,
+}
+
+impl Uploads {
+    pub fn new() -> Self {
+        Self {
+            belt: StagingBelt::new(CHUNK_SIZE),
+            encoder: None,
+        }
+    }
+
+    /// Queues `data` to be copied into `buffer` at `offset`. The buffer needs `COPY_DST`, and
+    /// offset and length must be multiples of `wgpu::COPY_BUFFER_ALIGNMENT`.
+    pub fn write(&mut self, device: &wgpu::Device, buffer: &wgpu::Buffer, offset: wgpu::BufferAddress, data: &[u8]) {
+        let Some(size) = NonZeroU64::new(data.len() as u64) else {
+            return;
+        };
+        let encoder = self.encoder.get_or_insert_with(|| create_encoder(device));
+        self.belt
+            .write_buffer(encoder, buffer, offset, size, device)
+            .copy_from_slice(data);
+    }
+
+    /// Queues a GPU-side copy of the first `size` bytes of `source` into `destination`, ordered after the
+    /// writes queued so far. Both buffers need the matching `COPY_SRC`/`COPY_DST` usage.
+    pub fn copy(
+        &mut self,
+        device: &wgpu::Device,
+        source: &wgpu::Buffer,
+        destination: &wgpu::Buffer,
+        size: wgpu::BufferAddress,
+    ) {
+        let encoder = self.encoder.get_or_insert_with(|| create_encoder(device));
+        encoder.copy_buffer_to_buffer(source, 0, destination, 0, size);
+    }
+
+    /// Queues tightly packed `pixels`, in the texture's own format, to be copied into the first mip level of
+    /// `texture`, which needs `COPY_DST`. Rows are padded to the copy alignment in a staging buffer of their own.
+    pub fn write_texture(&mut self, device: &wgpu::Device, texture: &wgpu::Texture, pixels: &[u8]) {
+        self.write_texture_level(device, texture, 0, pixels);
+    }
+
+    /// Like `write_texture`, into mip level `level`, whose size is halved from the first's per level. Compressed
+    /// formats take whole blocks, a row of blocks at a time, even where the level is smaller than one.
+    pub fn write_texture_level(&mut self, device: &wgpu::Device, texture: &wgpu::Texture, level: u32, pixels: &[u8]) {
+        let format = texture.format();
+        let size = texture.size().mip_level_size(level, texture.dimension()).physical_size(format);
+        let (block_width, block_height) = format.block_dimensions();
+        let (width, height) = (size.width / block_width, size.height / block_height);
+        let texel = format.block_copy_size(None).unwrap_or(4);
+        let row = (width * texel) as usize;
+        let padded_row = row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+        let mut padded = vec![0; padded_row * height as usize];
+        for (target, source) in padded.chunks_exact_mut(padded_row).zip(pixels.chunks_exact(row)) {
+            target[..row].copy_from_slice(source);
+        }
+        let staging = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Texture Staging Buffer"),
+            contents: &padded,
+            usage: wgpu::BufferUsages::COPY_SRC,
+        });
+        let encoder = self.encoder.get_or_insert_with(|| create_encoder(device));
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer: &staging,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row as u32),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            size,
+        );
+    }
+
+    /// Submits the staged copies followed by `commands`, then hands the staging memory back to
+    /// the belt for reuse once the GPU is done with it.
+    pub fn submit(
+        &mut self,
+        queue: &wgpu::Queue,
+        commands: impl IntoIterator<Item = wgpu::CommandBuffer>,
+    ) -> wgpu::SubmissionIndex {
+        self.belt.finish();
+        let uploads = self.encoder.take().map(|encoder| encoder.finish());
+        let index = queue.submit(uploads.into_iter().chain(commands));
+        self.belt.recall();
+        index
+    }
+}
+
+fn create_encoder(device: &wgpu::Device) -> wgpu::CommandEncoder {
+    device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Upload Encoder"),
+    })
+}
+
+impl Default for Uploads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,47 @@
+// Stretches the scene, rendered at a reduced resolution, over the whole surface before the UI is drawn
+
+@group(0) @binding(0)
+var scene: texture_2d<f32>;
+@group(0) @binding(1)
+var scene_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// A single triangle covering the screen, so no vertex buffer is needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_bilinear(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(scene, scene_sampler, in.uv);
+}
+
+// Bilinear followed by contrast-adaptive sharpening in the spirit of FSR's RCAS pass: the four neighbours
+// are subtracted in proportion to the contrast headroom left around the pixel, so soft edges regain detail
+// without ringing where contrast is already high
+@fragment
+fn fs_sharpen(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(scene));
+    let center = textureSample(scene, scene_sampler, in.uv).rgb;
+    let north = textureSample(scene, scene_sampler, in.uv - vec2<f32>(0.0, texel.y)).rgb;
+    let south = textureSample(scene, scene_sampler, in.uv + vec2<f32>(0.0, texel.y)).rgb;
+    let west = textureSample(scene, scene_sampler, in.uv - vec2<f32>(texel.x, 0.0)).rgb;
+    let east = textureSample(scene, scene_sampler, in.uv + vec2<f32>(texel.x, 0.0)).rgb;
+
+    let lowest = min(center, min(min(north, south), min(west, east)));
+    let highest = max(center, max(max(north, south), max(west, east)));
+    let headroom = clamp(min(lowest, 1.0 - highest) / max(highest, vec3<f32>(1e-4)), vec3<f32>(0.0), vec3<f32>(1.0));
+    // Negative lobe weight, capped at -1/8 for moderate sharpening
+    let lobe = -sqrt(headroom) * 0.125;
+    let color = (center + (north + south + west + east) * lobe) / (1.0 + 4.0 * lobe);
+    return vec4<f32>(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
@@ -1,32 +1,54 @@
 // vertex.rs
 
+use crate::vertex_layout::{vertex_layout, VertexLayout};
 use bytemuck::{Pod, Zeroable};
 use egui_wgpu::wgpu;
-use wgpu::util::DeviceExt;
+use rayon::prelude::*;
+
+// Below this many sides a polygon is generated on a single thread, where splitting costs more than it saves
+const PARALLEL_MIN_LEN: usize = 4096;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub struct Vertex {
     pub position: [f32; 3],
     pub color: [f32; 3],
+    pub uv: [f32; 2], // Texture coordinates, with V growing downwards as images are stored
+    pub normal: [f32; 3], // Zero on most generated shapes, which the scene shader shades flat
 }
 
 impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+    // Locations must match the `@location`s of `VertexInput` in the shaders
+    pub const LAYOUT: VertexLayout = vertex_layout!(Vertex, Vertex {
+        0 => position: Float32x3,
+        1 => color: Float32x3,
+        2 => uv: Float32x2,
+        3 => normal: Float32x3,
+    });
 
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
-        use std::mem;
+        const LAYOUT: &VertexLayout = &Vertex::LAYOUT;
+        LAYOUT.buffer_layout()
+    }
+
+    /// A vertex whose texture coordinates project the texture onto the XY plane, one copy across -1 to 1.
+    pub fn new(position: [f32; 3], color: [f32; 3]) -> Self {
+        let uv = [position[0] * 0.5 + 0.5, position[1] * -0.5 + 0.5];
+        Self::with_uv(position, color, uv)
+    }
 
-        wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &Self::ATTRIBS,
+    pub fn with_uv(position: [f32; 3], color: [f32; 3], uv: [f32; 2]) -> Self {
+        Self {
+            position,
+            color,
+            uv,
+            normal: [0.0; 3],
         }
     }
 
-    pub fn new(position: [f32; 3], color: [f32; 3]) -> Self {
-        Self { position, color }
+    /// The vertex with `normal` in place of none, for shapes lit smoothly rather than shaded flat.
+    pub fn with_normal(self, normal: [f32; 3]) -> Self {
+        Self { normal, ..self }
     }
 
     pub fn generate_cube() -> (Vec<Vertex>, Vec<u16>) {
@@ -48,7 +70,7 @@ impl Vertex {
             // Front face
             0, 1, 2, 0, 2, 3,
             // Back face
-            4, 5, 6, 4, 6, 7,
+            5, 4, 7, 5, 7, 6,
             // Left face
             4, 0, 3, 4, 3, 7,
             // Right face
@@ -62,26 +84,52 @@ impl Vertex {
         (vertices, indices)
     }
 
+    /// A white cube with four vertices per face, so each face shows the whole texture the right way up and
+    /// has its own normal.
+    pub fn generate_textured_cube() -> (Vec<Vertex>, Vec<u16>) {
+        // Each face's outward axis and the axes its texture's U and V run along, seen from outside
+        let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),  // Front
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]), // Back
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),  // Left
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),  // Right
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),    // Top
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),  // Bottom
+        ];
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+        for (normal, u, v) in faces {
+            let base = vertices.len() as u16;
+            for uv in [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]] {
+                let position = std::array::from_fn(|axis| {
+                    0.5 * normal[axis] + (uv[0] - 0.5) * u[axis] + (uv[1] - 0.5) * v[axis]
+                });
+                vertices.push(Vertex::with_uv(position, [1.0, 1.0, 1.0], uv).with_normal(normal));
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
+        (vertices, indices)
+    }
+
     pub fn generate_polygon(sides: u16, radius: f32) -> (Vec<Vertex>, Vec<u16>) {
-        let mut vertices = Vec::new();
-        let mut indices = Vec::new();
         let angle_step = 2.0 * std::f32::consts::PI / sides as f32;
 
-        vertices.push(Vertex::new([0.0, 0.0, 0.0], [0.5, 0.0, 0.5]));  // Center vertex
-
-        for i in 0..sides {
+        let mut vertices = vec![Vertex::new([0.0, 0.0, 0.0], [0.5, 0.0, 0.5])];  // Center vertex
+        vertices.par_extend((0..sides).into_par_iter().with_min_len(PARALLEL_MIN_LEN).map(|i| {
             let angle = i as f32 * angle_step;
             let x = radius * angle.cos();
             let y = radius * angle.sin();
-            vertices.push(Vertex::new([x, y, 0.0], [0.5, 0.0, 0.5]));  // Adjust color as needed
-        }
+            Vertex::new([x, y, 0.0], [0.5, 0.0, 0.5])  // Adjust color as needed
+        }));
 
-        for i in 0..sides {
-            indices.push(0);  
-            indices.push(i + 1);  
-            indices.push((i + 1) % sides + 1);  
-        }
+        let indices = (0..sides)
+            .into_par_iter()
+            .with_min_len(PARALLEL_MIN_LEN)
+            .flat_map_iter(|i| [0, i + 1, (i + 1) % sides + 1])
+            .collect();
 
         (vertices, indices)
     }
 }
+
@@ -0,0 +1,176 @@
+// vertex_layout.rs
+
+use egui_wgpu::wgpu;
+use naga::{Binding, ScalarKind, ShaderStage, TypeInner};
+
+// Attributes one layout can hold, wgpu's per-pipeline maximum
+pub const MAX_ATTRIBUTES: usize = 16;
+
+// A vertex buffer layout built from a struct's fields in a const context, usually through `vertex_layout!`.
+// Every attribute is checked as it is added: its format must be exactly the size of the field it reads and
+// fit inside the stride, and no two attributes may overlap or share a location. A layout that disagrees with
+// its struct therefore fails to compile instead of reading garbage
+#[derive(Debug, Copy, Clone)]
+pub struct VertexLayout {
+    stride: wgpu::BufferAddress,
+    step_mode: wgpu::VertexStepMode,
+    attributes: [wgpu::VertexAttribute; MAX_ATTRIBUTES],
+    len: usize,
+}
+
+impl VertexLayout {
+    pub const fn new<T>(step_mode: wgpu::VertexStepMode) -> Self {
+        let unused = wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32,
+            offset: 0,
+            shader_location: 0,
+        };
+        Self {
+            stride: size_of::<T>() as wgpu::BufferAddress,
+            step_mode,
+            attributes: [unused; MAX_ATTRIBUTES],
+            len: 0,
+        }
+    }
+
+    /// Adds an attribute reading `field_size` bytes at `offset`. Panics, at compile time when used in a
+    /// const, if the format doesn't match the field or collides with an earlier attribute.
+    pub const fn attribute(
+        mut self,
+        location: wgpu::ShaderLocation,
+        format: wgpu::VertexFormat,
+        offset: usize,
+        field_size: usize,
+    ) -> Self {
+        assert!(self.len < MAX_ATTRIBUTES, "Too many vertex attributes");
+        assert!(format.size() == field_size as u64, "Vertex format size differs from the field it reads");
+        let (start, end) = (offset as u64, offset as u64 + format.size());
+        assert!(end <= self.stride, "Vertex attribute extends past the stride");
+        let mut i = 0;
+        while i < self.len {
+            let other = self.attributes[i];
+            assert!(other.shader_location != location, "Vertex attribute location used twice");
+            assert!(
+                end <= other.offset || other.offset + other.format.size() <= start,
+                "Vertex attributes overlap"
+            );
+            i += 1;
+        }
+        self.attributes[self.len] = wgpu::VertexAttribute {
+            format,
+            offset: start,
+            shader_location: location,
+        };
+        self.len += 1;
+        self
+    }
+
+    pub const fn attributes(&self) -> &[wgpu::VertexAttribute] {
+        self.attributes.split_at(self.len).0
+    }
+
+    pub const fn buffer_layout(&'static self) -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.stride,
+            step_mode: self.step_mode,
+            attributes: self.attributes(),
+        }
+    }
+
+    /// Compares the layout with the `@location` inputs of a WGSL vertex entry point, so a shader edit that
+    /// adds or retypes an input is reported by name rather than as a pipeline validation error.
+    pub fn check_shader(&self, source: &str, entry_point: &str) -> Result<(), String> {
+        let module = naga::front::wgsl::parse_str(source).map_err(|e| e.emit_to_string(source))?;
+        self.check_module(&module, entry_point)
+    }
+
+    /// Like `check_shader`, for a module from any front end, e.g. SPIR-V.
+    pub fn check_module(&self, module: &naga::Module, entry_point: &str) -> Result<(), String> {
+        let entry = module
+            .entry_points
+            .iter()
+            .find(|entry| entry.stage == ShaderStage::Vertex && entry.name == entry_point)
+            .ok_or_else(|| format!("No vertex entry point named {entry_point}"))?;
+
+        // Inputs are either direct arguments or members of a struct argument
+        let mut inputs = Vec::new();
+        for argument in &entry.function.arguments {
+            match (&argument.binding, &module.types[argument.ty].inner) {
+                (Some(binding), _) => inputs.push((binding.clone(), argument.ty)),
+                (None, TypeInner::Struct { members, .. }) => {
+                    inputs.extend(members.iter().filter_map(|m| Some((m.binding.clone()?, m.ty))))
+                }
+                (None, _) => {}
+            }
+        }
+
+        for (binding, ty) in inputs {
+            let Binding::Location { location, .. } = binding else {
+                continue; // Builtins such as the vertex index need no buffer data
+            };
+            let Some(attribute) = self.attributes().iter().find(|a| a.shader_location == location) else {
+                return Err(format!("Shader reads @location({location}), which the vertex layout does not provide"));
+            };
+            let (kind, components) = match module.types[ty].inner {
+                TypeInner::Scalar(scalar) => (scalar.kind, 1),
+                TypeInner::Vector { size, scalar } => (scalar.kind, size as u32),
+                _ => return Err(format!("@location({location}) has a type vertex buffers cannot feed")),
+            };
+            if (kind, components) != shader_type(attribute.format) {
+                return Err(format!(
+                    "@location({location}) is {components} {kind:?} component(s) in the shader but {:?} in the layout",
+                    attribute.format
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Size of the field a projection like `|v: &Vertex| &v.position` selects, for `vertex_layout!`.
+pub const fn field_size<T, F>(_field: fn(&T) -> &F) -> usize {
+    size_of::<F>()
+}
+
+// The shader-side scalar kind and component count a vertex format is read as
+fn shader_type(format: wgpu::VertexFormat) -> (ScalarKind, u32) {
+    use wgpu::VertexFormat::*;
+    match format {
+        Uint8x2 | Uint16x2 | Uint32x2 => (ScalarKind::Uint, 2),
+        Uint32x3 => (ScalarKind::Uint, 3),
+        Uint8x4 | Uint16x4 | Uint32x4 => (ScalarKind::Uint, 4),
+        Uint32 => (ScalarKind::Uint, 1),
+        Sint8x2 | Sint16x2 | Sint32x2 => (ScalarKind::Sint, 2),
+        Sint32x3 => (ScalarKind::Sint, 3),
+        Sint8x4 | Sint16x4 | Sint32x4 => (ScalarKind::Sint, 4),
+        Sint32 => (ScalarKind::Sint, 1),
+        Float32 | Float64 => (ScalarKind::Float, 1),
+        Unorm8x2 | Snorm8x2 | Unorm16x2 | Snorm16x2 | Float16x2 | Float32x2 | Float64x2 => (ScalarKind::Float, 2),
+        Float32x3 | Float64x3 => (ScalarKind::Float, 3),
+        Unorm8x4 | Snorm8x4 | Unorm16x4 | Snorm16x4 | Float16x4 | Float32x4 | Float64x4 | Unorm10_10_10_2 => {
+            (ScalarKind::Float, 4)
+        }
+    }
+}
+
+/// Builds a `VertexLayout` for a `#[repr(C)]` struct from `location => field: Format` entries, taking each
+/// attribute's offset and size from the field itself.
+///
+/// ```ignore
+/// const LAYOUT: VertexLayout = vertex_layout!(Vertex, Vertex {
+///     0 => position: Float32x3,
+///     1 => color: Float32x3,
+/// });
+/// ```
+macro_rules! vertex_layout {
+    ($ty:ty, $step_mode:ident { $($location:literal => $field:ident: $format:ident),* $(,)? }) => {
+        $crate::vertex_layout::VertexLayout::new::<$ty>(egui_wgpu::wgpu::VertexStepMode::$step_mode)
+            $(.attribute(
+                $location,
+                egui_wgpu::wgpu::VertexFormat::$format,
+                std::mem::offset_of!($ty, $field),
+                $crate::vertex_layout::field_size(|vertex: &$ty| &vertex.$field),
+            ))*
+    };
+}
+pub(crate) use vertex_layout;
@@ -0,0 +1,309 @@
+// video_capture.rs
+
+use crate::buffer_pool::BufferPool;
+use crate::file_dialog::{FileDialogs, FileKind};
+use crate::screenshot::{self, MappingScreenshot, Screenshot, ScreenshotPixels};
+use crate::settings;
+use crate::stats::RenderStats;
+use crate::toasts::Toasts;
+use egui::Context;
+use egui_wgpu::wgpu;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+// Frames being copied or mapped at once. Past this the frame is dropped rather than waited for, so a slow
+// readback never holds up the render loop
+const IN_FLIGHT: usize = 3;
+// Frames read back but not yet written; past this the writer has fallen behind and frames are dropped
+const QUEUED: usize = 8;
+
+// Where the frames go, picked by the output file's extension
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Sink {
+    Frames, // Numbered PNGs next to the chosen file: clip-00000.png, clip-00001.png, ...
+    Ffmpeg, // Raw RGBA piped to an ffmpeg child process, which encodes the chosen file
+}
+
+impl Sink {
+    fn of(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("png") => Sink::Frames,
+            _ => Sink::Ffmpeg,
+        }
+    }
+}
+
+// A capture in progress: copies on their way off the GPU and the thread writing them out
+struct Recording {
+    recorded: Vec<Screenshot>, // Copied this frame, mapped once the frame has been submitted
+    mapping: VecDeque<MappingScreenshot>,
+    sender: SyncSender<ScreenshotPixels>,
+    started: Instant,
+    captured: u64,
+    dropped: u64,
+    stopping: bool, // No more frames are copied; the ones on their way are still written
+}
+
+/// Records the presented frames to numbered PNGs or, through ffmpeg, a video file. Each frame is copied into a
+/// pooled buffer and mapped without waiting; the render loop picks up mapped frames a few frames later and a
+/// writer thread encodes them, so capturing never stalls the frame. Frames that can't keep up are dropped.
+pub struct VideoCapture {
+    pub fps: u32, // The video's frame rate, for ffmpeg
+    path: PathBuf, // `.png` writes numbered frames; anything else is encoded by ffmpeg
+    chosen: bool,  // Picked in the save dialog; otherwise each capture gets a new file
+    recording: Option<Recording>,
+    finishing: Option<Receiver<Result<(PathBuf, u64), String>>>, // The writer, until it has flushed its queue
+}
+
+impl VideoCapture {
+    pub fn new() -> Self {
+        Self {
+            fps: 60,
+            path: default_path(),
+            chosen: false,
+            recording: None,
+            finishing: None,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.as_ref().is_some_and(|recording| !recording.stopping)
+    }
+
+    /// Whether a capture is recording or still being written, and the frames should keep coming.
+    pub fn is_active(&self) -> bool {
+        self.recording.is_some() || self.finishing.is_some()
+    }
+
+    /// Where the next capture goes, as picked in the save dialog.
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+        self.chosen = true;
+    }
+
+    /// Starts capturing, or stops and lets the writer finish in the background.
+    pub fn toggle(&mut self, toasts: &mut Toasts) {
+        if let Some(recording) = &mut self.recording {
+            if !recording.stopping {
+                log::info!("Stopping video capture: {} frames, {} dropped", recording.captured, recording.dropped);
+                recording.stopping = true;
+            }
+        } else if let Err(err) = self.start() {
+            log::warn!("Failed to start video capture: {err}");
+            toasts.error(format!("Video capture failed: {err}"));
+        }
+    }
+
+    fn start(&mut self) -> Result<(), String> {
+        // A browser can neither start processes nor write files
+        if cfg!(target_arch = "wasm32") {
+            return Err("not supported in a browser".into());
+        }
+        if self.finishing.is_some() || self.recording.is_some() {
+            return Err("the last capture is still being written".into());
+        }
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let (sender, receiver) = mpsc::sync_channel(QUEUED);
+        let (done, finishing) = mpsc::channel();
+        let (path, sink, fps) = (self.path.clone(), Sink::of(&self.path), self.fps);
+        std::thread::spawn(move || {
+            let _ = done.send(write_frames(&path, sink, fps, receiver).map(|frames| (path, frames)));
+        });
+        self.recording = Some(Recording {
+            recorded: Vec::new(),
+            mapping: VecDeque::new(),
+            sender,
+            started: Instant::now(),
+            captured: 0,
+            dropped: 0,
+            stopping: false,
+        });
+        self.finishing = Some(finishing);
+        Ok(())
+    }
+
+    /// Records a copy of the frame into `encoder` while capturing, unless too many are already on their way.
+    /// The texture needs `COPY_SRC` usage.
+    pub fn record(
+        &mut self,
+        device: &wgpu::Device,
+        pool: &mut BufferPool,
+        stats: &mut RenderStats,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) {
+        let Some(recording) = self.recording.as_mut().filter(|recording| !recording.stopping) else {
+            return;
+        };
+        if recording.mapping.len() + recording.recorded.len() >= IN_FLIGHT {
+            recording.dropped += 1;
+            return;
+        }
+        recording.recorded.push(Screenshot::record(device, pool, stats, encoder, texture));
+    }
+
+    /// Maps the copies submitted this frame and hands the ones mapped since to the writer, oldest first, without
+    /// waiting on the GPU. Reports a capture that has finished writing, or failed.
+    pub fn end_frame(&mut self, device: &wgpu::Device, toasts: &mut Toasts) {
+        if let Some(recording) = &mut self.recording {
+            recording.mapping.extend(recording.recorded.drain(..).map(Screenshot::map));
+            device.poll(wgpu::Maintain::Poll);
+            while let Some(pixels) = recording.mapping.front().and_then(MappingScreenshot::try_read) {
+                recording.mapping.pop_front();
+                let Ok(pixels) = pixels else {
+                    recording.dropped += 1;
+                    continue;
+                };
+                match recording.sender.try_send(pixels) {
+                    Ok(()) => recording.captured += 1,
+                    Err(TrySendError::Full(_)) => recording.dropped += 1,
+                    // The writer gave up; its error is reported once the frames on their way are unmapped
+                    Err(TrySendError::Disconnected(_)) => recording.stopping = true,
+                }
+            }
+            // Mapped buffers go back to the pool only once they've been read, so the last ones are waited for
+            if recording.stopping && recording.mapping.is_empty() {
+                self.recording = None;
+            }
+            return;
+        }
+        let Some(finishing) = &self.finishing else {
+            return;
+        };
+        match finishing.try_recv() {
+            Ok(Ok((path, frames))) => {
+                log::info!("Wrote {frames} frames to {}", path.display());
+                toasts.success(format!("Saved {frames} frames to {}", path.display()));
+            }
+            Ok(Err(err)) => {
+                log::warn!("Video capture failed: {err}");
+                toasts.error(format!("Video capture failed: {err}"));
+            }
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => toasts.error("Video capture failed: the writer stopped"),
+        }
+        self.finishing = None;
+        if !self.chosen {
+            self.path = default_path();
+        }
+    }
+
+    /// The Video Capture window: where the capture goes, and starting and stopping it.
+    pub fn show(&mut self, ctx: &Context, toasts: &mut Toasts, file_dialogs: &mut FileDialogs) {
+        egui::Window::new("Video Capture")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.add_enabled_ui(self.recording.is_none() && self.finishing.is_none(), |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(self.path.display().to_string());
+                        if ui.button("Choose...").clicked() {
+                            file_dialogs.save(FileKind::Video);
+                        }
+                    });
+                    ui.weak(match Sink::of(&self.path) {
+                        Sink::Frames => "Writes numbered PNG frames",
+                        Sink::Ffmpeg => "Encodes with ffmpeg, which must be on the PATH",
+                    });
+                    ui.add_enabled_ui(Sink::of(&self.path) == Sink::Ffmpeg, |ui| {
+                        ui.add(egui::Slider::new(&mut self.fps, 1..=120).text("Frame rate"));
+                    });
+                });
+                ui.separator();
+                match &self.recording {
+                    Some(recording) if !recording.stopping => {
+                        ui.colored_label(
+                            egui::Color32::from_rgb(230, 70, 70),
+                            format!(
+                                "● {:.1}s, {} frames, {} dropped",
+                                recording.started.elapsed().as_secs_f32(),
+                                recording.captured,
+                                recording.dropped
+                            ),
+                        );
+                        if ui.button("Stop").clicked() {
+                            self.toggle(toasts);
+                        }
+                    }
+                    _ => {
+                        let idle = self.recording.is_none() && self.finishing.is_none();
+                        let start = ui.add_enabled(idle, egui::Button::new("Start"));
+                        if start.on_disabled_hover_text("Writing the last capture").clicked() {
+                            self.toggle(toasts);
+                        }
+                    }
+                }
+            });
+    }
+}
+
+impl Default for VideoCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A new file in the `videos` directory next to the settings, named after the current time.
+fn default_path() -> PathBuf {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    settings::settings_path("videos").join(format!("clip-{millis}.mp4"))
+}
+
+// Runs on the writer thread until the capture stops, returning how many frames were written
+fn write_frames(path: &Path, sink: Sink, fps: u32, frames: Receiver<ScreenshotPixels>) -> Result<u64, String> {
+    let mut encoder: Option<(Child, ChildStdin, (u32, u32))> = None;
+    let mut written = 0;
+    for pixels in frames {
+        let size = (pixels.width(), pixels.height());
+        match sink {
+            Sink::Frames => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let frame = path.with_file_name(format!("{stem}-{written:05}.png"));
+                screenshot::save_png(&frame, size.0, size.1, pixels.into_rgba())?;
+            }
+            Sink::Ffmpeg => {
+                if encoder.is_none() {
+                    encoder = Some(spawn_ffmpeg(path, size, fps)?);
+                }
+                let Some((_, stdin, encoded_size)) = &mut encoder else {
+                    continue;
+                };
+                // The video keeps the size it started at; frames from after a resize are left out
+                if *encoded_size != size {
+                    continue;
+                }
+                stdin.write_all(&pixels.into_rgba()).map_err(|err| format!("ffmpeg stopped reading: {err}"))?;
+            }
+        }
+        written += 1;
+    }
+    if let Some((mut child, stdin, _)) = encoder {
+        drop(stdin); // The end of its input tells ffmpeg to finish the file
+        let status = child.wait().map_err(|err| err.to_string())?;
+        if !status.success() {
+            return Err(format!("ffmpeg exited with {status}"));
+        }
+    }
+    Ok(written)
+}
+
+fn spawn_ffmpeg(path: &Path, (width, height): (u32, u32), fps: u32) -> Result<(Child, ChildStdin, (u32, u32)), String> {
+    let mut child = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba"])
+        .args(["-s", &format!("{width}x{height}"), "-r", &fps.to_string(), "-i", "-"])
+        // Most players want 4:2:0, which needs even sizes
+        .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("couldn't start ffmpeg: {err}"))?;
+    let stdin = child.stdin.take().ok_or("ffmpeg has no input")?;
+    Ok((child, stdin, (width, height)))
+}
@@ -0,0 +1,216 @@
+// viewport_panel.rs
+
+use crate::egui_tools::EguiRenderer;
+use crate::stats::RenderStats;
+use egui::{Context, Id, Order, Rect};
+use egui_wgpu::wgpu;
+use glam::Vec2;
+
+// Where the scene was shown last frame, kept in egui's memory for the overlays that project onto it
+#[derive(Debug, Copy, Clone)]
+struct SceneArea {
+    rect: Rect,
+    hovered: bool,
+}
+
+fn scene_area_id() -> Id {
+    Id::new("scene_viewport")
+}
+
+/// Where overlays drawn over the scene project onto: the panel showing it, or else the whole window.
+pub fn scene_rect(ctx: &Context) -> Rect {
+    let area = ctx.data(|data| data.get_temp::<SceneArea>(scene_area_id()));
+    area.map_or(ctx.screen_rect(), |area| area.rect)
+}
+
+/// Whether the pointer is over a window or panel of the UI rather than the scene, wherever the scene is shown.
+pub fn pointer_over_ui(ctx: &Context) -> bool {
+    let area = ctx.data(|data| data.get_temp::<SceneArea>(scene_area_id()));
+    ctx.is_pointer_over_area() && !area.is_some_and(|area| area.hovered)
+}
+
+// The texture the scene ends up in while it's shown in the panel, and its id in egui
+struct PanelTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    texture_id: egui::TextureId,
+}
+
+/// The scene drawn into a texture and shown in the central panel of the UI, between the docked panels, rather
+/// than filling the window under them. The camera follows the panel's aspect; clicks, drags and scrolling over
+/// it reach the viewport, and the camera's keys only move it while the panel has focus.
+pub struct ViewportPanel {
+    format: wgpu::TextureFormat, // The surface's, which the last pass of the scene writes
+    target: Option<PanelTarget>,
+    rect: Option<Rect>, // Where the panel was last shown, in points
+    pixels_per_point: f32,
+    hovered: bool,
+    focused: bool,
+}
+
+impl ViewportPanel {
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            target: None,
+            rect: None,
+            pixels_per_point: 1.0,
+            hovered: false,
+            focused: true,
+        }
+    }
+
+    /// Fits the texture to the size the panel was last shown at, or frees it if `active` is false. Returns the
+    /// size the scene is drawn at this frame while it goes to the panel.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        egui: &mut EguiRenderer,
+        active: bool,
+    ) -> Option<(u32, u32)> {
+        if !active {
+            self.release(stats, egui);
+            self.rect = None;
+            self.hovered = false;
+            self.focused = true;
+            return None;
+        }
+        // Shown for the first time this frame; the scene fills the window behind the UI until then
+        let size = self.size()?;
+        let max = device.limits().max_texture_dimension_2d;
+        let size = (size.0.min(max), size.1.min(max));
+        let matches = self.target.as_ref().is_some_and(|target| {
+            let extent = target.texture.size();
+            (extent.width, extent.height) == size
+        });
+        if !matches {
+            self.resize(device, stats, egui, size);
+        }
+        Some(size)
+    }
+
+    // The panel's size in physical pixels, at least one
+    fn size(&self) -> Option<(u32, u32)> {
+        let size = self.rect?.size() * self.pixels_per_point;
+        Some(((size.x.round() as u32).max(1), (size.y.round() as u32).max(1)))
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, stats: &mut RenderStats, egui: &mut EguiRenderer, size: (u32, u32)) {
+        let previous = self.target.take().map(|target| {
+            stats.texture_destroyed(&target.texture);
+            target.texture_id
+        });
+        // egui samples it through an sRGB view, so the scene's colors come out the same on a surface without one
+        let srgb = self.format.add_srgb_suffix();
+        let view_formats = [srgb];
+        let texture = stats.create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Viewport Panel Texture"),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: if srgb == self.format { &[] } else { &view_formats },
+            },
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampled = texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(srgb),
+            ..Default::default()
+        });
+        let texture_id = match previous {
+            Some(id) => {
+                egui.update_texture(device, &sampled, wgpu::FilterMode::Linear, id);
+                id
+            }
+            None => egui.register_texture(device, &sampled, wgpu::FilterMode::Linear),
+        };
+        self.target = Some(PanelTarget {
+            texture,
+            view,
+            texture_id,
+        });
+    }
+
+    fn release(&mut self, stats: &mut RenderStats, egui: &mut EguiRenderer) {
+        if let Some(target) = self.target.take() {
+            stats.texture_destroyed(&target.texture);
+            egui.free_texture(target.texture_id);
+        }
+    }
+
+    /// What the scene's last pass draws into this frame, if it goes to the panel.
+    pub fn view(&self) -> Option<&wgpu::TextureView> {
+        self.target.as_ref().map(|target| &target.view)
+    }
+
+    pub fn texture(&self) -> Option<&wgpu::Texture> {
+        self.target.as_ref().map(|target| &target.texture)
+    }
+
+    /// The panel's top-left corner and size in the window's physical pixels, for rays through the cursor.
+    pub fn pixel_rect(&self) -> Option<(Vec2, Vec2)> {
+        let rect = self.rect.filter(|_| self.target.is_some())?;
+        let min = rect.min.to_vec2() * self.pixels_per_point;
+        let size = rect.size() * self.pixels_per_point;
+        Some((Vec2::new(min.x, min.y), Vec2::new(size.x, size.y)))
+    }
+
+    /// Whether the pointer was over the scene in the panel last frame, so presses and scrolling there are the
+    /// viewport's even though egui saw them.
+    pub fn hovered(&self) -> bool {
+        self.hovered
+    }
+
+    /// Whether the camera's keys move it: always while the scene fills the window, and otherwise once the panel
+    /// has been clicked, until something else in the UI is.
+    pub fn has_focus(&self) -> bool {
+        self.focused
+    }
+
+    /// Shows the scene in the space the docked panels leave, after they've been laid out. Does nothing but
+    /// forget the panel unless `active`.
+    pub fn show(&mut self, ctx: &Context, active: bool) {
+        if !active {
+            ctx.data_mut(|data| data.remove::<SceneArea>(scene_area_id()));
+            return;
+        }
+        let rect = egui::CentralPanel::default()
+            .frame(egui::Frame::none())
+            .show(ctx, |ui| ui.max_rect())
+            .inner;
+        self.rect = Some(rect);
+        self.pixels_per_point = ctx.pixels_per_point();
+        // An area rather than the panel's own layer, so the overlays painted over the scene stay on top of it
+        let response = egui::Area::new(scene_area_id())
+            .order(Order::Background)
+            .fixed_pos(rect.min)
+            .show(ctx, |ui| match &self.target {
+                Some(target) => {
+                    let image = egui::Image::new((target.texture_id, rect.size()));
+                    ui.add(image.sense(egui::Sense::click_and_drag()))
+                }
+                None => ui.allocate_rect(rect, egui::Sense::click_and_drag()),
+            })
+            .inner;
+        self.hovered = response.hovered();
+        if response.is_pointer_button_down_on() {
+            self.focused = true;
+        } else if ctx.input(|i| i.pointer.any_pressed()) && !self.hovered {
+            self.focused = false;
+        }
+        let area = SceneArea {
+            rect,
+            hovered: self.hovered,
+        };
+        ctx.data_mut(|data| data.insert_temp(scene_area_id(), area));
+    }
+}
@@ -0,0 +1,358 @@
+// water.rs
+
+use crate::camera::Camera;
+use crate::stats::RenderStats;
+use crate::upload::Uploads;
+use bytemuck::{Pod, Zeroable};
+use egui::Context;
+use egui_wgpu::wgpu;
+use glam::Vec3;
+
+// Laid out like `Water` in water.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct WaterUniform {
+    view_proj: [[f32; 4]; 4],
+    inverse_view_proj: [[f32; 4]; 4],
+    camera_position: [f32; 3],
+    time: f32,
+    color: [f32; 3],
+    height: f32,
+    sky_color: [f32; 3],
+    extent: f32,
+    amplitude: f32,
+    wavelength: f32,
+    speed: f32,
+    direction: f32,
+    near: f32,
+    far: f32,
+    murkiness: f32,
+    refraction: f32,
+    reflections: u32,
+    scene: u32,
+    _padding: [u32; 2],
+}
+
+// A square of water on a horizontal plane, with waves that travel while it is enabled
+#[derive(Debug, Clone)]
+pub struct Water {
+    pub enabled: bool,
+    pub height: f32,
+    pub extent: f32,       // Half the side of the square, centered on the origin
+    pub color: Vec3,       // Linear RGB of deep water
+    pub murkiness: f32,    // How quickly the scene below fades to `color` with depth
+    pub amplitude: f32,    // Of the longest wave
+    pub wavelength: f32,   // Of the longest wave; each of the others is shorter
+    pub speed: f32,        // World units per second
+    pub direction: f32,    // Radians about Y the waves travel towards
+    pub refraction: f32,   // How far the waves shift the scene below, in screen fractions
+    pub reflections: bool, // Traced in screen space; off, the water only reflects the background
+    time: f32,
+}
+
+impl Water {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            height: 0.0,
+            extent: 20.0,
+            color: Vec3::new(0.02, 0.1, 0.13),
+            murkiness: 0.4,
+            amplitude: 0.05,
+            wavelength: 2.0,
+            speed: 0.8,
+            direction: 0.5,
+            refraction: 0.02,
+            reflections: true,
+            time: 0.0,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.speed != 0.0
+    }
+
+    /// Moves the waves on by `dt`.
+    pub fn update(&mut self, dt: f32) {
+        if self.enabled {
+            self.time += dt;
+        }
+    }
+
+    /// The Water window: switches the water on and sets its plane, look and waves.
+    pub fn show(&mut self, ctx: &Context) {
+        egui::Window::new("Water")
+            .default_open(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.checkbox(&mut self.enabled, "Water");
+                ui.add_enabled_ui(self.enabled, |ui| {
+                    ui.add(egui::Slider::new(&mut self.height, -10.0..=10.0).text("Height"));
+                    ui.add(egui::Slider::new(&mut self.extent, 1.0..=500.0).logarithmic(true).text("Extent"));
+                    ui.horizontal(|ui| {
+                        let mut color = self.color.to_array();
+                        if ui.color_edit_button_rgb(&mut color).changed() {
+                            self.color = Vec3::from(color);
+                        }
+                        ui.label("Deep color");
+                    });
+                    ui.add(egui::Slider::new(&mut self.murkiness, 0.0..=5.0).text("Murkiness"));
+                    ui.add(egui::Slider::new(&mut self.refraction, 0.0..=0.1).text("Refraction"));
+                    ui.checkbox(&mut self.reflections, "Screen-space reflections");
+                    ui.separator();
+                    ui.add(egui::Slider::new(&mut self.amplitude, 0.0..=0.5).text("Wave height"));
+                    ui.add(egui::Slider::new(&mut self.wavelength, 0.1..=20.0).logarithmic(true).text("Wavelength"));
+                    ui.add(egui::Slider::new(&mut self.speed, -5.0..=5.0).text("Wave speed"));
+                    ui.horizontal(|ui| {
+                        ui.drag_angle(&mut self.direction);
+                        ui.label("Wave direction");
+                    });
+                });
+            });
+    }
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The scene as it was before the water went over it, at the scene's resolution
+struct SceneCopy {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+// Draws the water over the opaque scene with a fullscreen triangle. The scene's color is copied out first, so
+// the water can show it refracted below and reflected above; the depth is read as a texture to hide the water
+// behind the scene and to tint it by how deep it is
+pub struct WaterRenderer {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    copy: Option<SceneCopy>,
+}
+
+impl WaterRenderer {
+    pub fn new(device: &wgpu::Device, stats: &mut RenderStats, format: wgpu::TextureFormat) -> Self {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Water Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("water.wgsl").into()),
+        });
+        let uniform_buffer = stats.create_buffer(
+            device,
+            &wgpu::BufferDescriptor {
+                label: Some("Water Uniform Buffer"),
+                size: size_of::<WaterUniform>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            },
+        );
+        let texture = |binding, filterable| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Water Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(1, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture(3, false),
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Water Scene Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Water Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Water Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Tested against the scene by hand in the fragment shader, which reads the depth as a texture
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            pipeline,
+            layout,
+            uniform_buffer,
+            sampler,
+            copy: None,
+        }
+    }
+
+    /// Draws `water` over `target`, seen through `camera`, reflecting `sky_color` where the scene has nothing
+    /// to reflect. `texture` is the texture behind `target`; when it can't be copied from, the water is drawn
+    /// as a plain translucent color.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+        water: &Water,
+        sky_color: Vec3,
+        texture: &wgpu::Texture,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) {
+        let size = texture.size();
+        let stale = |copy: &SceneCopy| copy.texture.size() != size || copy.texture.format() != texture.format();
+        if self.copy.as_ref().is_none_or(stale) {
+            if let Some(copy) = self.copy.take() {
+                stats.texture_destroyed(&copy.texture);
+            }
+            self.copy = Some(self.create_copy(device, stats, texture, depth));
+        }
+        let Some(copy) = &self.copy else {
+            return;
+        };
+        let copyable = texture.usage().contains(wgpu::TextureUsages::COPY_SRC);
+        if copyable {
+            encoder.copy_texture_to_texture(texture.as_image_copy(), copy.texture.as_image_copy(), size);
+        }
+
+        let view_proj = camera.view_projection_matrix();
+        let uniform = WaterUniform {
+            view_proj: view_proj.to_cols_array_2d(),
+            inverse_view_proj: view_proj.inverse().to_cols_array_2d(),
+            camera_position: camera.position.to_array(),
+            time: water.time,
+            color: water.color.to_array(),
+            height: water.height,
+            sky_color: sky_color.to_array(),
+            extent: water.extent,
+            amplitude: water.amplitude,
+            wavelength: water.wavelength.max(0.01),
+            speed: water.speed,
+            direction: water.direction,
+            near: camera.znear,
+            far: camera.zfar,
+            murkiness: water.murkiness,
+            refraction: water.refraction,
+            reflections: water.reflections as u32,
+            scene: copyable as u32,
+            _padding: [0; 2],
+        };
+        uploads.write(device, &self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Water Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &copy.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+        stats.record_draw(3, 1);
+    }
+
+    // A texture to copy `texture` into, bound with the scene's `depth`, which is resized along with it
+    fn create_copy(
+        &self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        texture: &wgpu::Texture,
+        depth: &wgpu::TextureView,
+    ) -> SceneCopy {
+        let copy = stats.create_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Water Scene Copy"),
+                size: texture.size(),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: texture.format(),
+                usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+        );
+        let view = copy.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Water Bind Group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(depth),
+                },
+            ],
+        });
+        SceneCopy {
+            texture: copy,
+            bind_group,
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// Water surface on a horizontal plane: each pixel's view ray is intersected with the plane, the flat surface's
+// normal is bent by a few travelling waves, and the scene behind it is read back from a copy of the color and
+// depth buffers for refraction and screen-space reflection
+
+struct Water {
+    view_proj: mat4x4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+    camera_position: vec3<f32>,
+    time: f32,
+    color: vec3<f32>, // What deep water fades to
+    height: f32,
+    sky_color: vec3<f32>, // Reflected where the reflected ray leaves the screen
+    extent: f32, // Half the side of the square the water covers
+    amplitude: f32,
+    wavelength: f32,
+    speed: f32,
+    direction: f32, // Radians about Y the waves travel towards
+    near: f32,
+    far: f32,
+    murkiness: f32, // How quickly depth fades the scene below to `color`
+    refraction: f32, // How far the waves shift the scene below, in screen fractions
+    reflections: u32, // 1 to trace reflections in screen space
+    scene: u32, // 1 if `scene_color` holds the scene; 0 draws the plain color, blended
+};
+@group(0) @binding(0)
+var<uniform> water: Water;
+@group(0) @binding(1)
+var scene_color: texture_2d<f32>;
+@group(0) @binding(2)
+var scene_sampler: sampler;
+// Bound as a plain float texture, as GL can't load texels from a depth texture
+@group(0) @binding(3)
+var scene_depth: texture_2d<f32>;
+
+const REFLECTION_STEPS: i32 = 32;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) near: vec3<f32>, // Where the pixel's view ray meets the near and far planes
+    @location(1) far: vec3<f32>,
+};
+
+fn unproject(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+    let world = water.inverse_view_proj * vec4<f32>(ndc, depth, 1.0);
+    return world.xyz / world.w;
+}
+
+// A single triangle covering the screen, so no vertex buffer is needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.near = unproject(ndc, 0.0);
+    out.far = unproject(ndc, 1.0);
+    return out;
+}
+
+// Distance from the camera of a depth buffer value
+fn linear_depth(depth: f32) -> f32 {
+    return water.near * water.far / (water.far - depth * (water.far - water.near));
+}
+
+fn scene_depth_at(uv: vec2<f32>) -> f32 {
+    let size = vec2<f32>(textureDimensions(scene_depth));
+    let texel = clamp(vec2<i32>(uv * size), vec2<i32>(0), vec2<i32>(size) - 1);
+    return linear_depth(textureLoad(scene_depth, texel, 0).r);
+}
+
+// Where `clip` lands in texture coordinates
+fn screen_uv(clip: vec4<f32>) -> vec2<f32> {
+    let ndc = clip.xy / clip.w;
+    return vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+// Surface normal at `position`: four waves fanned around the main direction, each shorter and faster than
+// the last, summed as slopes
+fn wave_normal(position: vec2<f32>) -> vec3<f32> {
+    var slope = vec2<f32>(0.0);
+    var wavelength = water.wavelength;
+    var amplitude = water.amplitude;
+    for (var i = 0; i < 4; i++) {
+        let angle = water.direction + (f32(i) - 1.5) * 0.6;
+        let direction = vec2<f32>(cos(angle), sin(angle));
+        let k = 6.2831853 / wavelength;
+        let phase = k * dot(direction, position) - k * water.speed * water.time;
+        slope += direction * amplitude * k * cos(phase);
+        wavelength *= 0.6;
+        amplitude *= 0.5;
+    }
+    return normalize(vec3<f32>(-slope.x, 1.0, -slope.y));
+}
+
+// Scene color along the reflected ray, marched in world space until it passes behind the depth buffer
+fn trace_reflection(origin: vec3<f32>, direction: vec3<f32>) -> vec3<f32> {
+    var stride = 0.1;
+    var t = stride;
+    for (var i = 0; i < REFLECTION_STEPS; i++) {
+        let clip = water.view_proj * vec4<f32>(origin + direction * t, 1.0);
+        let uv = screen_uv(clip);
+        if clip.w <= 0.0 || any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+            break;
+        }
+        let behind = clip.w - scene_depth_at(uv);
+        // Passing just behind a surface hits it; far behind is a gap the buffer can't see into
+        if behind > 0.0 && behind < stride * 2.0 {
+            return textureSampleLevel(scene_color, scene_sampler, uv, 0.0).rgb;
+        }
+        stride *= 1.2;
+        t += stride;
+    }
+    return water.sky_color;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let ray = in.far - in.near;
+    let t = (water.height - in.near.y) / select(ray.y, 1e-6, abs(ray.y) < 1e-6);
+    let point = in.near + ray * t;
+    if t <= 0.0 || abs(point.x) > water.extent || abs(point.z) > water.extent {
+        discard;
+    }
+    let clip = water.view_proj * vec4<f32>(point, 1.0);
+    let uv = screen_uv(clip);
+    let bottom = scene_depth_at(uv);
+    // The scene stands in front of the water here
+    if bottom <= clip.w {
+        discard;
+    }
+
+    let normal = wave_normal(point.xz);
+    let view = normalize(point - water.camera_position);
+    // Schlick's approximation, with water's reflectance head on
+    let fresnel = 0.02 + 0.98 * pow(1.0 - max(dot(-view, normal), 0.0), 5.0);
+    if water.scene == 0u {
+        return vec4<f32>(mix(water.color, water.sky_color, fresnel), 0.8);
+    }
+
+    // Shifted by the waves, unless that lands on something in front of the water
+    var refracted_uv = uv + normal.xz * water.refraction;
+    var refracted_depth = scene_depth_at(refracted_uv);
+    if refracted_depth <= clip.w {
+        refracted_uv = uv;
+        refracted_depth = bottom;
+    }
+    let below = textureSampleLevel(scene_color, scene_sampler, refracted_uv, 0.0).rgb;
+    let thickness = refracted_depth - clip.w;
+    let refraction = mix(water.color, below, exp(-thickness * water.murkiness));
+
+    var reflection = water.sky_color;
+    if water.reflections == 1u {
+        reflection = trace_reflection(point, reflect(view, normal));
+    }
+    return vec4<f32>(mix(refraction, reflection, fresnel), 1.0);
+}
@@ -0,0 +1,45 @@
+// web.rs
+
+use std::sync::Arc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use winit::dpi::PhysicalSize;
+use winit::window::Window;
+
+/// The `<canvas>` with id `id` on the page. Panics without one, as there would be nothing to draw into.
+pub fn canvas(id: &str) -> web_sys::HtmlCanvasElement {
+    web_sys::window()
+        .and_then(|browser| browser.document())
+        .and_then(|document| document.get_element_by_id(id))
+        .and_then(|element| element.dyn_into::<web_sys::HtmlCanvasElement>().ok())
+        .unwrap_or_else(|| panic!("No canvas with id {id:?} on the page"))
+}
+
+/// Sizes `window`'s canvas to the browser window, now and whenever the browser window is resized. winit
+/// reports each change as the window resizing. Returns the size it starts at.
+pub fn fill_browser_window(window: Arc<Window>) -> (u32, u32) {
+    let size = browser_window_size();
+    let _ = window.request_inner_size(size);
+    let on_resize = Closure::<dyn FnMut()>::new(move || {
+        let _ = window.request_inner_size(browser_window_size());
+    });
+    if let Some(browser) = web_sys::window() {
+        let _ = browser.add_event_listener_with_callback("resize", on_resize.as_ref().unchecked_ref());
+    }
+    // Listens for as long as the page is open
+    on_resize.forget();
+    (size.width, size.height)
+}
+
+// The browser window's viewport in physical pixels
+fn browser_window_size() -> PhysicalSize<u32> {
+    let Some(browser) = web_sys::window() else {
+        return PhysicalSize::new(1, 1);
+    };
+    let scale = browser.device_pixel_ratio();
+    let pixels = |css: Result<JsValue, JsValue>| {
+        let css = css.ok().and_then(|css| css.as_f64()).unwrap_or(1.0);
+        (css * scale).max(1.0) as u32
+    };
+    PhysicalSize::new(pixels(browser.inner_width()), pixels(browser.inner_height()))
+}
@@ -0,0 +1,272 @@
+// world.rs
+
+use crate::bounds::{Aabb, Frustum};
+use crate::camera::Camera;
+use crate::geometry::MeshAllocation;
+use crate::material::{MaterialId, MaterialKey, Materials};
+use crate::transform::Transform;
+use glam::{Mat4, Vec3};
+use hecs::{ChangeTracker, Entity, RefMut, World};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// Longest parent chain followed when propagating transforms, so a parent cycle can't hang a frame
+const MAX_DEPTH: usize = 64;
+
+// Makes the entity's `Transform` relative to another entity's instead of to the world
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Parent(pub Entity);
+
+// What the Scene Tree window calls the entity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Name(pub String);
+
+// World-space matrix of the entity, cached by `propagate_transforms` and only recomputed once the entity's
+// transform or parent, or those of an ancestor, have changed
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GlobalTransform(pub Mat4);
+
+// Geometry in the shared arena, with its bounds in the entity's local space
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshHandle {
+    pub allocation: MeshAllocation,
+    pub bounds: Option<Aabb>,
+}
+
+// Which material draws the entity. Its shader falls back to the fallback shader while compiling
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MaterialHandle(pub MaterialId);
+
+// How a light spreads. Directional lights shine along their entity's -Z from infinitely far away; point lights
+// shine every way from their entity's position; spot lights shine along -Z within a cone. Angles are half
+// angles in radians
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    Directional,
+    Point { range: f32 },
+    Spot { range: f32, inner_angle: f32, outer_angle: f32 },
+}
+
+// A light in the scene, gathered each frame by `lighting::Lighting` into the lights the shaders read
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub enabled: bool,
+    pub casts_shadows: bool, // Nothing renders shadows yet; kept so scenes can already say which lights should
+}
+
+// Marks the `Camera` entity the viewport renders through
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ActiveCamera;
+
+// Whether the mesh was inside the view at the last `cull`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Visible(pub bool);
+
+// Keeps a mesh out of every view, wherever it is
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Hidden;
+
+// One mesh to draw this frame, as collected by `draws`
+#[derive(Debug, Clone)]
+pub struct Draw {
+    pub entity: Entity,
+    pub mesh: MeshAllocation,
+    pub material: MaterialKey,
+    pub model: Mat4,
+}
+
+impl LightKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LightKind::Directional => "Directional",
+            LightKind::Point { .. } => "Point",
+            LightKind::Spot { .. } => "Spot",
+        }
+    }
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            enabled: true,
+            casts_shadows: false,
+        }
+    }
+}
+
+/// Adds a drawable object with every component the systems below expect.
+pub fn spawn_object(world: &mut World, transform: Transform, mesh: MeshHandle, material: MaterialHandle) -> Entity {
+    world.spawn((transform, GlobalTransform(transform.matrix()), mesh, material, Visible(true)))
+}
+
+/// The entity's local transform.
+pub fn transform(world: &World, entity: Entity) -> Transform {
+    *world.get::<&Transform>(entity).expect("Entity has no Transform")
+}
+
+/// Borrows the entity's local transform for editing. Panics if it has none, which the UI's object always has.
+pub fn transform_mut(world: &World, entity: Entity) -> RefMut<'_, Transform> {
+    world.get::<&mut Transform>(entity).expect("Entity has no Transform")
+}
+
+/// The entity's world-space matrix, following its parents. Up to date even before `propagate_transforms`
+/// runs, for input handling between frames.
+pub fn world_matrix(world: &World, entity: Entity) -> Mat4 {
+    let mut matrix = Mat4::IDENTITY;
+    let mut current = Some(entity);
+    for _ in 0..MAX_DEPTH {
+        let Some(entity) = current else {
+            break;
+        };
+        if let Ok(transform) = world.get::<&Transform>(entity) {
+            matrix = transform.matrix() * matrix;
+        }
+        current = world.get::<&Parent>(entity).ok().map(|parent| parent.0);
+    }
+    matrix
+}
+
+/// World-space bounds of the entity's mesh.
+pub fn world_bounds(world: &World, entity: Entity) -> Option<Aabb> {
+    let bounds = world.get::<&MeshHandle>(entity).ok()?.bounds?;
+    Some(bounds.transformed(world_matrix(world, entity)))
+}
+
+/// The nearest mesh whose world-space bounds the ray passes through, and how far along the ray it's hit.
+/// Hidden meshes can't be picked.
+pub fn pick(world: &World, origin: Vec3, direction: Vec3) -> Option<(Entity, f32)> {
+    let mut query = world.query::<(Entity, &MeshHandle)>().without::<&Hidden>();
+    query
+        .iter()
+        .filter_map(|(entity, mesh)| {
+            let bounds = mesh.bounds?.transformed(world_matrix(world, entity));
+            Some((entity, bounds.ray_intersection(origin, direction)?))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// The camera marked `ActiveCamera`, if any.
+pub fn active_camera(world: &World) -> Option<Camera> {
+    let mut query = world.query::<&Camera>().with::<&ActiveCamera>();
+    query.iter().next().copied()
+}
+
+// Remembers every entity's `Transform` and `Parent` as of the last propagation, to find the ones changed since
+pub struct TransformTracker {
+    transforms: ChangeTracker<Transform>,
+    parents: ChangeTracker<Parent>,
+}
+
+impl TransformTracker {
+    pub fn new() -> Self {
+        Self {
+            transforms: ChangeTracker::new(),
+            parents: ChangeTracker::new(),
+        }
+    }
+
+    // Entities whose own transform or parent was added, changed or removed since the last call
+    fn changed(&mut self, world: &mut World) -> HashSet<Entity> {
+        let mut dirty = HashSet::new();
+        let mut transforms = self.transforms.track(world);
+        dirty.extend(transforms.added().map(|(entity, _)| entity));
+        dirty.extend(transforms.changed().map(|(entity, _, _)| entity));
+        dirty.extend(transforms.removed().map(|(entity, _)| entity));
+        drop(transforms);
+        let mut parents = self.parents.track(world);
+        dirty.extend(parents.added().map(|(entity, _)| entity));
+        dirty.extend(parents.changed().map(|(entity, _, _)| entity));
+        dirty.extend(parents.removed().map(|(entity, _)| entity));
+        dirty
+    }
+}
+
+impl Default for TransformTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Transform propagation system: recomputes the `GlobalTransform` of every entity whose transform or parent
+/// changed since the last call, and of all their descendants. Returns the entities it recomputed.
+pub fn propagate_transforms(world: &mut World, tracker: &mut TransformTracker) -> HashSet<Entity> {
+    let mut dirty = tracker.changed(world);
+    let mut children: HashMap<Entity, Vec<Entity>> = HashMap::new();
+    for (entity, parent) in world.query::<(Entity, &Parent)>().iter() {
+        // A despawned parent leaves nothing behind to track, so its children are checked here
+        if !world.contains(parent.0) {
+            dirty.insert(entity);
+        }
+        children.entry(parent.0).or_default().push(entity);
+    }
+    let mut pending: Vec<Entity> = dirty.iter().copied().collect();
+    while let Some(entity) = pending.pop() {
+        for &child in children.get(&entity).into_iter().flatten() {
+            if dirty.insert(child) {
+                pending.push(child);
+            }
+        }
+    }
+    dirty.retain(|&entity| {
+        let matrix = world_matrix(world, entity);
+        match world.get::<&mut GlobalTransform>(entity) {
+            Ok(mut global) => {
+                global.0 = matrix;
+                true
+            }
+            Err(_) => false,
+        }
+    });
+    dirty
+}
+
+/// Culling system: marks each mesh visible if its world-space bounds intersect `frustum`. Returns how many
+/// were culled.
+pub fn cull(world: &mut World, frustum: &Frustum) -> u32 {
+    let mut culled = 0;
+    let meshes = world.query_mut::<(&MeshHandle, &GlobalTransform, &mut Visible, Option<&Hidden>)>();
+    for (mesh, global, visible, hidden) in meshes {
+        visible.0 = hidden.is_none()
+            && mesh
+                .bounds
+                .map(|bounds| bounds.transformed(global.0))
+                .is_none_or(|bounds| frustum.intersects(&bounds));
+        culled += u32::from(!visible.0);
+    }
+    culled
+}
+
+/// Render system: the visible meshes to draw, grouped by material key so pipelines and bind groups switch as
+/// rarely as possible.
+pub fn draws(world: &World, materials: &Materials) -> Vec<Draw> {
+    collect_draws(world, materials, true)
+}
+
+/// Every mesh to draw, culled or not, for views other than the camera's such as reflection probes.
+pub fn all_draws(world: &World, materials: &Materials) -> Vec<Draw> {
+    collect_draws(world, materials, false)
+}
+
+fn collect_draws(world: &World, materials: &Materials, visible_only: bool) -> Vec<Draw> {
+    let query = world.query::<(Entity, &MeshHandle, &MaterialHandle, &GlobalTransform, &Visible)>();
+    let mut draws: Vec<Draw> = query
+        .without::<&Hidden>()
+        .iter()
+        .filter(|(_, _, _, _, visible)| visible.0 || !visible_only)
+        .map(|(entity, mesh, material, global, _)| Draw {
+            entity,
+            mesh: mesh.allocation.clone(),
+            material: materials.key(material.0),
+            model: global.0,
+        })
+        .collect();
+    draws.sort_by_key(|draw| (draw.material, draw.entity));
+    draws
+}
@@ -0,0 +1,732 @@
+// xr.rs
+
+use crate::camera::Camera;
+use crate::capabilities::Capabilities;
+use crate::stats::RenderStats;
+use crate::toasts::Toasts;
+use crate::upload::Uploads;
+use egui_wgpu::wgpu;
+use glam::Vec3;
+
+// The device a headset drives, from the OpenXR runtime's choice of GPU. The window's surface is created from the
+// same instance, so the desktop view keeps drawing alongside the headset
+pub struct XrDevice {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub capabilities: Capabilities,
+    pub session: XrSession,
+}
+
+// Experimental VR through OpenXR: every frame the scene's meshes are drawn once per eye into the headset's
+// swapchain, and the window mirrors the headset's view with the UI over it. The headset stands where the viewport
+// camera is, so the desktop controls move the player around. A no-op unless built with the `xr` feature and
+// started with `--xr`
+pub struct XrSession {
+    state: Option<runtime::State>, // None on the desktop, or once the runtime stopped the session
+}
+
+impl XrSession {
+    pub fn new() -> Self {
+        Self { state: None }
+    }
+
+    /// Whether `--xr` is among `args`.
+    pub fn requested(args: &[String]) -> bool {
+        args.iter().skip(1).any(|arg| arg == "--xr")
+    }
+
+    /// Creates the wgpu instance, adapter and device through the OpenXR runtime, with a session on them.
+    /// Fails without the `xr` feature, a runtime or a headset.
+    pub fn create_device() -> Result<XrDevice, String> {
+        runtime::State::create()
+    }
+
+    /// Whether the headset is showing this session's frames.
+    pub fn is_running(&self) -> bool {
+        self.state.as_ref().is_some_and(runtime::State::is_running)
+    }
+
+    /// Whether the frame begun last wants the eyes drawn.
+    pub fn is_rendering(&self) -> bool {
+        self.state.as_ref().is_some_and(runtime::State::is_rendering)
+    }
+
+    /// Creates the swapchain the eyes are drawn into in the window's `format`, with a camera group per eye
+    /// sharing `lights`.
+    pub fn attach(
+        &mut self,
+        device: &wgpu::Device,
+        stats: &mut RenderStats,
+        camera_layout: &wgpu::BindGroupLayout,
+        lights: &wgpu::Buffer,
+        format: wgpu::TextureFormat,
+        toasts: &mut Toasts,
+    ) {
+        self.run(toasts, |state| state.attach(device, stats, camera_layout, lights, format));
+    }
+
+    /// Waits for the headset's next frame and locates the eyes in it, placed relative to `rig`. Returns the
+    /// camera the window mirrors the headset through, if the headset is showing the frame.
+    pub fn begin_frame(&mut self, rig: &Camera, toasts: &mut Toasts) -> Option<Camera> {
+        self.run(toasts, |state| state.begin_frame(rig)).flatten()
+    }
+
+    /// Draws the frame begun last into both eyes, each cleared to `background` and drawn with the bundles
+    /// `record` returns for the eye's camera group. Returns whether the eyes were drawn.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        uploads: &mut Uploads,
+        encoder: &mut wgpu::CommandEncoder,
+        background: Vec3,
+        record: impl FnMut(&wgpu::BindGroup) -> Vec<wgpu::RenderBundle>,
+        toasts: &mut Toasts,
+    ) -> bool {
+        self.run(toasts, |state| state.render(device, uploads, encoder, background, record)).unwrap_or(false)
+    }
+
+    /// Hands the frame begun last to the headset. Call once the encoder `render` drew into was submitted.
+    pub fn end_frame(&mut self, toasts: &mut Toasts) {
+        self.run(toasts, runtime::State::end_frame);
+    }
+
+    // Runs `step` on a running session, ending it if the runtime failed or asked the session to exit
+    fn run<T>(
+        &mut self,
+        toasts: &mut Toasts,
+        step: impl FnOnce(&mut runtime::State) -> Result<T, String>,
+    ) -> Option<T> {
+        let result = step(self.state.as_mut()?);
+        match result {
+            Ok(value) if !self.state.as_ref().is_some_and(runtime::State::is_exiting) => Some(value),
+            Ok(_) => {
+                self.state = None;
+                toasts.info("VR session ended; the window keeps drawing");
+                None
+            }
+            Err(err) => {
+                self.state = None;
+                log::warn!("VR stopped: {err}");
+                toasts.error(format!("VR stopped: {err}"));
+                None
+            }
+        }
+    }
+}
+
+impl Default for XrSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "xr")]
+mod runtime {
+    use super::{XrDevice, XrSession};
+    use crate::camera::{off_center_projection, Camera, CameraUniform};
+    use crate::capabilities::Capabilities;
+    use crate::depth::DEPTH_FORMAT;
+    use crate::stats::RenderStats;
+    use crate::upload::Uploads;
+    use ash::vk::{self, Handle};
+    use egui_wgpu::wgpu;
+    use egui_wgpu::wgpu::hal;
+    use glam::{Mat4, Quat, Vec3};
+    use openxr as xr;
+    use std::ffi::c_char;
+
+    type Vulkan = hal::api::Vulkan;
+
+    const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
+
+    const VULKAN_VERSION: u32 = vk::API_VERSION_1_1;
+
+    // The Vulkan format of the window's surface format. Eyes take it so the scene's bundles draw into them
+    fn swapchain_format(format: wgpu::TextureFormat) -> Option<vk::Format> {
+        match format {
+            wgpu::TextureFormat::Bgra8UnormSrgb => Some(vk::Format::B8G8R8A8_SRGB),
+            wgpu::TextureFormat::Rgba8UnormSrgb => Some(vk::Format::R8G8B8A8_SRGB),
+            _ => None,
+        }
+    }
+
+    // A session on the runtime, with what it needs between frames. Fields drop in order, so the swapchain goes
+    // before the session and the session before the instance
+    pub struct State {
+        eyes: Option<Eyes>,
+        frame: Option<Frame>,
+        space: xr::Space, // The headset's seated space, its origin where the head was when the session began
+        frame_stream: xr::FrameStream<xr::Vulkan>,
+        frame_waiter: xr::FrameWaiter,
+        session: xr::Session<xr::Vulkan>,
+        instance: xr::Instance,
+        blend_mode: xr::EnvironmentBlendMode,
+        size: (u32, u32), // Of each eye, as the runtime recommends
+        events: xr::EventDataBuffer,
+        running: bool,
+        exiting: bool,
+    }
+
+    // The swapchain the eyes are drawn into, one layer per eye, and what they are drawn with
+    struct Eyes {
+        views: Vec<[wgpu::TextureView; 2]>, // Per swapchain image, a view of each eye's layer
+        _textures: Vec<wgpu::Texture>,
+        swapchain: xr::Swapchain<xr::Vulkan>,
+        depth_view: wgpu::TextureView, // Shared by the eyes, which are drawn one after the other
+        cameras: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+    }
+
+    // A frame between `begin_frame` and `end_frame`
+    struct Frame {
+        state: xr::FrameState,
+        views: Vec<xr::View>, // Empty when the headset isn't showing the frame
+        rig: Mat4,
+        depth_range: (f32, f32),
+        acquired: bool, // A swapchain image was acquired, to be released before the frame ends
+    }
+
+    fn xr_error(err: xr::sys::Result) -> String {
+        format!("OpenXR error {err}")
+    }
+
+    fn vk_error(err: i32) -> String {
+        format!("Vulkan error {}", vk::Result::from_raw(err))
+    }
+
+    impl State {
+        pub fn create() -> Result<XrDevice, String> {
+            let entry = unsafe { xr::Entry::load() }.map_err(|err| format!("no OpenXR loader: {err}"))?;
+            if !entry.enumerate_extensions().map_err(xr_error)?.khr_vulkan_enable2 {
+                return Err("the OpenXR runtime can't drive Vulkan".into());
+            }
+            let mut extensions = xr::ExtensionSet::default();
+            extensions.khr_vulkan_enable2 = true;
+            let instance = entry
+                .create_instance(
+                    &xr::ApplicationInfo {
+                        application_name: "winit-egui-wgpu",
+                        engine_name: "winit-egui-wgpu",
+                        ..Default::default()
+                    },
+                    &extensions,
+                    &[],
+                )
+                .map_err(xr_error)?;
+            let system = instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY).map_err(xr_error)?;
+            let blend_mode = *instance
+                .enumerate_environment_blend_modes(system, VIEW_TYPE)
+                .map_err(xr_error)?
+                .first()
+                .ok_or("the headset has no blend modes")?;
+            let requirements = instance.graphics_requirements::<xr::Vulkan>(system).map_err(xr_error)?;
+            if requirements.min_api_version_supported > xr::Version::new(1, 1, 0) {
+                return Err(format!("the runtime needs Vulkan {}", requirements.min_api_version_supported));
+            }
+            let size = instance
+                .enumerate_view_configuration_views(system, VIEW_TYPE)
+                .map_err(xr_error)?
+                .first()
+                .map(|view| (view.recommended_image_rect_width, view.recommended_image_rect_height))
+                .ok_or("the headset has no views")?;
+
+            // The runtime creates the Vulkan instance and device, with the extensions it needs on top of wgpu's
+            let vk_entry = unsafe { ash::Entry::load() }.map_err(|err| format!("no Vulkan loader: {err}"))?;
+            let get_instance_proc_addr = unsafe {
+                std::mem::transmute::<vk::PFN_vkGetInstanceProcAddr, xr::sys::platform::VkGetInstanceProcAddr>(
+                    vk_entry.static_fn().get_instance_proc_addr,
+                )
+            };
+            let flags = wgpu::InstanceFlags::empty();
+            let instance_extensions = hal::vulkan::Instance::desired_extensions(&vk_entry, VULKAN_VERSION, flags)
+                .map_err(|err| err.to_string())?;
+            let names: Vec<*const c_char> = instance_extensions.iter().map(|name| name.as_ptr()).collect();
+            let application = vk::ApplicationInfo::builder().api_version(VULKAN_VERSION);
+            let create_info =
+                vk::InstanceCreateInfo::builder().application_info(&application).enabled_extension_names(&names);
+            let raw_instance = unsafe {
+                instance.create_vulkan_instance(system, get_instance_proc_addr, &*create_info as *const _ as *const _)
+            }
+            .map_err(xr_error)?
+            .map_err(vk_error)?;
+            let vk_instance =
+                unsafe { ash::Instance::load(vk_entry.static_fn(), vk::Instance::from_raw(raw_instance as _)) };
+            let physical_device = vk::PhysicalDevice::from_raw(
+                unsafe { instance.vulkan_graphics_device(system, raw_instance) }.map_err(xr_error)? as _,
+            );
+            let queue_family = unsafe { vk_instance.get_physical_device_queue_family_properties(physical_device) }
+                .iter()
+                .position(|family| family.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+                .ok_or("the headset's GPU has no graphics queue")? as u32;
+
+            let hal_instance = unsafe {
+                hal::vulkan::Instance::from_raw(
+                    vk_entry,
+                    vk_instance.clone(),
+                    VULKAN_VERSION,
+                    0,
+                    None,
+                    instance_extensions,
+                    flags,
+                    false,
+                    None,
+                )
+            }
+            .map_err(|err| err.to_string())?;
+            let exposed = hal_instance.expose_adapter(physical_device).ok_or("wgpu can't use the headset's GPU")?;
+            let wgpu_instance = unsafe { wgpu::Instance::from_hal::<Vulkan>(hal_instance) };
+            let adapter = unsafe { wgpu_instance.create_adapter_from_hal(exposed) };
+            let capabilities = Capabilities::negotiate(&adapter);
+            let descriptor = capabilities.device_descriptor();
+            let features = descriptor.required_features;
+            let (open, raw_device) = unsafe {
+                adapter.as_hal::<Vulkan, _, _>(|hal_adapter| {
+                    let hal_adapter = hal_adapter.ok_or("the headset's adapter isn't Vulkan")?;
+                    let enabled = hal_adapter.required_device_extensions(features);
+                    let mut device_features = hal_adapter.physical_device_features(&enabled, features);
+                    let names: Vec<*const c_char> = enabled.iter().map(|name| name.as_ptr()).collect();
+                    let queues = [vk::DeviceQueueCreateInfo::builder()
+                        .queue_family_index(queue_family)
+                        .queue_priorities(&[1.0])
+                        .build()];
+                    let info = device_features
+                        .add_to_device_create_builder(
+                            vk::DeviceCreateInfo::builder().queue_create_infos(&queues).enabled_extension_names(&names),
+                        )
+                        .build();
+                    let raw_device = instance
+                        .create_vulkan_device(
+                            system,
+                            get_instance_proc_addr,
+                            physical_device.as_raw() as _,
+                            &info as *const _ as *const _,
+                        )
+                        .map_err(xr_error)?
+                        .map_err(vk_error)?;
+                    let device = ash::Device::load(vk_instance.fp_v1_0(), vk::Device::from_raw(raw_device as _));
+                    let open = hal_adapter
+                        .device_from_raw(device, true, &enabled, features, queue_family, 0)
+                        .map_err(|err| err.to_string())?;
+                    Ok::<_, String>((open, raw_device))
+                })
+            }?;
+            let (device, queue) =
+                unsafe { adapter.create_device_from_hal(open, &descriptor, None) }.map_err(|err| err.to_string())?;
+
+            let (session, frame_waiter, frame_stream) = unsafe {
+                instance.create_session::<xr::Vulkan>(
+                    system,
+                    &xr::vulkan::SessionCreateInfo {
+                        instance: raw_instance,
+                        physical_device: physical_device.as_raw() as _,
+                        device: raw_device,
+                        queue_family_index: queue_family,
+                        queue_index: 0,
+                    },
+                )
+            }
+            .map_err(xr_error)?;
+            let space = session
+                .create_reference_space(xr::ReferenceSpaceType::LOCAL, xr::Posef::IDENTITY)
+                .map_err(xr_error)?;
+            let properties = instance.properties().map_err(xr_error)?;
+            log::info!("VR through {} {}", properties.runtime_name, properties.runtime_version);
+            let state = State {
+                eyes: None,
+                frame: None,
+                space,
+                frame_stream,
+                frame_waiter,
+                session,
+                instance,
+                blend_mode,
+                size,
+                events: xr::EventDataBuffer::new(),
+                running: false,
+                exiting: false,
+            };
+            Ok(XrDevice {
+                instance: wgpu_instance,
+                adapter,
+                device,
+                queue,
+                capabilities,
+                session: XrSession { state: Some(state) },
+            })
+        }
+
+        pub fn is_running(&self) -> bool {
+            self.running
+        }
+
+        pub fn is_rendering(&self) -> bool {
+            self.frame.as_ref().is_some_and(|frame| !frame.views.is_empty())
+        }
+
+        pub fn is_exiting(&self) -> bool {
+            self.exiting
+        }
+
+        pub fn attach(
+            &mut self,
+            device: &wgpu::Device,
+            stats: &mut RenderStats,
+            camera_layout: &wgpu::BindGroupLayout,
+            lights: &wgpu::Buffer,
+            format: wgpu::TextureFormat,
+        ) -> Result<(), String> {
+            let supported = self.session.enumerate_swapchain_formats().map_err(xr_error)?;
+            let swapchain_format = swapchain_format(format)
+                .filter(|vk_format| supported.contains(&(vk_format.as_raw() as u32)))
+                .ok_or_else(|| format!("the headset can't show the window's {format:?} format"))?;
+            let (width, height) = self.size;
+            let swapchain = self
+                .session
+                .create_swapchain(&xr::SwapchainCreateInfo {
+                    create_flags: xr::SwapchainCreateFlags::EMPTY,
+                    usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT,
+                    format: swapchain_format.as_raw() as u32,
+                    sample_count: 1,
+                    width,
+                    height,
+                    face_count: 1,
+                    array_size: 2,
+                    mip_count: 1,
+                })
+                .map_err(xr_error)?;
+            let descriptor = wgpu::TextureDescriptor {
+                label: Some("XR Swapchain Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 2,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            };
+            let textures: Vec<wgpu::Texture> = swapchain
+                .enumerate_images()
+                .map_err(xr_error)?
+                .into_iter()
+                .map(|image| unsafe {
+                    let raw = hal::vulkan::Device::texture_from_raw(
+                        vk::Image::from_raw(image),
+                        &hal::TextureDescriptor {
+                            label: descriptor.label,
+                            size: descriptor.size,
+                            mip_level_count: 1,
+                            sample_count: 1,
+                            dimension: descriptor.dimension,
+                            format: descriptor.format,
+                            usage: hal::TextureUses::COLOR_TARGET,
+                            memory_flags: hal::MemoryFlags::empty(),
+                            view_formats: Vec::new(),
+                        },
+                        // The runtime owns the images; a drop guard keeps wgpu from destroying them
+                        Some(Box::new(())),
+                    );
+                    device.create_texture_from_hal::<Vulkan>(raw, &descriptor)
+                })
+                .collect();
+            let views = textures
+                .iter()
+                .map(|texture| {
+                    [0, 1].map(|layer| {
+                        texture.create_view(&wgpu::TextureViewDescriptor {
+                            label: Some("XR Eye View"),
+                            dimension: Some(wgpu::TextureViewDimension::D2),
+                            base_array_layer: layer,
+                            array_layer_count: Some(1),
+                            ..Default::default()
+                        })
+                    })
+                })
+                .collect();
+            let depth = stats.create_texture(
+                device,
+                &wgpu::TextureDescriptor {
+                    label: Some("XR Depth Texture"),
+                    format: DEPTH_FORMAT,
+                    size: wgpu::Extent3d {
+                        depth_or_array_layers: 1,
+                        ..descriptor.size
+                    },
+                    ..descriptor
+                },
+            );
+            let cameras = (0..2)
+                .map(|_| {
+                    let buffer = stats.create_buffer(
+                        device,
+                        &wgpu::BufferDescriptor {
+                            label: Some("XR Camera Buffer"),
+                            size: size_of::<CameraUniform>() as wgpu::BufferAddress,
+                            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                            mapped_at_creation: false,
+                        },
+                    );
+                    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("XR Camera Bind Group"),
+                        layout: camera_layout,
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: buffer.as_entire_binding(),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 1,
+                                resource: lights.as_entire_binding(),
+                            },
+                        ],
+                    });
+                    (buffer, bind_group)
+                })
+                .collect();
+            self.eyes = Some(Eyes {
+                views,
+                _textures: textures,
+                swapchain,
+                depth_view: depth.create_view(&wgpu::TextureViewDescriptor::default()),
+                cameras,
+            });
+            Ok(())
+        }
+
+        // Begins and ends the session as the runtime asks
+        fn poll_events(&mut self) -> Result<(), String> {
+            while let Some(event) = self.instance.poll_event(&mut self.events).map_err(xr_error)? {
+                match event {
+                    xr::Event::SessionStateChanged(change) => match change.state() {
+                        xr::SessionState::READY => {
+                            self.session.begin(VIEW_TYPE).map_err(xr_error)?;
+                            self.running = true;
+                        }
+                        xr::SessionState::STOPPING => {
+                            self.session.end().map_err(xr_error)?;
+                            self.running = false;
+                        }
+                        xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING => self.exiting = true,
+                        _ => {}
+                    },
+                    xr::Event::InstanceLossPending(_) => self.exiting = true,
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+
+        pub fn begin_frame(&mut self, rig: &Camera) -> Result<Option<Camera>, String> {
+            // A frame the render loop gave up on is still owed to the runtime
+            self.end_frame()?;
+            self.poll_events()?;
+            if !self.running || self.exiting || self.eyes.is_none() {
+                return Ok(None);
+            }
+            let state = self.frame_waiter.wait().map_err(xr_error)?;
+            self.frame_stream.begin().map_err(xr_error)?;
+            let views = if state.should_render {
+                let (_, views) = self
+                    .session
+                    .locate_views(VIEW_TYPE, state.predicted_display_time, &self.space)
+                    .map_err(xr_error)?;
+                views
+            } else {
+                Vec::new()
+            };
+            let rig_matrix = rig_matrix(rig);
+            // The window looks out from between the eyes
+            let head = views.first().zip(views.get(1)).map(|(left, right)| {
+                let left = rig_matrix * pose_matrix(left.pose);
+                let (_, rotation, left_position) = left.to_scale_rotation_translation();
+                let right_position = (rig_matrix * pose_matrix(right.pose)).w_axis.truncate();
+                let position = (left_position + right_position) / 2.0;
+                Camera {
+                    position,
+                    target: position + rotation * Vec3::NEG_Z,
+                    up: rotation * Vec3::Y,
+                    ..*rig
+                }
+            });
+            self.frame = Some(Frame {
+                state,
+                views,
+                rig: rig_matrix,
+                depth_range: (rig.znear, rig.zfar),
+                acquired: false,
+            });
+            Ok(head)
+        }
+
+        pub fn render(
+            &mut self,
+            device: &wgpu::Device,
+            uploads: &mut Uploads,
+            encoder: &mut wgpu::CommandEncoder,
+            background: Vec3,
+            mut record: impl FnMut(&wgpu::BindGroup) -> Vec<wgpu::RenderBundle>,
+        ) -> Result<bool, String> {
+            let (Some(frame), Some(eyes)) = (&mut self.frame, &mut self.eyes) else {
+                return Ok(false);
+            };
+            if frame.views.len() != 2 {
+                return Ok(false);
+            }
+            let image = eyes.swapchain.acquire_image().map_err(xr_error)? as usize;
+            frame.acquired = true;
+            eyes.swapchain.wait_image(xr::Duration::INFINITE).map_err(xr_error)?;
+            let eyes_drawn = frame.views.iter().zip(&eyes.cameras).zip(&eyes.views[image]);
+            for ((view, (buffer, camera_group)), target) in eyes_drawn {
+                let eye = frame.rig * pose_matrix(view.pose);
+                let fov = view.fov;
+                let tangents = (fov.angle_left.tan(), fov.angle_right.tan(), fov.angle_up.tan(), fov.angle_down.tan());
+                let (near, far) = frame.depth_range;
+                let uniform = CameraUniform {
+                    view_proj: (off_center_projection(tangents, near, far) * eye.inverse()).to_cols_array_2d(),
+                    position: eye.w_axis.to_array(),
+                };
+                uploads.write(device, buffer, 0, bytemuck::bytes_of(&uniform));
+                let bundles = record(camera_group);
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("XR Eye Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color {
+                                r: background.x as f64,
+                                g: background.y as f64,
+                                b: background.z as f64,
+                                a: 1.0,
+                            }),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &eyes.depth_view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
+                pass.execute_bundles(&bundles);
+            }
+            Ok(true)
+        }
+
+        pub fn end_frame(&mut self) -> Result<(), String> {
+            let Some(frame) = self.frame.take() else {
+                return Ok(());
+            };
+            let time = frame.state.predicted_display_time;
+            let Some(eyes) = self.eyes.as_mut().filter(|_| frame.acquired) else {
+                return self.frame_stream.end(time, self.blend_mode, &[]).map_err(xr_error);
+            };
+            eyes.swapchain.release_image().map_err(xr_error)?;
+            let (width, height) = self.size;
+            let rect = xr::Rect2Di {
+                offset: xr::Offset2Di { x: 0, y: 0 },
+                extent: xr::Extent2Di {
+                    width: width as i32,
+                    height: height as i32,
+                },
+            };
+            let views = [0, 1].map(|eye| {
+                xr::CompositionLayerProjectionView::new()
+                    .pose(frame.views[eye].pose)
+                    .fov(frame.views[eye].fov)
+                    .sub_image(
+                        xr::SwapchainSubImage::new()
+                            .swapchain(&eyes.swapchain)
+                            .image_array_index(eye as u32)
+                            .image_rect(rect),
+                    )
+            });
+            let layer = xr::CompositionLayerProjection::new().space(&self.space).views(&views);
+            self.frame_stream.end(time, self.blend_mode, &[&layer]).map_err(xr_error)
+        }
+    }
+
+    // Where the headset's tracking space sits in the world: at the camera, turned to face where it looks
+    fn rig_matrix(camera: &Camera) -> Mat4 {
+        let forward = camera.target - camera.position;
+        Mat4::from_rotation_translation(Quat::from_rotation_y(f32::atan2(-forward.x, -forward.z)), camera.position)
+    }
+
+    fn pose_matrix(pose: xr::Posef) -> Mat4 {
+        let (rotation, position) = (pose.orientation, pose.position);
+        Mat4::from_rotation_translation(
+            Quat::from_xyzw(rotation.x, rotation.y, rotation.z, rotation.w),
+            Vec3::new(position.x, position.y, position.z),
+        )
+    }
+}
+
+// Without the `xr` feature there is never a session, so none of these can be called
+#[cfg(not(feature = "xr"))]
+mod runtime {
+    use super::XrDevice;
+    use crate::camera::Camera;
+    use crate::stats::RenderStats;
+    use crate::upload::Uploads;
+    use egui_wgpu::wgpu;
+    use glam::Vec3;
+
+    pub enum State {}
+
+    impl State {
+        pub fn create() -> Result<XrDevice, String> {
+            Err("built without the `xr` feature".into())
+        }
+
+        pub fn is_running(&self) -> bool {
+            match *self {}
+        }
+
+        pub fn is_rendering(&self) -> bool {
+            match *self {}
+        }
+
+        pub fn is_exiting(&self) -> bool {
+            match *self {}
+        }
+
+        pub fn attach(
+            &mut self,
+            _: &wgpu::Device,
+            _: &mut RenderStats,
+            _: &wgpu::BindGroupLayout,
+            _: &wgpu::Buffer,
+            _: wgpu::TextureFormat,
+        ) -> Result<(), String> {
+            match *self {}
+        }
+
+        pub fn begin_frame(&mut self, _: &Camera) -> Result<Option<Camera>, String> {
+            match *self {}
+        }
+
+        pub fn render(
+            &mut self,
+            _: &wgpu::Device,
+            _: &mut Uploads,
+            _: &mut wgpu::CommandEncoder,
+            _: Vec3,
+            _: impl FnMut(&wgpu::BindGroup) -> Vec<wgpu::RenderBundle>,
+        ) -> Result<bool, String> {
+            match *self {}
+        }
+
+        pub fn end_frame(&mut self) -> Result<(), String> {
+            match *self {}
+        }
+    }
+}
@@ -0,0 +1,281 @@
+// compressed_texture.rs
+
+// Decodes hand-built 4x4 blocks of every BC format and checks them against texels worked out from the format
+// specifications, and reads minimal DDS and KTX2 files, whole and cut short
+
+use egui_wgpu::wgpu::TextureFormat as F;
+use winit_egui_wgpu::compressed_texture::{parse_dds, parse_ktx2, CompressedImage};
+
+// One 4x4 image of `block`, decoded
+fn decode(format: F, opaque: bool, block: &[u8]) -> Vec<u8> {
+    let image = CompressedImage {
+        format,
+        width: 4,
+        height: 4,
+        levels: vec![block.to_vec()],
+        opaque,
+    };
+    image.decoded_levels().remove(0)
+}
+
+fn texel(pixels: &[u8], index: usize) -> [u8; 4] {
+    pixels[index * 4..index * 4 + 4].try_into().unwrap()
+}
+
+// Half-float bits of a decoded BC6H texel
+fn half_texel(pixels: &[u8], index: usize) -> [u16; 4] {
+    std::array::from_fn(|channel| {
+        let start = index * 8 + channel * 2;
+        u16::from_le_bytes([pixels[start], pixels[start + 1]])
+    })
+}
+
+// A 128-bit block with each `(first bit, value)` written from its lowest bit up
+fn bits(fields: &[(u32, u128)]) -> Vec<u8> {
+    fields.iter().fold(0u128, |block, &(first, value)| block | value << first).to_le_bytes().to_vec()
+}
+
+// A BC1 color block: two RGB565 endpoints and a 2-bit index per texel
+fn bc1_block(c0: u16, c1: u16, indices: [u32; 16]) -> Vec<u8> {
+    let packed = indices.iter().enumerate().fold(0u32, |packed, (i, &index)| packed | index << (i * 2));
+    [c0.to_le_bytes(), c1.to_le_bytes()].concat().into_iter().chain(packed.to_le_bytes()).collect()
+}
+
+// A BC4 channel block: two endpoints and a 3-bit index per texel
+fn bc4_block(a0: u8, a1: u8, indices: [u64; 16]) -> Vec<u8> {
+    let packed = indices.iter().enumerate().fold(0u64, |packed, (i, &index)| packed | index << (i * 3));
+    [a0, a1].into_iter().chain(packed.to_le_bytes()[..6].iter().copied()).collect()
+}
+
+fn indices<const N: usize>(first: &[(usize, u64)]) -> [u64; N] {
+    let mut indices = [0; N];
+    for &(texel, index) in first {
+        indices[texel] = index;
+    }
+    indices
+}
+
+#[test]
+fn bc1_four_colors() {
+    let mut map = [0; 16];
+    map[1..4].copy_from_slice(&[1, 2, 3]);
+    let pixels = decode(F::Bc1RgbaUnorm, false, &bc1_block(0xF800, 0x001F, map));
+    assert_eq!(texel(&pixels, 0), [255, 0, 0, 255]);
+    assert_eq!(texel(&pixels, 1), [0, 0, 255, 255]);
+    assert_eq!(texel(&pixels, 2), [170, 0, 85, 255]);
+    assert_eq!(texel(&pixels, 3), [85, 0, 170, 255]);
+}
+
+#[test]
+fn bc1_three_colors_and_transparent_black() {
+    let mut map = [0; 16];
+    map[1..4].copy_from_slice(&[1, 2, 3]);
+    let block = bc1_block(0x001F, 0xF800, map);
+    let pixels = decode(F::Bc1RgbaUnorm, false, &block);
+    assert_eq!(texel(&pixels, 2), [128, 0, 128, 255]);
+    assert_eq!(texel(&pixels, 3), [0, 0, 0, 0]);
+    // BC1 without alpha, as KTX2's BC1_RGB formats are, keeps the black opaque
+    let pixels = decode(F::Bc1RgbaUnorm, true, &block);
+    assert_eq!(texel(&pixels, 3), [0, 0, 0, 255]);
+}
+
+#[test]
+fn bc2_explicit_alpha() {
+    let alpha = (0..16u64).fold(0u64, |packed, i| packed | i << (i * 4));
+    let block: Vec<u8> = alpha.to_le_bytes().into_iter().chain(bc1_block(0xFFFF, 0, [0; 16])).collect();
+    let pixels = decode(F::Bc2RgbaUnorm, false, &block);
+    for i in 0..16 {
+        assert_eq!(texel(&pixels, i), [255, 255, 255, i as u8 * 17]);
+    }
+}
+
+#[test]
+fn bc3_interpolated_alpha() {
+    let alpha = bc4_block(255, 0, indices(&[(1, 2), (2, 1), (3, 7)]));
+    let block: Vec<u8> = alpha.into_iter().chain(bc1_block(0xFFFF, 0, [0; 16])).collect();
+    let pixels = decode(F::Bc3RgbaUnorm, false, &block);
+    let alphas: Vec<u8> = (0..4).map(|i| texel(&pixels, i)[3]).collect();
+    // Eight values from 255 down to 0 in sevenths, rounded
+    assert_eq!(alphas, [255, 219, 0, 36]);
+}
+
+#[test]
+fn bc4_six_values_with_zero_and_one() {
+    let pixels = decode(F::Bc4RUnorm, false, &bc4_block(0, 255, indices(&[(1, 2), (2, 6), (3, 7)])));
+    assert_eq!(texel(&pixels, 0), [0, 0, 0, 255]);
+    assert_eq!(texel(&pixels, 1), [51, 0, 0, 255]);
+    assert_eq!(texel(&pixels, 2), [0, 0, 0, 255]);
+    assert_eq!(texel(&pixels, 3), [255, 0, 0, 255]);
+}
+
+#[test]
+fn bc4_signed() {
+    let pixels = decode(F::Bc4RSnorm, false, &bc4_block(0x81, 0x7F, indices(&[(1, 2), (2, 6), (3, 7)])));
+    let red: Vec<i8> = (0..4).map(|i| texel(&pixels, i)[0] as i8).collect();
+    assert_eq!(red, [-127, -76, -127, 127]);
+    assert_eq!(texel(&pixels, 0)[1..], [0, 0, 127]);
+}
+
+#[test]
+fn bc5_two_channels() {
+    let block = [bc4_block(0, 255, [0; 16]), bc4_block(255, 0, [0; 16])].concat();
+    let pixels = decode(F::Bc5RgUnorm, false, &block);
+    assert_eq!(texel(&pixels, 0), [0, 255, 0, 255]);
+    let block = [bc4_block(0x81, 0x7F, [0; 16]), bc4_block(0x7F, 0x81, [0; 16])].concat();
+    let pixels = decode(F::Bc5RgSnorm, false, &block);
+    assert_eq!(texel(&pixels, 0), [0x81, 0x7F, 0, 127]);
+}
+
+#[test]
+fn bc7_mode_6_single_subset_with_alpha() {
+    // Mode bit, endpoints R0 R1 G0 G1 B0 B1 A0 A1 of 7 bits from bit 7, a p-bit per endpoint at 63 and 64, then
+    // 4-bit indices from bit 65, the first texel's 3 bits
+    let block = bits(&[(6, 1), (7, 127), (49, 127), (56, 127), (63, 1), (68, 15), (72, 8)]);
+    let pixels = decode(F::Bc7RgbaUnorm, false, &block);
+    assert_eq!(texel(&pixels, 0), [255, 1, 1, 255]);
+    assert_eq!(texel(&pixels, 1), [0, 0, 0, 254]);
+    assert_eq!(texel(&pixels, 2), [120, 0, 0, 254]);
+}
+
+#[test]
+fn bc7_mode_1_two_subsets_with_shared_p_bits() {
+    // Mode bits, partition 0 (the right two columns are the second subset), 6-bit endpoints from bit 8: red of
+    // both subsets, then green, then blue. Shared p-bits at 80 and 81, indices all 0
+    let block = bits(&[(1, 1), (8, 63), (14, 63), (68, 63), (74, 63), (80, 1), (81, 1)]);
+    let pixels = decode(F::Bc7RgbaUnorm, false, &block);
+    assert_eq!(texel(&pixels, 0), [255, 2, 2, 255]);
+    assert_eq!(texel(&pixels, 2), [2, 2, 255, 255]);
+    assert_eq!(texel(&pixels, 15), [2, 2, 255, 255]);
+}
+
+#[test]
+fn bc7_mode_4_rotation() {
+    // Mode bit, rotation 1 (alpha and red swap), 5-bit color and 6-bit alpha endpoints from bit 8, 2-bit color
+    // indices from bit 50 and 3-bit alpha indices from bit 81
+    let block = bits(&[(4, 1), (5, 1), (8, 31), (44, 63), (51, 3), (83, 7)]);
+    let pixels = decode(F::Bc7RgbaUnorm, false, &block);
+    assert_eq!(texel(&pixels, 0), [0, 0, 0, 255]);
+    assert_eq!(texel(&pixels, 1), [255, 0, 0, 0]);
+}
+
+#[test]
+fn bc7_reserved_mode() {
+    assert_eq!(decode(F::Bc7RgbaUnorm, false, &[0; 16]), vec![0; 64]);
+}
+
+#[test]
+fn bc6h_mode_11_untransformed() {
+    // Mode 00011, then W and X of 10 bits a channel: W black, X the largest value. 4-bit indices from bit 65
+    let block = bits(&[(0, 3), (35, 1023), (45, 1023), (55, 1023), (68, 15), (72, 8)]);
+    let pixels = decode(F::Bc6hRgbUfloat, false, &block);
+    assert_eq!(half_texel(&pixels, 0), [0, 0, 0, 0x3C00]);
+    assert_eq!(half_texel(&pixels, 1), [0x7BFF, 0x7BFF, 0x7BFF, 0x3C00]);
+    assert_eq!(half_texel(&pixels, 2), [0x41DF, 0x41DF, 0x41DF, 0x3C00]);
+}
+
+#[test]
+fn bc6h_mode_1_two_regions_transformed() {
+    // Mode 00 with W at 512 in each channel and every difference 0 but Y's red, -1. Partition 0 puts the right
+    // two columns in the second region, whose first endpoint is Y
+    let block = bits(&[(14, 1), (24, 1), (34, 1), (65, 0b11111)]);
+    let pixels = decode(F::Bc6hRgbUfloat, false, &block);
+    assert_eq!(half_texel(&pixels, 0), [0x3E0F, 0x3E0F, 0x3E0F, 0x3C00]);
+    assert_eq!(half_texel(&pixels, 2), [0x3DF0, 0x3E0F, 0x3E0F, 0x3C00]);
+}
+
+#[test]
+fn bc6h_reserved_mode() {
+    let pixels = decode(F::Bc6hRgbFloat, false, &bits(&[(0, 0b10011)]));
+    assert_eq!(half_texel(&pixels, 5), [0, 0, 0, 0x3C00]);
+}
+
+fn dds(four_cc: &[u8; 4], dxgi_format: Option<u32>, width: u32, height: u32, levels: &[&[u8]]) -> Vec<u8> {
+    let mut file = vec![0u8; 128];
+    let mut put = |offset: usize, value: u32| file[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    put(4, 124);
+    put(12, height);
+    put(16, width);
+    put(28, levels.len() as u32);
+    put(76, 32);
+    put(80, 0x4); // DDPF_FOURCC
+    file[84..88].copy_from_slice(four_cc);
+    if let Some(format) = dxgi_format {
+        for value in [format, 3, 0, 1, 0] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    file[..4].copy_from_slice(b"DDS ");
+    file.extend(levels.concat());
+    file
+}
+
+fn ktx2(vk_format: u32, width: u32, height: u32, levels: &[&[u8]]) -> Vec<u8> {
+    let mut file = vec![0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n'];
+    for value in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0, 0, 0, 0, 0, 0, 0, 0, 0] {
+        file.extend_from_slice(&value.to_le_bytes());
+    }
+    let mut offset = 80 + levels.len() * 24;
+    for level in levels {
+        for value in [offset, level.len(), level.len()] {
+            file.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+        offset += level.len();
+    }
+    file.extend(levels.concat());
+    file
+}
+
+#[test]
+fn dds_legacy_header() {
+    let (top, bottom) = (vec![1; 32], vec![2; 8]);
+    let image = parse_dds(&dds(b"DXT1", None, 8, 8, &[&top, &bottom])).unwrap();
+    assert_eq!(image.format, F::Bc1RgbaUnormSrgb);
+    assert_eq!((image.width, image.height), (8, 8));
+    assert_eq!(image.levels, [top, bottom]);
+    assert!(!image.opaque);
+}
+
+#[test]
+fn dds_dx10_header() {
+    let block = vec![7; 16];
+    let image = parse_dds(&dds(b"DX10", Some(98), 4, 4, &[&block])).unwrap();
+    assert_eq!(image.format, F::Bc7RgbaUnorm);
+    assert_eq!(image.levels, [block]);
+    assert!(parse_dds(&dds(b"DX10", Some(28), 4, 4, &[&[0; 64]])).is_err()); // RGBA8, not block-compressed
+}
+
+#[test]
+fn ktx2_header() {
+    let (top, bottom) = (vec![3; 32], vec![4; 8]);
+    let image = parse_ktx2(&ktx2(131, 8, 4, &[&top, &bottom])).unwrap();
+    assert_eq!(image.format, F::Bc1RgbaUnorm);
+    assert_eq!((image.width, image.height), (8, 4));
+    assert_eq!(image.levels, [top, bottom]);
+    assert!(image.opaque);
+    assert!(!parse_ktx2(&ktx2(146, 4, 4, &[&[0; 16]])).unwrap().opaque);
+}
+
+#[test]
+fn truncated_files_are_rejected() {
+    let files = [
+        (dds(b"DXT5", None, 8, 8, &[&[0; 64], &[0; 16]]), false),
+        (dds(b"DX10", Some(95), 4, 4, &[&[0; 16]]), false),
+        (ktx2(141, 8, 8, &[&[0; 64], &[0; 16]]), true),
+    ];
+    for (file, is_ktx2) in files {
+        let parse = if is_ktx2 { parse_ktx2 } else { parse_dds };
+        assert!(parse(&file).is_ok());
+        for length in 0..file.len() {
+            assert!(parse(&file[..length]).is_err(), "{length} of {} bytes read", file.len());
+        }
+    }
+}
+
+#[test]
+fn impossible_sizes_are_rejected() {
+    assert!(parse_dds(&dds(b"DXT1", None, u32::MAX, u32::MAX, &[&[0; 8]])).is_err());
+    assert!(parse_dds(&dds(b"DXT1", None, 0, 4, &[&[0; 8]])).is_err());
+    assert!(parse_ktx2(&ktx2(133, u32::MAX, u32::MAX, &[&[0; 8]])).is_err());
+    let too_many_levels: Vec<&[u8]> = vec![&[0; 8]; 5];
+    assert!(parse_ktx2(&ktx2(133, 4, 4, &too_many_levels)).is_err());
+}
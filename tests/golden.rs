@@ -0,0 +1,107 @@
+// golden.rs
+
+// Renders each test scene headlessly and compares it with its stored image in tests/golden, within a
+// perceptual tolerance. Run with UPDATE_GOLDEN=1 to write the current frames as the new golden images
+
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+use winit_egui_wgpu::headless::{HeadlessRenderer, TestScene};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 192;
+
+// CIE76 color difference past which a pixel counts as changed; about 2.3 is just noticeable side by side
+const MAX_DELTA_E: f32 = 3.0;
+
+// Share of pixels that may change, for the edge rasterization and precision differences between drivers
+const MAX_CHANGED: f32 = 0.01;
+
+#[test]
+fn golden_images() {
+    let Some(mut renderer) = HeadlessRenderer::new(WIDTH, HEIGHT) else {
+        eprintln!("No adapter to render with; skipping the golden image tests");
+        return;
+    };
+    let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+    let failure_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut failures = Vec::new();
+    for scene in TestScene::ALL {
+        let pixels = renderer.render(scene).expect("Failed to read the frame back");
+        let actual = RgbaImage::from_raw(WIDTH, HEIGHT, pixels).expect("Frame has the wrong size");
+        let path = golden_dir.join(format!("{}.png", scene.name()));
+        if update {
+            std::fs::create_dir_all(&golden_dir).expect("Failed to create the golden image directory");
+            actual.save(&path).expect("Failed to write the golden image");
+            continue;
+        }
+
+        let golden = match image::open(&path) {
+            Ok(golden) => golden.to_rgba8(),
+            Err(err) => {
+                failures.push(format!("{}: no golden image at {} ({err})", scene.name(), path.display()));
+                continue;
+            }
+        };
+        if golden.dimensions() != actual.dimensions() {
+            failures.push(format!("{}: golden image is {:?}", scene.name(), golden.dimensions()));
+            continue;
+        }
+        let (diff, changed) = compare(&golden, &actual);
+        let share = changed as f32 / (WIDTH * HEIGHT) as f32;
+        if share > MAX_CHANGED {
+            std::fs::create_dir_all(&failure_dir).expect("Failed to create the failure directory");
+            let actual_path = failure_dir.join(format!("{}.png", scene.name()));
+            let diff_path = failure_dir.join(format!("{}_diff.png", scene.name()));
+            actual.save(&actual_path).expect("Failed to write the rendered frame");
+            diff.save(&diff_path).expect("Failed to write the difference image");
+            failures.push(format!(
+                "{}: {:.2}% of pixels changed; see {} and {}",
+                scene.name(),
+                share * 100.0,
+                actual_path.display(),
+                diff_path.display()
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "Frames differ from their golden images:\n{}", failures.join("\n"));
+}
+
+// An image marking changed pixels in red over a faded copy of the golden image, and how many changed
+fn compare(golden: &RgbaImage, actual: &RgbaImage) -> (RgbaImage, usize) {
+    let mut diff = RgbaImage::new(golden.width(), golden.height());
+    let mut changed = 0;
+    for ((expected, found), marked) in golden.pixels().zip(actual.pixels()).zip(diff.pixels_mut()) {
+        if delta_e(lab(*expected), lab(*found)) > MAX_DELTA_E {
+            changed += 1;
+            *marked = Rgba([255, 0, 0, 255]);
+        } else {
+            let [r, g, b, _] = expected.0.map(|channel| channel / 4);
+            *marked = Rgba([r, g, b, 255]);
+        }
+    }
+    (diff, changed)
+}
+
+// CIELAB of an sRGB pixel under the D65 white point
+fn lab(pixel: Rgba<u8>) -> [f32; 3] {
+    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|channel| {
+        let c = channel as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.9505;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.089;
+    let f = |t: f32| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn delta_e(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a.iter().zip(&b).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+}
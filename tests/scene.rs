@@ -0,0 +1,133 @@
+// scene.rs
+
+// Loads scene files as older versions wrote them and saves the current one back, checking what each version
+// migrates to survives the round trip
+
+use std::path::PathBuf;
+use winit_egui_wgpu::history::{History, SceneEdit};
+use winit_egui_wgpu::mesh_builder::MeshShape;
+use winit_egui_wgpu::scene::{NodeParent, SceneFile, SceneMaterial, SceneNode, SCENE_VERSION};
+use winit_egui_wgpu::transform::Transform;
+
+fn scene_path(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("scenes");
+    std::fs::create_dir_all(&dir).expect("Failed to create the scene directory");
+    dir.join(name)
+}
+
+fn load(name: &str, text: &str) -> SceneFile {
+    let path = scene_path(name);
+    std::fs::write(&path, text).expect("Failed to write the scene");
+    SceneFile::load(&path).expect("Failed to load the scene")
+}
+
+#[test]
+fn version_3_scene_keeps_the_current_materials() {
+    let scene = load("version_3.scene", "(version: 3, material: Some(2), materials: [(name: \"stray\")])");
+    assert_eq!(scene.version, SCENE_VERSION);
+    assert!(scene.materials.is_empty());
+    assert_eq!(scene.material, None);
+}
+
+#[test]
+fn version_4_scene_has_no_nodes() {
+    let node = "(name: \"stray\", parent: None, transform: (translation: (0, 0, 0), rotation: (0, 0, 0, 1), \
+                scale: (1, 1, 1)), shape: None, material: None)";
+    let scene = load("version_4.scene", &format!("(version: 4, nodes: [{node}])"));
+    assert_eq!(scene.version, SCENE_VERSION);
+    assert!(scene.nodes.is_empty());
+}
+
+fn node(name: &str, parent: Option<NodeParent>) -> SceneNode {
+    SceneNode {
+        name: name.to_string(),
+        parent,
+        transform: Transform::IDENTITY,
+        shape: Some(MeshShape::Cube),
+        material: Some(0),
+    }
+}
+
+#[test]
+fn nodes_round_trip() {
+    let scene = SceneFile {
+        nodes: vec![node("Cube 7", Some(NodeParent::Node(1))), node("Cube 8", Some(NodeParent::Object))],
+        ..SceneFile::default()
+    };
+    for name in ["nodes.scene", "nodes.json"] {
+        let path = scene_path(name);
+        scene.save(&path).expect("Failed to save the scene");
+        assert_eq!(SceneFile::load(&path).expect("Failed to load the scene").nodes, scene.nodes);
+    }
+}
+
+#[test]
+fn tree_edits_are_undoable_and_dirty() {
+    let mut history = History::new();
+    history.track_nodes(Vec::new(), false);
+    assert!(!history.is_dirty());
+
+    let added = vec![node("Cube 7", None)];
+    history.track_nodes(added.clone(), true);
+    assert!(!history.is_dirty(), "an edit in progress is not committed");
+    history.track_nodes(added.clone(), false);
+    assert!(history.is_dirty());
+
+    let undone = history.undo();
+    assert_eq!(
+        undone,
+        Some(SceneEdit::Nodes {
+            before: added.clone(),
+            after: Vec::new(),
+        })
+    );
+    // The render loop restores the nodes it was given, which must not count as another edit
+    history.track_nodes(Vec::new(), false);
+    assert!(history.can_redo());
+    history.mark_saved();
+    history.redo();
+    assert!(history.is_dirty());
+}
+
+#[test]
+fn materials_round_trip() {
+    let scene = SceneFile {
+        material: Some(1),
+        materials: vec![
+            SceneMaterial {
+                name: "main".to_string(),
+                shader: "main".to_string(),
+                ..SceneMaterial::default()
+            },
+            SceneMaterial {
+                name: "Brick".to_string(),
+                shader: "main".to_string(),
+                params: vec![("tint".to_string(), vec![0.8, 0.3, 0.2])],
+                textures: vec![("albedo".to_string(), PathBuf::from("textures/brick.png"))],
+            },
+        ],
+        ..SceneFile::default()
+    };
+    for name in ["materials.scene", "materials.json"] {
+        let path = scene_path(name);
+        scene.save(&path).expect("Failed to save the scene");
+        let loaded = SceneFile::load(&path).expect("Failed to load the scene");
+        assert_eq!(loaded.material, Some(1));
+        assert_eq!(loaded.materials.len(), 2);
+        assert_eq!(loaded.materials[1].params, scene.materials[1].params);
+        assert_eq!(loaded.materials[1].textures, scene.materials[1].textures);
+    }
+}
+
+#[test]
+fn stripping_paths_clears_material_textures() {
+    let mut scene = SceneFile {
+        materials: vec![SceneMaterial {
+            textures: vec![("albedo".to_string(), PathBuf::from("brick.png"))],
+            ..SceneMaterial::default()
+        }],
+        ..SceneFile::default()
+    };
+    assert_eq!(scene.strip_paths(), 1);
+    assert!(scene.materials[0].textures.is_empty());
+}